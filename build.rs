@@ -1,4 +1,18 @@
+use std::{path::Path, process::Command};
+
 fn main () -> Result<(), Box<dyn std::error::Error>> {
     tonic_build::compile_protos("proto/orderbook.proto")?;
+    // commit of the build, reported to clients with the server version
+    let git_hash = Command::new("git").args(["rev-parse", "--short", "HEAD"]).output().ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=GIT_HASH={}", git_hash);
+    for path in [".git/HEAD", ".git/refs/heads"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
     Ok(())
-}
\ No newline at end of file
+}
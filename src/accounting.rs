@@ -0,0 +1,248 @@
+//! Per-client usage accounting: stream time, messages delivered and symbols used
+//! for each API key, periodically persisted to a JSON file.
+
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::time::{Duration, Instant};
+
+use crate::clock::SharedClock;
+
+
+/// API key used for requests not providing one.
+pub const ANONYMOUS_API_KEY: &str = "anonymous";
+/// Request metadata key carrying the API key.
+pub const API_KEY_METADATA: &str = "x-api-key";
+
+
+/// Usage accumulated for an API key.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct ClientUsage {
+    /// Number of streams opened.
+    pub streams_opened: u64,
+    /// Total time spent streaming, in seconds.
+    pub stream_seconds: f64,
+    /// Number of messages delivered.
+    pub messages_delivered: u64,
+    /// Symbols requested.
+    pub symbols: BTreeSet<String>,
+}
+
+/// Registry of the usage of all the API keys, shared between client streams.
+#[derive(Clone)]
+pub struct UsageRegistry {
+    /// Usage for each API key.
+    usage: Arc<Mutex<BTreeMap<String, ClientUsage>>>,
+    /// Time source to measure stream time.
+    clock: SharedClock,
+}
+
+impl UsageRegistry {
+    /// Create a new, empty [UsageRegistry](UsageRegistry) object.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - Time source to measure stream time.
+    pub fn new(clock: SharedClock) -> Self {
+        Self { usage: Arc::new(Mutex::new(BTreeMap::new())), clock }
+    }
+
+    /// Create a new [UsageRegistry](UsageRegistry) object, with the usage persisted in a file
+    /// if it exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the JSON file.
+    ///
+    /// * `clock` - Time source to measure stream time.
+    ///
+    /// # Returns
+    ///
+    /// A [UsageRegistry](UsageRegistry) object, or an error if the file exists and cannot be read.
+    pub fn load(path: &Path, clock: SharedClock) -> Result<Self, Box<dyn std::error::Error>> {
+        let registry = Self::new(clock);
+        if path.exists() {
+            let usage: BTreeMap<String, ClientUsage> = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+            *registry.usage.lock().unwrap() = usage;
+        }
+        Ok(registry)
+    }
+
+    /// Write the usage of all the API keys to a JSON file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the JSON file.
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(&*self.usage.lock().unwrap())?;
+        std::fs::write(path, json)
+    }
+
+    /// Spawn a task saving the usage to a JSON file at regular intervals.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the JSON file.
+    ///
+    /// * `interval` - Time between two saves.
+    pub fn spawn_persistence(&self, path: PathBuf, interval: Duration) {
+        let registry = self.clone();
+        tokio::spawn(async move {
+            loop {
+                registry.clock.sleep(interval).await;
+                match registry.save(&path) {
+                    Ok(_) => info!("Client usage saved to {}", path.display()),
+                    Err(error) => error!("Error saving client usage to {}: {:?}", path.display(), error),
+                }
+            }
+        });
+    }
+
+    /// Register the start of a client stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `api_key` - The API key of the client.
+    ///
+    /// * `symbol` - The symbol streamed.
+    ///
+    /// # Returns
+    ///
+    /// A [StreamUsage](StreamUsage) object, recording the usage of the stream until dropped.
+    pub fn start_stream(&self, api_key: &str, symbol: &str) -> StreamUsage {
+        let mut usage = self.usage.lock().unwrap();
+        let client_usage = usage.entry(api_key.to_string()).or_default();
+        client_usage.streams_opened += 1;
+        client_usage.symbols.insert(symbol.to_string());
+        StreamUsage {
+            registry: self.clone(),
+            api_key: api_key.to_string(),
+            last_update: self.clock.now(),
+        }
+    }
+
+    /// Usage for an API key.
+    ///
+    /// # Arguments
+    ///
+    /// * `api_key` - The API key.
+    ///
+    /// # Returns
+    ///
+    /// An optional [ClientUsage](ClientUsage), [None](None) if the key was never used.
+    pub fn get(&self, api_key: &str) -> Option<ClientUsage> {
+        self.usage.lock().unwrap().get(api_key).cloned()
+    }
+
+    /// Usage for all the API keys.
+    ///
+    /// # Returns
+    ///
+    /// A [vector](Vec) of API keys and their [usage](ClientUsage), ordered by API key.
+    pub fn all(&self) -> Vec<(String, ClientUsage)> {
+        self.usage.lock().unwrap().iter().map(|(k, u)| (k.clone(), u.clone())).collect()
+    }
+}
+
+/// Usage recorder for a single client stream. Stream time is accounted when messages
+/// are delivered, and when the object is dropped.
+pub struct StreamUsage {
+    /// The registry where usage is accumulated.
+    registry: UsageRegistry,
+    /// The API key of the client.
+    api_key: String,
+    /// Instant up to which stream time has been accounted.
+    last_update: Instant,
+}
+
+impl StreamUsage {
+    /// Record a message delivered to the client.
+    pub fn record_message(&mut self) {
+        self.update(1);
+    }
+
+    /// Record a further symbol streamed, for streams multiplexing several symbols.
+    ///
+    /// # Arguments
+    ///
+    /// * `symbol` - The symbol streamed.
+    pub fn record_symbol(&self, symbol: &str) {
+        let mut usage = self.registry.usage.lock().unwrap();
+        if let Some(client_usage) = usage.get_mut(&self.api_key) {
+            client_usage.symbols.insert(symbol.to_string());
+        }
+    }
+
+    /// Internal function accumulating messages and stream time in the registry.
+    fn update(&mut self, messages: u64) {
+        let now = self.registry.clock.now();
+        let mut usage = self.registry.usage.lock().unwrap();
+        // the key is cloned only for a new client, not for each message
+        if !usage.contains_key(&self.api_key) {
+            usage.insert(self.api_key.clone(), ClientUsage::default());
+        }
+        let client_usage = usage.get_mut(&self.api_key).unwrap();
+        client_usage.messages_delivered += messages;
+        client_usage.stream_seconds += (now - self.last_update).as_secs_f64();
+        self.last_update = now;
+    }
+}
+
+impl Drop for StreamUsage {
+    fn drop(&mut self) {
+        self.update(0);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn test_stream_usage() {
+        let clock = Arc::new(ManualClock::new());
+        let registry = UsageRegistry::new(clock.clone());
+        let mut stream_usage = registry.start_stream("key1", "ETH-BTC");
+        clock.advance(Duration::from_secs(2));
+        stream_usage.record_message();
+        stream_usage.record_message();
+        clock.advance(Duration::from_secs(1));
+        drop(stream_usage);
+        let _ = registry.start_stream("key1", "BTC-USDT");
+        let usage = registry.get("key1").unwrap();
+        assert_eq!(usage.streams_opened, 2);
+        assert_eq!(usage.messages_delivered, 2);
+        assert_eq!(usage.stream_seconds, 3.0);
+        assert_eq!(usage.symbols, BTreeSet::from(["BTC-USDT".to_string(), "ETH-BTC".to_string()]));
+        assert_eq!(registry.get("key2"), None);
+    }
+
+    #[test]
+    fn test_stream_of_several_symbols() {
+        let registry = UsageRegistry::new(Arc::new(ManualClock::new()));
+        let stream_usage = registry.start_stream("key1", "ETH-BTC");
+        stream_usage.record_symbol("ETH-BTC");
+        stream_usage.record_symbol("BTC-USDT");
+        let usage = registry.get("key1").unwrap();
+        assert_eq!(usage.streams_opened, 1);
+        assert_eq!(usage.symbols, BTreeSet::from(["BTC-USDT".to_string(), "ETH-BTC".to_string()]));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let clock = Arc::new(ManualClock::new());
+        let registry = UsageRegistry::new(clock.clone());
+        let mut stream_usage = registry.start_stream("key1", "ETH-BTC");
+        stream_usage.record_message();
+        drop(stream_usage);
+        let path = std::env::temp_dir().join(format!("orderbook-usage-{}.json", std::process::id()));
+        registry.save(&path).unwrap();
+        let loaded = UsageRegistry::load(&path, clock).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.all(), registry.all());
+    }
+}
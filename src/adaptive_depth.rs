@@ -0,0 +1,101 @@
+//! Optional adaptive depth of the published summaries, read from a JSON file: few levels are
+//! published in calm markets, reducing bandwidth, and more levels, up to a cap, as the
+//! consolidated spread widens beyond a threshold, preserving information in stressed conditions.
+
+use rust_decimal::Decimal;
+use rust_decimal::prelude::ToPrimitive;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{DocumentedConfig, Validate, Validator};
+
+
+/// Levels published within the threshold in the printed defaults.
+const DEFAULT_MIN_LEVELS: usize = 3;
+/// Maximum levels published in the printed defaults.
+const DEFAULT_MAX_LEVELS: usize = 10;
+/// Spread threshold, in basis points, in the printed defaults.
+const DEFAULT_SPREAD_THRESHOLD_BPS: f64 = 5.0;
+
+
+/// Levels of each side published, depending on the consolidated spread.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct AdaptiveDepth {
+    /// Levels published while the spread is within the threshold.
+    pub min_levels: usize,
+    /// Maximum levels published, also limited by the levels maintained in the aggregate book.
+    pub max_levels: usize,
+    /// Spread, in basis points of the mid price, beyond which more levels are published, in
+    /// proportion to the spread.
+    pub spread_threshold_bps: f64,
+}
+
+impl Default for AdaptiveDepth {
+    fn default() -> Self {
+        Self {
+            min_levels: DEFAULT_MIN_LEVELS,
+            max_levels: DEFAULT_MAX_LEVELS,
+            spread_threshold_bps: DEFAULT_SPREAD_THRESHOLD_BPS,
+        }
+    }
+}
+
+impl AdaptiveDepth {
+    /// Levels of each side to publish.
+    ///
+    /// # Arguments
+    ///
+    /// * `spread` - The consolidated spread, [None](None) if any of the sides is empty.
+    ///
+    /// * `mid_price` - The mid price, [None](None) if any of the sides is empty.
+    ///
+    /// # Returns
+    ///
+    /// The minimum levels within the threshold, the spread over the threshold times the minimum
+    /// levels beyond it, up to the maximum levels.
+    pub fn levels(&self, spread: Option<Decimal>, mid_price: Option<Decimal>) -> usize {
+        let spread_bps = match (spread, mid_price) {
+            (Some(spread), Some(mid_price)) if mid_price > Decimal::ZERO =>
+                (spread * Decimal::from(10_000) / mid_price).to_f64().unwrap_or_default(),
+            _ => return self.min_levels,
+        };
+        if spread_bps <= self.spread_threshold_bps {
+            return self.min_levels;
+        }
+        let levels = (self.min_levels as f64 * spread_bps / self.spread_threshold_bps).ceil();
+        (levels as usize).clamp(self.min_levels, self.max_levels)
+    }
+}
+
+impl Validate for AdaptiveDepth {
+    fn validate(&self, validator: &mut Validator) {
+        validator.positive("min_levels", self.min_levels as u64);
+        validator.not_above("min_levels", self.min_levels, "max_levels", self.max_levels);
+        validator.positive_f64("spread_threshold_bps", self.spread_threshold_bps);
+    }
+}
+
+impl DocumentedConfig for AdaptiveDepth {
+    const DESCRIPTION: &'static str = "Levels of each side published depending on the consolidated spread, all levels are published if the file is missing.";
+    const FIELDS: &'static [(&'static str, &'static str)] = &[
+        ("min_levels", "Levels published while the spread is within the threshold."),
+        ("max_levels", "Maximum levels published, also limited by the levels maintained in the aggregate book."),
+        ("spread_threshold_bps", "Spread, in basis points of the mid price, beyond which more levels are published, in proportion to the spread."),
+    ];
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels() {
+        let adaptive_depth = AdaptiveDepth { min_levels: 3, max_levels: 10, spread_threshold_bps: 10.0 };
+        let mid = Some(Decimal::from(1000));
+        assert_eq!(adaptive_depth.levels(Some(Decimal::ONE), mid), 3);
+        assert_eq!(adaptive_depth.levels(Some(Decimal::TWO), mid), 6);
+        assert_eq!(adaptive_depth.levels(Some(Decimal::from(3)), mid), 9);
+        assert_eq!(adaptive_depth.levels(Some(Decimal::from(100)), mid), 10);
+        assert_eq!(adaptive_depth.levels(None, None), 3);
+    }
+}
@@ -1,7 +1,6 @@
 //! Data structures for a consolidated trading book built from
 //! data coming from multiple sources.
 
-use std::cmp::{min};
 use std::ops::Index;
 use rust_decimal::prelude::*;
 use std::collections::HashMap;
@@ -11,7 +10,7 @@ use crate::core::*;
 /// Internally used type to differentiate between trading book sides:
 /// within _ask_ sides the prices are ordered from lower to higher,
 /// within _bid_ sides, it is the other way around.
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 enum Ranking {
     /// Prices must be ordered with the lower first
     LessFirst,
@@ -19,8 +18,34 @@ enum Ranking {
     GreaterFirst
 }
 
+/// Result of sweeping a side of the book up to a notional, across all exchanges.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Sweep {
+    /// Last price reached.
+    pub worst_price: Decimal,
+    /// Average price of the amount filled.
+    pub average_price: Decimal,
+    /// Amount filled.
+    pub amount: Decimal,
+    /// Notional filled: less than requested if the book is not deep enough.
+    pub notional: Decimal,
+}
+
+/// Total amount of a side of the book within a distance from the mid price, maintained as the
+/// levels change rather than computed on request.
+#[derive(PartialEq, Debug, Clone)]
+struct DepthBandTotal {
+    /// The distance from the mid price, in basis points.
+    bps: u32,
+    /// The limit price of the band, [None](None) while the mid price is not available.
+    limit_price: Option<Decimal>,
+    /// Total amount from all the exchanges for the prices from the top of the side down to the
+    /// limit price, included.
+    amount: Decimal,
+}
+
 /// Container for the consolidated trading book
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub struct AggregateBook {
     bids: AggregateBookSide,
     asks: AggregateBookSide,
@@ -43,6 +68,45 @@ impl AggregateBook {
         }
     }
 
+    /// Create a new object maintaining all the price levels received, for exchanges delivering
+    /// their complete books: the levels of an exchange missing from its last update are removed.
+    /// Only the best levels are published.
+    ///
+    /// # Arguments
+    ///
+    /// * `published_levels` - How many price levels to publish, e.g. in [best_bids](AggregateBook::best_bids)
+    ///
+    /// # Returns
+    ///
+    /// An instance of [AggregateBook](AggregateBook)
+    pub fn full_depth(published_levels: usize) -> Self {
+        Self {
+            bids: AggregateBookSide::full_depth(Ranking::GreaterFirst, published_levels),
+            asks: AggregateBookSide::full_depth(Ranking::LessFirst, published_levels),
+        }
+    }
+
+    /// Maintain the total amount available on each side within some distances from the mid
+    /// price as the book is updated, so that [depth_within](AggregateBook::depth_within) does
+    /// not scan the levels for them.
+    ///
+    /// # Arguments
+    ///
+    /// * `depth_bands_bps` - The distances from the mid price, in basis points.
+    ///
+    /// # Returns
+    ///
+    /// The modified [AggregateBook](AggregateBook).
+    pub fn with_depth_bands(mut self, depth_bands_bps: Vec<u32>) -> Self {
+        let bands: Vec<DepthBandTotal> = depth_bands_bps.into_iter()
+            .map(|bps| DepthBandTotal { bps, limit_price: None, amount: Decimal::ZERO })
+            .collect();
+        self.bids.bands = bands.clone();
+        self.asks.bands = bands;
+        self.move_bands();
+        self
+    }
+
     /// Vector of best bids, from the highest price. Maximum `max_levels` items.
     ///
     /// # Returns
@@ -67,23 +131,201 @@ impl AggregateBook {
     /// # Arguments
     ///
     /// * `book_update` - an object of type [BookUpdate](BookUpdate) containing a book
-    /// snapshot from an exchange
+    ///   snapshot from an exchange
     pub fn update(&mut self, book_update: BookUpdate) {
-        self.bids.update_side(book_update.bids);
-        self.asks.update_side(book_update.asks);
+        if self.bids.full_depth {
+            self.bids.update_complete_side(book_update.exchange_code.clone(), book_update.bids);
+            self.asks.update_complete_side(book_update.exchange_code, book_update.asks);
+        } else {
+            self.bids.update_side(book_update.bids);
+            self.asks.update_side(book_update.asks);
+        }
+        self.move_bands();
+    }
+
+    /// Internal function moving the limit prices of the depth bands with the mid price.
+    fn move_bands(&mut self) {
+        let mid_price = self.mid_price();
+        self.bids.move_bands(mid_price);
+        self.asks.move_bands(mid_price);
+    }
+
+    /// Mid price between the best bid and the best ask.
+    ///
+    /// # Returns
+    ///
+    /// An optional [Decimal](Decimal), [None](None) if any of the sides is empty.
+    pub fn mid_price(&self) -> Option<Decimal> {
+        match (self.bids.data.first(), self.asks.data.first()) {
+            (Some(bid), Some(ask)) => Some((bid.price + ask.price) / Decimal::TWO),
+            _ => None,
+        }
+    }
+
+    /// Difference between the best ask and the best bid.
+    ///
+    /// # Returns
+    ///
+    /// An optional [Decimal](Decimal), [None](None) if any of the sides is empty.
+    pub fn spread(&self) -> Option<Decimal> {
+        match (self.bids.data.first(), self.asks.data.first()) {
+            (Some(bid), Some(ask)) => Some(ask.price - bid.price),
+            _ => None,
+        }
+    }
+
+    /// Total amount available on each side within a distance from the mid price.
+    ///
+    /// # Arguments
+    ///
+    /// * `bps` - The distance from the mid price, in basis points.
+    ///
+    /// # Returns
+    ///
+    /// An optional pair of [Decimal](Decimal) amounts, for bids and asks respectively,
+    /// [None](None) if the mid price is not available. The amounts of the
+    /// [maintained distances](AggregateBook::with_depth_bands) are not computed again.
+    pub fn depth_within(&self, bps: u32) -> Option<(Decimal, Decimal)> {
+        let mid_price = self.mid_price()?;
+        if let (Some(bid_amount), Some(ask_amount)) = (self.bids.band_amount(bps), self.asks.band_amount(bps)) {
+            return Some((bid_amount, ask_amount));
+        }
+        Some((
+            self.bids.amount_within(self.bids.band_limit(mid_price, bps)),
+            self.asks.amount_within(self.asks.band_limit(mid_price, bps)),
+        ))
+    }
+
+    /// Remove all the price levels from an exchange, e.g. after losing its connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `exchange_code` - The exchange code.
+    pub fn remove_exchange(&mut self, exchange_code: &str) {
+        self.bids.remove_exchange(exchange_code);
+        self.asks.remove_exchange(exchange_code);
+        self.move_bands();
+    }
+
+    /// Internal utility function selecting a side of the book: bids for [Buy](Side::Buy),
+    /// asks for [Sell](Side::Sell).
+    fn side(&self, side: Side) -> &AggregateBookSide {
+        match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        }
+    }
+
+    /// Total amount from all the exchanges at a price.
+    ///
+    /// # Arguments
+    ///
+    /// * `side` - The [Side](Side) of the book: bids for [Buy](Side::Buy), asks for [Sell](Side::Sell).
+    ///
+    /// * `price` - The price.
+    ///
+    /// # Returns
+    ///
+    /// A [Decimal](Decimal) amount, zero if the price is not in the book.
+    pub fn amount_at(&self, side: Side, price: Decimal) -> Decimal {
+        self.side(side).level_at(price).map(|level| level.total_amount()).unwrap_or_default()
+    }
+
+    /// Exchange price levels at a price.
+    ///
+    /// # Arguments
+    ///
+    /// * `side` - The [Side](Side) of the book: bids for [Buy](Side::Buy), asks for [Sell](Side::Sell).
+    ///
+    /// * `price` - The price.
+    ///
+    /// # Returns
+    ///
+    /// A [vector](Vec) of references to [exchange price levels](ExchangeLevel), ordered by
+    /// amount decreasing, empty if the price is not in the book.
+    pub fn venues_at(&self, side: Side, price: Decimal) -> Vec<&ExchangeLevel> {
+        self.side(side).level_at(price).map(|level| level.levels_by_amount()).unwrap_or_default()
+    }
+
+    /// Number of distinct prices on a side of the book.
+    ///
+    /// # Arguments
+    ///
+    /// * `side` - The [Side](Side) of the book: bids for [Buy](Side::Buy), asks for [Sell](Side::Sell).
+    ///
+    /// # Returns
+    ///
+    /// An [usize](usize).
+    pub fn level_count(&self, side: Side) -> usize {
+        self.side(side).len()
+    }
+
+    /// Sweep a side of the book from the best price, across all exchanges, until a notional
+    /// is filled: the last price reached estimates the price at which an order for that
+    /// notional could be filled, e.g. sweeping the asks for a buy order.
+    ///
+    /// # Arguments
+    ///
+    /// * `side` - The [Side](Side) of the book: bids for [Buy](Side::Buy), asks for [Sell](Side::Sell).
+    ///
+    /// * `max_notional` - The notional to fill, in counter currency.
+    ///
+    /// # Returns
+    ///
+    /// An optional [Sweep](Sweep), [None](None) if the side is empty or the notional is not positive.
+    pub fn cumulative(&self, side: Side, max_notional: Decimal) -> Option<Sweep> {
+        if max_notional <= Decimal::ZERO {
+            return None;
+        }
+        let mut amount = Decimal::ZERO;
+        let mut notional = Decimal::ZERO;
+        let mut worst_price = None;
+        for level in self.levels(side) {
+            worst_price = Some(level.price);
+            let level_notional = level.price * level.total_amount();
+            if notional + level_notional >= max_notional {
+                amount += (max_notional - notional) / level.price;
+                notional = max_notional;
+                break;
+            }
+            amount += level.total_amount();
+            notional += level_notional;
+        }
+        let worst_price = worst_price?;
+        let average_price = if amount.is_zero() { worst_price } else { notional / amount };
+        Some(Sweep { worst_price, average_price, amount, notional })
+    }
+
+    /// Iterate over the price levels of a side of the book, from the best price.
+    ///
+    /// # Arguments
+    ///
+    /// * `side` - The [Side](Side) of the book: bids for [Buy](Side::Buy), asks for [Sell](Side::Sell).
+    ///
+    /// # Returns
+    ///
+    /// An [Iterator](Iterator) over references to [aggregate levels](AggregateLevel).
+    pub fn levels(&self, side: Side) -> impl Iterator<Item = &AggregateLevel> {
+        self.side(side).data.iter()
     }
 }
 
 
 /// A side of the consolidate trading book [AggregateBook](AggregateBook)
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 struct AggregateBookSide {
     /// The way price levels are ordered within this side
     ordering: Ranking,
     /// Maximum number of levels to maintain
     max_levels: usize,
+    /// Maximum number of levels to publish
+    published_levels: usize,
+    /// Whether updates are complete sides of the exchange books, rather than their best levels
+    full_depth: bool,
     /// The actual levels
     data: Vec<AggregateLevel>,
+    /// Depth bands maintained as the levels change
+    bands: Vec<DepthBandTotal>,
 }
 
 impl AggregateBookSide {
@@ -100,12 +342,34 @@ impl AggregateBookSide {
         let instance = Self {
             ordering,
             max_levels,
+            published_levels: max_levels,
+            full_depth: false,
             data,
+            bands: vec![],
         };
         instance.check_integrity();
         instance
     }
 
+    /// Creates a new empty [AggregateBookSide](AggregateBookSide) object, maintaining all the
+    /// levels of complete exchange books
+    ///
+    /// # Arguments
+    ///
+    /// * `ordering` - How to order levels in this book side
+    ///
+    /// * `published_levels` - Maximum number of price levels to publish
+    fn full_depth(ordering: Ranking, published_levels: usize) -> Self {
+        Self {
+            ordering,
+            max_levels: usize::MAX,
+            published_levels,
+            full_depth: true,
+            data: vec![],
+            bands: vec![],
+        }
+    }
+
     /// Utility function to check that price levels are ordered accoring to
     /// the `ordering` member. To be used when a new object is created from
     /// existing levels.
@@ -132,30 +396,139 @@ impl AggregateBookSide {
         self.data.len()
     }
 
-    /// Calculate the best `max_levels` price levels and return them in a [vector](Vec).
+    /// Calculate the best `published_levels` price levels and return them in a [vector](Vec).
     /// When the same price is available on multiple exchanges, each quantity offered
     /// represents a level, and they are ordered by amount decreasing.
     ///
     /// # Returns
     ///
     /// A [vector](Vec) of references to [exchange price levels](ExchangeLevel).
+    /// The levels of each price are sorted in place in the result, allocated once, except for
+    /// the last price when only some of its levels are published.
     fn best_levels(&self) -> Vec<&ExchangeLevel> {
-        let mut result: Vec<&ExchangeLevel> = vec![];
-        let mut levels_to_add = self.max_levels;
-        if !self.data.is_empty() {
-            for price_cons_level in &self.data {
-                let price_levels = price_cons_level.levels_by_amount();
-                let price_levels_to_add = min(price_levels.len(), levels_to_add);
-                result.extend_from_slice(&price_levels[0..price_levels_to_add]);
-                levels_to_add -= price_levels_to_add;
-                if levels_to_add == 0 {
-                    break;
-                }
+        let mut result: Vec<&ExchangeLevel> = Vec::with_capacity(self.published_levels);
+        for price_cons_level in &self.data {
+            let levels_to_add = self.published_levels - result.len();
+            if levels_to_add == 0 {
+                break;
+            }
+            if price_cons_level.exchange_levels.len() <= levels_to_add {
+                let start = result.len();
+                result.extend(price_cons_level.exchange_levels.values());
+                result[start..].sort_by_key(|&l| std::cmp::Reverse(l.amount));
+            } else {
+                result.extend_from_slice(&price_cons_level.levels_by_amount()[0..levels_to_add]);
             }
         }
         result
     }
 
+    /// Total amount from all the exchanges for the prices from the top of this side
+    /// down to a limit price, included.
+    ///
+    /// # Arguments
+    ///
+    /// `limit_price` - The limit price.
+    ///
+    /// # Returns
+    ///
+    /// A [Decimal](Decimal) amount.
+    fn amount_within(&self, limit_price: Decimal) -> Decimal {
+        self.data.iter()
+            .take_while(|level| !self.is_before(limit_price, level.price))
+            .flat_map(|level| level.exchange_levels.values())
+            .map(|level| level.amount)
+            .sum()
+    }
+
+    /// Limit price of a band of this side, at a distance from the mid price: below the mid
+    /// price for bids, above for asks.
+    fn band_limit(&self, mid_price: Decimal, bps: u32) -> Decimal {
+        let distance = mid_price * Decimal::from(bps) / Decimal::from(10_000);
+        match self.ordering {
+            Ranking::LessFirst => mid_price + distance,
+            Ranking::GreaterFirst => mid_price - distance,
+        }
+    }
+
+    /// Maintained total amount of a band, if maintained and the mid price is available.
+    fn band_amount(&self, bps: u32) -> Option<Decimal> {
+        self.bands.iter()
+            .find(|band| band.bps == bps && band.limit_price.is_some())
+            .map(|band| band.amount)
+    }
+
+    /// Number of levels from the top of this side down to a limit price, included.
+    fn levels_within(&self, limit_price: Decimal) -> usize {
+        self.data.partition_point(|level| !self.is_before(limit_price, level.price))
+    }
+
+    /// Account a change of the amount at a price in the totals of the bands including it.
+    ///
+    /// # Arguments
+    ///
+    /// `price` - The price.
+    ///
+    /// `delta` - The change of the total amount at the price.
+    fn add_to_bands(&mut self, price: Decimal, delta: Decimal) {
+        if delta.is_zero() {
+            return;
+        }
+        for band in self.bands.iter_mut() {
+            if let Some(limit_price) = band.limit_price {
+                let within = match self.ordering {
+                    Ranking::LessFirst => price <= limit_price,
+                    Ranking::GreaterFirst => price >= limit_price,
+                };
+                if within {
+                    band.amount += delta;
+                }
+            }
+        }
+    }
+
+    /// Move the limit prices of the bands with the mid price, adding or subtracting the
+    /// amounts of the levels between the former and the new limit prices only.
+    ///
+    /// # Arguments
+    ///
+    /// `mid_price` - The mid price, [None](None) if not available.
+    fn move_bands(&mut self, mid_price: Option<Decimal>) {
+        let mut bands = std::mem::take(&mut self.bands);
+        for band in bands.iter_mut() {
+            let limit_price = mid_price.map(|mid_price| self.band_limit(mid_price, band.bps));
+            match (band.limit_price, limit_price) {
+                (_, None) => band.amount = Decimal::ZERO,
+                (None, Some(limit_price)) => band.amount = self.amount_within(limit_price),
+                (Some(previous), Some(limit_price)) => {
+                    let (previous_end, end) = (self.levels_within(previous), self.levels_within(limit_price));
+                    if end > previous_end {
+                        band.amount += self.data[previous_end..end].iter().map(AggregateLevel::total_amount).sum::<Decimal>();
+                    } else {
+                        band.amount -= self.data[end..previous_end].iter().map(AggregateLevel::total_amount).sum::<Decimal>();
+                    }
+                },
+            }
+            band.limit_price = limit_price;
+        }
+        self.bands = bands;
+    }
+
+    /// Find the price level at a price.
+    ///
+    /// # Arguments
+    ///
+    /// `price` - The price.
+    ///
+    /// # Returns
+    ///
+    /// An optional reference to an [AggregateLevel](AggregateLevel).
+    fn level_at(&self, price: Decimal) -> Option<&AggregateLevel> {
+        self.data.iter()
+            .take_while(|level| !self.is_before(price, level.price))
+            .find(|level| level.price == price)
+    }
+
     /// Internal utility function to generalise price comparison based on the side's `ordering`.
     fn is_before(&self, price_a: Decimal, price_b: Decimal) -> bool {
         match self.ordering {
@@ -176,12 +549,51 @@ impl AggregateBookSide {
     ///
     /// `side_update` - A side of a trading book snapshot from an exchange
     fn update_side(&mut self, side_update: Vec<ExchangeLevel>) {
+        self.apply_updates(side_update);
+        self.data.retain(|level| !level.exchange_levels.is_empty());
+    }
+
+    /// Update the trading book side based on the complete corresponding side of an exchange
+    /// trading book: unlike [update_side](AggregateBookSide::update_side), the prices from the
+    /// same exchange beyond the last price of the update are removed too.
+    ///
+    /// # Arguments
+    ///
+    /// `exchange_code` - The exchange code
+    ///
+    /// `side_update` - A complete side of a trading book from an exchange
+    fn update_complete_side(&mut self, exchange_code: ExchangeId, side_update: Vec<ExchangeLevel>) {
+        let end = self.apply_updates(side_update);
+        for index in end..self.data.len() {
+            let delta = self.data[index].remove(&exchange_code);
+            self.add_to_bands(self.data[index].price, delta);
+        }
+        self.data.retain(|level| !level.exchange_levels.is_empty());
+    }
+
+    /// Internal function applying the levels of an update in order, leaving levels without
+    /// amounts in place. Returns the index following the last level updated.
+    fn apply_updates(&mut self, side_update: Vec<ExchangeLevel>) -> usize {
         let mut update_strategy = AggregateBookSideUpdateStrategy::new();
         for level_update in side_update {
             if !update_strategy.apply(self, level_update) {
                 break;
             }
         }
+        update_strategy.current_index
+    }
+
+    /// Remove all the price levels from an exchange from this side. Price levels left
+    /// without amounts from any exchange are removed.
+    ///
+    /// # Arguments
+    ///
+    /// `exchange_code` - The exchange code.
+    fn remove_exchange(&mut self, exchange_code: &str) {
+        for index in 0..self.data.len() {
+            let delta = self.data[index].remove(exchange_code);
+            self.add_to_bands(self.data[index].price, delta);
+        }
         self.data.retain(|level| !level.exchange_levels.is_empty());
     }
 }
@@ -247,6 +659,7 @@ impl AggregateBookSideUpdateStrategy {
             if side.len() >= side.max_levels {
                 false
             } else {
+                side.add_to_bands(level_update.price, level_update.amount);
                 side.data.push(AggregateLevel::from_level(level_update));
                 self.current_index += 1;
                 true
@@ -254,16 +667,19 @@ impl AggregateBookSideUpdateStrategy {
         } else {
             let price = side[self.current_index].price;
             if side.is_before(level_update.price, price) {
+                side.add_to_bands(level_update.price, level_update.amount);
                 side.data.insert(self.current_index, AggregateLevel::from_level(level_update));
                 self.current_index += 1;
                 true
             } else if level_update.price == price {
-                side.data[self.current_index].update(level_update);
+                let delta = side.data[self.current_index].update(level_update);
+                side.add_to_bands(price, delta);
                 self.current_index += 1;
                 true
             } else {
                 while side.is_before(side[self.current_index].price, level_update.price) {
-                    side.data[self.current_index].remove(level_update.exchange_code);
+                    let delta = side.data[self.current_index].remove(&level_update.exchange_code);
+                    side.add_to_bands(side[self.current_index].price, delta);
                     self.current_index += 1;
                     if self.current_index == side.len() {
                         break;
@@ -277,12 +693,12 @@ impl AggregateBookSideUpdateStrategy {
 
 /// A price level of one side of the aggregate trading book.
 /// Each price level can contain more than one amounts: one per exchange.
-#[derive(PartialEq, Debug)]
-struct AggregateLevel {
+#[derive(PartialEq, Debug, Clone)]
+pub struct AggregateLevel {
     /// The price
     price: Decimal,
     /// A map from the exchange code to the [price level](ExchangeLevel)s.
-    exchange_levels: HashMap<&'static str, ExchangeLevel>,
+    exchange_levels: HashMap<ExchangeId, ExchangeLevel>,
 }
 
 impl AggregateLevel {
@@ -299,7 +715,7 @@ impl AggregateLevel {
     fn from_level(level: ExchangeLevel) -> Self {
         Self {
             price: level.price,
-            exchange_levels: HashMap::from([(level.exchange_code, level)]),
+            exchange_levels: HashMap::from([(level.exchange_code.clone(), level)]),
         }
     }
 
@@ -321,9 +737,15 @@ impl AggregateLevel {
     /// # Arguments
     ///
     /// `level` - An exchange [price level](ExchangeLevel).
-    fn update(&mut self, level: ExchangeLevel) {
+    ///
+    /// # Returns
+    ///
+    /// The change of the total amount at this price.
+    fn update(&mut self, level: ExchangeLevel) -> Decimal {
         assert_eq!(self.price, level.price);
-        self.exchange_levels.insert(level.exchange_code, level);
+        let amount = level.amount;
+        let previous = self.exchange_levels.insert(level.exchange_code.clone(), level);
+        amount - previous.map(|previous| previous.amount).unwrap_or_default()
     }
 
     /// Remove the price level from an exchange from the aggregate price level.
@@ -331,13 +753,34 @@ impl AggregateLevel {
     /// # Arguments
     ///
     /// `exchange_code` - The exchange code.
-    fn remove(&mut self, exchange_code: &'static str) {
-        self.exchange_levels.remove(exchange_code);
+    ///
+    /// # Returns
+    ///
+    /// The change of the total amount at this price.
+    fn remove(&mut self, exchange_code: &str) -> Decimal {
+        self.exchange_levels.remove(exchange_code).map(|level| -level.amount).unwrap_or_default()
+    }
+
+    /// The price of this level.
+    pub fn price(&self) -> Decimal {
+        self.price
+    }
+
+    /// The amount of an exchange at this price.
+    ///
+    /// # Arguments
+    ///
+    /// `exchange_code` - The exchange code.
+    ///
+    /// # Returns
+    ///
+    /// An optional [Decimal](Decimal) amount, [None](None) if the exchange has no amount at this price.
+    pub fn amount_of(&self, exchange_code: &str) -> Option<Decimal> {
+        self.exchange_levels.get(exchange_code).map(|level| level.amount)
     }
 
     /// Utility function calculating the total amount for a price from all the exchanges.
-    #[cfg(test)]
-    fn total_amount(&self) -> Decimal {
+    pub fn total_amount(&self) -> Decimal {
         let mut result: Decimal = Decimal::zero();
         for level in self.exchange_levels.values() {
             result += level.amount;
@@ -345,14 +788,14 @@ impl AggregateLevel {
         result
     }
 
-    /// Return the exchange price levels for a price.
+    /// Return the exchange price levels for a price, ordered by amount decreasing.
     ///
     /// # Returns
     ///
     /// A [vector](Vec) of references to [exchange price level](ExchangeLevel)s.
-    fn levels_by_amount(&self) -> Vec<&ExchangeLevel> {
+    pub fn levels_by_amount(&self) -> Vec<&ExchangeLevel> {
         let mut levels: Vec<&ExchangeLevel> = self.exchange_levels.values().collect();
-        levels.sort_by(|&a, &b| b.amount.cmp(&a.amount));
+        levels.sort_by_key(|&l| std::cmp::Reverse(l.amount));
         levels
     }
 }
@@ -430,7 +873,7 @@ mod tests {
     fn test_empty_book() {
         let mut book = AggregateBook::new(3);
         let book_update = BookUpdate {
-            exchange_code: "test",
+            exchange_code: "test".into(),
             bids: vec![
                 ExchangeLevel::from_strs("test", "99", "10"),
                 ExchangeLevel::from_strs("test", "98", "10"),
@@ -458,6 +901,158 @@ mod tests {
         assert_eq!(book, exp_book);
     }
 
+    #[test]
+    fn test_remove_exchange() {
+        let mut book = AggregateBook::new(3);
+        book.update(BookUpdate {
+            exchange_code: "test1".into(),
+            bids: vec![ExchangeLevel::from_strs("test1", "99", "10"), ExchangeLevel::from_strs("test1", "98", "10")],
+            asks: vec![ExchangeLevel::from_strs("test1", "100", "10")],
+        });
+        book.update(BookUpdate {
+            exchange_code: "test2".into(),
+            bids: vec![ExchangeLevel::from_strs("test2", "99", "5")],
+            asks: vec![ExchangeLevel::from_strs("test2", "101", "5")],
+        });
+        book.remove_exchange("test1");
+        let exp_book = AggregateBook {
+            bids: AggregateBookSide::new(Ranking::GreaterFirst, 3, vec![
+                AggregateLevel::from_level(ExchangeLevel::from_strs("test2", "99", "5")),
+            ]),
+            asks: AggregateBookSide::new(Ranking::LessFirst, 3, vec![
+                AggregateLevel::from_level(ExchangeLevel::from_strs("test2", "101", "5")),
+            ]),
+        };
+        assert_eq!(book, exp_book);
+    }
+
+    #[test]
+    fn test_decimal_formatting() {
+        let mut book = AggregateBook::full_depth(3);
+        book.update(BookUpdate {
+            exchange_code: "test1".into(),
+            bids: vec![ExchangeLevel::from_strs("test1", "0.00001040", "100.0")],
+            asks: vec![ExchangeLevel::from_strs("test1", "0.00001050", "100.0")],
+        }.normalize());
+        book.update(BookUpdate {
+            exchange_code: "test2".into(),
+            bids: vec![ExchangeLevel::from_strs("test2", "0.000010400", "50")],
+            asks: vec![ExchangeLevel::from_strs("test2", "0.0000105", "50.00")],
+        }.normalize());
+        assert_eq!(book.level_count(Side::Buy), 1);
+        assert_eq!(book.level_count(Side::Sell), 1);
+        let price = Decimal::from_str("0.0000104").unwrap();
+        assert_eq!(book.amount_at(Side::Buy, price), Decimal::from(150));
+        assert_eq!(book.venues_at(Side::Buy, price).len(), 2);
+        // a single representation, whichever exchange created the level
+        let bid = book.levels(Side::Buy).next().unwrap();
+        assert_eq!(bid.price().to_string(), "0.0000104");
+        assert_eq!(bid.total_amount().to_string(), "150");
+        assert_eq!(book.levels(Side::Sell).next().unwrap().price().to_string(), "0.0000105");
+    }
+
+    #[test]
+    fn test_full_depth() {
+        let mut book = AggregateBook::full_depth(2);
+        book.update(BookUpdate {
+            exchange_code: "test1".into(),
+            bids: vec![
+                ExchangeLevel::from_strs("test1", "99", "1"),
+                ExchangeLevel::from_strs("test1", "98", "1"),
+                ExchangeLevel::from_strs("test1", "97", "1"),
+                ExchangeLevel::from_strs("test1", "96", "1"),
+            ],
+            asks: vec![ExchangeLevel::from_strs("test1", "100", "1")],
+        });
+        book.update(BookUpdate {
+            exchange_code: "test2".into(),
+            bids: vec![ExchangeLevel::from_strs("test2", "98", "2"), ExchangeLevel::from_strs("test2", "95", "2")],
+            asks: vec![],
+        });
+        assert_eq!(book.level_count(Side::Buy), 5);
+        assert_eq!(book.best_bids(), vec![
+            &ExchangeLevel::from_strs("test1", "99", "1"),
+            &ExchangeLevel::from_strs("test2", "98", "2"),
+        ]);
+        // levels missing from a complete update are removed, beyond its last price too
+        book.update(BookUpdate {
+            exchange_code: "test1".into(),
+            bids: vec![ExchangeLevel::from_strs("test1", "98", "1")],
+            asks: vec![],
+        });
+        assert_eq!(book.levels(Side::Buy).map(|level| level.price).collect::<Vec<_>>(), vec![
+            Decimal::from(98), Decimal::from(95),
+        ]);
+        assert_eq!(book.level_count(Side::Sell), 0);
+        assert_eq!(book.amount_at(Side::Buy, Decimal::from(98)), Decimal::from(3));
+    }
+
+    #[test]
+    fn test_depth_within() {
+        let mut book = AggregateBook::new(10);
+        assert_eq!(book.mid_price(), None);
+        assert_eq!(book.depth_within(10), None);
+        book.update(BookUpdate {
+            exchange_code: "test1".into(),
+            bids: vec![
+                ExchangeLevel::from_strs("test1", "99.9", "1"),
+                ExchangeLevel::from_strs("test1", "99.85", "2"),
+                ExchangeLevel::from_strs("test1", "99", "4"),
+            ],
+            asks: vec![
+                ExchangeLevel::from_strs("test1", "100.1", "1"),
+                ExchangeLevel::from_strs("test1", "100.2", "2"),
+            ],
+        });
+        book.update(BookUpdate {
+            exchange_code: "test2".into(),
+            bids: vec![ExchangeLevel::from_strs("test2", "99.9", "3")],
+            asks: vec![ExchangeLevel::from_strs("test2", "100.15", "5")],
+        });
+        assert_eq!(book.mid_price(), Some(Decimal::from_str("100").unwrap()));
+        assert_eq!(book.depth_within(10), Some((Decimal::from(4), Decimal::from(1))));
+        assert_eq!(book.depth_within(15), Some((Decimal::from(6), Decimal::from(6))));
+        assert_eq!(book.depth_within(100), Some((Decimal::from(10), Decimal::from(8))));
+    }
+
+    #[test]
+    fn test_depth_bands_maintained() {
+        for full_depth in [false, true] {
+            let book = if full_depth { AggregateBook::full_depth(10) } else { AggregateBook::new(10) };
+            let mut book = book.with_depth_bands(vec![10, 50]);
+            let scanned = |book: &AggregateBook, bps: u32| {
+                let mid_price = book.mid_price()?;
+                Some((
+                    book.bids.amount_within(book.bids.band_limit(mid_price, bps)),
+                    book.asks.amount_within(book.asks.band_limit(mid_price, bps)),
+                ))
+            };
+            let updates = [
+                ("test1", vec![("99.9", "1"), ("99.6", "2"), ("99", "4")], vec![("100.1", "1"), ("100.4", "2")]),
+                ("test2", vec![("99.95", "3"), ("99.6", "1")], vec![("100.05", "5"), ("100.4", "1")]),
+                // the mid price moves up, the bands with it
+                ("test1", vec![("100.2", "2"), ("99.9", "1")], vec![("100.3", "1"), ("100.6", "3")]),
+                // the mid price moves down, past the previous levels
+                ("test2", vec![("99.5", "6")], vec![("99.8", "2"), ("100.4", "1")]),
+                ("test1", vec![("99.7", "1")], vec![("100.6", "3")]),
+            ];
+            for (exchange_code, bids, asks) in updates {
+                book.update(BookUpdate {
+                    exchange_code: exchange_code.into(),
+                    bids: bids.iter().map(|(price, amount)| ExchangeLevel::from_strs(exchange_code, price, amount)).collect(),
+                    asks: asks.iter().map(|(price, amount)| ExchangeLevel::from_strs(exchange_code, price, amount)).collect(),
+                });
+                for bps in [10, 50] {
+                    assert_eq!(book.depth_within(bps), scanned(&book, bps), "full depth {}, {} bps", full_depth, bps);
+                }
+            }
+            book.remove_exchange("test2");
+            assert_eq!(book.depth_within(50), scanned(&book, 50));
+            book.remove_exchange("test1");
+            assert_eq!(book.depth_within(50), None);
+        }
+    }
+
     #[test]
     fn test_insert_into_bids_side() {
         let mut bids = AggregateBookSide::new(Ranking::GreaterFirst, 10, vec![
@@ -829,7 +1424,7 @@ mod tests {
             ]),
         };
         let book_update1 = BookUpdate {
-            exchange_code: "test1",
+            exchange_code: "test1".into(),
             bids: vec![
                 ExchangeLevel::from_strs("test1", "100", "10"),
                 ExchangeLevel::from_strs("test1", "99", "10"),
@@ -846,7 +1441,7 @@ mod tests {
         };
         book.update(book_update1);
         let book_update2 = BookUpdate {
-            exchange_code: "test2",
+            exchange_code: "test2".into(),
             bids: vec![
                 ExchangeLevel::from_strs("test2", "100", "20"),
                 ExchangeLevel::from_strs("test2", "97", "15"),
@@ -899,6 +1494,60 @@ mod tests {
         assert_eq!(ask6.total_amount(), Decimal::from_str("10").unwrap());
     }
 
+    #[test]
+    fn test_book_queries() {
+        let book = AggregateBook {
+            bids: AggregateBookSide::new(Ranking::GreaterFirst, 10, vec![
+                AggregateLevel::from_levels(vec![
+                    ExchangeLevel::from_strs("test1", "99", "5"),
+                    ExchangeLevel::from_strs("test2", "99", "10"),
+                ]),
+                AggregateLevel::from_level(ExchangeLevel::from_strs("test1", "97", "10")),
+            ]),
+            asks: AggregateBookSide::new(Ranking::LessFirst, 10, vec![
+                AggregateLevel::from_level(ExchangeLevel::from_strs("test2", "102", "3")),
+            ]),
+        };
+        let price = |p: &str| Decimal::from_str(p).unwrap();
+        assert_eq!(book.amount_at(Side::Buy, price("99")), price("15"));
+        assert_eq!(book.amount_at(Side::Buy, price("98")), Decimal::ZERO);
+        assert_eq!(book.amount_at(Side::Sell, price("102")), price("3"));
+        assert_eq!(
+            book.venues_at(Side::Buy, price("99")),
+            vec![&ExchangeLevel::from_strs("test2", "99", "10"), &ExchangeLevel::from_strs("test1", "99", "5")]
+        );
+        assert!(book.venues_at(Side::Sell, price("99")).is_empty());
+        assert_eq!(book.level_count(Side::Buy), 2);
+        assert_eq!(book.level_count(Side::Sell), 1);
+        let bid_levels: Vec<(Decimal, Decimal)> = book.levels(Side::Buy).map(|l| (l.price(), l.total_amount())).collect();
+        assert_eq!(bid_levels, vec![(price("99"), price("15")), (price("97"), price("10"))]);
+    }
+
+    #[test]
+    fn test_cumulative() {
+        let book = AggregateBook {
+            bids: AggregateBookSide::new(Ranking::GreaterFirst, 10, vec![]),
+            asks: AggregateBookSide::new(Ranking::LessFirst, 10, vec![
+                AggregateLevel::from_levels(vec![
+                    ExchangeLevel::from_strs("test1", "100", "1"),
+                    ExchangeLevel::from_strs("test2", "100", "1"),
+                ]),
+                AggregateLevel::from_level(ExchangeLevel::from_strs("test1", "110", "2")),
+            ]),
+        };
+        let d = |p: &str| Decimal::from_str(p).unwrap();
+        assert_eq!(book.cumulative(Side::Sell, d("150")), Some(Sweep {
+            worst_price: d("100"), average_price: d("100"), amount: d("1.5"), notional: d("150"),
+        }));
+        assert_eq!(book.cumulative(Side::Sell, d("310")), Some(Sweep {
+            worst_price: d("110"), average_price: d("310") / d("3"), amount: d("3"), notional: d("310"),
+        }));
+        let exhausted = book.cumulative(Side::Sell, d("1000")).unwrap();
+        assert_eq!((exhausted.worst_price, exhausted.amount, exhausted.notional), (d("110"), d("4"), d("420")));
+        assert_eq!(book.cumulative(Side::Buy, d("100")), None);
+        assert_eq!(book.cumulative(Side::Sell, Decimal::ZERO), None);
+    }
+
     #[test]
     fn test_book_update_panics_if_wrong_order() {
         let mut book = AggregateBook {
@@ -914,7 +1563,7 @@ mod tests {
             ]),
         };
         let book_update = BookUpdate {
-            exchange_code: "test1",
+            exchange_code: "test1".into(),
             bids: vec![
                 ExchangeLevel::from_strs("test1", "99", "10"), // <- wrong order
                 ExchangeLevel::from_strs("test1", "100", "10"),
@@ -0,0 +1,431 @@
+//! Rules engine raising alerts on conditions of the consolidated trading book, such as
+//! a wide spread, a stale exchange or a crossed book. Alerts are logged, broadcast to
+//! subscribers and, with the `webhook` feature, posted to a webhook.
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use tokio::time::{Duration, Instant};
+
+use crate::clock::SharedClock;
+use crate::config::{DocumentedConfig, Validate, Validator};
+use crate::feed::{venue_bbos, FeedReceiver, VenueBbo};
+use crate::maintenance::MaintenanceSchedule;
+use crate::orderbook::Summary;
+#[cfg(feature = "webhook")]
+use crate::webhook::Webhook;
+
+
+/// Interval between two evaluations of the rules when no summary is published.
+const EVALUATION_INTERVAL: Duration = Duration::from_secs(1);
+
+
+/// A condition of the book, checked on each summary.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "condition", rename_all = "snake_case")]
+pub enum Condition {
+    /// The spread is wider than a number of basis points of the mid price.
+    SpreadAbove {
+        /// Threshold, in basis points.
+        bps: f64,
+    },
+    /// The best prices of an exchange did not change since the previous summary,
+    /// or the exchange is missing from the book.
+    VenueStale {
+        /// Exchange code.
+        exchange: String,
+    },
+    /// The best bid is not lower than the best ask.
+    Crossed,
+}
+
+impl Condition {
+    /// Check the condition.
+    ///
+    /// # Arguments
+    ///
+    /// * `summary` - The current [Summary](Summary).
+    ///
+    /// * `venues` - The best prices of each exchange in the current summary.
+    ///
+    /// * `previous_venues` - The best prices of each exchange in the previous summary,
+    ///   [None](None) if no new summary was published.
+    fn holds(&self, summary: &Summary, venues: &[VenueBbo], previous_venues: Option<&[VenueBbo]>) -> bool {
+        match self {
+            Condition::SpreadAbove { bps } => match (summary.bids.first(), summary.asks.first()) {
+                (Some(bid), Some(ask)) => {
+                    let mid = (bid.price + ask.price) / 2.0;
+                    mid > 0.0 && (ask.price - bid.price) / mid * 10_000.0 > *bps
+                }
+                _ => false,
+            },
+            Condition::VenueStale { exchange } => {
+                let find = |venues: &[VenueBbo]| venues.iter()
+                    .find(|v| &v.exchange == exchange)
+                    .map(|v| (v.best_bid.to_bits(), v.best_ask.to_bits()));
+                match (find(venues), previous_venues) {
+                    (None, _) | (Some(_), None) => true,
+                    (current, Some(previous_venues)) => current == find(previous_venues),
+                }
+            },
+            Condition::Crossed => match (summary.bids.first(), summary.asks.first()) {
+                (Some(bid), Some(ask)) => bid.price >= ask.price,
+                _ => false,
+            },
+        }
+    }
+
+    /// Human readable description of the condition.
+    fn describe(&self) -> String {
+        match self {
+            Condition::SpreadAbove { bps } => format!("spread above {} bps", bps),
+            Condition::VenueStale { exchange } => format!("{} stale", exchange),
+            Condition::Crossed => "book crossed".to_string(),
+        }
+    }
+}
+
+/// A named [Condition](Condition), raising an alert once it held for a minimum time.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AlertRule {
+    /// Name of the rule, reported in the alerts.
+    pub name: String,
+    /// The condition.
+    #[serde(flatten)]
+    pub condition: Condition,
+    /// How long the condition must hold before the alert is raised, in milliseconds.
+    #[serde(default)]
+    pub for_ms: u64,
+}
+
+/// Alerting configuration, read from a JSON file.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct AlertsConfig {
+    /// The rules.
+    #[serde(default)]
+    pub rules: Vec<AlertRule>,
+    /// URL where alerts are posted, with the `webhook` feature.
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+}
+
+impl Validate for AlertsConfig {
+    fn validate(&self, validator: &mut Validator) {
+        for (i, rule) in self.rules.iter().enumerate() {
+            if rule.name.is_empty() {
+                validator.error(&format!("rules[{}].name", i), "must not be empty");
+            }
+            if let Condition::SpreadAbove { bps } = rule.condition {
+                validator.positive_f64(&format!("rules[{}].bps", i), bps);
+            }
+        }
+        if let Some(webhook_url) = &self.webhook_url {
+            validator.url("webhook_url", webhook_url, &["http"]);
+        }
+    }
+}
+
+impl DocumentedConfig for AlertsConfig {
+    const DESCRIPTION: &'static str = "Alerting rules, each with a name, a condition (spread_above with bps, venue_stale with exchange, or crossed) and an optional for_ms.";
+    const FIELDS: &'static [(&'static str, &'static str)] = &[
+        ("rules", "The rules."),
+        ("webhook_url", "URL where alerts are posted, with the webhook feature, null for none."),
+    ];
+}
+
+/// Raising or clearing of an alert.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AlertEvent {
+    /// Name of the rule.
+    pub rule: String,
+    /// Canonical symbol.
+    pub symbol: String,
+    /// True if the alert is raised, false if it is cleared.
+    pub raised: bool,
+    /// Description of the condition.
+    pub message: String,
+    /// Time of the event, in milliseconds since the UNIX epoch.
+    pub timestamp_ms: u64,
+}
+
+/// Evaluation state of an [AlertRule](AlertRule).
+struct RuleState {
+    /// The rule.
+    rule: AlertRule,
+    /// Instant since which the condition holds.
+    since: Option<Instant>,
+    /// The alert is raised.
+    raised: bool,
+}
+
+/// Evaluates [rules](AlertRule) on the summaries of a feed.
+pub struct AlertEngine {
+    /// The rules and their state.
+    rules: Vec<RuleState>,
+    /// Latest summary, with the best prices of each exchange.
+    last_summary: Option<(Summary, Vec<VenueBbo>)>,
+    /// Time source for the rule durations.
+    clock: SharedClock,
+    /// Maintenance windows, during which the staleness of the exchange is expected.
+    maintenance: MaintenanceSchedule,
+    /// Webhook where alerts are posted.
+    #[cfg(feature = "webhook")]
+    webhook: Option<Webhook>,
+}
+
+impl AlertEngine {
+    /// Create a new [AlertEngine](AlertEngine) object.
+    ///
+    /// # Arguments
+    ///
+    /// * `rules` - The [rules](AlertRule).
+    ///
+    /// * `clock` - Time source for the rule durations.
+    pub fn new(rules: Vec<AlertRule>, clock: SharedClock) -> Self {
+        Self {
+            rules: rules.into_iter().map(|rule| RuleState { rule, since: None, raised: false }).collect(),
+            last_summary: None,
+            clock,
+            maintenance: MaintenanceSchedule::default(),
+            #[cfg(feature = "webhook")]
+            webhook: None,
+        }
+    }
+
+    /// Create a new [AlertEngine](AlertEngine) object from a configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The [AlertsConfig](AlertsConfig).
+    ///
+    /// * `clock` - Time source for the rule durations.
+    pub fn from_config(config: AlertsConfig, clock: SharedClock) -> Self {
+        let engine = Self::new(config.rules, clock);
+        match config.webhook_url {
+            #[cfg(feature = "webhook")]
+            Some(url) => engine.with_webhook(Webhook::new(&url)),
+            #[cfg(not(feature = "webhook"))]
+            Some(_) => {
+                warn!("Alert webhook ignored, the server was built without the webhook feature");
+                engine
+            },
+            None => engine,
+        }
+    }
+
+    /// Do not raise [stale](Condition::VenueStale) alerts for the exchanges in a maintenance window.
+    ///
+    /// # Arguments
+    ///
+    /// * `maintenance` - The [MaintenanceSchedule](MaintenanceSchedule).
+    ///
+    /// # Returns
+    ///
+    /// The modified [AlertEngine](AlertEngine).
+    pub fn with_maintenance(mut self, maintenance: MaintenanceSchedule) -> Self {
+        self.maintenance = maintenance;
+        self
+    }
+
+    /// Post the alerts to a webhook.
+    ///
+    /// # Arguments
+    ///
+    /// * `webhook` - The [Webhook](Webhook).
+    ///
+    /// # Returns
+    ///
+    /// The modified [AlertEngine](AlertEngine).
+    #[cfg(feature = "webhook")]
+    pub fn with_webhook(mut self, webhook: Webhook) -> Self {
+        self.webhook = Some(webhook);
+        self
+    }
+
+    /// Evaluate the rules.
+    ///
+    /// # Arguments
+    ///
+    /// * `summary` - A new [Summary](Summary), or [None](None) to evaluate the latest one again
+    ///   after some time passed.
+    ///
+    /// # Returns
+    ///
+    /// The [alert events](AlertEvent) for the rules whose alert was raised or cleared.
+    pub fn evaluate(&mut self, summary: Option<Summary>) -> Vec<AlertEvent> {
+        // compared with the first summary, no exchange is stale
+        let previous_venues = summary.map(|summary| {
+            let venues = venue_bbos(&summary);
+            self.last_summary.replace((summary, venues)).map(|(_, venues)| venues).unwrap_or_default()
+        });
+        let Some((summary, venues)) = &self.last_summary else {
+            return vec![];
+        };
+        let now = self.clock.now();
+        let wall_time = SystemTime::now();
+        let timestamp_ms = wall_time.duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        let mut events = vec![];
+        for state in &mut self.rules {
+            let scheduled_offline = match &state.rule.condition {
+                Condition::VenueStale { exchange } => self.maintenance.is_offline(exchange, wall_time),
+                _ => false,
+            };
+            let holds = !scheduled_offline && state.rule.condition.holds(summary, venues, previous_venues.as_deref());
+            if holds {
+                let since = *state.since.get_or_insert(now);
+                if !state.raised && now - since >= Duration::from_millis(state.rule.for_ms) {
+                    state.raised = true;
+                    events.push(AlertEvent {
+                        rule: state.rule.name.clone(),
+                        symbol: summary.symbol.clone(),
+                        raised: true,
+                        message: state.rule.condition.describe(),
+                        timestamp_ms,
+                    });
+                }
+            } else {
+                state.since = None;
+                if state.raised {
+                    state.raised = false;
+                    events.push(AlertEvent {
+                        rule: state.rule.name.clone(),
+                        symbol: summary.symbol.clone(),
+                        raised: false,
+                        message: state.rule.condition.describe(),
+                        timestamp_ms,
+                    });
+                }
+            }
+        }
+        events
+    }
+
+    /// Spawn a task evaluating the rules on each summary of a feed, and at regular intervals.
+    ///
+    /// # Arguments
+    ///
+    /// * `feed` - A [FeedReceiver](FeedReceiver).
+    ///
+    /// * `alerts` - A [broadcast::Sender](broadcast::Sender) delivering the alerts to subscribers.
+    pub fn spawn(mut self, mut feed: FeedReceiver, alerts: broadcast::Sender<AlertEvent>) {
+        tokio::spawn(async move {
+            loop {
+                let summary = tokio::select! {
+                    changed = feed.changed() => {
+                        if changed.is_err() {
+                            info!("Feed closed, alerts stopped");
+                            break;
+                        }
+                        feed.borrow_and_update().clone()
+                    },
+                    _ = self.clock.sleep(EVALUATION_INTERVAL) => None,
+                };
+                for event in self.evaluate(summary) {
+                    if event.raised {
+                        warn!("Alert raised: {} ({}, {})", event.rule, event.symbol, event.message);
+                    } else {
+                        info!("Alert cleared: {} ({}, {})", event.rule, event.symbol, event.message);
+                    }
+                    #[cfg(feature = "webhook")]
+                    if let Some(webhook) = self.webhook.clone() {
+                        let event = event.clone();
+                        tokio::spawn(async move {
+                            if let Err(error) = webhook.post(&event).await {
+                                log::error!("Error posting alert to webhook: {:?}", error);
+                            }
+                        });
+                    }
+                    // no subscribers is not an error
+                    let _ = alerts.send(event);
+                }
+            }
+        });
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::clock::ManualClock;
+    use crate::orderbook::Level;
+
+    fn summary(bids: &[(&str, f64)], asks: &[(&str, f64)]) -> Summary {
+        let levels = |levels: &[(&str, f64)]| levels.iter()
+            .map(|(exchange, price)| Level { exchange: exchange.to_string(), price: *price, amount: 1.0, order_count: None, latency_class: 0 })
+            .collect();
+        Summary { spread: f64::NAN, bids: levels(bids), asks: levels(asks), symbol: "ETH-BTC".to_string(), depth: vec![], ..Default::default() }
+    }
+
+    fn rule(name: &str, condition: Condition, for_ms: u64) -> AlertRule {
+        AlertRule { name: name.to_string(), condition, for_ms }
+    }
+
+    fn raised(events: &[AlertEvent]) -> Vec<(&str, bool)> {
+        events.iter().map(|e| (e.rule.as_str(), e.raised)).collect()
+    }
+
+    #[test]
+    fn test_spread_held_for_duration() {
+        let clock = Arc::new(ManualClock::new());
+        let mut engine = AlertEngine::new(vec![rule("wide", Condition::SpreadAbove { bps: 20.0 }, 5000)], clock.clone());
+        assert!(engine.evaluate(Some(summary(&[("a", 99.0)], &[("a", 101.0)]))).is_empty());
+        clock.advance(Duration::from_secs(4));
+        assert!(engine.evaluate(None).is_empty());
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(raised(&engine.evaluate(None)), vec![("wide", true)]);
+        assert!(engine.evaluate(None).is_empty());
+        assert_eq!(raised(&engine.evaluate(Some(summary(&[("a", 100.0)], &[("a", 100.1)])))), vec![("wide", false)]);
+    }
+
+    #[test]
+    fn test_crossed_and_stale() {
+        let clock = Arc::new(ManualClock::new());
+        let mut engine = AlertEngine::new(vec![
+            rule("crossed", Condition::Crossed, 0),
+            rule("stale", Condition::VenueStale { exchange: "b".to_string() }, 10_000),
+        ], clock.clone());
+        assert!(engine.evaluate(Some(summary(&[("a", 99.0), ("b", 98.0)], &[("b", 101.0)]))).is_empty());
+        clock.advance(Duration::from_secs(5));
+        assert!(engine.evaluate(Some(summary(&[("a", 99.5), ("b", 98.0)], &[("b", 101.0)]))).is_empty());
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(raised(&engine.evaluate(Some(summary(&[("a", 102.0), ("b", 98.0)], &[("b", 101.0)])))),
+                   vec![("crossed", true), ("stale", true)]);
+        assert_eq!(raised(&engine.evaluate(Some(summary(&[("a", 99.0), ("b", 98.5)], &[("b", 101.0)])))),
+                   vec![("crossed", false), ("stale", false)]);
+    }
+
+    #[test]
+    fn test_stale_during_maintenance() {
+        let clock = Arc::new(ManualClock::new());
+        let now = SystemTime::now();
+        let maintenance = MaintenanceSchedule::default().with_window("b", now - Duration::from_secs(60), now + Duration::from_secs(3600));
+        let mut engine = AlertEngine::new(vec![
+            rule("stale a", Condition::VenueStale { exchange: "a".to_string() }, 0),
+            rule("stale b", Condition::VenueStale { exchange: "b".to_string() }, 0),
+        ], clock.clone()).with_maintenance(maintenance);
+        assert_eq!(raised(&engine.evaluate(Some(summary(&[("c", 99.0)], &[("c", 101.0)])))), vec![("stale a", true)]);
+    }
+
+    #[test]
+    fn test_config() {
+        let config: AlertsConfig = serde_json::from_str(r#"{
+            "rules": [
+                {"name": "wide", "condition": "spread_above", "bps": 20, "for_ms": 5000},
+                {"name": "stale", "condition": "venue_stale", "exchange": "binance"},
+                {"name": "crossed", "condition": "crossed"}
+            ],
+            "webhook_url": "http://localhost/alerts"
+        }"#).unwrap();
+        assert_eq!(config.rules, vec![
+            rule("wide", Condition::SpreadAbove { bps: 20.0 }, 5000),
+            rule("stale", Condition::VenueStale { exchange: "binance".to_string() }, 0),
+            rule("crossed", Condition::Crossed, 0),
+        ]);
+        assert_eq!(config.webhook_url.as_deref(), Some("http://localhost/alerts"));
+    }
+}
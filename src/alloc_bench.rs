@@ -0,0 +1,129 @@
+//! Allocation benchmark: runs the server against a simulated exchange with many concurrent
+//! summary streams, and reports the allocations of the whole process for each summary
+//! delivered, to track the allocator pressure of the response path.
+
+use log::{LevelFilter, info};
+use simple_logger::SimpleLogger;
+use std::alloc::{GlobalAlloc, Layout};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::{env, net::{Ipv6Addr, TcpListener}};
+use tokio::time::{sleep, timeout, Duration};
+use tokio_stream::StreamExt;
+
+use orderbook_server::accounting::UsageRegistry;
+use orderbook_server::allocator::{stats, ALLOCATOR_NAME, GLOBAL_ALLOCATOR};
+use orderbook_server::cli::ArgParser;
+use orderbook_server::clock::system_clock;
+use orderbook_server::grpc::ProtobufOrderbookServer;
+use orderbook_server::orderbook::{orderbook_aggregator_client::OrderbookAggregatorClient, BookSummaryRequest};
+use orderbook_server::simulated::{book_update_message, SimulatedExchange};
+use orderbook_server::symbols::parse_currency_pair;
+
+
+const USAGE_MESSAGE: &str = "Usage: alloc_bench [streams] [snapshots]";
+const DEFAULT_STREAMS: usize = 100;
+const DEFAULT_SNAPSHOTS: usize = 200;
+/// Interval between two snapshots published by the simulated exchange.
+const PUBLISH_INTERVAL_MS: u64 = 5;
+/// Maximum wait for the connections of the streams and for the delivery of the summaries.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+
+/// Global allocator counting allocations and allocated bytes, on top of the
+/// [allocator selected](orderbook_server::allocator).
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        GLOBAL_ALLOCATOR.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        GLOBAL_ALLOCATOR.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size as u64, Ordering::Relaxed);
+        GLOBAL_ALLOCATOR.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+
+/// Levels of a side of the published snapshots, ten prices from `start` by `step`.
+fn levels(start: f64, step: f64) -> Vec<(String, String)> {
+    (0..10).map(|i| (format!("{:.2}", start + step * i as f64), format!("{}", i + 1))).collect()
+}
+
+#[tokio::main]
+async fn main() {
+    SimpleLogger::new().with_level(LevelFilter::Warn).env().init().unwrap();
+    let mut arg_parser = ArgParser::new(env::args(), USAGE_MESSAGE);
+    let streams = arg_parser.extract_number(DEFAULT_STREAMS);
+    let snapshots = arg_parser.extract_number(DEFAULT_SNAPSHOTS);
+
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    let exchange = SimulatedExchange::start().await.expect("Could not start simulated exchange");
+    let server = ProtobufOrderbookServer::new(product.clone(), vec![Box::new(exchange.adapter(&product))], UsageRegistry::new(system_clock()));
+    let port = TcpListener::bind((Ipv6Addr::LOCALHOST, 0)).unwrap().local_addr().unwrap().port();
+    tokio::spawn(async move {
+        let _ = server.serve(port).await;
+    });
+
+    let server_url = format!("http://[::1]:{}", port);
+    let client = timeout(TIMEOUT, async {
+        loop {
+            match OrderbookAggregatorClient::connect(server_url.clone()).await {
+                Ok(client) => break client,
+                Err(_) => sleep(Duration::from_millis(50)).await,
+            }
+        }
+    }).await.expect("Server not started");
+    let mut receivers = vec![];
+    for _ in 0..streams {
+        let summaries = client.clone().book_summary(BookSummaryRequest::default()).await.expect("Stream not opened").into_inner();
+        receivers.push(tokio::spawn(async move {
+            let mut summaries = summaries.take(snapshots);
+            let mut count = 0;
+            while let Some(Ok(_)) = summaries.next().await {
+                count += 1;
+            }
+            count
+        }));
+    }
+    timeout(TIMEOUT, exchange.wait_for_connections(streams)).await.expect("Streams not connected");
+
+    let allocations_before = ALLOCATIONS.load(Ordering::Relaxed);
+    let bytes_before = ALLOCATED_BYTES.load(Ordering::Relaxed);
+    let asks = levels(101.0, 0.5);
+    let asks: Vec<(&str, &str)> = asks.iter().map(|(price, amount)| (price.as_str(), amount.as_str())).collect();
+    for i in 0..snapshots {
+        let bids = levels(100.0 - (i % 50) as f64 / 100.0, -0.5);
+        let bids: Vec<(&str, &str)> = bids.iter().map(|(price, amount)| (price.as_str(), amount.as_str())).collect();
+        exchange.publish(book_update_message(&bids, &asks));
+        sleep(Duration::from_millis(PUBLISH_INTERVAL_MS)).await;
+    }
+    let mut delivered = 0;
+    for receiver in receivers {
+        delivered += timeout(TIMEOUT, receiver).await.expect("Summaries not delivered").unwrap();
+    }
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations_before;
+    let bytes = ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes_before;
+
+    info!("Delivered {} summaries on {} streams", delivered, streams);
+    println!(
+        "{} allocator, {} streams, {} summaries: {:.1} allocations and {:.0} bytes allocated per summary",
+        ALLOCATOR_NAME, streams, delivered, allocations as f64 / delivered as f64, bytes as f64 / delivered as f64,
+    );
+    if let Some(stats) = stats() {
+        println!("{} bytes allocated, {} bytes resident", stats.allocated_bytes, stats.resident_bytes);
+    }
+}
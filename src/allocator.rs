@@ -0,0 +1,151 @@
+//! Global allocator of the binaries, selected by feature: `jemalloc`, `mimalloc`, or the
+//! system allocator by default (`jemalloc` wins if both features are enabled). Binaries
+//! declare it with
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static GLOBAL: GlobalAllocator = GLOBAL_ALLOCATOR;
+//! ```
+//!
+//! The statistics of jemalloc and mimalloc are [sampled](record_stats) in
+//! [ALLOCATOR_MEMORY](crate::metrics::ALLOCATOR_MEMORY).
+
+use log::debug;
+use tokio::time::{interval, Duration};
+
+use crate::metrics::ALLOCATOR_MEMORY;
+
+
+/// Type of the global allocator.
+#[cfg(feature = "jemalloc")]
+pub type GlobalAllocator = tikv_jemallocator::Jemalloc;
+/// The global allocator.
+#[cfg(feature = "jemalloc")]
+pub const GLOBAL_ALLOCATOR: GlobalAllocator = tikv_jemallocator::Jemalloc;
+/// Name of the global allocator.
+#[cfg(feature = "jemalloc")]
+pub const ALLOCATOR_NAME: &str = "jemalloc";
+
+/// Type of the global allocator.
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+pub type GlobalAllocator = mimalloc::MiMalloc;
+/// The global allocator.
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+pub const GLOBAL_ALLOCATOR: GlobalAllocator = mimalloc::MiMalloc;
+/// Name of the global allocator.
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+pub const ALLOCATOR_NAME: &str = "mimalloc";
+
+/// Type of the global allocator.
+#[cfg(not(any(feature = "jemalloc", feature = "mimalloc")))]
+pub type GlobalAllocator = std::alloc::System;
+/// The global allocator.
+#[cfg(not(any(feature = "jemalloc", feature = "mimalloc")))]
+pub const GLOBAL_ALLOCATOR: GlobalAllocator = std::alloc::System;
+/// Name of the global allocator.
+#[cfg(not(any(feature = "jemalloc", feature = "mimalloc")))]
+pub const ALLOCATOR_NAME: &str = "system";
+
+
+/// Memory statistics of the global allocator.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct AllocatorStats {
+    /// Bytes allocated by the application (jemalloc), or committed by the allocator (mimalloc).
+    pub allocated_bytes: u64,
+    /// Bytes of physical memory mapped by the allocator.
+    pub resident_bytes: u64,
+}
+
+/// Read the statistics of the global allocator.
+///
+/// # Returns
+///
+/// The [AllocatorStats](AllocatorStats), [None](None) for the system allocator, which keeps no
+/// statistics.
+#[cfg(feature = "jemalloc")]
+pub fn stats() -> Option<AllocatorStats> {
+    use tikv_jemalloc_ctl::{epoch, stats};
+    // statistics are cached by jemalloc until the epoch advances
+    epoch::advance().ok()?;
+    Some(AllocatorStats {
+        allocated_bytes: stats::allocated::read().ok()? as u64,
+        resident_bytes: stats::resident::read().ok()? as u64,
+    })
+}
+
+/// Read the statistics of the global allocator.
+///
+/// # Returns
+///
+/// The [AllocatorStats](AllocatorStats), [None](None) for the system allocator, which keeps no
+/// statistics.
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+pub fn stats() -> Option<AllocatorStats> {
+    let (mut elapsed, mut user, mut system) = (0, 0, 0);
+    let (mut current_rss, mut peak_rss, mut current_commit, mut peak_commit, mut page_faults) = (0, 0, 0, 0, 0);
+    unsafe {
+        libmimalloc_sys::mi_process_info(
+            &mut elapsed, &mut user, &mut system,
+            &mut current_rss, &mut peak_rss, &mut current_commit, &mut peak_commit, &mut page_faults,
+        );
+    }
+    Some(AllocatorStats { allocated_bytes: current_commit as u64, resident_bytes: current_rss as u64 })
+}
+
+/// Read the statistics of the global allocator.
+///
+/// # Returns
+///
+/// The [AllocatorStats](AllocatorStats), [None](None) for the system allocator, which keeps no
+/// statistics.
+#[cfg(not(any(feature = "jemalloc", feature = "mimalloc")))]
+pub fn stats() -> Option<AllocatorStats> {
+    None
+}
+
+/// Sample the statistics of the global allocator in [ALLOCATOR_MEMORY](ALLOCATOR_MEMORY),
+/// labelled `allocated` and `resident`.
+///
+/// # Returns
+///
+/// The [AllocatorStats](AllocatorStats) recorded, if any.
+pub fn record_stats() -> Option<AllocatorStats> {
+    let stats = stats()?;
+    ALLOCATOR_MEMORY.set("allocated", stats.allocated_bytes);
+    ALLOCATOR_MEMORY.set("resident", stats.resident_bytes);
+    Some(stats)
+}
+
+/// Sample the statistics of the global allocator periodically in a background task, logging
+/// them at DEBUG level. Nothing is started for the system allocator.
+///
+/// # Arguments
+///
+/// * `period` - Interval between two samples.
+pub fn spawn_stats(period: Duration) {
+    if stats().is_none() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut ticks = interval(period);
+        loop {
+            ticks.tick().await;
+            if let Some(stats) = record_stats() {
+                debug!("Allocator {}: {} bytes allocated, {} bytes resident", ALLOCATOR_NAME, stats.allocated_bytes, stats.resident_bytes);
+            }
+        }
+    });
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_stats() {
+        let stats = record_stats();
+        assert_eq!(stats.is_some(), ALLOCATOR_NAME != "system");
+        assert_eq!(ALLOCATOR_MEMORY.get("resident"), stats.map(|stats| stats.resident_bytes));
+    }
+}
@@ -0,0 +1,282 @@
+//! Analytics computed from the [aggregate book](AggregateBook): fair value estimates of the
+//! consolidated book, more robust than the plain mid price to thin or lopsided best levels.
+//!
+//! Available methods, selected by name with optional parameter:
+//! * `mid`: plain mid price, the reference of the other methods.
+//! * `weighted_mid`: mid price weighted by the amounts at the best levels, leaning towards
+//!   the side with less liquidity, where the price is more likely to move.
+//! * `depth_weighted_mid[:levels]`: same as `weighted_mid`, with the average prices and total
+//!   amounts of the best `levels` of each side, 5 by default.
+//! * `microprice[:levels]`: best bid and ask weighted by the imbalance of the total amounts of
+//!   the best `levels` of each side, 1 by default, leaning towards the side with less liquidity.
+//! * `ewma_mid[:half_life_ms]`: mid price smoothed by an exponentially weighted moving average,
+//!   with weights halving every `half_life_ms`, 1000 by default.
+
+use rust_decimal::{Decimal, prelude::FromPrimitive};
+use std::fmt;
+use tokio::time::{Duration, Instant};
+
+use crate::aggregator::AggregateBook;
+use crate::core::Side;
+
+
+/// Name of the plain mid price.
+const MID: &str = "mid";
+/// Name of the size-weighted mid price.
+const WEIGHTED_MID: &str = "weighted_mid";
+/// Name of the depth-weighted mid price.
+const DEPTH_WEIGHTED_MID: &str = "depth_weighted_mid";
+/// Name of the smoothed mid price.
+const EWMA_MID: &str = "ewma_mid";
+/// Name of the imbalance-weighted mid price.
+const MICROPRICE: &str = "microprice";
+/// Levels of each side of the depth-weighted mid price, if not specified.
+const DEFAULT_DEPTH_LEVELS: usize = 5;
+/// Levels of each side of the imbalance of the microprice, if not specified.
+const DEFAULT_MICROPRICE_LEVELS: usize = 1;
+/// Half-life of the smoothed mid price, if not specified.
+const DEFAULT_EWMA_HALF_LIFE: Duration = Duration::from_millis(1000);
+
+
+/// Method of calculation of a fair value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FairValueMethod {
+    /// Mid price between the best bid and ask.
+    Mid,
+    /// Mid price weighted by the amounts at the best levels.
+    WeightedMid,
+    /// Mid price weighted by the amounts of the best levels of each side, between their
+    /// average prices.
+    DepthWeightedMid {
+        /// Levels of each side.
+        levels: usize,
+    },
+    /// Exponentially weighted moving average of the mid price.
+    EwmaMid {
+        /// Time after which the weight of a mid price is halved.
+        half_life: Duration,
+    },
+    /// Best bid and ask weighted by the imbalance of the amounts of the best levels of each side.
+    Microprice {
+        /// Levels of each side.
+        levels: usize,
+    },
+}
+
+impl fmt::Display for FairValueMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mid => write!(f, "{}", MID),
+            Self::WeightedMid => write!(f, "{}", WEIGHTED_MID),
+            Self::DepthWeightedMid { levels } => write!(f, "{}:{}", DEPTH_WEIGHTED_MID, levels),
+            Self::EwmaMid { half_life } => write!(f, "{}:{}", EWMA_MID, half_life.as_millis()),
+            Self::Microprice { levels } => write!(f, "{}:{}", MICROPRICE, levels),
+        }
+    }
+}
+
+impl FairValueMethod {
+    /// Parse a comma separated list of methods, e.g. `weighted_mid,ewma_mid:500`.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The method list, empty for none of them.
+    ///
+    /// # Returns
+    ///
+    /// A [vector](Vec) of [FairValueMethod](FairValueMethod), or an error naming an unknown or
+    /// invalid method.
+    pub fn parse_list(value: &str) -> Result<Vec<Self>, String> {
+        value.split(',').map(str::trim).filter(|method| !method.is_empty()).map(Self::parse).collect()
+    }
+
+    /// Internal function parsing a single method, with its optional parameter.
+    fn parse(method: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid fair value method: {}", method);
+        let (name, parameter) = match method.split_once(':') {
+            Some((name, parameter)) => (name, Some(parameter.parse::<u64>().ok().filter(|&p| p > 0).ok_or_else(invalid)?)),
+            None => (method, None),
+        };
+        match (name.to_ascii_lowercase().as_str(), parameter) {
+            (MID, None) => Ok(Self::Mid),
+            (WEIGHTED_MID, None) => Ok(Self::WeightedMid),
+            (DEPTH_WEIGHTED_MID, levels) => Ok(Self::DepthWeightedMid {
+                levels: levels.map(|levels| levels as usize).unwrap_or(DEFAULT_DEPTH_LEVELS),
+            }),
+            (EWMA_MID, half_life_ms) => Ok(Self::EwmaMid {
+                half_life: half_life_ms.map(Duration::from_millis).unwrap_or(DEFAULT_EWMA_HALF_LIFE),
+            }),
+            (MICROPRICE, levels) => Ok(Self::Microprice {
+                levels: levels.map(|levels| levels as usize).unwrap_or(DEFAULT_MICROPRICE_LEVELS),
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Calculator of a fair value, keeping the state of the stateful methods.
+#[derive(Clone, Debug)]
+pub struct FairValueCalculator {
+    /// Method of calculation.
+    method: FairValueMethod,
+    /// Latest smoothed value and its time, for [EwmaMid](FairValueMethod::EwmaMid).
+    last: Option<(Decimal, Instant)>,
+}
+
+impl FairValueCalculator {
+    /// Create a new [FairValueCalculator](FairValueCalculator) object.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - The [FairValueMethod](FairValueMethod).
+    pub fn new(method: FairValueMethod) -> Self {
+        Self { method, last: None }
+    }
+
+    /// The method of calculation.
+    pub fn method(&self) -> FairValueMethod {
+        self.method
+    }
+
+    /// Calculate the fair value of the current book.
+    ///
+    /// # Arguments
+    ///
+    /// * `book` - The [AggregateBook](AggregateBook).
+    ///
+    /// * `now` - The time of the book, for the smoothed methods.
+    ///
+    /// # Returns
+    ///
+    /// An optional [Decimal](Decimal), [None](None) if any of the sides is empty.
+    pub fn update(&mut self, book: &AggregateBook, now: Instant) -> Option<Decimal> {
+        match self.method {
+            FairValueMethod::Mid => book.mid_price(),
+            FairValueMethod::WeightedMid => depth_weighted_mid(book, 1),
+            FairValueMethod::DepthWeightedMid { levels } => depth_weighted_mid(book, levels),
+            FairValueMethod::EwmaMid { half_life } => {
+                let mid = book.mid_price()?;
+                let smoothed = match self.last {
+                    Some((previous, time)) => {
+                        let elapsed = now.saturating_duration_since(time).as_secs_f64();
+                        let weight = 1.0 - 0.5f64.powf(elapsed / half_life.as_secs_f64());
+                        previous + Decimal::from_f64(weight).unwrap_or(Decimal::ONE) * (mid - previous)
+                    },
+                    None => mid,
+                };
+                self.last = Some((smoothed, now));
+                Some(smoothed)
+            },
+            FairValueMethod::Microprice { levels } => microprice(book, levels),
+        }
+    }
+}
+
+/// Average price and total amount of the best levels of a side of the book.
+fn average_price(book: &AggregateBook, side: Side, levels: usize) -> Option<(Decimal, Decimal)> {
+    let (notional, amount) = book.levels(side).take(levels)
+        .map(|level| (level.price(), level.total_amount()))
+        .fold((Decimal::ZERO, Decimal::ZERO), |(notional, amount), (price, level_amount)| {
+            (notional + price * level_amount, amount + level_amount)
+        });
+    (amount > Decimal::ZERO).then(|| (notional / amount, amount))
+}
+
+/// Mid price between the average prices of the best levels of each side, each weighted by the
+/// total amount of the opposite side.
+fn depth_weighted_mid(book: &AggregateBook, levels: usize) -> Option<Decimal> {
+    let (bid_price, bid_amount) = average_price(book, Side::Buy, levels)?;
+    let (ask_price, ask_amount) = average_price(book, Side::Sell, levels)?;
+    Some((bid_price * ask_amount + ask_price * bid_amount) / (bid_amount + ask_amount))
+}
+
+/// Best bid and ask, each weighted by the total amount of the best levels of its own side: the
+/// more the bids outweigh the asks, the closer to the best ask.
+fn microprice(book: &AggregateBook, levels: usize) -> Option<Decimal> {
+    let (_, bid_amount) = average_price(book, Side::Buy, levels)?;
+    let (_, ask_amount) = average_price(book, Side::Sell, levels)?;
+    let best_bid = book.levels(Side::Buy).next()?.price();
+    let best_ask = book.levels(Side::Sell).next()?.price();
+    Some(best_bid + (best_ask - best_bid) * bid_amount / (bid_amount + ask_amount))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{BookUpdate, ExchangeLevel};
+
+    fn book() -> AggregateBook {
+        let mut book = AggregateBook::new(10);
+        book.update(BookUpdate {
+            exchange_code: "test".into(),
+            bids: vec![ExchangeLevel::from_strs("test", "99", "3"), ExchangeLevel::from_strs("test", "98", "1")],
+            asks: vec![ExchangeLevel::from_strs("test", "101", "1"), ExchangeLevel::from_strs("test", "102", "3")],
+        });
+        book
+    }
+
+    #[test]
+    fn test_parse_list() {
+        assert_eq!(FairValueMethod::parse_list(""), Ok(vec![]));
+        assert_eq!(
+            FairValueMethod::parse_list(" weighted_mid, depth_weighted_mid,EWMA_MID:500"),
+            Ok(vec![
+                FairValueMethod::WeightedMid,
+                FairValueMethod::DepthWeightedMid { levels: 5 },
+                FairValueMethod::EwmaMid { half_life: Duration::from_millis(500) },
+            ]),
+        );
+        assert!(FairValueMethod::parse_list("weighted_mid:2").is_err());
+        assert!(FairValueMethod::parse_list("ewma_mid:0").is_err());
+        assert!(FairValueMethod::parse_list("vwap").is_err());
+        assert!(FairValueMethod::parse_list("mid:2").is_err());
+        assert_eq!(
+            FairValueMethod::parse_list("mid,microprice,microprice:3"),
+            Ok(vec![FairValueMethod::Mid, FairValueMethod::Microprice { levels: 1 }, FairValueMethod::Microprice { levels: 3 }]),
+        );
+        let method = FairValueMethod::DepthWeightedMid { levels: 3 };
+        assert_eq!(FairValueMethod::parse_list(&method.to_string()), Ok(vec![method]));
+    }
+
+    #[test]
+    fn test_weighted_mids() {
+        let now = Instant::now();
+        // more amount on the bid side pushes the fair value towards the ask
+        let weighted_mid = FairValueCalculator::new(FairValueMethod::WeightedMid).update(&book(), now);
+        assert_eq!(weighted_mid, Some(Decimal::from_str_exact("100.5").unwrap()));
+        // averages 98.75 and 101.75, with 4 on each side
+        let depth_weighted_mid = FairValueCalculator::new(FairValueMethod::DepthWeightedMid { levels: 2 }).update(&book(), now);
+        assert_eq!(depth_weighted_mid, Some(Decimal::from_str_exact("100.25").unwrap()));
+        assert_eq!(FairValueCalculator::new(FairValueMethod::WeightedMid).update(&AggregateBook::new(10), now), None);
+    }
+
+    #[test]
+    fn test_microprice() {
+        let now = Instant::now();
+        assert_eq!(FairValueCalculator::new(FairValueMethod::Mid).update(&book(), now), Some(Decimal::from(100)));
+        // 3 bid against 1 ask at the best levels, three quarters of the way to the best ask
+        let microprice = FairValueCalculator::new(FairValueMethod::Microprice { levels: 1 }).update(&book(), now);
+        assert_eq!(microprice, Some(Decimal::from_str_exact("100.5").unwrap()));
+        // balanced over the best 2 levels, between the best prices rather than their averages
+        let microprice = FairValueCalculator::new(FairValueMethod::Microprice { levels: 2 }).update(&book(), now);
+        assert_eq!(microprice, Some(Decimal::from(100)));
+        assert_eq!(FairValueCalculator::new(FairValueMethod::Microprice { levels: 2 }).update(&AggregateBook::new(10), now), None);
+    }
+
+    #[test]
+    fn test_ewma_mid() {
+        let start = Instant::now();
+        let mut calculator = FairValueCalculator::new(FairValueMethod::EwmaMid { half_life: Duration::from_secs(1) });
+        assert_eq!(calculator.update(&book(), start), Some(Decimal::from(100)));
+        let mut moved = book();
+        moved.update(BookUpdate {
+            exchange_code: "test".into(),
+            bids: vec![ExchangeLevel::from_strs("test", "103", "1")],
+            asks: vec![ExchangeLevel::from_strs("test", "105", "1")],
+        });
+        // half way after one half-life
+        assert_eq!(calculator.update(&moved, start + Duration::from_secs(1)), Some(Decimal::from(102)));
+        assert_eq!(calculator.update(&moved, start + Duration::from_secs(1)), Some(Decimal::from(102)));
+    }
+}
@@ -2,43 +2,238 @@
 
 use log::debug;
 use rust_decimal::prelude::*;
-use serde::{Deserialize};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
+use crate::capabilities::{ExchangeCapabilities, HeartbeatKind, UpdateKind};
+use crate::config::{DocumentedConfig, Validate, Validator};
 use crate::core::*;
-use crate::exchange::{ExchangeAdapter, ExchangeProtocol};
+use crate::exchange::{ExchangeAdapter, ExchangeProtocol, SubscriptionRequest};
+use crate::request_ids::REQUEST_ID_PLACEHOLDER;
+#[cfg(feature = "full-depth")]
+use crate::local_book::{read_diff, read_snapshot, DepthDiff, DepthSnapshot, LocalBook, SharedLocalBook};
+#[cfg(feature = "rest")]
+use crate::rest::RestFallback;
+#[cfg(feature = "full-depth")]
+use std::sync::Mutex;
 
 
 const BINANCE_CODE: &str = "binance";
 const BINANCE_WS_URL: &str = "wss://stream.binance.com:443/ws";
+/// Failover endpoints of the Binance WebSocket service, in order of preference.
+const BINANCE_FAILOVER_WS_URLS: [&str; 2] = ["wss://stream.binance.com:9443/ws", "wss://data-stream.binance.vision/ws"];
+/// Identifier of the first request of each connection, the following ones numbered in sequence.
+const BINANCE_FIRST_REQUEST_ID: u64 = 10;
+/// Region of the Binance endpoints.
+const BINANCE_REGION: &str = "ap-northeast-1";
+#[cfg(feature = "rest")]
+const BINANCE_REST_URL: &str = "https://api.binance.com/api/v3/depth";
+/// Levels of each side of the REST snapshots in full-depth mode, the maximum served.
+#[cfg(feature = "full-depth")]
+const BINANCE_SNAPSHOT_LEVELS: usize = 5000;
+/// Levels of each side of the periodic snapshots served by Binance.
+const BINANCE_DEPTH_LEVELS: [usize; 3] = [5, 10, 20];
+/// Intervals between two periodic snapshots served by Binance, in milliseconds.
+const BINANCE_UPDATE_INTERVALS_MS: [u64; 2] = [100, 1000];
+/// Default interval between two periodic snapshots, in milliseconds.
+const DEFAULT_BINANCE_UPDATE_INTERVAL_MS: u64 = 100;
+/// Interval between two periodic snapshots of the channels without an interval suffix, in milliseconds.
+const BINANCE_STANDARD_UPDATE_INTERVAL_MS: u64 = 1000;
+
+
+/// Channel of the periodic snapshots of Binance, read from a JSON file, trading freshness
+/// against bandwidth. Missing values take their defaults.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct BinanceDepthConfig {
+    /// Levels of each side of the snapshots: 5, 10 or 20.
+    pub levels: usize,
+    /// Interval between two snapshots, in milliseconds: 100 or 1000.
+    pub update_interval_ms: u64,
+}
+
+impl Default for BinanceDepthConfig {
+    fn default() -> Self {
+        Self {
+            levels: NUM_LEVELS,
+            update_interval_ms: DEFAULT_BINANCE_UPDATE_INTERVAL_MS,
+        }
+    }
+}
+
+impl Validate for BinanceDepthConfig {
+    fn validate(&self, validator: &mut Validator) {
+        validator.one_of("levels", self.levels, &BINANCE_DEPTH_LEVELS);
+        validator.one_of("update_interval_ms", self.update_interval_ms, &BINANCE_UPDATE_INTERVALS_MS);
+    }
+}
+
+impl DocumentedConfig for BinanceDepthConfig {
+    const DESCRIPTION: &'static str = "Channel of the periodic snapshots of Binance.";
+    const FIELDS: &'static [(&'static str, &'static str)] = &[
+        ("levels", "Levels of each side of the snapshots: 5, 10 or 20."),
+        ("update_interval_ms", "Interval between two snapshots, in milliseconds: 100 or 1000."),
+    ];
+}
 
 /// Parse string messages from trading book update Binance WebSocket service into
 /// the exchange [protocol](ExchangeProtocol).
-/// It recognizes trading book updates.
+/// It recognizes trading book updates and the responses to requests, by identifier.
 fn read_binance_book_update(value: &str) -> Option<ExchangeProtocol<BookUpdate>> {
     let parse_res: serde_json::Result<BinanceBookUpdate> = serde_json::from_str(value);
     match parse_res {
         Ok(book_update @ BinanceBookUpdate{..}) => {
             Some(ExchangeProtocol::Data(book_update.into()))
         },
-        _ => {
+        _ => match serde_json::from_str::<BinanceResponse>(value) {
+            Ok(BinanceResponse { result: None, id }) => Some(ExchangeProtocol::Response(id)),
+            _ => {
+                debug!("Parse failed {:?}", value);
+                None
+            }
+        }
+    }
+}
+
+/// Format a currency pair as in Binance channel names, e.g. `ethbtc`.
+pub fn binance_symbol(product: &CurrencyPair) -> String {
+    product.to_string().to_lowercase()
+}
+
+/// Format the request subscribing to, or unsubscribing from, a channel of the Binance
+/// WebSocket service.
+fn binance_request(channel_code: &str, request: SubscriptionRequest) -> String {
+    let method = match request {
+        SubscriptionRequest::Subscribe => "SUBSCRIBE",
+        SubscriptionRequest::Unsubscribe => "UNSUBSCRIBE",
+    };
+    format!(r#"{{"method":"{}","params":["{}"],"id":{}}}"#, method, channel_code, REQUEST_ID_PLACEHOLDER)
+}
+
+/// Name of the channel of the periodic snapshots of a currency pair, e.g. `ethbtc@depth10@100ms`,
+/// or `ethbtc@depth10` at the standard interval.
+fn binance_channel(product: &CurrencyPair, depth_config: &BinanceDepthConfig) -> String {
+    if depth_config.update_interval_ms == BINANCE_STANDARD_UPDATE_INTERVAL_MS {
+        format!("{}@depth{}", binance_symbol(product), depth_config.levels)
+    } else {
+        format!("{}@depth{}@{}ms", binance_symbol(product), depth_config.levels, depth_config.update_interval_ms)
+    }
+}
+
+/// Creates an [exchange adapter](ExchangeAdapter) for Binance, with the default
+/// [BinanceDepthConfig](BinanceDepthConfig).
+pub fn make_binance_exchange_adapter(product: &CurrencyPair) -> ExchangeAdapter<BookUpdate> {
+    make_binance_configured_adapter(product, BinanceDepthConfig::default())
+}
+
+/// Creates an [exchange adapter](ExchangeAdapter) for Binance.
+///
+/// # Arguments
+///
+/// * `product` - The currency pair.
+///
+/// * `depth_config` - The [BinanceDepthConfig](BinanceDepthConfig) of the snapshots.
+pub fn make_binance_configured_adapter(product: &CurrencyPair, depth_config: BinanceDepthConfig) -> ExchangeAdapter<BookUpdate> {
+    let product_code = binance_symbol(product);
+    let channel_code = binance_channel(product, &depth_config);
+    let ws_url = format!("{}/{}", BINANCE_WS_URL, channel_code);
+    let adapter = with_failover_urls(ExchangeAdapter::new(BINANCE_CODE, ws_url, Arc::new(read_binance_book_update)), &channel_code)
+        .with_subscribe_message(binance_request(&channel_code, SubscriptionRequest::Subscribe))
+        .with_subscription_formatter(Arc::new(move |product, request| binance_request(&binance_channel(product, &depth_config), request)))
+        .with_first_request_id(BINANCE_FIRST_REQUEST_ID)
+        .with_subscription_acks()
+        .with_capabilities(ExchangeCapabilities {
+            updates: UpdateKind::Snapshots,
+            max_depth: Some(depth_config.levels),
+            heartbeat: HeartbeatKind::WebsocketPing,
+            region: BINANCE_REGION,
+        });
+    with_rest_fallback(adapter, &product_code, depth_config.levels)
+}
+
+/// Add the failover endpoints of the Binance WebSocket service, streaming the same channel.
+fn with_failover_urls(adapter: ExchangeAdapter<BookUpdate>, channel_code: &str) -> ExchangeAdapter<BookUpdate> {
+    BINANCE_FAILOVER_WS_URLS.iter().fold(adapter, |adapter, ws_url| adapter.with_failover_url(format!("{}/{}", ws_url, channel_code)))
+}
+
+/// Poll REST depth snapshots while the WebSocket service cannot be reached. They have the
+/// same format as the WebSocket ones.
+#[cfg(feature = "rest")]
+fn with_rest_fallback(adapter: ExchangeAdapter<BookUpdate>, product_code: &str, levels: usize) -> ExchangeAdapter<BookUpdate> {
+    adapter.with_rest_fallback(RestFallback::new(
+        format!("{}?symbol={}&limit={}", BINANCE_REST_URL, product_code.to_uppercase(), levels),
+        Arc::new(read_binance_book_update),
+    ))
+}
+
+/// Without the `rest` feature, the adapter is unchanged.
+#[cfg(not(feature = "rest"))]
+fn with_rest_fallback(adapter: ExchangeAdapter<BookUpdate>, _product_code: &str, _levels: usize) -> ExchangeAdapter<BookUpdate> {
+    adapter
+}
+
+/// Parse the diffs of the Binance WebSocket service in full-depth mode, applying them to
+/// the local book. Other messages are parsed as by the snapshot service.
+#[cfg(feature = "full-depth")]
+fn read_binance_depth_diff(local_book: &SharedLocalBook, value: &str) -> Option<ExchangeProtocol<BookUpdate>> {
+    match serde_json::from_str::<BinanceDepthDiff>(value) {
+        Ok(diff) => Some(read_diff(local_book, diff.into())),
+        Err(_) => read_binance_book_update(value),
+    }
+}
+
+/// Parse the depth snapshots of the Binance REST service in full-depth mode, replacing
+/// the local book.
+#[cfg(feature = "full-depth")]
+fn read_binance_depth_snapshot(local_book: &SharedLocalBook, value: &str) -> Option<ExchangeProtocol<BookUpdate>> {
+    match serde_json::from_str::<BinanceDepthSnapshot>(value) {
+        Ok(snapshot) => Some(read_snapshot(local_book, snapshot.into())),
+        Err(_) => {
             debug!("Parse failed {:?}", value);
             None
         }
     }
 }
 
-/// Creates an [exchange adapter](ExchangeAdapter) for Binance.
-pub async fn make_binance_exchange_adapter(product: &CurrencyPair) -> ExchangeAdapter<BookUpdate> {
-    let product_code = product.to_string().to_lowercase();
-    let channel_code = format!("{}@depth{}@100ms", product_code, NUM_LEVELS);
+/// Name of the channel of the diffs of a currency pair, e.g. `ethbtc@depth@100ms`.
+#[cfg(feature = "full-depth")]
+fn binance_diff_channel(product: &CurrencyPair) -> String {
+    format!("{}@depth@100ms", binance_symbol(product))
+}
+
+/// Creates an [exchange adapter](ExchangeAdapter) for Binance in full-depth mode: the
+/// complete book is maintained from a REST snapshot and the diffs streamed, and delivered
+/// after each change.
+#[cfg(feature = "full-depth")]
+pub fn make_binance_full_depth_adapter(product: &CurrencyPair) -> ExchangeAdapter<BookUpdate> {
+    let product_code = binance_symbol(product);
+    let channel_code = binance_diff_channel(product);
     let ws_url = format!("{}/{}", BINANCE_WS_URL, channel_code);
-    let subscribe_message = format!(r#"{{"method":"SUBSCRIBE","params":["{}"],"id":10}}"#, channel_code);
-    ExchangeAdapter::new(
-        BINANCE_CODE,
-        ws_url,
-        subscribe_message,
-        &read_binance_book_update,
-    ).await
+    // diffs are numbered contiguously
+    let local_book: SharedLocalBook = Arc::new(Mutex::new(LocalBook::new(BINANCE_CODE, true)));
+    let diff_book = local_book.clone();
+    with_failover_urls(ExchangeAdapter::new(BINANCE_CODE, ws_url, Arc::new(move |value: &str| read_binance_depth_diff(&diff_book, value))), &channel_code)
+        .with_subscribe_message(binance_request(&channel_code, SubscriptionRequest::Subscribe))
+        .with_subscription_formatter(Arc::new(|product, request| binance_request(&binance_diff_channel(product), request)))
+        .with_first_request_id(BINANCE_FIRST_REQUEST_ID)
+        .with_subscription_acks()
+        .with_capabilities(ExchangeCapabilities {
+            updates: UpdateKind::Deltas,
+            max_depth: None,
+            heartbeat: HeartbeatKind::WebsocketPing,
+            region: BINANCE_REGION,
+        })
+        .with_depth_snapshot(RestFallback::new(
+            format!("{}?symbol={}&limit={}", BINANCE_REST_URL, product_code.to_uppercase(), BINANCE_SNAPSHOT_LEVELS),
+            Arc::new(move |value: &str| read_binance_depth_snapshot(&local_book, value)),
+        ))
+}
+
+/// Response to a request, such as a subscription: a null result for success.
+#[derive(Deserialize, Debug)]
+struct BinanceResponse {
+    result: Option<serde_json::Value>,
+    id: u64,
 }
 
 #[derive(Deserialize, Debug)]
@@ -51,13 +246,38 @@ struct BinanceBookUpdate {
     asks: Vec<BinancePair>,
 }
 
+/// Depth snapshot of the REST service, with the identifier of its last change.
+#[cfg(feature = "full-depth")]
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BinanceDepthSnapshot {
+    last_update_id: u64,
+    bids: Vec<BinancePair>,
+    asks: Vec<BinancePair>,
+}
+
+/// Diff of the book, with the identifiers of its first and last changes.
+#[cfg(feature = "full-depth")]
+#[derive(Deserialize, Debug)]
+struct BinanceDepthDiff {
+    #[serde(rename = "U")]
+    first_update_id: u64,
+    #[serde(rename = "u")]
+    last_update_id: u64,
+    #[serde(rename = "b")]
+    bids: Vec<BinancePair>,
+    #[serde(rename = "a")]
+    asks: Vec<BinancePair>,
+}
+
 impl From<BinancePair> for ExchangeLevel {
     fn from(value: BinancePair) -> Self {
         let BinancePair((price_str, amount_str)) = value;
         Self {
-            exchange_code: BINANCE_CODE,
+            exchange_code: BINANCE_CODE.into(),
             price: Decimal::from_str(&price_str).unwrap(),
             amount: Decimal::from_str(&amount_str).unwrap(),
+            order_count: None,
         }
     }
 }
@@ -65,7 +285,30 @@ impl From<BinancePair> for ExchangeLevel {
 impl From<BinanceBookUpdate> for BookUpdate {
     fn from(value: BinanceBookUpdate) -> Self {
         Self {
-            exchange_code: BINANCE_CODE,
+            exchange_code: BINANCE_CODE.into(),
+            bids: value.bids.into_iter().map(|pair| pair.into()).collect(),
+            asks: value.asks.into_iter().map(|pair| pair.into()).collect(),
+        }
+    }
+}
+
+#[cfg(feature = "full-depth")]
+impl From<BinanceDepthSnapshot> for DepthSnapshot {
+    fn from(value: BinanceDepthSnapshot) -> Self {
+        Self {
+            sequence: value.last_update_id,
+            bids: value.bids.into_iter().map(|pair| pair.into()).collect(),
+            asks: value.asks.into_iter().map(|pair| pair.into()).collect(),
+        }
+    }
+}
+
+#[cfg(feature = "full-depth")]
+impl From<BinanceDepthDiff> for DepthDiff {
+    fn from(value: BinanceDepthDiff) -> Self {
+        Self {
+            first_sequence: value.first_update_id,
+            last_sequence: value.last_update_id,
             bids: value.bids.into_iter().map(|pair| pair.into()).collect(),
             asks: value.asks.into_iter().map(|pair| pair.into()).collect(),
         }
@@ -76,13 +319,14 @@ impl From<BinanceBookUpdate> for BookUpdate {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config;
 
     #[test]
     fn test_read_binance_book_update_success() {
         let websocket_msg = r#"{"lastUpdateId":1580041371,"bids":[["0.00001049","9383.30000000"],["0.00001048","186198.30000000"]],"asks":[["0.00001050","133639.50000000"],["0.00001051","133083.10000000"]]}"#;
         let parsed = read_binance_book_update(websocket_msg);
         let expected = Some(ExchangeProtocol::Data(BookUpdate{
-            exchange_code: "binance",
+            exchange_code: "binance".into(),
             bids: vec![
                 ExchangeLevel::from_strs("binance", "0.00001049","9383.30000000"),
                 ExchangeLevel::from_strs("binance", "0.00001048","186198.30000000")
@@ -102,6 +346,38 @@ mod tests {
         assert_eq!(parsed, None);
     }
 
+    #[test]
+    fn test_read_binance_subscription_ack() {
+        assert_eq!(read_binance_book_update(r#"{"result":null,"id":10}"#), Some(ExchangeProtocol::Response(10)));
+        assert_eq!(read_binance_book_update(r#"{"result":["ethbtc@depth20@100ms"],"id":11}"#), None);
+    }
+
+    #[test]
+    fn test_binance_request() {
+        let product = CurrencyPair { main: "eth".to_string(), counter: "btc".to_string() };
+        let depth_config = BinanceDepthConfig::default();
+        assert_eq!(binance_request(&binance_channel(&product, &depth_config), SubscriptionRequest::Subscribe), r#"{"method":"SUBSCRIBE","params":["ethbtc@depth10@100ms"],"id":{request_id}}"#);
+        assert_eq!(binance_request(&binance_channel(&product, &depth_config), SubscriptionRequest::Unsubscribe), r#"{"method":"UNSUBSCRIBE","params":["ethbtc@depth10@100ms"],"id":{request_id}}"#);
+    }
+
+    #[test]
+    fn test_binance_depth_config() {
+        let product = CurrencyPair { main: "eth".to_string(), counter: "btc".to_string() };
+        let depth_config: BinanceDepthConfig = serde_json::from_str(r#"{"levels": 20, "update_interval_ms": 1000}"#).unwrap();
+        assert_eq!(binance_channel(&product, &depth_config), "ethbtc@depth20");
+        let adapter = make_binance_configured_adapter(&product, BinanceDepthConfig { levels: 5, ..depth_config });
+        let venue_config = adapter.config();
+        assert_eq!(venue_config.capabilities.max_depth, Some(5));
+        assert_eq!(venue_config.subscribe_messages, vec![binance_request("ethbtc@depth5", SubscriptionRequest::Subscribe)]);
+
+        let path = std::env::temp_dir().join(format!("binance_{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"levels": 15, "update_interval_ms": 500}"#).unwrap();
+        let errors = config::load::<BinanceDepthConfig>(&path).unwrap_err().to_string();
+        assert!(errors.contains("levels must be one of 5, 10, 20, not 15"), "{}", errors);
+        assert!(errors.contains("update_interval_ms must be one of 100, 1000, not 500"), "{}", errors);
+        std::fs::remove_file(&path).unwrap();
+    }
+
         #[test]
     fn test_convert_binance_book_update() {
         let b_book_update = BinanceBookUpdate {
@@ -115,7 +391,7 @@ mod tests {
             ],
         };
         let exp_book_update = BookUpdate {
-            exchange_code: BINANCE_CODE,
+            exchange_code: BINANCE_CODE.into(),
             bids: vec![
                 ExchangeLevel::from_strs(BINANCE_CODE, "0.123", "123.1"),
                 ExchangeLevel::from_strs(BINANCE_CODE, "0.321", "321.3"),
@@ -128,4 +404,26 @@ mod tests {
         let book_update: BookUpdate = b_book_update.into();
         assert_eq!(book_update, exp_book_update);
     }
-}
\ No newline at end of file
+
+    #[cfg(feature = "full-depth")]
+    #[test]
+    fn test_read_binance_full_depth() {
+        let local_book: SharedLocalBook = Arc::new(Mutex::new(LocalBook::new(BINANCE_CODE, true)));
+        let snapshot = r#"{"lastUpdateId":160,"bids":[["0.0024","10"]],"asks":[["0.0026","100"],["0.0027","5"]]}"#;
+        assert!(matches!(read_binance_depth_snapshot(&local_book, snapshot), Some(ExchangeProtocol::Data(_))));
+        let stale = r#"{"e":"depthUpdate","E":123456789,"s":"BNBBTC","U":157,"u":160,"b":[["0.0024","0"]],"a":[]}"#;
+        assert_eq!(read_binance_depth_diff(&local_book, stale), Some(ExchangeProtocol::Skipped));
+        let diff = r#"{"e":"depthUpdate","E":123456790,"s":"BNBBTC","U":158,"u":161,"b":[["0.0023","1"]],"a":[["0.0026","0"]]}"#;
+        assert_eq!(read_binance_depth_diff(&local_book, diff), Some(ExchangeProtocol::Data(BookUpdate {
+            exchange_code: BINANCE_CODE.into(),
+            bids: vec![
+                ExchangeLevel::from_strs(BINANCE_CODE, "0.0024", "10"),
+                ExchangeLevel::from_strs(BINANCE_CODE, "0.0023", "1"),
+            ],
+            asks: vec![ExchangeLevel::from_strs(BINANCE_CODE, "0.0027", "5")],
+        })));
+        let gap = r#"{"e":"depthUpdate","E":123456791,"s":"BNBBTC","U":170,"u":171,"b":[],"a":[]}"#;
+        assert_eq!(read_binance_depth_diff(&local_book, gap), Some(ExchangeProtocol::ReconnectionRequest));
+        assert_eq!(read_binance_depth_diff(&local_book, r#"{"result":null,"id":10}"#), Some(ExchangeProtocol::Response(10)));
+    }
+}
@@ -0,0 +1,167 @@
+//! Bitfinex `WebSocket` exchange adapter for raw (R0) books, streaming each order: orders are
+//! maintained in an [OrderBook](OrderBook) and reduced to price levels.
+
+use log::debug;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+
+use crate::capabilities::{ExchangeCapabilities, HeartbeatKind, UpdateKind};
+use crate::core::*;
+use crate::exchange::{ExchangeAdapter, ExchangeProtocol};
+use crate::numbers::json_decimal;
+use crate::orders::OrderBook;
+
+
+const BITFINEX_CODE: &str = "bitfinex";
+const BITFINEX_WS_URL: &str = "wss://api-pub.bitfinex.com/ws/2";
+/// Region of the Bitfinex endpoints.
+const BITFINEX_REGION: &str = "eu-central-1";
+/// Maximum number of orders of each side of the raw book, the maximum served.
+const BITFINEX_RAW_BOOK_LENGTH: usize = 250;
+/// Code of the info event announcing a restart of the WebSocket service.
+const BITFINEX_RESTART_CODE: u64 = 20051;
+
+
+/// An [OrderBook](OrderBook) shared by the reader of an adapter and by its clones.
+pub type SharedOrderBook = Arc<Mutex<OrderBook>>;
+
+/// Parse string messages from the Bitfinex raw book WebSocket service into the exchange
+/// [protocol](ExchangeProtocol), applying the orders to the book. Each change of the book is
+/// delivered as its best price levels.
+/// It recognizes snapshots, order updates, heartbeats, subscription acknowledgments and
+/// restart announcements.
+fn read_bitfinex_raw_book(order_book: &SharedOrderBook, value: &str) -> Option<ExchangeProtocol<BookUpdate>> {
+    let message: Value = match serde_json::from_str(value) {
+        Ok(message) => message,
+        Err(_) => {
+            debug!("Parse failed {:?}", value);
+            return None;
+        }
+    };
+    if let Some(event) = message.get("event").and_then(Value::as_str) {
+        return match event {
+            "subscribed" => Some(ExchangeProtocol::SubscriptionAck),
+            "info" if message.get("code").and_then(Value::as_u64) == Some(BITFINEX_RESTART_CODE) => {
+                Some(ExchangeProtocol::ReconnectionRequest)
+            },
+            "info" => Some(ExchangeProtocol::Skipped),
+            _ => {
+                debug!("Event not recognized: {}", event);
+                None
+            }
+        };
+    }
+    let payload = message.as_array()?.get(1)?;
+    if payload.is_string() {
+        // heartbeat or checksum
+        return Some(ExchangeProtocol::Skipped);
+    }
+    let entries = payload.as_array()?;
+    let mut order_book = order_book.lock().unwrap();
+    let changed = if entries.first().map(Value::is_array).unwrap_or(true) {
+        let events = entries.iter().map(bitfinex_order_event).collect::<Option<Vec<OrderEvent>>>()?;
+        order_book.clear();
+        for event in events {
+            order_book.apply(event);
+        }
+        true
+    } else {
+        order_book.apply(bitfinex_order_event(payload)?)
+    };
+    if changed {
+        Some(ExchangeProtocol::Data(order_book.book_update(Some(NUM_LEVELS))))
+    } else {
+        Some(ExchangeProtocol::Skipped)
+    }
+}
+
+/// Convert an order entry `[ORDER_ID, PRICE, AMOUNT]` into an [OrderEvent](OrderEvent):
+/// a zero price deletes the order, a positive amount is a bid and a negative amount an ask.
+fn bitfinex_order_event(entry: &Value) -> Option<OrderEvent> {
+    let entry = entry.as_array()?;
+    let order_id = entry.first()?.as_u64()?.to_string();
+    let price = json_decimal(entry.get(1)?)?;
+    let amount = json_decimal(entry.get(2)?)?;
+    if price.is_zero() {
+        return Some(OrderEvent::Delete { order_id });
+    }
+    let side = if amount.is_sign_negative() { Side::Sell } else { Side::Buy };
+    Some(OrderEvent::Add { order_id, side, price, amount: amount.abs() })
+}
+
+/// Format a currency pair as in Bitfinex trading symbols, e.g. `tETHBTC`.
+pub fn bitfinex_symbol(product: &CurrencyPair) -> String {
+    format!("t{}", product.to_string().to_uppercase())
+}
+
+/// Creates an [exchange adapter](ExchangeAdapter) for the Bitfinex raw book. The order book
+/// is shared by the clones of the adapter, each snapshot replacing it.
+pub fn make_bitfinex_exchange_adapter(product: &CurrencyPair) -> ExchangeAdapter<BookUpdate> {
+    let subscribe_message = format!(
+        r#"{{"event":"subscribe","channel":"book","symbol":"{}","prec":"R0","len":"{}"}}"#,
+        bitfinex_symbol(product), BITFINEX_RAW_BOOK_LENGTH,
+    );
+    let order_book: SharedOrderBook = Arc::new(Mutex::new(OrderBook::new(BITFINEX_CODE)));
+    ExchangeAdapter::new(BITFINEX_CODE, String::from(BITFINEX_WS_URL), Arc::new(move |value: &str| read_bitfinex_raw_book(&order_book, value)))
+        .with_subscribe_message(subscribe_message)
+        .with_subscription_acks()
+        .with_capabilities(ExchangeCapabilities {
+            updates: UpdateKind::Orders,
+            max_depth: Some(BITFINEX_RAW_BOOK_LENGTH),
+            heartbeat: HeartbeatKind::Application,
+            region: BITFINEX_REGION,
+        })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order_book() -> SharedOrderBook {
+        Arc::new(Mutex::new(OrderBook::new(BITFINEX_CODE)))
+    }
+
+    #[test]
+    fn test_read_bitfinex_raw_book() {
+        let order_book = order_book();
+        let snapshot = r#"[17470,[[1001,0.0612,1.5],[1002,0.0612,0.5],[1003,0.0611,2],[1004,0.0613,-1],[1005,0.0614,-3e-7]]]"#;
+        assert_eq!(read_bitfinex_raw_book(&order_book, snapshot), Some(ExchangeProtocol::Data(BookUpdate {
+            exchange_code: BITFINEX_CODE.into(),
+            bids: vec![
+                ExchangeLevel::from_strs(BITFINEX_CODE, "0.0612", "2").with_order_count(2),
+                ExchangeLevel::from_strs(BITFINEX_CODE, "0.0611", "2").with_order_count(1),
+            ],
+            asks: vec![
+                ExchangeLevel::from_strs(BITFINEX_CODE, "0.0613", "1").with_order_count(1),
+                ExchangeLevel::from_strs(BITFINEX_CODE, "0.0614", "0.0000003").with_order_count(1),
+            ],
+        })));
+        let delete = r#"[17470,[1001,0,1]]"#;
+        let Some(ExchangeProtocol::Data(book_update)) = read_bitfinex_raw_book(&order_book, delete) else {
+            panic!("delete not delivered");
+        };
+        assert_eq!(book_update.bids[0], ExchangeLevel::from_strs(BITFINEX_CODE, "0.0612", "0.5").with_order_count(1));
+        assert_eq!(read_bitfinex_raw_book(&order_book, delete), Some(ExchangeProtocol::Skipped));
+        assert_eq!(order_book.lock().unwrap().order_count(), 4);
+    }
+
+    #[test]
+    fn test_read_bitfinex_events() {
+        let order_book = order_book();
+        assert_eq!(read_bitfinex_raw_book(&order_book, r#"[17470,"hb"]"#), Some(ExchangeProtocol::Skipped));
+        assert_eq!(
+            read_bitfinex_raw_book(&order_book, r#"{"event":"subscribed","channel":"book","chanId":17470,"symbol":"tETHBTC","prec":"R0","len":"250","pair":"ETHBTC"}"#),
+            Some(ExchangeProtocol::SubscriptionAck),
+        );
+        assert_eq!(read_bitfinex_raw_book(&order_book, r#"{"event":"info","version":2,"platform":{"status":1}}"#), Some(ExchangeProtocol::Skipped));
+        assert_eq!(read_bitfinex_raw_book(&order_book, r#"{"event":"info","code":20051,"msg":"Stop/Restart Websocket Server"}"#), Some(ExchangeProtocol::ReconnectionRequest));
+        assert_eq!(read_bitfinex_raw_book(&order_book, r#"[17470,["__INCORRECT__"]]"#), None);
+    }
+
+    #[test]
+    fn test_bitfinex_symbol() {
+        let product = CurrencyPair { main: "eth".to_string(), counter: "btc".to_string() };
+        assert_eq!(bitfinex_symbol(&product), "tETHBTC");
+    }
+}
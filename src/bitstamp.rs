@@ -3,17 +3,31 @@
 use log::debug;
 use rust_decimal::prelude::*;
 use serde::{Deserialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
+use crate::capabilities::{ExchangeCapabilities, HeartbeatKind, UpdateKind};
 use crate::core::*;
-use crate::exchange::{ExchangeAdapter, ExchangeProtocol};
+use crate::exchange::{ExchangeAdapter, ExchangeProtocol, SubscriptionRequest};
+#[cfg(feature = "full-depth")]
+use crate::local_book::{read_diff, read_snapshot, DepthDiff, DepthSnapshot, LocalBook, SharedLocalBook};
+use crate::metrics::REORDERED_SNAPSHOTS;
+#[cfg(feature = "rest")]
+use crate::rest::RestFallback;
 
 
 const BITSTAMP_CODE: &str = "bitstamp";
 const BITSTAMP_WS_URL: &str = "wss://ws.bitstamp.net";
+/// Region of the Bitstamp endpoints.
+const BITSTAMP_REGION: &str = "eu-west-1";
+/// Levels of each side of the snapshots of the `order_book` channel.
+const BITSTAMP_SNAPSHOT_LEVELS: usize = 100;
+#[cfg(feature = "rest")]
+const BITSTAMP_REST_URL: &str = "https://www.bitstamp.net/api/v2/order_book";
 
 /// Parse string messages from trading book update Bitstamp WebSocket service into
 /// the exchange [protocol](ExchangeProtocol).
-/// It recognizes trading book updates and reconnection requests.
+/// It recognizes trading book updates, subscription acknowledgments, unsubscriptions and reconnection requests.
 fn read_bitstamp_book_update(value: &str) -> Option<ExchangeProtocol<BookUpdate>> {
     let data_result: serde_json::Result<BitstampBookUpdate> = serde_json::from_str(value);
     match data_result {
@@ -25,6 +39,10 @@ fn read_bitstamp_book_update(value: &str) -> Option<ExchangeProtocol<BookUpdate>
             if let Ok(BitstampEvent {event}) = event_result {
                 if event == "bts:request_reconnect" {
                     Some(ExchangeProtocol::ReconnectionRequest)
+                } else if event == "bts:subscription_succeeded" {
+                    Some(ExchangeProtocol::SubscriptionAck)
+                } else if event == "bts:unsubscription_succeeded" {
+                    Some(ExchangeProtocol::Skipped)
                 } else {
                     debug!("Event not recognized: {}", event);
                     None
@@ -37,18 +55,151 @@ fn read_bitstamp_book_update(value: &str) -> Option<ExchangeProtocol<BookUpdate>
     }
 }
 
+/// Microtimestamp of the latest snapshot applied, by channel, kept across reconnections.
+type SnapshotTimes = Arc<Mutex<HashMap<String, u64>>>;
+
+/// Parse the snapshots of the Bitstamp WebSocket service, dropping those older than the latest
+/// applied on the same channel, which can be replayed after a reconnection, and counting them in
+/// [REORDERED_SNAPSHOTS](REORDERED_SNAPSHOTS). Other messages are parsed as by
+/// [read_bitstamp_book_update](read_bitstamp_book_update).
+fn read_bitstamp_ordered_book_update(snapshot_times: &SnapshotTimes, value: &str) -> Option<ExchangeProtocol<BookUpdate>> {
+    let book_update = match serde_json::from_str::<BitstampBookUpdate>(value) {
+        Ok(book_update) => book_update,
+        Err(_) => return read_bitstamp_book_update(value),
+    };
+    if let Some(microtimestamp) = book_update.data.microtimestamp.as_deref().and_then(|time| time.parse::<u64>().ok()) {
+        let mut snapshot_times = snapshot_times.lock().unwrap();
+        let last_microtimestamp = snapshot_times.entry(book_update.channel.clone()).or_default();
+        if microtimestamp < *last_microtimestamp {
+            debug!("Snapshot of {} at {} older than {}, dropped", book_update.channel, microtimestamp, last_microtimestamp);
+            REORDERED_SNAPSHOTS.increment(BITSTAMP_CODE);
+            return Some(ExchangeProtocol::Skipped);
+        }
+        *last_microtimestamp = microtimestamp;
+    }
+    Some(ExchangeProtocol::Data(book_update.into()))
+}
+
+/// Format a currency pair as in Bitstamp channel names, e.g. `ethbtc`.
+pub fn bitstamp_symbol(product: &CurrencyPair) -> String {
+    product.to_string().to_lowercase()
+}
+
+/// Format the request subscribing to, or unsubscribing from, a channel of the Bitstamp
+/// WebSocket service.
+fn bitstamp_request(channel_code: &str, request: SubscriptionRequest) -> String {
+    let event = match request {
+        SubscriptionRequest::Subscribe => "bts:subscribe",
+        SubscriptionRequest::Unsubscribe => "bts:unsubscribe",
+    };
+    format!(r#"{{"event": "{}","data":{{"channel":"{}"}}}}"#, event, channel_code)
+}
+
+/// Parse the depth snapshots of the Bitstamp REST service, the book data without the
+/// WebSocket envelope.
+#[cfg(feature = "rest")]
+fn read_bitstamp_rest_snapshot(value: &str) -> Option<ExchangeProtocol<BookUpdate>> {
+    match serde_json::from_str::<BitstampBookUpdateData>(value) {
+        Ok(data) => Some(ExchangeProtocol::Data(data.into())),
+        Err(_) => {
+            debug!("Parse failed {:?}", &value);
+            None
+        }
+    }
+}
+
+/// Parse the diffs of the Bitstamp WebSocket service in full-depth mode, applying them to
+/// the local book. Other messages are parsed as by the snapshot service.
+#[cfg(feature = "full-depth")]
+fn read_bitstamp_depth_diff(local_book: &SharedLocalBook, value: &str) -> Option<ExchangeProtocol<BookUpdate>> {
+    match serde_json::from_str::<BitstampDepthDiff>(value) {
+        Ok(BitstampDepthDiff { data }) => match DepthDiff::try_from(data) {
+            Ok(diff) => Some(read_diff(local_book, diff)),
+            Err(_) => {
+                debug!("Parse failed {:?}", &value);
+                None
+            }
+        },
+        Err(_) => read_bitstamp_book_update(value),
+    }
+}
+
+/// Parse the depth snapshots of the Bitstamp REST service in full-depth mode, replacing
+/// the local book.
+#[cfg(feature = "full-depth")]
+fn read_bitstamp_depth_snapshot(local_book: &SharedLocalBook, value: &str) -> Option<ExchangeProtocol<BookUpdate>> {
+    match serde_json::from_str::<BitstampDepthData>(value).map(DepthSnapshot::try_from) {
+        Ok(Ok(snapshot)) => Some(read_snapshot(local_book, snapshot)),
+        _ => {
+            debug!("Parse failed {:?}", &value);
+            None
+        }
+    }
+}
+
+/// Creates an [exchange adapter](ExchangeAdapter) for Bitstamp in full-depth mode: the
+/// complete book is maintained from a REST snapshot and the diffs streamed, and delivered
+/// after each change.
+#[cfg(feature = "full-depth")]
+pub fn make_bitstamp_full_depth_adapter(product: &CurrencyPair) -> ExchangeAdapter<BookUpdate> {
+    let product_code = bitstamp_symbol(product);
+    let channel_code = format!("diff_order_book_{}", product_code);
+    let ws_url = String::from(BITSTAMP_WS_URL);
+    // diffs are only ordered by time, gaps cannot be detected
+    let local_book: SharedLocalBook = Arc::new(Mutex::new(LocalBook::new(BITSTAMP_CODE, false)));
+    let diff_book = local_book.clone();
+    ExchangeAdapter::new(BITSTAMP_CODE, ws_url, Arc::new(move |value: &str| read_bitstamp_depth_diff(&diff_book, value)))
+        .with_subscribe_message(bitstamp_request(&channel_code, SubscriptionRequest::Subscribe))
+        .with_subscription_formatter(Arc::new(|product, request| {
+            bitstamp_request(&format!("diff_order_book_{}", bitstamp_symbol(product)), request)
+        }))
+        .with_subscription_acks()
+        .with_capabilities(ExchangeCapabilities {
+            updates: UpdateKind::Deltas,
+            max_depth: None,
+            heartbeat: HeartbeatKind::WebsocketPing,
+            region: BITSTAMP_REGION,
+        })
+        .with_depth_snapshot(RestFallback::new(
+            format!("{}/{}/", BITSTAMP_REST_URL, product_code),
+            Arc::new(move |value: &str| read_bitstamp_depth_snapshot(&local_book, value)),
+        ))
+}
+
 /// Creates an [exchange adapter](ExchangeAdapter) for Bitstamp.
-pub async fn make_bitstamp_echange_adapter(product: &CurrencyPair) -> ExchangeAdapter<BookUpdate> {
-    let product_code = product.to_string().to_lowercase();
+pub fn make_bitstamp_echange_adapter(product: &CurrencyPair) -> ExchangeAdapter<BookUpdate> {
+    let product_code = bitstamp_symbol(product);
     let channel_code = format!("order_book_{}", product_code);
     let ws_url = String::from(BITSTAMP_WS_URL);
-    let subscribe_message = format!(r#"{{"event": "bts:subscribe","data":{{"channel":"{}"}}}}"#, channel_code);
-    ExchangeAdapter::new(
-        BITSTAMP_CODE,
-        ws_url,
-        subscribe_message,
-        &read_bitstamp_book_update,
-    ).await
+    let snapshot_times = SnapshotTimes::default();
+    let adapter = ExchangeAdapter::new(BITSTAMP_CODE, ws_url, Arc::new(move |value: &str| read_bitstamp_ordered_book_update(&snapshot_times, value)))
+        .with_subscribe_message(bitstamp_request(&channel_code, SubscriptionRequest::Subscribe))
+        .with_subscription_formatter(Arc::new(|product, request| {
+            bitstamp_request(&format!("order_book_{}", bitstamp_symbol(product)), request)
+        }))
+        .with_subscription_acks()
+        .with_capabilities(ExchangeCapabilities {
+            updates: UpdateKind::Snapshots,
+            max_depth: Some(BITSTAMP_SNAPSHOT_LEVELS),
+            heartbeat: HeartbeatKind::WebsocketPing,
+            region: BITSTAMP_REGION,
+        });
+    with_rest_fallback(adapter, &product_code)
+}
+
+/// Poll REST depth snapshots while the WebSocket service cannot be reached.
+#[cfg(feature = "rest")]
+fn with_rest_fallback(adapter: ExchangeAdapter<BookUpdate>, product_code: &str) -> ExchangeAdapter<BookUpdate> {
+    adapter.with_rest_fallback(RestFallback::new(
+        format!("{}/{}/", BITSTAMP_REST_URL, product_code),
+        Arc::new(read_bitstamp_rest_snapshot),
+    ))
+}
+
+/// Without the `rest` feature, the adapter is unchanged.
+#[cfg(not(feature = "rest"))]
+fn with_rest_fallback(adapter: ExchangeAdapter<BookUpdate>, _product_code: &str) -> ExchangeAdapter<BookUpdate> {
+    adapter
 }
 
 #[derive(Deserialize, Debug)]
@@ -56,15 +207,36 @@ struct BitstampPair((String, String));
 
 #[derive(Deserialize, Debug)]
 struct BitstampBookUpdateData {
+    /// Time of the snapshot in microseconds, as a string.
+    #[serde(default)]
+    microtimestamp: Option<String>,
     bids: Vec<BitstampPair>,
     asks: Vec<BitstampPair>,
 }
 
 #[derive(Deserialize, Debug)]
 struct BitstampBookUpdate {
+    /// Channel of the snapshot, e.g. `order_book_ethbtc`.
+    #[serde(default)]
+    channel: String,
     data: BitstampBookUpdateData,
 }
 
+/// Book data of the full-depth mode, with its time in microseconds, used as sequence number.
+#[cfg(feature = "full-depth")]
+#[derive(Deserialize, Debug)]
+struct BitstampDepthData {
+    microtimestamp: String,
+    bids: Vec<BitstampPair>,
+    asks: Vec<BitstampPair>,
+}
+
+#[cfg(feature = "full-depth")]
+#[derive(Deserialize, Debug)]
+struct BitstampDepthDiff {
+    data: BitstampDepthData,
+}
+
 #[derive(Deserialize, Debug)]
 struct BitstampEvent {
     event: String,
@@ -74,23 +246,58 @@ impl From<BitstampPair> for ExchangeLevel {
     fn from(value: BitstampPair) -> Self {
         let BitstampPair((price_str, amount_str)) = value;
         Self {
-            exchange_code: BITSTAMP_CODE,
+            exchange_code: BITSTAMP_CODE.into(),
             price: Decimal::from_str(&price_str).unwrap(),
             amount: Decimal::from_str(&amount_str).unwrap(),
+            order_count: None,
         }
     }
 }
 
-impl From<BitstampBookUpdate> for BookUpdate {
-    fn from(value: BitstampBookUpdate) -> Self {
+impl From<BitstampBookUpdateData> for BookUpdate {
+    fn from(value: BitstampBookUpdateData) -> Self {
         Self {
-            exchange_code: BITSTAMP_CODE,
-            bids: value.data.bids.into_iter().take(NUM_LEVELS).map(|pair| pair.into()).collect(),
-            asks: value.data.asks.into_iter().take(NUM_LEVELS).map(|pair| pair.into()).collect(),
+            exchange_code: BITSTAMP_CODE.into(),
+            bids: value.bids.into_iter().take(NUM_LEVELS).map(|pair| pair.into()).collect(),
+            asks: value.asks.into_iter().take(NUM_LEVELS).map(|pair| pair.into()).collect(),
         }
     }
 }
 
+impl From<BitstampBookUpdate> for BookUpdate {
+    fn from(value: BitstampBookUpdate) -> Self {
+        value.data.into()
+    }
+}
+
+#[cfg(feature = "full-depth")]
+impl TryFrom<BitstampDepthData> for DepthSnapshot {
+    type Error = std::num::ParseIntError;
+
+    fn try_from(value: BitstampDepthData) -> Result<Self, Self::Error> {
+        Ok(Self {
+            sequence: value.microtimestamp.parse()?,
+            bids: value.bids.into_iter().map(|pair| pair.into()).collect(),
+            asks: value.asks.into_iter().map(|pair| pair.into()).collect(),
+        })
+    }
+}
+
+#[cfg(feature = "full-depth")]
+impl TryFrom<BitstampDepthData> for DepthDiff {
+    type Error = std::num::ParseIntError;
+
+    fn try_from(value: BitstampDepthData) -> Result<Self, Self::Error> {
+        let sequence = value.microtimestamp.parse()?;
+        Ok(Self {
+            first_sequence: sequence,
+            last_sequence: sequence,
+            bids: value.bids.into_iter().map(|pair| pair.into()).collect(),
+            asks: value.asks.into_iter().map(|pair| pair.into()).collect(),
+        })
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -101,7 +308,7 @@ mod tests {
         let websocket_msg = r#"{"data":{"timestamp":"1686727555","microtimestamp":"1686727555138288","bids":[["0.00001041","9076.13940234"],["0.00001040","9994.00000000"]],"asks":[["0.00001046","27295.53635305"],["0.00001102","73663.12239490"]]},"channel":"order_book_adabtc","event":"data"}"#;
         let parsed = read_bitstamp_book_update(websocket_msg);
         let expected = Some(ExchangeProtocol::Data(BookUpdate{
-            exchange_code: "bitstamp",
+            exchange_code: "bitstamp".into(),
             bids: vec![
                 ExchangeLevel::from_strs("bitstamp", "0.00001041","9076.13940234"),
                 ExchangeLevel::from_strs("bitstamp", "0.00001040","9994.00000000")
@@ -114,6 +321,29 @@ mod tests {
         assert_eq!(parsed, expected);
     }
 
+    #[test]
+    fn test_read_bitstamp_ordered_book_update() {
+        let snapshot_times = SnapshotTimes::default();
+        let snapshot = |channel: &str, microtimestamp: &str, bid: &str| format!(
+            r#"{{"data":{{"timestamp":"1686727555","microtimestamp":"{}","bids":[["{}","1"]],"asks":[["0.061","2"]]}},"channel":"{}","event":"data"}}"#,
+            microtimestamp, bid, channel,
+        );
+        let best_bid = |parsed: Option<ExchangeProtocol<BookUpdate>>| match parsed {
+            Some(ExchangeProtocol::Data(book_update)) => Some(book_update.bids[0].price.to_string()),
+            _ => None,
+        };
+        let reordered = REORDERED_SNAPSHOTS.get(BITSTAMP_CODE);
+        assert_eq!(best_bid(read_bitstamp_ordered_book_update(&snapshot_times, &snapshot("order_book_ethbtc", "1686727555138288", "0.06"))), Some("0.06".to_string()));
+        let replayed = snapshot("order_book_ethbtc", "1686727554000000", "0.05");
+        assert_eq!(read_bitstamp_ordered_book_update(&snapshot_times, &replayed), Some(ExchangeProtocol::Skipped));
+        // other channels are ordered independently
+        assert_eq!(best_bid(read_bitstamp_ordered_book_update(&snapshot_times, &snapshot("order_book_btcusd", "1686727554000000", "27000"))), Some("27000".to_string()));
+        assert_eq!(best_bid(read_bitstamp_ordered_book_update(&snapshot_times, &snapshot("order_book_ethbtc", "1686727555138288", "0.059"))), Some("0.059".to_string()));
+        assert!(REORDERED_SNAPSHOTS.get(BITSTAMP_CODE) > reordered);
+        let ack = r#"{"event":"bts:subscription_succeeded","channel":"order_book_ethbtc","data":{}}"#;
+        assert_eq!(read_bitstamp_ordered_book_update(&snapshot_times, ack), Some(ExchangeProtocol::SubscriptionAck));
+    }
+
     #[test]
     fn test_read_bitstamp_reconnect_success() {
         let websocket_msg = r#"{"event":"bts:request_reconnect","channel":"","data":"" }"#;
@@ -122,6 +352,32 @@ mod tests {
         assert_eq!(parsed, expected);
     }
 
+    #[test]
+    fn test_read_bitstamp_subscription_ack() {
+        let websocket_msg = r#"{"event":"bts:subscription_succeeded","channel":"order_book_ethbtc","data":{}}"#;
+        assert_eq!(read_bitstamp_book_update(websocket_msg), Some(ExchangeProtocol::SubscriptionAck));
+        let websocket_msg = r#"{"event":"bts:unsubscription_succeeded","channel":"order_book_ethbtc","data":{}}"#;
+        assert_eq!(read_bitstamp_book_update(websocket_msg), Some(ExchangeProtocol::Skipped));
+    }
+
+    #[test]
+    fn test_bitstamp_request() {
+        assert_eq!(bitstamp_request("order_book_ethbtc", SubscriptionRequest::Subscribe), r#"{"event": "bts:subscribe","data":{"channel":"order_book_ethbtc"}}"#);
+        assert_eq!(bitstamp_request("order_book_ethbtc", SubscriptionRequest::Unsubscribe), r#"{"event": "bts:unsubscribe","data":{"channel":"order_book_ethbtc"}}"#);
+    }
+
+    #[cfg(feature = "rest")]
+    #[test]
+    fn test_read_bitstamp_rest_snapshot() {
+        let rest_msg = r#"{"timestamp":"1686727555","microtimestamp":"1686727555138288","bids":[["0.06","1.5"]],"asks":[["0.061","2"]]}"#;
+        let expected = Some(ExchangeProtocol::Data(BookUpdate{
+            exchange_code: "bitstamp".into(),
+            bids: vec![ExchangeLevel::from_strs("bitstamp", "0.06", "1.5")],
+            asks: vec![ExchangeLevel::from_strs("bitstamp", "0.061", "2")],
+        }));
+        assert_eq!(read_bitstamp_rest_snapshot(rest_msg), expected);
+    }
+
     #[test]
     fn test_read_bitstamp_book_update_failure() {
         let websocket_msg = r#"{"lastUpdateId":1580041371,"bids":[["0.00001049","9383.30000000"],["__INCORRECT__"]],"asks":[["0.00001050","133639.50000000"],["0.00001051","133083.10000000"]]}"#;
@@ -132,7 +388,9 @@ mod tests {
     #[test]
     fn test_convert_bitstamp_book_update() {
         let b_book_update = BitstampBookUpdate {
+            channel: "order_book_ethbtc".to_string(),
             data: BitstampBookUpdateData {
+                microtimestamp: None,
                 bids: vec![
                     BitstampPair(("0.123".to_string(), "123.1".to_string())),
                     BitstampPair(("0.321".to_string(), "321.3".to_string()))
@@ -144,7 +402,7 @@ mod tests {
             },
         };
         let exp_book_update = BookUpdate {
-            exchange_code: BITSTAMP_CODE,
+            exchange_code: BITSTAMP_CODE.into(),
             bids: vec![
                 ExchangeLevel::from_strs(BITSTAMP_CODE, "0.123", "123.1"),
                 ExchangeLevel::from_strs(BITSTAMP_CODE, "0.321", "321.3"),
@@ -157,4 +415,25 @@ mod tests {
         let book_update: BookUpdate = b_book_update.into();
         assert_eq!(book_update, exp_book_update);
     }
-}
\ No newline at end of file
+
+    #[cfg(feature = "full-depth")]
+    #[test]
+    fn test_read_bitstamp_full_depth() {
+        let local_book: SharedLocalBook = Arc::new(Mutex::new(LocalBook::new(BITSTAMP_CODE, false)));
+        let snapshot = r#"{"timestamp":"1686727555","microtimestamp":"1686727555138288","bids":[["0.06","1.5"],["0.059","1"]],"asks":[["0.061","2"]]}"#;
+        assert!(matches!(read_bitstamp_depth_snapshot(&local_book, snapshot), Some(ExchangeProtocol::Data(_))));
+        let stale = r#"{"data":{"timestamp":"1686727555","microtimestamp":"1686727555000000","bids":[["0.06","0"]],"asks":[]},"channel":"diff_order_book_ethbtc","event":"data"}"#;
+        assert_eq!(read_bitstamp_depth_diff(&local_book, stale), Some(ExchangeProtocol::Skipped));
+        let diff = r#"{"data":{"timestamp":"1686727556","microtimestamp":"1686727556000000","bids":[["0.06","0"]],"asks":[["0.062","3"]]},"channel":"diff_order_book_ethbtc","event":"data"}"#;
+        assert_eq!(read_bitstamp_depth_diff(&local_book, diff), Some(ExchangeProtocol::Data(BookUpdate {
+            exchange_code: BITSTAMP_CODE.into(),
+            bids: vec![ExchangeLevel::from_strs(BITSTAMP_CODE, "0.059", "1")],
+            asks: vec![
+                ExchangeLevel::from_strs(BITSTAMP_CODE, "0.061", "2"),
+                ExchangeLevel::from_strs(BITSTAMP_CODE, "0.062", "3"),
+            ],
+        })));
+        let ack = r#"{"event":"bts:subscription_succeeded","channel":"diff_order_book_ethbtc","data":{}}"#;
+        assert_eq!(read_bitstamp_depth_diff(&local_book, ack), Some(ExchangeProtocol::SubscriptionAck));
+    }
+}
@@ -0,0 +1,272 @@
+//! Debug log of the mutations of the aggregate book, to diagnose reports of wrong levels:
+//! once [started](start) for a symbol and a time window, each change applied by the shared
+//! feed is written to a file as one human-readable line per price level changed, with the
+//! exchange, side, price, old and new amounts, and the resulting rank of the price.
+
+use log::{error, info};
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::aggregator::AggregateBook;
+use crate::core::Side;
+
+
+/// Whether a log is running, checked before locking [SESSION](SESSION).
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+/// The running log, if any.
+static SESSION: Mutex<Option<DiffLogSession>> = Mutex::new(None);
+
+
+/// A running log.
+struct DiffLogSession {
+    /// Canonical symbol whose book is logged.
+    symbol: String,
+    /// End of the time window.
+    until: Instant,
+    /// Path of the log file.
+    path: PathBuf,
+    /// Writer of the log file.
+    writer: BufWriter<File>,
+}
+
+/// Status of the log.
+#[derive(PartialEq, Debug, Clone)]
+pub struct DiffLogStatus {
+    /// Canonical symbol whose book is logged.
+    pub symbol: String,
+    /// Path of the log file.
+    pub path: PathBuf,
+    /// Time left in the window.
+    pub remaining: Duration,
+}
+
+/// A change of the amount of an exchange at a price of the aggregate book.
+#[derive(PartialEq, Debug, Clone)]
+pub struct LevelDiff {
+    /// Side of the book.
+    pub side: Side,
+    /// The price.
+    pub price: Decimal,
+    /// Amount of the exchange before the change, zero if absent.
+    pub old_amount: Decimal,
+    /// Amount of the exchange after the change, zero if removed.
+    pub new_amount: Decimal,
+    /// Rank of the price in its side after the change, from 1 for the best price,
+    /// [None](None) if the price left the book.
+    pub rank: Option<usize>,
+}
+
+/// Start logging the mutations of the aggregate book of a symbol, replacing the running log if any.
+///
+/// # Arguments
+///
+/// * `dir` - The directory of the log files, created if missing.
+///
+/// * `symbol` - The canonical symbol.
+///
+/// * `duration` - The time window.
+///
+/// # Returns
+///
+/// The path of the log file, or an error if it cannot be created.
+pub fn start(dir: &Path, symbol: &str, duration: Duration) -> io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!("book-diffs-{}-{}.log", symbol, timestamp_ms()));
+    let writer = BufWriter::new(File::create(&path)?);
+    let mut session = SESSION.lock().unwrap();
+    close(session.take());
+    info!("Logging book diffs of {} for {}ms to {}", symbol, duration.as_millis(), path.display());
+    *session = Some(DiffLogSession { symbol: symbol.to_string(), until: Instant::now() + duration, path: path.clone(), writer });
+    ACTIVE.store(true, Ordering::Relaxed);
+    Ok(path)
+}
+
+/// Stop the running log, if any.
+pub fn stop() {
+    close(SESSION.lock().unwrap().take());
+}
+
+/// Status of the running log.
+///
+/// # Returns
+///
+/// An optional [DiffLogStatus](DiffLogStatus), [None](None) if no log is running.
+pub fn status() -> Option<DiffLogStatus> {
+    let mut session = SESSION.lock().unwrap();
+    expire(&mut session);
+    session.as_ref().map(|session| DiffLogStatus {
+        symbol: session.symbol.clone(),
+        path: session.path.clone(),
+        remaining: session.until.saturating_duration_since(Instant::now()),
+    })
+}
+
+/// Whether the mutations of the aggregate book of a symbol are being logged.
+///
+/// # Arguments
+///
+/// * `symbol` - The canonical symbol.
+pub fn is_logging(symbol: &str) -> bool {
+    if !ACTIVE.load(Ordering::Relaxed) {
+        return false;
+    }
+    let mut session = SESSION.lock().unwrap();
+    expire(&mut session);
+    session.as_ref().map(|session| session.symbol == symbol).unwrap_or(false)
+}
+
+/// Write the changes of the levels of an exchange to the running log, if it is for the symbol.
+///
+/// # Arguments
+///
+/// * `symbol` - The canonical symbol.
+///
+/// * `exchange_code` - The exchange whose levels changed.
+///
+/// * `diffs` - The [level changes](LevelDiff).
+pub fn record(symbol: &str, exchange_code: &str, diffs: &[LevelDiff]) {
+    let mut session = SESSION.lock().unwrap();
+    let Some(running) = session.as_mut().filter(|session| session.symbol == symbol) else {
+        return;
+    };
+    let timestamp_ms = timestamp_ms();
+    let result = diffs.iter()
+        .try_for_each(|diff| writeln!(running.writer, "{} {}", timestamp_ms, format_diff(exchange_code, diff)))
+        .and_then(|_| running.writer.flush());
+    if let Err(write_error) = result {
+        error!("Book diffs not written to {}: {}", running.path.display(), write_error);
+        close(session.take());
+    }
+}
+
+/// Levels of an exchange in an aggregate book.
+///
+/// # Arguments
+///
+/// * `aggregate_book` - The [AggregateBook](AggregateBook).
+///
+/// * `exchange_code` - The exchange code.
+///
+/// # Returns
+///
+/// The amount of the exchange at each side and price.
+pub fn venue_levels(aggregate_book: &AggregateBook, exchange_code: &str) -> BTreeMap<(Side, Decimal), Decimal> {
+    let mut result = BTreeMap::new();
+    for side in [Side::Buy, Side::Sell] {
+        for level in aggregate_book.levels(side) {
+            if let Some(amount) = level.amount_of(exchange_code) {
+                result.insert((side, level.price()), amount);
+            }
+        }
+    }
+    result
+}
+
+/// Compare the levels of an exchange before a change with the aggregate book after it.
+///
+/// # Arguments
+///
+/// * `before` - The [levels of the exchange](venue_levels) before the change.
+///
+/// * `aggregate_book` - The [AggregateBook](AggregateBook) after the change.
+///
+/// * `exchange_code` - The exchange code.
+///
+/// # Returns
+///
+/// The [level changes](LevelDiff), bids then asks, by price.
+pub fn diff(before: &BTreeMap<(Side, Decimal), Decimal>, aggregate_book: &AggregateBook, exchange_code: &str) -> Vec<LevelDiff> {
+    let after = venue_levels(aggregate_book, exchange_code);
+    let mut keys: Vec<&(Side, Decimal)> = before.keys().chain(after.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    keys.into_iter().filter_map(|&(side, price)| {
+        let old_amount = before.get(&(side, price)).copied().unwrap_or_default();
+        let new_amount = after.get(&(side, price)).copied().unwrap_or_default();
+        (old_amount != new_amount).then(|| LevelDiff {
+            side,
+            price,
+            old_amount,
+            new_amount,
+            rank: aggregate_book.levels(side).position(|level| level.price() == price).map(|rank| rank + 1),
+        })
+    }).collect()
+}
+
+/// Format a level change on a single line, e.g. `binance bid 100.5 1->2 rank 3`.
+///
+/// # Arguments
+///
+/// * `exchange_code` - The exchange whose level changed.
+///
+/// * `diff` - The [LevelDiff](LevelDiff).
+///
+/// # Returns
+///
+/// The formatted line.
+pub fn format_diff(exchange_code: &str, diff: &LevelDiff) -> String {
+    let side = match diff.side {
+        Side::Buy => "bid",
+        Side::Sell => "ask",
+    };
+    let rank = diff.rank.map(|rank| rank.to_string()).unwrap_or_else(|| "-".to_string());
+    format!("{} {} {} {}->{} rank {}", exchange_code, side, diff.price, diff.old_amount, diff.new_amount, rank)
+}
+
+/// Internal function closing the running log once its window is over.
+fn expire(session: &mut Option<DiffLogSession>) {
+    if session.as_ref().map(|session| Instant::now() >= session.until).unwrap_or(false) {
+        close(session.take());
+    }
+}
+
+/// Internal function closing a log.
+fn close(session: Option<DiffLogSession>) {
+    if let Some(mut session) = session {
+        let _ = session.writer.flush();
+        info!("Stopped logging book diffs of {} to {}", session.symbol, session.path.display());
+    }
+    ACTIVE.store(false, Ordering::Relaxed);
+}
+
+/// Internal function returning the current time, in milliseconds since the UNIX epoch.
+fn timestamp_ms() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or_default()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{BookUpdate, ExchangeLevel};
+
+    #[test]
+    fn test_diff() {
+        let mut aggregate_book = AggregateBook::full_depth(10);
+        aggregate_book.update(BookUpdate {
+            exchange_code: "bitstamp".into(),
+            bids: vec![ExchangeLevel::from_strs("bitstamp", "101", "1")],
+            asks: vec![],
+        });
+        aggregate_book.update(BookUpdate {
+            exchange_code: "binance".into(),
+            bids: vec![ExchangeLevel::from_strs("binance", "100", "1"), ExchangeLevel::from_strs("binance", "99", "2")],
+            asks: vec![ExchangeLevel::from_strs("binance", "102", "3")],
+        });
+        let before = venue_levels(&aggregate_book, "binance");
+        aggregate_book.update(BookUpdate {
+            exchange_code: "binance".into(),
+            bids: vec![ExchangeLevel::from_strs("binance", "100", "2")],
+            asks: vec![ExchangeLevel::from_strs("binance", "102", "3")],
+        });
+        let diffs = diff(&before, &aggregate_book, "binance");
+        let lines: Vec<String> = diffs.iter().map(|diff| format_diff("binance", diff)).collect();
+        assert_eq!(lines, vec!["binance bid 99 2->0 rank -", "binance bid 100 1->2 rank 2"]);
+    }
+}
@@ -0,0 +1,235 @@
+//! Bybit spot `WebSocket` exchange adapter for the `orderbook.50` topic: a snapshot of the best
+//! 50 levels is streamed after each subscription, followed by deltas, maintained in a
+//! [LocalBook](LocalBook) and delivered after each change.
+
+use log::debug;
+use rust_decimal::prelude::*;
+use serde::Deserialize;
+use std::sync::{Arc, Mutex};
+use tokio::time::Duration;
+
+use crate::capabilities::{ExchangeCapabilities, HeartbeatKind, UpdateKind};
+use crate::core::*;
+use crate::exchange::{ExchangeAdapter, ExchangeProtocol, SubscriptionRequest};
+use crate::local_book::{read_diff, read_snapshot, DepthDiff, DepthSnapshot, LocalBook, SharedLocalBook};
+
+
+const BYBIT_CODE: &str = "bybit";
+const BYBIT_WS_URL: &str = "wss://stream.bybit.com/v5/public/spot";
+/// Region of the Bybit endpoints.
+const BYBIT_REGION: &str = "ap-southeast-1";
+/// Levels of each side of the book streamed.
+const BYBIT_BOOK_DEPTH: usize = 50;
+/// Heartbeat request, without which Bybit closes the connection.
+const BYBIT_PING_MESSAGE: &str = r#"{"op":"ping"}"#;
+/// Interval between two heartbeat requests, as recommended by Bybit.
+const BYBIT_PING_INTERVAL: Duration = Duration::from_secs(20);
+
+
+/// Parse string messages from the Bybit spot WebSocket service into the exchange
+/// [protocol](ExchangeProtocol), applying the snapshots and deltas to the local book.
+/// It recognizes book messages, subscription acknowledgments and heartbeat responses.
+fn read_bybit_book(local_book: &SharedLocalBook, value: &str) -> Option<ExchangeProtocol<BookUpdate>> {
+    let protocol = match serde_json::from_str::<BybitMessage>(value) {
+        Ok(BybitMessage::Book { kind: BybitBookKind::Snapshot, data }) => {
+            DepthSnapshot::try_from(data).ok().map(|snapshot| read_snapshot(local_book, snapshot))
+        },
+        Ok(BybitMessage::Book { kind: BybitBookKind::Delta, data }) => {
+            DepthDiff::try_from(data).ok().map(|diff| read_diff(local_book, diff))
+        },
+        Ok(BybitMessage::Response { success: true, op }) if op == "subscribe" => Some(ExchangeProtocol::SubscriptionAck),
+        Ok(BybitMessage::Response { success: true, .. }) => Some(ExchangeProtocol::Skipped),
+        _ => None,
+    };
+    if protocol.is_none() {
+        debug!("Parse failed {:?}", value);
+    }
+    protocol
+}
+
+/// Format a currency pair as in Bybit symbols, e.g. `ETHBTC`.
+pub fn bybit_symbol(product: &CurrencyPair) -> String {
+    product.to_string().to_uppercase()
+}
+
+/// Format the request subscribing to, or unsubscribing from, the book topic of a currency pair.
+fn bybit_request(product: &CurrencyPair, request: SubscriptionRequest) -> String {
+    let op = match request {
+        SubscriptionRequest::Subscribe => "subscribe",
+        SubscriptionRequest::Unsubscribe => "unsubscribe",
+    };
+    format!(r#"{{"op":"{}","args":["orderbook.{}.{}"]}}"#, op, BYBIT_BOOK_DEPTH, bybit_symbol(product))
+}
+
+/// Creates an [exchange adapter](ExchangeAdapter) for the Bybit spot book. The local book is
+/// shared by the clones of the adapter, each snapshot replacing it.
+pub fn make_bybit_exchange_adapter(product: &CurrencyPair) -> ExchangeAdapter<BookUpdate> {
+    // update identifiers are not documented as contiguous, gaps cannot be detected
+    let local_book: SharedLocalBook = Arc::new(Mutex::new(LocalBook::new(BYBIT_CODE, false)));
+    ExchangeAdapter::new(BYBIT_CODE, String::from(BYBIT_WS_URL), Arc::new(move |value: &str| read_bybit_book(&local_book, value)))
+        .with_subscribe_message(bybit_request(product, SubscriptionRequest::Subscribe))
+        .with_subscription_formatter(Arc::new(bybit_request))
+        .with_subscription_acks()
+        .with_keepalive_message(String::from(BYBIT_PING_MESSAGE), BYBIT_PING_INTERVAL)
+        .with_capabilities(ExchangeCapabilities {
+            updates: UpdateKind::Deltas,
+            max_depth: Some(BYBIT_BOOK_DEPTH),
+            heartbeat: HeartbeatKind::Application,
+            region: BYBIT_REGION,
+        })
+}
+
+/// A message of the Bybit WebSocket service.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum BybitMessage {
+    /// A message of the book topic.
+    Book {
+        #[serde(rename = "type")]
+        kind: BybitBookKind,
+        data: BybitBookData,
+    },
+    /// Response to a request, such as a subscription or a ping.
+    Response {
+        success: bool,
+        op: String,
+    },
+}
+
+/// Kind of a message of the book topic.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "lowercase")]
+enum BybitBookKind {
+    /// The complete book, replacing the local one.
+    Snapshot,
+    /// Changes of the book: a level with a zero amount is removed.
+    Delta,
+}
+
+#[derive(Deserialize, Debug)]
+struct BybitPair((String, String));
+
+/// Levels of a book message, with the identifier of its update.
+#[derive(Deserialize, Debug)]
+struct BybitBookData {
+    #[serde(rename = "b")]
+    bids: Vec<BybitPair>,
+    #[serde(rename = "a")]
+    asks: Vec<BybitPair>,
+    #[serde(rename = "u")]
+    update_id: u64,
+}
+
+impl TryFrom<BybitPair> for ExchangeLevel {
+    type Error = rust_decimal::Error;
+
+    fn try_from(value: BybitPair) -> Result<Self, Self::Error> {
+        let BybitPair((price_str, amount_str)) = value;
+        Ok(Self {
+            exchange_code: BYBIT_CODE.into(),
+            price: Decimal::from_str(&price_str)?,
+            amount: Decimal::from_str(&amount_str)?,
+            order_count: None,
+        })
+    }
+}
+
+/// Internal function converting the pairs of a side.
+fn bybit_levels(pairs: Vec<BybitPair>) -> Result<Vec<ExchangeLevel>, rust_decimal::Error> {
+    pairs.into_iter().map(ExchangeLevel::try_from).collect()
+}
+
+impl TryFrom<BybitBookData> for DepthSnapshot {
+    type Error = rust_decimal::Error;
+
+    fn try_from(value: BybitBookData) -> Result<Self, Self::Error> {
+        Ok(Self {
+            sequence: value.update_id,
+            bids: bybit_levels(value.bids)?,
+            asks: bybit_levels(value.asks)?,
+        })
+    }
+}
+
+impl TryFrom<BybitBookData> for DepthDiff {
+    type Error = rust_decimal::Error;
+
+    fn try_from(value: BybitBookData) -> Result<Self, Self::Error> {
+        Ok(Self {
+            first_sequence: value.update_id,
+            last_sequence: value.update_id,
+            bids: bybit_levels(value.bids)?,
+            asks: bybit_levels(value.asks)?,
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local_book() -> SharedLocalBook {
+        Arc::new(Mutex::new(LocalBook::new(BYBIT_CODE, false)))
+    }
+
+    #[test]
+    fn test_read_bybit_book() {
+        let local_book = local_book();
+        let snapshot = r#"{"topic":"orderbook.50.ETHBTC","ts":1687940967466,"type":"snapshot","data":{"s":"ETHBTC","b":[["0.0612","1.5"],["0.0611","2"]],"a":[["0.0613","1"],["0.0614","3"]],"u":18521288,"seq":7961638724},"cts":1687940967464}"#;
+        assert_eq!(read_bybit_book(&local_book, snapshot), Some(ExchangeProtocol::Data(BookUpdate {
+            exchange_code: BYBIT_CODE.into(),
+            bids: vec![
+                ExchangeLevel::from_strs(BYBIT_CODE, "0.0612", "1.5"),
+                ExchangeLevel::from_strs(BYBIT_CODE, "0.0611", "2"),
+            ],
+            asks: vec![
+                ExchangeLevel::from_strs(BYBIT_CODE, "0.0613", "1"),
+                ExchangeLevel::from_strs(BYBIT_CODE, "0.0614", "3"),
+            ],
+        })));
+        let delta = r#"{"topic":"orderbook.50.ETHBTC","ts":1687940967566,"type":"delta","data":{"s":"ETHBTC","b":[["0.0612","0"],["0.0610","4"]],"a":[["0.0613","2.5"]],"u":18521289,"seq":7961638725},"cts":1687940967564}"#;
+        assert_eq!(read_bybit_book(&local_book, delta), Some(ExchangeProtocol::Data(BookUpdate {
+            exchange_code: BYBIT_CODE.into(),
+            bids: vec![
+                ExchangeLevel::from_strs(BYBIT_CODE, "0.0611", "2"),
+                ExchangeLevel::from_strs(BYBIT_CODE, "0.0610", "4"),
+            ],
+            asks: vec![
+                ExchangeLevel::from_strs(BYBIT_CODE, "0.0613", "2.5"),
+                ExchangeLevel::from_strs(BYBIT_CODE, "0.0614", "3"),
+            ],
+        })));
+        assert_eq!(read_bybit_book(&local_book, delta), Some(ExchangeProtocol::Skipped));
+    }
+
+    #[test]
+    fn test_read_bybit_responses() {
+        let local_book = local_book();
+        assert_eq!(
+            read_bybit_book(&local_book, r#"{"success":true,"ret_msg":"subscribe","conn_id":"2324d924-aa4d-45b0-a858-7b8be29ab52b","req_id":"","op":"subscribe"}"#),
+            Some(ExchangeProtocol::SubscriptionAck),
+        );
+        assert_eq!(
+            read_bybit_book(&local_book, r#"{"success":true,"ret_msg":"pong","conn_id":"0970e817-426e-429a-a679-ff7f55e0b16a","op":"ping"}"#),
+            Some(ExchangeProtocol::Skipped),
+        );
+        assert_eq!(
+            read_bybit_book(&local_book, r#"{"success":false,"ret_msg":"error:handler not found","conn_id":"2324d924-aa4d-45b0-a858-7b8be29ab52b","op":"subscribe"}"#),
+            None,
+        );
+        // a delta before any snapshot requires a new one
+        let delta = r#"{"topic":"orderbook.50.ETHBTC","ts":1687940967566,"type":"delta","data":{"s":"ETHBTC","b":[],"a":[],"u":2,"seq":1},"cts":1687940967564}"#;
+        assert_eq!(read_bybit_book(&local_book, delta), Some(ExchangeProtocol::ReconnectionRequest));
+        assert_eq!(read_bybit_book(&local_book, r#"{"topic":"orderbook.50.ETHBTC","type":"delta","data":{"b":[["__INCORRECT__"]]}}"#), None);
+        assert_eq!(read_bybit_book(&local_book, r#"{"topic":"orderbook.50.ETHBTC","type":"snapshot","data":{"s":"ETHBTC","b":[["0.0612","1.5"]],"a":[["__INCORRECT__","1"]],"u":1}}"#), None);
+    }
+
+    #[test]
+    fn test_bybit_symbol() {
+        let product = CurrencyPair { main: "eth".to_string(), counter: "btc".to_string() };
+        assert_eq!(bybit_symbol(&product), "ETHBTC");
+        assert_eq!(bybit_request(&product, SubscriptionRequest::Subscribe), r#"{"op":"subscribe","args":["orderbook.50.ETHBTC"]}"#);
+        assert_eq!(bybit_request(&product, SubscriptionRequest::Unsubscribe), r#"{"op":"unsubscribe","args":["orderbook.50.ETHBTC"]}"#);
+    }
+}
@@ -0,0 +1,93 @@
+//! Capabilities declared by each [exchange adapter](crate::exchange::ExchangeAdapter), so that
+//! clients can discover what each venue delivers instead of hardcoding it.
+
+use serde::Serialize;
+
+
+/// Kind of the book data streamed by an exchange.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateKind {
+    /// Snapshots of the best levels.
+    #[default]
+    Snapshots,
+    /// Changes of the price levels, applied to a local book.
+    Deltas,
+    /// Individual orders, reduced to price levels.
+    Orders,
+}
+
+/// How the liveness of the connection to an exchange is checked.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum HeartbeatKind {
+    /// WebSocket ping frames sent by the exchange.
+    #[default]
+    WebsocketPing,
+    /// Heartbeat messages of the exchange protocol.
+    Application,
+    /// No heartbeat.
+    None,
+}
+
+/// Capabilities of an exchange.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct ExchangeCapabilities {
+    /// Kind of the book data streamed.
+    pub updates: UpdateKind,
+    /// Maximum number of levels, or orders, of each side received, [None](None) if unlimited.
+    pub max_depth: Option<usize>,
+    /// How the liveness of the connection is checked.
+    pub heartbeat: HeartbeatKind,
+    /// Region of the endpoint.
+    pub region: &'static str,
+}
+
+impl Default for ExchangeCapabilities {
+    fn default() -> Self {
+        Self {
+            updates: UpdateKind::default(),
+            max_depth: None,
+            heartbeat: HeartbeatKind::default(),
+            region: "unknown",
+        }
+    }
+}
+
+impl UpdateKind {
+    /// Name of the kind, as serialized.
+    pub fn name(&self) -> &'static str {
+        match self {
+            UpdateKind::Snapshots => "snapshots",
+            UpdateKind::Deltas => "deltas",
+            UpdateKind::Orders => "orders",
+        }
+    }
+}
+
+impl HeartbeatKind {
+    /// Name of the kind, as serialized.
+    pub fn name(&self) -> &'static str {
+        match self {
+            HeartbeatKind::WebsocketPing => "websocket_ping",
+            HeartbeatKind::Application => "application",
+            HeartbeatKind::None => "none",
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_as_serialized() {
+        for updates in [UpdateKind::Snapshots, UpdateKind::Deltas, UpdateKind::Orders] {
+            assert_eq!(serde_json::to_value(updates).unwrap(), updates.name());
+        }
+        for heartbeat in [HeartbeatKind::WebsocketPing, HeartbeatKind::Application, HeartbeatKind::None] {
+            assert_eq!(serde_json::to_value(heartbeat).unwrap(), heartbeat.name());
+        }
+    }
+}
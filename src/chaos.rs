@@ -0,0 +1,139 @@
+//! Fault injection for resilience testing: an [exchange adapter](crate::exchange::ExchangeAdapter)
+//! configured with [with_chaos](crate::exchange::ExchangeAdapter::with_chaos) randomly drops,
+//! corrupts or delays the messages received, or drops its connection, at configurable rates.
+//! Faults are drawn from a seeded pseudo-random sequence, so that failing runs can be replayed.
+
+use tokio::time::Duration;
+
+
+/// Rates of the faults injected, as probabilities per message received.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChaosConfig {
+    /// Probability of dropping a message.
+    pub drop_rate: f64,
+    /// Probability of truncating a message, making it invalid JSON.
+    pub corrupt_rate: f64,
+    /// Probability of dropping the connection.
+    pub disconnect_rate: f64,
+    /// Probability of delaying a message.
+    pub delay_rate: f64,
+    /// Maximum delay of a delayed message.
+    pub max_delay: Duration,
+    /// Seed of the pseudo-random sequence.
+    pub seed: u64,
+}
+
+impl Default for ChaosConfig {
+    /// No faults.
+    fn default() -> Self {
+        Self { drop_rate: 0.0, corrupt_rate: 0.0, disconnect_rate: 0.0, delay_rate: 0.0, max_delay: Duration::ZERO, seed: 1 }
+    }
+}
+
+/// Fault applied to a message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChaosAction {
+    /// Process the message normally.
+    Deliver,
+    /// Ignore the message.
+    Drop,
+    /// Process a truncated copy of the message.
+    Corrupt(String),
+    /// Drop the connection.
+    Disconnect,
+    /// Process the message after a delay.
+    Delay(Duration),
+}
+
+/// Draws the fault applied to each message.
+pub struct ChaosMonkey {
+    /// Fault rates.
+    config: ChaosConfig,
+    /// State of the xorshift pseudo-random generator, never zero.
+    state: u64,
+}
+
+impl ChaosMonkey {
+    /// Create a new [ChaosMonkey](ChaosMonkey) object.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The [ChaosConfig](ChaosConfig).
+    ///
+    /// * `stream` - Distinguishes the sequences of successive connections with the same seed.
+    pub fn new(config: ChaosConfig, stream: u64) -> Self {
+        let state = (config.seed ^ stream.wrapping_mul(0x9E37_79B9_7F4A_7C15)).max(1);
+        Self { config, state }
+    }
+
+    /// Next pseudo-random number, uniform in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Draw the fault applied to a message.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message received.
+    ///
+    /// # Returns
+    ///
+    /// A [ChaosAction](ChaosAction).
+    pub fn next_action(&mut self, message: &str) -> ChaosAction {
+        let draw = self.next_f64();
+        let mut threshold = self.config.disconnect_rate;
+        if draw < threshold {
+            return ChaosAction::Disconnect;
+        }
+        threshold += self.config.drop_rate;
+        if draw < threshold {
+            return ChaosAction::Drop;
+        }
+        threshold += self.config.corrupt_rate;
+        if draw < threshold {
+            let cut = message.char_indices().nth(message.chars().count() / 2).map(|(i, _)| i).unwrap_or(0);
+            return ChaosAction::Corrupt(message[..cut].to_string());
+        }
+        threshold += self.config.delay_rate;
+        if draw < threshold {
+            return ChaosAction::Delay(self.config.max_delay.mul_f64(self.next_f64()));
+        }
+        ChaosAction::Deliver
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_faults_by_default() {
+        let mut monkey = ChaosMonkey::new(ChaosConfig::default(), 0);
+        assert!((0..1000).all(|_| monkey.next_action("{}") == ChaosAction::Deliver));
+    }
+
+    #[test]
+    fn test_fault_rates() {
+        let config = ChaosConfig { drop_rate: 0.2, corrupt_rate: 0.1, delay_rate: 0.1, max_delay: Duration::from_millis(10), ..ChaosConfig::default() };
+        let mut monkey = ChaosMonkey::new(config, 0);
+        let actions: Vec<ChaosAction> = (0..10_000).map(|_| monkey.next_action("0123456789")).collect();
+        let count = |f: fn(&ChaosAction) -> bool| actions.iter().filter(|&a| f(a)).count();
+        assert!((1800..2200).contains(&count(|a| *a == ChaosAction::Drop)));
+        assert!((800..1200).contains(&count(|a| matches!(a, ChaosAction::Corrupt(m) if m == "01234"))));
+        assert!((800..1200).contains(&count(|a| matches!(a, ChaosAction::Delay(d) if *d < Duration::from_millis(10)))));
+        assert_eq!(count(|a| *a == ChaosAction::Disconnect), 0);
+    }
+
+    #[test]
+    fn test_replayable() {
+        let config = ChaosConfig { drop_rate: 0.5, seed: 42, ..ChaosConfig::default() };
+        let mut first = ChaosMonkey::new(config.clone(), 3);
+        let mut second = ChaosMonkey::new(config, 3);
+        assert!((0..100).all(|_| first.next_action("{}") == second.next_action("{}")));
+    }
+}
@@ -1,33 +1,40 @@
 //! Utility to parse command line arguments for server and client programs.
 
-use std::env::Args;
+use std::{env::Args, iter::Peekable};
 use crate::core::CurrencyPair;
+use crate::symbols::parse_currency_pair;
 
 
 const DEFAULT_PORT: u16 = 50000;
 const DEFAULT_MESSAGE_NUM: usize = 500;
-const CURRENCY_PAIR_MESSAGE: &str = "ERROR: argument <currency pair> must have shape cur1-cur2 (e.g. ETH-BTC)";
+const CURRENCY_PAIR_MESSAGE: &str = "ERROR: argument <currency pair> must have shape cur1-cur2 (e.g. ETH-BTC), or a common alias (ETH/BTC, ETHBTC, XETHXXBT)";
 
 
 /// Utility class to help with command line option parsing.
 pub struct ArgParser {
-    args: Args,
+    args: Peekable<Args>,
     usage: &'static str,
 }
 
 impl ArgParser {
     pub fn new(mut args: Args, usage: &'static str) -> Self {
         let _ = args.next();
-        Self { args, usage }
+        Self { args: args.peekable(), usage }
     }
 
     pub fn extract_currency_pair(&mut self) -> CurrencyPair {
         let pair_str = self.args.next().expect(self.usage);
-        assert!(pair_str.len() >= 7 && pair_str.contains('-'), "{}", CURRENCY_PAIR_MESSAGE);
-        let mut cur_strs = pair_str.split('-');
-        let main = cur_strs.next().expect(CURRENCY_PAIR_MESSAGE).to_string();
-        let counter = cur_strs.next().expect(CURRENCY_PAIR_MESSAGE).to_string();
-        CurrencyPair { main, counter }
+        parse_currency_pair(&pair_str).expect(CURRENCY_PAIR_MESSAGE)
+    }
+
+    /// Extract one or more currency pairs, up to the first argument which is not one.
+    pub fn extract_currency_pairs(&mut self) -> Vec<CurrencyPair> {
+        let mut pairs = vec![self.extract_currency_pair()];
+        while let Some(pair) = self.args.peek().and_then(|arg| parse_currency_pair(arg)) {
+            let _ = self.args.next();
+            pairs.push(pair);
+        }
+        pairs
     }
 
     pub fn extract_message_num(&mut self) -> usize {
@@ -39,6 +46,23 @@ impl ArgParser {
         }
     }
 
+    pub fn extract_number<T: std::str::FromStr>(&mut self, default: T) -> T {
+        let number_str = self.args.next();
+        match number_str.as_deref().map(|s| s.parse()) {
+            None => default,
+            Some(Ok(n)) => n,
+            Some(Err(_)) => panic!("Could not parse provided number {}", number_str.unwrap()),
+        }
+    }
+
+    pub fn extract_string(&mut self) -> String {
+        self.args.next().expect(self.usage)
+    }
+
+    pub fn extract_optional_string(&mut self) -> Option<String> {
+        self.args.next()
+    }
+
     pub fn extract_port(&mut self) -> u16 {
         let port_str = self.args.next();
         let port_res = port_str.as_deref().map(|s| s.parse()).unwrap_or(Ok(DEFAULT_PORT));
@@ -5,7 +5,7 @@ use log::{LevelFilter, info};
 use simple_logger::SimpleLogger;
 use tokio_stream::StreamExt;
 
-use orderbook_server::orderbook::{orderbook_aggregator_client::OrderbookAggregatorClient, Empty};
+use orderbook_server::orderbook::{orderbook_aggregator_client::OrderbookAggregatorClient, BookSummaryRequest};
 use orderbook_server::cli::ArgParser;
 
 
@@ -24,7 +24,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     );
     info!("Streaming orderbook for {} messages", message_num);
     let stream = client
-        .book_summary(Empty {})
+        .book_summary(BookSummaryRequest::default())
         .await
         .unwrap()
         .into_inner();
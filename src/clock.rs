@@ -0,0 +1,123 @@
+//! Time source abstraction, so that time-driven behaviours (reconnection delays,
+//! update windows, etc.) can be driven deterministically in tests.
+
+use std::{future::Future, pin::Pin, sync::Arc};
+use tokio::{sync::watch, time::{sleep, Duration, Instant}};
+
+
+/// Type alias for a future returned by [Clock::sleep](Clock::sleep).
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Type alias for a clock shared between tasks.
+pub type SharedClock = Arc<dyn Clock>;
+
+/// A source of time.
+pub trait Clock: Send + Sync {
+    /// Current instant according to this clock.
+    fn now(&self) -> Instant;
+
+    /// Create a future completing once `duration` has elapsed according to this clock.
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - How long to wait.
+    ///
+    /// # Returns
+    ///
+    /// A [Sleep](Sleep) future.
+    fn sleep(&self, duration: Duration) -> Sleep;
+}
+
+/// Clock backed by the `tokio` timer, to be used in production.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(sleep(duration))
+    }
+}
+
+/// Create a [shared](SharedClock) [system clock](SystemClock).
+pub fn system_clock() -> SharedClock {
+    Arc::new(SystemClock)
+}
+
+/// Clock whose time only moves when [advance](ManualClock::advance) is called.
+/// Pending [sleeps](Clock::sleep) complete as soon as the clock is advanced past their deadline.
+pub struct ManualClock {
+    /// Instant corresponding to the creation of the clock.
+    start: Instant,
+    /// Time elapsed since `start`, observed by pending sleeps.
+    elapsed: watch::Sender<Duration>,
+}
+
+impl ManualClock {
+    /// Create a new [ManualClock](ManualClock) object, starting at the current instant.
+    pub fn new() -> Self {
+        let (elapsed, _) = watch::channel(Duration::ZERO);
+        Self { start: Instant::now(), elapsed }
+    }
+
+    /// Move the clock forward.
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - The amount of time to add to the clock.
+    pub fn advance(&self, duration: Duration) {
+        self.elapsed.send_modify(|elapsed| *elapsed += duration);
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.borrow()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        let deadline = *self.elapsed.borrow() + duration;
+        let mut elapsed = self.elapsed.subscribe();
+        Box::pin(async move {
+            while *elapsed.borrow_and_update() < deadline {
+                if elapsed.changed().await.is_err() {
+                    break;
+                }
+            }
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    #[test]
+    fn test_manual_clock_now() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(clock.now() - start, Duration::from_millis(250));
+    }
+
+    #[tokio::test]
+    async fn test_manual_clock_sleep() {
+        let clock = ManualClock::new();
+        let mut sleep = clock.sleep(Duration::from_millis(200));
+        assert!((&mut sleep).now_or_never().is_none());
+        clock.advance(Duration::from_millis(199));
+        assert!((&mut sleep).now_or_never().is_none());
+        clock.advance(Duration::from_millis(1));
+        assert!(sleep.now_or_never().is_some());
+    }
+}
@@ -0,0 +1,477 @@
+//! Typed schema of the JSON configuration files: each file is deserialized into its struct,
+//! then checked against the constraints of its fields (positive counts and depths, ordered
+//! thresholds, well-formed URLs and ports), every violation of every file being collected so
+//! that a misconfigured server reports all of them at once on startup.
+//! The files with defaults can be printed fully commented, one comment line per field; full-line
+//! `//` comments are accepted when the files are read, so that the printed files can be edited in place.
+
+use serde::{de::DeserializeOwned, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+
+/// Prefix of the comment lines of the configuration files.
+const COMMENT_PREFIX: &str = "//";
+/// Indentation of the top level fields in the pretty printed files.
+const FIELD_INDENT: &str = "  ";
+
+
+/// A constraint violated by a configuration file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationError {
+    /// The file.
+    pub file: String,
+    /// Path of the field, e.g. `symbol_significant_digits.BTC-USDT`, empty if the file cannot be read.
+    pub field: String,
+    /// Description of the violation.
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.field.is_empty() {
+            write!(f, "{}: {}", self.file, self.message)
+        } else {
+            write!(f, "{}: {} {}", self.file, self.field, self.message)
+        }
+    }
+}
+
+/// Every constraint violated by one or more configuration files.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ValidationErrors(Vec<ValidationError>);
+
+impl ValidationErrors {
+    /// The violations, in order of detection.
+    pub fn errors(&self) -> &[ValidationError] {
+        &self.0
+    }
+
+    /// Whether no constraint is violated.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid configuration ({} errors)", self.0.len())?;
+        for error in &self.0 {
+            write!(f, "\n  {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+/// Collector of the constraints violated by a configuration file.
+pub struct Validator {
+    /// The file.
+    file: String,
+    /// The violations.
+    errors: Vec<ValidationError>,
+}
+
+impl Validator {
+    /// Create a new [Validator](Validator) object.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The file, reported in the violations.
+    pub fn new(file: &str) -> Self {
+        Self { file: file.to_string(), errors: vec![] }
+    }
+
+    /// Record a violation.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The path of the field.
+    ///
+    /// * `message` - The description of the violation.
+    pub fn error(&mut self, field: &str, message: &str) {
+        self.errors.push(ValidationError { file: self.file.clone(), field: field.to_string(), message: message.to_string() });
+    }
+
+    /// Check that a count, depth or duration is positive.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The path of the field.
+    ///
+    /// * `value` - The value.
+    pub fn positive(&mut self, field: &str, value: u64) {
+        if value == 0 {
+            self.error(field, "must be positive");
+        }
+    }
+
+    /// Check that a real number is finite and positive.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The path of the field.
+    ///
+    /// * `value` - The value.
+    pub fn positive_f64(&mut self, field: &str, value: f64) {
+        if !value.is_finite() || value <= 0.0 {
+            self.error(field, "must be a positive number");
+        }
+    }
+
+    /// Check that a value is not above another.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The path of the field.
+    ///
+    /// * `value` - The value.
+    ///
+    /// * `other_field` - The path of the other field.
+    ///
+    /// * `other` - The value of the other field.
+    pub fn not_above<T: PartialOrd>(&mut self, field: &str, value: T, other_field: &str, other: T) {
+        if value > other {
+            self.error(field, &format!("must not be above {}", other_field));
+        }
+    }
+
+    /// Check that a value is one of those supported.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The path of the field.
+    ///
+    /// * `value` - The value.
+    ///
+    /// * `supported` - The supported values.
+    pub fn one_of<T: PartialEq + fmt::Display>(&mut self, field: &str, value: T, supported: &[T]) {
+        if !supported.contains(&value) {
+            let supported: Vec<String> = supported.iter().map(|value| value.to_string()).collect();
+            self.error(field, &format!("must be one of {}, not {}", supported.join(", "), value));
+        }
+    }
+
+    /// Check that a URL has one of some schemes, a host, and a valid port if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The path of the field.
+    ///
+    /// * `url` - The URL.
+    ///
+    /// * `schemes` - The accepted schemes.
+    pub fn url(&mut self, field: &str, url: &str, schemes: &[&str]) {
+        let Some((scheme, rest)) = url.split_once("://") else {
+            return self.error(field, &format!("must be a URL, not \"{}\"", url));
+        };
+        if !schemes.contains(&scheme) {
+            return self.error(field, &format!("must be a {} URL, not \"{}\"", schemes.join(" or "), url));
+        }
+        let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+        let authority = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+        // the port follows the last colon, except within a bracketed IPv6 address
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.ends_with(']') => (host, Some(port)),
+            _ => (authority, None),
+        };
+        if host.is_empty() || host == "[]" {
+            return self.error(field, &format!("must have a host, not \"{}\"", url));
+        }
+        if let Some(port) = port {
+            self.port(field, port);
+        }
+    }
+
+    /// Check that a port number is valid.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The path of the field.
+    ///
+    /// * `port` - The port number.
+    pub fn port(&mut self, field: &str, port: &str) {
+        if !matches!(port.parse::<u16>(), Ok(port) if port > 0) {
+            self.error(field, &format!("must have a port between 1 and 65535, not \"{}\"", port));
+        }
+    }
+}
+
+/// A configuration struct with constraints on its fields.
+pub trait Validate {
+    /// Record the constraints violated.
+    ///
+    /// # Arguments
+    ///
+    /// * `validator` - The [Validator](Validator) of the file.
+    fn validate(&self, validator: &mut Validator);
+}
+
+/// A configuration file whose defaults can be printed, fully commented.
+pub trait DocumentedConfig: Validate + Serialize + Default {
+    /// Description of the file.
+    const DESCRIPTION: &'static str;
+    /// Description of each top level field, by name.
+    const FIELDS: &'static [(&'static str, &'static str)];
+}
+
+/// Remove the full-line comments of a configuration file, keeping the line numbers.
+fn strip_comments(text: &str) -> String {
+    text.lines()
+        .map(|line| if line.trim_start().starts_with(COMMENT_PREFIX) { "" } else { line })
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// Read and validate a configuration file, if it exists.
+///
+/// # Arguments
+///
+/// * `path` - The path of the JSON file.
+///
+/// # Returns
+///
+/// The configuration, [None](None) if the file does not exist, or the violations if the file
+/// cannot be read or the configuration is not valid.
+pub fn load_optional<T: DeserializeOwned + Validate>(path: &Path) -> Result<Option<T>, ValidationErrors> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let mut validator = Validator::new(&path.display().to_string());
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(error) => {
+            validator.error("", &error.to_string());
+            return Err(ValidationErrors(validator.errors));
+        },
+    };
+    match serde_json::from_str::<T>(&strip_comments(&text)) {
+        Ok(config) => {
+            config.validate(&mut validator);
+            if validator.errors.is_empty() {
+                Ok(Some(config))
+            } else {
+                Err(ValidationErrors(validator.errors))
+            }
+        },
+        Err(error) => {
+            validator.error("", &error.to_string());
+            Err(ValidationErrors(validator.errors))
+        },
+    }
+}
+
+/// Read and validate a configuration file, if it exists.
+///
+/// # Arguments
+///
+/// * `path` - The path of the JSON file.
+///
+/// # Returns
+///
+/// The configuration, with the defaults if the file does not exist, or the violations if the
+/// file cannot be read or the configuration is not valid.
+pub fn load<T: DeserializeOwned + Validate + Default>(path: &Path) -> Result<T, ValidationErrors> {
+    Ok(load_optional(path)?.unwrap_or_default())
+}
+
+/// Loader of several configuration files, collecting the violations of all of them.
+#[derive(Default)]
+pub struct ConfigLoader {
+    /// The violations so far.
+    errors: ValidationErrors,
+}
+
+impl ConfigLoader {
+    /// Read and validate a configuration file, if it exists, with [load](load).
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the JSON file.
+    ///
+    /// # Returns
+    ///
+    /// The configuration, with the defaults if the file does not exist or is not valid.
+    pub fn load<T: DeserializeOwned + Validate + Default>(&mut self, path: &Path) -> T {
+        self.load_optional(path).unwrap_or_default()
+    }
+
+    /// Read and validate a configuration file, if it exists, with [load_optional](load_optional).
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the JSON file.
+    ///
+    /// # Returns
+    ///
+    /// The configuration, [None](None) if the file does not exist or is not valid.
+    pub fn load_optional<T: DeserializeOwned + Validate>(&mut self, path: &Path) -> Option<T> {
+        match load_optional(path) {
+            Ok(config) => config,
+            Err(errors) => {
+                self.errors.0.extend(errors.0);
+                None
+            },
+        }
+    }
+
+    /// Record a violation detected outside the loader.
+    ///
+    /// # Arguments
+    ///
+    /// * `error` - The violation.
+    pub fn error(&mut self, error: ValidationError) {
+        self.errors.0.push(error);
+    }
+
+    /// Terminate the loading.
+    ///
+    /// # Returns
+    ///
+    /// The violations of all the files read, if any.
+    pub fn finish(self) -> Result<(), ValidationErrors> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors)
+        }
+    }
+}
+
+/// Print the defaults of a configuration file, with a comment describing the file and a
+/// comment before each top level field.
+///
+/// # Arguments
+///
+/// * `file` - The name of the file.
+///
+/// # Returns
+///
+/// The commented JSON content of the file.
+pub fn commented_defaults<T: DocumentedConfig>(file: &str) -> String {
+    commented_file(file, T::DESCRIPTION, &commented_json(&T::default()))
+}
+
+/// Serialize a configuration file as its description and its JSON content with a comment before
+/// each top level field, for a configuration serialized with one field per file, named after the
+/// file, to be printed with [commented_files](commented_files).
+///
+/// # Arguments
+///
+/// * `config` - The configuration of the file.
+///
+/// * `serializer` - The [Serializer](Serializer).
+pub fn serialize_documented<T: DocumentedConfig, S: Serializer>(config: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    (T::DESCRIPTION, commented_json(config)).serialize(serializer)
+}
+
+/// Serialize a configuration file which may be missing as with
+/// [serialize_documented](serialize_documented), with its defaults if it is.
+///
+/// # Arguments
+///
+/// * `config` - The optional configuration of the file.
+///
+/// * `serializer` - The [Serializer](Serializer).
+pub fn serialize_documented_optional<T: DocumentedConfig, S: Serializer>(config: &Option<T>, serializer: S) -> Result<S::Ok, S::Error> {
+    match config {
+        Some(config) => serialize_documented(config, serializer),
+        None => serialize_documented(&T::default(), serializer),
+    }
+}
+
+/// Print the configuration files of a configuration, each with a comment describing the file
+/// and a comment before each top level field.
+///
+/// # Arguments
+///
+/// * `config` - The configuration, serialized with one field per file, named after the file,
+///   each with [serialize_documented](serialize_documented).
+///
+/// # Returns
+///
+/// The name and commented JSON content of each file, by name, or an error if a field is not
+/// serialized with [serialize_documented](serialize_documented).
+pub fn commented_files<C: Serialize>(config: &C) -> Result<Vec<(String, String)>, serde_json::Error> {
+    let files: BTreeMap<String, (String, String)> = serde_json::from_value(serde_json::to_value(config)?)?;
+    Ok(files.into_iter()
+        .map(|(file, (description, json))| {
+            let text = commented_file(&file, &description, &json);
+            (file, text)
+        })
+        .collect())
+}
+
+/// Internal function prefixing the commented JSON content of a file with its description.
+fn commented_file(file: &str, description: &str, json: &str) -> String {
+    format!("{} {}: {}\n{}", COMMENT_PREFIX, file, description, json)
+}
+
+/// Internal function pretty printing a configuration with a comment before each top level field.
+fn commented_json<T: DocumentedConfig>(config: &T) -> String {
+    let json = serde_json::to_string_pretty(config).expect("configuration not serializable");
+    let mut lines = vec![];
+    for line in json.lines() {
+        let field = line.strip_prefix(FIELD_INDENT)
+            .filter(|field| field.starts_with('"'))
+            .and_then(|field| field[1..].split_once('"'))
+            .map(|(name, _)| name);
+        if let Some((_, description)) = field.and_then(|name| T::FIELDS.iter().find(|(field, _)| *field == name)) {
+            lines.push(format!("{}{} {}", FIELD_INDENT, COMMENT_PREFIX, description));
+        }
+        lines.push(line.to_string());
+    }
+    lines.join("\n")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queues::QueueCapacities;
+
+    #[test]
+    fn test_url() {
+        let mut validator = Validator::new("test.json");
+        validator.url("a", "https://example.com:8443/hook?x=1", &["http", "https"]);
+        validator.url("b", "http://user@[::1]:80", &["http"]);
+        validator.url("c", "http://[::1]/hook", &["http"]);
+        assert!(validator.errors.is_empty(), "{:?}", validator.errors);
+        validator.url("d", "example.com", &["http"]);
+        validator.url("e", "ftp://example.com", &["http", "https"]);
+        validator.url("f", "http://:80", &["http"]);
+        validator.url("g", "http://example.com:0", &["http"]);
+        validator.url("h", "http://example.com:http", &["http"]);
+        let fields: Vec<&str> = validator.errors.iter().map(|error| error.field.as_str()).collect();
+        assert_eq!(fields, vec!["d", "e", "f", "g", "h"]);
+        assert_eq!(validator.errors[1].to_string(), "test.json: e must be a http or https URL, not \"ftp://example.com\"");
+    }
+
+    #[test]
+    fn test_commented_defaults_loadable() {
+        let text = commented_defaults::<QueueCapacities>("queues.json");
+        assert!(text.starts_with("// queues.json: "));
+        assert!(text.contains("  // Data from each exchange adapter.\n  \"adapter_data\": 16,"));
+        let capacities: QueueCapacities = serde_json::from_str(&strip_comments(&text)).unwrap();
+        assert_eq!(capacities, QueueCapacities::default());
+    }
+
+    #[test]
+    fn test_commented_files() {
+        #[derive(Serialize)]
+        struct Files {
+            #[serde(rename = "queues.json", serialize_with = "serialize_documented")]
+            queues: QueueCapacities,
+            #[serde(rename = "optional_queues.json", serialize_with = "serialize_documented_optional")]
+            optional_queues: Option<QueueCapacities>,
+        }
+        let files = commented_files(&Files { queues: QueueCapacities::default(), optional_queues: None }).unwrap();
+        let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["optional_queues.json", "queues.json"]);
+        assert_eq!(files[1].1, commented_defaults::<QueueCapacities>("queues.json"));
+        let capacities: QueueCapacities = serde_json::from_str(&strip_comments(&files[0].1)).unwrap();
+        assert_eq!(capacities, QueueCapacities::default());
+    }
+}
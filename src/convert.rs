@@ -0,0 +1,57 @@
+//! Converts a recording of summaries, e.g. the output of the pipe sink redirected to a file,
+//! to another format, optionally keeping the summaries of a single symbol, created within a
+//! time range.
+
+use log::{LevelFilter, info};
+use simple_logger::SimpleLogger;
+use std::{env, fs::File, io::{BufReader, BufWriter}, str::FromStr, time::SystemTime};
+
+use orderbook_server::cli::ArgParser;
+use orderbook_server::proto_ext::{Timestamp, TimestampExt};
+use orderbook_server::recording::{convert, RecordingFormat, TimeRange};
+use orderbook_server::symbols::canonicalize;
+
+
+const USAGE_MESSAGE: &str = "Usage: convert <input> <ndjson|protobuf> <output> <ndjson|protobuf|csv> [currency pair] [--from <time>] [--to <time>]";
+
+
+/// Parse a time, as an RFC 3339 string or milliseconds since the epoch.
+fn parse_time(time: &str) -> Result<SystemTime, String> {
+    let timestamp = match time.parse::<u64>() {
+        Ok(millis) => Timestamp::from_millis(millis),
+        Err(_) => Timestamp::from_str(time).map_err(|_| format!("invalid time: {}\n{}", time, USAGE_MESSAGE))?,
+    };
+    timestamp.to_system_time().ok_or_else(|| format!("invalid time: {}\n{}", time, USAGE_MESSAGE))
+}
+
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    SimpleLogger::new().with_level(LevelFilter::Info).init().unwrap();
+    let mut arg_parser = ArgParser::new(env::args(), USAGE_MESSAGE);
+    let input = arg_parser.extract_string();
+    let input_format = RecordingFormat::parse(&arg_parser.extract_string())
+        .map_err(|error| format!("{}\n{}", error, USAGE_MESSAGE))?;
+    let output = arg_parser.extract_string();
+    let output_format = RecordingFormat::parse(&arg_parser.extract_string())
+        .map_err(|error| format!("{}\n{}", error, USAGE_MESSAGE))?;
+    let mut symbol = None;
+    let mut time_range = TimeRange::default();
+    while let Some(arg) = arg_parser.extract_optional_string() {
+        match arg.as_str() {
+            "--from" => time_range.from = Some(parse_time(&arg_parser.extract_string())?),
+            "--to" => time_range.to = Some(parse_time(&arg_parser.extract_string())?),
+            pair if symbol.is_none() => symbol = Some(canonicalize(pair).ok_or(USAGE_MESSAGE)?),
+            _ => return Err(USAGE_MESSAGE.into()),
+        }
+    }
+    if let (Some(from), Some(to)) = (time_range.from, time_range.to) {
+        if from >= to {
+            return Err(format!("empty time range\n{}", USAGE_MESSAGE).into());
+        }
+    }
+    let mut reader = BufReader::new(File::open(&input)?);
+    let mut writer = BufWriter::new(File::create(&output)?);
+    let stats = convert(&mut reader, input_format, &mut writer, output_format, symbol.as_deref(), &time_range)?;
+    info!("Converted {} of {} summaries from {} to {}", stats.written, stats.read, input, output);
+    Ok(())
+}
@@ -1,21 +1,26 @@
 //! Base data structures.
 
-use std::fmt::{Display, Formatter};
+use std::borrow::Borrow;
+use std::collections::HashSet;
+use std::fmt::{Debug, Display, Formatter};
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, OnceLock};
 use rust_decimal::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 /// Default number of levels for each side of the consolidated trading book.
 pub const NUM_LEVELS: usize = 10;
 
 
 /// Trading book side indicator
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum Side {
     Buy,
     Sell,
 }
 
 /// The product traded: a currency pair
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub struct CurrencyPair {
     pub main: String,
     pub counter: String,
@@ -27,36 +32,296 @@ impl Display for CurrencyPair {
     }
 }
 
+/// Get the shared allocation of an exchange code, allocating it on first use only, so that
+/// every [ExchangeId](ExchangeId) of the same code shares one allocation.
+///
+/// # Arguments
+///
+/// * `exchange_code` - The exchange code.
+///
+/// # Returns
+///
+/// The interned exchange code.
+fn intern_exchange_code(exchange_code: &str) -> Arc<str> {
+    static CODES: OnceLock<Mutex<HashSet<Arc<str>>>> = OnceLock::new();
+    let mut codes = CODES.get_or_init(|| Mutex::new(HashSet::new())).lock().unwrap();
+    match codes.get(exchange_code) {
+        Some(code) => code.clone(),
+        None => {
+            let code: Arc<str> = Arc::from(exchange_code);
+            codes.insert(code.clone());
+            code
+        }
+    }
+}
+
+/// Code of an exchange, cheap to clone and compare: every code, known at compile time or
+/// discovered at runtime, e.g. from configuration files, plugins or deserialized data, is
+/// [interned](ExchangeId::new) once and shared.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ExchangeId(Arc<str>);
+
+impl ExchangeId {
+    /// Create an [ExchangeId](ExchangeId), interning its code.
+    ///
+    /// # Arguments
+    ///
+    /// * `exchange_code` - The exchange code.
+    pub fn new(exchange_code: &str) -> Self {
+        Self(intern_exchange_code(exchange_code))
+    }
+
+    /// The exchange code.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for ExchangeId {
+    fn from(exchange_code: &str) -> Self {
+        Self::new(exchange_code)
+    }
+}
+
+impl From<&ExchangeId> for ExchangeId {
+    fn from(exchange_code: &ExchangeId) -> Self {
+        exchange_code.clone()
+    }
+}
+
+impl Deref for ExchangeId {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Consistent with the derived [Hash](Hash), which hashes the code itself.
+impl Borrow<str> for ExchangeId {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for ExchangeId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Formatted as the code, like a string.
+impl Debug for ExchangeId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&*self.0, f)
+    }
+}
+
+impl PartialEq<str> for ExchangeId {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for ExchangeId {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl PartialEq<ExchangeId> for &str {
+    fn eq(&self, other: &ExchangeId) -> bool {
+        *self == &*other.0
+    }
+}
+
+/// Serialized as the code, like a string.
+impl Serialize for ExchangeId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for ExchangeId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self::new(&String::deserialize(deserializer)?))
+    }
+}
+
 /// Part of a trading book snapshot received from an exchange.
 /// This object represents a single price level belonging to a side of the book (bid/ask).
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Hash, Debug, Clone, Serialize, Deserialize)]
 pub struct ExchangeLevel {
     /// Exchange code
-    pub exchange_code: &'static str,
+    pub exchange_code: ExchangeId,
     /// Level price
     pub price: Decimal,
     /// Amount available on the exchange's book
     pub amount: Decimal,
+    /// Number of orders at this price, if reported by the exchange
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order_count: Option<u32>,
 }
 
 impl ExchangeLevel {
     /// Utility function to create an [ExchangeLevel](ExchangeLevel) object from string values.
-    pub fn from_strs(exchange_code: &'static str, price_str: &str, amount_str: &str) -> ExchangeLevel {
+    pub fn from_strs(exchange_code: impl Into<ExchangeId>, price_str: &str, amount_str: &str) -> ExchangeLevel {
         ExchangeLevel {
-            exchange_code,
+            exchange_code: exchange_code.into(),
             price: Decimal::from_str(price_str).unwrap(),
             amount: Decimal::from_str(amount_str).unwrap(),
+            order_count: None,
         }
     }
+
+    /// Set the number of orders at this price.
+    ///
+    /// # Arguments
+    ///
+    /// * `order_count` - The number of orders.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeLevel](ExchangeLevel) object.
+    pub fn with_order_count(mut self, order_count: u32) -> Self {
+        self.order_count = Some(order_count);
+        self
+    }
 }
 
 /// A trading book snapshot from an exchange.
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Hash, Debug, Clone, Serialize, Deserialize)]
 pub struct BookUpdate {
     /// Exchange code
-    pub exchange_code: &'static str,
+    pub exchange_code: ExchangeId,
     /// Bid levels
     pub bids: Vec<ExchangeLevel>,
     /// Ask levels
     pub asks: Vec<ExchangeLevel>,
 }
+
+impl BookUpdate {
+    /// Whether the best bid is at or above the best ask, a sign of a corrupt book.
+    /// Levels are expected from the best price.
+    pub fn is_inverted(&self) -> bool {
+        match (self.bids.first(), self.asks.first()) {
+            (Some(best_bid), Some(best_ask)) => best_bid.price >= best_ask.price,
+            _ => false,
+        }
+    }
+
+    /// Normalize the prices and amounts, removing their trailing zeros, so that the same
+    /// value formatted differently by different exchanges, e.g. `0.00001040` and `0.000010400`,
+    /// has a single representation downstream.
+    ///
+    /// # Returns
+    ///
+    /// The normalized [BookUpdate](BookUpdate).
+    pub fn normalize(mut self) -> Self {
+        for level in self.bids.iter_mut().chain(self.asks.iter_mut()) {
+            level.price = level.price.normalize();
+            level.amount = level.amount.normalize();
+        }
+        self
+    }
+}
+
+/// Change of a single order of the book of an exchange, from order-by-order (L3) feeds.
+/// Orders are reduced to price levels by an [OrderBook](crate::orders::OrderBook).
+#[derive(PartialEq, Debug, Clone)]
+pub enum OrderEvent {
+    /// New order, or replacement of an existing order with the same identifier.
+    Add {
+        /// Exchange-specific order identifier.
+        order_id: String,
+        /// [Buy](Side::Buy) for bids, [Sell](Side::Sell) for asks.
+        side: Side,
+        /// Limit price.
+        price: Decimal,
+        /// Amount left.
+        amount: Decimal,
+    },
+    /// Change of the amount left of an order, keeping its place in the queue.
+    Modify {
+        /// Exchange-specific order identifier.
+        order_id: String,
+        /// Amount left.
+        amount: Decimal,
+    },
+    /// Order filled or cancelled.
+    Delete {
+        /// Exchange-specific order identifier.
+        order_id: String,
+    },
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern_exchange_code() {
+        let owned = String::from("test");
+        let code = intern_exchange_code(&owned);
+        assert_eq!(&*code, "test");
+        assert!(Arc::ptr_eq(&code, &intern_exchange_code(&owned)));
+    }
+
+    #[test]
+    fn test_exchange_id() {
+        let runtime = ExchangeId::new(&String::from("test"));
+        assert_eq!(runtime, ExchangeId::from("test"));
+        assert_eq!(runtime, "test");
+        assert!(std::ptr::eq(runtime.as_str(), ExchangeId::from("test").as_str()));
+        assert_eq!(format!("{} {:?}", runtime, runtime), "test \"test\"");
+        let levels = std::collections::HashMap::from([(runtime, 1)]);
+        assert_eq!(levels.get("test"), Some(&1));
+    }
+
+    #[test]
+    fn test_book_update_serde_round_trip() {
+        let book_update = BookUpdate {
+            exchange_code: "test".into(),
+            bids: vec![ExchangeLevel::from_strs("test", "0.0612", "1.5")],
+            asks: vec![ExchangeLevel::from_strs("test", "0.0613", "2")],
+        };
+        let json = serde_json::to_string(&book_update).unwrap();
+        assert_eq!(
+            json,
+            r#"{"exchange_code":"test","bids":[{"exchange_code":"test","price":"0.0612","amount":"1.5"}],"asks":[{"exchange_code":"test","price":"0.0613","amount":"2"}]}"#
+        );
+        assert_eq!(serde_json::from_str::<BookUpdate>(&json).unwrap(), book_update.clone());
+        let level = ExchangeLevel::from_strs("test", "0.0612", "1.5").with_order_count(3);
+        let json = serde_json::to_string(&level).unwrap();
+        assert_eq!(json, r#"{"exchange_code":"test","price":"0.0612","amount":"1.5","order_count":3}"#);
+        assert_eq!(serde_json::from_str::<ExchangeLevel>(&json).unwrap(), level);
+    }
+
+    #[test]
+    fn test_inverted_book() {
+        let book_update = |bid: &str, ask: &str| BookUpdate {
+            exchange_code: "test".into(),
+            bids: vec![ExchangeLevel::from_strs("test", bid, "1")],
+            asks: vec![ExchangeLevel::from_strs("test", ask, "1")],
+        };
+        assert!(!book_update("0.0612", "0.0613").is_inverted());
+        assert!(book_update("0.0613", "0.0613").is_inverted());
+        assert!(book_update("0.0614", "0.0613").is_inverted());
+        assert!(!BookUpdate { exchange_code: "test".into(), bids: vec![], asks: vec![ExchangeLevel::from_strs("test", "0.0613", "1")] }.is_inverted());
+    }
+
+    #[test]
+    fn test_normalize() {
+        let book_update = BookUpdate {
+            exchange_code: "test".into(),
+            bids: vec![ExchangeLevel::from_strs("test", "0.000010400", "12.50"), ExchangeLevel::from_strs("test", "100.00", "1000")],
+            asks: vec![ExchangeLevel::from_strs("test", "0.00001050", "0.0")],
+        }.normalize();
+        let prices: Vec<String> = book_update.bids.iter().chain(&book_update.asks).map(|level| level.price.to_string()).collect();
+        let amounts: Vec<String> = book_update.bids.iter().chain(&book_update.asks).map(|level| level.amount.to_string()).collect();
+        assert_eq!(prices, vec!["0.0000104", "100", "0.0000105"]);
+        assert_eq!(amounts, vec!["12.5", "1000", "0"]);
+    }
+}
@@ -0,0 +1,214 @@
+//! Diagnostic dumps of unexpected panics in the exchange adapters and in the aggregation, to
+//! make rare failures debuggable: once the [panic hook](install_panic_hook) is installed, the
+//! last raw message and the sequence state of each exchange are tracked, and a panic while
+//! parsing a message or updating the aggregate book writes them to a file in the dump
+//! directory, with the panic message and the top levels of the aggregate book, before the
+//! supervisor restarts the task.
+
+use log::error;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::aggregator::AggregateBook;
+use crate::core::{ExchangeId, ExchangeLevel};
+
+
+/// Directory of the dumps, set when the panic hook is installed.
+static DUMP_DIR: OnceLock<PathBuf> = OnceLock::new();
+/// Diagnostic state of each exchange, tracked once the panic hook is installed.
+static VENUES: Mutex<BTreeMap<ExchangeId, VenueDiagnostics>> = Mutex::new(BTreeMap::new());
+
+thread_local! {
+    /// Message and location of the last panic of the thread, recorded by the panic hook.
+    static LAST_PANIC: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+
+/// Diagnostic state of an exchange.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct VenueDiagnostics {
+    /// Number of messages received.
+    pub messages: u64,
+    /// Sequence number of the last change applied to the local book, for exchanges in
+    /// full-depth mode.
+    pub sequence: Option<u64>,
+    /// Last raw message received.
+    pub last_message: String,
+}
+
+/// Install a panic hook recording the message and location of each panic for the dumps,
+/// before running the previous hook, and start tracking the state of the exchanges.
+/// Only the first call has effect.
+///
+/// # Arguments
+///
+/// * `dir` - The directory of the dumps, created if missing.
+///
+/// # Returns
+///
+/// An error if the directory cannot be created.
+pub fn install_panic_hook(dir: PathBuf) -> io::Result<()> {
+    std::fs::create_dir_all(&dir)?;
+    if DUMP_DIR.set(dir).is_err() {
+        return Ok(());
+    }
+    let previous_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        LAST_PANIC.with(|last_panic| *last_panic.borrow_mut() = Some(info.to_string()));
+        previous_hook(info);
+    }));
+    Ok(())
+}
+
+/// Whether the panic hook is installed and the state of the exchanges tracked.
+pub fn enabled() -> bool {
+    DUMP_DIR.get().is_some()
+}
+
+/// Track a raw message received from an exchange, if enabled. The buffer of the previous
+/// message is reused.
+///
+/// # Arguments
+///
+/// * `exchange_code` - The exchange code.
+///
+/// * `text` - The raw message.
+pub fn record_message(exchange_code: &ExchangeId, text: &str) {
+    if !enabled() {
+        return;
+    }
+    let mut venues = VENUES.lock().unwrap();
+    let venue = venues.entry(exchange_code.clone()).or_default();
+    venue.messages += 1;
+    venue.last_message.clear();
+    venue.last_message.push_str(text);
+}
+
+/// Track the sequence number of the last change applied to the local book of an exchange,
+/// if enabled.
+///
+/// # Arguments
+///
+/// * `exchange_code` - The exchange code.
+///
+/// * `sequence` - The sequence number.
+pub fn record_sequence(exchange_code: &ExchangeId, sequence: u64) {
+    if !enabled() {
+        return;
+    }
+    VENUES.lock().unwrap().entry(exchange_code.clone()).or_default().sequence = Some(sequence);
+}
+
+/// Format the best levels of an aggregate book for a dump, one level per line.
+///
+/// # Arguments
+///
+/// * `aggregate_book` - The [AggregateBook](AggregateBook).
+///
+/// # Returns
+///
+/// The formatted levels, bids then asks.
+pub fn format_book(aggregate_book: &AggregateBook) -> String {
+    let mut result = String::new();
+    for (side, levels) in [("bid", aggregate_book.best_bids()), ("ask", aggregate_book.best_asks())] {
+        for ExchangeLevel { exchange_code, price, amount, .. } in levels {
+            let _ = writeln!(result, "{} {} {} {}", side, price, amount, exchange_code);
+        }
+    }
+    result
+}
+
+/// Write a dump after a panic caught while processing data from an exchange, if enabled.
+/// It must be called on the thread which panicked.
+///
+/// # Arguments
+///
+/// * `exchange_code` - The exchange whose data was being processed.
+///
+/// * `book` - The [formatted](format_book) top levels of the aggregate book, if available.
+///
+/// # Returns
+///
+/// The path of the dump, [None](None) if not enabled or not written.
+pub fn write_dump(exchange_code: &str, book: Option<&str>) -> Option<PathBuf> {
+    let dir = DUMP_DIR.get()?;
+    let panic = LAST_PANIC.with(|last_panic| last_panic.borrow_mut().take())
+        .unwrap_or_else(|| "unknown panic".to_string());
+    let venues = VENUES.lock().map(|venues| venues.clone()).unwrap_or_else(|poisoned| poisoned.into_inner().clone());
+    let timestamp_ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or_default();
+    let path = dir.join(format!("crash-{}-{}.txt", exchange_code, timestamp_ms));
+    match std::fs::write(&path, render(&panic, exchange_code, book, &venues)) {
+        Ok(_) => {
+            error!("Crash dump for {} written to {}", exchange_code, path.display());
+            Some(path)
+        },
+        Err(write_error) => {
+            error!("Crash dump for {} not written to {}: {}", exchange_code, path.display(), write_error);
+            None
+        },
+    }
+}
+
+/// Render the content of a dump.
+///
+/// # Arguments
+///
+/// * `panic` - The panic message and location.
+///
+/// * `exchange_code` - The exchange whose data was being processed.
+///
+/// * `book` - The formatted top levels of the aggregate book, if available.
+///
+/// * `venues` - The [diagnostic state](VenueDiagnostics) of each exchange.
+///
+/// # Returns
+///
+/// The content of the dump.
+pub fn render(panic: &str, exchange_code: &str, book: Option<&str>, venues: &BTreeMap<ExchangeId, VenueDiagnostics>) -> String {
+    let mut result = format!("panic: {}\nexchange: {}\n", panic, exchange_code);
+    if let Some(book) = book {
+        let _ = write!(result, "\naggregate book:\n{}", book);
+    }
+    for (venue_code, venue) in venues {
+        let sequence = venue.sequence.map(|sequence| sequence.to_string()).unwrap_or_else(|| "none".to_string());
+        let _ = write!(
+            result,
+            "\nvenue {}: {} messages, sequence {}\nlast message: {}\n",
+            venue_code, venue.messages, sequence, venue.last_message,
+        );
+    }
+    result
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::BookUpdate;
+
+    #[test]
+    fn test_render() {
+        let mut aggregate_book = AggregateBook::new(2);
+        aggregate_book.update(BookUpdate {
+            exchange_code: "binance".into(),
+            bids: vec![ExchangeLevel::from_strs("binance", "99", "1")],
+            asks: vec![ExchangeLevel::from_strs("binance", "100", "2")],
+        });
+        let venues = BTreeMap::from([
+            (ExchangeId::from("binance"), VenueDiagnostics { messages: 3, sequence: Some(42), last_message: r#"{"u":42}"#.to_string() }),
+            (ExchangeId::from("bitstamp"), VenueDiagnostics { messages: 1, sequence: None, last_message: "{}".to_string() }),
+        ]);
+        assert_eq!(
+            render("boom at src/aggregator.rs:1:1", "binance", Some(&format_book(&aggregate_book)), &venues),
+            "panic: boom at src/aggregator.rs:1:1\nexchange: binance\n\
+             \naggregate book:\nbid 99 1 binance\nask 100 2 binance\n\
+             \nvenue binance: 3 messages, sequence 42\nlast message: {\"u\":42}\n\
+             \nvenue bitstamp: 1 messages, sequence none\nlast message: {}\n",
+        );
+    }
+}
@@ -0,0 +1,188 @@
+//! Cross-check of the consolidation: a naive book, rebuilt from the latest book of each exchange
+//! on every update, is maintained alongside the incremental [aggregate book](AggregateBook), and
+//! the best price levels of both are compared, to detect defects of the incremental algorithm.
+//! The exchange books are taken as complete, as delivered in the full depth mode.
+
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::aggregator::AggregateBook;
+use crate::core::{BookUpdate, ExchangeId, Side};
+
+
+/// A consolidated price level: the amount of each exchange at a price.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PriceLevel {
+    /// The price.
+    pub price: Decimal,
+    /// The amount of each exchange at the price, by exchange code.
+    pub amounts: BTreeMap<ExchangeId, Decimal>,
+}
+
+/// A difference between the naive and the incremental books.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Discrepancy {
+    /// The side of the books.
+    pub side: Side,
+    /// The position of the price level, from the best price.
+    pub position: usize,
+    /// The level of the naive book, [None](None) if missing.
+    pub expected: Option<PriceLevel>,
+    /// The level of the incremental book, [None](None) if missing.
+    pub actual: Option<PriceLevel>,
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} level {}: expected {:?}, found {:?}", self.side, self.position, self.expected, self.actual)
+    }
+}
+
+/// The naive consolidated book, keeping the latest book of each exchange.
+#[derive(Clone, Debug)]
+pub struct CrossCheck {
+    /// Number of price levels compared on each side, from the best price.
+    depth: usize,
+    /// The latest book of each exchange, by exchange code.
+    books: BTreeMap<ExchangeId, BookUpdate>,
+}
+
+impl CrossCheck {
+    /// Create a new [CrossCheck](CrossCheck) object, with no books.
+    ///
+    /// # Arguments
+    ///
+    /// * `depth` - Number of price levels to compare on each side.
+    pub fn new(depth: usize) -> Self {
+        Self { depth, books: BTreeMap::new() }
+    }
+
+    /// Replace the book of an exchange.
+    ///
+    /// # Arguments
+    ///
+    /// * `book_update` - The complete [book](BookUpdate) of the exchange.
+    pub fn update(&mut self, book_update: BookUpdate) {
+        self.books.insert(book_update.exchange_code.clone(), book_update);
+    }
+
+    /// Remove the book of an exchange, e.g. disconnected.
+    ///
+    /// # Arguments
+    ///
+    /// * `exchange_code` - The exchange code.
+    pub fn remove_exchange(&mut self, exchange_code: &str) {
+        self.books.remove(exchange_code);
+    }
+
+    /// Rebuild the best price levels of a side from the books of all the exchanges.
+    ///
+    /// # Arguments
+    ///
+    /// * `side` - The [side](Side).
+    ///
+    /// # Returns
+    ///
+    /// A [vector](Vec) of at most `depth` [price levels](PriceLevel), from the best price.
+    pub fn levels(&self, side: Side) -> Vec<PriceLevel> {
+        let mut prices: BTreeMap<Decimal, BTreeMap<ExchangeId, Decimal>> = BTreeMap::new();
+        for book in self.books.values() {
+            let levels = match side {
+                Side::Buy => &book.bids,
+                Side::Sell => &book.asks,
+            };
+            for level in levels {
+                prices.entry(level.price).or_default().insert(level.exchange_code.clone(), level.amount);
+            }
+        }
+        let levels = prices.into_iter().map(|(price, amounts)| PriceLevel { price, amounts });
+        match side {
+            Side::Buy => levels.rev().take(self.depth).collect(),
+            Side::Sell => levels.take(self.depth).collect(),
+        }
+    }
+
+    /// Compare the best price levels of the naive book with the ones of an aggregate book.
+    ///
+    /// # Arguments
+    ///
+    /// * `aggregate_book` - The incremental [AggregateBook](AggregateBook).
+    ///
+    /// # Returns
+    ///
+    /// A [vector](Vec) of [discrepancies](Discrepancy), bids first, empty if the books agree.
+    pub fn compare(&self, aggregate_book: &AggregateBook) -> Vec<Discrepancy> {
+        let mut discrepancies = vec![];
+        for side in [Side::Buy, Side::Sell] {
+            let expected = self.levels(side);
+            let actual: Vec<PriceLevel> = aggregate_book.levels(side)
+                .take(self.depth)
+                .map(|level| PriceLevel {
+                    price: level.price(),
+                    amounts: level.levels_by_amount().into_iter()
+                        .map(|venue| (venue.exchange_code.clone(), venue.amount))
+                        .collect(),
+                })
+                .collect();
+            for position in 0..expected.len().max(actual.len()) {
+                let (expected, actual) = (expected.get(position), actual.get(position));
+                if expected != actual {
+                    discrepancies.push(Discrepancy { side, position, expected: expected.cloned(), actual: actual.cloned() });
+                }
+            }
+        }
+        discrepancies
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ExchangeLevel;
+
+    fn book(exchange_code: &'static str, bids: &[(&str, &str)], asks: &[(&str, &str)]) -> BookUpdate {
+        BookUpdate {
+            exchange_code: exchange_code.into(),
+            bids: bids.iter().map(|&(price, amount)| ExchangeLevel::from_strs(exchange_code, price, amount)).collect(),
+            asks: asks.iter().map(|&(price, amount)| ExchangeLevel::from_strs(exchange_code, price, amount)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_levels() {
+        let mut cross_check = CrossCheck::new(2);
+        cross_check.update(book("binance", &[("10", "1"), ("9", "2"), ("8", "3")], &[("11", "1")]));
+        cross_check.update(book("bitstamp", &[("9", "4")], &[("12", "5"), ("11", "6")]));
+        let bids = cross_check.levels(Side::Buy);
+        assert_eq!(bids.iter().map(|level| level.price).collect::<Vec<_>>(), vec![Decimal::from(10), Decimal::from(9)]);
+        assert_eq!(bids[1].amounts, BTreeMap::from([(ExchangeId::from("binance"), Decimal::from(2)), (ExchangeId::from("bitstamp"), Decimal::from(4))]));
+        let asks = cross_check.levels(Side::Sell);
+        assert_eq!(asks[0].amounts, BTreeMap::from([(ExchangeId::from("binance"), Decimal::from(1)), (ExchangeId::from("bitstamp"), Decimal::from(6))]));
+        cross_check.remove_exchange("binance");
+        assert_eq!(cross_check.levels(Side::Buy).len(), 1);
+    }
+
+    #[test]
+    fn test_compare() {
+        let mut cross_check = CrossCheck::new(10);
+        let mut aggregate_book = AggregateBook::full_depth(10);
+        for update in [
+            book("binance", &[("10", "1"), ("9", "2")], &[("11", "1"), ("12", "1")]),
+            book("bitstamp", &[("9", "4")], &[("11", "6")]),
+            book("binance", &[("9", "3")], &[("12", "1")]),
+        ] {
+            cross_check.update(update.clone());
+            aggregate_book.update(update);
+            assert_eq!(cross_check.compare(&aggregate_book), vec![]);
+        }
+        cross_check.update(book("bitstamp", &[("9", "5")], &[]));
+        let discrepancies = cross_check.compare(&aggregate_book);
+        assert_eq!(
+            discrepancies.iter().map(|discrepancy| (discrepancy.side, discrepancy.position)).collect::<Vec<_>>(),
+            vec![(Side::Buy, 0), (Side::Sell, 0), (Side::Sell, 1)]
+        );
+        assert_eq!(discrepancies[2].expected, None);
+    }
+}
@@ -0,0 +1,256 @@
+//! Deribit JSON-RPC `WebSocket` exchange adapter for the `book.<instrument>.100ms` channel of
+//! futures and perpetual instruments: a snapshot of the complete book is streamed after each
+//! subscription, followed by changes chained by their identifiers, maintained in a
+//! [LocalBook](LocalBook) and delivered after each change.
+
+use log::debug;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+
+use crate::capabilities::{ExchangeCapabilities, HeartbeatKind, UpdateKind};
+use crate::core::*;
+use crate::exchange::{ExchangeAdapter, ExchangeProtocol, SubscriptionRequest};
+use crate::local_book::{read_diff, read_snapshot, DepthDiff, DepthSnapshot, LocalBook, SharedLocalBook};
+use crate::numbers::json_decimal;
+use crate::request_ids::REQUEST_ID_PLACEHOLDER;
+
+
+const DERIBIT_CODE: &str = "deribit";
+const DERIBIT_WS_URL: &str = "wss://www.deribit.com/ws/api/v2";
+/// Region of the Deribit endpoints.
+const DERIBIT_REGION: &str = "eu-west-2";
+/// Interval of the aggregated changes of the book channel, the raw one requiring authentication.
+const DERIBIT_BOOK_INTERVAL: &str = "100ms";
+/// Answer to the heartbeat requests, without which Deribit closes the connection once heartbeats
+/// are enabled.
+const DERIBIT_TEST_MESSAGE: &str = r#"{"jsonrpc":"2.0","method":"public/test","params":{}}"#;
+
+
+/// Parse string messages from the Deribit WebSocket service into the exchange
+/// [protocol](ExchangeProtocol), applying the snapshots and changes to the local book.
+/// It recognizes book notifications, the responses to requests, by identifier, and the
+/// heartbeats, answering their test requests.
+fn read_deribit_book(local_book: &SharedLocalBook, value: &str) -> Option<ExchangeProtocol<BookUpdate>> {
+    let protocol = match serde_json::from_str::<DeribitMessage>(value) {
+        Ok(DeribitMessage::Notification { params: DeribitParams::Subscription { data } }) => match data.kind {
+            DeribitBookKind::Snapshot => deribit_snapshot(data).map(|snapshot| read_snapshot(local_book, snapshot)),
+            DeribitBookKind::Change => deribit_diff(data).map(|diff| read_diff(local_book, diff)),
+        },
+        Ok(DeribitMessage::Notification { params: DeribitParams::Heartbeat { kind } }) if kind == "test_request" => {
+            Some(ExchangeProtocol::Reply(String::from(DERIBIT_TEST_MESSAGE)))
+        },
+        Ok(DeribitMessage::Notification { params: DeribitParams::Heartbeat { .. } }) => Some(ExchangeProtocol::Skipped),
+        Ok(DeribitMessage::Response { id, .. }) => Some(ExchangeProtocol::Response(id)),
+        Err(_) => None,
+    };
+    if protocol.is_none() {
+        debug!("Parse failed {:?}", value);
+    }
+    protocol
+}
+
+/// Format a currency pair as a Deribit instrument name, e.g. `BTC-PERPETUAL` or `ETH-29DEC23`,
+/// the expiry of futures taking the place of the counter currency.
+pub fn deribit_instrument(product: &CurrencyPair) -> String {
+    format!("{}-{}", product.main.to_uppercase(), product.counter.to_uppercase())
+}
+
+/// Format the request subscribing to, or unsubscribing from, the book channel of an
+/// instrument, answered by identifier.
+fn deribit_request(product: &CurrencyPair, request: SubscriptionRequest) -> String {
+    let method = match request {
+        SubscriptionRequest::Subscribe => "public/subscribe",
+        SubscriptionRequest::Unsubscribe => "public/unsubscribe",
+    };
+    format!(
+        r#"{{"jsonrpc":"2.0","id":{},"method":"{}","params":{{"channels":["book.{}.{}"]}}}}"#,
+        REQUEST_ID_PLACEHOLDER, method, deribit_instrument(product), DERIBIT_BOOK_INTERVAL,
+    )
+}
+
+/// Creates an [exchange adapter](ExchangeAdapter) for the Deribit book of an instrument. The
+/// local book is shared by the clones of the adapter, each snapshot replacing it.
+pub fn make_deribit_exchange_adapter(product: &CurrencyPair) -> ExchangeAdapter<BookUpdate> {
+    // each change names the previous one, a change not following the book reveals a gap
+    let local_book: SharedLocalBook = Arc::new(Mutex::new(LocalBook::new(DERIBIT_CODE, true)));
+    ExchangeAdapter::new(DERIBIT_CODE, String::from(DERIBIT_WS_URL), Arc::new(move |value: &str| read_deribit_book(&local_book, value)))
+        .with_subscribe_message(deribit_request(product, SubscriptionRequest::Subscribe))
+        .with_subscription_formatter(Arc::new(deribit_request))
+        .with_subscription_acks()
+        .with_capabilities(ExchangeCapabilities {
+            updates: UpdateKind::Deltas,
+            max_depth: None,
+            heartbeat: HeartbeatKind::WebsocketPing,
+            region: DERIBIT_REGION,
+        })
+}
+
+/// A message of the Deribit WebSocket service.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum DeribitMessage {
+    /// A notification, of a subscribed channel or a heartbeat.
+    Notification {
+        params: DeribitParams,
+    },
+    /// Successful response to the request with the given identifier, errors carrying no result.
+    Response {
+        id: u64,
+        #[serde(rename = "result")]
+        _result: Value,
+    },
+}
+
+/// Parameters of a notification.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum DeribitParams {
+    /// Data of a subscribed channel.
+    Subscription {
+        data: DeribitBook,
+    },
+    /// Heartbeat, of kind `heartbeat` or `test_request`, the latter expecting an answer.
+    Heartbeat {
+        #[serde(rename = "type")]
+        kind: String,
+    },
+}
+
+/// Kind of a notification of the book channel.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "lowercase")]
+enum DeribitBookKind {
+    /// The complete book, replacing the local one.
+    Snapshot,
+    /// Changes of the book following the change with identifier `prev_change_id`.
+    Change,
+}
+
+/// Action, price and amount of a level, the numbers as JSON numbers.
+#[derive(Deserialize, Debug)]
+struct DeribitLevel((String, Value, Value));
+
+/// A notification of the book channel.
+#[derive(Deserialize, Debug)]
+struct DeribitBook {
+    #[serde(rename = "type")]
+    kind: DeribitBookKind,
+    change_id: u64,
+    prev_change_id: Option<u64>,
+    bids: Vec<DeribitLevel>,
+    asks: Vec<DeribitLevel>,
+}
+
+/// Internal function converting the levels of a side, those deleted with a zero amount,
+/// [None](None) if any number is invalid.
+fn deribit_levels(levels: Vec<DeribitLevel>) -> Option<Vec<ExchangeLevel>> {
+    levels.into_iter()
+        .map(|DeribitLevel((action, price, amount))| Some(ExchangeLevel {
+            exchange_code: DERIBIT_CODE.into(),
+            price: json_decimal(&price)?,
+            amount: if action == "delete" { Decimal::ZERO } else { json_decimal(&amount)? },
+            order_count: None,
+        }))
+        .collect()
+}
+
+/// Internal function converting a snapshot, [None](None) if any number is invalid.
+fn deribit_snapshot(book: DeribitBook) -> Option<DepthSnapshot> {
+    Some(DepthSnapshot {
+        sequence: book.change_id,
+        bids: deribit_levels(book.bids)?,
+        asks: deribit_levels(book.asks)?,
+    })
+}
+
+/// Internal function converting a change, starting right after the previous one, [None](None)
+/// if any number is invalid or the previous change is missing.
+fn deribit_diff(book: DeribitBook) -> Option<DepthDiff> {
+    Some(DepthDiff {
+        first_sequence: book.prev_change_id? + 1,
+        last_sequence: book.change_id,
+        bids: deribit_levels(book.bids)?,
+        asks: deribit_levels(book.asks)?,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local_book() -> SharedLocalBook {
+        Arc::new(Mutex::new(LocalBook::new(DERIBIT_CODE, true)))
+    }
+
+    #[test]
+    fn test_read_deribit_book() {
+        let local_book = local_book();
+        let snapshot = r#"{"jsonrpc":"2.0","method":"subscription","params":{"channel":"book.BTC-PERPETUAL.100ms","data":{"type":"snapshot","timestamp":1554373962454,"instrument_name":"BTC-PERPETUAL","change_id":297217,"bids":[["new",5042.34,30],["new",5041.94,20]],"asks":[["new",5042.64,40],["new",5043.3,40]]}}}"#;
+        assert_eq!(read_deribit_book(&local_book, snapshot), Some(ExchangeProtocol::Data(BookUpdate {
+            exchange_code: DERIBIT_CODE.into(),
+            bids: vec![
+                ExchangeLevel::from_strs(DERIBIT_CODE, "5042.34", "30"),
+                ExchangeLevel::from_strs(DERIBIT_CODE, "5041.94", "20"),
+            ],
+            asks: vec![
+                ExchangeLevel::from_strs(DERIBIT_CODE, "5042.64", "40"),
+                ExchangeLevel::from_strs(DERIBIT_CODE, "5043.3", "40"),
+            ],
+        })));
+        let change = r#"{"jsonrpc":"2.0","method":"subscription","params":{"channel":"book.BTC-PERPETUAL.100ms","data":{"type":"change","timestamp":1554373911330,"instrument_name":"BTC-PERPETUAL","prev_change_id":297217,"change_id":297218,"bids":[["delete",5042.34,0],["change",5041.94,25]],"asks":[["new",5042.5,10]]}}}"#;
+        assert_eq!(read_deribit_book(&local_book, change), Some(ExchangeProtocol::Data(BookUpdate {
+            exchange_code: DERIBIT_CODE.into(),
+            bids: vec![ExchangeLevel::from_strs(DERIBIT_CODE, "5041.94", "25")],
+            asks: vec![
+                ExchangeLevel::from_strs(DERIBIT_CODE, "5042.5", "10"),
+                ExchangeLevel::from_strs(DERIBIT_CODE, "5042.64", "40"),
+                ExchangeLevel::from_strs(DERIBIT_CODE, "5043.3", "40"),
+            ],
+        })));
+        // a change not following the last one requires a new snapshot
+        let missed = r#"{"jsonrpc":"2.0","method":"subscription","params":{"channel":"book.BTC-PERPETUAL.100ms","data":{"type":"change","prev_change_id":297220,"change_id":297221,"bids":[],"asks":[]}}}"#;
+        assert_eq!(read_deribit_book(&local_book, missed), Some(ExchangeProtocol::ReconnectionRequest));
+        let incorrect = r#"{"jsonrpc":"2.0","method":"subscription","params":{"channel":"book.BTC-PERPETUAL.100ms","data":{"type":"snapshot","change_id":1,"bids":[["new","5042.34",30]],"asks":[]}}}"#;
+        assert_eq!(read_deribit_book(&local_book, incorrect), None);
+    }
+
+    #[test]
+    fn test_read_deribit_responses() {
+        let local_book = local_book();
+        assert_eq!(
+            read_deribit_book(&local_book, r#"{"jsonrpc":"2.0","id":3,"result":["book.BTC-PERPETUAL.100ms"],"usIn":1535043730126248,"usOut":1535043730126250,"usDiff":2,"testnet":false}"#),
+            Some(ExchangeProtocol::Response(3)),
+        );
+        assert_eq!(
+            read_deribit_book(&local_book, r#"{"jsonrpc":"2.0","method":"heartbeat","params":{"type":"test_request"}}"#),
+            Some(ExchangeProtocol::Reply(String::from(DERIBIT_TEST_MESSAGE))),
+        );
+        assert_eq!(
+            read_deribit_book(&local_book, r#"{"jsonrpc":"2.0","method":"heartbeat","params":{"type":"heartbeat"}}"#),
+            Some(ExchangeProtocol::Skipped),
+        );
+        assert_eq!(
+            read_deribit_book(&local_book, r#"{"jsonrpc":"2.0","id":4,"error":{"message":"Invalid params","code":-32602}}"#),
+            None,
+        );
+    }
+
+    #[test]
+    fn test_deribit_request() {
+        let product = CurrencyPair { main: "btc".to_string(), counter: "perpetual".to_string() };
+        assert_eq!(deribit_instrument(&product), "BTC-PERPETUAL");
+        let future = crate::symbols::parse_currency_pair("eth-29dec23").unwrap();
+        assert_eq!(deribit_instrument(&future), "ETH-29DEC23");
+        assert_eq!(
+            deribit_request(&product, SubscriptionRequest::Subscribe),
+            r#"{"jsonrpc":"2.0","id":{request_id},"method":"public/subscribe","params":{"channels":["book.BTC-PERPETUAL.100ms"]}}"#,
+        );
+        assert_eq!(
+            deribit_request(&product, SubscriptionRequest::Unsubscribe),
+            r#"{"jsonrpc":"2.0","id":{request_id},"method":"public/unsubscribe","params":{"channels":["book.BTC-PERPETUAL.100ms"]}}"#,
+        );
+    }
+}
@@ -0,0 +1,282 @@
+//! dYdX v4 indexer `WebSocket` exchange adapter for the `v4_orderbook` channel, the first
+//! decentralized venue: a snapshot of the complete book comes with the subscription response,
+//! followed by `channel_data` deltas, maintained in a [LocalBook](LocalBook) and delivered after
+//! each change.
+//!
+//! Messages are numbered per connection only, so the clones of an adapter, each on a connection
+//! of its own, keep a book for each connection and market.
+
+use log::debug;
+use rust_decimal::prelude::*;
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use crate::capabilities::{ExchangeCapabilities, HeartbeatKind, UpdateKind};
+use crate::core::*;
+use crate::exchange::{ExchangeAdapter, ExchangeProtocol, SubscriptionRequest};
+use crate::local_book::{DepthDiff, DepthSnapshot, DiffOutcome, LocalBook};
+
+
+const DYDX_CODE: &str = "dydx";
+const DYDX_WS_URL: &str = "wss://indexer.dydx.trade/v4/ws";
+/// Region of the dYdX indexer endpoints.
+const DYDX_REGION: &str = "ap-northeast-1";
+/// Maximum number of books kept, the books of the oldest connections being dropped first.
+const MAX_DYDX_BOOKS: usize = 16;
+
+
+/// Books of the markets subscribed, by connection and market.
+#[derive(Debug, Default)]
+pub struct DydxBooks {
+    /// Book of each connection and market.
+    books: HashMap<(String, String), LocalBook>,
+    /// Connections and markets, from the oldest snapshot.
+    order: VecDeque<(String, String)>,
+}
+
+/// [DydxBooks](DydxBooks) shared by the reader of an adapter and by its clones.
+pub type SharedDydxBooks = Arc<Mutex<DydxBooks>>;
+
+impl DydxBooks {
+    /// Internal function replacing the book of a connection and market with a snapshot,
+    /// dropping the oldest books beyond [MAX_DYDX_BOOKS](MAX_DYDX_BOOKS).
+    fn apply_snapshot(&mut self, key: (String, String), snapshot: DepthSnapshot) -> BookUpdate {
+        if !self.books.contains_key(&key) {
+            self.order.push_back(key.clone());
+        }
+        while self.order.len() > MAX_DYDX_BOOKS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.books.remove(&oldest);
+            }
+        }
+        // message numbers are shared by the markets of a connection, gaps cannot be detected
+        let book = self.books.entry(key).or_insert_with(|| LocalBook::new(DYDX_CODE, false));
+        book.apply_snapshot(snapshot);
+        book.book_update()
+    }
+
+    /// Internal function applying a delta to the book of a connection and market.
+    fn apply_diff(&mut self, key: &(String, String), diff: DepthDiff) -> ExchangeProtocol<BookUpdate> {
+        let Some(book) = self.books.get_mut(key) else {
+            return ExchangeProtocol::ReconnectionRequest;
+        };
+        match book.apply_diff(diff) {
+            DiffOutcome::Applied => ExchangeProtocol::Data(book.book_update()),
+            DiffOutcome::Stale => ExchangeProtocol::Skipped,
+            DiffOutcome::Gap => ExchangeProtocol::ReconnectionRequest,
+        }
+    }
+
+    /// Internal function dropping the book of a connection and market unsubscribed.
+    fn remove(&mut self, key: &(String, String)) {
+        self.books.remove(key);
+        self.order.retain(|current| current != key);
+    }
+}
+
+/// Parse string messages from the dYdX indexer WebSocket service into the exchange
+/// [protocol](ExchangeProtocol), applying the snapshots and deltas to the book of their
+/// connection and market.
+/// It recognizes the connection and subscription messages and the book channel data.
+fn read_dydx_book(books: &SharedDydxBooks, value: &str) -> Option<ExchangeProtocol<BookUpdate>> {
+    let protocol = match serde_json::from_str::<DydxMessage>(value) {
+        Ok(DydxMessage::Subscribed { connection_id, message_id, id, contents }) => {
+            dydx_levels(contents).map(|(bids, asks)| {
+                let snapshot = DepthSnapshot { sequence: message_id, bids, asks };
+                ExchangeProtocol::Data(books.lock().unwrap().apply_snapshot((connection_id, id), snapshot))
+            })
+        },
+        Ok(DydxMessage::ChannelData { connection_id, message_id, id, contents }) => {
+            dydx_levels(contents).map(|(bids, asks)| {
+                let diff = DepthDiff { first_sequence: message_id, last_sequence: message_id, bids, asks };
+                books.lock().unwrap().apply_diff(&(connection_id, id), diff)
+            })
+        },
+        Ok(DydxMessage::Unsubscribed { connection_id, id }) => {
+            books.lock().unwrap().remove(&(connection_id, id));
+            Some(ExchangeProtocol::Skipped)
+        },
+        Ok(DydxMessage::Connected) => Some(ExchangeProtocol::Skipped),
+        Err(_) => None,
+    };
+    if protocol.is_none() {
+        debug!("Parse failed {:?}", value);
+    }
+    protocol
+}
+
+/// Format a currency pair as a dYdX market, e.g. `BTC-USD`.
+pub fn dydx_market(product: &CurrencyPair) -> String {
+    format!("{}-{}", product.main.to_uppercase(), product.counter.to_uppercase())
+}
+
+/// Format the request subscribing to, or unsubscribing from, the book channel of a market.
+fn dydx_request(product: &CurrencyPair, request: SubscriptionRequest) -> String {
+    let kind = match request {
+        SubscriptionRequest::Subscribe => "subscribe",
+        SubscriptionRequest::Unsubscribe => "unsubscribe",
+    };
+    format!(r#"{{"type":"{}","channel":"v4_orderbook","id":"{}"}}"#, kind, dydx_market(product))
+}
+
+/// Creates an [exchange adapter](ExchangeAdapter) for the dYdX v4 book of a market. The books
+/// are shared by the clones of the adapter, by connection.
+pub fn make_dydx_exchange_adapter(product: &CurrencyPair) -> ExchangeAdapter<BookUpdate> {
+    let books: SharedDydxBooks = Arc::new(Mutex::new(DydxBooks::default()));
+    ExchangeAdapter::new(DYDX_CODE, String::from(DYDX_WS_URL), Arc::new(move |value: &str| read_dydx_book(&books, value)))
+        .with_subscribe_message(dydx_request(product, SubscriptionRequest::Subscribe))
+        .with_subscription_formatter(Arc::new(dydx_request))
+        .with_capabilities(ExchangeCapabilities {
+            updates: UpdateKind::Deltas,
+            max_depth: None,
+            heartbeat: HeartbeatKind::WebsocketPing,
+            region: DYDX_REGION,
+        })
+}
+
+/// A message of the dYdX indexer WebSocket service, by type.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum DydxMessage {
+    /// The connection is open.
+    Connected,
+    /// Response to a subscription, with the complete book of the market.
+    Subscribed {
+        connection_id: String,
+        message_id: u64,
+        id: String,
+        contents: DydxBookContents,
+    },
+    /// Changes of the book of a market: a level with a zero size is removed.
+    ChannelData {
+        connection_id: String,
+        message_id: u64,
+        id: String,
+        contents: DydxBookContents,
+    },
+    /// Response to an unsubscription.
+    Unsubscribed {
+        connection_id: String,
+        id: String,
+    },
+}
+
+/// Levels of a book message.
+#[derive(Deserialize, Debug)]
+struct DydxBookContents {
+    #[serde(default)]
+    bids: Vec<DydxLevel>,
+    #[serde(default)]
+    asks: Vec<DydxLevel>,
+}
+
+/// Price and size of a level: an object in the snapshots, an array, possibly followed by
+/// further fields, in the deltas.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum DydxLevel {
+    Object {
+        price: String,
+        size: String,
+    },
+    Array(Vec<String>),
+}
+
+impl TryFrom<DydxLevel> for ExchangeLevel {
+    type Error = ();
+
+    fn try_from(value: DydxLevel) -> Result<Self, Self::Error> {
+        let (price, size) = match value {
+            DydxLevel::Object { price, size } => (price, size),
+            DydxLevel::Array(fields) => {
+                let mut fields = fields.into_iter();
+                (fields.next().ok_or(())?, fields.next().ok_or(())?)
+            },
+        };
+        Ok(Self {
+            exchange_code: DYDX_CODE.into(),
+            price: Decimal::from_str(&price).map_err(|_| ())?,
+            amount: Decimal::from_str(&size).map_err(|_| ())?,
+            order_count: None,
+        })
+    }
+}
+
+/// Internal function converting the levels of a book message, [None](None) if any is invalid.
+fn dydx_levels(contents: DydxBookContents) -> Option<(Vec<ExchangeLevel>, Vec<ExchangeLevel>)> {
+    let side = |levels: Vec<DydxLevel>| levels.into_iter().map(ExchangeLevel::try_from).collect::<Result<Vec<_>, _>>().ok();
+    Some((side(contents.bids)?, side(contents.asks)?))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SNAPSHOT: &str = r#"{"type":"subscribed","connection_id":"c1","message_id":1,"channel":"v4_orderbook","id":"BTC-USD","contents":{"bids":[{"price":"30000","size":"1.5"},{"price":"29999","size":"2"}],"asks":[{"price":"30001","size":"1"}]}}"#;
+
+    fn books() -> SharedDydxBooks {
+        Arc::new(Mutex::new(DydxBooks::default()))
+    }
+
+    #[test]
+    fn test_read_dydx_book() {
+        let books = books();
+        assert_eq!(read_dydx_book(&books, r#"{"type":"connected","connection_id":"c1","message_id":0}"#), Some(ExchangeProtocol::Skipped));
+        assert_eq!(read_dydx_book(&books, SNAPSHOT), Some(ExchangeProtocol::Data(BookUpdate {
+            exchange_code: DYDX_CODE.into(),
+            bids: vec![
+                ExchangeLevel::from_strs(DYDX_CODE, "30000", "1.5"),
+                ExchangeLevel::from_strs(DYDX_CODE, "29999", "2"),
+            ],
+            asks: vec![ExchangeLevel::from_strs(DYDX_CODE, "30001", "1")],
+        })));
+        let delta = r#"{"type":"channel_data","connection_id":"c1","message_id":2,"id":"BTC-USD","channel":"v4_orderbook","version":"1.0.0","contents":{"bids":[["30000","0"]],"asks":[["30002","3","123"]]}}"#;
+        assert_eq!(read_dydx_book(&books, delta), Some(ExchangeProtocol::Data(BookUpdate {
+            exchange_code: DYDX_CODE.into(),
+            bids: vec![ExchangeLevel::from_strs(DYDX_CODE, "29999", "2")],
+            asks: vec![
+                ExchangeLevel::from_strs(DYDX_CODE, "30001", "1"),
+                ExchangeLevel::from_strs(DYDX_CODE, "30002", "3"),
+            ],
+        })));
+        assert_eq!(read_dydx_book(&books, delta), Some(ExchangeProtocol::Skipped));
+        let incorrect = r#"{"type":"channel_data","connection_id":"c1","message_id":3,"id":"BTC-USD","contents":{"bids":[["__INCORRECT__","1"]]}}"#;
+        assert_eq!(read_dydx_book(&books, incorrect), None);
+    }
+
+    #[test]
+    fn test_books_by_connection() {
+        let books = books();
+        read_dydx_book(&books, SNAPSHOT);
+        // deltas of a connection without snapshot require a new one
+        let other = r#"{"type":"channel_data","connection_id":"c2","message_id":2,"id":"BTC-USD","contents":{"asks":[["30001","0"]]}}"#;
+        assert_eq!(read_dydx_book(&books, other), Some(ExchangeProtocol::ReconnectionRequest));
+        read_dydx_book(&books, &SNAPSHOT.replace("c1", "c2"));
+        let ExchangeProtocol::Data(book_update) = read_dydx_book(&books, other).unwrap() else {
+            panic!("delta not delivered");
+        };
+        assert!(book_update.asks.is_empty());
+        // the book of the first connection is unchanged
+        let first = r#"{"type":"channel_data","connection_id":"c1","message_id":5,"id":"BTC-USD","contents":{}}"#;
+        let ExchangeProtocol::Data(book_update) = read_dydx_book(&books, first).unwrap() else {
+            panic!("delta not delivered");
+        };
+        assert_eq!(book_update.asks.len(), 1);
+
+        assert_eq!(read_dydx_book(&books, r#"{"type":"unsubscribed","connection_id":"c1","message_id":6,"channel":"v4_orderbook","id":"BTC-USD"}"#), Some(ExchangeProtocol::Skipped));
+        assert_eq!(read_dydx_book(&books, first), Some(ExchangeProtocol::ReconnectionRequest));
+        for connection in 0..MAX_DYDX_BOOKS + 1 {
+            read_dydx_book(&books, &SNAPSHOT.replace("c1", &format!("new{}", connection)));
+        }
+        assert_eq!(books.lock().unwrap().books.len(), MAX_DYDX_BOOKS);
+    }
+
+    #[test]
+    fn test_dydx_request() {
+        let product = CurrencyPair { main: "btc".to_string(), counter: "usd".to_string() };
+        assert_eq!(dydx_request(&product, SubscriptionRequest::Subscribe), r#"{"type":"subscribe","channel":"v4_orderbook","id":"BTC-USD"}"#);
+        assert_eq!(dydx_request(&product, SubscriptionRequest::Unsubscribe), r#"{"type":"unsubscribe","channel":"v4_orderbook","id":"BTC-USD"}"#);
+    }
+}
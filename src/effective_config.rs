@@ -0,0 +1,171 @@
+//! Effective configuration of the server, fully resolved from defaults, configuration files
+//! and features: logged at startup and served by the
+//! [GetConfiguration](crate::orderbook::orderbook_aggregator_server::OrderbookAggregator::get_configuration)
+//! RPC, so that operators can verify what the process is actually running with.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::adaptive_depth::AdaptiveDepth;
+use crate::alerts::AlertsConfig;
+use crate::capabilities::ExchangeCapabilities;
+use crate::core::ExchangeId;
+use crate::ingest::IngestLimits;
+use crate::queues::QueueCapacities;
+use crate::shadow::ShadowConfig;
+
+
+/// Effective configuration of an [exchange adapter](crate::exchange::ExchangeAdapter).
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct VenueConfig {
+    /// Exchange code.
+    pub exchange: ExchangeId,
+    /// WebSocket URL of the primary endpoint.
+    pub ws_url: String,
+    /// WebSocket URLs of the failover endpoints, in order of preference.
+    pub failover_urls: Vec<String>,
+    /// Subscription messages sent after each connection.
+    pub subscribe_messages: Vec<String>,
+    /// Whether the exchange acknowledges each subscription.
+    pub subscription_acks: bool,
+    /// Minimum interval between two updates delivered downstream, in milliseconds, if conflated.
+    pub min_update_interval_ms: Option<u64>,
+    /// Delay before reconnecting, in milliseconds.
+    pub reconnect_delay_ms: u64,
+    /// Maximum delay before restarting a failed adapter task, in milliseconds.
+    pub max_restart_delay_ms: u64,
+    /// Whether connection attempts are limited by a budget.
+    pub reconnect_budget: bool,
+    /// REST endpoint polled while the WebSocket service cannot be reached, if any.
+    pub rest_fallback_url: Option<String>,
+    /// REST endpoint of the snapshot fetched after each connection in full-depth mode, if any.
+    pub depth_snapshot_url: Option<String>,
+    /// Limits of the messages received.
+    pub ingest_limits: IngestLimits,
+    /// Capabilities of the exchange.
+    pub capabilities: ExchangeCapabilities,
+}
+
+/// Effective configuration of the server.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct EffectiveConfig {
+    /// Version of the server.
+    pub version: &'static str,
+    /// Canonical symbols of the currency pairs served.
+    pub symbols: Vec<String>,
+    /// TCP port of the Protobuf RPC server, once serving.
+    pub port: Option<u16>,
+    /// Cargo features enabled.
+    pub features: Vec<&'static str>,
+    /// Name of the global allocator.
+    pub allocator: &'static str,
+    /// Configuration of each exchange of each currency pair.
+    pub venues: Vec<VenueConfig>,
+    /// Levels of each side published in the summaries.
+    pub published_levels: usize,
+    /// Levels published depending on the spread, if adaptive.
+    pub adaptive_depth: Option<AdaptiveDepth>,
+    /// Significant digits to which the numbers of the summaries of each symbol are rounded, if any.
+    pub significant_digits: BTreeMap<String, u32>,
+    /// Whether complete books are consolidated.
+    pub full_depth: bool,
+    /// Distances from the mid price, in basis points, for which the total depth is published.
+    pub depth_bands_bps: Vec<u32>,
+    /// Horizons of the volatility estimates of the mid price, in milliseconds.
+    pub volatility_horizons_ms: Vec<u64>,
+    /// Whether publishing is suppressed until every (re)connected exchange delivered a snapshot.
+    pub wait_for_snapshots: bool,
+    /// Rate at which published summaries are logged, if any.
+    pub summary_log: Option<String>,
+    /// Capacities of the internal queues.
+    pub queue_capacities: QueueCapacities,
+    /// Alerting rules.
+    pub alerts: AlertsConfig,
+    /// Endpoint of each sink, by sink name.
+    pub sinks: BTreeMap<&'static str, String>,
+    /// Instance the feeds are compared with, if any.
+    pub shadow: Option<ShadowConfig>,
+}
+
+impl EffectiveConfig {
+    /// Format the configuration as compact JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("configuration not serializable")
+    }
+}
+
+/// Cargo features enabled in this build.
+///
+/// # Returns
+///
+/// The names of the features, in alphabetical order.
+pub fn enabled_features() -> Vec<&'static str> {
+    [
+        ("chaos", cfg!(feature = "chaos")),
+        ("cross-check", cfg!(feature = "cross-check")),
+        ("full-depth", cfg!(feature = "full-depth")),
+        ("influx", cfg!(feature = "influx")),
+        ("jemalloc", cfg!(feature = "jemalloc")),
+        ("latency-budget", cfg!(feature = "latency-budget")),
+        ("mimalloc", cfg!(feature = "mimalloc")),
+        ("mqtt", cfg!(feature = "mqtt")),
+        ("pipe", cfg!(feature = "pipe")),
+        ("postgres", cfg!(feature = "postgres")),
+        ("prometheus", cfg!(feature = "prometheus")),
+        ("rest", cfg!(feature = "rest")),
+        ("sqlite", cfg!(feature = "sqlite")),
+        ("systemd", cfg!(feature = "systemd")),
+        ("webhook", cfg!(feature = "webhook")),
+    ].into_iter().filter_map(|(feature, enabled)| enabled.then_some(feature)).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_json() {
+        let config = EffectiveConfig {
+            version: "1.0.0",
+            symbols: vec!["ETH-BTC".to_string(), "BTC-USDT".to_string()],
+            port: Some(50051),
+            features: vec!["rest"],
+            allocator: "system",
+            venues: vec![VenueConfig {
+                exchange: "binance".into(),
+                ws_url: "wss://stream.binance.com:9443/ws".to_string(),
+                failover_urls: vec![],
+                subscribe_messages: vec![],
+                subscription_acks: false,
+                min_update_interval_ms: None,
+                reconnect_delay_ms: 200,
+                max_restart_delay_ms: 30_000,
+                reconnect_budget: true,
+                rest_fallback_url: None,
+                depth_snapshot_url: None,
+                ingest_limits: IngestLimits::default(),
+                capabilities: ExchangeCapabilities::default(),
+            }],
+            published_levels: 10,
+            adaptive_depth: None,
+            significant_digits: BTreeMap::from([("BTC-USDT".to_string(), 6)]),
+            full_depth: false,
+            depth_bands_bps: vec![10],
+            volatility_horizons_ms: vec![1_000, 60_000],
+            wait_for_snapshots: true,
+            summary_log: None,
+            queue_capacities: QueueCapacities::default(),
+            alerts: AlertsConfig::default(),
+            sinks: BTreeMap::from([("sqlite", "snapshots.sqlite".to_string())]),
+            shadow: Some(ShadowConfig { url: "http://[::1]:50052".to_string(), tolerance_ms: 1000 }),
+        };
+        let json: serde_json::Value = serde_json::from_str(&config.to_json()).unwrap();
+        assert_eq!(json["symbols"][1], "BTC-USDT");
+        assert_eq!(json["significant_digits"]["BTC-USDT"], 6);
+        assert_eq!(json["venues"][0]["exchange"], "binance");
+        assert_eq!(json["venues"][0]["ingest_limits"]["max_levels"], 5000);
+        assert_eq!(json["sinks"]["sqlite"], "snapshots.sqlite");
+        assert_eq!(json["shadow"]["tolerance_ms"], 1000);
+    }
+}
@@ -0,0 +1,326 @@
+//! Internal event bus decoupling the ingestion of the exchange books from their consumers:
+//! the [book events](BookEvent) of the exchanges, the changes of their [status](ExchangeStatusEvent),
+//! the [alerts](AlertEvent) and the [summaries](Summary) of the feed of a symbol are each published on
+//! a typed topic, which the aggregation, the outputs and the metrics subscribe to independently,
+//! so that a new output is a new subscriber rather than a change of the producers.
+//!
+//! The latest book of each exchange is kept, and replayed to each new subscriber of the book
+//! events, so that it starts from the current books rather than waiting for the next updates.
+
+use futures::stream::{self, Stream, StreamExt};
+use log::{info, warn};
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+use crate::alerts::AlertEvent;
+use crate::core::{BookUpdate, ExchangeId};
+use crate::exchange::{ExchangeDataStream, ExchangeEvent};
+use crate::feed::record_market_gauges;
+use crate::metrics::{BUS_EVENTS, BUS_LAGGED};
+use crate::orderbook::Summary;
+use crate::scheduling::spawn_ingest;
+use crate::status::{ExchangeStatusEvent, StatusSender};
+
+
+/// Book events buffered for each subscriber.
+const BOOKS_CAPACITY: usize = 1024;
+/// Exchange status events buffered for each subscriber.
+const STATUS_CAPACITY: usize = 64;
+/// Alerts buffered for each subscriber, older ones are dropped for slow subscribers.
+const ALERTS_CAPACITY: usize = 64;
+/// Summaries buffered for each subscriber.
+const SUMMARIES_CAPACITY: usize = 256;
+/// Topic of the book events.
+const BOOKS_TOPIC: &str = "books";
+/// Topic of the exchange status events.
+const STATUS_TOPIC: &str = "status";
+/// Topic of the alerts.
+const ALERTS_TOPIC: &str = "alerts";
+/// Topic of the summaries.
+const SUMMARIES_TOPIC: &str = "summaries";
+
+
+/// Event of the book of an exchange.
+pub type BookEvent = ExchangeEvent<BookUpdate>;
+
+/// Subscription to the [book events](BookEvent) of an [EventBus](EventBus).
+pub type BookEventStream = Pin<Box<dyn Stream<Item = BookEvent> + Send>>;
+
+/// Latest book of each exchange delivering data.
+type LatestBooks = Arc<Mutex<BTreeMap<ExchangeId, BookUpdate>>>;
+
+
+/// Typed topics of the events of the server, cheap to clone.
+#[derive(Clone)]
+pub struct EventBus {
+    /// Book events of the exchanges.
+    books: broadcast::Sender<BookEvent>,
+    /// Latest book of each exchange, replayed to new subscribers.
+    latest_books: LatestBooks,
+    /// Changes of the status of the exchanges.
+    statuses: StatusSender,
+    /// Alerts raised by the rules engine.
+    alerts: broadcast::Sender<AlertEvent>,
+    /// Summaries of the feed.
+    summaries: broadcast::Sender<Arc<Summary>>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    /// Create a new [EventBus](EventBus) object, without subscribers.
+    pub fn new() -> Self {
+        Self {
+            books: broadcast::channel(BOOKS_CAPACITY).0,
+            latest_books: Arc::new(Mutex::new(BTreeMap::new())),
+            statuses: broadcast::channel(STATUS_CAPACITY).0,
+            alerts: broadcast::channel(ALERTS_CAPACITY).0,
+            summaries: broadcast::channel(SUMMARIES_CAPACITY).0,
+        }
+    }
+
+    /// Publish a book event, keeping the latest book of each exchange.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The [BookEvent](BookEvent).
+    pub fn publish_book_event(&self, event: BookEvent) {
+        // the book events are counted here, since a subscriber would keep the exchanges connected
+        BUS_EVENTS.increment(BOOKS_TOPIC);
+        let mut latest_books = self.latest_books.lock().unwrap();
+        match &event {
+            ExchangeEvent::Data(book_update) => {
+                latest_books.insert(book_update.exchange_code.clone(), book_update.clone());
+            },
+            ExchangeEvent::Disconnected(exchange_code) => {
+                latest_books.remove(exchange_code);
+            },
+            ExchangeEvent::Connected(_) => (),
+        }
+        // no subscribers is not an error
+        let _ = self.books.send(event);
+    }
+
+    /// Subscribe to the book events, starting with the latest book of each exchange.
+    /// Events missed by a slow subscriber are replaced by the latest books.
+    ///
+    /// # Returns
+    ///
+    /// A [BookEventStream](BookEventStream).
+    pub fn book_events(&self) -> BookEventStream {
+        let latest_books = self.latest_books.lock().unwrap();
+        let subscription = BookSubscription {
+            pending: latest_books.values()
+                .flat_map(|book_update| [ExchangeEvent::Connected(book_update.exchange_code.clone()), ExchangeEvent::Data(book_update.clone())])
+                .collect(),
+            receiver: self.books.subscribe(),
+            latest_books: self.latest_books.clone(),
+            delivered: HashSet::new(),
+        };
+        Box::pin(stream::unfold(subscription, |mut subscription| async move {
+            subscription.next().await.map(|event| (event, subscription))
+        }))
+    }
+
+    /// Number of subscribers of the book events.
+    pub fn book_subscriber_count(&self) -> usize {
+        self.books.receiver_count()
+    }
+
+    /// Spawn an [ingest task](spawn_ingest) publishing the events of some exchanges, until
+    /// the book events have no subscriber left: the exchanges are then disconnected.
+    ///
+    /// # Arguments
+    ///
+    /// * `book_update_stream` - The [ExchangeDataStream](ExchangeDataStream) of the exchanges.
+    pub fn spawn_publisher(&self, mut book_update_stream: ExchangeDataStream<BookUpdate>) {
+        let bus = self.clone();
+        spawn_ingest(async move {
+            while let Some(event) = book_update_stream.next().await {
+                if bus.book_subscriber_count() == 0 {
+                    break;
+                }
+                bus.publish_book_event(event);
+            }
+            info!("No subscriber of the book events left, disconnecting the exchanges");
+            bus.latest_books.lock().unwrap().clear();
+            book_update_stream.disconnect().await;
+        });
+    }
+
+    /// Sender of the changes of the status of the exchanges, for the
+    /// [exchange adapters](crate::exchange::ExchangeAdapter::with_status_sender).
+    pub fn status_sender(&self) -> StatusSender {
+        self.statuses.clone()
+    }
+
+    /// Subscribe to the changes of the status of the exchanges.
+    ///
+    /// # Returns
+    ///
+    /// A [broadcast::Receiver](broadcast::Receiver) of [status events](ExchangeStatusEvent).
+    pub fn statuses(&self) -> broadcast::Receiver<ExchangeStatusEvent> {
+        self.statuses.subscribe()
+    }
+
+    /// Sender of the alerts, for the [rules engine](crate::alerts::AlertEngine::spawn).
+    pub fn alert_sender(&self) -> broadcast::Sender<AlertEvent> {
+        self.alerts.clone()
+    }
+
+    /// Subscribe to the alerts.
+    ///
+    /// # Returns
+    ///
+    /// A [broadcast::Receiver](broadcast::Receiver) of [alerts](AlertEvent).
+    pub fn alerts(&self) -> broadcast::Receiver<AlertEvent> {
+        self.alerts.subscribe()
+    }
+
+    /// Publish a summary of the feed.
+    ///
+    /// # Arguments
+    ///
+    /// * `summary` - The [Summary](Summary).
+    pub fn publish_summary(&self, summary: Summary) {
+        // no subscribers is not an error
+        let _ = self.summaries.send(Arc::new(summary));
+    }
+
+    /// Subscribe to the summaries of the feed.
+    ///
+    /// # Returns
+    ///
+    /// A [broadcast::Receiver](broadcast::Receiver) of [summaries](Summary).
+    pub fn summaries(&self) -> broadcast::Receiver<Arc<Summary>> {
+        self.summaries.subscribe()
+    }
+
+    /// Spawn a task counting the events of each topic in [BUS_EVENTS](BUS_EVENTS), and setting the
+    /// [market gauges](record_market_gauges) of the summaries published, until the bus is dropped.
+    pub fn spawn_metrics(&self) {
+        let mut statuses = self.statuses();
+        let mut alerts = self.alerts();
+        let mut summaries = self.summaries();
+        tokio::spawn(async move {
+            let (mut statuses_open, mut alerts_open, mut summaries_open) = (true, true, true);
+            while statuses_open || alerts_open || summaries_open {
+                let (topic, received) = tokio::select! {
+                    received = statuses.recv(), if statuses_open => (STATUS_TOPIC, received.map(|_| ())),
+                    received = alerts.recv(), if alerts_open => (ALERTS_TOPIC, received.map(|_| ())),
+                    received = summaries.recv(), if summaries_open => (SUMMARIES_TOPIC, received.map(|summary| record_market_gauges(&summary))),
+                };
+                match received {
+                    Ok(()) => BUS_EVENTS.increment(topic),
+                    Err(broadcast::error::RecvError::Lagged(_)) => BUS_LAGGED.increment(topic),
+                    Err(broadcast::error::RecvError::Closed) => match topic {
+                        STATUS_TOPIC => statuses_open = false,
+                        ALERTS_TOPIC => alerts_open = false,
+                        _ => summaries_open = false,
+                    },
+                }
+            }
+        });
+    }
+}
+
+/// State of a subscription to the book events.
+struct BookSubscription {
+    /// Events to deliver before receiving new ones.
+    pending: VecDeque<BookEvent>,
+    /// Receiver of the book events.
+    receiver: broadcast::Receiver<BookEvent>,
+    /// Latest book of each exchange, to resynchronize after missing events.
+    latest_books: LatestBooks,
+    /// Exchanges whose books were delivered and not disconnected since.
+    delivered: HashSet<ExchangeId>,
+}
+
+impl BookSubscription {
+    /// Internal function receiving the next event, resynchronizing after missing events:
+    /// the exchanges delivered which have no book any more are disconnected, and the latest
+    /// book of every exchange is delivered again.
+    async fn next(&mut self) -> Option<BookEvent> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                match &event {
+                    ExchangeEvent::Data(book_update) => {
+                        self.delivered.insert(book_update.exchange_code.clone());
+                    },
+                    ExchangeEvent::Disconnected(exchange_code) => {
+                        self.delivered.remove(exchange_code);
+                    },
+                    ExchangeEvent::Connected(_) => (),
+                }
+                return Some(event);
+            }
+            match self.receiver.recv().await {
+                Ok(event) => self.pending.push_back(event),
+                Err(broadcast::error::RecvError::Lagged(count)) => {
+                    warn!("Book events lagging, {} events replaced by the latest books", count);
+                    BUS_LAGGED.increment(BOOKS_TOPIC);
+                    let latest_books = self.latest_books.lock().unwrap();
+                    self.pending.extend(self.delivered.iter()
+                        .filter(|exchange_code| !latest_books.contains_key(*exchange_code))
+                        .map(|exchange_code| ExchangeEvent::Disconnected(exchange_code.clone())));
+                    self.pending.extend(latest_books.values().map(|book_update| ExchangeEvent::Data(book_update.clone())));
+                },
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ExchangeLevel;
+
+    fn book_update(exchange_code: &'static str, bid: &str) -> BookUpdate {
+        BookUpdate {
+            exchange_code: exchange_code.into(),
+            bids: vec![ExchangeLevel::from_strs(exchange_code, bid, "1")],
+            asks: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_latest_books() {
+        let bus = EventBus::new();
+        bus.publish_book_event(ExchangeEvent::Connected("test1".into()));
+        bus.publish_book_event(ExchangeEvent::Data(book_update("test1", "99")));
+        bus.publish_book_event(ExchangeEvent::Data(book_update("test1", "98")));
+        bus.publish_book_event(ExchangeEvent::Data(book_update("test2", "97")));
+        bus.publish_book_event(ExchangeEvent::Disconnected("test2".into()));
+        let mut events = bus.book_events();
+        bus.publish_book_event(ExchangeEvent::Data(book_update("test2", "96")));
+        assert_eq!(events.next().await, Some(ExchangeEvent::Connected("test1".into())));
+        assert_eq!(events.next().await, Some(ExchangeEvent::Data(book_update("test1", "98"))));
+        assert_eq!(events.next().await, Some(ExchangeEvent::Data(book_update("test2", "96"))));
+        drop(bus);
+        assert_eq!(events.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_resynchronize_after_lag() {
+        let bus = EventBus::new();
+        let mut events = bus.book_events();
+        bus.publish_book_event(ExchangeEvent::Data(book_update("test1", "99")));
+        assert_eq!(events.next().await, Some(ExchangeEvent::Data(book_update("test1", "99"))));
+        bus.publish_book_event(ExchangeEvent::Disconnected("test1".into()));
+        for i in 0..BOOKS_CAPACITY + 1 {
+            bus.publish_book_event(ExchangeEvent::Data(book_update("test2", &i.to_string())));
+        }
+        let lagged = BUS_LAGGED.get(BOOKS_TOPIC);
+        assert_eq!(events.next().await, Some(ExchangeEvent::Disconnected("test1".into())));
+        assert_eq!(events.next().await, Some(ExchangeEvent::Data(book_update("test2", &BOOKS_CAPACITY.to_string()))));
+        assert_eq!(BUS_LAGGED.get(BOOKS_TOPIC), lagged + 1);
+    }
+}
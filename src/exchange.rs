@@ -1,25 +1,94 @@
 //! Common functionalities to create `WebSocket` exchange adapters and merging their
 //! [streams](Stream) of data.
 
-use log::{info, error};
+use log::{info, warn, error};
 use futures::prelude::*;
-use std::{pin::Pin, task::{Context, Poll}};
-use futures::stream::{Stream, select, Select};
-use tokio::{time::{sleep, Duration}, sync::mpsc, net::TcpStream};
+use std::{any::Any, cmp::min, panic::{catch_unwind, resume_unwind, AssertUnwindSafe}, pin::Pin, sync::Arc, task::{Context, Poll}, time::{SystemTime, UNIX_EPOCH}};
+use futures::future::BoxFuture;
+use futures::stream::{BoxStream, Stream, select, Select};
+use tokio::{time::{Duration, Instant}, sync::mpsc, net::TcpStream, task::JoinError};
 use tokio_tungstenite::{connect_async, tungstenite::protocol::Message, tungstenite, MaybeTlsStream, WebSocketStream};
 
+use crate::capabilities::ExchangeCapabilities;
+use crate::clock::{SharedClock, system_clock};
+use crate::crash_dump;
+use crate::effective_config::VenueConfig;
+use crate::failover::{Endpoints, FailoverPolicy};
+use crate::core::{BookUpdate, CurrencyPair, ExchangeId};
+use crate::metrics::{QUEUE_DEPTHS, REJECTED_MESSAGES, REJECTED_UPDATES};
+use crate::ingest::IngestLimits;
+use crate::latency_budget::{ParseTiming, PARSE_TIMINGS};
+use crate::maintenance::MaintenanceSchedule;
+use crate::queues::QueueCapacities;
+use crate::reconnect::{ReconnectBudget, ReconnectGuard};
+use crate::request_ids::{RequestTracker, DEFAULT_FIRST_REQUEST_ID};
+use crate::scheduling::spawn_ingest;
+use crate::status::{ExchangeStatus, ExchangeStatusEvent, StatusSender};
+use crate::subscriptions::SubscriptionRegistry;
+#[cfg(feature = "chaos")]
+use crate::chaos::{ChaosAction, ChaosConfig, ChaosMonkey};
+#[cfg(feature = "rest")]
+use crate::rest::RestFallback;
 
-/// Delay before trying reconnection
+
+/// Default delay before trying reconnection, and before the first restart of a failed adapter task
 const SLEEP_BEFORE_RECONNECT_MS: u64 = 200;
+/// Default maximum delay before restarting a failed adapter task
+const MAX_SLEEP_BEFORE_RESTART_MS: u64 = 30_000;
+/// Consecutive messages which could not be parsed before the failures are notified
+const PARSE_FAILURES_NOTIFIED: u64 = 10;
+/// Default connection delay added for each exchange after the first, to avoid simultaneous subscriptions
+pub const DEFAULT_CONNECTION_STAGGER_MS: u64 = 250;
 
 
 /// Type alias for an exchange-specific function that parses a message into an
-/// [ExchangeProtocol](ExchangeProtocol) object.
+/// [ExchangeProtocol](ExchangeProtocol) object. It may capture state, e.g. the symbols of
+/// the connection, shared by the clones of the adapter.
 /// 
 /// # Generic arguments
 /// 
 /// * `T` - Output data type from the [exchange Stream](ExchangeAdapterStream).
-pub type ExchangeProtocolReader<T> = &'static (dyn Fn(&str) -> Option<ExchangeProtocol<T>> + Send + Sync);
+pub type ExchangeProtocolReader<T> = Arc<dyn Fn(&str) -> Option<ExchangeProtocol<T>> + Send + Sync>;
+
+/// Type alias for a check of the data parsed from each message, before it is delivered: data
+/// failing it is rejected, with the reason, counted in [REJECTED_UPDATES](REJECTED_UPDATES),
+/// and the adapter reconnects to resynchronize the book of the exchange.
+///
+/// # Generic arguments
+///
+/// * `T` - Output data type from the [exchange Stream](ExchangeAdapterStream).
+pub type DataCheck<T> = Arc<dyn Fn(&T) -> Result<(), String> + Send + Sync>;
+
+/// Type alias for a normalization of the data delivered downstream, e.g. of its decimals.
+///
+/// # Generic arguments
+///
+/// * `T` - Output data type from the [exchange Stream](ExchangeAdapterStream).
+pub type DataNormalizer<T> = Arc<dyn Fn(T) -> T + Send + Sync>;
+
+/// Requests managing the channel of a currency pair on an open connection.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SubscriptionRequest {
+    /// Subscribe to the channel.
+    Subscribe,
+    /// Unsubscribe from the channel.
+    Unsubscribe,
+}
+
+/// Type alias for an exchange-specific function formatting the [request](SubscriptionRequest)
+/// subscribing to, or unsubscribing from, the channel of a currency pair. The requests may carry
+/// the [placeholder](crate::request_ids::REQUEST_ID_PLACEHOLDER) of their identifier.
+pub type SubscriptionFormatter = Arc<dyn Fn(&CurrencyPair, SubscriptionRequest) -> String + Send + Sync>;
+
+/// Type alias for an asynchronous step performed before each connection, e.g. obtaining a token
+/// from a REST service: given the URL of the endpoint selected, it returns the WebSocket URL to
+/// connect to, or the reason of its failure.
+pub type Bootstrap = Arc<dyn Fn(String) -> future::BoxFuture<'static, Result<String, String>> + Send + Sync>;
+
+/// Type alias for an exchange-specific function decoding the binary frames into the text
+/// messages parsed, e.g. [decompressing](crate::htx::decode_gzip_frame) them, or returning the
+/// reason of its failure.
+pub type FrameDecoder = Arc<dyn Fn(&[u8]) -> Result<String, String> + Send + Sync>;
 
 /// Messages received from an exchange.
 #[derive(PartialEq, Debug)]
@@ -28,73 +97,600 @@ pub enum ExchangeProtocol<T: 'static + Send> {
     Data(T),
     /// Exchange requested a reconnection.
     ReconnectionRequest,
+    /// Exchange acknowledged a subscription.
+    SubscriptionAck,
+    /// Exchange answered successfully the request with the given identifier, correlated to the
+    /// requests pending on the connection.
+    Response(u64),
+    /// Valid message without data to deliver, e.g. a diff already included in the last snapshot.
+    Skipped,
+    /// Exchange expects a reply on the connection, e.g. to an application heartbeat.
+    Reply(String),
 } 
 
+/// Events delivered by an [exchange stream](ExchangeAdapterStream).
+#[derive(PartialEq, Debug, Clone)]
+pub enum ExchangeEvent<T: 'static + Send> {
+    /// Service data.
+    Data(T),
+    /// The adapter for the exchange (re)connected and subscribed successfully.
+    Connected(ExchangeId),
+    /// The adapter task for the exchange failed and is being restarted:
+    /// data previously received from this exchange must be discarded.
+    Disconnected(ExchangeId),
+}
+
+/// Delays applied by an [exchange adapter](ExchangeAdapter) when the connection is lost or
+/// the adapter task fails.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReconnectPolicy {
+    /// Delay before reconnecting after the connection is lost, and before the first restart
+    /// of a failed adapter task.
+    pub reconnect_delay: Duration,
+    /// Maximum delay before restarting a failed adapter task, the delay doubling at each
+    /// consecutive failure.
+    pub max_restart_delay: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            reconnect_delay: Duration::from_millis(SLEEP_BEFORE_RECONNECT_MS),
+            max_restart_delay: Duration::from_millis(MAX_SLEEP_BEFORE_RESTART_MS),
+        }
+    }
+}
+
 /// Type used to send commands from the [exchange stream](ExchangeAdapterStream)
 /// to the internal loop of the [exchange adapter](ExchangeAdapter).
 enum AdapterCommand {
     /// Disconnect the exchange and exit the loop
     Close,
+    /// Subscribe to the channel of a currency pair, on the open connection and after each reconnection.
+    Subscribe(CurrencyPair),
+    /// Unsubscribe from the channel of a currency pair, on the open connection and after each reconnection.
+    Unsubscribe(CurrencyPair),
 }
 
 /// Contains all the information to connect to an exchange
 pub struct ExchangeAdapter<T: 'static + Send> {
     /// Exchange code. Used for messages.
-    exchange_code: &'static str,
-    /// WebSocket URL.
-    ws_url: String,
-    /// WebSocket subscription message.
-    subscribe_message: String,
+    exchange_code: ExchangeId,
+    /// WebSocket endpoints, the primary one first.
+    endpoints: Endpoints,
+    /// WebSocket subscription messages, one for each channel subscribed, sent again after
+    /// each reconnection. Empty if the URL alone subscribes.
+    subscribe_messages: Vec<String>,
+    /// Identifier of the first request issued on each connection.
+    first_request_id: u64,
+    /// Formatter of the requests managing channels on an open connection, if supported.
+    subscription_formatter: Option<SubscriptionFormatter>,
+    /// Whether the exchange acknowledges each subscription: the connection is then notified
+    /// [up](ExchangeStatus::Up) only once every subscription was acknowledged.
+    subscription_acks: bool,
+    /// Application heartbeat message sent periodically, with its interval, if the exchange
+    /// requires one to keep the connection open.
+    keepalive: Option<(String, Duration)>,
+    /// Step performed before each connection, returning the WebSocket URL to connect to, if any.
+    bootstrap: Option<Bootstrap>,
+    /// Decoder of the binary frames, which are ignored without one.
+    frame_decoder: Option<FrameDecoder>,
     /// Exchange-specific message parser function.
     protocol_reader: ExchangeProtocolReader<T>,
+    /// Check of the data parsed, if any.
+    data_check: Option<DataCheck<T>>,
+    /// Normalization of the data delivered, if any.
+    data_normalizer: Option<DataNormalizer<T>>,
+    /// Whether the parsing of each message is timed for the [latency budget](crate::latency_budget).
+    parse_timing: bool,
+    /// Time source for reconnection delays and update intervals.
+    clock: SharedClock,
+    /// Minimum interval between two data items delivered downstream, if any.
+    min_update_interval: Option<Duration>,
+    /// Delays before reconnecting and restarting.
+    reconnect_policy: ReconnectPolicy,
+    /// Budget of the connection attempts, unlimited if [None](None).
+    reconnect_guard: Option<ReconnectGuard>,
+    /// Registry leasing the subscription, and its symbol, if any.
+    subscription_registry: Option<(SubscriptionRegistry, String)>,
+    /// Faults injected in the messages received, if any.
+    #[cfg(feature = "chaos")]
+    chaos: Option<ChaosConfig>,
+    /// REST endpoint polled while the WebSocket service cannot be reached, if any.
+    #[cfg(feature = "rest")]
+    rest_fallback: Option<RestFallback<T>>,
+    /// REST endpoint of the snapshot fetched after each connection, before the diffs received, if any.
+    #[cfg(feature = "rest")]
+    depth_snapshot: Option<RestFallback<T>>,
+    /// Where changes of the connection status are notified, if any.
+    status_sender: Option<StatusSender>,
+    /// Maintenance windows, during which disconnections are notified as expected.
+    maintenance: MaintenanceSchedule,
+    /// Capacities of the data and command queues.
+    queue_capacities: QueueCapacities,
+    /// Limits of the messages received, checked before parsing.
+    ingest_limits: IngestLimits,
+    /// Capabilities of the exchange, reported to clients.
+    capabilities: ExchangeCapabilities,
 }
 
 impl <T: 'static + Send> ExchangeAdapter<T> {
-    /// Create a new [ExchangeAdapter](ExchangeAdapter) object.
+    /// Create a new [ExchangeAdapter](ExchangeAdapter) object, with the default settings:
+    /// no subscription message nor acknowledgment, no rate limit, the default [ReconnectPolicy](ReconnectPolicy),
+    /// [QueueCapacities](QueueCapacities) and [IngestLimits](IngestLimits).
     ///
     /// # Arguments
     ///
     /// * `exchange_code` - The code of the exchange.
     ///
-    /// * `ws_url` - WebSocket URL.
-    ///
-    /// * `subscribe_message` - WebSocket subscription message.
+    /// * `ws_url` - WebSocket URL of the primary endpoint.
     ///
     /// * `protocol_reader` - Exchange-specific message parser function.
     ///
     /// # Returns
     ///
     /// A [ExchangeAdapter](ExchangeAdapter) object.
-    pub async fn new(
-        exchange_code: &'static str,
-        ws_url: String,
-        subscribe_message: String,
-        protocol_reader: ExchangeProtocolReader<T>) -> ExchangeAdapter<T> {
+    pub fn new(exchange_code: impl Into<ExchangeId>, ws_url: String, protocol_reader: ExchangeProtocolReader<T>) -> ExchangeAdapter<T> {
         ExchangeAdapter {
-            exchange_code,
-            ws_url,
-            subscribe_message,
+            exchange_code: exchange_code.into(),
+            endpoints: Endpoints::new(ws_url),
+            subscribe_messages: vec![],
+            first_request_id: DEFAULT_FIRST_REQUEST_ID,
+            subscription_formatter: None,
+            subscription_acks: false,
+            keepalive: None,
+            bootstrap: None,
+            frame_decoder: None,
             protocol_reader,
+            data_check: None,
+            data_normalizer: None,
+            parse_timing: false,
+            clock: system_clock(),
+            min_update_interval: None,
+            reconnect_policy: ReconnectPolicy::default(),
+            reconnect_guard: None,
+            subscription_registry: None,
+            #[cfg(feature = "chaos")]
+            chaos: None,
+            #[cfg(feature = "rest")]
+            rest_fallback: None,
+            #[cfg(feature = "rest")]
+            depth_snapshot: None,
+            status_sender: None,
+            maintenance: MaintenanceSchedule::default(),
+            queue_capacities: QueueCapacities::default(),
+            ingest_limits: IngestLimits::default(),
+            capabilities: ExchangeCapabilities::default(),
         }
     }
 
+    /// Add a channel subscription. The subscription messages of all the channels are sent
+    /// in order after each connection, including reconnections.
+    ///
+    /// # Arguments
+    ///
+    /// * `subscribe_message` - WebSocket subscription message. Its [placeholder](crate::request_ids::REQUEST_ID_PLACEHOLDER),
+    ///   if any, is replaced by the identifier of the request.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_subscribe_message(mut self, subscribe_message: String) -> Self {
+        self.subscribe_messages.push(subscribe_message);
+        self
+    }
+
+    /// Manage the channels of currency pairs on the open connection, with the
+    /// [subscribe](ExchangeAdapterStream::subscribe) and [unsubscribe](ExchangeAdapterStream::unsubscribe)
+    /// commands of the stream. The initial subscription should be formatted by the same function,
+    /// so that unsubscribing from its currency pair stops resubscribing after each reconnection.
+    ///
+    /// # Arguments
+    ///
+    /// * `subscription_formatter` - A [SubscriptionFormatter](SubscriptionFormatter).
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_subscription_formatter(mut self, subscription_formatter: SubscriptionFormatter) -> Self {
+        self.subscription_formatter = Some(subscription_formatter);
+        self
+    }
+
+    /// Set the identifier of the first request issued on each connection, the following ones
+    /// being numbered in sequence.
+    ///
+    /// # Arguments
+    ///
+    /// * `first_request_id` - The identifier of the first request.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_first_request_id(mut self, first_request_id: u64) -> Self {
+        self.first_request_id = first_request_id;
+        self
+    }
+
+    /// Wait for the exchange to acknowledge every subscription, as parsed by the protocol
+    /// reader into [SubscriptionAck](ExchangeProtocol::SubscriptionAck), or into a
+    /// [Response](ExchangeProtocol::Response) to a pending request, before notifying
+    /// the connection [up](ExchangeStatus::Up).
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_subscription_acks(mut self) -> Self {
+        self.subscription_acks = true;
+        self
+    }
+
+    /// Send an application heartbeat message periodically while connected, for exchanges
+    /// closing the connections of silent clients. The responses are parsed as any other message.
+    ///
+    /// # Arguments
+    ///
+    /// * `keepalive_message` - The heartbeat message, e.g. a ping request.
+    ///
+    /// * `interval` - The interval between two heartbeat messages.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_keepalive_message(mut self, keepalive_message: String, interval: Duration) -> Self {
+        self.keepalive = Some((keepalive_message, interval));
+        self
+    }
+
+    /// Check the data parsed from each message before delivering it: data failing the check
+    /// is rejected, and the adapter reconnects to resynchronize.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_check` - A [DataCheck](DataCheck).
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_data_check(mut self, data_check: DataCheck<T>) -> Self {
+        self.data_check = Some(data_check);
+        self
+    }
+
+    /// Perform an asynchronous step before each connection, for exchanges whose WebSocket URL
+    /// is obtained from another service, e.g. with a token. The endpoints of the adapter are then
+    /// those of that service. When the step fails, the connection is retried after the
+    /// [reconnection delay](ReconnectPolicy::reconnect_delay).
+    ///
+    /// # Arguments
+    ///
+    /// * `bootstrap` - The step, returning the WebSocket URL.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_bootstrap(mut self, bootstrap: Bootstrap) -> Self {
+        self.bootstrap = Some(bootstrap);
+        self
+    }
+
+    /// Decode the binary frames into text messages, parsed as those received in text frames,
+    /// for exchanges compressing their frames. Frames which cannot be decoded count as
+    /// parse failures.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame_decoder` - A [FrameDecoder](FrameDecoder).
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_frame_decoder(mut self, frame_decoder: FrameDecoder) -> Self {
+        self.frame_decoder = Some(frame_decoder);
+        self
+    }
+
+    /// Normalize the data before delivering it, whatever its source.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_normalizer` - A [DataNormalizer](DataNormalizer).
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_data_normalizer(mut self, data_normalizer: DataNormalizer<T>) -> Self {
+        self.data_normalizer = Some(data_normalizer);
+        self
+    }
+
+    /// Time the parsing of each message, recording it in [PARSE_TIMINGS](PARSE_TIMINGS) for the
+    /// [latency budget](crate::latency_budget) of the summaries.
+    ///
+    /// # Arguments
+    ///
+    /// * `parse_timing` - Whether to time the parsing.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_parse_timing(mut self, parse_timing: bool) -> Self {
+        self.parse_timing = parse_timing;
+        self
+    }
+
+    /// Replace the time source used by the adapter, by default the system clock.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - A [shared clock](SharedClock).
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Replace the WebSocket URL, e.g. to connect to a [simulated exchange](crate::simulated::SimulatedExchange).
+    /// The failover endpoints, if any, are removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `ws_url` - WebSocket URL.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_ws_url(mut self, ws_url: String) -> Self {
+        self.endpoints = Endpoints::new(ws_url);
+        self
+    }
+
+    /// Add a failover WebSocket endpoint, less preferred than the previous ones. Before each
+    /// connection the endpoints are probed, and the first one reachable and not degraded is
+    /// used; when the connection to it fails, the next one is tried.
+    ///
+    /// # Arguments
+    ///
+    /// * `ws_url` - WebSocket URL.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_failover_url(mut self, ws_url: String) -> Self {
+        self.endpoints = self.endpoints.with_url(ws_url);
+        self
+    }
+
+    /// Replace the thresholds of the probes of the failover endpoints.
+    ///
+    /// # Arguments
+    ///
+    /// * `failover_policy` - A [FailoverPolicy](FailoverPolicy).
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_failover_policy(mut self, failover_policy: FailoverPolicy) -> Self {
+        self.endpoints = self.endpoints.with_policy(failover_policy);
+        self
+    }
+
+    /// Limit the rate of data delivered by the adapter: data arriving less than `interval`
+    /// after the previous delivery is held back, and only the latest item is delivered
+    /// once the interval has elapsed.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - Minimum interval between two deliveries.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_min_update_interval(mut self, interval: Duration) -> Self {
+        self.min_update_interval = Some(interval);
+        self
+    }
+
+    /// Replace the delays before reconnecting and restarting.
+    ///
+    /// # Arguments
+    ///
+    /// * `reconnect_policy` - A [ReconnectPolicy](ReconnectPolicy).
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_reconnect_policy(mut self, reconnect_policy: ReconnectPolicy) -> Self {
+        self.reconnect_policy = reconnect_policy;
+        self
+    }
+
+    /// Inject faults in the messages received, for resilience testing.
+    ///
+    /// # Arguments
+    ///
+    /// * `chaos` - A [ChaosConfig](ChaosConfig) with the fault rates.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    #[cfg(feature = "chaos")]
+    pub fn with_chaos(mut self, chaos: ChaosConfig) -> Self {
+        self.chaos = Some(chaos);
+        self
+    }
+
+    /// Poll snapshots from a REST endpoint when the connection to the WebSocket service
+    /// fails, instead of restarting the adapter. The WebSocket service is tried again
+    /// periodically.
+    ///
+    /// # Arguments
+    ///
+    /// * `rest_fallback` - The [RestFallback](RestFallback).
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    #[cfg(feature = "rest")]
+    pub fn with_rest_fallback(mut self, rest_fallback: RestFallback<T>) -> Self {
+        self.rest_fallback = Some(rest_fallback);
+        self
+    }
+
+    /// Fetch a snapshot from a REST endpoint after each connection and subscription, and
+    /// deliver it before the data received from the WebSocket service, for services
+    /// streaming diffs of the book. If the snapshot cannot be fetched, the adapter reconnects.
+    ///
+    /// # Arguments
+    ///
+    /// * `depth_snapshot` - The REST endpoint, as a [RestFallback](RestFallback) whose
+    ///   polling interval and retry time are not used.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    #[cfg(feature = "rest")]
+    pub fn with_depth_snapshot(mut self, depth_snapshot: RestFallback<T>) -> Self {
+        self.depth_snapshot = Some(depth_snapshot);
+        self
+    }
+
+    /// Notify the changes of the connection status: connection, disconnection, restart
+    /// and repeated parse failures.
+    ///
+    /// # Arguments
+    ///
+    /// * `status_sender` - A [StatusSender](StatusSender).
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_status_sender(mut self, status_sender: StatusSender) -> Self {
+        self.status_sender = Some(status_sender);
+        self
+    }
+
+    /// Notify the disconnections during the maintenance windows of the exchange as
+    /// [scheduled offline](ExchangeStatus::ScheduledOffline), rather than as failures.
+    ///
+    /// # Arguments
+    ///
+    /// * `maintenance` - The [MaintenanceSchedule](MaintenanceSchedule), possibly with windows
+    ///   of other exchanges.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_maintenance(mut self, maintenance: MaintenanceSchedule) -> Self {
+        self.maintenance = maintenance;
+        self
+    }
+
+    /// Set the capacities of the queues of data delivered and commands received by the adapter.
+    ///
+    /// # Arguments
+    ///
+    /// * `queue_capacities` - The [QueueCapacities](QueueCapacities).
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_queue_capacities(mut self, queue_capacities: QueueCapacities) -> Self {
+        self.queue_capacities = queue_capacities;
+        self
+    }
+
+    /// Limit the connection attempts, including reconnections and restarts, to a budget.
+    /// The adapter gets its own budget, and shares the budget of all the exchanges with the
+    /// other adapters limited by the same [ReconnectBudget](ReconnectBudget).
+    ///
+    /// # Arguments
+    ///
+    /// * `reconnect_budget` - The [ReconnectBudget](ReconnectBudget).
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_reconnect_budget(mut self, reconnect_budget: &ReconnectBudget) -> Self {
+        self.reconnect_guard = Some(reconnect_budget.guard());
+        self
+    }
+
+    /// Lease the subscription from a registry before connecting, waiting for any other adapter
+    /// holding it, so that at most one subscription to the symbol of the exchange is open. The
+    /// lease is kept across reconnections, and released when the adapter stops or is restarted.
+    ///
+    /// # Arguments
+    ///
+    /// * `subscription_registry` - The [SubscriptionRegistry](SubscriptionRegistry).
+    ///
+    /// * `symbol` - The canonical symbol subscribed to.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_subscription_registry(mut self, subscription_registry: &SubscriptionRegistry, symbol: &str) -> Self {
+        self.subscription_registry = Some((subscription_registry.clone(), symbol.to_string()));
+        self
+    }
+
+    /// Set the limits of the messages received, checked before parsing.
+    ///
+    /// # Arguments
+    ///
+    /// * `ingest_limits` - The [IngestLimits](IngestLimits).
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_ingest_limits(mut self, ingest_limits: IngestLimits) -> Self {
+        self.ingest_limits = ingest_limits;
+        self
+    }
+
+    /// Declare the capabilities of the exchange, reported to clients.
+    ///
+    /// # Arguments
+    ///
+    /// * `capabilities` - The [ExchangeCapabilities](ExchangeCapabilities).
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_capabilities(mut self, capabilities: ExchangeCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
     /// Connects to the exchange WebSocket service and returns an object implementing [Stream](Stream).
     ///
     /// # Returns
     ///
     /// A [ExchangeAdapterStream](ExchangeAdapterStream) object.
     pub async fn make_stream(&self) -> ExchangeAdapterStream<T> {
-        let exchange_code = self.exchange_code;
-        let ws_url = self.ws_url.clone();
-        let subscribe_message = self.subscribe_message.clone();
-        let (data_sender, data_receiver) = mpsc::channel::<T>(16);
-        let (command_sender, command_receiver) = mpsc::channel::<AdapterCommand>(1);
-        tokio::spawn(
-            Self::process_stream(
-                exchange_code,
-                ws_url,
-                subscribe_message,
-                self.protocol_reader,
+        self.make_staggered_stream(Duration::ZERO).await
+    }
+
+    /// Connects to the exchange WebSocket service and returns an object implementing [Stream](Stream).
+    /// Each connection attempt, including the first one, is delayed by `stagger`, so that
+    /// several exchanges connecting at the same time can be spread apart.
+    ///
+    /// # Arguments
+    ///
+    /// * `stagger` - Delay before each connection attempt.
+    ///
+    /// # Returns
+    ///
+    /// A [ExchangeAdapterStream](ExchangeAdapterStream) object.
+    pub async fn make_staggered_stream(&self, stagger: Duration) -> ExchangeAdapterStream<T> {
+        let (data_sender, data_receiver) = mpsc::channel::<ExchangeEvent<T>>(self.queue_capacities.adapter_data);
+        let (command_sender, command_receiver) = mpsc::channel::<AdapterCommand>(self.queue_capacities.adapter_command);
+        spawn_ingest(
+            self.clone().supervise(
+                stagger,
                 data_sender,
                 command_receiver
             )
@@ -105,29 +701,252 @@ impl <T: 'static + Send> ExchangeAdapter<T> {
         }
     }
 
+    /// The code of the exchange.
+    pub fn exchange_code(&self) -> &ExchangeId {
+        &self.exchange_code
+    }
+
+    /// The capabilities of the exchange.
+    pub fn capabilities(&self) -> ExchangeCapabilities {
+        self.capabilities
+    }
+
+    /// The WebSocket URL of the endpoint in use, or to be used by the next connection.
+    pub fn active_endpoint(&self) -> &str {
+        self.endpoints.active_url()
+    }
+
+    /// The effective configuration of the adapter.
+    ///
+    /// # Returns
+    ///
+    /// A [VenueConfig](VenueConfig) object.
+    pub fn config(&self) -> VenueConfig {
+        VenueConfig {
+            exchange: self.exchange_code.clone(),
+            ws_url: self.endpoints.urls()[0].clone(),
+            failover_urls: self.endpoints.urls()[1..].to_vec(),
+            subscribe_messages: self.subscribe_messages.clone(),
+            subscription_acks: self.subscription_acks,
+            min_update_interval_ms: self.min_update_interval.map(|interval| interval.as_millis() as u64),
+            reconnect_delay_ms: self.reconnect_policy.reconnect_delay.as_millis() as u64,
+            max_restart_delay_ms: self.reconnect_policy.max_restart_delay.as_millis() as u64,
+            reconnect_budget: self.reconnect_guard.is_some(),
+            #[cfg(feature = "rest")]
+            rest_fallback_url: self.rest_fallback.as_ref().map(|rest_fallback| rest_fallback.url().to_string()),
+            #[cfg(not(feature = "rest"))]
+            rest_fallback_url: None,
+            #[cfg(feature = "rest")]
+            depth_snapshot_url: self.depth_snapshot.as_ref().map(|depth_snapshot| depth_snapshot.url().to_string()),
+            #[cfg(not(feature = "rest"))]
+            depth_snapshot_url: None,
+            ingest_limits: self.ingest_limits,
+            capabilities: self.capabilities,
+        }
+    }
+
+    /// Internal function running [process_stream](ExchangeAdapter::process_stream) in a
+    /// separate task, and restarting it with an increasing delay when it fails, panics or exits
+    /// without being asked to. Each restart is notified downstream with an
+    /// [ExchangeEvent::Disconnected](ExchangeEvent::Disconnected) event.
+    /// Commands received are forwarded to the running task, and the channels subscribed
+    /// are kept for the restarted ones.
+    async fn supervise(
+            mut self,
+            stagger: Duration,
+            data_sender: mpsc::Sender<ExchangeEvent<T>>,
+            mut command_receiver: mpsc::Receiver<AdapterCommand>) {
+        let exchange_code = self.exchange_code.clone();
+        let initial_delay = self.reconnect_policy.reconnect_delay;
+        let max_delay = self.reconnect_policy.max_restart_delay;
+        let mut delay = initial_delay;
+        loop {
+            let (task_command_sender, task_command_receiver) = mpsc::channel::<AdapterCommand>(self.queue_capacities.adapter_command);
+            let started = self.clock.now();
+            let mut task = tokio::spawn(
+                self.clone().process_stream(
+                    stagger,
+                    data_sender.clone(),
+                    task_command_receiver
+                )
+            );
+            let closed = loop {
+                tokio::select! {
+                    result = &mut task => {
+                        match result {
+                            Ok(Ok(())) => error!("Exchange {} task exited unexpectedly", exchange_code),
+                            Ok(Err(error)) => error!("Connection error for {}: {:?}", exchange_code, error),
+                            Err(join_error) => error!("Exchange {} task failed: {}", exchange_code, panic_message(join_error)),
+                        }
+                        break false;
+                    },
+                    command = command_receiver.recv() => match command {
+                        Some(AdapterCommand::Close) | None => {
+                            if task_command_sender.send(AdapterCommand::Close).await.is_err() {
+                                error!("Error queueing command");
+                            }
+                            QUEUE_DEPTHS.record_channel("adapter_command", &exchange_code, &task_command_sender);
+                            match task.await {
+                                Ok(Ok(())) => (),
+                                Ok(Err(error)) => error!("Connection error for {}: {:?}", exchange_code, error),
+                                Err(join_error) => error!("Exchange {} task failed: {}", exchange_code, panic_message(join_error)),
+                            }
+                            break true;
+                        },
+                        Some(command) => {
+                            let mut channels = self.subscribe_messages.clone();
+                            self.apply_subscription(&mut channels, &command);
+                            self.subscribe_messages = channels;
+                            if task_command_sender.send(command).await.is_err() {
+                                error!("Error queueing command");
+                            }
+                            QUEUE_DEPTHS.record_channel("adapter_command", &exchange_code, &task_command_sender);
+                        },
+                    },
+                }
+            };
+            if closed {
+                break;
+            }
+            self.notify(ExchangeStatus::Ejected);
+            if data_sender.send(ExchangeEvent::Disconnected(exchange_code.clone())).await.is_err() {
+                info!("Exchange {} stream dropped, not restarting", exchange_code);
+                break;
+            }
+            if self.clock.now() - started > max_delay {
+                delay = initial_delay;
+            }
+            info!("Restarting exchange {} in {}ms", exchange_code, delay.as_millis());
+            self.clock.sleep(delay).await;
+            delay = min(delay * 2, max_delay);
+        }
+    }
+
     /// Internal function implementing a loop reading from the exchange WebSocket service, and
     /// delivering the data received to the corresponding [ExchangeAdapterStream](ExchangeAdapterStream)
     /// object through a channel.
-    /// It handles pings and it tries to reconnect in case of connection error.
-    /// It receives [AdapterCommand](AdapterCommand) instances through a channel, to drive its behavior.
-    /// Currently only closing behavior implemented.
+    /// It handles pings and it tries to reconnect once the connection is lost, returning the error
+    /// when it cannot connect, for the [supervisor](ExchangeAdapter::supervise) to restart it.
+    /// After each successful subscription it sends an [ExchangeEvent::Connected](ExchangeEvent::Connected) event.
+    /// It receives [AdapterCommand](AdapterCommand) instances through a channel, to drive its behavior:
+    /// closing, and subscribing to or unsubscribing from channels.
     async fn process_stream(
-            exchange_code: &str,
-            ws_url: String,
-            subscribe_message: String,
-            protocol_reader: ExchangeProtocolReader<T>,
-            data_sender: mpsc::Sender<T>,
-            mut command_receiver: mpsc::Receiver<AdapterCommand>) {
+            self,
+            stagger: Duration,
+            data_sender: mpsc::Sender<ExchangeEvent<T>>,
+            mut command_receiver: mpsc::Receiver<AdapterCommand>) -> Result<(), tungstenite::Error> {
+        let exchange_code = self.exchange_code.clone();
+        // subscription messages sent after each connection, changed by the commands
+        let mut channels = self.subscribe_messages.clone();
+        #[cfg(feature = "chaos")]
+        let mut connections: u64 = 0;
+        let mut failed_endpoints = vec![false; self.endpoints.len()];
+        // held across reconnections, until the loop exits
+        let _lease = match &self.subscription_registry {
+            Some((registry, symbol)) => tokio::select! {
+                lease = registry.acquire(&exchange_code, symbol) => Some(lease),
+                _ = self.wait_for_close(&mut command_receiver, &mut channels) => {
+                    info!("Stopped waiting for the subscription to {} {}", exchange_code, symbol);
+                    return Ok(());
+                },
+            },
+            None => None,
+        };
         'connection:
         loop {
-            let mut pinned_ws = Self::connect(
-                exchange_code,
-                ws_url.clone(),
-                subscribe_message.clone()
-            ).await;
+            self.clock.sleep(stagger).await;
+            if let Some(reconnect_guard) = &self.reconnect_guard {
+                while !reconnect_guard.try_acquire(self.clock.now()) {
+                    let cool_down = reconnect_guard.cool_down();
+                    warn!("Connection attempts to {} exhausted their budget, cooling down for {}ms", exchange_code, cool_down.as_millis());
+                    self.notify(ExchangeStatus::ReconnectsThrottled { cool_down_ms: cool_down.as_millis() as u64 });
+                    tokio::select! {
+                        _ = self.clock.sleep(cool_down) => (),
+                        _ = self.wait_for_close(&mut command_receiver, &mut channels) => {
+                            info!("Stopped cooling down exchange {}", exchange_code);
+                            break 'connection;
+                        },
+                    }
+                }
+            }
+            let (endpoint, changed) = self.endpoints.select(&failed_endpoints).await;
+            let ws_url = &self.endpoints.urls()[endpoint];
+            if changed {
+                warn!("Exchange {} failing over to {}", exchange_code, ws_url);
+                self.notify(ExchangeStatus::FailedOver { endpoint: ws_url.clone() });
+            }
+            let ws_url = match &self.bootstrap {
+                Some(bootstrap) => match bootstrap(ws_url.clone()).await {
+                    Ok(bootstrapped_url) => bootstrapped_url,
+                    Err(error) => {
+                        error!("Bootstrap of exchange {} failed: {}", exchange_code, error);
+                        self.notify(ExchangeStatus::Down);
+                        info!("Trying reconnection in {}ms", self.reconnect_policy.reconnect_delay.as_millis());
+                        self.clock.sleep(self.reconnect_policy.reconnect_delay).await;
+                        continue 'connection;
+                    },
+                },
+                None => ws_url.clone(),
+            };
+            let mut requests = RequestTracker::new(self.first_request_id);
+            let mut pinned_ws = match self.connect(&ws_url, &channels, &mut requests).await {
+                Ok(pinned_ws) => {
+                    failed_endpoints.fill(false);
+                    pinned_ws
+                },
+                Err(error) if failed_endpoints.iter().filter(|failed| !**failed).count() > 1 => {
+                    error!("Connection to exchange {} at {} failed: {:?}, trying the next endpoint", exchange_code, ws_url, error);
+                    failed_endpoints[endpoint] = true;
+                    continue 'connection;
+                },
+                #[cfg(feature = "rest")]
+                Err(error) if self.rest_fallback.is_some() => {
+                    error!("Connection to exchange {} failed: {:?}, polling REST snapshots", exchange_code, error);
+                    failed_endpoints.fill(false);
+                    if self.poll_rest(&data_sender, &mut command_receiver, &mut channels).await {
+                        break 'connection;
+                    }
+                    continue 'connection;
+                },
+                Err(error) => return Err(error),
+            };
+            #[cfg(feature = "rest")]
+            let snapshot = match &self.depth_snapshot {
+                Some(depth_snapshot) => match depth_snapshot.poll(&exchange_code).await {
+                    Some(snapshot) => Some(snapshot),
+                    None => {
+                        self.notify(ExchangeStatus::Down);
+                        info!("Trying reconnection in {}ms", self.reconnect_policy.reconnect_delay.as_millis());
+                        self.clock.sleep(self.reconnect_policy.reconnect_delay).await;
+                        continue 'connection;
+                    },
+                },
+                None => None,
+            };
+            if data_sender.send(ExchangeEvent::Connected(exchange_code.clone())).await.is_err() {
+                error!("Error queueing data");
+            }
+            #[cfg(feature = "rest")]
+            if let Some(snapshot) = snapshot {
+                self.send_data(&data_sender, snapshot).await;
+            }
+            QUEUE_DEPTHS.record_channel("adapter_data", &exchange_code, &data_sender);
+            let mut pending_acks = if self.subscription_acks { channels.len() } else { 0 };
+            if pending_acks == 0 {
+                self.notify(ExchangeStatus::Up);
+            }
+            let mut parse_failures: u64 = 0;
+            let mut conflator = Conflator::new(self.min_update_interval);
+            let mut next_keepalive = self.keepalive.as_ref().map(|(_, interval)| self.clock.now() + *interval);
+            // command received while waiting for a message, handled first
+            let mut received_command: Option<AdapterCommand> = None;
+            #[cfg(feature = "chaos")]
+            let mut chaos_monkey = self.chaos.clone().map(|config| ChaosMonkey::new(config, connections));
+            #[cfg(feature = "chaos")]
+            { connections += 1; }
             'message:
             loop {
-                if let Ok(command) = command_receiver.try_recv() {
+                if let Some(command) = received_command.take().or_else(|| command_receiver.try_recv().ok()) {
                     match command {
                         AdapterCommand::Close => {
                             info!("Disconnecting exchange {}", exchange_code);
@@ -136,23 +955,143 @@ impl <T: 'static + Send> ExchangeAdapter<T> {
                                 Err(error) => error!("Error disconnecting from {}: {:?}", exchange_code, error),
                             }
                             break 'connection;
+                        },
+                        command => {
+                            if let Some(request) = self.apply_subscription(&mut channels, &command) {
+                                let request = requests.issue(&request);
+                                info!("Request '{}' to {}", request, exchange_code);
+                                if let Err(error) = pinned_ws.send(Message::Text(request)).await {
+                                    error!("Error sending request to {}: {:?}", exchange_code, error);
+                                    break 'message;
+                                }
+                            }
+                        },
+                    }
+                }
+                if let (Some((keepalive_message, interval)), Some(due)) = (&self.keepalive, next_keepalive) {
+                    let now = self.clock.now();
+                    if due <= now {
+                        if let Err(error) = pinned_ws.send(Message::Text(keepalive_message.clone())).await {
+                            error!("Error sending heartbeat to {}: {:?}", exchange_code, error);
+                            break 'message;
                         }
+                        next_keepalive = Some(now + *interval);
                     }
                 }
-                match pinned_ws.next().await {
+                let now = self.clock.now();
+                let keepalive_delay = next_keepalive.map(|due| due.saturating_duration_since(now));
+                let message = match conflator.flush_delay(now).into_iter().chain(keepalive_delay).min() {
+                    Some(delay) => tokio::select! {
+                        message = pinned_ws.next() => message,
+                        Some(command) = command_receiver.recv() => {
+                            received_command = Some(command);
+                            continue 'message;
+                        },
+                        _ = self.clock.sleep(delay) => {
+                            // the heartbeat may be due before the data held back
+                            let now = self.clock.now();
+                            if conflator.flush_delay(now) == Some(Duration::ZERO) {
+                                if let Some(data) = conflator.take_pending(now) {
+                                    self.send_data(&data_sender, data).await;
+                                }
+                            }
+                            continue 'message;
+                        },
+                    },
+                    None => tokio::select! {
+                        message = pinned_ws.next() => message,
+                        Some(command) = command_receiver.recv() => {
+                            received_command = Some(command);
+                            continue 'message;
+                        },
+                    },
+                };
+                let message = match (message, &self.frame_decoder) {
+                    (Some(Ok(Message::Binary(frame))), Some(frame_decoder)) => match frame_decoder(&frame) {
+                        Ok(text) => Some(Ok(Message::Text(text))),
+                        Err(reason) => {
+                            warn!("Undecodable frame from {}: {}", exchange_code, reason);
+                            self.count_parse_failure(&mut parse_failures);
+                            continue 'message;
+                        },
+                    },
+                    (message, _) => message,
+                };
+                match message {
                     Some(Ok(Message::Text(text))) => {
-                        match protocol_reader(&text) {
+                        #[cfg(feature = "chaos")]
+                        let text = match chaos_monkey.as_mut().map(|m| m.next_action(&text)) {
+                            Some(ChaosAction::Drop) => continue 'message,
+                            Some(ChaosAction::Corrupt(corrupted)) => corrupted,
+                            Some(ChaosAction::Disconnect) => {
+                                info!("Chaos: dropping connection to {}", exchange_code);
+                                break 'message;
+                            },
+                            Some(ChaosAction::Delay(delay)) => {
+                                self.clock.sleep(delay).await;
+                                text
+                            },
+                            Some(ChaosAction::Deliver) | None => text,
+                        };
+                        if let Err(rejection) = self.ingest_limits.check(&text) {
+                            warn!("Rejected {} from {}", rejection, exchange_code);
+                            REJECTED_MESSAGES.increment(&exchange_code);
+                            continue 'message;
+                        }
+                        crash_dump::record_message(&exchange_code, &text);
+                        let parse_started = self.parse_timing.then(std::time::Instant::now);
+                        let protocol = match catch_unwind(AssertUnwindSafe(|| (self.protocol_reader)(&text))) {
+                            Ok(protocol) => protocol,
+                            Err(payload) => {
+                                crash_dump::write_dump(&exchange_code, None);
+                                resume_unwind(payload);
+                            },
+                        };
+                        match protocol {
                             Some(ExchangeProtocol::Data(data)) => {
-                                match data_sender.send(data).await {
-                                    Ok(_) => (),
-                                    Err(_) => error!("Error queueing data"),
+                                parse_failures = 0;
+                                if let Some(parse_started) = parse_started {
+                                    let parsed_at = std::time::Instant::now();
+                                    PARSE_TIMINGS.record(&exchange_code, ParseTiming { duration: parsed_at - parse_started, parsed_at });
+                                }
+                                if let Some(Err(reason)) = self.data_check.as_ref().map(|data_check| data_check(&data)) {
+                                    warn!("Rejected {} from {}, resynchronizing", reason, exchange_code);
+                                    REJECTED_UPDATES.increment(&exchange_code);
+                                    break 'message;
+                                }
+                                if let Some(data) = conflator.offer(data, self.clock.now()) {
+                                    self.send_data(&data_sender, data).await;
                                 }
                             },
                             Some(ExchangeProtocol::ReconnectionRequest) => {
                                 info!("Reconnection request from {}", exchange_code);
                                 break 'message;
                             },
-                            _ => ()
+                            Some(ExchangeProtocol::Skipped) => {
+                                parse_failures = 0;
+                            },
+                            Some(ExchangeProtocol::SubscriptionAck) => {
+                                parse_failures = 0;
+                                self.acknowledge(&mut pending_acks);
+                            },
+                            Some(ExchangeProtocol::Response(request_id)) => {
+                                parse_failures = 0;
+                                match requests.correlate(request_id) {
+                                    Some(request) => {
+                                        info!("Request '{}' acknowledged by {}", request, exchange_code);
+                                        self.acknowledge(&mut pending_acks);
+                                    },
+                                    None => warn!("Response from {} to unknown request {}", exchange_code, request_id),
+                                }
+                            },
+                            Some(ExchangeProtocol::Reply(reply)) => {
+                                parse_failures = 0;
+                                if let Err(error) = pinned_ws.send(Message::Text(reply)).await {
+                                    error!("Error sending reply to {}: {:?}", exchange_code, error);
+                                    break 'message;
+                                }
+                            },
+                            None => self.count_parse_failure(&mut parse_failures),
                         }
                     },
                     Some(Ok(Message::Ping(data))) => {
@@ -170,46 +1109,587 @@ impl <T: 'static + Send> ExchangeAdapter<T> {
                         error!("Connection to exchange {} closed", exchange_code);
                         break 'message;
                     },
+                    Some(Err(error)) => {
+                        error!("Connection to exchange {} failed: {:?}", exchange_code, error);
+                        break 'message;
+                    },
                     Some(other) => info!("Received unexpected message: {:?}", other),
-                    _ => (),
+                    None => {
+                        error!("Connection to exchange {} ended", exchange_code);
+                        break 'message;
+                    },
                 }
             }
-            info!("Trying reconnection in {}ms", SLEEP_BEFORE_RECONNECT_MS);
-            sleep(Duration::from_millis(SLEEP_BEFORE_RECONNECT_MS)).await;
+            self.notify(ExchangeStatus::Down);
+            info!("Trying reconnection in {}ms", self.reconnect_policy.reconnect_delay.as_millis());
+            self.clock.sleep(self.reconnect_policy.reconnect_delay).await;
+        }
+        Ok(())
+    }
+
+    /// Internal function applying a subscription command to the subscription messages sent
+    /// after each connection.
+    ///
+    /// # Returns
+    ///
+    /// The request to send on the open connection, [None](None) if the channel is already in
+    /// the requested state or the adapter cannot manage channels.
+    fn apply_subscription(&self, channels: &mut Vec<String>, command: &AdapterCommand) -> Option<String> {
+        let (product, request) = match command {
+            AdapterCommand::Subscribe(product) => (product, SubscriptionRequest::Subscribe),
+            AdapterCommand::Unsubscribe(product) => (product, SubscriptionRequest::Unsubscribe),
+            AdapterCommand::Close => return None,
+        };
+        let Some(subscription_formatter) = &self.subscription_formatter else {
+            warn!("Exchange {} cannot manage the channel of {}", self.exchange_code, product);
+            return None;
+        };
+        let subscribe_message = subscription_formatter(product, SubscriptionRequest::Subscribe);
+        let subscribed = channels.contains(&subscribe_message);
+        match request {
+            SubscriptionRequest::Subscribe if !subscribed => channels.push(subscribe_message.clone()),
+            SubscriptionRequest::Unsubscribe if subscribed => channels.retain(|channel| *channel != subscribe_message),
+            _ => return None,
+        }
+        Some(subscription_formatter(product, request))
+    }
+
+    /// Internal function waiting for the [Close](AdapterCommand::Close) command, or the end of the
+    /// commands, while not connected: other commands only change the subscription messages.
+    async fn wait_for_close(&self, command_receiver: &mut mpsc::Receiver<AdapterCommand>, channels: &mut Vec<String>) {
+        while let Some(command) = command_receiver.recv().await {
+            if let AdapterCommand::Close = command {
+                return;
+            }
+            self.apply_subscription(channels, &command);
+        }
+    }
+
+    /// Internal function counting an acknowledged subscription, notifying the connection
+    /// [up](ExchangeStatus::Up) once none is pending.
+    fn acknowledge(&self, pending_acks: &mut usize) {
+        if *pending_acks > 0 {
+            *pending_acks -= 1;
+            info!("Subscription acknowledged by {}, {} pending", self.exchange_code, pending_acks);
+            if *pending_acks == 0 {
+                self.notify(ExchangeStatus::Up);
+            }
+        }
+    }
+
+    /// Internal function counting a message which could not be parsed, notifying the
+    /// [parse failures](ExchangeStatus::ParseFailures) once they reach a threshold.
+    fn count_parse_failure(&self, parse_failures: &mut u64) {
+        *parse_failures += 1;
+        if *parse_failures == PARSE_FAILURES_NOTIFIED {
+            self.notify(ExchangeStatus::ParseFailures { count: *parse_failures });
+        }
+    }
+
+    /// Internal function notifying a change of the connection status, if requested. During a
+    /// maintenance window, failures of the connection are notified as scheduled offline.
+    fn notify(&self, status: ExchangeStatus) {
+        let status = match (&status, self.maintenance.offline_until(&self.exchange_code, SystemTime::now())) {
+            (ExchangeStatus::Down | ExchangeStatus::Ejected | ExchangeStatus::ReconnectsThrottled { .. } | ExchangeStatus::FailedOver { .. }, Some(until)) => {
+                let until_ms = until.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or_default();
+                ExchangeStatus::ScheduledOffline { until_ms }
+            },
+            _ => status,
+        };
+        if let Some(status_sender) = &self.status_sender {
+            // no subscribers is not an error
+            let _ = status_sender.send(ExchangeStatusEvent::new(&self.exchange_code, status));
+        }
+    }
+
+    /// Internal function delivering data downstream, and sampling the depth of the queue.
+    async fn send_data(&self, data_sender: &mpsc::Sender<ExchangeEvent<T>>, data: T) {
+        let data = match &self.data_normalizer {
+            Some(data_normalizer) => data_normalizer(data),
+            None => data,
+        };
+        match data_sender.send(ExchangeEvent::Data(data)).await {
+            Ok(_) => QUEUE_DEPTHS.record_channel("adapter_data", &self.exchange_code, data_sender),
+            Err(_) => error!("Error queueing data"),
+        }
+    }
+
+    /// Internal function polling snapshots from the [REST fallback](RestFallback) after the
+    /// connection to the WebSocket service failed, until it is time to try it again.
+    /// Polling starts with an [ExchangeEvent::Connected](ExchangeEvent::Connected) event.
+    /// Returns true if the adapter was asked to close.
+    #[cfg(feature = "rest")]
+    async fn poll_rest(
+            &self,
+            data_sender: &mpsc::Sender<ExchangeEvent<T>>,
+            command_receiver: &mut mpsc::Receiver<AdapterCommand>,
+            channels: &mut Vec<String>) -> bool {
+        let Some(rest_fallback) = &self.rest_fallback else {
+            return false;
+        };
+        let exchange_code = self.exchange_code.clone();
+        if data_sender.send(ExchangeEvent::Connected(exchange_code.clone())).await.is_err() {
+            error!("Error queueing data");
+        }
+        self.notify(ExchangeStatus::Degraded);
+        let started = self.clock.now();
+        while self.clock.now() - started < rest_fallback.websocket_retry() {
+            if let Some(data) = rest_fallback.poll(&exchange_code).await {
+                self.send_data(data_sender, data).await;
+            }
+            tokio::select! {
+                _ = self.clock.sleep(rest_fallback.poll_interval()) => (),
+                _ = self.wait_for_close(command_receiver, channels) => {
+                    info!("Stopped polling exchange {}", exchange_code);
+                    return true;
+                },
+            }
         }
+        self.notify(ExchangeStatus::Down);
+        false
     }
 
     /// Internal function performing a two step operation to create a functioning
     /// stream from an exchange WebSocket service:
-    /// * Connecting to the WebSocket URL
-    /// * Sending a message to subscribe to the relevant channel
-    /// It panics in case of error.
-    async fn connect(
-            exchange_code: &str,
-            ws_url: String,
-            subscribe_message: String) -> Pin<Box<WebSocketStream<MaybeTlsStream<TcpStream>>>> {
-        info!("Connecting to WebSocket: {}", &ws_url);
-        let (ws, _) = connect_async(ws_url.clone()).await.unwrap_or_else(
-            |_| panic!("Connection error for {}", exchange_code));
-        info!("Subscription '{}'.", subscribe_message);
+    /// * Connecting to the WebSocket URL, returning an error if it fails
+    /// * Sending the messages to subscribe to the relevant channels, if any, issued by the
+    ///   [tracker](RequestTracker) of the requests of the connection
+    ///
+    /// It panics in case of subscription error.
+    async fn connect(&self, ws_url: &str, channels: &[String], requests: &mut RequestTracker) -> Result<Pin<Box<WebSocketStream<MaybeTlsStream<TcpStream>>>>, tungstenite::Error> {
+        info!("Connecting to WebSocket: {}", ws_url);
+        let (ws, _) = connect_async(ws_url).await?;
         let mut pinned_ws = Box::pin(ws);
-        pinned_ws.send(Message::Text(subscribe_message.clone())).await.unwrap_or_else(
-            |_| panic!("Subscription error for {}", subscribe_message));
-        info!("Subscription to {} succeeded.", exchange_code);
-        pinned_ws
+        for subscribe_message in channels {
+            let subscribe_message = requests.issue(subscribe_message);
+            info!("Subscription '{}'.", subscribe_message);
+            pinned_ws.send(Message::Text(subscribe_message.clone())).await.unwrap_or_else(
+                |_| panic!("Subscription error for {}", subscribe_message));
+            info!("Subscription to {} succeeded.", self.exchange_code);
+        }
+        Ok(pinned_ws)
+    }
+}
+
+impl ExchangeAdapter<BookUpdate> {
+    /// Reject the books whose best bid is at or above the best ask, rather than merging them
+    /// into the consolidated book, and resynchronize.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_inverted_book_check(self) -> Self {
+        self.with_data_check(Arc::new(|book_update: &BookUpdate| match book_update.is_inverted() {
+            true => Err(format!("inverted book, best bid {} at or above best ask {}", book_update.bids[0].price, book_update.asks[0].price)),
+            false => Ok(()),
+        }))
+    }
+
+    /// Normalize the prices and amounts of the books, so that equal prices from exchanges
+    /// formatting their decimals differently have a single representation.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ExchangeAdapter](ExchangeAdapter) object.
+    pub fn with_decimal_normalization(self) -> Self {
+        self.with_data_normalizer(Arc::new(BookUpdate::normalize))
+    }
+}
+
+/// Settings applied by the server to the connections of all its [venues](Exchange).
+#[derive(Clone, Default)]
+pub struct ConnectionSettings {
+    /// Capacities of the queues of the connections, if set for the server.
+    pub queue_capacities: Option<QueueCapacities>,
+    /// Whether the parsers are timed, for the [latency budget](crate::latency_budget).
+    pub parse_timing: bool,
+    /// Maintenance windows of the venues, if any.
+    pub maintenance: Option<MaintenanceSchedule>,
+    /// Sender notifying the changes of the status of the venues, if any.
+    pub status_sender: Option<StatusSender>,
+    /// Registry where the subscriptions are leased, with the canonical symbol subscribed, if any.
+    pub subscription_registry: Option<(SubscriptionRegistry, String)>,
+    /// Connection delay added for each venue after the first, the default if not set.
+    pub connection_stagger: Option<Duration>,
+    /// Limits of the messages received, checked before parsing, if set for the server.
+    pub ingest_limits: Option<IngestLimits>,
+    /// Whether the books whose best bid is at or above the best ask are rejected.
+    pub inverted_book_check: bool,
+    /// Budget of the connection attempts shared by the venues, unlimited if [None](None).
+    pub reconnect_budget: Option<ReconnectBudget>,
+}
+
+/// A venue whose books are consolidated by the server. It is implemented by the
+/// [ExchangeAdapter](ExchangeAdapter) of the `WebSocket` services of the exchanges, and can be
+/// implemented by downstream crates for venues of their own, without modifying this crate,
+/// delivering their events through [ExchangeAdapterStream::from_stream](ExchangeAdapterStream::from_stream).
+pub trait Exchange: Send + Sync {
+    /// The code of the exchange, labelling its levels.
+    fn code(&self) -> ExchangeId;
+
+    /// The capabilities of the exchange, reported to clients, unknown by default.
+    fn capabilities(&self) -> ExchangeCapabilities {
+        ExchangeCapabilities::default()
+    }
+
+    /// The endpoint in use, e.g. a `WebSocket` URL, empty by default.
+    fn active_endpoint(&self) -> String {
+        String::new()
+    }
+
+    /// The effective configuration of the venue, reported on start, [None](None) by default.
+    fn config(&self) -> Option<VenueConfig> {
+        None
+    }
+
+    /// Connect to the venue.
+    ///
+    /// # Arguments
+    ///
+    /// * `stagger` - Delay before each connection attempt, so that several venues connecting
+    ///   at the same time can be spread apart.
+    ///
+    /// * `settings` - The [ConnectionSettings](ConnectionSettings) of the server.
+    ///
+    /// # Returns
+    ///
+    /// A future of the [ExchangeAdapterStream](ExchangeAdapterStream) of the book events.
+    fn stream(&self, stagger: Duration, settings: &ConnectionSettings) -> BoxFuture<'static, ExchangeAdapterStream<BookUpdate>>;
+}
+
+impl Exchange for ExchangeAdapter<BookUpdate> {
+    fn code(&self) -> ExchangeId {
+        self.exchange_code.clone()
+    }
+
+    fn capabilities(&self) -> ExchangeCapabilities {
+        self.capabilities
+    }
+
+    fn active_endpoint(&self) -> String {
+        self.endpoints.active_url().to_string()
+    }
+
+    fn config(&self) -> Option<VenueConfig> {
+        Some(ExchangeAdapter::config(self))
+    }
+
+    fn stream(&self, stagger: Duration, settings: &ConnectionSettings) -> BoxFuture<'static, ExchangeAdapterStream<BookUpdate>> {
+        let mut adapter = self.clone().with_parse_timing(self.parse_timing || settings.parse_timing);
+        if let Some(queue_capacities) = settings.queue_capacities {
+            adapter = adapter.with_queue_capacities(queue_capacities);
+        }
+        if let Some(maintenance) = &settings.maintenance {
+            adapter = adapter.with_maintenance(maintenance.clone());
+        }
+        if let Some(status_sender) = &settings.status_sender {
+            adapter = adapter.with_status_sender(status_sender.clone());
+        }
+        if let Some((subscription_registry, symbol)) = &settings.subscription_registry {
+            adapter = adapter.with_subscription_registry(subscription_registry, symbol);
+        }
+        if let Some(ingest_limits) = settings.ingest_limits {
+            adapter = adapter.with_ingest_limits(ingest_limits);
+            if ingest_limits.normalize_decimals {
+                adapter = adapter.with_decimal_normalization();
+            }
+        }
+        if settings.inverted_book_check {
+            adapter = adapter.with_inverted_book_check();
+        }
+        if let Some(reconnect_budget) = &settings.reconnect_budget {
+            adapter = adapter.with_reconnect_budget(reconnect_budget);
+        }
+        Box::pin(async move { adapter.make_staggered_stream(stagger).await })
+    }
+}
+
+/// Factory of the [venue](Exchange) of a currency pair.
+pub type AdapterFactory = Arc<dyn Fn(&CurrencyPair) -> Box<dyn Exchange> + Send + Sync>;
+
+/// Create the [AdapterFactory](AdapterFactory) of an [exchange adapter](ExchangeAdapter).
+///
+/// # Arguments
+///
+/// * `make_adapter` - The function creating the adapter of a currency pair.
+///
+/// # Returns
+///
+/// The [AdapterFactory](AdapterFactory).
+pub fn adapter_factory<F>(make_adapter: F) -> AdapterFactory
+    where F: Fn(&CurrencyPair) -> ExchangeAdapter<BookUpdate> + Send + Sync + 'static {
+    Arc::new(move |product| Box::new(make_adapter(product)))
+}
+
+/// Factories of the [venues](Exchange), by exchange name, so that the venues consolidated
+/// can be chosen at startup from a list of names, e.g. `binance,bitstamp`.
+#[derive(Clone, Default)]
+pub struct Registry {
+    /// The factories, in order of registration.
+    factories: Vec<(ExchangeId, AdapterFactory)>,
+}
+
+impl Registry {
+    /// Create a [Registry](Registry) with the adapters of this crate: the full-depth ones of
+    /// Binance and Bitstamp with the `full-depth` feature, and KuCoin with the `rest` feature.
+    /// An empty registry is created with [default](Registry::default).
+    pub fn builtin() -> Self {
+        #[cfg(not(feature = "full-depth"))]
+        let registry = Self::default()
+            .with_exchange("binance", adapter_factory(crate::binance::make_binance_exchange_adapter))
+            .with_exchange("bitstamp", adapter_factory(crate::bitstamp::make_bitstamp_echange_adapter));
+        #[cfg(feature = "full-depth")]
+        let registry = Self::default()
+            .with_exchange("binance", adapter_factory(crate::binance::make_binance_full_depth_adapter))
+            .with_exchange("bitstamp", adapter_factory(crate::bitstamp::make_bitstamp_full_depth_adapter));
+        let registry = registry
+            .with_exchange("bitfinex", adapter_factory(crate::bitfinex::make_bitfinex_exchange_adapter))
+            .with_exchange("bybit", adapter_factory(crate::bybit::make_bybit_exchange_adapter));
+        #[cfg(feature = "rest")]
+        let registry = registry.with_exchange("kucoin", adapter_factory(crate::kucoin::make_kucoin_exchange_adapter));
+        registry
+            .with_exchange("htx", adapter_factory(crate::htx::make_htx_exchange_adapter))
+            .with_exchange("deribit", adapter_factory(crate::deribit::make_deribit_exchange_adapter))
+            .with_exchange("dydx", adapter_factory(crate::dydx::make_dydx_exchange_adapter))
+    }
+
+    /// Register the factory of an exchange, replacing any previous one with the same name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the exchange.
+    ///
+    /// * `factory` - The [AdapterFactory](AdapterFactory).
+    ///
+    /// # Returns
+    ///
+    /// The modified [Registry](Registry).
+    pub fn with_exchange(mut self, name: impl Into<ExchangeId>, factory: AdapterFactory) -> Self {
+        let name = name.into();
+        match self.factories.iter_mut().find(|(registered, _)| *registered == name) {
+            Some((_, registered)) => *registered = factory,
+            None => self.factories.push((name, factory)),
+        }
+        self
+    }
+
+    /// The names of the exchanges registered, in order of registration.
+    pub fn names(&self) -> Vec<ExchangeId> {
+        self.factories.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    /// Check a comma separated list of exchange names, e.g. `binance,bitstamp`.
+    ///
+    /// # Arguments
+    ///
+    /// * `names` - The list, case insensitive, blanks around the names being ignored.
+    ///
+    /// # Returns
+    ///
+    /// The names registered, without repetitions, or an error listing those not registered.
+    pub fn parse_names(&self, names: &str) -> Result<Vec<ExchangeId>, String> {
+        let mut parsed = vec![];
+        let mut unknown = vec![];
+        for name in names.split(',').map(|name| name.trim().to_lowercase()).filter(|name| !name.is_empty()) {
+            match self.factories.iter().find(|(registered, _)| *registered == name.as_str()) {
+                Some((registered, _)) if !parsed.contains(registered) => parsed.push(registered.clone()),
+                Some(_) => (),
+                None => unknown.push(name),
+            }
+        }
+        if !unknown.is_empty() {
+            return Err(format!("unknown exchanges {}, expected some of {}", unknown.join(", "), self.names().join(", ")));
+        }
+        if parsed.is_empty() {
+            return Err(format!("no exchange in \"{}\", expected some of {}", names, self.names().join(", ")));
+        }
+        Ok(parsed)
+    }
+
+    /// Create the adapters of some exchanges.
+    ///
+    /// # Arguments
+    ///
+    /// * `names` - The names of the exchanges, as returned by [parse_names](Registry::parse_names).
+    ///
+    /// * `product` - The currency pair.
+    ///
+    /// # Returns
+    ///
+    /// The [venues](Exchange), in the order of the names, skipping those not registered.
+    pub fn make_adapters(&self, names: &[ExchangeId], product: &CurrencyPair) -> Vec<Box<dyn Exchange>> {
+        names.iter()
+            .filter_map(|name| self.factories.iter().find(|(registered, _)| registered == name))
+            .map(|(_, factory)| factory(product))
+            .collect()
+    }
+}
+
+/// Manual implementation, since `T` is not required to be [Clone](Clone).
+impl <T: 'static + Send> Clone for ExchangeAdapter<T> {
+    fn clone(&self) -> Self {
+        Self {
+            exchange_code: self.exchange_code.clone(),
+            endpoints: self.endpoints.clone(),
+            subscribe_messages: self.subscribe_messages.clone(),
+            first_request_id: self.first_request_id,
+            subscription_formatter: self.subscription_formatter.clone(),
+            subscription_acks: self.subscription_acks,
+            keepalive: self.keepalive.clone(),
+            bootstrap: self.bootstrap.clone(),
+            frame_decoder: self.frame_decoder.clone(),
+            protocol_reader: self.protocol_reader.clone(),
+            data_check: self.data_check.clone(),
+            data_normalizer: self.data_normalizer.clone(),
+            parse_timing: self.parse_timing,
+            clock: self.clock.clone(),
+            min_update_interval: self.min_update_interval,
+            reconnect_policy: self.reconnect_policy,
+            reconnect_guard: self.reconnect_guard.clone(),
+            subscription_registry: self.subscription_registry.clone(),
+            #[cfg(feature = "chaos")]
+            chaos: self.chaos.clone(),
+            #[cfg(feature = "rest")]
+            rest_fallback: self.rest_fallback.clone(),
+            #[cfg(feature = "rest")]
+            depth_snapshot: self.depth_snapshot.clone(),
+            status_sender: self.status_sender.clone(),
+            maintenance: self.maintenance.clone(),
+            queue_capacities: self.queue_capacities,
+            ingest_limits: self.ingest_limits,
+            capabilities: self.capabilities,
+        }
+    }
+}
+
+/// Internal helper limiting the rate of data delivered by an adapter, holding back
+/// data which arrives too early and keeping only the latest item.
+struct Conflator<T> {
+    /// Minimum interval between two deliveries. No limit if [None](None).
+    min_interval: Option<Duration>,
+    /// Instant of the last delivery.
+    last_delivery: Option<Instant>,
+    /// Latest data held back.
+    pending: Option<T>,
+}
+
+impl <T> Conflator<T> {
+    /// Creates a new [Conflator](Conflator) object.
+    ///
+    /// # Arguments
+    ///
+    /// * `min_interval` - Optional minimum interval between two deliveries.
+    fn new(min_interval: Option<Duration>) -> Self {
+        Self { min_interval, last_delivery: None, pending: None }
+    }
+
+    /// Offer new data for delivery.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The new data.
+    ///
+    /// * `now` - The current instant.
+    ///
+    /// # Returns
+    ///
+    /// The data, if it can be delivered immediately, otherwise [None](None).
+    fn offer(&mut self, data: T, now: Instant) -> Option<T> {
+        match (self.min_interval, self.last_delivery) {
+            (Some(interval), Some(last)) if now - last < interval => {
+                self.pending = Some(data);
+                None
+            },
+            _ => {
+                self.pending = None;
+                self.last_delivery = Some(now);
+                Some(data)
+            }
+        }
+    }
+
+    /// Time left before the data held back can be delivered.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current instant.
+    ///
+    /// # Returns
+    ///
+    /// An optional [Duration](Duration), [None](None) if no data is held back.
+    fn flush_delay(&self, now: Instant) -> Option<Duration> {
+        match (&self.pending, self.min_interval, self.last_delivery) {
+            (Some(_), Some(interval), Some(last)) => Some((last + interval).saturating_duration_since(now)),
+            _ => None,
+        }
+    }
+
+    /// Take the data held back for delivery.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current instant.
+    ///
+    /// # Returns
+    ///
+    /// The data held back, if any.
+    fn take_pending(&mut self, now: Instant) -> Option<T> {
+        let pending = self.pending.take();
+        if pending.is_some() {
+            self.last_delivery = Some(now);
+        }
+        pending
     }
 }
 
 /// Structure representing a connected exchange adapter.
 pub struct ExchangeAdapterStream<T: 'static + Send> {
-    /// Channel receiver for exchange data of type `T`.
-    data_receiver: mpsc::Receiver<T>,
+    /// Channel receiver for exchange events carrying data of type `T`.
+    data_receiver: mpsc::Receiver<ExchangeEvent<T>>,
     /// Channel sender for commands to drive the behaviour of the processing loop in the
     /// [ExchangeAdapter](ExchangeAdapter) object.
     command_sender: mpsc::Sender<AdapterCommand>,
 }
 
 impl <T: 'static + Send> ExchangeAdapterStream<T> {
+    /// Create a stream delivering the events of a venue of its own, e.g. of an [Exchange](Exchange)
+    /// implemented downstream. The events are forwarded until the venue stream ends or is
+    /// [disconnected](ExchangeAdapterStream::disconnect), the subscription commands are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - The [ExchangeEvent](ExchangeEvent) stream of the venue.
+    ///
+    /// * `queue_capacities` - The [QueueCapacities](QueueCapacities) of the events and commands.
+    ///
+    /// # Returns
+    ///
+    /// A [ExchangeAdapterStream](ExchangeAdapterStream) object.
+    pub fn from_stream(mut events: BoxStream<'static, ExchangeEvent<T>>, queue_capacities: QueueCapacities) -> Self {
+        let (data_sender, data_receiver) = mpsc::channel::<ExchangeEvent<T>>(queue_capacities.adapter_data);
+        let (command_sender, mut command_receiver) = mpsc::channel::<AdapterCommand>(queue_capacities.adapter_command);
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    event = events.next() => match event {
+                        Some(event) => if data_sender.send(event).await.is_err() {
+                            break;
+                        },
+                        None => break,
+                    },
+                    command = command_receiver.recv() => match command {
+                        Some(AdapterCommand::Close) | None => break,
+                        Some(_) => (),
+                    },
+                }
+            }
+        });
+        Self {
+            data_receiver,
+            command_sender,
+        }
+    }
+
     /// Disconnect from the exchange.
     pub async fn disconnect(&mut self) {
         match self.command_sender.send(AdapterCommand::Close).await {
@@ -217,10 +1697,36 @@ impl <T: 'static + Send> ExchangeAdapterStream<T> {
             Err(_) => error!("Error queueing command"),
         };
     }
+
+    /// Subscribe to the channel of a currency pair on the open connection, without reconnecting,
+    /// and after each reconnection. Its data is delivered by this stream. Ignored if already
+    /// subscribed, or if the adapter has no [formatter](ExchangeAdapter::with_subscription_formatter).
+    ///
+    /// # Arguments
+    ///
+    /// * `product` - The currency pair.
+    pub async fn subscribe(&mut self, product: &CurrencyPair) {
+        if self.command_sender.send(AdapterCommand::Subscribe(product.clone())).await.is_err() {
+            error!("Error queueing command");
+        }
+    }
+
+    /// Unsubscribe from the channel of a currency pair on the open connection, without
+    /// reconnecting, and stop subscribing to it after each reconnection. Ignored if not subscribed,
+    /// or if the adapter has no [formatter](ExchangeAdapter::with_subscription_formatter).
+    ///
+    /// # Arguments
+    ///
+    /// * `product` - The currency pair.
+    pub async fn unsubscribe(&mut self, product: &CurrencyPair) {
+        if self.command_sender.send(AdapterCommand::Unsubscribe(product.clone())).await.is_err() {
+            error!("Error queueing command");
+        }
+    }
 }
 
 impl <T: 'static + Send> Stream for ExchangeAdapterStream<T> {
-    type Item = T;
+    type Item = ExchangeEvent<T>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         match self.data_receiver.poll_recv(cx) {
@@ -243,22 +1749,28 @@ pub enum ExchangeDataStream<T: 'static + Send> {
 }
 
 impl <T: 'static + Send> ExchangeDataStream<T> {
-    /// Creates a new object from exchange adapters.
+    /// Creates a new object from exchange adapters. Connections are staggered, so that
+    /// exchanges do not (re)connect all at the same time.
     ///
     /// # Arguments
     ///
-    /// `exchange_adapters` - A reference to a [Vector](Vec) of [ExchangeAdapter](ExchangeAdapter) objects.
+    /// `exchange_adapters` - A slice of [ExchangeAdapter](ExchangeAdapter) objects.
     ///
     /// # Returns
     ///
     /// An [ExchangeDataStream](ExchangeDataStream) object.
-    pub async fn new(exchange_adapters: &Vec<ExchangeAdapter<T>>) -> ExchangeDataStream<T> {
-        assert!(!exchange_adapters.is_empty());
+    pub async fn new(exchange_adapters: &[ExchangeAdapter<T>]) -> ExchangeDataStream<T> {
         let mut adapter_streams: Vec<ExchangeAdapterStream<T>> = vec![];
-        for p in exchange_adapters {
-            let c = p.make_stream().await;
+        for (i, p) in exchange_adapters.iter().enumerate() {
+            let c = p.make_staggered_stream(connection_stagger(i, None)).await;
             adapter_streams.push(c);
         }
+        Self::from_streams(adapter_streams)
+    }
+
+    /// Internal function merging the streams of the exchanges.
+    fn from_streams(adapter_streams: Vec<ExchangeAdapterStream<T>>) -> ExchangeDataStream<T> {
+        assert!(!adapter_streams.is_empty());
         if adapter_streams.len() > 1 {
             let mut wrapped_streams = adapter_streams.into_iter().map(
                 |p| Self::ExchangeStream(Box::pin(p))
@@ -274,6 +1786,17 @@ impl <T: 'static + Send> ExchangeDataStream<T> {
         }
     }
 
+    /// Number of exchanges merged. Recursive method.
+    pub fn exchange_count(&self) -> usize {
+        match self {
+            Self::ExchangeStream(_) => 1,
+            Self::CompositeStream(s) => {
+                let (s1, s2) = s.get_ref();
+                s1.exchange_count() + s2.exchange_count()
+            },
+        }
+    }
+
     /// Disconnects all exchange adapters. Asynchronous recursive method.
     pub fn disconnect(self) -> Pin<Box<dyn Future<Output = ()> + Send>> {
         Box::pin(async move {
@@ -291,8 +1814,36 @@ impl <T: 'static + Send> ExchangeDataStream<T> {
     }
 }
 
+impl ExchangeDataStream<BookUpdate> {
+    /// Creates a new object from [venues](Exchange), connected with the settings of the server.
+    /// Connections are staggered, so that venues do not (re)connect all at the same time.
+    ///
+    /// # Arguments
+    ///
+    /// `exchanges` - A slice of [Exchange](Exchange) objects.
+    ///
+    /// `settings` - The [ConnectionSettings](ConnectionSettings).
+    ///
+    /// # Returns
+    ///
+    /// An [ExchangeDataStream](ExchangeDataStream) object.
+    pub async fn from_exchanges(exchanges: &[Box<dyn Exchange>], settings: &ConnectionSettings) -> ExchangeDataStream<BookUpdate> {
+        let mut exchange_streams = vec![];
+        for (i, exchange) in exchanges.iter().enumerate() {
+            exchange_streams.push(exchange.stream(connection_stagger(i, settings.connection_stagger), settings).await);
+        }
+        Self::from_streams(exchange_streams)
+    }
+}
+
+/// Internal function computing the connection delay of the exchange at an index, given the
+/// delay added for each exchange, the default if not set.
+fn connection_stagger(index: usize, stagger: Option<Duration>) -> Duration {
+    stagger.unwrap_or(Duration::from_millis(DEFAULT_CONNECTION_STAGGER_MS)) * index as u32
+}
+
 impl <T: 'static + Send> Stream for ExchangeDataStream<T> {
-    type Item = T;
+    type Item = ExchangeEvent<T>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         match self.get_mut() {
@@ -303,3 +1854,78 @@ impl <T: 'static + Send> Stream for ExchangeDataStream<T> {
         }
     }
 }
+
+/// Extract a readable description from a failed task.
+fn panic_message(join_error: JoinError) -> String {
+    if join_error.is_panic() {
+        let payload: Box<dyn Any + Send> = join_error.into_panic();
+        if let Some(message) = payload.downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = payload.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "unknown panic".to_string()
+        }
+    } else {
+        join_error.to_string()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conflator_without_interval() {
+        let mut conflator = Conflator::new(None);
+        let now = Instant::now();
+        assert_eq!(conflator.offer(1, now), Some(1));
+        assert_eq!(conflator.offer(2, now), Some(2));
+        assert_eq!(conflator.flush_delay(now), None);
+    }
+
+    #[test]
+    fn test_conflator_with_interval() {
+        let interval = Duration::from_millis(250);
+        let mut conflator = Conflator::new(Some(interval));
+        let start = Instant::now();
+        assert_eq!(conflator.offer(1, start), Some(1));
+        assert_eq!(conflator.flush_delay(start), None);
+        assert_eq!(conflator.offer(2, start + Duration::from_millis(100)), None);
+        assert_eq!(conflator.offer(3, start + Duration::from_millis(200)), None);
+        assert_eq!(conflator.flush_delay(start + Duration::from_millis(200)), Some(Duration::from_millis(50)));
+        assert_eq!(conflator.take_pending(start + interval), Some(3));
+        assert_eq!(conflator.flush_delay(start + interval), None);
+        assert_eq!(conflator.offer(4, start + Duration::from_millis(400)), None);
+        assert_eq!(conflator.offer(5, start + Duration::from_millis(500)), Some(5));
+        assert_eq!(conflator.flush_delay(start + Duration::from_millis(500)), None);
+    }
+
+    #[test]
+    fn test_connection_stagger() {
+        assert_eq!(connection_stagger(0, None), Duration::ZERO);
+        assert_eq!(connection_stagger(2, None), Duration::from_millis(2 * DEFAULT_CONNECTION_STAGGER_MS));
+        assert_eq!(connection_stagger(3, Some(Duration::from_millis(100))), Duration::from_millis(300));
+        assert_eq!(connection_stagger(3, Some(Duration::ZERO)), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_registry() {
+        let registry = Registry::builtin();
+        let product = CurrencyPair { main: "ETH".to_string(), counter: "BTC".to_string() };
+        for name in registry.names() {
+            assert_eq!(registry.make_adapters(std::slice::from_ref(&name), &product)[0].code(), name);
+        }
+        let names = registry.parse_names(" Bitstamp,binance ,,bitstamp").unwrap();
+        assert_eq!(names, vec!["bitstamp", "binance"]);
+        let codes: Vec<ExchangeId> = registry.make_adapters(&names, &product).iter().map(|adapter| adapter.code()).collect();
+        assert_eq!(codes, names);
+        let error = registry.parse_names("binance,kraken,ftx").unwrap_err();
+        assert!(error.starts_with("unknown exchanges kraken, ftx, expected some of binance, bitstamp,"), "{}", error);
+        assert!(registry.parse_names(" , ").is_err());
+
+        let custom = Registry::default().with_exchange("kraken", adapter_factory(crate::bitfinex::make_bitfinex_exchange_adapter));
+        assert_eq!(custom.parse_names("kraken").unwrap(), vec!["kraken"]);
+    }
+}
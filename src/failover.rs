@@ -0,0 +1,221 @@
+//! Failover of the [exchange adapters](crate::exchange::ExchangeAdapter) between several
+//! WebSocket endpoints of an exchange, e.g. the clusters of Binance. Before each connection
+//! the endpoints are probed, timing the opening of a TCP connection, and the first one in
+//! order of preference which is reachable and not degraded is chosen. When the connection
+//! to it fails, the next one is tried. The endpoint in use is shared by the clones of the
+//! adapter, and each change is notified as [FailedOver](crate::status::ExchangeStatus::FailedOver).
+
+use futures::future::join_all;
+use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+use tokio::{net::TcpStream, time::{timeout, Duration, Instant}};
+use tokio_tungstenite::tungstenite::http::Uri;
+
+
+/// Default maximum time to open a TCP connection to an endpoint, before it is deemed unreachable.
+const DEFAULT_PROBE_TIMEOUT_MS: u64 = 2_000;
+/// Default time to open a TCP connection above which an endpoint is deemed degraded.
+const DEFAULT_MAX_LATENCY_MS: u64 = 500;
+
+
+/// Thresholds of the probes of the endpoints.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FailoverPolicy {
+    /// Maximum time to open a TCP connection, before the endpoint is deemed unreachable.
+    pub probe_timeout: Duration,
+    /// Time to open a TCP connection above which the endpoint is deemed degraded.
+    pub max_latency: Duration,
+}
+
+impl Default for FailoverPolicy {
+    fn default() -> Self {
+        Self {
+            probe_timeout: Duration::from_millis(DEFAULT_PROBE_TIMEOUT_MS),
+            max_latency: Duration::from_millis(DEFAULT_MAX_LATENCY_MS),
+        }
+    }
+}
+
+/// WebSocket endpoints of an exchange, in order of preference, the first one being the
+/// primary. Clones share the endpoint in use.
+#[derive(Clone, Debug)]
+pub struct Endpoints {
+    /// WebSocket URLs.
+    urls: Vec<String>,
+    /// Thresholds of the probes.
+    policy: FailoverPolicy,
+    /// Index of the endpoint in use.
+    active: Arc<AtomicUsize>,
+}
+
+impl Endpoints {
+    /// Create a new [Endpoints](Endpoints) object, with the primary endpoint only and the
+    /// default [FailoverPolicy](FailoverPolicy).
+    ///
+    /// # Arguments
+    ///
+    /// * `primary` - WebSocket URL of the primary endpoint.
+    pub fn new(primary: String) -> Self {
+        Self { urls: vec![primary], policy: FailoverPolicy::default(), active: Arc::new(AtomicUsize::new(0)) }
+    }
+
+    /// Add an endpoint, less preferred than the previous ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - WebSocket URL.
+    ///
+    /// # Returns
+    ///
+    /// The modified [Endpoints](Endpoints) object.
+    pub fn with_url(mut self, url: String) -> Self {
+        self.urls.push(url);
+        self
+    }
+
+    /// Replace the thresholds of the probes.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - A [FailoverPolicy](FailoverPolicy).
+    ///
+    /// # Returns
+    ///
+    /// The modified [Endpoints](Endpoints) object.
+    pub fn with_policy(mut self, policy: FailoverPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// WebSocket URLs, in order of preference.
+    pub fn urls(&self) -> &[String] {
+        &self.urls
+    }
+
+    /// Number of endpoints.
+    pub fn len(&self) -> usize {
+        self.urls.len()
+    }
+
+    /// Whether there are no endpoints, never true.
+    pub fn is_empty(&self) -> bool {
+        self.urls.is_empty()
+    }
+
+    /// WebSocket URL of the endpoint in use.
+    pub fn active_url(&self) -> &str {
+        &self.urls[self.active.load(Ordering::Relaxed)]
+    }
+
+    /// Choose the endpoint of the next connection, probing all of them if there are several.
+    ///
+    /// # Arguments
+    ///
+    /// * `failed` - Whether the connection to each endpoint failed since the last successful one:
+    ///   those endpoints are skipped.
+    ///
+    /// # Returns
+    ///
+    /// The index of the endpoint, and whether it is different from the one in use until then.
+    pub async fn select(&self, failed: &[bool]) -> (usize, bool) {
+        let chosen = if self.urls.len() > 1 {
+            let latencies = join_all(self.urls.iter().map(|url| probe(url, self.policy.probe_timeout))).await;
+            choose(&latencies, failed, self.policy.max_latency)
+        } else {
+            0
+        };
+        (chosen, self.active.swap(chosen, Ordering::Relaxed) != chosen)
+    }
+}
+
+/// Time to open a TCP connection to the host of a WebSocket URL.
+///
+/// # Arguments
+///
+/// * `url` - WebSocket URL, with the port implied by the scheme if missing.
+///
+/// * `probe_timeout` - Maximum time to open the connection.
+///
+/// # Returns
+///
+/// The latency, or [None](None) if the URL is invalid or the host cannot be reached in time.
+pub async fn probe(url: &str, probe_timeout: Duration) -> Option<Duration> {
+    let uri: Uri = url.parse().ok()?;
+    let host = uri.host()?;
+    let port = uri.port_u16().unwrap_or(if uri.scheme_str() == Some("wss") { 443 } else { 80 });
+    let started = Instant::now();
+    match timeout(probe_timeout, TcpStream::connect((host, port))).await {
+        Ok(Ok(_)) => Some(started.elapsed()),
+        _ => None,
+    }
+}
+
+/// Choose an endpoint from the latencies of the probes: the first one, in order of preference,
+/// reachable within the maximum latency, or else the fastest reachable one, or else the first one.
+/// Failed endpoints are skipped, unless they all failed.
+///
+/// # Arguments
+///
+/// * `latencies` - The latency of each endpoint, [None](None) if unreachable.
+///
+/// * `failed` - Whether the connection to each endpoint failed.
+///
+/// * `max_latency` - Latency above which an endpoint is degraded.
+///
+/// # Returns
+///
+/// The index of the endpoint.
+pub fn choose(latencies: &[Option<Duration>], failed: &[bool], max_latency: Duration) -> usize {
+    let candidates: Vec<(usize, Option<Duration>)> = latencies.iter().copied().enumerate()
+        .filter(|(index, _)| !failed.get(*index).copied().unwrap_or(false))
+        .collect();
+    candidates.iter()
+        .find(|(_, latency)| latency.is_some_and(|latency| latency <= max_latency))
+        .or_else(|| candidates.iter().filter(|(_, latency)| latency.is_some()).min_by_key(|(_, latency)| *latency))
+        .or_else(|| candidates.first())
+        .map(|(index, _)| *index)
+        .unwrap_or_default()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    fn ms(millis: u64) -> Option<Duration> {
+        Some(Duration::from_millis(millis))
+    }
+
+    #[test]
+    fn test_choose() {
+        let max_latency = Duration::from_millis(100);
+        assert_eq!(choose(&[ms(50), ms(10)], &[false, false], max_latency), 0);
+        // primary degraded
+        assert_eq!(choose(&[ms(300), ms(80), ms(10)], &[false, false, false], max_latency), 1);
+        // primary unreachable
+        assert_eq!(choose(&[None, ms(80)], &[false, false], max_latency), 1);
+        // all degraded, the fastest one
+        assert_eq!(choose(&[ms(300), ms(200), None], &[false, false, false], max_latency), 1);
+        // failed endpoints skipped
+        assert_eq!(choose(&[ms(50), ms(300), None], &[true, false, false], max_latency), 1);
+        assert_eq!(choose(&[None, None, None], &[true, false, false], max_latency), 1);
+        assert_eq!(choose(&[ms(50), ms(50)], &[true, true], max_latency), 0);
+    }
+
+    #[tokio::test]
+    async fn test_select() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let reachable = format!("ws://{}/ws", listener.local_addr().unwrap());
+        let unreachable = {
+            let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            format!("ws://{}/ws", closed.local_addr().unwrap())
+        };
+        let endpoints = Endpoints::new(unreachable.clone()).with_url(reachable.clone());
+        assert_eq!(endpoints.active_url(), unreachable);
+        assert!(probe(&reachable, Duration::from_secs(1)).await.is_some());
+        assert_eq!(probe(&unreachable, Duration::from_secs(1)).await, None);
+        assert_eq!(endpoints.clone().select(&[false, false]).await, (1, true));
+        assert_eq!(endpoints.active_url(), reachable);
+        assert_eq!(endpoints.select(&[false, true]).await, (0, true));
+    }
+}
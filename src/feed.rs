@@ -0,0 +1,354 @@
+//! Server-wide consolidated feed: a [BookSummaryService](BookSummaryService) running in
+//! the background, independently of client streams, whose latest [summary](Summary) is
+//! shared with consumers such as sinks, and optionally published on the [event bus](EventBus).
+
+use futures::StreamExt;
+use log::info;
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+use crate::aggregator::AggregateBook;
+use crate::event_bus::EventBus;
+use crate::metrics::{BEST_ASKS, BEST_BIDS, SPREADS_BPS, VENUE_BEST_ASKS, VENUE_BEST_BIDS};
+use crate::numbers::{CanonicalNumber, NumberFormat};
+use crate::scheduling::spawn_ingest;
+use crate::service::BookSummaryService;
+use crate::orderbook::{Level, Summary};
+
+
+/// Receiver of the latest [summary](Summary) of the feed, if any was produced.
+pub type FeedReceiver = watch::Receiver<Option<Summary>>;
+
+/// Receiver of the [aggregate book](AggregateBook) reflected in the latest summary of the feed,
+/// if any was produced.
+pub type BookReceiver = watch::Receiver<Option<Arc<AggregateBook>>>;
+
+
+/// Run a service in a background [ingest task](spawn_ingest), sharing its latest summary.
+///
+/// # Arguments
+///
+/// * `service` - The [BookSummaryService](BookSummaryService) producing the summaries.
+///
+/// # Returns
+///
+/// A [FeedReceiver](FeedReceiver). The service is disconnected once all the receivers are dropped.
+pub fn spawn_feed(service: BookSummaryService) -> FeedReceiver {
+    spawn_feeds(service).0
+}
+
+/// Run a service in a background task, sharing its latest summary and aggregate book.
+///
+/// # Arguments
+///
+/// * `service` - The [BookSummaryService](BookSummaryService) producing the summaries.
+///
+/// # Returns
+///
+/// A [FeedReceiver](FeedReceiver) and a [BookReceiver](BookReceiver). The service is disconnected
+/// once all the receivers of both kinds are dropped.
+pub fn spawn_feeds(service: BookSummaryService) -> (FeedReceiver, BookReceiver) {
+    spawn_feeds_with(service, None)
+}
+
+/// Run a service in a background task, sharing its latest summary and aggregate book, and
+/// publishing each summary on an event bus.
+///
+/// # Arguments
+///
+/// * `service` - The [BookSummaryService](BookSummaryService) producing the summaries.
+///
+/// * `bus` - The [EventBus](EventBus) where the summaries are published.
+///
+/// # Returns
+///
+/// A [FeedReceiver](FeedReceiver) and a [BookReceiver](BookReceiver). The service is disconnected
+/// once all the receivers of both kinds are dropped.
+pub fn spawn_bus_feeds(service: BookSummaryService, bus: EventBus) -> (FeedReceiver, BookReceiver) {
+    spawn_feeds_with(service, Some(bus))
+}
+
+/// Internal function running a service in a background task, publishing its summaries on an
+/// event bus if any.
+fn spawn_feeds_with(mut service: BookSummaryService, bus: Option<EventBus>) -> (FeedReceiver, BookReceiver) {
+    let (sender, receiver) = watch::channel(None);
+    let (book_sender, book_receiver) = watch::channel(None);
+    spawn_ingest(async move {
+        while let Some(summary) = service.next().await {
+            if sender.is_closed() && book_sender.is_closed() {
+                break;
+            }
+            if let Some(bus) = &bus {
+                bus.publish_summary(summary.clone());
+            }
+            sender.send_replace(Some(summary));
+            book_sender.send_replace(Some(Arc::new(service.aggregate_book().clone())));
+        }
+        info!("Feed stopped");
+        service.disconnect().await;
+    });
+    (receiver, book_receiver)
+}
+
+/// Top of book and depth figures extracted from a [summary](Summary), as stored by sinks.
+/// Missing values are represented as `NaN` prices and empty exchange codes.
+#[derive(PartialEq, Debug, Clone)]
+pub struct SummaryMetrics {
+    /// Best bid price.
+    pub best_bid: f64,
+    /// Exchange offering the best bid.
+    pub best_bid_exchange: String,
+    /// Best ask price.
+    pub best_ask: f64,
+    /// Exchange offering the best ask.
+    pub best_ask_exchange: String,
+    /// Mid price.
+    pub mid: f64,
+    /// Difference between best ask and best bid.
+    pub spread: f64,
+    /// Total amount of the published bid levels.
+    pub bid_depth: f64,
+    /// Total amount of the published ask levels.
+    pub ask_depth: f64,
+}
+
+impl From<&Summary> for SummaryMetrics {
+    fn from(value: &Summary) -> Self {
+        let best_bid = value.bids.first();
+        let best_ask = value.asks.first();
+        let best_bid_price = best_bid.map(|l| l.price).unwrap_or(f64::NAN);
+        let best_ask_price = best_ask.map(|l| l.price).unwrap_or(f64::NAN);
+        Self {
+            best_bid: best_bid_price,
+            best_bid_exchange: best_bid.map(|l| l.exchange.clone()).unwrap_or_default(),
+            best_ask: best_ask_price,
+            best_ask_exchange: best_ask.map(|l| l.exchange.clone()).unwrap_or_default(),
+            mid: (best_bid_price + best_ask_price) / 2.0,
+            spread: value.spread,
+            bid_depth: value.bids.iter().map(|l| l.amount).sum(),
+            ask_depth: value.asks.iter().map(|l| l.amount).sum(),
+        }
+    }
+}
+
+/// Best bid and offer of an exchange.
+#[derive(PartialEq, Debug, Clone)]
+pub struct VenueBbo {
+    /// Exchange code.
+    pub exchange: String,
+    /// Best bid price, `NaN` if not available.
+    pub best_bid: f64,
+    /// Best ask price, `NaN` if not available.
+    pub best_ask: f64,
+}
+
+/// Extract the best bid and offer of each exchange contributing to a summary.
+///
+/// # Arguments
+///
+/// * `summary` - A [Summary](Summary).
+///
+/// # Returns
+///
+/// A [vector](Vec) of [VenueBbo](VenueBbo), in order of first appearance.
+pub fn venue_bbos(summary: &Summary) -> Vec<VenueBbo> {
+    let mut result: Vec<VenueBbo> = vec![];
+    for (level, is_bid) in summary.bids.iter().map(|l| (l, true)).chain(summary.asks.iter().map(|l| (l, false))) {
+        let index = match result.iter().position(|v| v.exchange == level.exchange) {
+            Some(index) => index,
+            None => {
+                result.push(VenueBbo { exchange: level.exchange.clone(), best_bid: f64::NAN, best_ask: f64::NAN });
+                result.len() - 1
+            }
+        };
+        let venue = &mut result[index];
+        if is_bid && venue.best_bid.is_nan() {
+            venue.best_bid = level.price;
+        } else if !is_bid && venue.best_ask.is_nan() {
+            venue.best_ask = level.price;
+        }
+    }
+    result
+}
+
+/// Set the [market gauges](crate::metrics::MarketGauge) of the symbol of a published summary:
+/// consolidated best bid, best ask and spread in basis points, `NaN` if a side is empty, and
+/// best bid and ask of each exchange among the published levels.
+///
+/// # Arguments
+///
+/// * `summary` - A [Summary](Summary).
+pub fn record_market_gauges(summary: &Summary) {
+    let metrics = SummaryMetrics::from(summary);
+    BEST_BIDS.set(&summary.symbol, metrics.best_bid);
+    BEST_ASKS.set(&summary.symbol, metrics.best_ask);
+    SPREADS_BPS.set(&summary.symbol, (metrics.best_ask - metrics.best_bid) / metrics.mid * 10_000.0);
+    let bbos = venue_bbos(summary);
+    let exchange_values = |price: fn(&VenueBbo) -> f64| bbos.iter()
+        .filter(|bbo| !price(bbo).is_nan())
+        .map(|bbo| (bbo.exchange.clone(), price(bbo)))
+        .collect();
+    VENUE_BEST_BIDS.set_exchanges(&summary.symbol, exchange_values(|bbo| bbo.best_bid));
+    VENUE_BEST_ASKS.set_exchanges(&summary.symbol, exchange_values(|bbo| bbo.best_ask));
+}
+
+/// Compact JSON representation of a [summary](Summary).
+/// Levels are serialized as `[exchange, price, amount]` arrays, numbers in their
+/// [canonical form](crate::numbers).
+#[derive(Serialize, PartialEq, Debug)]
+pub struct CompactSummary<'a> {
+    /// Canonical symbol.
+    pub s: &'a str,
+    /// Spread, `null` if not available.
+    pub sp: Option<CanonicalNumber>,
+    /// Bid levels.
+    pub b: Vec<(&'a str, Option<CanonicalNumber>, Option<CanonicalNumber>)>,
+    /// Ask levels.
+    pub a: Vec<(&'a str, Option<CanonicalNumber>, Option<CanonicalNumber>)>,
+}
+
+impl<'a> CompactSummary<'a> {
+    /// Create a new [CompactSummary](CompactSummary) object.
+    ///
+    /// # Arguments
+    ///
+    /// * `summary` - A [Summary](Summary).
+    ///
+    /// * `number_format` - The [NumberFormat](NumberFormat) of the prices, amounts and spread.
+    pub fn new(summary: &'a Summary, number_format: &NumberFormat) -> Self {
+        let format = |value: f64| number_format.format(&summary.symbol, value);
+        let levels = |levels: &'a [Level]| levels.iter()
+            .map(|l| (l.exchange.as_str(), format(l.price), format(l.amount)))
+            .collect();
+        Self {
+            s: &summary.symbol,
+            sp: format(summary.spread),
+            b: levels(&summary.bids),
+            a: levels(&summary.asks),
+        }
+    }
+}
+
+impl<'a> From<&'a Summary> for CompactSummary<'a> {
+    fn from(value: &'a Summary) -> Self {
+        Self::new(value, &NumberFormat::default())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn venue_level(exchange: &str, price: f64) -> Level {
+        Level { exchange: exchange.to_string(), price, amount: 1.0, order_count: None, latency_class: 0 }
+    }
+
+    #[test]
+    fn test_summary_metrics() {
+        let summary = Summary {
+            spread: 1.0,
+            bids: vec![
+                Level { exchange: "test1".to_string(), price: 99.0, amount: 1.0, order_count: None, latency_class: 0 },
+                Level { exchange: "test2".to_string(), price: 98.0, amount: 2.0, order_count: None, latency_class: 0 },
+            ],
+            asks: vec![Level { exchange: "test2".to_string(), price: 100.0, amount: 3.0, order_count: None, latency_class: 0 }],
+            symbol: "ETH-BTC".to_string(),
+            depth: vec![],
+            ..Default::default()
+        };
+        let metrics = SummaryMetrics::from(&summary);
+        assert_eq!(metrics, SummaryMetrics {
+            best_bid: 99.0,
+            best_bid_exchange: "test1".to_string(),
+            best_ask: 100.0,
+            best_ask_exchange: "test2".to_string(),
+            mid: 99.5,
+            spread: 1.0,
+            bid_depth: 3.0,
+            ask_depth: 3.0,
+        });
+    }
+
+    #[test]
+    fn test_venue_bbos() {
+        let summary = Summary {
+            spread: 1.0,
+            bids: vec![venue_level("test1", 99.0), venue_level("test1", 98.5), venue_level("test2", 98.0)],
+            asks: vec![venue_level("test2", 100.0), venue_level("test3", 100.5), venue_level("test2", 101.0)],
+            symbol: "ETH-BTC".to_string(),
+            depth: vec![],
+            ..Default::default()
+        };
+        let bbos = venue_bbos(&summary);
+        assert_eq!(bbos.len(), 3);
+        assert_eq!((bbos[0].exchange.as_str(), bbos[0].best_bid), ("test1", 99.0));
+        assert!(bbos[0].best_ask.is_nan());
+        assert_eq!((bbos[1].exchange.as_str(), bbos[1].best_bid, bbos[1].best_ask), ("test2", 98.0, 100.0));
+        assert!(bbos[2].best_bid.is_nan());
+        assert_eq!((bbos[2].exchange.as_str(), bbos[2].best_ask), ("test3", 100.5));
+    }
+
+    #[test]
+    fn test_record_market_gauges() {
+        // a symbol of its own, the gauges being process-wide
+        let symbol = "FEED-TEST";
+        let summary = Summary {
+            spread: 1.0,
+            bids: vec![venue_level("test1", 99.0), venue_level("test2", 98.0)],
+            asks: vec![venue_level("test2", 100.0)],
+            symbol: symbol.to_string(),
+            ..Default::default()
+        };
+        record_market_gauges(&summary);
+        assert_eq!((BEST_BIDS.get(symbol, None), BEST_ASKS.get(symbol, None)), (Some(99.0), Some(100.0)));
+        assert!((SPREADS_BPS.get(symbol, None).unwrap() - 1.0 / 99.5 * 10_000.0).abs() < 1e-9);
+        assert_eq!((VENUE_BEST_BIDS.get(symbol, Some("test1")), VENUE_BEST_BIDS.get(symbol, Some("test2"))), (Some(99.0), Some(98.0)));
+        assert_eq!((VENUE_BEST_ASKS.get(symbol, Some("test1")), VENUE_BEST_ASKS.get(symbol, Some("test2"))), (None, Some(100.0)));
+
+        record_market_gauges(&Summary { symbol: symbol.to_string(), ..Default::default() });
+        assert!(BEST_BIDS.get(symbol, None).unwrap().is_nan() && SPREADS_BPS.get(symbol, None).unwrap().is_nan());
+        assert_eq!(VENUE_BEST_BIDS.get(symbol, Some("test1")), None);
+    }
+
+    #[test]
+    fn test_summary_metrics_empty_book() {
+        let summary = Summary { spread: f64::NAN, bids: vec![], asks: vec![], symbol: "ETH-BTC".to_string(), depth: vec![], ..Default::default() };
+        let metrics = SummaryMetrics::from(&summary);
+        assert!(metrics.best_bid.is_nan() && metrics.best_ask.is_nan() && metrics.mid.is_nan());
+        assert_eq!(metrics.best_bid_exchange, "");
+        assert_eq!(metrics.bid_depth, 0.0);
+    }
+
+    #[test]
+    fn test_compact_summary() {
+        let summary = Summary {
+            spread: f64::NAN,
+            bids: vec![Level { exchange: "test1".to_string(), price: 99.0, amount: 1.5, order_count: None, latency_class: 0 }],
+            asks: vec![],
+            symbol: "ETH-BTC".to_string(),
+            depth: vec![],
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&CompactSummary::from(&summary)).unwrap();
+        assert_eq!(json, r#"{"s":"ETH-BTC","sp":null,"b":[["test1",99,1.5]],"a":[]}"#);
+    }
+
+    #[test]
+    fn test_compact_summary_number_format() {
+        let summary = Summary {
+            spread: 0.00000105,
+            bids: vec![Level { exchange: "test1".to_string(), price: 0.0708149, amount: 1e-7, order_count: None, latency_class: 0 }],
+            asks: vec![],
+            symbol: "ETH-BTC".to_string(),
+            depth: vec![],
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&CompactSummary::from(&summary)).unwrap();
+        assert_eq!(json, r#"{"s":"ETH-BTC","sp":0.00000105,"b":[["test1",0.0708149,0.0000001]],"a":[]}"#);
+        let number_format = NumberFormat::default().with_symbol_max_decimals("ETH-BTC", 5);
+        let json = serde_json::to_string(&CompactSummary::new(&summary, &number_format)).unwrap();
+        assert_eq!(json, r#"{"s":"ETH-BTC","sp":0,"b":[["test1",0.07081,0]],"a":[]}"#);
+    }
+}
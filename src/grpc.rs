@@ -0,0 +1,1526 @@
+//! Protobuf RPC server for continuously updated snapshots of a trading book
+//! consolidated from multiple exchanges.
+
+use log::info;
+use futures::{future::join_all, stream::{self, select_all}, Stream};
+use rust_decimal::{Decimal, prelude::{FromPrimitive, ToPrimitive}};
+use std::{collections::{BTreeMap, BTreeSet}, path::Path, pin::Pin, net, str::FromStr, sync::{Arc, OnceLock}};
+use tokio::{sync::{broadcast, mpsc, OnceCell}, time::{timeout, timeout_at, Duration, Instant}};
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tonic::{codec::CompressionEncoding, metadata::MetadataValue, transport::Server, Request, Response, Status, Streaming};
+
+use crate::orderbook::{BookSummaryRequest, Summary, SummaryBatch, StreamStatusReason, SubscriptionAction, SubscriptionControl, Empty, Configuration, ServerInfo, PingRequest, PingResponse, SymbolList, SymbolInfo, SymbolVenue, ExchangeList, ExchangeInfo, BookDiffLogRequest, BookDiffLogStatus, QuoteShare, VenueQuoteShare, TopOfBookEvent, UsageRequest, UsageReport, ClientUsage, SweepPriceRequest, SweepPrice, RouteRequest, RouteSuggestion, VenueAllocation, Alert, Volatility, HorizonVolatility, BookSide, orderbook_aggregator_server::{OrderbookAggregator, OrderbookAggregatorServer}};
+
+use crate::orderbook::summary::Frame;
+
+use crate::accounting::{StreamUsage, UsageRegistry, ANONYMOUS_API_KEY, API_KEY_METADATA};
+use crate::allocator::ALLOCATOR_NAME;
+use crate::adaptive_depth::AdaptiveDepth;
+use crate::aggregator::AggregateBook;
+use crate::analytics::FairValueMethod;
+use crate::book_diff;
+use crate::clock::system_clock;
+use crate::core::{CurrencyPair, ExchangeId, Side, NUM_LEVELS};
+use crate::effective_config::{enabled_features, EffectiveConfig};
+use crate::exchange::{ConnectionSettings, Exchange, ExchangeDataStream};
+use crate::alerts::{AlertEngine, AlertEvent, AlertsConfig};
+use crate::event_bus::EventBus;
+use crate::ingest::IngestLimits;
+use crate::feed::{spawn_bus_feeds, BookReceiver, FeedReceiver};
+use crate::latency_budget;
+use crate::maintenance::MaintenanceSchedule;
+use crate::multiplex::SummaryMultiplexer;
+use crate::pause::PauseBuffer;
+use crate::reconnect::ReconnectBudget;
+use crate::routing::Router;
+use crate::staleness::StalenessThresholds;
+use crate::service::BookSummaryService;
+use crate::proto_ext::{Timestamp, TimestampExt};
+use crate::shadow::{spawn_shadow, ShadowConfig};
+use crate::status::{spawn_status_board, ExchangeStatus, ExchangeStatusEvent, StatusBoard};
+use crate::subscriptions::SubscriptionRegistry;
+use crate::summary_fields::SummaryFields;
+use crate::summary_log::{SummaryLogSampling, DEFAULT_SUMMARY_LOG_INTERVAL_MS};
+use crate::symbol_groups::SymbolGroups;
+use crate::symbols::{canonical_symbol, canonicalize};
+#[cfg(feature = "webhook")]
+use crate::status::spawn_webhook;
+#[cfg(feature = "webhook")]
+use crate::webhook::Webhook;
+use crate::top_of_book::TopOfBookEventStream;
+use crate::metrics::QUEUE_DEPTHS;
+use crate::numbers::FloatRounding;
+use crate::queues::QueueCapacities;
+use crate::volatility::{spawn_volatility, VolatilityReceiver};
+use crate::quote_share::{spawn_quote_share, QuoteShareReceiver};
+
+type ResponseStream = Pin<Box<dyn Stream<Item = Result<Summary, Status>> + Send>>;
+type SummaryResult = Result<Response<ResponseStream>, Status>;
+type TopOfBookResponseStream = Pin<Box<dyn Stream<Item = Result<TopOfBookEvent, Status>> + Send>>;
+type TopOfBookResult = Result<Response<TopOfBookResponseStream>, Status>;
+type AlertResponseStream = Pin<Box<dyn Stream<Item = Result<Alert, Status>> + Send>>;
+type VolatilityResponseStream = Pin<Box<dyn Stream<Item = Result<Volatility, Status>> + Send>>;
+
+
+/// Default grace period, in seconds, during which the last known summary of a symbol is published
+/// once all its exchanges are disconnected.
+pub const DEFAULT_STALE_GRACE_S: u64 = 30;
+/// Default distances from the mid price, in basis points, for which the total depth is published.
+pub const DEFAULT_DEPTH_BANDS_BPS: [u32; 3] = [10, 50, 100];
+/// Default horizons of the volatility estimates of the mid price, in milliseconds.
+pub const DEFAULT_VOLATILITY_HORIZONS_MS: [u64; 3] = [1_000, 10_000, 60_000];
+/// Default rate at which published summaries are logged.
+const DEFAULT_SUMMARY_LOG_SAMPLING: SummaryLogSampling = SummaryLogSampling::Interval(Duration::from_millis(DEFAULT_SUMMARY_LOG_INTERVAL_MS));
+/// Maximum wait for the first aggregate book of a feed, when answering queries.
+const FEED_WARMUP_TIMEOUT: Duration = Duration::from_secs(5);
+/// Request metadata asking for the current summary of the first symbol selected as the first message
+/// of a [BookSummary](OrderbookAggregator::book_summary) stream, with value `true`.
+pub const SNAPSHOT_METADATA: &str = "x-snapshot-on-subscribe";
+/// Request metadata restricting the optional fields of the summaries of a
+/// [BookSummary](OrderbookAggregator::book_summary) stream, as a comma separated list of
+/// [field names](SummaryFields::parse), e.g. `spread,venues`.
+pub const SUMMARY_FIELDS_METADATA: &str = "x-summary-fields";
+/// Request metadata restricting a [BookSummary](OrderbookAggregator::book_summary) stream to the
+/// symbols served matching a comma separated list of [symbols, groups and patterns](SymbolGroups::select),
+/// e.g. `majors,*-USDT`, each summary carrying its symbol.
+pub const SYMBOLS_METADATA: &str = "x-symbols";
+/// Request metadata adding fair values to the summaries of a [BookSummary](OrderbookAggregator::book_summary)
+/// stream, as a comma separated list of [methods](FairValueMethod::parse_list), e.g. `weighted_mid,ewma_mid:500`.
+pub const FAIR_VALUES_METADATA: &str = "x-fair-values";
+/// Version of the server.
+pub const SERVER_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Abbreviated hash of the commit the server was built from, `unknown` outside a git checkout.
+pub const GIT_HASH: &str = env!("GIT_HASH");
+/// Version of the Protobuf protocol, incremented whenever RPCs or messages are added or changed.
+pub const PROTOCOL_VERSION: u32 = 19;
+/// Initial response metadata of every stream carrying the [server version](SERVER_VERSION).
+pub const SERVER_VERSION_METADATA: &str = "x-server-version";
+/// Initial response metadata of every stream carrying the [git hash](GIT_HASH) of the build.
+pub const GIT_HASH_METADATA: &str = "x-git-hash";
+/// Initial response metadata of every stream carrying the [protocol version](PROTOCOL_VERSION).
+pub const PROTOCOL_VERSION_METADATA: &str = "x-protocol-version";
+/// Rolling window of the shares of the best quotes of each exchange.
+const QUOTE_SHARE_WINDOW: Duration = Duration::from_secs(300);
+/// Directory of the book diff logs.
+const BOOK_DIFF_DIR: &str = "book_diffs";
+/// Interval between two logs of the latency budget of the summaries, when attached.
+const LATENCY_BUDGET_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+/// Maximum time window of a book diff log.
+const MAX_BOOK_DIFF_WINDOW: Duration = Duration::from_secs(3600);
+/// Maximum number of symbols listed in a [GetSnapshots](OrderbookAggregator::get_snapshots) request.
+pub const MAX_SNAPSHOT_SYMBOLS: usize = 100;
+
+
+/// Top level object representing a Profobuf RPC server.
+pub struct ProtobufOrderbookServer {
+    /// The currency pairs served, each with venues and a feed of its own, by canonical symbol.
+    products: BTreeMap<String, ProductFeeds>,
+    /// The canonical symbols served, in the order added, the first one answering the queries
+    /// without a symbol.
+    symbols: Vec<String>,
+    /// Usage accounting for client API keys.
+    usage: UsageRegistry,
+    /// Capacities of the internal queues.
+    queue_capacities: QueueCapacities,
+    /// Whether the exchange adapters deliver complete books.
+    full_depth: bool,
+    /// Whether the aggregate books of the feeds are cross-checked against a naive book.
+    cross_check: bool,
+    /// Whether the summaries carry their latency budget.
+    latency_budget: bool,
+    /// Levels published depending on the spread, if adaptive.
+    adaptive_depth: Option<AdaptiveDepth>,
+    /// Rounding of the numbers of the summaries.
+    float_rounding: FloatRounding,
+    /// Thresholds of the latency classes of the exchanges.
+    staleness_thresholds: StalenessThresholds,
+    /// How long the client streams publish the last known summary once no exchange of its
+    /// symbol contributes, before ending with an error, [None](None) to publish the empty book.
+    stale_grace: Option<Duration>,
+    /// Connection delay added for each venue after the first, the default if not set.
+    connection_stagger: Option<Duration>,
+    /// Limits of the messages received from the venues, their own if not set.
+    ingest_limits: Option<IngestLimits>,
+    /// Whether the venues reject the books whose best bid is at or above the best ask.
+    inverted_book_check: bool,
+    /// Budget of the connection attempts of the venues, unlimited if not set.
+    reconnect_budget: Option<ReconnectBudget>,
+    /// Distances from the mid price, in basis points, for which the total depth is published.
+    depth_bands_bps: Vec<u32>,
+    /// Horizons of the volatility estimates of the mid price.
+    volatility_horizons: Vec<Duration>,
+    /// Rate at which published summaries are logged, [None](None) to disable.
+    summary_log: Option<SummaryLogSampling>,
+    /// Suppress publishing after (re)connections, until every exchange delivered a snapshot.
+    wait_for_snapshots: bool,
+    /// Alerting configuration.
+    alerts_config: AlertsConfig,
+    /// Instance the feeds are compared with, if any.
+    shadow: Option<ShadowConfig>,
+    /// Maintenance windows of the exchanges, as configured.
+    maintenance: MaintenanceSchedule,
+    /// Maintenance windows of the exchanges served, resolved on first use.
+    served_maintenance: OnceLock<MaintenanceSchedule>,
+    /// Named groups of symbols, which clients can subscribe to.
+    symbol_groups: SymbolGroups,
+    /// Subscriptions to the exchanges of every currency pair, at most one per exchange and symbol.
+    subscriptions: SubscriptionRegistry,
+    /// Webhook where changes of the status of the exchanges are posted.
+    #[cfg(feature = "webhook")]
+    status_webhook: Option<Webhook>,
+    /// Endpoint of each sink of the feeds, by sink name, reported in the effective configuration.
+    sinks: BTreeMap<&'static str, String>,
+    /// TCP port, once serving.
+    port: Option<u16>,
+}
+
+impl ProtobufOrderbookServer {
+    /// Create a new [ProtobufOrderbookServer](ProtobufOrderbookServer) object.
+    ///
+    /// # Arguments
+    ///
+    /// * `product` - The currency pair traded.
+    ///
+    /// * `exchanges` - A [vector](Vec) of [Exchange](Exchange) objects, one for each venue, e.g.
+    ///   [exchange adapters](crate::exchange::ExchangeAdapter).
+    ///
+    /// * `usage` - A [UsageRegistry](UsageRegistry) object, accounting the usage of each client.
+    ///
+    /// # Returns
+    ///
+    /// A [ProtobufOrderbookServer](ProtobufOrderbookServer) object.
+    pub fn new(product: CurrencyPair, exchanges: Vec<Box<dyn Exchange>>, usage: UsageRegistry) -> Self {
+        let symbol = canonical_symbol(&product);
+        Self {
+            products: BTreeMap::from([(symbol.clone(), ProductFeeds::new(product, exchanges))]),
+            symbols: vec![symbol],
+            usage,
+            queue_capacities: QueueCapacities::default(),
+            full_depth: false,
+            cross_check: false,
+            latency_budget: false,
+            adaptive_depth: None,
+            float_rounding: FloatRounding::default(),
+            staleness_thresholds: StalenessThresholds::default(),
+            stale_grace: None,
+            connection_stagger: None,
+            ingest_limits: None,
+            inverted_book_check: false,
+            reconnect_budget: None,
+            depth_bands_bps: DEFAULT_DEPTH_BANDS_BPS.to_vec(),
+            volatility_horizons: DEFAULT_VOLATILITY_HORIZONS_MS.iter().map(|&ms| Duration::from_millis(ms)).collect(),
+            summary_log: Some(DEFAULT_SUMMARY_LOG_SAMPLING),
+            wait_for_snapshots: true,
+            alerts_config: AlertsConfig::default(),
+            shadow: None,
+            maintenance: MaintenanceSchedule::default(),
+            served_maintenance: OnceLock::new(),
+            symbol_groups: SymbolGroups::default(),
+            subscriptions: SubscriptionRegistry::new(),
+            #[cfg(feature = "webhook")]
+            status_webhook: None,
+            sinks: BTreeMap::new(),
+            port: None,
+        }
+    }
+
+    /// Set the capacities of the internal queues, of the exchange adapters and of the
+    /// responses to streaming clients.
+    ///
+    /// # Arguments
+    ///
+    /// * `queue_capacities` - The [QueueCapacities](QueueCapacities).
+    ///
+    /// # Returns
+    ///
+    /// The modified [ProtobufOrderbookServer](ProtobufOrderbookServer).
+    pub fn with_queue_capacities(mut self, queue_capacities: QueueCapacities) -> Self {
+        self.queue_capacities = queue_capacities;
+        self
+    }
+
+    /// Set the limits of the messages received from every venue, checked before parsing.
+    ///
+    /// # Arguments
+    ///
+    /// * `ingest_limits` - The [IngestLimits](IngestLimits).
+    ///
+    /// # Returns
+    ///
+    /// The modified [ProtobufOrderbookServer](ProtobufOrderbookServer).
+    pub fn with_ingest_limits(mut self, ingest_limits: IngestLimits) -> Self {
+        self.ingest_limits = Some(ingest_limits);
+        self
+    }
+
+    /// Reject the books whose best bid is at or above the best ask, rather than merging them
+    /// into the consolidated book, the venues resynchronizing.
+    ///
+    /// # Arguments
+    ///
+    /// * `inverted_book_check` - Whether to reject the inverted books.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ProtobufOrderbookServer](ProtobufOrderbookServer).
+    pub fn with_inverted_book_check(mut self, inverted_book_check: bool) -> Self {
+        self.inverted_book_check = inverted_book_check;
+        self
+    }
+
+    /// Limit the connection attempts of every venue to a budget, shared by all the venues.
+    ///
+    /// # Arguments
+    ///
+    /// * `reconnect_budget` - The [ReconnectBudget](ReconnectBudget).
+    ///
+    /// # Returns
+    ///
+    /// The modified [ProtobufOrderbookServer](ProtobufOrderbookServer).
+    pub fn with_reconnect_budget(mut self, reconnect_budget: ReconnectBudget) -> Self {
+        self.reconnect_budget = Some(reconnect_budget);
+        self
+    }
+
+    /// Serve a further currency pair, consolidated from venues of its own, to the summary
+    /// streams selecting it with [SYMBOLS_METADATA](SYMBOLS_METADATA) and to the queries about it,
+    /// with the same sinks, alerts and metrics as the first one. A currency pair already served
+    /// is replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `product` - The currency pair traded.
+    ///
+    /// * `exchanges` - A [vector](Vec) of [Exchange](Exchange) objects, one for each venue.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ProtobufOrderbookServer](ProtobufOrderbookServer).
+    pub fn with_product(mut self, product: CurrencyPair, exchanges: Vec<Box<dyn Exchange>>) -> Self {
+        let symbol = canonical_symbol(&product);
+        if self.products.insert(symbol.clone(), ProductFeeds::new(product, exchanges)).is_none() {
+            self.symbols.push(symbol);
+        }
+        self
+    }
+
+    /// Internal function collecting the settings of the connections to the venues of a
+    /// currency pair, each leasing its subscription from the registry of the server.
+    fn connection_settings(&self, product: &CurrencyPair) -> ConnectionSettings {
+        ConnectionSettings {
+            queue_capacities: Some(self.queue_capacities),
+            parse_timing: self.latency_budget,
+            maintenance: Some(self.served_maintenance().clone()),
+            subscription_registry: Some((self.subscriptions.clone(), canonical_symbol(product))),
+            connection_stagger: self.connection_stagger,
+            ingest_limits: self.ingest_limits,
+            inverted_book_check: self.inverted_book_check,
+            reconnect_budget: self.reconnect_budget.clone(),
+            ..ConnectionSettings::default()
+        }
+    }
+
+    /// Publish few levels while the consolidated spread is narrow, and more as it widens.
+    ///
+    /// # Arguments
+    ///
+    /// * `adaptive_depth` - The [AdaptiveDepth](AdaptiveDepth) configuration.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ProtobufOrderbookServer](ProtobufOrderbookServer).
+    pub fn with_adaptive_depth(mut self, adaptive_depth: AdaptiveDepth) -> Self {
+        self.adaptive_depth = Some(adaptive_depth);
+        self
+    }
+
+    /// Round the numbers of the summaries to significant digits, per symbol.
+    ///
+    /// # Arguments
+    ///
+    /// * `float_rounding` - The [FloatRounding](FloatRounding) configuration.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ProtobufOrderbookServer](ProtobufOrderbookServer).
+    pub fn with_float_rounding(mut self, float_rounding: FloatRounding) -> Self {
+        self.float_rounding = float_rounding;
+        self
+    }
+
+    /// Set the thresholds of the latency classes annotating the levels of each exchange.
+    ///
+    /// # Arguments
+    ///
+    /// * `staleness_thresholds` - The [StalenessThresholds](StalenessThresholds).
+    ///
+    /// # Returns
+    ///
+    /// The modified [ProtobufOrderbookServer](ProtobufOrderbookServer).
+    pub fn with_staleness_thresholds(mut self, staleness_thresholds: StalenessThresholds) -> Self {
+        self.staleness_thresholds = staleness_thresholds;
+        self
+    }
+
+    /// Keep publishing the last known summary of a symbol to the client streams, marked stale
+    /// with its age, once all its exchanges are disconnected, and end the streams with an
+    /// `UNAVAILABLE` error if none delivers a snapshot within the grace period.
+    ///
+    /// # Arguments
+    ///
+    /// * `stale_grace` - The grace period.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ProtobufOrderbookServer](ProtobufOrderbookServer).
+    pub fn with_stale_grace(mut self, stale_grace: Duration) -> Self {
+        self.stale_grace = Some(stale_grace);
+        self
+    }
+
+    /// Delay the connection of each venue of a currency pair after the first, so that they do not
+    /// (re)connect all at the same time.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection_stagger` - The delay added for each venue.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ProtobufOrderbookServer](ProtobufOrderbookServer).
+    pub fn with_connection_stagger(mut self, connection_stagger: Duration) -> Self {
+        self.connection_stagger = Some(connection_stagger);
+        self
+    }
+
+    /// Publish the total depth within some distances from the mid price, by default
+    /// [DEFAULT_DEPTH_BANDS_BPS](DEFAULT_DEPTH_BANDS_BPS).
+    ///
+    /// # Arguments
+    ///
+    /// * `depth_bands_bps` - The distances from the mid price, in basis points.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ProtobufOrderbookServer](ProtobufOrderbookServer).
+    pub fn with_depth_bands(mut self, depth_bands_bps: Vec<u32>) -> Self {
+        self.depth_bands_bps = depth_bands_bps;
+        self
+    }
+
+    /// Estimate the volatility of the mid price at some horizons, by default
+    /// [DEFAULT_VOLATILITY_HORIZONS_MS](DEFAULT_VOLATILITY_HORIZONS_MS).
+    ///
+    /// # Arguments
+    ///
+    /// * `volatility_horizons` - The horizons, in increasing order.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ProtobufOrderbookServer](ProtobufOrderbookServer).
+    pub fn with_volatility_horizons(mut self, volatility_horizons: Vec<Duration>) -> Self {
+        self.volatility_horizons = volatility_horizons;
+        self
+    }
+
+    /// Log a sample of the published summaries, by default one per minute.
+    ///
+    /// # Arguments
+    ///
+    /// * `summary_log` - The [SummaryLogSampling](SummaryLogSampling) rate, [None](None) to
+    ///   disable the log.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ProtobufOrderbookServer](ProtobufOrderbookServer).
+    pub fn with_summary_log(mut self, summary_log: Option<SummaryLogSampling>) -> Self {
+        self.summary_log = summary_log;
+        self
+    }
+
+    /// Suppress publishing after (re)connections, until every exchange delivered a snapshot,
+    /// which is the default.
+    ///
+    /// # Arguments
+    ///
+    /// * `wait_for_snapshots` - Whether to suppress publishing.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ProtobufOrderbookServer](ProtobufOrderbookServer).
+    pub fn with_wait_for_snapshots(mut self, wait_for_snapshots: bool) -> Self {
+        self.wait_for_snapshots = wait_for_snapshots;
+        self
+    }
+
+    /// Consolidate complete books, for exchange adapters in full-depth mode, e.g. created by
+    /// [make_binance_full_depth_adapter](crate::binance::make_binance_full_depth_adapter).
+    ///
+    /// # Arguments
+    ///
+    /// * `full_depth` - Whether the exchange adapters deliver complete books.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ProtobufOrderbookServer](ProtobufOrderbookServer).
+    pub fn with_full_depth(mut self, full_depth: bool) -> Self {
+        self.full_depth = full_depth;
+        self
+    }
+
+    /// Cross-check the aggregate books of the feeds against a naive book rebuilt on every
+    /// update, logging the discrepancies, as a shadow of the production consolidation.
+    ///
+    /// # Arguments
+    ///
+    /// * `cross_check` - Whether to cross-check the aggregate book.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ProtobufOrderbookServer](ProtobufOrderbookServer).
+    pub fn with_cross_check(mut self, cross_check: bool) -> Self {
+        self.cross_check = cross_check;
+        self
+    }
+
+    /// Attach to each summary its [latency budget](latency_budget): parse, aggregation, queue wait
+    /// and serialization times, also aggregated in histograms logged periodically. The exchange
+    /// adapters are set to time their parser.
+    ///
+    /// # Arguments
+    ///
+    /// * `latency_budget` - Whether the summaries carry their latency budget.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ProtobufOrderbookServer](ProtobufOrderbookServer).
+    pub fn with_latency_budget(mut self, latency_budget: bool) -> Self {
+        self.latency_budget = latency_budget;
+        self
+    }
+
+    /// Post the changes of the status of the exchanges to a webhook, once the server is started.
+    ///
+    /// # Arguments
+    ///
+    /// * `webhook` - The [Webhook](Webhook).
+    ///
+    /// # Returns
+    ///
+    /// The modified [ProtobufOrderbookServer](ProtobufOrderbookServer).
+    #[cfg(feature = "webhook")]
+    pub fn with_status_webhook(mut self, webhook: Webhook) -> Self {
+        self.status_webhook = Some(webhook);
+        self
+    }
+
+    /// Report a sink of the feeds in the effective configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The sink name.
+    ///
+    /// * `endpoint` - Where the sink writes, e.g. a URL or a file.
+    ///
+    /// # Returns
+    ///
+    /// The modified [ProtobufOrderbookServer](ProtobufOrderbookServer).
+    pub fn with_sink_endpoint(mut self, name: &'static str, endpoint: &str) -> Self {
+        self.sinks.insert(name, endpoint.to_string());
+        self
+    }
+
+    /// The effective configuration of the server, fully resolved.
+    ///
+    /// # Returns
+    ///
+    /// An [EffectiveConfig](EffectiveConfig) object.
+    pub fn effective_config(&self) -> EffectiveConfig {
+        EffectiveConfig {
+            version: SERVER_VERSION,
+            symbols: self.served_symbols(),
+            port: self.port,
+            features: enabled_features(),
+            allocator: ALLOCATOR_NAME,
+            venues: self.served_products()
+                .flat_map(|product| &product.exchanges)
+                .filter_map(|exchange| exchange.config())
+                .collect(),
+            published_levels: NUM_LEVELS,
+            adaptive_depth: self.adaptive_depth,
+            significant_digits: self.symbols.iter()
+                .filter_map(|symbol| Some((symbol.clone(), self.float_rounding.significant_digits(symbol)?)))
+                .collect(),
+            full_depth: self.full_depth,
+            depth_bands_bps: self.depth_bands_bps.clone(),
+            volatility_horizons_ms: self.volatility_horizons.iter().map(|horizon| horizon.as_millis() as u64).collect(),
+            wait_for_snapshots: self.wait_for_snapshots,
+            summary_log: self.summary_log.map(|sampling| match sampling {
+                SummaryLogSampling::EveryNth(n) => format!("every {} summaries", n),
+                SummaryLogSampling::Interval(interval) => format!("every {}ms", interval.as_millis()),
+            }),
+            queue_capacities: self.queue_capacities,
+            alerts: self.alerts_config.clone(),
+            sinks: self.sinks.clone(),
+            shadow: self.shadow.clone(),
+        }
+    }
+
+    /// Subscribe to the changes of the status of the exchanges of a symbol served.
+    ///
+    /// # Arguments
+    ///
+    /// * `symbol` - The canonical symbol.
+    ///
+    /// # Returns
+    ///
+    /// A [broadcast::Receiver](broadcast::Receiver) of [status events](ExchangeStatusEvent),
+    /// [None](None) if the symbol is not served.
+    pub fn exchange_status(&self, symbol: &str) -> Option<broadcast::Receiver<ExchangeStatusEvent>> {
+        Some(self.products.get(symbol)?.bus.statuses())
+    }
+
+    /// The event bus of a symbol served, where new outputs subscribe to the book events and
+    /// status of its exchanges, the alerts and the summaries.
+    ///
+    /// # Arguments
+    ///
+    /// * `symbol` - The canonical symbol.
+    ///
+    /// # Returns
+    ///
+    /// A reference to the [EventBus](EventBus), [None](None) if the symbol is not served.
+    pub fn event_bus(&self, symbol: &str) -> Option<&EventBus> {
+        Some(&self.products.get(symbol)?.bus)
+    }
+
+    /// Evaluate alerting rules on the feed of every symbol served, once the server is started.
+    ///
+    /// # Arguments
+    ///
+    /// * `alerts_config` - The [AlertsConfig](AlertsConfig).
+    ///
+    /// # Returns
+    ///
+    /// The modified [ProtobufOrderbookServer](ProtobufOrderbookServer).
+    pub fn with_alerts(mut self, alerts_config: AlertsConfig) -> Self {
+        self.alerts_config = alerts_config;
+        self
+    }
+
+    /// Compare the feed of every symbol served, once the server is started, with the summary
+    /// stream of another instance serving the same symbol, e.g. a canary, reporting their
+    /// divergences as metrics.
+    ///
+    /// # Arguments
+    ///
+    /// * `shadow` - The [ShadowConfig](ShadowConfig).
+    ///
+    /// # Returns
+    ///
+    /// The modified [ProtobufOrderbookServer](ProtobufOrderbookServer).
+    pub fn with_shadow(mut self, shadow: ShadowConfig) -> Self {
+        self.shadow = Some(shadow);
+        self
+    }
+
+    /// Expect the exchanges to be offline during their maintenance windows: their disconnections
+    /// are notified as scheduled, their staleness does not raise alerts, and they are not counted
+    /// among the expected exchanges of the summaries unless they deliver data anyway.
+    ///
+    /// # Arguments
+    ///
+    /// * `maintenance` - The [MaintenanceSchedule](MaintenanceSchedule).
+    ///
+    /// # Returns
+    ///
+    /// The modified [ProtobufOrderbookServer](ProtobufOrderbookServer).
+    pub fn with_maintenance(mut self, maintenance: MaintenanceSchedule) -> Self {
+        self.maintenance = maintenance;
+        self
+    }
+
+    /// Internal function resolving, on first use, the maintenance windows of the exchanges of
+    /// every currency pair served, whichever order the server was configured in.
+    fn served_maintenance(&self) -> &MaintenanceSchedule {
+        self.served_maintenance.get_or_init(|| {
+            let exchange_codes: Vec<ExchangeId> = self.served_products()
+                .flat_map(|product| &product.exchanges)
+                .map(|exchange| exchange.code())
+                .collect();
+            self.maintenance.clone().for_exchanges(&exchange_codes.iter().map(ExchangeId::as_str).collect::<Vec<_>>())
+        })
+    }
+
+    /// Define groups of symbols, which clients can subscribe to by name.
+    ///
+    /// # Arguments
+    ///
+    /// * `symbol_groups` - The [SymbolGroups](SymbolGroups).
+    ///
+    /// # Returns
+    ///
+    /// The modified [ProtobufOrderbookServer](ProtobufOrderbookServer).
+    pub fn with_symbol_groups(mut self, symbol_groups: SymbolGroups) -> Self {
+        self.symbol_groups = symbol_groups;
+        self
+    }
+
+    /// The summaries of a symbol served, from its feed, started on first use.
+    ///
+    /// # Arguments
+    ///
+    /// * `symbol` - The canonical symbol.
+    ///
+    /// # Returns
+    ///
+    /// A [FeedReceiver](FeedReceiver), [None](None) if the symbol is not served.
+    pub async fn feed(&self, symbol: &str) -> Option<FeedReceiver> {
+        let product = self.products.get(symbol)?;
+        Some(self.product_feeds(product).await.0.clone())
+    }
+
+    /// The aggregate book of a symbol served, from its feed, started on first use.
+    ///
+    /// # Arguments
+    ///
+    /// * `symbol` - The canonical symbol.
+    ///
+    /// # Returns
+    ///
+    /// A [BookReceiver](BookReceiver), [None](None) if the symbol is not served.
+    pub async fn book(&self, symbol: &str) -> Option<BookReceiver> {
+        let product = self.products.get(symbol)?;
+        Some(self.product_feeds(product).await.1.clone())
+    }
+
+    /// The volatility of the mid price of a symbol served, estimated from first use.
+    ///
+    /// # Arguments
+    ///
+    /// * `symbol` - The canonical symbol.
+    ///
+    /// # Returns
+    ///
+    /// A [VolatilityReceiver](VolatilityReceiver), [None](None) if the symbol is not served.
+    pub async fn volatility(&self, symbol: &str) -> Option<VolatilityReceiver> {
+        let product = self.products.get(symbol)?;
+        Some(product.volatility.get_or_init(|| async {
+            spawn_volatility(self.product_feeds(product).await.0.clone(), self.volatility_horizons.clone(), system_clock())
+        }).await.clone())
+    }
+
+    /// Wait for the first aggregate book of a symbol served, from its feed, up to
+    /// [FEED_WARMUP_TIMEOUT](FEED_WARMUP_TIMEOUT).
+    async fn current_book(&self, symbol: &str) -> Result<Arc<AggregateBook>, Status> {
+        let mut book = self.book(symbol).await
+            .ok_or_else(|| Status::invalid_argument(format!("symbol not served: {}", symbol)))?;
+        timeout(FEED_WARMUP_TIMEOUT, book.wait_for(Option::is_some)).await
+            .ok()
+            .and_then(|book| book.ok())
+            .and_then(|book| book.clone())
+            .ok_or_else(|| Status::unavailable("no book available yet"))
+    }
+
+    /// The shares of the best quotes of each exchange of a symbol served, tracked from the
+    /// start of its feed.
+    async fn quote_share(&self, symbol: &str) -> Option<QuoteShareReceiver> {
+        let product = self.products.get(symbol)?;
+        self.product_feeds(product).await;
+        Some(product.quote_share.get().expect("quote share not tracked").clone())
+    }
+
+    /// Internal function starting the feed of a currency pair on first use, its exchanges
+    /// publishing their book events and changes of their status on the event bus of the
+    /// currency pair, and tracking the shares of the best quotes.
+    async fn product_feeds<'a>(&self, product: &'a ProductFeeds) -> &'a (FeedReceiver, BookReceiver) {
+        product.feeds.get_or_init(|| async {
+            let _ = product.status_board.set(spawn_status_board(product.bus.statuses()));
+            let settings = ConnectionSettings {
+                status_sender: Some(product.bus.status_sender()),
+                ..self.connection_settings(&product.product)
+            };
+            product.bus.spawn_publisher(ExchangeDataStream::from_exchanges(&product.exchanges, &settings).await);
+            let feeds = spawn_bus_feeds(self.product_service(product).with_book_diff_log(true), product.bus.clone());
+            let _ = product.quote_share.set(spawn_quote_share(feeds.0.clone(), QUOTE_SHARE_WINDOW, system_clock()));
+            feeds
+        }).await
+    }
+
+    /// Start the Protobuf RPC server on a port, with the metrics, status webhook, alerts and
+    /// shadow comparison of every symbol served. The messages are compressed with gzip for
+    /// the clients accepting it, and the requests may be.
+    ///
+    /// # Arguments
+    ///
+    /// * `port` - The TCP port of the server.
+    ///
+    /// # Returns
+    ///
+    /// An empty [Result](Result).
+    pub async fn serve(mut self, port: u16) -> Result<(), Box<dyn std::error::Error>> {
+        self.port = Some(port);
+        info!(
+            "orderbook-server {} ({}, protocol {}) serving {} on port {}",
+            SERVER_VERSION, GIT_HASH, PROTOCOL_VERSION, self.served_symbols().join(", "), port,
+        );
+        info!("Effective configuration: {}", self.effective_config().to_json());
+        let our_address = net::SocketAddr::new(
+            net::IpAddr::V6(net::Ipv6Addr::from_str("::1").unwrap()),
+            port
+        );
+        if self.latency_budget {
+            latency_budget::spawn_report(LATENCY_BUDGET_REPORT_INTERVAL);
+        }
+        for product in self.served_products() {
+            let symbol = canonical_symbol(&product.product);
+            product.bus.spawn_metrics();
+            #[cfg(feature = "webhook")]
+            if let Some(webhook) = self.status_webhook.clone() {
+                spawn_webhook(product.bus.statuses(), webhook);
+                self.product_feeds(product).await;
+            }
+            if !self.alerts_config.rules.is_empty() {
+                AlertEngine::from_config(self.alerts_config.clone(), system_clock())
+                    .with_maintenance(self.served_maintenance().clone())
+                    .spawn(self.product_feeds(product).await.0.clone(), product.bus.alert_sender());
+            }
+            if let Some(shadow) = self.shadow.clone() {
+                spawn_shadow(self.product_feeds(product).await.0.clone(), symbol, shadow, system_clock());
+            }
+        }
+        Server::builder()
+            .add_service(OrderbookAggregatorServer::new(self)
+                .send_compressed(CompressionEncoding::Gzip)
+                .accept_compressed(CompressionEncoding::Gzip))
+            .serve(our_address)
+            .await
+            .unwrap();
+        Ok(())
+    }
+
+    /// Create a new [BookSummaryService](BookSummaryService) object subscribed to the book events
+    /// of a symbol served, its feed started on first use, so that the exchanges are subscribed
+    /// only once whatever the number of clients.
+    ///
+    /// # Arguments
+    ///
+    /// * `symbol` - The canonical symbol.
+    ///
+    /// # Returns
+    ///
+    /// A [BookSummaryService](BookSummaryService), [None](None) if the symbol is not served.
+    pub async fn make_service(&self, symbol: &str) -> Option<BookSummaryService> {
+        let product = self.products.get(symbol)?;
+        self.product_feeds(product).await;
+        Some(self.product_service(product))
+    }
+
+    /// The canonical symbols served, in the order added.
+    ///
+    /// # Returns
+    ///
+    /// A [vector](Vec) of symbols.
+    pub fn served_symbols(&self) -> Vec<String> {
+        self.symbols.clone()
+    }
+
+    /// Internal function iterating over the currency pairs served, in the order added.
+    fn served_products(&self) -> impl Iterator<Item = &ProductFeeds> {
+        self.symbols.iter().map(|symbol| &self.products[symbol])
+    }
+
+    /// Internal function collecting the canonical symbol of each currency pair served, in the
+    /// order added, with its venues and their latest status, starting the feeds on first use.
+    async fn served_venues(&self) -> Vec<(String, &[Box<dyn Exchange>], BTreeMap<ExchangeId, ExchangeStatus>)> {
+        let mut served = vec![];
+        for product in self.served_products() {
+            self.product_feeds(product).await;
+            let board = product.status_board.get().expect("status board not started").borrow().clone();
+            served.push((canonical_symbol(&product.product), product.exchanges.as_slice(), board));
+        }
+        served
+    }
+
+    /// Internal function resolving the symbol a query is about, in any accepted notation, the
+    /// first one served if not set.
+    fn query_symbol(&self, symbol: &str) -> Result<String, String> {
+        if symbol.is_empty() {
+            return Ok(self.symbols[0].clone());
+        }
+        canonicalize(symbol)
+            .filter(|canonical| self.products.contains_key(canonical))
+            .ok_or_else(|| format!("symbol not served: {}", symbol))
+    }
+
+    /// Internal function creating a new [BookSummaryService](BookSummaryService) object subscribed
+    /// to the book events of a currency pair served.
+    fn product_service(&self, product: &ProductFeeds) -> BookSummaryService {
+        self.configure_service(&product.product, BookSummaryService::from_bus(&product.product, &product.bus, product.exchanges.len()))
+    }
+
+    /// Internal function waiting for the first summary of a symbol served, up to a deadline.
+    async fn current_symbol_summary(&self, symbol: &str, deadline: Instant) -> Option<Summary> {
+        let mut feed = self.feed(symbol).await?;
+        timeout_at(deadline, feed.wait_for(Option::is_some)).await
+            .ok()
+            .and_then(|summary| summary.ok())
+            .and_then(|summary| summary.clone())
+    }
+
+    /// Internal function configuring a new [BookSummaryService](BookSummaryService) object of a
+    /// currency pair as set for the server.
+    fn configure_service(&self, product: &CurrencyPair, service: BookSummaryService) -> BookSummaryService {
+        let service = service
+            .with_wait_for_snapshots(self.wait_for_snapshots)
+            .with_full_depth(self.full_depth)
+            .with_cross_check(self.cross_check)
+            .with_latency_budget(self.latency_budget)
+            .with_depth_bands(self.depth_bands_bps.clone())
+            .with_staleness_thresholds(self.staleness_thresholds)
+            .with_maintenance(self.served_maintenance().clone());
+        let service = match self.adaptive_depth {
+            Some(adaptive_depth) => service.with_adaptive_depth(adaptive_depth),
+            None => service,
+        };
+        let service = match self.float_rounding.significant_digits(&canonical_symbol(product)) {
+            Some(significant_digits) => service.with_significant_digits(significant_digits),
+            None => service,
+        };
+        match self.summary_log {
+            Some(sampling) => service.with_summary_log(sampling, system_clock()),
+            None => service,
+        }
+    }
+}
+
+/// A currency pair served, with its venues, its event bus and its feed.
+struct ProductFeeds {
+    /// The currency pair traded.
+    product: CurrencyPair,
+    /// The venues, e.g. exchange adapters.
+    exchanges: Vec<Box<dyn Exchange>>,
+    /// Events of the currency pair: book events and status of its venues, alerts and summaries.
+    bus: EventBus,
+    /// Latest status of the venues, once the feed is started.
+    status_board: OnceCell<StatusBoard>,
+    /// The summaries and aggregate book of the currency pair, started on first use.
+    feeds: OnceCell<(FeedReceiver, BookReceiver)>,
+    /// Volatility of the mid price, estimated from first use.
+    volatility: OnceCell<VolatilityReceiver>,
+    /// Shares of the best quotes of each venue, tracked from the start of the feed.
+    quote_share: OnceCell<QuoteShareReceiver>,
+}
+
+impl ProductFeeds {
+    /// Create a new [ProductFeeds](ProductFeeds) object, its feed not started.
+    fn new(product: CurrencyPair, exchanges: Vec<Box<dyn Exchange>>) -> Self {
+        Self {
+            product,
+            exchanges,
+            bus: EventBus::new(),
+            status_board: OnceCell::new(),
+            feeds: OnceCell::new(),
+            volatility: OnceCell::new(),
+            quote_share: OnceCell::new(),
+        }
+    }
+}
+
+/// Summary streams of a client subscription, opened from the request metadata.
+struct SummarySubscription {
+    /// Current summary of the first symbol selected, delivered first if asked for.
+    snapshot: Option<Summary>,
+    /// Summaries of the symbols selected.
+    multiplexer: SummaryMultiplexer<BookSummaryService>,
+    /// Usage recorder of the stream.
+    stream_usage: StreamUsage,
+}
+
+impl ProtobufOrderbookServer {
+    /// Internal function opening the summary streams of a subscription, as asked by the
+    /// request parameters, symbols and depth, and by the request metadata:
+    /// [fields](SUMMARY_FIELDS_METADATA), [fair values](FAIR_VALUES_METADATA),
+    /// [symbols](SYMBOLS_METADATA), unless set in the parameters, and [snapshot](SNAPSHOT_METADATA).
+    async fn open_subscription<T>(&self, req: &Request<T>, params: &BookSummaryRequest) -> Result<SummarySubscription, Status> {
+        let fields = summary_fields(req).map_err(Status::invalid_argument)?;
+        let fair_value_methods = fair_value_methods(req).map_err(Status::invalid_argument)?;
+        let depth = requested_depth(params.depth).map_err(Status::invalid_argument)?;
+        let served = self.served_symbols();
+        let selection = match params.symbol.is_empty() {
+            true => symbol_selection(req).map_err(Status::invalid_argument)?,
+            false => Some(params.symbol.as_str()),
+        };
+        let symbols = match selection {
+            Some(selection) => {
+                let selected = self.symbol_groups.select(selection, &served).map_err(Status::invalid_argument)?;
+                if selected.is_empty() {
+                    return Err(Status::not_found(format!("no symbol served matches: {}", selection)));
+                }
+                selected
+            },
+            None => served,
+        };
+        // the snapshot comes from the feed of the first symbol selected
+        let snapshot = if wants_snapshot(req) {
+            let mut snapshot = self.current_symbol_summary(&symbols[0], Instant::now() + FEED_WARMUP_TIMEOUT).await;
+            if let Some(snapshot) = snapshot.as_mut() {
+                fields.trim(snapshot);
+                snapshot.bids.truncate(depth);
+                snapshot.asks.truncate(depth);
+            }
+            if snapshot.is_none() {
+                info!("No snapshot available yet, waiting for the next update");
+            }
+            snapshot
+        } else {
+            None
+        };
+        let stream_usage = self.usage.start_stream(&api_key(req), &symbols[0]);
+        let mut services = vec![];
+        for symbol in symbols {
+            let service: BookSummaryService = self.make_service(&symbol).await
+                .expect("symbol selected among the ones served")
+                .with_depth(depth)
+                .with_summary_fields(fields)
+                .with_status_frames(fields.status)
+                .with_stale_grace(self.stale_grace)
+                .with_fair_values(&fair_value_methods, system_clock());
+            stream_usage.record_symbol(&symbol);
+            services.push((symbol, service));
+        }
+        let multiplexer = SummaryMultiplexer::new(services, self.queue_capacities.client_response, system_clock());
+        Ok(SummarySubscription { snapshot, multiplexer, stream_usage })
+    }
+}
+
+/// Extract the client API key from the request metadata.
+fn api_key<T>(req: &Request<T>) -> String {
+    req.metadata().get(API_KEY_METADATA)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or(ANONYMOUS_API_KEY)
+        .to_string()
+}
+
+/// Stream of the alerts published on an event bus, skipping the ones missed when lagging.
+fn alert_events(bus: &EventBus) -> Pin<Box<dyn Stream<Item = AlertEvent> + Send>> {
+    Box::pin(stream::unfold(bus.alerts(), |mut alerts| async move {
+        loop {
+            match alerts.recv().await {
+                Ok(event) => return Some((event, alerts)),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }))
+}
+
+/// Wrap a stream in a response, with the version of the server as initial metadata.
+///
+/// # Arguments
+///
+/// * `stream` - The stream of messages.
+///
+/// # Returns
+///
+/// A [Response](Response) carrying [SERVER_VERSION_METADATA](SERVER_VERSION_METADATA),
+/// [GIT_HASH_METADATA](GIT_HASH_METADATA) and [PROTOCOL_VERSION_METADATA](PROTOCOL_VERSION_METADATA).
+fn stream_response<T>(stream: T) -> Response<T> {
+    let mut response = Response::new(stream);
+    let metadata = response.metadata_mut();
+    metadata.insert(SERVER_VERSION_METADATA, MetadataValue::from_static(SERVER_VERSION));
+    metadata.insert(GIT_HASH_METADATA, MetadataValue::from_static(GIT_HASH));
+    metadata.insert(PROTOCOL_VERSION_METADATA, MetadataValue::from(PROTOCOL_VERSION));
+    response
+}
+
+/// Check whether the request metadata asks for a snapshot on subscription.
+fn wants_snapshot<T>(req: &Request<T>) -> bool {
+    req.metadata().get(SNAPSHOT_METADATA)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+/// Extract the optional summary fields requested in the metadata, all of them if not specified.
+fn summary_fields<T>(req: &Request<T>) -> Result<SummaryFields, String> {
+    match req.metadata().get(SUMMARY_FIELDS_METADATA) {
+        Some(value) => value.to_str()
+            .map_err(|_| String::from("invalid summary fields"))
+            .and_then(SummaryFields::parse),
+        None => Ok(SummaryFields::default()),
+    }
+}
+
+/// Check the levels of each side requested, [NUM_LEVELS](NUM_LEVELS) if not specified, and at
+/// most the [NUM_LEVELS](NUM_LEVELS) maintained in the aggregate books.
+fn requested_depth(depth: u32) -> Result<usize, String> {
+    match depth as usize {
+        0 => Ok(NUM_LEVELS),
+        depth if depth <= NUM_LEVELS => Ok(depth),
+        depth => Err(format!("depth {} above the {} levels maintained", depth, NUM_LEVELS)),
+    }
+}
+
+/// Extract the symbol selection requested in the metadata, if any.
+fn symbol_selection<T>(req: &Request<T>) -> Result<Option<&str>, String> {
+    req.metadata().get(SYMBOLS_METADATA)
+        .map(|value| value.to_str().map_err(|_| String::from("invalid symbol selection")))
+        .transpose()
+}
+
+/// Extract the fair value methods requested in the metadata, none if not specified.
+fn fair_value_methods<T>(req: &Request<T>) -> Result<Vec<FairValueMethod>, String> {
+    match req.metadata().get(FAIR_VALUES_METADATA) {
+        Some(value) => value.to_str()
+            .map_err(|_| String::from("invalid fair value methods"))
+            .and_then(FairValueMethod::parse_list),
+        None => Ok(vec![]),
+    }
+}
+
+/// Check whether a summary is the frame notifying that no exchange of its symbol contributed
+/// during the [grace period](ProtobufOrderbookServer::with_stale_grace).
+///
+/// # Arguments
+///
+/// * `summary` - The summary.
+///
+/// # Returns
+///
+/// The [Status](Status) ending the client stream, [None](None) for other summaries.
+fn stale_expiry(summary: &Summary) -> Option<Status> {
+    match &summary.frame {
+        Some(Frame::Status(status)) if status.reason() == StreamStatusReason::StaleExpired =>
+            Some(Status::unavailable(format!("no exchange of {} delivers data", summary.symbol))),
+        _ => None,
+    }
+}
+
+/// Convert a side of the Protobuf API into the [Side](Side) of the aggregate book holding it.
+fn book_side(side: BookSide) -> Side {
+    match side {
+        BookSide::Bid => Side::Buy,
+        BookSide::Ask => Side::Sell,
+    }
+}
+
+/// Implementation of the trait automatically generated from the file `proto/orderbook.proto`.
+#[tonic::async_trait]
+impl OrderbookAggregator for ProtobufOrderbookServer {
+
+    type BookSummaryStream = ResponseStream;
+
+    async fn book_summary(&self, req: Request<BookSummaryRequest>) -> SummaryResult {
+        info!("OrderbookServer::book_summary");
+        info!("Client connected from: {:?}", req.remote_addr());
+
+        let SummarySubscription { snapshot, mut multiplexer, mut stream_usage } = self.open_subscription(&req, req.get_ref()).await?;
+        let (tx, rx) = mpsc::channel(self.queue_capacities.client_response);
+
+        tokio::spawn(async move {
+            if let Some(mut item) = snapshot {
+                latency_budget::complete(&mut item);
+                if tx.send(Result::<Summary, Status>::Ok(item)).await.is_ok() {
+                    stream_usage.record_message();
+                }
+            }
+            while let Some(mut item) = multiplexer.next().await {
+                if let Some(status) = stale_expiry(&item) {
+                    let _ = tx.send(Err(status)).await;
+                    break;
+                }
+                latency_budget::complete(&mut item);
+                if tx.send(Result::<Summary, Status>::Ok(item)).await.is_err() {
+                    break;
+                }
+                QUEUE_DEPTHS.record_channel("client_response", "book_summary", &tx);
+                stream_usage.record_message();
+            }
+            info!("Client disconnected");
+            for service in multiplexer.into_sources() {
+                service.disconnect().await;
+            }
+        });
+
+        let output_stream = ReceiverStream::new(rx);
+        Ok(stream_response(
+            Box::pin(output_stream) as Self::BookSummaryStream
+        ))
+    }
+
+    type TopOfBookEventsStream = TopOfBookResponseStream;
+
+    async fn top_of_book_events(&self, req: Request<Empty>) -> TopOfBookResult {
+        info!("OrderbookServer::top_of_book_events");
+        info!("Client connected from: {:?}", req.remote_addr());
+
+        let symbol = self.query_symbol(symbol_selection(&req).map_err(Status::invalid_argument)?.unwrap_or_default()).map_err(Status::invalid_argument)?;
+        let service = self.make_service(&symbol).await.expect("symbol resolved among the ones served");
+        let (tx, rx) = mpsc::channel(self.queue_capacities.client_response);
+        let mut event_stream = TopOfBookEventStream::new(service);
+        let mut stream_usage = self.usage.start_stream(&api_key(&req), &symbol);
+
+        tokio::spawn(async move {
+            while let Some(item) = event_stream.next().await {
+                if tx.send(Result::<TopOfBookEvent, Status>::Ok(item)).await.is_err() {
+                    break;
+                }
+                QUEUE_DEPTHS.record_channel("client_response", "top_of_book_events", &tx);
+                stream_usage.record_message();
+            }
+            info!("Client disconnected");
+            event_stream.disconnect().await;
+        });
+
+        let output_stream = ReceiverStream::new(rx);
+        Ok(stream_response(
+            Box::pin(output_stream) as Self::TopOfBookEventsStream
+        ))
+    }
+
+    async fn get_usage(&self, req: Request<UsageRequest>) -> Result<Response<UsageReport>, Status> {
+        info!("OrderbookServer::get_usage");
+        // only the usage of the caller's own API key is reported
+        let caller = api_key(&req);
+        let requested = &req.get_ref().api_key;
+        if !requested.is_empty() && *requested != caller {
+            return Err(Status::permission_denied("usage of another API key"));
+        }
+        let usage = self.usage.get(&caller).map(|u| vec![(caller, u)]).unwrap_or_default();
+        let usage = usage.into_iter().map(|(api_key, u)| ClientUsage {
+            api_key,
+            streams_opened: u.streams_opened,
+            stream_seconds: u.stream_seconds,
+            messages_delivered: u.messages_delivered,
+            symbols: u.symbols.into_iter().collect(),
+        }).collect();
+        Ok(Response::new(UsageReport { usage }))
+    }
+
+    async fn get_sweep_price(&self, req: Request<SweepPriceRequest>) -> Result<Response<SweepPrice>, Status> {
+        info!("OrderbookServer::get_sweep_price");
+        let request = req.get_ref();
+        let side = BookSide::from_i32(request.side)
+            .ok_or_else(|| Status::invalid_argument("unknown book side"))?;
+        let notional = Decimal::from_f64(request.notional)
+            .filter(|notional| *notional > Decimal::ZERO)
+            .ok_or_else(|| Status::invalid_argument("notional must be positive"))?;
+        let symbol = self.query_symbol(&request.symbol).map_err(Status::invalid_argument)?;
+        let book = self.current_book(&symbol).await?;
+        let sweep = book.cumulative(book_side(side), notional)
+            .ok_or_else(|| Status::unavailable("book side is empty"))?;
+        Ok(Response::new(SweepPrice {
+            symbol,
+            side: side as i32,
+            worst_price: sweep.worst_price.to_f64().unwrap_or(f64::NAN),
+            average_price: sweep.average_price.to_f64().unwrap_or(f64::NAN),
+            amount: sweep.amount.to_f64().unwrap_or(f64::NAN),
+            notional: sweep.notional.to_f64().unwrap_or(f64::NAN),
+            complete: sweep.notional >= notional,
+        }))
+    }
+
+    async fn suggest_route(&self, req: Request<RouteRequest>) -> Result<Response<RouteSuggestion>, Status> {
+        info!("OrderbookServer::suggest_route");
+        let request = req.get_ref();
+        let side = BookSide::from_i32(request.side)
+            .ok_or_else(|| Status::invalid_argument("unknown book side"))?;
+        let amount = Decimal::from_f64(request.amount)
+            .filter(|amount| *amount > Decimal::ZERO)
+            .ok_or_else(|| Status::invalid_argument("amount must be positive"))?;
+        let mut router = Router::new();
+        for (exchange_code, fee_bps) in &request.fees_bps {
+            let fee_bps = Decimal::from_f64(*fee_bps)
+                .ok_or_else(|| Status::invalid_argument("invalid fee"))?;
+            router = router.with_fee(exchange_code, fee_bps);
+        }
+        let symbol = self.query_symbol(&request.symbol).map_err(Status::invalid_argument)?;
+        let book = self.current_book(&symbol).await?;
+        let route = router.suggest_split(&book, book_side(side), amount);
+        Ok(Response::new(RouteSuggestion {
+            symbol,
+            side: side as i32,
+            allocations: route.allocations.into_iter().map(|allocation| VenueAllocation {
+                exchange: allocation.exchange_code.to_string(),
+                amount: allocation.amount.to_f64().unwrap_or(f64::NAN),
+                average_price: allocation.average_price.to_f64().unwrap_or(f64::NAN),
+                worst_price: allocation.worst_price.to_f64().unwrap_or(f64::NAN),
+                fees: allocation.fees.to_f64().unwrap_or(f64::NAN),
+            }).collect(),
+            unfilled: route.unfilled.to_f64().unwrap_or(f64::NAN),
+        }))
+    }
+
+    type StreamAlertsStream = AlertResponseStream;
+
+    async fn stream_alerts(&self, req: Request<Empty>) -> Result<Response<AlertResponseStream>, Status> {
+        info!("OrderbookServer::stream_alerts");
+        info!("Client connected from: {:?}", req.remote_addr());
+
+        let (tx, rx) = mpsc::channel(self.queue_capacities.client_response);
+        let mut alerts = select_all(self.served_products().map(|product| alert_events(&product.bus)));
+
+        tokio::spawn(async move {
+            while let Some(event) = alerts.next().await {
+                let alert = Alert {
+                    rule: event.rule,
+                    symbol: event.symbol,
+                    raised: event.raised,
+                    message: event.message,
+                    timestamp_ms: event.timestamp_ms,
+                };
+                if tx.send(Result::<Alert, Status>::Ok(alert)).await.is_err() {
+                    break;
+                }
+                QUEUE_DEPTHS.record_channel("client_response", "stream_alerts", &tx);
+            }
+            info!("Client disconnected");
+        });
+
+        let output_stream = ReceiverStream::new(rx);
+        Ok(stream_response(
+            Box::pin(output_stream) as Self::StreamAlertsStream
+        ))
+    }
+
+    async fn get_configuration(&self, _req: Request<Empty>) -> Result<Response<Configuration>, Status> {
+        info!("OrderbookServer::get_configuration");
+        Ok(Response::new(Configuration { json: self.effective_config().to_json() }))
+    }
+
+    async fn get_server_info(&self, _req: Request<Empty>) -> Result<Response<ServerInfo>, Status> {
+        info!("OrderbookServer::get_server_info");
+        Ok(Response::new(ServerInfo {
+            version: SERVER_VERSION.to_string(),
+            git_hash: GIT_HASH.to_string(),
+            protocol_version: PROTOCOL_VERSION,
+        }))
+    }
+
+    async fn ping(&self, req: Request<PingRequest>) -> Result<Response<PingResponse>, Status> {
+        // not logged, as clients may probe frequently
+        let server_received_at = Timestamp::now();
+        Ok(Response::new(PingResponse {
+            client_sent_at: req.into_inner().client_sent_at,
+            server_received_at: Some(server_received_at),
+            server_sent_at: Some(Timestamp::now()),
+        }))
+    }
+
+    async fn list_symbols(&self, _req: Request<Empty>) -> Result<Response<SymbolList>, Status> {
+        info!("OrderbookServer::list_symbols");
+        let symbols = self.served_venues().await.into_iter().map(|(symbol, exchanges, board)| {
+            let venues: Vec<SymbolVenue> = exchanges.iter().map(|exchange| {
+                let status = board.get(&exchange.code());
+                SymbolVenue {
+                    exchange: exchange.code().to_string(),
+                    healthy: status.map(|status| status.is_healthy()).unwrap_or(false),
+                    status: status.map(|status| status.name()).unwrap_or("unknown").to_string(),
+                }
+            }).collect();
+            SymbolInfo {
+                symbol,
+                healthy: venues.iter().any(|venue| venue.healthy),
+                venues,
+            }
+        }).collect();
+        Ok(Response::new(SymbolList { symbols }))
+    }
+
+    async fn list_exchanges(&self, _req: Request<Empty>) -> Result<Response<ExchangeList>, Status> {
+        info!("OrderbookServer::list_exchanges");
+        let exchanges = self.served_venues().await.into_iter().flat_map(|(symbol, exchanges, board)| {
+            exchanges.iter().map(move |exchange| {
+                let capabilities = exchange.capabilities();
+                ExchangeInfo {
+                    code: exchange.code().to_string(),
+                    updates: capabilities.updates.name().to_string(),
+                    max_depth: capabilities.max_depth.unwrap_or_default() as u32,
+                    heartbeat: capabilities.heartbeat.name().to_string(),
+                    region: capabilities.region.to_string(),
+                    state: board.get(&exchange.code()).map(|status| status.name()).unwrap_or("unknown").to_string(),
+                    endpoint: exchange.active_endpoint(),
+                    symbol: symbol.clone(),
+                }
+            }).collect::<Vec<ExchangeInfo>>()
+        }).collect();
+        Ok(Response::new(ExchangeList { exchanges }))
+    }
+
+    async fn set_book_diff_log(&self, req: Request<BookDiffLogRequest>) -> Result<Response<BookDiffLogStatus>, Status> {
+        info!("OrderbookServer::set_book_diff_log");
+        let request = req.get_ref();
+        let symbol = self.query_symbol(&request.symbol).map_err(Status::invalid_argument)?;
+        if request.duration_ms == 0 {
+            book_diff::stop();
+        } else {
+            let window = Duration::from_millis(request.duration_ms).min(MAX_BOOK_DIFF_WINDOW);
+            book_diff::start(Path::new(BOOK_DIFF_DIR), &symbol, window)
+                .map_err(|error| Status::internal(format!("book diff log not created: {}", error)))?;
+            self.feed(&symbol).await;
+        }
+        Ok(Response::new(match book_diff::status() {
+            Some(status) => BookDiffLogStatus {
+                active: true,
+                symbol: status.symbol,
+                path: status.path.display().to_string(),
+                remaining_ms: status.remaining.as_millis() as u64,
+            },
+            None => BookDiffLogStatus { active: false, ..Default::default() },
+        }))
+    }
+
+    async fn get_quote_share(&self, req: Request<Empty>) -> Result<Response<QuoteShare>, Status> {
+        info!("OrderbookServer::get_quote_share");
+        let symbol = self.query_symbol(symbol_selection(&req).map_err(Status::invalid_argument)?.unwrap_or_default()).map_err(Status::invalid_argument)?;
+        let quote_share = self.quote_share(&symbol).await.expect("symbol resolved among the ones served");
+        let shares = quote_share.borrow().clone().unwrap_or_default();
+        let exchanges: BTreeSet<&String> = shares.best_bid.keys().chain(shares.best_ask.keys()).collect();
+        let venues = exchanges.into_iter().map(|exchange| VenueQuoteShare {
+            exchange: exchange.clone(),
+            best_bid_share: shares.best_bid.get(exchange).copied().unwrap_or_default(),
+            best_ask_share: shares.best_ask.get(exchange).copied().unwrap_or_default(),
+        }).collect();
+        Ok(Response::new(QuoteShare {
+            symbol,
+            window_ms: QUOTE_SHARE_WINDOW.as_millis() as u64,
+            covered_ms: shares.covered.as_millis() as u64,
+            venues,
+        }))
+    }
+
+    async fn get_snapshots(&self, req: Request<SymbolList>) -> Result<Response<SummaryBatch>, Status> {
+        info!("OrderbookServer::get_snapshots");
+        let listed = &req.get_ref().symbols;
+        if listed.len() > MAX_SNAPSHOT_SYMBOLS {
+            return Err(Status::invalid_argument(format!("at most {} symbols per request, got {}", MAX_SNAPSHOT_SYMBOLS, listed.len())));
+        }
+        let requested: Vec<String> = match listed.is_empty() {
+            true => self.served_symbols(),
+            false => listed.iter().map(|info| info.symbol.clone()).collect(),
+        };
+        // the symbols are looked up concurrently, all of them up to the same deadline
+        let deadline = Instant::now() + FEED_WARMUP_TIMEOUT;
+        let summaries = join_all(requested.iter().map(|symbol| async move {
+            match canonicalize(symbol) {
+                Some(canonical) => self.current_symbol_summary(&canonical, deadline).await,
+                None => None,
+            }
+        })).await;
+        let mut batch = SummaryBatch::default();
+        for (symbol, summary) in requested.into_iter().zip(summaries) {
+            match summary {
+                Some(summary) => batch.summaries.push(summary),
+                None => batch.missing.push(symbol),
+            }
+        }
+        Ok(Response::new(batch))
+    }
+
+    type SubscribeStream = ResponseStream;
+
+    async fn subscribe(&self, req: Request<Streaming<SubscriptionControl>>) -> SummaryResult {
+        info!("OrderbookServer::subscribe");
+        info!("Client connected from: {:?}", req.remote_addr());
+
+        // the stream of controls is not shared between threads, unlike the metadata
+        let (metadata, extensions, mut controls) = req.into_parts();
+        let req = Request::from_parts(metadata, extensions, ());
+        let SummarySubscription { snapshot, mut multiplexer, mut stream_usage } = self.open_subscription(&req, &BookSummaryRequest::default()).await?;
+        let (tx, rx) = mpsc::channel(self.queue_capacities.client_response);
+
+        tokio::spawn(async move {
+            let mut pause_buffer = PauseBuffer::new();
+            let mut controls_open = true;
+            let mut items: Vec<Summary> = snapshot.into_iter().collect();
+            'stream: loop {
+                for mut item in items.drain(..) {
+                    latency_budget::complete(&mut item);
+                    if tx.send(Result::<Summary, Status>::Ok(item)).await.is_err() {
+                        break 'stream;
+                    }
+                    QUEUE_DEPTHS.record_channel("client_response", "subscribe", &tx);
+                    stream_usage.record_message();
+                }
+                tokio::select! {
+                    control = controls.next(), if controls_open => match control {
+                        Some(Ok(control)) => match control.action() {
+                            SubscriptionAction::Pause => {
+                                info!("Subscription paused");
+                                pause_buffer.pause();
+                            },
+                            SubscriptionAction::Resume => {
+                                if pause_buffer.is_paused() {
+                                    info!("Subscription resumed");
+                                }
+                                items = pause_buffer.resume();
+                            },
+                        },
+                        // the client may stop sending controls and keep receiving summaries
+                        Some(Err(_)) | None => controls_open = false,
+                    },
+                    item = multiplexer.next() => match item {
+                        Some(item) => match stale_expiry(&item) {
+                            Some(status) => {
+                                let _ = tx.send(Err(status)).await;
+                                break 'stream;
+                            },
+                            None => items.extend(pause_buffer.offer(item)),
+                        },
+                        None => break 'stream,
+                    },
+                    _ = tx.closed() => break 'stream,
+                }
+            }
+            info!("Client disconnected");
+            for service in multiplexer.into_sources() {
+                service.disconnect().await;
+            }
+        });
+
+        let output_stream = ReceiverStream::new(rx);
+        Ok(stream_response(
+            Box::pin(output_stream) as Self::SubscribeStream
+        ))
+    }
+
+    type VolatilityStreamStream = VolatilityResponseStream;
+
+    async fn volatility_stream(&self, req: Request<Empty>) -> Result<Response<VolatilityResponseStream>, Status> {
+        info!("OrderbookServer::volatility_stream");
+        info!("Client connected from: {:?}", req.remote_addr());
+
+        let symbol = self.query_symbol(symbol_selection(&req).map_err(Status::invalid_argument)?.unwrap_or_default()).map_err(Status::invalid_argument)?;
+        let (tx, rx) = mpsc::channel(self.queue_capacities.client_response);
+        let mut volatility = self.volatility(&symbol).await.expect("symbol resolved among the ones served");
+        let mut stream_usage = self.usage.start_stream(&api_key(&req), &symbol);
+
+        tokio::spawn(async move {
+            while volatility.changed().await.is_ok() {
+                let maybe_estimate = volatility.borrow_and_update().clone();
+                if let Some(estimate) = maybe_estimate {
+                    let item = Volatility {
+                        symbol: estimate.symbol,
+                        mid: estimate.mid,
+                        horizons: estimate.horizons.into_iter().map(|(horizon, volatility_bps)| HorizonVolatility {
+                            horizon_ms: horizon.as_millis() as u32,
+                            volatility_bps,
+                        }).collect(),
+                    };
+                    if tx.send(Result::<Volatility, Status>::Ok(item)).await.is_err() {
+                        break;
+                    }
+                    QUEUE_DEPTHS.record_channel("client_response", "volatility_stream", &tx);
+                    stream_usage.record_message();
+                }
+            }
+            info!("Client disconnected");
+        });
+
+        let output_stream = ReceiverStream::new(rx);
+        Ok(stream_response(
+            Box::pin(output_stream) as Self::VolatilityStreamStream
+        ))
+    }
+}
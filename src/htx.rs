@@ -0,0 +1,191 @@
+//! HTX (formerly Huobi) `WebSocket` exchange adapter for the `depth.step0` snapshots. HTX sends
+//! gzip-compressed binary frames, [decoded](decode_gzip_frame) before parsing, and
+//! closes the connections which do not answer its heartbeats, `{"ping":<timestamp>}`, with
+//! `{"pong":<timestamp>}`.
+
+use flate2::read::GzDecoder;
+use log::debug;
+use serde::Deserialize;
+use serde_json::Value;
+use std::io::Read;
+use std::sync::Arc;
+
+use crate::capabilities::{ExchangeCapabilities, HeartbeatKind, UpdateKind};
+use crate::core::*;
+use crate::exchange::{ExchangeAdapter, ExchangeProtocol, SubscriptionRequest};
+use crate::numbers::json_decimal;
+use crate::request_ids::REQUEST_ID_PLACEHOLDER;
+
+
+const HTX_CODE: &str = "htx";
+const HTX_WS_URL: &str = "wss://api.huobi.pro/ws";
+/// Region of the HTX endpoints.
+const HTX_REGION: &str = "ap-northeast-1";
+/// Levels of each side of the snapshots of the `depth.step0` topic.
+const HTX_SNAPSHOT_LEVELS: usize = 150;
+
+
+/// Parse string messages from the HTX WebSocket service, once decompressed, into the exchange
+/// [protocol](ExchangeProtocol).
+/// It recognizes book snapshots, the responses to subscriptions, by identifier, and the
+/// heartbeats, answered with the same timestamp.
+fn read_htx_book_update(value: &str) -> Option<ExchangeProtocol<BookUpdate>> {
+    let protocol = match serde_json::from_str::<HtxMessage>(value) {
+        Ok(HtxMessage::Ping { ping }) => Some(ExchangeProtocol::Reply(format!(r#"{{"pong":{}}}"#, ping))),
+        Ok(HtxMessage::Tick { tick }) => htx_book_update(tick).map(ExchangeProtocol::Data),
+        Ok(HtxMessage::Response { id, status }) if status == "ok" => id.parse().ok().map(ExchangeProtocol::Response),
+        Ok(HtxMessage::Response { .. }) | Err(_) => None,
+    };
+    if protocol.is_none() {
+        debug!("Parse failed {:?}", value);
+    }
+    protocol
+}
+
+/// Decompress a gzip `WebSocket` frame into text, as the [frame decoder](crate::exchange::FrameDecoder)
+/// of HTX.
+///
+/// # Arguments
+///
+/// * `frame` - The payload of the binary frame.
+///
+/// # Returns
+///
+/// The text of the frame, or the reason why it is invalid.
+pub fn decode_gzip_frame(frame: &[u8]) -> Result<String, String> {
+    let mut text = String::new();
+    GzDecoder::new(frame).read_to_string(&mut text).map_err(|error| format!("invalid gzip frame: {}", error))?;
+    Ok(text)
+}
+
+/// Format a currency pair as in HTX symbols, e.g. `ethbtc`.
+pub fn htx_symbol(product: &CurrencyPair) -> String {
+    product.to_string().to_lowercase()
+}
+
+/// Format the request subscribing to, or unsubscribing from, the snapshot topic of a currency
+/// pair, answered by identifier.
+fn htx_request(product: &CurrencyPair, request: SubscriptionRequest) -> String {
+    let kind = match request {
+        SubscriptionRequest::Subscribe => "sub",
+        SubscriptionRequest::Unsubscribe => "unsub",
+    };
+    format!(r#"{{"{}":"market.{}.depth.step0","id":"{}"}}"#, kind, htx_symbol(product), REQUEST_ID_PLACEHOLDER)
+}
+
+/// Creates an [exchange adapter](ExchangeAdapter) for HTX.
+pub fn make_htx_exchange_adapter(product: &CurrencyPair) -> ExchangeAdapter<BookUpdate> {
+    ExchangeAdapter::new(HTX_CODE, String::from(HTX_WS_URL), Arc::new(read_htx_book_update))
+        .with_frame_decoder(Arc::new(decode_gzip_frame))
+        .with_subscribe_message(htx_request(product, SubscriptionRequest::Subscribe))
+        .with_subscription_formatter(Arc::new(htx_request))
+        .with_subscription_acks()
+        .with_capabilities(ExchangeCapabilities {
+            updates: UpdateKind::Snapshots,
+            max_depth: Some(HTX_SNAPSHOT_LEVELS),
+            heartbeat: HeartbeatKind::Application,
+            region: HTX_REGION,
+        })
+}
+
+/// A message of the HTX WebSocket service, by its fields.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum HtxMessage {
+    /// Heartbeat, with the timestamp to return.
+    Ping {
+        ping: u64,
+    },
+    /// Snapshot of a topic subscribed.
+    Tick {
+        tick: HtxTick,
+    },
+    /// Response to the request with the given identifier, `ok` or `error`.
+    Response {
+        id: String,
+        status: String,
+    },
+}
+
+/// Price and amount of a level, as JSON numbers.
+#[derive(Deserialize, Debug)]
+struct HtxPair((Value, Value));
+
+#[derive(Deserialize, Debug)]
+struct HtxTick {
+    bids: Vec<HtxPair>,
+    asks: Vec<HtxPair>,
+}
+
+/// Internal function converting the best levels of a side, [None](None) if any number is invalid.
+fn htx_levels(pairs: Vec<HtxPair>) -> Option<Vec<ExchangeLevel>> {
+    pairs.into_iter()
+        .take(NUM_LEVELS)
+        .map(|HtxPair((price, amount))| Some(ExchangeLevel {
+            exchange_code: HTX_CODE.into(),
+            price: json_decimal(&price)?,
+            amount: json_decimal(&amount)?,
+            order_count: None,
+        }))
+        .collect()
+}
+
+/// Internal function converting a snapshot, [None](None) if any number is invalid.
+fn htx_book_update(tick: HtxTick) -> Option<BookUpdate> {
+    Some(BookUpdate {
+        exchange_code: HTX_CODE.into(),
+        bids: htx_levels(tick.bids)?,
+        asks: htx_levels(tick.asks)?,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(value: &str) -> Vec<u8> {
+        (0..value.len()).step_by(2).map(|i| u8::from_str_radix(&value[i..i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_decode_gzip_frame() {
+        let ping = hex("1f8b0800000000000203ab562ac8cc4b57b23234b134323132303137363032af05006c9fbb7a16000000");
+        assert_eq!(decode_gzip_frame(&ping), Ok(String::from(r#"{"ping":1492420473027}"#)));
+        assert!(decode_gzip_frame(r#"{"ping":1492420473027}"#.as_bytes()).is_err());
+        assert!(decode_gzip_frame(&ping[..ping.len() - 4]).is_err());
+        let mut corrupted = ping.clone();
+        let last = corrupted.len() - 5;
+        corrupted[last] ^= 1;
+        assert!(decode_gzip_frame(&corrupted).is_err());
+    }
+
+    #[test]
+    fn test_read_htx_book_update() {
+        let message = r#"{"ch":"market.ethbtc.depth.step0","ts":1686727555138,"tick":{"bids":[[0.0612,1.5]],"asks":[[0.0613,1],[0.0614,3]],"version":100,"ts":1686727555000}}"#;
+        assert_eq!(read_htx_book_update(message), Some(ExchangeProtocol::Data(BookUpdate {
+            exchange_code: HTX_CODE.into(),
+            bids: vec![ExchangeLevel::from_strs(HTX_CODE, "0.0612", "1.5")],
+            asks: vec![
+                ExchangeLevel::from_strs(HTX_CODE, "0.0613", "1"),
+                ExchangeLevel::from_strs(HTX_CODE, "0.0614", "3"),
+            ],
+        })));
+        let incorrect = r#"{"ch":"market.ethbtc.depth.step0","tick":{"bids":[["0.0612",1.5]],"asks":[]}}"#;
+        assert_eq!(read_htx_book_update(incorrect), None);
+    }
+
+    #[test]
+    fn test_read_htx_responses() {
+        assert_eq!(read_htx_book_update(r#"{"ping":1492420473027}"#), Some(ExchangeProtocol::Reply(String::from(r#"{"pong":1492420473027}"#))));
+        assert_eq!(read_htx_book_update(r#"{"id":"1","status":"ok","subbed":"market.ethbtc.depth.step0","ts":1686727555138}"#), Some(ExchangeProtocol::Response(1)));
+        assert_eq!(read_htx_book_update(r#"{"status":"error","ts":1686727555138,"id":"2","err-code":"bad-request","err-msg":"invalid topic market.ethxxx.depth.step0"}"#), None);
+    }
+
+    #[test]
+    fn test_htx_request() {
+        let product = CurrencyPair { main: "eth".to_string(), counter: "btc".to_string() };
+        assert_eq!(htx_request(&product, SubscriptionRequest::Subscribe), r#"{"sub":"market.ethbtc.depth.step0","id":"{request_id}"}"#);
+        assert_eq!(htx_request(&product, SubscriptionRequest::Unsubscribe), r#"{"unsub":"market.ethbtc.depth.step0","id":"{request_id}"}"#);
+    }
+}
@@ -0,0 +1,285 @@
+//! Optional sink emitting spread, mid, depth and per-exchange best bid and offer of the
+//! consolidated book in InfluxDB line protocol, over UDP or HTTP, at regular intervals.
+
+use hyper::{Body, Client, Method, Request, client::HttpConnector};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::net::UdpSocket;
+use tokio::time::Duration;
+
+use crate::config::{DocumentedConfig, Validate, Validator};
+use crate::feed::{venue_bbos, SummaryMetrics, VenueBbo};
+use crate::orderbook::Summary;
+use crate::sink::{SinkError, SinkResult, SummarySink};
+
+
+/// Measurement of the consolidated book figures.
+const SUMMARY_MEASUREMENT: &str = "orderbook";
+/// Measurement of the best bid and offer of each exchange.
+const VENUE_MEASUREMENT: &str = "orderbook_venue";
+/// Default time between two snapshots.
+const DEFAULT_INTERVAL_MS: u64 = 1000;
+/// Default destination of the points.
+const DEFAULT_URL: &str = "http://localhost:8086/write?db=orderbook";
+/// Scheme of the URLs of UDP listeners, e.g. `udp://127.0.0.1:8089`.
+const UDP_SCHEME: &str = "udp://";
+
+
+/// Where line protocol points are sent.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InfluxTransport {
+    /// UDP listener of InfluxDB or Telegraf.
+    Udp(SocketAddr),
+    /// HTTP write endpoint, e.g. `http://localhost:8086/write?db=orderbook`.
+    Http(String),
+}
+
+/// Settings of the InfluxDB sink, read from a JSON file. Missing values take their defaults.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct InfluxSinkConfig {
+    /// HTTP write endpoint, or UDP listener as `udp://<ip>:<port>`.
+    pub url: String,
+    /// Time between two snapshots, in milliseconds.
+    pub interval_ms: u64,
+}
+
+impl Default for InfluxSinkConfig {
+    fn default() -> Self {
+        Self { url: DEFAULT_URL.to_string(), interval_ms: DEFAULT_INTERVAL_MS }
+    }
+}
+
+impl InfluxSinkConfig {
+    /// The destination of the points.
+    ///
+    /// # Returns
+    ///
+    /// An [InfluxTransport](InfluxTransport), or the reason why the URL is not valid.
+    pub fn transport(&self) -> Result<InfluxTransport, String> {
+        match self.url.strip_prefix(UDP_SCHEME) {
+            Some(address) => address.parse().map(InfluxTransport::Udp)
+                .map_err(|_| format!("must be udp://<ip>:<port>, not \"{}\"", self.url)),
+            None => Ok(InfluxTransport::Http(self.url.clone())),
+        }
+    }
+}
+
+impl Validate for InfluxSinkConfig {
+    fn validate(&self, validator: &mut Validator) {
+        match self.transport() {
+            Ok(InfluxTransport::Http(url)) => validator.url("url", &url, &["http", "udp"]),
+            Ok(InfluxTransport::Udp(_)) => (),
+            Err(message) => validator.error("url", &message),
+        }
+        validator.positive("interval_ms", self.interval_ms);
+    }
+}
+
+impl DocumentedConfig for InfluxSinkConfig {
+    const DESCRIPTION: &'static str = "Settings of the InfluxDB sink.";
+    const FIELDS: &'static [(&'static str, &'static str)] = &[
+        ("url", "HTTP write endpoint, or UDP listener as `udp://<ip>:<port>`."),
+        ("interval_ms", "Time between two snapshots, in milliseconds."),
+    ];
+}
+
+/// Escape a tag value, as required by line protocol.
+fn escape_tag(value: &str) -> String {
+    value.replace(',', "\\,").replace('=', "\\=").replace(' ', "\\ ")
+}
+
+/// Format a line protocol point, omitting fields which are not finite.
+///
+/// # Returns
+///
+/// The point, or `None` if no field is finite.
+fn point(measurement: &str, tags: &[(&str, &str)], fields: &[(&str, f64)], timestamp_ns: u128) -> Option<String> {
+    let fields: Vec<String> = fields.iter()
+        .filter(|(_, value)| value.is_finite())
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect();
+    if fields.is_empty() {
+        return None;
+    }
+    let tags: String = tags.iter().map(|(name, value)| format!(",{}={}", name, escape_tag(value))).collect();
+    Some(format!("{}{} {} {}", measurement, tags, fields.join(","), timestamp_ns))
+}
+
+/// Format the points of a snapshot in line protocol.
+///
+/// # Arguments
+///
+/// * `symbol` - The canonical symbol of the currency pair.
+///
+/// * `metrics` - The [metrics](SummaryMetrics) of the consolidated book.
+///
+/// * `venues` - The [best bid and offer](VenueBbo) of each exchange.
+///
+/// * `timestamp_ns` - Snapshot time, in nanoseconds since the epoch.
+///
+/// # Returns
+///
+/// The points, one per line.
+pub fn line_protocol(symbol: &str, metrics: &SummaryMetrics, venues: &[VenueBbo], timestamp_ns: u128) -> String {
+    let summary_point = point(
+        SUMMARY_MEASUREMENT,
+        &[("symbol", symbol)],
+        &[
+            ("spread", metrics.spread),
+            ("mid", metrics.mid),
+            ("best_bid", metrics.best_bid),
+            ("best_ask", metrics.best_ask),
+            ("bid_depth", metrics.bid_depth),
+            ("ask_depth", metrics.ask_depth),
+        ],
+        timestamp_ns,
+    );
+    let venue_points = venues.iter().filter_map(|venue| point(
+        VENUE_MEASUREMENT,
+        &[("symbol", symbol), ("exchange", &venue.exchange)],
+        &[("best_bid", venue.best_bid), ("best_ask", venue.best_ask)],
+        timestamp_ns,
+    ));
+    let lines: Vec<String> = summary_point.into_iter().chain(venue_points).collect();
+    lines.join("\n")
+}
+
+/// Sink sending line protocol points to InfluxDB.
+pub struct InfluxSink {
+    /// Destination of the points.
+    transport: InfluxTransport,
+    /// Time between two snapshots.
+    interval: Duration,
+    /// Socket sending the points over UDP, bound on first use.
+    udp_socket: Option<UdpSocket>,
+    /// Client sending the points over HTTP.
+    http_client: Client<HttpConnector>,
+}
+
+impl InfluxSink {
+    /// Create a new [InfluxSink](InfluxSink) object, sending a snapshot every second by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `transport` - The [InfluxTransport](InfluxTransport).
+    pub fn new(transport: InfluxTransport) -> Self {
+        Self { transport, interval: Duration::from_millis(DEFAULT_INTERVAL_MS), udp_socket: None, http_client: Client::new() }
+    }
+
+    /// Create a new [InfluxSink](InfluxSink) object from a configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The [InfluxSinkConfig](InfluxSinkConfig).
+    ///
+    /// # Returns
+    ///
+    /// An [InfluxSink](InfluxSink) object, or the reason why the URL is not valid.
+    pub fn from_config(config: &InfluxSinkConfig) -> Result<Self, String> {
+        Ok(Self::new(config.transport()?).with_interval(Duration::from_millis(config.interval_ms)))
+    }
+
+    /// Set the time between two snapshots.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - The time between two snapshots.
+    ///
+    /// # Returns
+    ///
+    /// The modified [InfluxSink](InfluxSink).
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+}
+
+#[tonic::async_trait]
+impl SummarySink for InfluxSink {
+    fn name(&self) -> &'static str {
+        "influx"
+    }
+
+    fn sample_interval(&self) -> Option<Duration> {
+        Some(self.interval)
+    }
+
+    async fn on_summary(&mut self, summary: &Summary) -> SinkResult {
+        let timestamp_ns = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let lines = line_protocol(&summary.symbol, &SummaryMetrics::from(summary), &venue_bbos(summary), timestamp_ns);
+        if lines.is_empty() {
+            return Ok(());
+        }
+        match &self.transport {
+            InfluxTransport::Udp(address) => {
+                if self.udp_socket.is_none() {
+                    let socket = UdpSocket::bind("0.0.0.0:0").await
+                        .map_err(|error| SinkError::Fatal(format!("error binding UDP socket: {:?}", error)))?;
+                    self.udp_socket = Some(socket);
+                }
+                if let Some(socket) = &self.udp_socket {
+                    socket.send_to(lines.as_bytes(), address).await
+                        .map_err(|error| SinkError::Transient(format!("error sending points to InfluxDB: {:?}", error)))?;
+                }
+                Ok(())
+            },
+            InfluxTransport::Http(url) => {
+                let request = Request::builder()
+                    .method(Method::POST)
+                    .uri(url)
+                    .body(Body::from(lines))
+                    .map_err(|error| SinkError::Fatal(format!("invalid InfluxDB URL: {:?}", error)))?;
+                match self.http_client.request(request).await {
+                    Ok(response) if !response.status().is_success() =>
+                        Err(SinkError::Transient(format!("InfluxDB rejected points: {}", response.status()))),
+                    Ok(_) => Ok(()),
+                    Err(error) => Err(SinkError::Transient(format!("error sending points to InfluxDB: {:?}", error))),
+                }
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_protocol() {
+        let metrics = SummaryMetrics {
+            best_bid: 99.0,
+            best_bid_exchange: "test1".to_string(),
+            best_ask: 100.0,
+            best_ask_exchange: "test2".to_string(),
+            mid: 99.5,
+            spread: 1.0,
+            bid_depth: 3.0,
+            ask_depth: 4.5,
+        };
+        let venues = vec![
+            VenueBbo { exchange: "test1".to_string(), best_bid: 99.0, best_ask: f64::NAN },
+            VenueBbo { exchange: "test 2".to_string(), best_bid: 98.0, best_ask: 100.0 },
+            VenueBbo { exchange: "test3".to_string(), best_bid: f64::NAN, best_ask: f64::NAN },
+        ];
+        assert_eq!(
+            line_protocol("ETH-BTC", &metrics, &venues, 1000),
+            "orderbook,symbol=ETH-BTC spread=1,mid=99.5,best_bid=99,best_ask=100,bid_depth=3,ask_depth=4.5 1000\n\
+             orderbook_venue,symbol=ETH-BTC,exchange=test1 best_bid=99 1000\n\
+             orderbook_venue,symbol=ETH-BTC,exchange=test\\ 2 best_bid=98,best_ask=100 1000"
+        );
+    }
+
+    #[test]
+    fn test_config_transport() {
+        let config: InfluxSinkConfig = serde_json::from_str(r#"{"url": "udp://127.0.0.1:8089"}"#).unwrap();
+        assert_eq!(config.interval_ms, DEFAULT_INTERVAL_MS);
+        assert_eq!(config.transport(), Ok(InfluxTransport::Udp("127.0.0.1:8089".parse().unwrap())));
+        assert_eq!(InfluxSinkConfig::default().transport(), Ok(InfluxTransport::Http(DEFAULT_URL.to_string())));
+        assert!(InfluxSinkConfig { url: "udp://localhost".to_string(), ..config }.transport().is_err());
+    }
+}
@@ -0,0 +1,152 @@
+//! Guards applied by the [exchange adapters](crate::exchange::ExchangeAdapter) to each message
+//! received, before it is parsed: messages too large, or carrying too many levels, are rejected
+//! so that pathological or malicious frames cannot exhaust the parser or the memory.
+//! Rejections are counted by exchange in [REJECTED_MESSAGES](crate::metrics::REJECTED_MESSAGES).
+//! Once parsed, the decimals of the books can be [normalized](crate::core::BookUpdate::normalize),
+//! so that a price formatted differently by different exchanges has a single representation.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::config::{DocumentedConfig, Validate, Validator};
+
+
+/// Default maximum size of a message, in bytes.
+const DEFAULT_MAX_MESSAGE_BYTES: usize = 1 << 20;
+/// Default maximum number of levels of a message, bids and asks together.
+const DEFAULT_MAX_LEVELS: usize = 5000;
+
+
+/// Limits of the messages received from the exchanges. Missing values take their defaults.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct IngestLimits {
+    /// Maximum size of a message, in bytes.
+    pub max_message_bytes: usize,
+    /// Maximum number of levels of a message, bids and asks together.
+    pub max_levels: usize,
+    /// Whether the prices and amounts of the books parsed are normalized.
+    pub normalize_decimals: bool,
+}
+
+impl Default for IngestLimits {
+    fn default() -> Self {
+        Self {
+            max_message_bytes: DEFAULT_MAX_MESSAGE_BYTES,
+            max_levels: DEFAULT_MAX_LEVELS,
+            normalize_decimals: true,
+        }
+    }
+}
+
+/// Reason why a message was rejected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IngestRejection {
+    /// The message is larger than the maximum size.
+    TooLarge { bytes: usize },
+    /// The message carries more levels than the maximum.
+    TooManyLevels { levels: usize },
+}
+
+impl fmt::Display for IngestRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IngestRejection::TooLarge { bytes } => write!(f, "message of {} bytes", bytes),
+            IngestRejection::TooManyLevels { levels } => write!(f, "message with {} levels", levels),
+        }
+    }
+}
+
+impl IngestLimits {
+    /// Check a message against the limits, without parsing it.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The message.
+    ///
+    /// # Returns
+    ///
+    /// An [IngestRejection](IngestRejection) if the message exceeds a limit.
+    pub fn check(&self, text: &str) -> Result<(), IngestRejection> {
+        if text.len() > self.max_message_bytes {
+            return Err(IngestRejection::TooLarge { bytes: text.len() });
+        }
+        let levels = count_levels(text);
+        if levels > self.max_levels {
+            return Err(IngestRejection::TooManyLevels { levels });
+        }
+        Ok(())
+    }
+}
+
+impl Validate for IngestLimits {
+    fn validate(&self, validator: &mut Validator) {
+        validator.positive("max_message_bytes", self.max_message_bytes as u64);
+        validator.positive("max_levels", self.max_levels as u64);
+    }
+}
+
+impl DocumentedConfig for IngestLimits {
+    const DESCRIPTION: &'static str = "Limits of the messages received from the exchanges.";
+    const FIELDS: &'static [(&'static str, &'static str)] = &[
+        ("max_message_bytes", "Maximum size of a message, in bytes."),
+        ("max_levels", "Maximum number of levels of a message, bids and asks together."),
+        ("normalize_decimals", "Whether the prices and amounts of the books parsed are normalized."),
+    ];
+}
+
+/// Count the levels of a message without parsing it. Both exchanges send each level as an
+/// array of scalars, e.g. `["0.0612","1.2"]`, so the levels are the innermost non-empty
+/// arrays. Brackets inside strings are counted too, which can only overestimate.
+///
+/// # Arguments
+///
+/// * `text` - The message.
+///
+/// # Returns
+///
+/// The number of levels.
+pub fn count_levels(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let mut levels = 0;
+    for (i, &byte) in bytes.iter().enumerate() {
+        if byte == b'[' {
+            match bytes[i + 1..].iter().find(|b| !b.is_ascii_whitespace()) {
+                Some(b'[') | Some(b']') => (),
+                _ => levels += 1,
+            }
+        }
+    }
+    levels
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_levels() {
+        assert_eq!(count_levels(r#"{"bids":[["1","2"],["3","4"]],"asks":[ [ "5","6"] ]}"#), 3);
+        assert_eq!(count_levels(r#"{"bids":[],"asks":[]}"#), 0);
+        assert_eq!(count_levels(r#"{"result":null,"id":1}"#), 0);
+    }
+
+    #[test]
+    fn test_check() {
+        let limits = IngestLimits { max_message_bytes: 64, max_levels: 2, normalize_decimals: true };
+        assert_eq!(limits.check(r#"{"bids":[["1","2"]],"asks":[["3","4"]]}"#), Ok(()));
+        assert_eq!(
+            limits.check(r#"{"bids":[["1","2"],["3","4"]],"asks":[["5","6"]]}"#),
+            Err(IngestRejection::TooManyLevels { levels: 3 }),
+        );
+        let large = format!(r#"{{"padding":"{}"}}"#, "x".repeat(64));
+        assert_eq!(limits.check(&large), Err(IngestRejection::TooLarge { bytes: large.len() }));
+    }
+
+    #[test]
+    fn test_partial_config() {
+        let limits: IngestLimits = serde_json::from_str(r#"{"max_levels": 200}"#).unwrap();
+        assert_eq!(limits, IngestLimits { max_levels: 200, ..IngestLimits::default() });
+    }
+}
@@ -0,0 +1,222 @@
+//! KuCoin `WebSocket` exchange adapter for the `level2Depth50` snapshots. KuCoin requires a token
+//! before each connection: the adapter [bootstraps](crate::exchange::ExchangeAdapter::with_bootstrap)
+//! with a request to its REST service, returning the WebSocket endpoint and the token.
+
+use log::debug;
+use rust_decimal::prelude::*;
+use serde::Deserialize;
+use std::sync::Arc;
+use tokio::time::Duration;
+
+use crate::capabilities::{ExchangeCapabilities, HeartbeatKind, UpdateKind};
+use crate::core::*;
+use crate::exchange::{Bootstrap, ExchangeAdapter, ExchangeProtocol, SubscriptionRequest};
+use crate::request_ids::REQUEST_ID_PLACEHOLDER;
+use crate::rest::post;
+use crate::symbols::canonical_symbol;
+
+
+const KUCOIN_CODE: &str = "kucoin";
+/// REST endpoint issuing the tokens of the public WebSocket service.
+const KUCOIN_BULLET_URL: &str = "https://api.kucoin.com/api/v1/bullet-public";
+/// Region of the KuCoin endpoints.
+const KUCOIN_REGION: &str = "ap-northeast-1";
+/// Levels of each side of the snapshots of the `level2Depth50` topic.
+const KUCOIN_SNAPSHOT_LEVELS: usize = 50;
+/// Heartbeat request, without which KuCoin closes the connection.
+const KUCOIN_PING_MESSAGE: &str = r#"{"id":"keepalive","type":"ping"}"#;
+/// Interval between two heartbeat requests, the one advertised by KuCoin with the tokens.
+const KUCOIN_PING_INTERVAL: Duration = Duration::from_secs(18);
+
+
+/// Parse string messages from the KuCoin WebSocket service into the exchange
+/// [protocol](ExchangeProtocol).
+/// It recognizes book snapshots, the responses to subscriptions, by identifier, and the
+/// welcome and heartbeat messages.
+fn read_kucoin_book_update(value: &str) -> Option<ExchangeProtocol<BookUpdate>> {
+    let protocol = match serde_json::from_str::<KucoinMessage>(value) {
+        Ok(KucoinMessage::Message { data }) => BookUpdate::try_from(data).ok().map(ExchangeProtocol::Data),
+        Ok(KucoinMessage::Ack { id }) => id.parse().ok().map(ExchangeProtocol::Response),
+        Ok(KucoinMessage::Welcome | KucoinMessage::Pong) => Some(ExchangeProtocol::Skipped),
+        Err(_) => None,
+    };
+    if protocol.is_none() {
+        debug!("Parse failed {:?}", value);
+    }
+    protocol
+}
+
+/// Parse the response of the token request into the WebSocket URL to connect to.
+///
+/// # Arguments
+///
+/// * `value` - The response of the KuCoin REST service.
+///
+/// # Returns
+///
+/// The URL of the first WebSocket endpoint, with the token, or the reason of the failure.
+fn read_kucoin_bullet(value: &str) -> Result<String, String> {
+    let bullet: KucoinBullet = serde_json::from_str(value).map_err(|error| format!("invalid token response: {}", error))?;
+    let server = bullet.data.instance_servers.first().ok_or("no WebSocket endpoint in token response")?;
+    Ok(format!("{}?token={}", server.endpoint, bullet.data.token))
+}
+
+/// Request a token from the KuCoin REST service, returning the WebSocket URL to connect to.
+fn kucoin_bootstrap() -> Bootstrap {
+    Arc::new(|bullet_url: String| Box::pin(async move {
+        let response = post(&bullet_url).await.map_err(|error| format!("token request failed: {}", error))?;
+        read_kucoin_bullet(&response)
+    }))
+}
+
+/// Format a currency pair as in KuCoin symbols, e.g. `ETH-BTC`, the canonical notation.
+pub fn kucoin_symbol(product: &CurrencyPair) -> String {
+    canonical_symbol(product)
+}
+
+/// Format the request subscribing to, or unsubscribing from, the snapshot topic of a currency
+/// pair, acknowledged by identifier.
+fn kucoin_request(product: &CurrencyPair, request: SubscriptionRequest) -> String {
+    let kind = match request {
+        SubscriptionRequest::Subscribe => "subscribe",
+        SubscriptionRequest::Unsubscribe => "unsubscribe",
+    };
+    format!(
+        r#"{{"id":"{}","type":"{}","topic":"/spotMarket/level2Depth{}:{}","privateChannel":false,"response":true}}"#,
+        REQUEST_ID_PLACEHOLDER, kind, KUCOIN_SNAPSHOT_LEVELS, kucoin_symbol(product),
+    )
+}
+
+/// Creates an [exchange adapter](ExchangeAdapter) for KuCoin. Its endpoint is the REST
+/// service issuing the tokens, requested before each connection.
+pub fn make_kucoin_exchange_adapter(product: &CurrencyPair) -> ExchangeAdapter<BookUpdate> {
+    ExchangeAdapter::new(KUCOIN_CODE, String::from(KUCOIN_BULLET_URL), Arc::new(read_kucoin_book_update))
+        .with_bootstrap(kucoin_bootstrap())
+        .with_subscribe_message(kucoin_request(product, SubscriptionRequest::Subscribe))
+        .with_subscription_formatter(Arc::new(kucoin_request))
+        .with_subscription_acks()
+        .with_keepalive_message(String::from(KUCOIN_PING_MESSAGE), KUCOIN_PING_INTERVAL)
+        .with_capabilities(ExchangeCapabilities {
+            updates: UpdateKind::Snapshots,
+            max_depth: Some(KUCOIN_SNAPSHOT_LEVELS),
+            heartbeat: HeartbeatKind::Application,
+            region: KUCOIN_REGION,
+        })
+}
+
+/// Response of the token request.
+#[derive(Deserialize, Debug)]
+struct KucoinBullet {
+    data: KucoinBulletData,
+}
+
+#[derive(Deserialize, Debug)]
+struct KucoinBulletData {
+    token: String,
+    #[serde(rename = "instanceServers")]
+    instance_servers: Vec<KucoinInstanceServer>,
+}
+
+#[derive(Deserialize, Debug)]
+struct KucoinInstanceServer {
+    endpoint: String,
+}
+
+/// A message of the KuCoin WebSocket service, by type.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum KucoinMessage {
+    /// Sent once connected, before any subscription.
+    Welcome,
+    /// Response to a heartbeat request.
+    Pong,
+    /// Acknowledgment of the request with the given identifier.
+    Ack {
+        id: String,
+    },
+    /// A message of a topic subscribed.
+    Message {
+        data: KucoinBookData,
+    },
+}
+
+#[derive(Deserialize, Debug)]
+struct KucoinPair((String, String));
+
+#[derive(Deserialize, Debug)]
+struct KucoinBookData {
+    bids: Vec<KucoinPair>,
+    asks: Vec<KucoinPair>,
+}
+
+impl TryFrom<KucoinPair> for ExchangeLevel {
+    type Error = rust_decimal::Error;
+
+    fn try_from(value: KucoinPair) -> Result<Self, Self::Error> {
+        let KucoinPair((price_str, amount_str)) = value;
+        Ok(Self {
+            exchange_code: KUCOIN_CODE.into(),
+            price: Decimal::from_str(&price_str)?,
+            amount: Decimal::from_str(&amount_str)?,
+            order_count: None,
+        })
+    }
+}
+
+impl TryFrom<KucoinBookData> for BookUpdate {
+    type Error = rust_decimal::Error;
+
+    fn try_from(value: KucoinBookData) -> Result<Self, Self::Error> {
+        Ok(Self {
+            exchange_code: KUCOIN_CODE.into(),
+            bids: value.bids.into_iter().take(NUM_LEVELS).map(ExchangeLevel::try_from).collect::<Result<_, _>>()?,
+            asks: value.asks.into_iter().take(NUM_LEVELS).map(ExchangeLevel::try_from).collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_kucoin_book_update() {
+        let message = r#"{"type":"message","topic":"/spotMarket/level2Depth50:ETH-BTC","subject":"level2","data":{"asks":[["0.0613","1"],["0.0614","3"]],"bids":[["0.0612","1.5"]],"timestamp":1686727555138}}"#;
+        assert_eq!(read_kucoin_book_update(message), Some(ExchangeProtocol::Data(BookUpdate {
+            exchange_code: KUCOIN_CODE.into(),
+            bids: vec![ExchangeLevel::from_strs(KUCOIN_CODE, "0.0612", "1.5")],
+            asks: vec![
+                ExchangeLevel::from_strs(KUCOIN_CODE, "0.0613", "1"),
+                ExchangeLevel::from_strs(KUCOIN_CODE, "0.0614", "3"),
+            ],
+        })));
+        let incorrect = r#"{"type":"message","topic":"/spotMarket/level2Depth50:ETH-BTC","subject":"level2","data":{"asks":[],"bids":[["__INCORRECT__","1"]]}}"#;
+        assert_eq!(read_kucoin_book_update(incorrect), None);
+    }
+
+    #[test]
+    fn test_read_kucoin_responses() {
+        assert_eq!(read_kucoin_book_update(r#"{"id":"hQvf8jkno","type":"welcome"}"#), Some(ExchangeProtocol::Skipped));
+        assert_eq!(read_kucoin_book_update(r#"{"id":"keepalive","type":"pong"}"#), Some(ExchangeProtocol::Skipped));
+        assert_eq!(read_kucoin_book_update(r#"{"id":"1","type":"ack"}"#), Some(ExchangeProtocol::Response(1)));
+        assert_eq!(read_kucoin_book_update(r#"{"id":"1","type":"error","code":404,"data":"topic /spotMarket/level2Depth50:ETH-XXX is not found"}"#), None);
+    }
+
+    #[test]
+    fn test_kucoin_request() {
+        let product = CurrencyPair { main: "eth".to_string(), counter: "btc".to_string() };
+        assert_eq!(
+            kucoin_request(&product, SubscriptionRequest::Unsubscribe),
+            r#"{"id":"{request_id}","type":"unsubscribe","topic":"/spotMarket/level2Depth50:ETH-BTC","privateChannel":false,"response":true}"#,
+        );
+    }
+
+    #[test]
+    fn test_read_kucoin_bullet() {
+        let bullet = r#"{"code":"200000","data":{"token":"2neAiuYvAU61ZD","instanceServers":[{"endpoint":"wss://ws-api-spot.kucoin.com/","encrypt":true,"protocol":"websocket","pingInterval":18000,"pingTimeout":10000}]}}"#;
+        assert_eq!(read_kucoin_bullet(bullet), Ok(String::from("wss://ws-api-spot.kucoin.com/?token=2neAiuYvAU61ZD")));
+        assert!(read_kucoin_bullet(r#"{"code":"200000","data":{"token":"2neAiuYvAU61ZD","instanceServers":[]}}"#).is_err());
+        assert!(read_kucoin_bullet(r#"{"code":"400100","msg":"error"}"#).is_err());
+    }
+}
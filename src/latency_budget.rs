@@ -0,0 +1,189 @@
+//! Latency budget of the published summaries, in a debug mode: each [summary](Summary) carries
+//! the time spent parsing the latest message of the exchange whose update triggered it,
+//! aggregating the update, waiting in the queues between parsing and delivery, and serializing
+//! the summary. Each stage is recorded in the [LATENCY_BUDGET](LATENCY_BUDGET) histograms, so
+//! that performance work can be measured in production.
+
+use log::info;
+use prost::Message;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::core::ExchangeId;
+use crate::metrics::LATENCY_BUDGET;
+use crate::orderbook::{LatencyBudget, Summary};
+use crate::proto_ext::{Timestamp, TimestampExt};
+
+
+/// Label of the parsing stage in the [LATENCY_BUDGET](LATENCY_BUDGET) histograms.
+pub const PARSE_STAGE: &str = "parse";
+/// Label of the aggregation stage in the [LATENCY_BUDGET](LATENCY_BUDGET) histograms.
+pub const AGGREGATION_STAGE: &str = "aggregation";
+/// Label of the queue wait stage in the [LATENCY_BUDGET](LATENCY_BUDGET) histograms.
+pub const QUEUE_WAIT_STAGE: &str = "queue_wait";
+/// Label of the serialization stage in the [LATENCY_BUDGET](LATENCY_BUDGET) histograms.
+pub const SERIALIZE_STAGE: &str = "serialize";
+/// Quantiles of each stage logged by the [report](spawn_report).
+const REPORTED_QUANTILES: [f64; 3] = [0.5, 0.99, 0.999];
+
+/// Time of the parsing of a message from an exchange.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParseTiming {
+    /// Time spent parsing the message.
+    pub duration: Duration,
+    /// Instant the message was parsed.
+    pub parsed_at: Instant,
+}
+
+/// Time of the parsing of the latest message from each exchange.
+pub struct ParseTimings {
+    /// The latest timing of each exchange, by exchange code.
+    values: Mutex<BTreeMap<ExchangeId, ParseTiming>>,
+}
+
+impl ParseTimings {
+    /// Create a new [ParseTimings](ParseTimings) object, with no timings.
+    pub const fn new() -> Self {
+        Self { values: Mutex::new(BTreeMap::new()) }
+    }
+
+    /// Record the parsing of a message, also in the [LATENCY_BUDGET](LATENCY_BUDGET) histograms.
+    ///
+    /// # Arguments
+    ///
+    /// * `exchange_code` - The exchange code.
+    ///
+    /// * `timing` - The [ParseTiming](ParseTiming).
+    pub fn record(&self, exchange_code: &ExchangeId, timing: ParseTiming) {
+        LATENCY_BUDGET.record(PARSE_STAGE, timing.duration.as_micros() as u64);
+        let mut values = self.values.lock().unwrap();
+        match values.get_mut(exchange_code) {
+            Some(value) => *value = timing,
+            None => {
+                values.insert(exchange_code.clone(), timing);
+            }
+        }
+    }
+
+    /// Time of the parsing of the latest message from an exchange.
+    ///
+    /// # Arguments
+    ///
+    /// * `exchange_code` - The exchange code.
+    ///
+    /// # Returns
+    ///
+    /// An optional [ParseTiming](ParseTiming), [None](None) if never recorded.
+    pub fn get(&self, exchange_code: &str) -> Option<ParseTiming> {
+        self.values.lock().unwrap().get(exchange_code).copied()
+    }
+}
+
+impl Default for ParseTimings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Time of the parsing of the latest message from each exchange, recorded by the
+/// [adapters](crate::exchange::ExchangeAdapter::with_parse_timing) timing their parser.
+pub static PARSE_TIMINGS: ParseTimings = ParseTimings::new();
+
+
+/// Start the latency budget of a summary, once the update which triggered it is aggregated.
+///
+/// # Arguments
+///
+/// * `started` - The instant the aggregation of the update started.
+///
+/// * `parse_timing` - The [ParseTiming](ParseTiming) of the message of the update, if known.
+///
+/// # Returns
+///
+/// A [LatencyBudget](LatencyBudget), to be [completed](complete) on delivery.
+pub fn start(started: Instant, parse_timing: Option<ParseTiming>) -> LatencyBudget {
+    let aggregation = started.elapsed();
+    LATENCY_BUDGET.record(AGGREGATION_STAGE, aggregation.as_micros() as u64);
+    LatencyBudget {
+        parse_us: parse_timing.map(|timing| timing.duration.as_micros() as u64).unwrap_or_default(),
+        aggregation_us: aggregation.as_micros() as u64,
+        queue_wait_us: parse_timing
+            .map(|timing| started.saturating_duration_since(timing.parsed_at).as_micros() as u64)
+            .unwrap_or_default(),
+        serialize_us: 0,
+        created_at: Some(Timestamp::now()),
+    }
+}
+
+/// Complete the latency budget of a summary about to be delivered, if it carries one: the time
+/// since its creation is added to the queue wait, and the summary is serialized once to time it.
+///
+/// # Arguments
+///
+/// * `summary` - The [Summary](Summary).
+pub fn complete(summary: &mut Summary) {
+    let Some(mut budget) = summary.latency_budget.take() else {
+        return;
+    };
+    if let Some(created_at) = &budget.created_at {
+        budget.queue_wait_us += created_at.elapsed().as_micros() as u64;
+    }
+    let started = Instant::now();
+    std::hint::black_box(summary.encode_to_vec());
+    budget.serialize_us = started.elapsed().as_micros() as u64;
+    LATENCY_BUDGET.record(QUEUE_WAIT_STAGE, budget.queue_wait_us);
+    LATENCY_BUDGET.record(SERIALIZE_STAGE, budget.serialize_us);
+    summary.latency_budget = Some(budget);
+}
+
+/// Log the quantiles of each stage of the latency budget periodically.
+///
+/// # Arguments
+///
+/// * `interval` - The interval between two reports.
+pub fn spawn_report(interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        ticks.tick().await;
+        loop {
+            ticks.tick().await;
+            for stage in LATENCY_BUDGET.labels() {
+                let quantiles: Vec<String> = REPORTED_QUANTILES.iter()
+                    .filter_map(|&quantile| LATENCY_BUDGET.quantile(&stage, quantile)
+                        .map(|bound| format!("p{} < {}us", quantile * 100.0, bound)))
+                    .collect();
+                info!("Latency budget of {} ({} samples): {}", stage, LATENCY_BUDGET.count(&stage), quantiles.join(", "));
+            }
+        }
+    });
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget() {
+        let parsed_at = Instant::now();
+        let parse_timing = ParseTiming { duration: Duration::from_micros(120), parsed_at };
+        PARSE_TIMINGS.record(&"test_budget".into(), parse_timing);
+        assert_eq!(PARSE_TIMINGS.get("test_budget"), Some(parse_timing));
+        let budget = start(parsed_at + Duration::from_micros(300), PARSE_TIMINGS.get("test_budget"));
+        assert_eq!(budget.parse_us, 120);
+        assert_eq!(budget.queue_wait_us, 300);
+        assert!(budget.created_at.is_some());
+
+        let mut summary = Summary { symbol: "ETH-BTC".to_string(), latency_budget: Some(budget.clone()), ..Default::default() };
+        complete(&mut summary);
+        let completed = summary.latency_budget.unwrap();
+        assert_eq!(completed.parse_us, budget.parse_us);
+        assert!(completed.queue_wait_us >= budget.queue_wait_us);
+        assert!(LATENCY_BUDGET.count(SERIALIZE_STAGE) > 0);
+
+        let mut summary = Summary::default();
+        complete(&mut summary);
+        assert_eq!(summary.latency_budget, None);
+    }
+}
@@ -0,0 +1,65 @@
+//! Helpers for long-running soak tests: process memory sampling, and detection of
+//! resources growing steadily over time.
+
+
+/// Size of a memory page, assumed for the figures of `/proc/self/statm`.
+const PAGE_SIZE: u64 = 4096;
+
+
+/// Resident set size of the current process.
+///
+/// # Returns
+///
+/// The size in bytes, or [None](None) if not available (only supported on Linux).
+pub fn rss_bytes() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let resident_pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    Some(resident_pages * PAGE_SIZE)
+}
+
+/// Check if a series of samples grows steadily: the samples are split in consecutive
+/// windows, and the minimum of each window must be greater than the minimum of the
+/// previous one. Using minimums ignores transient spikes, while a leak raises the floor.
+///
+/// # Arguments
+///
+/// * `samples` - The samples, in chronological order.
+///
+/// * `windows` - The number of windows, at least 2.
+///
+/// # Returns
+///
+/// A [boolean](bool) value: [true](true) if the samples grow in every window, [false](false)
+/// if not, or if there are fewer samples than windows.
+pub fn grows_monotonically(samples: &[u64], windows: usize) -> bool {
+    if windows < 2 || samples.len() < windows {
+        return false;
+    }
+    let window_len = samples.len() / windows;
+    let minimums: Vec<u64> = samples.chunks(window_len)
+        .take(windows)
+        .map(|chunk| *chunk.iter().min().unwrap())
+        .collect();
+    minimums.windows(2).all(|pair| pair[1] > pair[0])
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grows_monotonically() {
+        assert!(grows_monotonically(&[10, 12, 11, 14, 13, 16, 15, 18], 4));
+        assert!(!grows_monotonically(&[10, 30, 11, 12, 10, 40, 11, 12], 4));
+        assert!(!grows_monotonically(&[10, 10, 10, 10], 4));
+        assert!(!grows_monotonically(&[10, 11], 4));
+    }
+
+    #[test]
+    fn test_rss_bytes() {
+        if cfg!(target_os = "linux") {
+            assert!(rss_bytes().unwrap() > 0);
+        }
+    }
+}
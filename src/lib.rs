@@ -4,12 +4,86 @@
 //! Example client implementation provided in `src/client.rs`.
 
 pub mod core;
-mod aggregator;
+pub mod symbols;
+pub mod symbol_groups;
+pub mod clock;
+pub mod aggregator;
+pub mod cross_check;
+pub mod local_book;
+pub mod orders;
+pub mod capabilities;
 pub mod exchange;
+pub mod reconnect;
+pub mod request_ids;
+pub mod failover;
+pub mod subscriptions;
+pub mod status;
+pub mod maintenance;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+#[cfg(feature = "rest")]
+pub mod rest;
 pub mod binance;
 pub mod bitstamp;
+pub mod bitfinex;
+pub mod bybit;
+#[cfg(feature = "rest")]
+pub mod kucoin;
+pub mod htx;
+pub mod deribit;
+pub mod dydx;
+pub mod simulated;
+pub mod adaptive_depth;
+pub mod staleness;
 pub mod service;
+pub mod summary_fields;
+pub mod analytics;
+pub mod feed;
+pub mod event_bus;
+pub mod multiplex;
+pub mod pause;
+pub mod sink;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_sink;
+#[cfg(feature = "postgres")]
+pub mod postgres_sink;
+#[cfg(feature = "influx")]
+pub mod influx_sink;
+#[cfg(feature = "mqtt")]
+pub mod mqtt_sink;
+#[cfg(feature = "pipe")]
+pub mod pipe_sink;
+#[cfg(all(feature = "systemd", unix))]
+pub mod systemd;
+pub mod top_of_book;
+pub mod routing;
+pub mod alerts;
+pub mod volatility;
+pub mod quote_share;
+pub mod shadow;
+#[cfg(feature = "webhook")]
+pub mod webhook;
 pub mod cli;
+pub mod metrics;
+pub mod prometheus;
+pub mod latency_budget;
+pub mod allocator;
+pub mod config;
+pub mod queues;
+pub mod settings;
+pub mod scheduling;
+pub mod ingest;
+pub mod numbers;
+pub mod accounting;
+pub mod summary_log;
+pub mod leak_detection;
+pub mod crash_dump;
+pub mod book_diff;
+pub mod effective_config;
+pub mod recording;
+pub mod rollup;
+pub mod grpc;
+pub mod proto_ext;
 
 pub mod orderbook {
     tonic::include_proto!("orderbook");
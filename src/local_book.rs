@@ -0,0 +1,282 @@
+//! Complete trading book of an exchange, maintained from a depth snapshot and the diffs
+//! following it, for the full-depth mode where the [aggregate book](crate::aggregator::AggregateBook)
+//! works against all the levels of each exchange.
+//!
+//! Diffs carry sequence numbers: diffs already included in the snapshot are skipped, and a
+//! missing diff, on exchanges whose diffs are contiguous, requires a new snapshot.
+
+use log::warn;
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use crate::core::{BookUpdate, ExchangeId, ExchangeLevel};
+use crate::crash_dump;
+use crate::exchange::ExchangeProtocol;
+
+
+/// A [LocalBook](LocalBook) shared by the readers of the snapshots and of the diffs of an
+/// [exchange adapter](crate::exchange::ExchangeAdapter), and by its clones: they all receive
+/// the same sequence of changes, so a diff applied through one connection is skipped when
+/// received through another.
+pub type SharedLocalBook = Arc<Mutex<LocalBook>>;
+
+
+/// Complete book of an exchange at some point of its sequence.
+#[derive(PartialEq, Debug, Clone)]
+pub struct DepthSnapshot {
+    /// Sequence number of the last change included.
+    pub sequence: u64,
+    /// Bid levels, in any order.
+    pub bids: Vec<ExchangeLevel>,
+    /// Ask levels, in any order.
+    pub asks: Vec<ExchangeLevel>,
+}
+
+/// Changes of the book of an exchange: a level with a zero amount is removed.
+#[derive(PartialEq, Debug, Clone)]
+pub struct DepthDiff {
+    /// Sequence number of the first change included.
+    pub first_sequence: u64,
+    /// Sequence number of the last change included.
+    pub last_sequence: u64,
+    /// Bid levels changed.
+    pub bids: Vec<ExchangeLevel>,
+    /// Ask levels changed.
+    pub asks: Vec<ExchangeLevel>,
+}
+
+/// Result of applying a [DepthDiff](DepthDiff) to a [LocalBook](LocalBook).
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum DiffOutcome {
+    /// The book changed.
+    Applied,
+    /// The diff was already included in the book, which is unchanged.
+    Stale,
+    /// The book misses changes, or never received a snapshot: a new snapshot is required.
+    Gap,
+}
+
+/// Complete trading book of an exchange.
+#[derive(Debug)]
+pub struct LocalBook {
+    /// Exchange code.
+    exchange_code: ExchangeId,
+    /// Whether each diff starts right after the previous one, so that gaps can be detected.
+    contiguous: bool,
+    /// Amount of each bid price.
+    bids: BTreeMap<Decimal, Decimal>,
+    /// Amount of each ask price.
+    asks: BTreeMap<Decimal, Decimal>,
+    /// Sequence number of the last change applied, [None](None) before the first snapshot.
+    sequence: Option<u64>,
+}
+
+impl LocalBook {
+    /// Create a new [LocalBook](LocalBook) object, empty until the first snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `exchange_code` - The code of the exchange.
+    ///
+    /// * `contiguous` - Whether each diff of the exchange starts right after the previous one.
+    pub fn new(exchange_code: impl Into<ExchangeId>, contiguous: bool) -> Self {
+        Self { exchange_code: exchange_code.into(), contiguous, bids: BTreeMap::new(), asks: BTreeMap::new(), sequence: None }
+    }
+
+    /// Replace the book with a snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshot` - The [DepthSnapshot](DepthSnapshot).
+    pub fn apply_snapshot(&mut self, snapshot: DepthSnapshot) {
+        self.bids.clear();
+        self.asks.clear();
+        apply_levels(&mut self.bids, snapshot.bids);
+        apply_levels(&mut self.asks, snapshot.asks);
+        self.sequence = Some(snapshot.sequence);
+        crash_dump::record_sequence(&self.exchange_code, snapshot.sequence);
+    }
+
+    /// Apply a diff following the last snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `diff` - The [DepthDiff](DepthDiff).
+    ///
+    /// # Returns
+    ///
+    /// The [DiffOutcome](DiffOutcome). After a [Gap](DiffOutcome::Gap), diffs are not applied
+    /// until the next snapshot.
+    pub fn apply_diff(&mut self, diff: DepthDiff) -> DiffOutcome {
+        let Some(sequence) = self.sequence else {
+            return DiffOutcome::Gap;
+        };
+        if diff.last_sequence <= sequence {
+            return DiffOutcome::Stale;
+        }
+        if self.contiguous && diff.first_sequence > sequence + 1 {
+            self.sequence = None;
+            return DiffOutcome::Gap;
+        }
+        apply_levels(&mut self.bids, diff.bids);
+        apply_levels(&mut self.asks, diff.asks);
+        self.sequence = Some(diff.last_sequence);
+        crash_dump::record_sequence(&self.exchange_code, diff.last_sequence);
+        DiffOutcome::Applied
+    }
+
+    /// The complete book, as a snapshot for the [aggregate book](crate::aggregator::AggregateBook).
+    ///
+    /// # Returns
+    ///
+    /// A [BookUpdate](BookUpdate) with all the levels, from the best price.
+    pub fn book_update(&self) -> BookUpdate {
+        let level = |(&price, &amount): (&Decimal, &Decimal)| ExchangeLevel { exchange_code: self.exchange_code.clone(), price, amount, order_count: None };
+        BookUpdate {
+            exchange_code: self.exchange_code.clone(),
+            bids: self.bids.iter().rev().map(level).collect(),
+            asks: self.asks.iter().map(level).collect(),
+        }
+    }
+
+    /// Number of levels on each side, bids and asks respectively.
+    pub fn len(&self) -> (usize, usize) {
+        (self.bids.len(), self.asks.len())
+    }
+
+    /// Whether both sides are empty.
+    pub fn is_empty(&self) -> bool {
+        self.bids.is_empty() && self.asks.is_empty()
+    }
+}
+
+/// Apply a snapshot to a shared book, for the reader of the snapshots of an adapter.
+///
+/// # Arguments
+///
+/// * `local_book` - The [SharedLocalBook](SharedLocalBook).
+///
+/// * `snapshot` - The [DepthSnapshot](DepthSnapshot).
+///
+/// # Returns
+///
+/// The complete book as [Data](ExchangeProtocol::Data).
+pub fn read_snapshot(local_book: &SharedLocalBook, snapshot: DepthSnapshot) -> ExchangeProtocol<BookUpdate> {
+    let mut local_book = local_book.lock().unwrap();
+    local_book.apply_snapshot(snapshot);
+    ExchangeProtocol::Data(local_book.book_update())
+}
+
+/// Apply a diff to a shared book, for the reader of the diffs of an adapter.
+///
+/// # Arguments
+///
+/// * `local_book` - The [SharedLocalBook](SharedLocalBook).
+///
+/// * `diff` - The [DepthDiff](DepthDiff).
+///
+/// # Returns
+///
+/// The complete book as [Data](ExchangeProtocol::Data) if it changed, [Skipped](ExchangeProtocol::Skipped)
+/// if the diff is stale, or a [ReconnectionRequest](ExchangeProtocol::ReconnectionRequest) to
+/// fetch a new snapshot after a gap.
+pub fn read_diff(local_book: &SharedLocalBook, diff: DepthDiff) -> ExchangeProtocol<BookUpdate> {
+    let mut local_book = local_book.lock().unwrap();
+    match local_book.apply_diff(diff) {
+        DiffOutcome::Applied => ExchangeProtocol::Data(local_book.book_update()),
+        DiffOutcome::Stale => ExchangeProtocol::Skipped,
+        DiffOutcome::Gap => {
+            warn!("Missing depth diffs from {}, fetching a new snapshot", local_book.exchange_code);
+            ExchangeProtocol::ReconnectionRequest
+        },
+    }
+}
+
+/// Internal function applying levels to a side of the book, removing those with a zero amount.
+fn apply_levels(side: &mut BTreeMap<Decimal, Decimal>, levels: Vec<ExchangeLevel>) {
+    for level in levels {
+        if level.amount.is_zero() {
+            side.remove(&level.price);
+        } else {
+            side.insert(level.price, level.amount);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(price: &str, amount: &str) -> ExchangeLevel {
+        ExchangeLevel::from_strs("test", price, amount)
+    }
+
+    fn snapshot() -> DepthSnapshot {
+        DepthSnapshot {
+            sequence: 100,
+            bids: vec![level("99", "1"), level("98", "2")],
+            asks: vec![level("101", "1"), level("100", "3")],
+        }
+    }
+
+    fn diff(first_sequence: u64, last_sequence: u64, bids: Vec<ExchangeLevel>, asks: Vec<ExchangeLevel>) -> DepthDiff {
+        DepthDiff { first_sequence, last_sequence, bids, asks }
+    }
+
+    #[test]
+    fn test_snapshot_and_diffs() {
+        let mut book = LocalBook::new("test", true);
+        assert_eq!(book.apply_diff(diff(90, 95, vec![], vec![])), DiffOutcome::Gap);
+        book.apply_snapshot(snapshot());
+        assert_eq!(book.book_update(), BookUpdate {
+            exchange_code: "test".into(),
+            bids: vec![level("99", "1"), level("98", "2")],
+            asks: vec![level("100", "3"), level("101", "1")],
+        });
+        assert_eq!(book.apply_diff(diff(95, 100, vec![level("99", "0")], vec![])), DiffOutcome::Stale);
+        assert_eq!(book.apply_diff(diff(98, 102, vec![level("99", "0"), level("97", "5")], vec![level("99.5", "1")])), DiffOutcome::Applied);
+        assert_eq!(book.book_update(), BookUpdate {
+            exchange_code: "test".into(),
+            bids: vec![level("98", "2"), level("97", "5")],
+            asks: vec![level("99.5", "1"), level("100", "3"), level("101", "1")],
+        });
+        assert_eq!(book.len(), (2, 3));
+    }
+
+    #[test]
+    fn test_gap() {
+        let mut book = LocalBook::new("test", true);
+        book.apply_snapshot(snapshot());
+        assert_eq!(book.apply_diff(diff(102, 103, vec![level("99", "0")], vec![])), DiffOutcome::Gap);
+        assert_eq!(book.apply_diff(diff(104, 105, vec![], vec![])), DiffOutcome::Gap);
+        book.apply_snapshot(DepthSnapshot { sequence: 105, ..snapshot() });
+        assert_eq!(book.apply_diff(diff(106, 106, vec![], vec![level("100", "0")])), DiffOutcome::Applied);
+        assert_eq!(book.book_update().asks, vec![level("101", "1")]);
+    }
+
+    #[test]
+    fn test_not_contiguous() {
+        let mut book = LocalBook::new("test", false);
+        book.apply_snapshot(snapshot());
+        assert_eq!(book.apply_diff(diff(150, 150, vec![level("99", "3")], vec![])), DiffOutcome::Applied);
+        assert_eq!(book.apply_diff(diff(120, 120, vec![level("99", "4")], vec![])), DiffOutcome::Stale);
+        assert_eq!(book.book_update().bids[0], level("99", "3"));
+    }
+
+    #[test]
+    fn test_read_shared() {
+        let local_book: SharedLocalBook = Arc::new(Mutex::new(LocalBook::new("test", true)));
+        assert_eq!(read_diff(&local_book, diff(101, 101, vec![], vec![])), ExchangeProtocol::ReconnectionRequest);
+        let ExchangeProtocol::Data(book_update) = read_snapshot(&local_book, snapshot()) else {
+            panic!("snapshot not delivered");
+        };
+        assert_eq!(book_update.bids.len(), 2);
+        assert_eq!(read_diff(&local_book, diff(100, 100, vec![], vec![])), ExchangeProtocol::Skipped);
+        let ExchangeProtocol::Data(book_update) = read_diff(&local_book, diff(101, 101, vec![level("99", "0")], vec![])) else {
+            panic!("diff not delivered");
+        };
+        assert_eq!(book_update.bids, vec![level("98", "2")]);
+    }
+}
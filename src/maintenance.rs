@@ -0,0 +1,252 @@
+//! Scheduled maintenance windows of the exchanges, during which disconnections are expected:
+//! the exchange is reported [scheduled offline](crate::status::ExchangeStatus::ScheduledOffline)
+//! rather than down, its staleness does not raise alerts, and it is not counted among the
+//! exchanges expected in the summaries.
+
+use log::warn;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::{DocumentedConfig, Validate, Validator};
+
+
+/// A maintenance window of an exchange.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MaintenanceWindow {
+    /// Exchange code.
+    pub exchange: String,
+    /// Start of the window, as UTC time `YYYY-MM-DDTHH:MM:SSZ` in the configuration.
+    #[serde(serialize_with = "serialize_utc", deserialize_with = "deserialize_utc")]
+    pub start: SystemTime,
+    /// End of the window, excluded, as UTC time `YYYY-MM-DDTHH:MM:SSZ` in the configuration.
+    #[serde(serialize_with = "serialize_utc", deserialize_with = "deserialize_utc")]
+    pub end: SystemTime,
+}
+
+/// Maintenance windows of the exchanges, read from a JSON file.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct MaintenanceSchedule {
+    /// The windows.
+    #[serde(default)]
+    windows: Vec<MaintenanceWindow>,
+}
+
+impl MaintenanceSchedule {
+    /// Add a maintenance window.
+    ///
+    /// # Arguments
+    ///
+    /// * `exchange` - The exchange code.
+    ///
+    /// * `start` - The start of the window.
+    ///
+    /// * `end` - The end of the window, excluded.
+    ///
+    /// # Returns
+    ///
+    /// The modified [MaintenanceSchedule](MaintenanceSchedule).
+    pub fn with_window(mut self, exchange: &str, start: SystemTime, end: SystemTime) -> Self {
+        self.windows.push(MaintenanceWindow { exchange: exchange.to_string(), start, end });
+        self
+    }
+
+    /// Keep only the windows of some exchanges, warning about the others.
+    ///
+    /// # Arguments
+    ///
+    /// * `exchange_codes` - The codes of the exchanges served.
+    ///
+    /// # Returns
+    ///
+    /// The modified [MaintenanceSchedule](MaintenanceSchedule).
+    pub fn for_exchanges(mut self, exchange_codes: &[&str]) -> Self {
+        self.windows.retain(|window| {
+            let served = exchange_codes.contains(&window.exchange.as_str());
+            if !served {
+                warn!("Maintenance window of unknown exchange {} ignored", window.exchange);
+            }
+            served
+        });
+        self
+    }
+
+    /// The windows of the schedule.
+    pub fn windows(&self) -> &[MaintenanceWindow] {
+        &self.windows
+    }
+
+    /// End of the maintenance window of an exchange in progress.
+    ///
+    /// # Arguments
+    ///
+    /// * `exchange` - The exchange code.
+    ///
+    /// * `now` - The current time.
+    ///
+    /// # Returns
+    ///
+    /// The end of the latest window in progress, [None](None) if the exchange is not in maintenance.
+    pub fn offline_until(&self, exchange: &str, now: SystemTime) -> Option<SystemTime> {
+        self.windows.iter()
+            .filter(|window| window.exchange == exchange && window.start <= now && now < window.end)
+            .map(|window| window.end)
+            .max()
+    }
+
+    /// Whether an exchange is in a maintenance window.
+    ///
+    /// # Arguments
+    ///
+    /// * `exchange` - The exchange code.
+    ///
+    /// * `now` - The current time.
+    pub fn is_offline(&self, exchange: &str, now: SystemTime) -> bool {
+        self.offline_until(exchange, now).is_some()
+    }
+
+    /// Number of distinct exchanges in a maintenance window.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time.
+    ///
+    /// * `except` - Exchanges not counted, e.g. those delivering data anyway.
+    pub fn offline_count(&self, now: SystemTime, except: impl Fn(&str) -> bool) -> usize {
+        let mut exchanges: Vec<&str> = self.windows.iter()
+            .map(|window| window.exchange.as_str())
+            .filter(|exchange| !except(exchange) && self.is_offline(exchange, now))
+            .collect();
+        exchanges.sort_unstable();
+        exchanges.dedup();
+        exchanges.len()
+    }
+}
+
+impl Validate for MaintenanceSchedule {
+    fn validate(&self, validator: &mut Validator) {
+        for (i, window) in self.windows.iter().enumerate() {
+            if window.end <= window.start {
+                validator.error(&format!("windows[{}].end", i), "must be after start");
+            }
+        }
+    }
+}
+
+impl DocumentedConfig for MaintenanceSchedule {
+    const DESCRIPTION: &'static str = "Maintenance windows of the exchanges.";
+    const FIELDS: &'static [(&'static str, &'static str)] = &[
+        ("windows", "Windows of the exchanges, e.g. {\"exchange\": \"binance\", \"start\": \"2024-03-01T06:00:00Z\", \"end\": \"2024-03-01T08:00:00Z\"}, with UTC times."),
+    ];
+}
+
+/// Parse a UTC time, e.g. `2024-03-01T06:00:00Z`.
+///
+/// # Arguments
+///
+/// * `value` - The time, as `YYYY-MM-DDTHH:MM:SSZ`.
+///
+/// # Returns
+///
+/// An optional [SystemTime](SystemTime), [None](None) if the time is not valid.
+pub fn parse_utc(value: &str) -> Option<SystemTime> {
+    let bytes = value.as_bytes();
+    let separators_valid = bytes.len() == 20
+        && [(4, b'-'), (7, b'-'), (10, b'T'), (13, b':'), (16, b':'), (19, b'Z')].iter().all(|&(i, c)| bytes[i] == c);
+    if !separators_valid {
+        return None;
+    }
+    let field = |start: usize, end: usize| value.get(start..end)?.parse::<i64>().ok();
+    let (year, month, day) = (field(0, 4)?, field(5, 7)?, field(8, 10)?);
+    let (hour, minute, second) = (field(11, 13)?, field(14, 16)?, field(17, 19)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    // days since the epoch of the proleptic Gregorian calendar date
+    let shifted_year = if month <= 2 { year - 1 } else { year };
+    let era = shifted_year.div_euclid(400);
+    let year_of_era = shifted_year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    let seconds = u64::try_from(days * 86_400 + hour * 3600 + minute * 60 + second).ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+/// Format a UTC time, e.g. `2024-03-01T06:00:00Z`, the inverse of [parse_utc](parse_utc).
+///
+/// # Arguments
+///
+/// * `time` - The time, rounded down to the second, the epoch if before it.
+///
+/// # Returns
+///
+/// The time, as `YYYY-MM-DDTHH:MM:SSZ`.
+pub fn format_utc(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+    let (days, second_of_day) = (seconds.div_euclid(86_400), seconds.rem_euclid(86_400));
+    // proleptic Gregorian calendar date of the days since the epoch
+    let shifted_days = days + 719_468;
+    let era = shifted_days.div_euclid(146_097);
+    let day_of_era = shifted_days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, second_of_day / 3600, second_of_day / 60 % 60, second_of_day % 60)
+}
+
+/// Internal function serializing a [UTC time](format_utc).
+fn serialize_utc<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+    format_utc(*time).serialize(serializer)
+}
+
+/// Internal function deserializing a [UTC time](parse_utc).
+fn deserialize_utc<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    parse_utc(&value).ok_or_else(|| serde::de::Error::custom(format!("invalid UTC time: {}", value)))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_utc() {
+        assert_eq!(parse_utc("1970-01-01T00:00:00Z"), Some(UNIX_EPOCH));
+        assert_eq!(parse_utc("2024-03-01T06:30:15Z"), Some(UNIX_EPOCH + Duration::from_secs(1_709_274_615)));
+        assert_eq!(parse_utc("2000-02-29T23:59:59Z"), Some(UNIX_EPOCH + Duration::from_secs(951_868_799)));
+        assert_eq!(parse_utc("2024-13-01T00:00:00Z"), None);
+        assert_eq!(parse_utc("2024-03-01 06:30:15Z"), None);
+        assert_eq!(parse_utc("2024-03-01T06:30:15"), None);
+    }
+
+    #[test]
+    fn test_format_utc() {
+        for time in ["1970-01-01T00:00:00Z", "2024-03-01T06:30:15Z", "2000-02-29T23:59:59Z", "2100-12-31T00:00:01Z"] {
+            assert_eq!(format_utc(parse_utc(time).unwrap()), time);
+        }
+    }
+
+    #[test]
+    fn test_schedule() {
+        let schedule: MaintenanceSchedule = serde_json::from_str(r#"{"windows": [
+            {"exchange": "binance", "start": "2024-03-01T06:00:00Z", "end": "2024-03-01T08:00:00Z"},
+            {"exchange": "binance", "start": "2024-03-01T07:00:00Z", "end": "2024-03-01T09:00:00Z"},
+            {"exchange": "kraken", "start": "2024-03-01T06:00:00Z", "end": "2024-03-01T08:00:00Z"}
+        ]}"#).unwrap();
+        let schedule = schedule.for_exchanges(&["binance", "bitstamp"]);
+        assert_eq!(schedule.windows().len(), 2);
+        let at = |time: &str| parse_utc(time).unwrap();
+        assert!(!schedule.is_offline("binance", at("2024-03-01T05:59:59Z")));
+        assert_eq!(schedule.offline_until("binance", at("2024-03-01T07:30:00Z")), Some(at("2024-03-01T09:00:00Z")));
+        assert_eq!(schedule.offline_until("binance", at("2024-03-01T08:00:00Z")), Some(at("2024-03-01T09:00:00Z")));
+        assert!(!schedule.is_offline("binance", at("2024-03-01T09:00:00Z")));
+        assert!(!schedule.is_offline("bitstamp", at("2024-03-01T07:00:00Z")));
+        assert_eq!(schedule.offline_count(at("2024-03-01T07:00:00Z"), |_| false), 1);
+        assert_eq!(schedule.offline_count(at("2024-03-01T07:00:00Z"), |exchange| exchange == "binance"), 0);
+        assert!(serde_json::from_str::<MaintenanceSchedule>(r#"{"windows": [{"exchange": "binance", "start": "tomorrow", "end": "2024-03-01T08:00:00Z"}]}"#).is_err());
+    }
+}
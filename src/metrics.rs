@@ -0,0 +1,555 @@
+//! Process-wide counters and gauges, used to keep track of events worth monitoring.
+
+use log::warn;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use tokio::sync::mpsc;
+
+
+/// Number of updates skipped because identical to the previous one from the same exchange.
+pub static SUPPRESSED_DUPLICATES: LabeledCounter = LabeledCounter::new("suppressed_duplicate_updates");
+/// Number of messages rejected by the [ingest limits](crate::ingest::IngestLimits), by exchange.
+pub static REJECTED_MESSAGES: LabeledCounter = LabeledCounter::new("rejected_messages");
+/// Number of updates rejected by the [data check](crate::exchange::DataCheck) of the adapters,
+/// e.g. inverted books, by exchange.
+pub static REJECTED_UPDATES: LabeledCounter = LabeledCounter::new("rejected_updates");
+/// Number of snapshots dropped because older than the latest applied from the same channel,
+/// e.g. replayed after a reconnection, by exchange.
+pub static REORDERED_SNAPSHOTS: LabeledCounter = LabeledCounter::new("reordered_snapshots");
+/// Number of errors of the [sinks](crate::sink::SummarySink) of the feeds, by sink.
+pub static SINK_ERRORS: LabeledCounter = LabeledCounter::new("sink_errors");
+/// Number of summaries dropped because the queue of a [sink](crate::sink::SummarySink) was full, by sink.
+pub static SINK_DROPPED: LabeledCounter = LabeledCounter::new("sink_dropped_summaries");
+/// Time between taking a summary from its feed and a [sink](crate::sink::SummarySink)
+/// consuming it, in milliseconds, by sink, as last measured.
+pub static SINK_LAGS: LabeledGauge = LabeledGauge::new("sink_lag_ms");
+/// Number of summaries dropped because the queue of a symbol of a [multiplexed](crate::multiplex)
+/// client stream was full, by symbol.
+pub static MULTIPLEX_DROPPED: LabeledCounter = LabeledCounter::new("multiplex_dropped_summaries");
+/// Time between receiving a summary of a symbol of a [multiplexed](crate::multiplex) client stream
+/// and delivering it, in milliseconds, by symbol, as last measured.
+pub static MULTIPLEX_LAGS: LabeledGauge = LabeledGauge::new("multiplex_lag_ms");
+/// Number of events published on the [event bus](crate::event_bus), by topic.
+pub static BUS_EVENTS: LabeledCounter = LabeledCounter::new("bus_events");
+/// Number of times a subscriber of the [event bus](crate::event_bus) missed events, by topic.
+pub static BUS_LAGGED: LabeledCounter = LabeledCounter::new("bus_lagged_subscribers");
+/// Number of price levels of the aggregate book differing from the naive book of the
+/// [cross-check](crate::cross_check), by side.
+pub static CROSS_CHECK_DISCREPANCIES: LabeledCounter = LabeledCounter::new("cross_check_discrepancies");
+/// Time spent by the published summaries in each stage of the [latency budget](crate::latency_budget),
+/// in microseconds, by stage.
+pub static LATENCY_BUDGET: LabeledHistogram = LabeledHistogram::new("latency_budget_us");
+/// Number of summaries not matched by the other instance of the [shadow comparison](crate::shadow),
+/// by kind of divergence.
+pub static SHADOW_DIVERGENCES: LabeledCounter = LabeledCounter::new("shadow_divergences");
+/// Lag of an instance of the [shadow comparison](crate::shadow) behind the other one publishing
+/// the same book, in milliseconds, by lagging instance.
+pub static SHADOW_LAGS: LabeledHistogram = LabeledHistogram::new("shadow_lag_ms");
+/// Number of items waiting in the internal queues, by queue and label.
+pub static QUEUE_DEPTHS: QueueGauge = QueueGauge::new("queue_depth");
+/// Share of time, in basis points, during which each exchange provided the consolidated best bid
+/// over the [rolling window](crate::quote_share), by exchange.
+pub static BEST_BID_SHARES: LabeledGauge = LabeledGauge::new("best_bid_share_bps");
+/// Share of time, in basis points, during which each exchange provided the consolidated best ask
+/// over the [rolling window](crate::quote_share), by exchange.
+pub static BEST_ASK_SHARES: LabeledGauge = LabeledGauge::new("best_ask_share_bps");
+/// Memory of the global allocator in bytes, by statistic, as last [sampled](crate::allocator::record_stats).
+pub static ALLOCATOR_MEMORY: LabeledGauge = LabeledGauge::new("allocator_memory_bytes");
+/// Consolidated best bid price, by symbol, as last [published](crate::feed::record_market_gauges).
+pub static BEST_BIDS: MarketGauge = MarketGauge::new("best_bid");
+/// Consolidated best ask price, by symbol, as last [published](crate::feed::record_market_gauges).
+pub static BEST_ASKS: MarketGauge = MarketGauge::new("best_ask");
+/// Consolidated spread in basis points of the mid price, by symbol, as last
+/// [published](crate::feed::record_market_gauges).
+pub static SPREADS_BPS: MarketGauge = MarketGauge::new("spread_bps");
+/// Best bid price of each exchange among the published levels, by symbol and exchange, as last
+/// [published](crate::feed::record_market_gauges).
+pub static VENUE_BEST_BIDS: MarketGauge = MarketGauge::new("venue_best_bid");
+/// Best ask price of each exchange among the published levels, by symbol and exchange, as last
+/// [published](crate::feed::record_market_gauges).
+pub static VENUE_BEST_ASKS: MarketGauge = MarketGauge::new("venue_best_ask");
+
+
+/// A counter holding a separate value for each label (e.g. an exchange code).
+pub struct LabeledCounter {
+    /// Counter name.
+    name: &'static str,
+    /// Current value for each label.
+    values: Mutex<BTreeMap<String, u64>>,
+}
+
+impl LabeledCounter {
+    /// Create a new [LabeledCounter](LabeledCounter) object, with no values.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The counter name.
+    pub const fn new(name: &'static str) -> Self {
+        Self { name, values: Mutex::new(BTreeMap::new()) }
+    }
+
+    /// The counter name.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Increment the counter for a label by one.
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - The label.
+    pub fn increment(&self, label: &str) {
+        let mut values = self.values.lock().unwrap();
+        match values.get_mut(label) {
+            Some(value) => *value += 1,
+            None => {
+                values.insert(label.to_string(), 1);
+            }
+        }
+    }
+
+    /// Current value of the counter for a label.
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - The label.
+    ///
+    /// # Returns
+    ///
+    /// The counter value, zero if never incremented.
+    pub fn get(&self, label: &str) -> u64 {
+        self.values.lock().unwrap().get(label).copied().unwrap_or(0)
+    }
+
+    /// Current values of the counter.
+    ///
+    /// # Returns
+    ///
+    /// A [vector](Vec) of label and value pairs, ordered by label.
+    pub fn values(&self) -> Vec<(String, u64)> {
+        self.values.lock().unwrap().iter().map(|(label, &value)| (label.clone(), value)).collect()
+    }
+}
+
+/// A gauge holding a separate value for each label, as last set.
+pub struct LabeledGauge {
+    /// Gauge name.
+    name: &'static str,
+    /// Current value for each label.
+    values: Mutex<BTreeMap<String, u64>>,
+}
+
+impl LabeledGauge {
+    /// Create a new [LabeledGauge](LabeledGauge) object, with no values.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The gauge name.
+    pub const fn new(name: &'static str) -> Self {
+        Self { name, values: Mutex::new(BTreeMap::new()) }
+    }
+
+    /// The gauge name.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Set the value of the gauge for a label.
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - The label.
+    ///
+    /// * `value` - The value.
+    pub fn set(&self, label: &str, value: u64) {
+        let mut values = self.values.lock().unwrap();
+        match values.get_mut(label) {
+            Some(current) => *current = value,
+            None => {
+                values.insert(label.to_string(), value);
+            }
+        }
+    }
+
+    /// Current value of the gauge for a label.
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - The label.
+    ///
+    /// # Returns
+    ///
+    /// The gauge value, [None](None) if never set.
+    pub fn get(&self, label: &str) -> Option<u64> {
+        self.values.lock().unwrap().get(label).copied()
+    }
+
+    /// Current values of the gauge.
+    ///
+    /// # Returns
+    ///
+    /// A [vector](Vec) of label and value pairs, ordered by label.
+    pub fn values(&self) -> Vec<(String, u64)> {
+        self.values.lock().unwrap().iter().map(|(label, &value)| (label.clone(), value)).collect()
+    }
+}
+
+/// A gauge of market figures, holding a separate value for each symbol, or for each symbol and
+/// exchange, as last set.
+pub struct MarketGauge {
+    /// Gauge name.
+    name: &'static str,
+    /// Current value for each symbol and exchange, empty for the consolidated figures.
+    values: Mutex<BTreeMap<(String, String), f64>>,
+}
+
+impl MarketGauge {
+    /// Create a new [MarketGauge](MarketGauge) object, with no values.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The gauge name.
+    pub const fn new(name: &'static str) -> Self {
+        Self { name, values: Mutex::new(BTreeMap::new()) }
+    }
+
+    /// The gauge name.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Set the consolidated value of the gauge for a symbol.
+    ///
+    /// # Arguments
+    ///
+    /// * `symbol` - The symbol.
+    ///
+    /// * `value` - The value.
+    pub fn set(&self, symbol: &str, value: f64) {
+        self.values.lock().unwrap().insert((symbol.to_string(), String::new()), value);
+    }
+
+    /// Replace the values of the gauge for the exchanges of a symbol, the exchanges missing
+    /// being removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `symbol` - The symbol.
+    ///
+    /// * `values` - The exchange and value pairs.
+    pub fn set_exchanges(&self, symbol: &str, values: Vec<(String, f64)>) {
+        let mut current = self.values.lock().unwrap();
+        current.retain(|(current_symbol, exchange), _| current_symbol != symbol || exchange.is_empty());
+        for (exchange, value) in values {
+            current.insert((symbol.to_string(), exchange), value);
+        }
+    }
+
+    /// Current value of the gauge for a symbol and optionally an exchange.
+    ///
+    /// # Arguments
+    ///
+    /// * `symbol` - The symbol.
+    ///
+    /// * `exchange` - The exchange, [None](None) for the consolidated value.
+    ///
+    /// # Returns
+    ///
+    /// The gauge value, [None](None) if never set.
+    pub fn get(&self, symbol: &str, exchange: Option<&str>) -> Option<f64> {
+        let key = (symbol.to_string(), exchange.unwrap_or_default().to_string());
+        self.values.lock().unwrap().get(&key).copied()
+    }
+
+    /// Current values of the gauge.
+    ///
+    /// # Returns
+    ///
+    /// A [vector](Vec) of symbol, optional exchange and value, ordered by symbol and exchange.
+    pub fn values(&self) -> Vec<(String, Option<String>, f64)> {
+        self.values.lock().unwrap().iter()
+            .map(|((symbol, exchange), &value)| (symbol.clone(), Some(exchange.clone()).filter(|e| !e.is_empty()), value))
+            .collect()
+    }
+}
+
+/// Number of buckets of a [LabeledHistogram](LabeledHistogram): bucket `i` counts the values
+/// below `2^i`, the last one all the larger values.
+const HISTOGRAM_BUCKETS: usize = 32;
+
+/// A histogram holding a separate distribution for each label, with buckets of exponentially
+/// increasing width.
+pub struct LabeledHistogram {
+    /// Histogram name.
+    name: &'static str,
+    /// Count of the values in each bucket, for each label.
+    values: Mutex<BTreeMap<String, [u64; HISTOGRAM_BUCKETS]>>,
+}
+
+impl LabeledHistogram {
+    /// Create a new [LabeledHistogram](LabeledHistogram) object, with no values.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The histogram name.
+    pub const fn new(name: &'static str) -> Self {
+        Self { name, values: Mutex::new(BTreeMap::new()) }
+    }
+
+    /// The histogram name.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Record a value for a label.
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - The label.
+    ///
+    /// * `value` - The value.
+    pub fn record(&self, label: &str, value: u64) {
+        let bucket = ((u64::BITS - value.leading_zeros()) as usize).min(HISTOGRAM_BUCKETS - 1);
+        let mut values = self.values.lock().unwrap();
+        match values.get_mut(label) {
+            Some(buckets) => buckets[bucket] += 1,
+            None => {
+                let mut buckets = [0; HISTOGRAM_BUCKETS];
+                buckets[bucket] = 1;
+                values.insert(label.to_string(), buckets);
+            }
+        }
+    }
+
+    /// Number of values recorded for a label.
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - The label.
+    pub fn count(&self, label: &str) -> u64 {
+        self.values.lock().unwrap().get(label).map(|buckets| buckets.iter().sum()).unwrap_or(0)
+    }
+
+    /// Upper bound of a quantile of the values recorded for a label.
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - The label.
+    ///
+    /// * `quantile` - The quantile, between 0 and 1.
+    ///
+    /// # Returns
+    ///
+    /// The exclusive upper bound of the bucket of the quantile, [None](None) if no value was
+    /// recorded, or [u64::MAX](u64::MAX) for the last bucket.
+    pub fn quantile(&self, label: &str, quantile: f64) -> Option<u64> {
+        let values = self.values.lock().unwrap();
+        let buckets = values.get(label)?;
+        let rank = (quantile * buckets.iter().sum::<u64>() as f64).ceil().max(1.0) as u64;
+        let mut cumulative = 0;
+        for (bucket, &count) in buckets.iter().enumerate() {
+            cumulative += count;
+            if cumulative >= rank {
+                return Some(if bucket == HISTOGRAM_BUCKETS - 1 { u64::MAX } else { 1 << bucket });
+            }
+        }
+        None
+    }
+
+    /// Labels with values recorded.
+    ///
+    /// # Returns
+    ///
+    /// A [vector](Vec) of labels, ordered.
+    pub fn labels(&self) -> Vec<String> {
+        self.values.lock().unwrap().keys().cloned().collect()
+    }
+}
+
+/// Depth of a queue, as last sampled.
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub struct QueueDepth {
+    /// Number of items waiting.
+    pub depth: usize,
+    /// Largest number of items waiting ever sampled.
+    pub high_watermark: usize,
+    /// Queue capacity.
+    pub capacity: usize,
+}
+
+/// A gauge holding the [depth](QueueDepth) of several queues, each identified by a queue
+/// name (e.g. the kind of channel) and a label (e.g. an exchange code).
+/// Each new high watermark reaching half of the capacity of a queue is logged, as an early
+/// sign of a consumer unable to keep up.
+pub struct QueueGauge {
+    /// Gauge name.
+    name: &'static str,
+    /// Current depth for each queue name, then for each label.
+    values: Mutex<BTreeMap<&'static str, BTreeMap<String, QueueDepth>>>,
+}
+
+impl QueueGauge {
+    /// Create a new [QueueGauge](QueueGauge) object, with no values.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The gauge name.
+    pub const fn new(name: &'static str) -> Self {
+        Self { name, values: Mutex::new(BTreeMap::new()) }
+    }
+
+    /// The gauge name.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Record the depth of a queue.
+    ///
+    /// # Arguments
+    ///
+    /// * `queue` - The queue name.
+    ///
+    /// * `label` - The label.
+    ///
+    /// * `depth` - The number of items waiting.
+    ///
+    /// * `capacity` - The queue capacity.
+    pub fn record(&self, queue: &'static str, label: &str, depth: usize, capacity: usize) {
+        let mut values = self.values.lock().unwrap();
+        let labels = values.entry(queue).or_default();
+        if !labels.contains_key(label) {
+            labels.insert(label.to_string(), QueueDepth::default());
+        }
+        let value = labels.get_mut(label).expect("queue depth inserted");
+        value.depth = depth;
+        value.capacity = capacity;
+        if depth > value.high_watermark {
+            value.high_watermark = depth;
+            if depth * 2 >= capacity {
+                warn!("Queue {} {} reached a high watermark of {} out of {}", queue, label, depth, capacity);
+            }
+        }
+    }
+
+    /// Record the depth of the queue of a [channel](mpsc::channel).
+    ///
+    /// # Arguments
+    ///
+    /// * `queue` - The queue name.
+    ///
+    /// * `label` - The label.
+    ///
+    /// * `sender` - A [Sender](mpsc::Sender) of the channel.
+    pub fn record_channel<T>(&self, queue: &'static str, label: &str, sender: &mpsc::Sender<T>) {
+        self.record(queue, label, sender.max_capacity() - sender.capacity(), sender.max_capacity());
+    }
+
+    /// Current depth of a queue.
+    ///
+    /// # Arguments
+    ///
+    /// * `queue` - The queue name.
+    ///
+    /// * `label` - The label.
+    ///
+    /// # Returns
+    ///
+    /// An optional [QueueDepth](QueueDepth), [None](None) if never recorded.
+    pub fn get(&self, queue: &str, label: &str) -> Option<QueueDepth> {
+        self.values.lock().unwrap().get(queue).and_then(|labels| labels.get(label)).copied()
+    }
+
+    /// Current depths of the queues.
+    ///
+    /// # Returns
+    ///
+    /// A [vector](Vec) of queue name, label and [QueueDepth](QueueDepth) triples, ordered by
+    /// queue name and label.
+    pub fn values(&self) -> Vec<(&'static str, String, QueueDepth)> {
+        self.values.lock().unwrap().iter()
+            .flat_map(|(&queue, labels)| labels.iter().map(move |(label, &value)| (queue, label.clone(), value)))
+            .collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labeled_counter() {
+        let counter = LabeledCounter::new("test");
+        counter.increment("test1");
+        counter.increment("test2");
+        counter.increment("test1");
+        assert_eq!(counter.name(), "test");
+        assert_eq!(counter.get("test1"), 2);
+        assert_eq!(counter.get("test3"), 0);
+        assert_eq!(counter.values(), vec![("test1".to_string(), 2), ("test2".to_string(), 1)]);
+    }
+
+    #[test]
+    fn test_labeled_gauge() {
+        let gauge = LabeledGauge::new("test");
+        gauge.set("test1", 5);
+        gauge.set("test2", 1);
+        gauge.set("test1", 3);
+        assert_eq!(gauge.name(), "test");
+        assert_eq!(gauge.get("test1"), Some(3));
+        assert_eq!(gauge.get("test3"), None);
+        assert_eq!(gauge.values(), vec![("test1".to_string(), 3), ("test2".to_string(), 1)]);
+    }
+
+    #[test]
+    fn test_market_gauge() {
+        let gauge = MarketGauge::new("test");
+        gauge.set("ETH-BTC", 0.5);
+        gauge.set_exchanges("ETH-BTC", vec![("test1".to_string(), 1.0), ("test2".to_string(), 2.0)]);
+        gauge.set_exchanges("ETH-BTC", vec![("test2".to_string(), 3.0)]);
+        gauge.set_exchanges("BTC-USDT", vec![("test1".to_string(), 4.0)]);
+        assert_eq!(gauge.name(), "test");
+        assert_eq!(gauge.get("ETH-BTC", None), Some(0.5));
+        assert_eq!(gauge.get("ETH-BTC", Some("test1")), None);
+        assert_eq!(gauge.values(), vec![
+            ("BTC-USDT".to_string(), Some("test1".to_string()), 4.0),
+            ("ETH-BTC".to_string(), None, 0.5),
+            ("ETH-BTC".to_string(), Some("test2".to_string()), 3.0),
+        ]);
+    }
+
+    #[test]
+    fn test_labeled_histogram() {
+        let histogram = LabeledHistogram::new("test");
+        assert_eq!(histogram.quantile("test1", 0.5), None);
+        for value in [0, 1, 3, 100, 100, 5000] {
+            histogram.record("test1", value);
+        }
+        histogram.record("test2", u64::MAX);
+        assert_eq!(histogram.name(), "test");
+        assert_eq!(histogram.count("test1"), 6);
+        assert_eq!(histogram.quantile("test1", 0.0), Some(1));
+        assert_eq!(histogram.quantile("test1", 0.5), Some(4));
+        assert_eq!(histogram.quantile("test1", 0.8), Some(128));
+        assert_eq!(histogram.quantile("test1", 1.0), Some(8192));
+        assert_eq!(histogram.quantile("test2", 0.5), Some(u64::MAX));
+        assert_eq!(histogram.labels(), vec!["test1", "test2"]);
+    }
+
+    #[test]
+    fn test_queue_gauge() {
+        let gauge = QueueGauge::new("test");
+        gauge.record("data", "test1", 3, 16);
+        gauge.record("data", "test1", 1, 16);
+        assert_eq!(gauge.get("data", "test1"), Some(QueueDepth { depth: 1, high_watermark: 3, capacity: 16 }));
+        assert_eq!(gauge.get("data", "test2"), None);
+        let (sender, _receiver) = mpsc::channel(4);
+        sender.try_send(()).unwrap();
+        sender.try_send(()).unwrap();
+        gauge.record_channel("response", "test2", &sender);
+        assert_eq!(gauge.values(), vec![
+            ("data", "test1".to_string(), QueueDepth { depth: 1, high_watermark: 3, capacity: 16 }),
+            ("response", "test2".to_string(), QueueDepth { depth: 2, high_watermark: 2, capacity: 4 }),
+        ]);
+    }
+}
@@ -0,0 +1,224 @@
+//! Optional sink publishing compact JSON summaries to a MQTT broker, one topic per symbol,
+//! for lightweight consumers such as edge dashboards or alerting scripts.
+//! Messages are retained, so that a new subscriber immediately receives the latest summary.
+
+use log::error;
+use rumqttc::{AsyncClient, MqttOptions};
+use serde::{Deserialize, Serialize};
+use tokio::time::Duration;
+
+use crate::config::{DocumentedConfig, Validate, Validator};
+use crate::feed::CompactSummary;
+use crate::numbers::NumberFormat;
+use crate::orderbook::Summary;
+use crate::sink::{SinkError, SinkResult, SummarySink};
+
+pub use rumqttc::QoS;
+
+
+/// Default prefix of the topics, followed by the symbol.
+const DEFAULT_TOPIC_PREFIX: &str = "orderbook";
+/// Default host of the broker.
+const DEFAULT_HOST: &str = "localhost";
+/// Default port of the broker.
+const DEFAULT_PORT: u16 = 1883;
+/// Default identifier of the client at the broker.
+const DEFAULT_CLIENT_ID: &str = "orderbook-server";
+/// Capacity of the queue of messages waiting to be sent to the broker.
+const QUEUE_CAPACITY: usize = 16;
+/// Keep alive interval of the connection to the broker.
+const KEEP_ALIVE_S: u64 = 30;
+/// Delay before polling the connection again after an error, which triggers a reconnection.
+const RECONNECT_DELAY_MS: u64 = 1000;
+
+
+/// Settings of the MQTT sink, read from a JSON file. Missing values take their defaults.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct MqttSinkConfig {
+    /// Host of the broker.
+    pub host: String,
+    /// Port of the broker.
+    pub port: u16,
+    /// Identifier of the client at the broker.
+    pub client_id: String,
+    /// Prefix of the topics, the topic of a symbol being `<prefix>/<symbol>`.
+    pub topic_prefix: String,
+    /// Quality of service of the published messages: 0 (at most once), 1 (at least once) or 2 (exactly once).
+    pub qos: u8,
+}
+
+impl Default for MqttSinkConfig {
+    fn default() -> Self {
+        Self {
+            host: DEFAULT_HOST.to_string(),
+            port: DEFAULT_PORT,
+            client_id: DEFAULT_CLIENT_ID.to_string(),
+            topic_prefix: DEFAULT_TOPIC_PREFIX.to_string(),
+            qos: 0,
+        }
+    }
+}
+
+impl MqttSinkConfig {
+    /// The quality of service of the published messages, at most once unless valid.
+    pub fn qos(&self) -> QoS {
+        match self.qos {
+            1 => QoS::AtLeastOnce,
+            2 => QoS::ExactlyOnce,
+            _ => QoS::AtMostOnce,
+        }
+    }
+}
+
+impl Validate for MqttSinkConfig {
+    fn validate(&self, validator: &mut Validator) {
+        if self.host.is_empty() {
+            validator.error("host", "must not be empty");
+        }
+        validator.port("port", &self.port.to_string());
+        if self.client_id.is_empty() {
+            validator.error("client_id", "must not be empty");
+        }
+        validator.one_of("qos", self.qos, &[0, 1, 2]);
+    }
+}
+
+impl DocumentedConfig for MqttSinkConfig {
+    const DESCRIPTION: &'static str = "Settings of the MQTT sink.";
+    const FIELDS: &'static [(&'static str, &'static str)] = &[
+        ("host", "Host of the broker."),
+        ("port", "Port of the broker."),
+        ("client_id", "Identifier of the client at the broker."),
+        ("topic_prefix", "Prefix of the topics, the topic of a symbol being `<prefix>/<symbol>`."),
+        ("qos", "Quality of service of the published messages: 0 (at most once), 1 (at least once) or 2 (exactly once)."),
+    ];
+}
+
+/// Sink publishing summaries to a MQTT broker.
+pub struct MqttSink {
+    /// Connection options.
+    options: MqttOptions,
+    /// Prefix of the topics.
+    topic_prefix: String,
+    /// Quality of service of the published messages.
+    qos: QoS,
+    /// Formatting of the JSON numbers.
+    number_format: NumberFormat,
+    /// Client connected to the broker, created on first use.
+    client: Option<AsyncClient>,
+}
+
+impl MqttSink {
+    /// Create a new [MqttSink](MqttSink) object, publishing at most once to `orderbook/<symbol>`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client_id` - Identifier of the client at the broker.
+    ///
+    /// * `host` - Host of the broker.
+    ///
+    /// * `port` - Port of the broker.
+    pub fn new(client_id: &str, host: &str, port: u16) -> Self {
+        let mut options = MqttOptions::new(client_id, host, port);
+        options.set_keep_alive(Duration::from_secs(KEEP_ALIVE_S));
+        Self { options, topic_prefix: DEFAULT_TOPIC_PREFIX.to_string(), qos: QoS::AtMostOnce, number_format: NumberFormat::default(), client: None }
+    }
+
+    /// Create a new [MqttSink](MqttSink) object from a configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The [MqttSinkConfig](MqttSinkConfig).
+    pub fn from_config(config: &MqttSinkConfig) -> Self {
+        Self::new(&config.client_id, &config.host, config.port)
+            .with_topic_prefix(&config.topic_prefix)
+            .with_qos(config.qos())
+    }
+
+    /// Set the prefix of the topics.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic_prefix` - The prefix, the topic of a symbol being `<prefix>/<symbol>`.
+    pub fn with_topic_prefix(mut self, topic_prefix: &str) -> Self {
+        self.topic_prefix = topic_prefix.to_string();
+        self
+    }
+
+    /// Set the quality of service of the published messages.
+    ///
+    /// # Arguments
+    ///
+    /// * `qos` - The [QoS](QoS).
+    pub fn with_qos(mut self, qos: QoS) -> Self {
+        self.qos = qos;
+        self
+    }
+
+    /// Set the formatting of the JSON numbers.
+    ///
+    /// # Arguments
+    ///
+    /// * `number_format` - The [NumberFormat](NumberFormat).
+    pub fn with_number_format(mut self, number_format: NumberFormat) -> Self {
+        self.number_format = number_format;
+        self
+    }
+
+    /// Internal function creating the client, and spawning the task polling its connection.
+    fn connect(&self) -> AsyncClient {
+        let (client, mut event_loop) = AsyncClient::new(self.options.clone(), QUEUE_CAPACITY);
+        tokio::spawn(async move {
+            loop {
+                if let Err(error) = event_loop.poll().await {
+                    error!("MQTT connection error: {:?}", error);
+                    tokio::time::sleep(Duration::from_millis(RECONNECT_DELAY_MS)).await;
+                }
+            }
+        });
+        client
+    }
+}
+
+#[tonic::async_trait]
+impl SummarySink for MqttSink {
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
+
+    async fn on_summary(&mut self, summary: &Summary) -> SinkResult {
+        let payload = serde_json::to_vec(&CompactSummary::new(summary, &self.number_format))
+            .map_err(|error| SinkError::Transient(format!("error serializing summary: {:?}", error)))?;
+        let topic = format!("{}/{}", self.topic_prefix, summary.symbol);
+        let client = match &self.client {
+            Some(client) => client,
+            None => self.client.insert(self.connect()),
+        };
+        client.publish(topic, self.qos, true, payload).await
+            .map_err(|error| SinkError::Fatal(format!("error publishing to MQTT: {:?}", error)))
+    }
+
+    async fn close(&mut self) -> SinkResult {
+        if let Some(client) = self.client.take() {
+            client.disconnect().await
+                .map_err(|error| SinkError::Transient(format!("error disconnecting from MQTT: {:?}", error)))?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_config() {
+        let config: MqttSinkConfig = serde_json::from_str(r#"{"host": "broker.local", "qos": 1}"#).unwrap();
+        assert_eq!(config, MqttSinkConfig { host: "broker.local".to_string(), qos: 1, ..MqttSinkConfig::default() });
+        let sink = MqttSink::from_config(&config);
+        assert_eq!(sink.options.broker_address(), ("broker.local".to_string(), 1883));
+        assert_eq!((sink.topic_prefix.as_str(), sink.qos), ("orderbook", QoS::AtLeastOnce));
+    }
+}
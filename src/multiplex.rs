@@ -0,0 +1,169 @@
+//! Multiplexing of the summary streams of several symbols on a single client stream, fairly:
+//! each symbol has a queue of its own, bounded so that a hot symbol only drops its own oldest
+//! summaries, never its [status frames](crate::orderbook::StreamStatus) while it has summaries
+//! queued, and the queues are served in round-robin order, so that a hot symbol cannot
+//! starve the others. The delivery lag of each symbol is published in
+//! [MULTIPLEX_LAGS](crate::metrics::MULTIPLEX_LAGS).
+
+use futures::stream::Stream;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::time::Instant;
+
+use crate::clock::SharedClock;
+use crate::metrics::{MULTIPLEX_DROPPED, MULTIPLEX_LAGS, QUEUE_DEPTHS};
+use crate::orderbook::Summary;
+
+
+/// Summary stream of a symbol, with the summaries received and not yet delivered.
+struct MultiplexedSource<S> {
+    /// Canonical symbol, also labelling its metrics.
+    symbol: String,
+    /// The summary stream.
+    stream: S,
+    /// Summaries received, with the time of their reception.
+    queue: VecDeque<(Summary, Instant)>,
+    /// The stream ended.
+    done: bool,
+}
+
+/// [Stream](Stream) of the summaries of several symbols, served in round-robin order.
+///
+/// # Generic arguments
+///
+/// * `S` - Summary stream of a symbol.
+pub struct SummaryMultiplexer<S> {
+    /// The streams, one per symbol.
+    sources: Vec<MultiplexedSource<S>>,
+    /// Capacity of the queue of each symbol.
+    capacity: usize,
+    /// Index of the source served first on the next delivery.
+    next: usize,
+    /// Time source of the delivery lags.
+    clock: SharedClock,
+}
+
+impl <S: Stream<Item = Summary> + Unpin> SummaryMultiplexer<S> {
+    /// Create a new [SummaryMultiplexer](SummaryMultiplexer) object.
+    ///
+    /// # Arguments
+    ///
+    /// * `sources` - The summary streams, with their canonical symbol.
+    ///
+    /// * `capacity` - Capacity of the queue of each symbol: once full, the oldest summary is dropped.
+    ///
+    /// * `clock` - Time source of the delivery lags.
+    pub fn new(sources: Vec<(String, S)>, capacity: usize, clock: SharedClock) -> Self {
+        let sources = sources.into_iter()
+            .map(|(symbol, stream)| MultiplexedSource {
+                symbol,
+                stream,
+                queue: VecDeque::with_capacity(capacity),
+                done: false,
+            })
+            .collect();
+        Self { sources, capacity: capacity.max(1), next: 0, clock }
+    }
+
+    /// Stop multiplexing, e.g. to disconnect the streams.
+    ///
+    /// # Returns
+    ///
+    /// The summary streams, in the order given.
+    pub fn into_sources(self) -> Vec<S> {
+        self.sources.into_iter().map(|source| source.stream).collect()
+    }
+}
+
+impl <S: Stream<Item = Summary> + Unpin> Stream for SummaryMultiplexer<S> {
+    type Item = Summary;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let now = this.clock.now();
+        // at most a queue worth of summaries received per symbol, so that a stream always
+        // ready cannot keep the others waiting
+        for source in &mut this.sources {
+            for _ in 0..this.capacity {
+                if source.done {
+                    break;
+                }
+                match Pin::new(&mut source.stream).poll_next(cx) {
+                    Poll::Ready(Some(summary)) => {
+                        if source.queue.len() == this.capacity {
+                            let oldest = source.queue.iter().position(|(queued, _)| queued.frame.is_none()).unwrap_or(0);
+                            source.queue.remove(oldest);
+                            MULTIPLEX_DROPPED.increment(&source.symbol);
+                        }
+                        source.queue.push_back((summary, now));
+                    },
+                    Poll::Ready(None) => source.done = true,
+                    Poll::Pending => break,
+                }
+            }
+            QUEUE_DEPTHS.record("multiplex", &source.symbol, source.queue.len(), this.capacity);
+        }
+        let count = this.sources.len();
+        for offset in 0..count {
+            let index = (this.next + offset) % count;
+            let source = &mut this.sources[index];
+            if let Some((summary, received)) = source.queue.pop_front() {
+                MULTIPLEX_LAGS.set(&source.symbol, now.saturating_duration_since(received).as_millis() as u64);
+                this.next = (index + 1) % count;
+                return Poll::Ready(Some(summary));
+            }
+        }
+        if this.sources.iter().all(|source| source.done) {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{stream, StreamExt};
+    use std::sync::Arc;
+    use crate::clock::ManualClock;
+    use crate::orderbook::StreamStatus;
+    use crate::orderbook::summary::Frame;
+
+    fn summaries(symbol: &str, count: usize) -> stream::Iter<std::vec::IntoIter<Summary>> {
+        let summaries: Vec<Summary> = (0..count).map(|i| Summary { symbol: symbol.to_string(), spread: i as f64, ..Default::default() }).collect();
+        stream::iter(summaries)
+    }
+
+    #[tokio::test]
+    async fn test_round_robin() {
+        let clock = Arc::new(ManualClock::new());
+        let dropped = MULTIPLEX_DROPPED.get("HOT-USDT");
+        let multiplexer = SummaryMultiplexer::new(vec![
+            ("HOT-USDT".to_string(), summaries("HOT-USDT", 100)),
+            ("COLD-USDT".to_string(), summaries("COLD-USDT", 2)),
+        ], 4, clock);
+        let delivered: Vec<(String, f64)> = multiplexer.map(|summary| (summary.symbol, summary.spread)).collect().await;
+        let symbols: Vec<&str> = delivered.iter().take(4).map(|(symbol, _)| symbol.as_str()).collect();
+        assert_eq!(symbols, vec!["HOT-USDT", "COLD-USDT", "HOT-USDT", "COLD-USDT"]);
+        // the hot symbol drops its own oldest summaries, and ends with its latest one
+        assert!(delivered.len() < 102);
+        assert_eq!(delivered.last(), Some(&("HOT-USDT".to_string(), 99.0)));
+        assert!(MULTIPLEX_DROPPED.get("HOT-USDT") > dropped);
+        assert_eq!(MULTIPLEX_DROPPED.get("COLD-USDT"), 0);
+        assert_eq!(MULTIPLEX_LAGS.get("COLD-USDT"), Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_status_frames_kept() {
+        let mut queued: Vec<Summary> = summaries("HOT-USDT", 10).collect().await;
+        queued.insert(1, Summary { symbol: "HOT-USDT".to_string(), frame: Some(Frame::Status(StreamStatus::default())), ..Default::default() });
+        let multiplexer = SummaryMultiplexer::new(vec![("HOT-USDT".to_string(), stream::iter(queued))], 4, Arc::new(ManualClock::new()));
+        let delivered: Vec<Summary> = multiplexer.collect().await;
+        assert!(delivered.len() < 11);
+        assert_eq!(delivered.iter().filter(|summary| summary.frame.is_some()).count(), 1);
+        assert_eq!(delivered.last().map(|summary| summary.spread), Some(9.0));
+    }
+}
@@ -0,0 +1,332 @@
+//! Canonical formatting of the numbers written to string outputs, such as the
+//! [compact JSON summaries](crate::feed::CompactSummary) of the sinks: plain decimal notation,
+//! never scientific, with `.` as the only separator whatever the locale, no trailing zeros,
+//! and optionally rounded to a maximum number of decimal places per symbol. The floating point
+//! numbers of the Protobuf summaries can also be rounded to significant digits per symbol.
+//! The JSON numbers received from the exchanges are parsed here into decimals.
+
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+use serde_json::value::RawValue;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::config::{DocumentedConfig, Validate, Validator};
+
+
+/// Format a decimal number.
+///
+/// # Arguments
+///
+/// * `value` - The number.
+///
+/// * `max_decimals` - Maximum number of decimal places, rounding half away from zero,
+///   [None](None) for no limit.
+///
+/// # Returns
+///
+/// The number in plain decimal notation, without trailing zeros, e.g. `0.00000105` or `99`.
+pub fn format_decimal(value: Decimal, max_decimals: Option<u32>) -> String {
+    let value = match max_decimals {
+        Some(decimals) => value.round_dp_with_strategy(decimals, RoundingStrategy::MidpointAwayFromZero),
+        None => value,
+    };
+    let value = value.normalize();
+    if value.is_zero() {
+        "0".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Format a floating point number.
+///
+/// # Arguments
+///
+/// * `value` - The number.
+///
+/// * `max_decimals` - Maximum number of decimal places, rounding half away from zero,
+///   [None](None) for no limit.
+///
+/// # Returns
+///
+/// The number in plain decimal notation, without trailing zeros, parsing back to the same
+/// value if not rounded, or [None](None) if the number is not finite.
+pub fn format_f64(value: f64, max_decimals: Option<u32>) -> Option<String> {
+    if !value.is_finite() {
+        return None;
+    }
+    if value == 0.0 {
+        return Some("0".to_string());
+    }
+    match max_decimals.and_then(|_| Decimal::from_f64(value)) {
+        Some(decimal) => Some(format_decimal(decimal, max_decimals)),
+        // the shortest representation of a float parsing back to the same value, never scientific
+        None => Some(value.to_string()),
+    }
+}
+
+/// Convert a decimal number to floating point, rounded to significant digits.
+///
+/// # Arguments
+///
+/// * `value` - The number.
+///
+/// * `significant_digits` - The number of significant digits, rounding half away from zero,
+///   [None](None) for no rounding.
+///
+/// # Returns
+///
+/// The floating point number closest to the rounded number, `NaN` if not representable.
+pub fn round_significant(value: Decimal, significant_digits: Option<u32>) -> f64 {
+    let value = match significant_digits {
+        Some(digits) => value.round_sf_with_strategy(digits, RoundingStrategy::MidpointAwayFromZero).unwrap_or(value),
+        None => value,
+    };
+    value.to_f64().unwrap_or(f64::NAN)
+}
+
+/// Convert a JSON number, as sent by some exchanges for prices and amounts, into a
+/// [Decimal](Decimal) without going through floating point, small numbers being written in
+/// scientific notation.
+///
+/// # Arguments
+///
+/// * `value` - The JSON value.
+///
+/// # Returns
+///
+/// An optional [Decimal](Decimal), [None](None) if the value is not a number or out of range.
+pub fn json_decimal(value: &Value) -> Option<Decimal> {
+    let Value::Number(number) = value else {
+        return None;
+    };
+    let text = number.to_string();
+    if text.contains(['e', 'E']) {
+        Decimal::from_scientific(&text).ok()
+    } else {
+        Decimal::from_str(&text).ok()
+    }
+}
+
+/// A number already formatted in its canonical form, serialized as a bare JSON number.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CanonicalNumber(String);
+
+impl CanonicalNumber {
+    /// The canonical form of the number.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Serialize for CanonicalNumber {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        RawValue::from_string(self.0.clone())
+            .map_err(serde::ser::Error::custom)?
+            .serialize(serializer)
+    }
+}
+
+/// Configuration of the formatting of numbers. Missing values take their defaults.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct NumberFormat {
+    /// Maximum number of decimal places of the symbols without a specific maximum,
+    /// [None](None) for no limit.
+    pub max_decimals: Option<u32>,
+    /// Maximum number of decimal places of specific symbols.
+    pub symbol_max_decimals: HashMap<String, u32>,
+}
+
+impl NumberFormat {
+    /// Set the maximum number of decimal places of the symbols without a specific maximum.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_decimals` - The maximum number of decimal places.
+    ///
+    /// # Returns
+    ///
+    /// The modified [NumberFormat](NumberFormat).
+    pub fn with_max_decimals(mut self, max_decimals: u32) -> Self {
+        self.max_decimals = Some(max_decimals);
+        self
+    }
+
+    /// Set the maximum number of decimal places of a symbol.
+    ///
+    /// # Arguments
+    ///
+    /// * `symbol` - The canonical symbol.
+    ///
+    /// * `max_decimals` - The maximum number of decimal places.
+    ///
+    /// # Returns
+    ///
+    /// The modified [NumberFormat](NumberFormat).
+    pub fn with_symbol_max_decimals(mut self, symbol: &str, max_decimals: u32) -> Self {
+        self.symbol_max_decimals.insert(symbol.to_string(), max_decimals);
+        self
+    }
+
+    /// Maximum number of decimal places of a symbol, [None](None) for no limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `symbol` - The canonical symbol.
+    pub fn max_decimals(&self, symbol: &str) -> Option<u32> {
+        self.symbol_max_decimals.get(symbol).copied().or(self.max_decimals)
+    }
+
+    /// Format a number of a symbol.
+    ///
+    /// # Arguments
+    ///
+    /// * `symbol` - The canonical symbol.
+    ///
+    /// * `value` - The number.
+    ///
+    /// # Returns
+    ///
+    /// The [CanonicalNumber](CanonicalNumber), [None](None) if the number is not finite.
+    pub fn format(&self, symbol: &str, value: f64) -> Option<CanonicalNumber> {
+        format_f64(value, self.max_decimals(symbol)).map(CanonicalNumber)
+    }
+}
+
+impl Validate for NumberFormat {
+    fn validate(&self, _validator: &mut Validator) {}
+}
+
+impl DocumentedConfig for NumberFormat {
+    const DESCRIPTION: &'static str = "Formatting of the numbers published by the JSON sinks.";
+    const FIELDS: &'static [(&'static str, &'static str)] = &[
+        ("max_decimals", "Maximum number of decimal places of the symbols without a specific maximum, null for no limit."),
+        ("symbol_max_decimals", "Maximum number of decimal places of specific symbols, by canonical symbol."),
+    ];
+}
+
+
+/// Configuration of the rounding of the floating point numbers of the Protobuf summaries.
+/// Missing values take their defaults.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct FloatRounding {
+    /// Significant digits of the symbols without a specific number, [None](None) for no rounding.
+    pub significant_digits: Option<u32>,
+    /// Significant digits of specific symbols.
+    pub symbol_significant_digits: HashMap<String, u32>,
+}
+
+impl FloatRounding {
+    /// Significant digits of a symbol, [None](None) for no rounding.
+    ///
+    /// # Arguments
+    ///
+    /// * `symbol` - The canonical symbol.
+    pub fn significant_digits(&self, symbol: &str) -> Option<u32> {
+        self.symbol_significant_digits.get(symbol).copied().or(self.significant_digits)
+    }
+}
+
+impl Validate for FloatRounding {
+    fn validate(&self, validator: &mut Validator) {
+        if let Some(significant_digits) = self.significant_digits {
+            validator.positive("significant_digits", significant_digits as u64);
+        }
+        let mut symbols: Vec<&String> = self.symbol_significant_digits.keys().collect();
+        symbols.sort();
+        for symbol in symbols {
+            validator.positive(&format!("symbol_significant_digits.{}", symbol), self.symbol_significant_digits[symbol] as u64);
+        }
+    }
+}
+
+impl DocumentedConfig for FloatRounding {
+    const DESCRIPTION: &'static str = "Rounding of the numbers of the summaries.";
+    const FIELDS: &'static [(&'static str, &'static str)] = &[
+        ("significant_digits", "Significant digits of the symbols without a specific number, null for no rounding."),
+        ("symbol_significant_digits", "Significant digits of specific symbols, by canonical symbol."),
+    ];
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_f64_never_scientific() {
+        assert_eq!(format_f64(1.05e-6, None).unwrap(), "0.00000105");
+        assert_eq!(format_f64(1e21, None).unwrap(), "1000000000000000000000");
+        assert_eq!(format_f64(99.0, None).unwrap(), "99");
+        assert_eq!(format_f64(-0.0, None).unwrap(), "0");
+        assert_eq!(format_f64(f64::NAN, None), None);
+        assert_eq!(format_f64(f64::INFINITY, Some(2)), None);
+    }
+
+    #[test]
+    fn test_format_f64_round_trip() {
+        for value in [0.1, 0.07081, 1.05e-6, 3.3e-12, 123456.789, 1e21, -42.5, f64::MIN_POSITIVE, f64::MAX] {
+            let formatted = format_f64(value, None).unwrap();
+            assert!(formatted.chars().all(|c| c.is_ascii_digit() || c == '.' || c == '-'), "{}", formatted);
+            assert_eq!(formatted.parse::<f64>().unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn test_round_significant() {
+        let value = Decimal::from_str("0.070814999").unwrap();
+        assert_eq!(round_significant(value, Some(4)), 0.07081);
+        assert_eq!(round_significant(value, None), 0.070814999);
+        assert_eq!(round_significant(Decimal::from_str("123456.5").unwrap(), Some(6)), 123457.0);
+        let rounding: FloatRounding = serde_json::from_str(r#"{"significant_digits": 8, "symbol_significant_digits": {"ETH-BTC": 6}}"#).unwrap();
+        assert_eq!((rounding.significant_digits("ETH-BTC"), rounding.significant_digits("BTC-USD")), (Some(6), Some(8)));
+    }
+
+    #[test]
+    fn test_format_max_decimals() {
+        assert_eq!(format_f64(0.123456, Some(4)).unwrap(), "0.1235");
+        assert_eq!(format_f64(1.5, Some(0)).unwrap(), "2");
+        assert_eq!(format_f64(1.10, Some(4)).unwrap(), "1.1");
+        assert_eq!(format_f64(1e-9, Some(4)).unwrap(), "0");
+        assert_eq!(format_decimal(Decimal::from_str("0.0708100").unwrap(), None), "0.07081");
+        assert_eq!(format_decimal(Decimal::from_str("-0.00001").unwrap(), Some(2)), "0");
+    }
+
+    #[test]
+    fn test_decimal_round_trip() {
+        for text in ["0.07081", "1234567.0000001", "0.00000000000000000001", "-17"] {
+            let value = Decimal::from_str(text).unwrap();
+            assert_eq!(Decimal::from_str(&format_decimal(value, None)).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn test_json_decimal() {
+        let values: Vec<Value> = serde_json::from_str("[0.0612, 1.5e-7, 27123, \"1\"]").unwrap();
+        let parsed: Vec<Option<Decimal>> = values.iter().map(json_decimal).collect();
+        assert_eq!(parsed, vec![Decimal::from_str("0.0612").ok(), Decimal::from_str("0.00000015").ok(), Some(Decimal::from(27123)), None]);
+    }
+
+    #[test]
+    fn test_symbol_max_decimals() {
+        let format: NumberFormat = serde_json::from_str(r#"{"max_decimals": 2, "symbol_max_decimals": {"ETH-BTC": 5}}"#).unwrap();
+        assert_eq!(format, NumberFormat::default().with_max_decimals(2).with_symbol_max_decimals("ETH-BTC", 5));
+        assert_eq!(format.format("ETH-BTC", 0.0708149).unwrap().as_str(), "0.07081");
+        assert_eq!(format.format("BTC-USD", 27123.456).unwrap().as_str(), "27123.46");
+        assert_eq!(NumberFormat::default().format("BTC-USD", 27123.456).unwrap().as_str(), "27123.456");
+    }
+
+    #[test]
+    fn test_canonical_number_json() {
+        let number = NumberFormat::default().format("ETH-BTC", 1.05e-6).unwrap();
+        let json = serde_json::to_string(&vec![number]).unwrap();
+        assert_eq!(json, "[0.00000105]");
+        let parsed: Vec<f64> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, vec![1.05e-6]);
+    }
+}
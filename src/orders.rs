@@ -0,0 +1,256 @@
+//! Order-by-order (L3) book of an exchange, maintained from [order events](OrderEvent) and
+//! reduced to price levels for the [aggregate book](crate::aggregator::AggregateBook).
+//! Orders at each price are kept in queue order, so that the position of an order in its
+//! queue can be analyzed.
+
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::core::{BookUpdate, ExchangeId, ExchangeLevel, OrderEvent, Side};
+
+
+/// An order of the book.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Order {
+    /// [Buy](Side::Buy) for bids, [Sell](Side::Sell) for asks.
+    pub side: Side,
+    /// Limit price.
+    pub price: Decimal,
+    /// Amount left.
+    pub amount: Decimal,
+}
+
+/// Position of an order in the queue of its price.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct QueuePosition {
+    /// Number of orders ahead.
+    pub orders_ahead: usize,
+    /// Total amount of the orders ahead.
+    pub amount_ahead: Decimal,
+}
+
+/// Orders of a price, in queue order, with their total amount.
+#[derive(Debug, Default)]
+struct PriceQueue {
+    /// Total amount of the orders.
+    amount: Decimal,
+    /// Order identifiers, from the first in the queue.
+    order_ids: Vec<String>,
+}
+
+/// Order-by-order book of an exchange.
+#[derive(Debug)]
+pub struct OrderBook {
+    /// Exchange code.
+    exchange_code: ExchangeId,
+    /// Orders by identifier.
+    orders: HashMap<String, Order>,
+    /// Queue of each bid price.
+    bids: BTreeMap<Decimal, PriceQueue>,
+    /// Queue of each ask price.
+    asks: BTreeMap<Decimal, PriceQueue>,
+}
+
+impl OrderBook {
+    /// Create a new empty [OrderBook](OrderBook) object.
+    ///
+    /// # Arguments
+    ///
+    /// * `exchange_code` - The code of the exchange.
+    pub fn new(exchange_code: impl Into<ExchangeId>) -> Self {
+        Self { exchange_code: exchange_code.into(), orders: HashMap::new(), bids: BTreeMap::new(), asks: BTreeMap::new() }
+    }
+
+    /// Remove all the orders, e.g. before applying a snapshot.
+    pub fn clear(&mut self) {
+        self.orders.clear();
+        self.bids.clear();
+        self.asks.clear();
+    }
+
+    /// Apply an order event. Events about unknown orders are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The [OrderEvent](OrderEvent).
+    ///
+    /// # Returns
+    ///
+    /// Whether the book changed.
+    pub fn apply(&mut self, event: OrderEvent) -> bool {
+        match event {
+            OrderEvent::Add { order_id, side, price, amount } => {
+                if let Some(order) = self.orders.get(&order_id) {
+                    if order.side == side && order.price == price {
+                        return self.modify(&order_id, amount);
+                    }
+                    self.delete(&order_id);
+                }
+                let queue = self.side_mut(side).entry(price).or_default();
+                queue.amount += amount;
+                queue.order_ids.push(order_id.clone());
+                self.orders.insert(order_id, Order { side, price, amount });
+                true
+            },
+            OrderEvent::Modify { order_id, amount } => self.modify(&order_id, amount),
+            OrderEvent::Delete { order_id } => self.delete(&order_id),
+        }
+    }
+
+    /// Internal function changing the amount of an order in place.
+    fn modify(&mut self, order_id: &str, amount: Decimal) -> bool {
+        let Some(order) = self.orders.get_mut(order_id) else {
+            return false;
+        };
+        let previous = std::mem::replace(&mut order.amount, amount);
+        let (side, price) = (order.side, order.price);
+        if let Some(queue) = self.side_mut(side).get_mut(&price) {
+            queue.amount += amount - previous;
+        }
+        true
+    }
+
+    /// Internal function removing an order, and its price if no order is left.
+    fn delete(&mut self, order_id: &str) -> bool {
+        let Some(order) = self.orders.remove(order_id) else {
+            return false;
+        };
+        let side = self.side_mut(order.side);
+        if let Some(queue) = side.get_mut(&order.price) {
+            queue.order_ids.retain(|id| id != order_id);
+            queue.amount -= order.amount;
+            if queue.order_ids.is_empty() {
+                side.remove(&order.price);
+            }
+        }
+        true
+    }
+
+    /// Internal utility function selecting a side of the book.
+    fn side_mut(&mut self, side: Side) -> &mut BTreeMap<Decimal, PriceQueue> {
+        match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        }
+    }
+
+    /// Reduce the orders to price levels.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_levels` - Maximum number of levels of each side, [None](None) for all of them.
+    ///
+    /// # Returns
+    ///
+    /// A [BookUpdate](BookUpdate) with the total amount and the number of orders of each price,
+    /// from the best price.
+    pub fn book_update(&self, max_levels: Option<usize>) -> BookUpdate {
+        let max_levels = max_levels.unwrap_or(usize::MAX);
+        let level = |(&price, queue): (&Decimal, &PriceQueue)| ExchangeLevel {
+            exchange_code: self.exchange_code.clone(),
+            price,
+            amount: queue.amount,
+            order_count: Some(queue.order_ids.len() as u32),
+        };
+        BookUpdate {
+            exchange_code: self.exchange_code.clone(),
+            bids: self.bids.iter().rev().take(max_levels).map(level).collect(),
+            asks: self.asks.iter().take(max_levels).map(level).collect(),
+        }
+    }
+
+    /// An order of the book.
+    ///
+    /// # Arguments
+    ///
+    /// * `order_id` - The order identifier.
+    pub fn order(&self, order_id: &str) -> Option<&Order> {
+        self.orders.get(order_id)
+    }
+
+    /// Number of orders in the book.
+    pub fn order_count(&self) -> usize {
+        self.orders.len()
+    }
+
+    /// Position of an order in the queue of its price.
+    ///
+    /// # Arguments
+    ///
+    /// * `order_id` - The order identifier.
+    ///
+    /// # Returns
+    ///
+    /// The [QueuePosition](QueuePosition), [None](None) if the order is not in the book.
+    pub fn queue_position(&self, order_id: &str) -> Option<QueuePosition> {
+        let order = self.orders.get(order_id)?;
+        let side = match order.side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
+        let queue = side.get(&order.price)?;
+        let orders_ahead = queue.order_ids.iter().position(|id| id == order_id)?;
+        let amount_ahead = queue.order_ids[..orders_ahead].iter()
+            .filter_map(|id| self.orders.get(id))
+            .map(|order| order.amount)
+            .sum();
+        Some(QueuePosition { orders_ahead, amount_ahead })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn add(order_id: &str, side: Side, price: &str, amount: &str) -> OrderEvent {
+        OrderEvent::Add {
+            order_id: order_id.to_string(),
+            side,
+            price: Decimal::from_str(price).unwrap(),
+            amount: Decimal::from_str(amount).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_reduce_to_levels() {
+        let mut book = OrderBook::new("test");
+        book.apply(add("1", Side::Buy, "99", "1"));
+        book.apply(add("2", Side::Buy, "99", "2"));
+        book.apply(add("3", Side::Buy, "98", "5"));
+        book.apply(add("4", Side::Sell, "100", "1"));
+        assert_eq!(book.book_update(None), BookUpdate {
+            exchange_code: "test".into(),
+            bids: vec![ExchangeLevel::from_strs("test", "99", "3").with_order_count(2), ExchangeLevel::from_strs("test", "98", "5").with_order_count(1)],
+            asks: vec![ExchangeLevel::from_strs("test", "100", "1").with_order_count(1)],
+        });
+        assert_eq!(book.book_update(Some(1)).bids, vec![ExchangeLevel::from_strs("test", "99", "3").with_order_count(2)]);
+        assert!(book.apply(OrderEvent::Modify { order_id: "2".to_string(), amount: Decimal::from_str("0.5").unwrap() }));
+        assert!(book.apply(OrderEvent::Delete { order_id: "4".to_string() }));
+        assert!(!book.apply(OrderEvent::Delete { order_id: "4".to_string() }));
+        assert_eq!(book.book_update(None), BookUpdate {
+            exchange_code: "test".into(),
+            bids: vec![ExchangeLevel::from_strs("test", "99", "1.5").with_order_count(2), ExchangeLevel::from_strs("test", "98", "5").with_order_count(1)],
+            asks: vec![],
+        });
+        assert_eq!(book.order_count(), 3);
+    }
+
+    #[test]
+    fn test_queue_position() {
+        let mut book = OrderBook::new("test");
+        book.apply(add("1", Side::Sell, "100", "1"));
+        book.apply(add("2", Side::Sell, "100", "2"));
+        book.apply(add("3", Side::Sell, "100", "3"));
+        assert_eq!(book.queue_position("3"), Some(QueuePosition { orders_ahead: 2, amount_ahead: Decimal::from(3) }));
+        // a new amount at the same price keeps the place in the queue, a new price loses it
+        book.apply(add("1", Side::Sell, "100", "4"));
+        assert_eq!(book.queue_position("2"), Some(QueuePosition { orders_ahead: 1, amount_ahead: Decimal::from(4) }));
+        book.apply(add("1", Side::Sell, "101", "4"));
+        book.apply(add("1", Side::Sell, "100", "4"));
+        assert_eq!(book.queue_position("1"), Some(QueuePosition { orders_ahead: 2, amount_ahead: Decimal::from(5) }));
+        assert_eq!(book.order("1").unwrap().amount, Decimal::from(4));
+        assert_eq!(book.queue_position("9"), None);
+    }
+}
@@ -0,0 +1,110 @@
+//! Pausing of a client subscription: while paused, no summary is delivered, but the
+//! subscription keeps consolidating the books, and the latest summary of each symbol is kept,
+//! so that the client receives a fresh snapshot on resuming without subscribing again. The
+//! latest [status frame](crate::orderbook::StreamStatus) of each symbol is kept alongside, and
+//! delivered before its summary.
+
+use std::collections::BTreeMap;
+
+use crate::orderbook::Summary;
+
+
+/// Summaries withheld from a paused subscription.
+#[derive(Debug, Default)]
+pub struct PauseBuffer {
+    /// Whether the subscription is paused.
+    paused: bool,
+    /// Latest summary of each symbol received while paused, by symbol and whether it is a
+    /// summary rather than a status frame.
+    latest: BTreeMap<(String, bool), Summary>,
+}
+
+impl PauseBuffer {
+    /// Create a new [PauseBuffer](PauseBuffer) object, not paused.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the subscription is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Stop delivering summaries.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Deliver summaries again.
+    ///
+    /// # Returns
+    ///
+    /// The latest status frame and summary of each symbol received while paused, ordered by
+    /// symbol, to be delivered first.
+    pub fn resume(&mut self) -> Vec<Summary> {
+        self.paused = false;
+        std::mem::take(&mut self.latest).into_values().collect()
+    }
+
+    /// Offer a summary for delivery.
+    ///
+    /// # Arguments
+    ///
+    /// * `summary` - The [Summary](Summary).
+    ///
+    /// # Returns
+    ///
+    /// The summary to deliver, [None](None) if paused: the summary is then kept until resumed,
+    /// replacing the previous one of its symbol and kind.
+    pub fn offer(&mut self, summary: Summary) -> Option<Summary> {
+        if self.paused {
+            self.latest.insert((summary.symbol.clone(), summary.frame.is_none()), summary);
+            None
+        } else {
+            Some(summary)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderbook::{StreamStatus, StreamStatusReason};
+    use crate::orderbook::summary::Frame;
+
+    fn summary(symbol: &str, spread: f64) -> Summary {
+        Summary { symbol: symbol.to_string(), spread, ..Default::default() }
+    }
+
+    #[test]
+    fn test_pause_and_resume() {
+        let mut buffer = PauseBuffer::new();
+        assert_eq!(buffer.offer(summary("ETH-BTC", 1.0)), Some(summary("ETH-BTC", 1.0)));
+        buffer.pause();
+        assert!(buffer.is_paused());
+        assert_eq!(buffer.offer(summary("ETH-BTC", 2.0)), None);
+        assert_eq!(buffer.offer(summary("BTC-USDT", 3.0)), None);
+        assert_eq!(buffer.offer(summary("ETH-BTC", 4.0)), None);
+        buffer.pause();
+        assert_eq!(buffer.resume(), vec![summary("BTC-USDT", 3.0), summary("ETH-BTC", 4.0)]);
+        assert!(!buffer.is_paused());
+        assert_eq!(buffer.resume(), vec![]);
+        assert_eq!(buffer.offer(summary("ETH-BTC", 5.0)), Some(summary("ETH-BTC", 5.0)));
+    }
+
+    #[test]
+    fn test_status_frames_kept_apart() {
+        let status = |reason: StreamStatusReason| Summary {
+            symbol: "ETH-BTC".to_string(),
+            frame: Some(Frame::Status(StreamStatus { reason: reason as i32, ..Default::default() })),
+            ..Default::default()
+        };
+        let mut buffer = PauseBuffer::new();
+        buffer.pause();
+        assert_eq!(buffer.offer(summary("ETH-BTC", 1.0)), None);
+        assert_eq!(buffer.offer(status(StreamStatusReason::VenueDropped)), None);
+        assert_eq!(buffer.offer(status(StreamStatusReason::VenueResyncing)), None);
+        assert_eq!(buffer.resume(), vec![status(StreamStatusReason::VenueResyncing), summary("ETH-BTC", 1.0)]);
+    }
+}
@@ -0,0 +1,243 @@
+//! Optional sink streaming summaries to the standard output or to a named pipe, for composition
+//! with other processes, e.g. `server ETH-BTC | my-strategy`.
+//! A slow reader only causes intermediate summaries to be skipped. When the reader goes away,
+//! the sink stops if writing to the standard output, or waits for a new reader of the named pipe.
+
+use log::{info, warn};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::config::{DocumentedConfig, Validate, Validator};
+use crate::feed::CompactSummary;
+use crate::numbers::NumberFormat;
+use crate::orderbook::Summary;
+use crate::sink::{SinkError, SinkResult, SummarySink};
+
+
+/// Encoding of the summaries written by a [PipeSink](PipeSink).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum PipeFormat {
+    /// One [compact JSON summary](CompactSummary) per line.
+    #[serde(rename = "ndjson")]
+    Ndjson,
+    /// Protobuf messages, each prefixed by its length as a varint.
+    #[serde(rename = "protobuf")]
+    LengthPrefixedProtobuf,
+}
+
+/// Settings of the pipe sink, read from a JSON file. Missing values take their defaults.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct PipeSinkConfig {
+    /// Encoding of the summaries, `ndjson` or `protobuf` (length-prefixed).
+    pub format: PipeFormat,
+    /// Named pipe to write to, created beforehand, or `null` for the standard output.
+    pub named_pipe: Option<PathBuf>,
+}
+
+impl Default for PipeSinkConfig {
+    fn default() -> Self {
+        Self { format: PipeFormat::Ndjson, named_pipe: None }
+    }
+}
+
+impl Validate for PipeSinkConfig {
+    fn validate(&self, validator: &mut Validator) {
+        if self.named_pipe.as_ref().is_some_and(|path| path.as_os_str().is_empty()) {
+            validator.error("named_pipe", "must not be empty");
+        }
+    }
+}
+
+impl DocumentedConfig for PipeSinkConfig {
+    const DESCRIPTION: &'static str = "Settings of the pipe sink.";
+    const FIELDS: &'static [(&'static str, &'static str)] = &[
+        ("format", "Encoding of the summaries, `ndjson` or `protobuf` (length-prefixed)."),
+        ("named_pipe", "Named pipe to write to, created beforehand, or `null` for the standard output."),
+    ];
+}
+
+/// Encode a summary.
+///
+/// # Arguments
+///
+/// * `summary` - A [Summary](Summary).
+///
+/// * `format` - The [PipeFormat](PipeFormat).
+///
+/// * `number_format` - The [NumberFormat](NumberFormat) of the JSON numbers.
+///
+/// # Returns
+///
+/// The encoded bytes.
+pub fn encode(summary: &Summary, format: PipeFormat, number_format: &NumberFormat) -> Vec<u8> {
+    match format {
+        PipeFormat::Ndjson => {
+            let mut bytes = serde_json::to_vec(&CompactSummary::new(summary, number_format)).unwrap_or_default();
+            bytes.push(b'\n');
+            bytes
+        },
+        PipeFormat::LengthPrefixedProtobuf => summary.encode_length_delimited_to_vec(),
+    }
+}
+
+/// Sink streaming summaries to the standard output or to a named pipe.
+pub struct PipeSink {
+    /// Named pipe to write to, or `None` for the standard output.
+    path: Option<PathBuf>,
+    /// Encoding of the summaries.
+    format: PipeFormat,
+    /// Formatting of the JSON numbers.
+    number_format: NumberFormat,
+    /// Writer of the summaries, opened on first use and after the reader of the named pipe went away.
+    writer: Option<Box<dyn AsyncWrite + Send + Unpin>>,
+}
+
+impl PipeSink {
+    /// Create a [PipeSink](PipeSink) writing to the standard output.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The [PipeFormat](PipeFormat).
+    pub fn stdout(format: PipeFormat) -> Self {
+        Self { path: None, format, number_format: NumberFormat::default(), writer: None }
+    }
+
+    /// Create a [PipeSink](PipeSink) writing to a named pipe, created beforehand e.g. with `mkfifo`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The named pipe.
+    ///
+    /// * `format` - The [PipeFormat](PipeFormat).
+    pub fn named_pipe(path: PathBuf, format: PipeFormat) -> Self {
+        Self { path: Some(path), format, number_format: NumberFormat::default(), writer: None }
+    }
+
+    /// Create a [PipeSink](PipeSink) from a configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The [PipeSinkConfig](PipeSinkConfig).
+    pub fn from_config(config: &PipeSinkConfig) -> Self {
+        match &config.named_pipe {
+            Some(path) => Self::named_pipe(path.clone(), config.format),
+            None => Self::stdout(config.format),
+        }
+    }
+
+    /// Set the formatting of the JSON numbers.
+    ///
+    /// # Arguments
+    ///
+    /// * `number_format` - The [NumberFormat](NumberFormat).
+    ///
+    /// # Returns
+    ///
+    /// The modified [PipeSink](PipeSink).
+    pub fn with_number_format(mut self, number_format: NumberFormat) -> Self {
+        self.number_format = number_format;
+        self
+    }
+
+    /// Internal function opening the writer of the summaries.
+    async fn open(path: Option<&Path>) -> std::io::Result<Box<dyn AsyncWrite + Send + Unpin>> {
+        match path {
+            None => Ok(Box::new(tokio::io::stdout())),
+            Some(path) => {
+                info!("Waiting for a reader of {}", path.display());
+                // opening a named pipe for writing blocks until a reader opens it
+                let file = tokio::fs::OpenOptions::new().write(true).open(path).await?;
+                Ok(Box::new(file))
+            },
+        }
+    }
+}
+
+/// Write a summary and flush the writer.
+///
+/// # Arguments
+///
+/// * `writer` - The writer.
+///
+/// * `summary` - A [Summary](Summary).
+///
+/// * `format` - The [PipeFormat](PipeFormat).
+///
+/// * `number_format` - The [NumberFormat](NumberFormat) of the JSON numbers.
+pub async fn write_summary(writer: &mut (dyn AsyncWrite + Send + Unpin), summary: &Summary, format: PipeFormat, number_format: &NumberFormat) -> std::io::Result<()> {
+    writer.write_all(&encode(summary, format, number_format)).await?;
+    writer.flush().await
+}
+
+#[tonic::async_trait]
+impl SummarySink for PipeSink {
+    fn name(&self) -> &'static str {
+        "pipe"
+    }
+
+    async fn on_summary(&mut self, summary: &Summary) -> SinkResult {
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => {
+                let writer = Self::open(self.path.as_deref()).await
+                    .map_err(|error| SinkError::Fatal(format!("error opening pipe: {:?}", error)))?;
+                self.writer.insert(writer)
+            },
+        };
+        match write_summary(writer.as_mut(), summary, self.format, &self.number_format).await {
+            Ok(()) => Ok(()),
+            Err(error) if error.kind() == ErrorKind::BrokenPipe && self.path.is_some() => {
+                warn!("Reader of the named pipe went away");
+                self.writer = None;
+                Ok(())
+            },
+            Err(error) => Err(SinkError::Fatal(format!("error writing to pipe: {:?}", error))),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderbook::Level;
+
+    fn summary() -> Summary {
+        Summary {
+            spread: 1.0,
+            bids: vec![Level { exchange: "test1".to_string(), price: 99.0, amount: 1.5, order_count: None, latency_class: 0 }],
+            asks: vec![Level { exchange: "test2".to_string(), price: 100.0, amount: 2.0, order_count: None, latency_class: 0 }],
+            symbol: "ETH-BTC".to_string(),
+            depth: vec![],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_partial_config() {
+        let config: PipeSinkConfig = serde_json::from_str(r#"{"format": "protobuf"}"#).unwrap();
+        assert_eq!(config, PipeSinkConfig { format: PipeFormat::LengthPrefixedProtobuf, named_pipe: None });
+        let sink = PipeSink::from_config(&PipeSinkConfig { named_pipe: Some(PathBuf::from("summaries.fifo")), ..config });
+        assert_eq!((sink.path, sink.format), (Some(PathBuf::from("summaries.fifo")), PipeFormat::LengthPrefixedProtobuf));
+    }
+
+    #[test]
+    fn test_encode_length_prefixed_protobuf() {
+        let bytes = encode(&summary(), PipeFormat::LengthPrefixedProtobuf, &NumberFormat::default());
+        assert_eq!(Summary::decode_length_delimited(bytes.as_slice()).unwrap(), summary());
+    }
+
+    #[tokio::test]
+    async fn test_write_summary() {
+        let mut output: Vec<u8> = vec![];
+        write_summary(&mut output, &summary(), PipeFormat::Ndjson, &NumberFormat::default()).await.unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"s\":\"ETH-BTC\",\"sp\":1,\"b\":[[\"test1\",99,1.5]],\"a\":[[\"test2\",100,2]]}\n"
+        );
+    }
+}
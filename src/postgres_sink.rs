@@ -0,0 +1,306 @@
+//! Optional asynchronous sink writing consolidated snapshots and the best bid and offer
+//! of each exchange into PostgreSQL tables, turned into hypertables when the
+//! TimescaleDB extension is available.
+//! Rows are inserted in batches. While the database is unreachable rows are kept,
+//! up to a limit, and the connection is retried at each flush. Since the feed only
+//! delivers its latest summary, a slow database conflates summaries instead of
+//! slowing down the publishing path.
+
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::SystemTime;
+use tokio::time::Duration;
+use tokio_postgres::{Client, NoTls, types::ToSql};
+
+use crate::config::{DocumentedConfig, Validate, Validator};
+use crate::feed::{venue_bbos, SummaryMetrics, VenueBbo};
+use crate::orderbook::Summary;
+use crate::sink::{SinkError, SinkQueue, SinkResult, SummarySink, DEFAULT_SINK_QUEUE_CAPACITY};
+
+
+/// Statements creating the tables and hypertables, if they do not exist.
+const CREATE_TABLES: &str = "
+CREATE TABLE IF NOT EXISTS summaries (
+    time TIMESTAMPTZ NOT NULL,
+    symbol TEXT NOT NULL,
+    best_bid DOUBLE PRECISION,
+    best_ask DOUBLE PRECISION,
+    spread DOUBLE PRECISION,
+    bid_depth DOUBLE PRECISION,
+    ask_depth DOUBLE PRECISION
+);
+CREATE TABLE IF NOT EXISTS venue_bbo (
+    time TIMESTAMPTZ NOT NULL,
+    symbol TEXT NOT NULL,
+    exchange TEXT NOT NULL,
+    best_bid DOUBLE PRECISION,
+    best_ask DOUBLE PRECISION
+);";
+
+/// Statements turning the tables into TimescaleDB hypertables.
+const CREATE_HYPERTABLES: &str = "
+SELECT create_hypertable('summaries', 'time', if_not_exists => TRUE);
+SELECT create_hypertable('venue_bbo', 'time', if_not_exists => TRUE);";
+
+/// Columns of the `summaries` table.
+const SUMMARY_COLUMNS: [&str; 7] = ["time", "symbol", "best_bid", "best_ask", "spread", "bid_depth", "ask_depth"];
+/// Columns of the `venue_bbo` table.
+const VENUE_BBO_COLUMNS: [&str; 5] = ["time", "symbol", "exchange", "best_bid", "best_ask"];
+
+/// Default connection string.
+const DEFAULT_CONNECTION: &str = "host=localhost user=postgres dbname=orderbook";
+/// Default number of snapshots inserted at once.
+const DEFAULT_BATCH_SIZE: usize = 100;
+/// Default maximum time between two inserts.
+const DEFAULT_FLUSH_INTERVAL_MS: u64 = 1000;
+/// Maximum number of snapshots kept while the database is unreachable.
+const MAX_PENDING_SNAPSHOTS: usize = 100_000;
+
+
+/// Settings of the PostgreSQL sink, read from a JSON file. Missing values take their defaults.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct PostgresSinkConfig {
+    /// Connection string, e.g. `host=localhost user=postgres dbname=orderbook`.
+    pub connection: String,
+    /// Number of snapshots inserted at once.
+    pub batch_size: usize,
+    /// Maximum time between two inserts, in milliseconds.
+    pub flush_interval_ms: u64,
+}
+
+impl Default for PostgresSinkConfig {
+    fn default() -> Self {
+        Self {
+            connection: DEFAULT_CONNECTION.to_string(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            flush_interval_ms: DEFAULT_FLUSH_INTERVAL_MS,
+        }
+    }
+}
+
+impl Validate for PostgresSinkConfig {
+    fn validate(&self, validator: &mut Validator) {
+        if self.connection.is_empty() {
+            validator.error("connection", "must not be empty");
+        }
+        validator.positive("batch_size", self.batch_size as u64);
+        validator.positive("flush_interval_ms", self.flush_interval_ms);
+    }
+}
+
+impl DocumentedConfig for PostgresSinkConfig {
+    const DESCRIPTION: &'static str = "Settings of the PostgreSQL sink.";
+    const FIELDS: &'static [(&'static str, &'static str)] = &[
+        ("connection", "Connection string, e.g. `host=localhost user=postgres dbname=orderbook`."),
+        ("batch_size", "Number of snapshots inserted at once."),
+        ("flush_interval_ms", "Maximum time between two inserts, in milliseconds."),
+    ];
+}
+
+/// Build a multi-row insert statement with numbered placeholders.
+///
+/// # Arguments
+///
+/// * `table` - The table name.
+///
+/// * `columns` - The column names.
+///
+/// * `rows` - The number of rows.
+///
+/// # Returns
+///
+/// The SQL statement.
+fn insert_statement(table: &str, columns: &[&str], rows: usize) -> String {
+    let values: Vec<String> = (0..rows).map(|row| {
+        let placeholders: Vec<String> = (1..=columns.len()).map(|c| format!("${}", row * columns.len() + c)).collect();
+        format!("({})", placeholders.join(", "))
+    }).collect();
+    format!("INSERT INTO {} ({}) VALUES {}", table, columns.join(", "), values.join(", "))
+}
+
+/// A snapshot waiting to be inserted.
+struct PendingSnapshot {
+    /// Time the snapshot was taken.
+    time: SystemTime,
+    /// Canonical symbol of the currency pair.
+    symbol: String,
+    /// Consolidated figures.
+    metrics: SummaryMetrics,
+    /// Best bid and offer of each exchange.
+    venues: Vec<VenueBbo>,
+}
+
+/// Sink inserting snapshots into PostgreSQL.
+pub struct PostgresSink {
+    /// Connection string, e.g. `host=localhost user=postgres dbname=orderbook`.
+    config: String,
+    /// Number of snapshots inserted at once.
+    batch_size: usize,
+    /// Maximum time between two inserts.
+    flush_interval: Duration,
+    /// Snapshots waiting to be inserted.
+    pending: VecDeque<PendingSnapshot>,
+    /// Connection to the database, if established.
+    client: Option<Client>,
+}
+
+impl PostgresSink {
+    /// Create a new [PostgresSink](PostgresSink) object, with default batch size and flush interval.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The connection string.
+    pub fn new(config: &str) -> Self {
+        Self {
+            config: config.to_string(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            flush_interval: Duration::from_millis(DEFAULT_FLUSH_INTERVAL_MS),
+            pending: VecDeque::new(),
+            client: None,
+        }
+    }
+
+    /// Create a new [PostgresSink](PostgresSink) object from a configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The [PostgresSinkConfig](PostgresSinkConfig).
+    pub fn from_config(config: &PostgresSinkConfig) -> Self {
+        Self {
+            batch_size: config.batch_size,
+            flush_interval: Duration::from_millis(config.flush_interval_ms),
+            ..Self::new(&config.connection)
+        }
+    }
+
+    /// Internal function connecting to the database and creating the tables.
+    async fn connect(&self) -> Option<Client> {
+        info!("Connecting to PostgreSQL");
+        let (client, connection) = match tokio_postgres::connect(&self.config, NoTls).await {
+            Ok(result) => result,
+            Err(error) => {
+                error!("Error connecting to PostgreSQL: {:?}", error);
+                return None;
+            }
+        };
+        tokio::spawn(async move {
+            if let Err(error) = connection.await {
+                error!("PostgreSQL connection error: {:?}", error);
+            }
+        });
+        if let Err(error) = client.batch_execute(CREATE_TABLES).await {
+            error!("Error creating PostgreSQL tables: {:?}", error);
+            return None;
+        }
+        if let Err(error) = client.batch_execute(CREATE_HYPERTABLES).await {
+            info!("Hypertables not created, TimescaleDB probably not available: {}", error);
+        }
+        Some(client)
+    }
+
+    /// Internal function inserting pending snapshots in batches, within a transaction per batch.
+    /// Snapshots are removed from `pending` only once inserted.
+    async fn insert(client: &mut Client, pending: &mut VecDeque<PendingSnapshot>, batch_size: usize) -> Result<(), tokio_postgres::Error> {
+        while !pending.is_empty() {
+            let batch_len = pending.len().min(batch_size);
+            let batch: Vec<&PendingSnapshot> = pending.iter().take(batch_len).collect();
+            let mut summary_params: Vec<&(dyn ToSql + Sync)> = vec![];
+            let mut venue_params: Vec<&(dyn ToSql + Sync)> = vec![];
+            for snapshot in &batch {
+                summary_params.extend_from_slice(&[
+                    &snapshot.time, &snapshot.symbol,
+                    &snapshot.metrics.best_bid, &snapshot.metrics.best_ask, &snapshot.metrics.spread,
+                    &snapshot.metrics.bid_depth, &snapshot.metrics.ask_depth,
+                ]);
+                for venue in &snapshot.venues {
+                    venue_params.extend_from_slice(&[
+                        &snapshot.time, &snapshot.symbol, &venue.exchange, &venue.best_bid, &venue.best_ask,
+                    ]);
+                }
+            }
+            let transaction = client.transaction().await?;
+            transaction.execute(&insert_statement("summaries", &SUMMARY_COLUMNS, batch.len()), &summary_params).await?;
+            if !venue_params.is_empty() {
+                let venue_rows = venue_params.len() / VENUE_BBO_COLUMNS.len();
+                transaction.execute(&insert_statement("venue_bbo", &VENUE_BBO_COLUMNS, venue_rows), &venue_params).await?;
+            }
+            transaction.commit().await?;
+            pending.drain(..batch_len);
+        }
+        Ok(())
+    }
+}
+
+#[tonic::async_trait]
+impl SummarySink for PostgresSink {
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    fn queue(&self) -> SinkQueue {
+        SinkQueue::Bounded(DEFAULT_SINK_QUEUE_CAPACITY)
+    }
+
+    fn flush_interval(&self) -> Option<Duration> {
+        Some(self.flush_interval)
+    }
+
+    async fn on_summary(&mut self, summary: &Summary) -> SinkResult {
+        if self.pending.len() >= MAX_PENDING_SNAPSHOTS {
+            warn!("PostgreSQL sink lagging, dropping oldest snapshot");
+            self.pending.pop_front();
+        }
+        self.pending.push_back(PendingSnapshot {
+            time: SystemTime::now(),
+            symbol: summary.symbol.clone(),
+            metrics: SummaryMetrics::from(summary),
+            venues: venue_bbos(summary),
+        });
+        if self.pending.len() >= self.batch_size {
+            self.flush().await
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn flush(&mut self) -> SinkResult {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        if self.client.is_none() {
+            self.client = self.connect().await;
+        }
+        let Some(client) = self.client.as_mut() else {
+            return Err(SinkError::Transient("PostgreSQL not reachable, retrying at next flush".to_string()));
+        };
+        if let Err(error) = Self::insert(client, &mut self.pending, self.batch_size).await {
+            self.client = None;
+            return Err(SinkError::Transient(format!("error inserting into PostgreSQL, reconnecting at next flush: {:?}", error)));
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_statement() {
+        assert_eq!(
+            insert_statement("venue_bbo", &["a", "b"], 2),
+            "INSERT INTO venue_bbo (a, b) VALUES ($1, $2), ($3, $4)"
+        );
+    }
+
+    #[test]
+    fn test_partial_config() {
+        let config: PostgresSinkConfig = serde_json::from_str(r#"{"connection": "host=db user=orderbook"}"#).unwrap();
+        assert_eq!(config, PostgresSinkConfig { connection: "host=db user=orderbook".to_string(), ..PostgresSinkConfig::default() });
+        let sink = PostgresSink::from_config(&config);
+        assert_eq!((sink.config.as_str(), sink.batch_size), ("host=db user=orderbook", DEFAULT_BATCH_SIZE));
+    }
+}
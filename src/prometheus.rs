@@ -0,0 +1,202 @@
+//! Exposition of the process-wide [metrics](crate::metrics) in the Prometheus text format,
+//! served over HTTP at `/metrics` with the `prometheus` feature. Metric names are prefixed with
+//! `orderbook_`, histograms are exposed as summaries of a few quantiles.
+
+use std::fmt::Write;
+
+use crate::metrics::{
+    LabeledHistogram, QueueDepth, ALLOCATOR_MEMORY, BEST_ASKS, BEST_ASK_SHARES, BEST_BIDS, BEST_BID_SHARES,
+    BUS_EVENTS, BUS_LAGGED, CROSS_CHECK_DISCREPANCIES, LATENCY_BUDGET, MULTIPLEX_DROPPED, MULTIPLEX_LAGS,
+    QUEUE_DEPTHS, REJECTED_MESSAGES, REJECTED_UPDATES, REORDERED_SNAPSHOTS, SHADOW_DIVERGENCES, SHADOW_LAGS,
+    SINK_DROPPED, SINK_ERRORS, SINK_LAGS, SPREADS_BPS, SUPPRESSED_DUPLICATES, VENUE_BEST_ASKS, VENUE_BEST_BIDS,
+};
+
+
+/// Prefix of the names of the metrics exposed.
+const METRIC_PREFIX: &str = "orderbook_";
+/// Quantiles of the histograms exposed.
+const QUANTILES: [f64; 3] = [0.5, 0.9, 0.99];
+/// Path of the HTTP endpoint of the metrics.
+#[cfg(feature = "prometheus")]
+const METRICS_PATH: &str = "/metrics";
+/// Content type of the Prometheus text format.
+#[cfg(feature = "prometheus")]
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+
+/// Render the current value of every metric in the Prometheus text format.
+///
+/// # Returns
+///
+/// A [String](String) with a `TYPE` comment and the samples of each metric with values.
+pub fn render() -> String {
+    let mut output = String::new();
+    for (counter, label) in [
+        (&SUPPRESSED_DUPLICATES, "exchange"),
+        (&REJECTED_MESSAGES, "exchange"),
+        (&REJECTED_UPDATES, "exchange"),
+        (&REORDERED_SNAPSHOTS, "exchange"),
+        (&SINK_ERRORS, "sink"),
+        (&SINK_DROPPED, "sink"),
+        (&MULTIPLEX_DROPPED, "symbol"),
+        (&BUS_EVENTS, "topic"),
+        (&BUS_LAGGED, "topic"),
+        (&CROSS_CHECK_DISCREPANCIES, "side"),
+        (&SHADOW_DIVERGENCES, "kind"),
+    ] {
+        let samples = counter.values().into_iter().map(|(value, count)| (vec![(label, value.to_string())], count as f64));
+        write_metric(&mut output, counter.name(), "counter", samples.collect());
+    }
+    for (gauge, label) in [
+        (&SINK_LAGS, "sink"),
+        (&MULTIPLEX_LAGS, "symbol"),
+        (&BEST_BID_SHARES, "exchange"),
+        (&BEST_ASK_SHARES, "exchange"),
+        (&ALLOCATOR_MEMORY, "statistic"),
+    ] {
+        let samples = gauge.values().into_iter().map(|(value, current)| (vec![(label, value.to_string())], current as f64));
+        write_metric(&mut output, gauge.name(), "gauge", samples.collect());
+    }
+    for gauge in [&BEST_BIDS, &BEST_ASKS, &SPREADS_BPS, &VENUE_BEST_BIDS, &VENUE_BEST_ASKS] {
+        let samples = gauge.values().into_iter().map(|(symbol, exchange, value)| {
+            let labels = std::iter::once(("symbol", symbol)).chain(exchange.map(|exchange| ("exchange", exchange)));
+            (labels.collect(), value)
+        });
+        write_metric(&mut output, gauge.name(), "gauge", samples.collect());
+    }
+    let queues = QUEUE_DEPTHS.values();
+    let queue_samples = |value: fn(&QueueDepth) -> usize| queues.iter()
+        .map(|(queue, label, depth)| (vec![("queue", queue.to_string()), ("label", label.to_string())], value(depth) as f64))
+        .collect();
+    write_metric(&mut output, QUEUE_DEPTHS.name(), "gauge", queue_samples(|depth| depth.depth));
+    write_metric(&mut output, &format!("{}_high_watermark", QUEUE_DEPTHS.name()), "gauge", queue_samples(|depth| depth.high_watermark));
+    write_metric(&mut output, &format!("{}_capacity", QUEUE_DEPTHS.name()), "gauge", queue_samples(|depth| depth.capacity));
+    write_histogram(&mut output, &LATENCY_BUDGET, "stage");
+    write_histogram(&mut output, &SHADOW_LAGS, "instance");
+    output
+}
+
+/// Internal function writing the samples of a metric, nothing if it has none.
+fn write_metric(output: &mut String, name: &str, kind: &str, samples: Vec<(Vec<(&str, String)>, f64)>) {
+    if samples.is_empty() {
+        return;
+    }
+    let _ = writeln!(output, "# TYPE {}{} {}", METRIC_PREFIX, name, kind);
+    for (labels, value) in samples {
+        write_sample(output, name, &labels, value);
+    }
+}
+
+/// Internal function writing a histogram as a summary: the quantiles and count of each label.
+fn write_histogram(output: &mut String, histogram: &LabeledHistogram, label: &str) {
+    let labels = histogram.labels();
+    if labels.is_empty() {
+        return;
+    }
+    let _ = writeln!(output, "# TYPE {}{} summary", METRIC_PREFIX, histogram.name());
+    for value in labels {
+        for quantile in QUANTILES {
+            let bound = histogram.quantile(&value, quantile).map(|bound| match bound {
+                u64::MAX => f64::INFINITY,
+                bound => bound as f64,
+            });
+            let labels = [(label, value.to_string()), ("quantile", quantile.to_string())];
+            write_sample(output, histogram.name(), &labels, bound.unwrap_or(f64::NAN));
+        }
+        let count_name = format!("{}_count", histogram.name());
+        write_sample(output, &count_name, &[(label, value.to_string())], histogram.count(&value) as f64);
+    }
+}
+
+/// Internal function writing a sample, with its labels escaped.
+fn write_sample(output: &mut String, name: &str, labels: &[(&str, String)], value: f64) {
+    let labels: Vec<String> = labels.iter()
+        .map(|(label, value)| format!("{}=\"{}\"", label, escape_label_value(value)))
+        .collect();
+    let _ = writeln!(output, "{}{}{{{}}} {}", METRIC_PREFIX, name, labels.join(","), format_value(value));
+}
+
+/// Escape the backslashes, double quotes and line feeds of a label value.
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Format a sample value, with the Prometheus spelling of the infinities and `NaN`.
+fn format_value(value: f64) -> String {
+    match value {
+        value if value.is_nan() => String::from("NaN"),
+        f64::INFINITY => String::from("+Inf"),
+        f64::NEG_INFINITY => String::from("-Inf"),
+        value => value.to_string(),
+    }
+}
+
+/// Serve the metrics over HTTP, at `/metrics` on a local port, in a background task.
+///
+/// # Arguments
+///
+/// * `port` - The TCP port of the endpoint.
+///
+/// # Returns
+///
+/// An empty [Result](Result), an error if the port cannot be bound.
+#[cfg(feature = "prometheus")]
+pub fn spawn_exporter(port: u16) -> Result<(), hyper::Error> {
+    use hyper::{service::{make_service_fn, service_fn}, Server};
+    use log::{error, info};
+    use std::{convert::Infallible, net};
+
+    let address = net::SocketAddr::new(net::IpAddr::V6(net::Ipv6Addr::LOCALHOST), port);
+    let server = Server::try_bind(&address)?
+        .serve(make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(respond)) }));
+    info!("Prometheus metrics served on http://{}{}", address, METRICS_PATH);
+    tokio::spawn(async move {
+        if let Err(error) = server.await {
+            error!("Prometheus exporter stopped: {}", error);
+        }
+    });
+    Ok(())
+}
+
+/// Internal function answering the requests of the exporter.
+#[cfg(feature = "prometheus")]
+async fn respond(request: hyper::Request<hyper::Body>) -> Result<hyper::Response<hyper::Body>, std::convert::Infallible> {
+    use hyper::{header, Body, Method, Response, StatusCode};
+
+    let response = match (request.method(), request.uri().path()) {
+        (&Method::GET, METRICS_PATH) => Response::builder()
+            .header(header::CONTENT_TYPE, CONTENT_TYPE)
+            .body(Body::from(render())),
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty()),
+    };
+    Ok(response.expect("valid response"))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        // a symbol of its own, the metrics being process-wide
+        BEST_BIDS.set("PROMETHEUS-TEST", 99.5);
+        BEST_ASKS.set("PROMETHEUS-TEST", f64::NAN);
+        VENUE_BEST_BIDS.set_exchanges("PROMETHEUS-TEST", vec![("test\"1".to_string(), 99.5)]);
+        let output = render();
+        assert!(output.contains("# TYPE orderbook_best_bid gauge\n"));
+        assert!(output.contains("orderbook_best_bid{symbol=\"PROMETHEUS-TEST\"} 99.5\n"));
+        assert!(output.contains("orderbook_best_ask{symbol=\"PROMETHEUS-TEST\"} NaN\n"));
+        assert!(output.contains("orderbook_venue_best_bid{symbol=\"PROMETHEUS-TEST\",exchange=\"test\\\"1\"} 99.5\n"));
+    }
+
+    #[test]
+    fn test_format_value() {
+        assert_eq!(format_value(1.5), "1.5");
+        assert_eq!(format_value(3.0), "3");
+        assert_eq!(format_value(f64::INFINITY), "+Inf");
+        assert_eq!(format_value(f64::NAN), "NaN");
+    }
+}
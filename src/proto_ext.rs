@@ -0,0 +1,177 @@
+//! Extensions of the generated Protobuf types. Time fields of the protocol use the well-known
+//! [Timestamp](Timestamp) type rather than integer epoch fields, and are converted from and to
+//! [SystemTime](SystemTime) here. Timestamps also format and parse as RFC 3339 strings.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub use prost_types::Timestamp;
+
+use crate::orderbook::PingResponse;
+
+
+/// Conversions of a [Timestamp](Timestamp).
+pub trait TimestampExt: Sized {
+    /// The current time.
+    fn now() -> Self;
+
+    /// Create a timestamp from milliseconds since the Unix epoch, e.g. of an integer epoch field.
+    ///
+    /// # Arguments
+    ///
+    /// * `millis` - The milliseconds since the Unix epoch.
+    fn from_millis(millis: u64) -> Self;
+
+    /// The timestamp as a [SystemTime](SystemTime).
+    ///
+    /// # Returns
+    ///
+    /// An optional [SystemTime](SystemTime), [None](None) if the timestamp is out of its range.
+    fn to_system_time(&self) -> Option<SystemTime>;
+
+    /// The timestamp as milliseconds since the Unix epoch.
+    ///
+    /// # Returns
+    ///
+    /// The milliseconds, [None](None) if the timestamp is before the epoch or out of range.
+    fn to_millis(&self) -> Option<u64>;
+
+    /// Time elapsed since the timestamp.
+    ///
+    /// # Returns
+    ///
+    /// A [Duration](Duration), zero if the timestamp is in the future or out of range.
+    fn elapsed(&self) -> Duration;
+}
+
+impl TimestampExt for Timestamp {
+    fn now() -> Self {
+        SystemTime::now().into()
+    }
+
+    fn from_millis(millis: u64) -> Self {
+        (UNIX_EPOCH + Duration::from_millis(millis)).into()
+    }
+
+    fn to_system_time(&self) -> Option<SystemTime> {
+        SystemTime::try_from(self.clone()).ok()
+    }
+
+    fn to_millis(&self) -> Option<u64> {
+        let since_epoch = self.to_system_time()?.duration_since(UNIX_EPOCH).ok()?;
+        u64::try_from(since_epoch.as_millis()).ok()
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.to_system_time()
+            .and_then(|time| SystemTime::now().duration_since(time).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Latency figures of a [PingResponse](PingResponse), as measured by the client, following NTP:
+/// the time spent by the server between receiving the request and sending the response is
+/// excluded from the network latency, and the clocks of the client and server are assumed to
+/// drift little during the round trip.
+pub trait PingResponseExt {
+    /// Time spent on the network by the request and the response.
+    ///
+    /// # Arguments
+    ///
+    /// * `client_received_at` - When the client received the response.
+    ///
+    /// # Returns
+    ///
+    /// A [Duration](Duration), [None](None) if any timestamp is missing or out of range.
+    fn network_latency(&self, client_received_at: SystemTime) -> Option<Duration>;
+
+    /// Time spent by the server between receiving the request and sending the response.
+    ///
+    /// # Returns
+    ///
+    /// A [Duration](Duration), [None](None) if any timestamp is missing or out of range.
+    fn server_processing(&self) -> Option<Duration>;
+
+    /// Offset of the clock of the server from the clock of the client, to be subtracted from
+    /// the timestamps of the server, e.g. of the summaries, to compare them with the client clock.
+    ///
+    /// # Arguments
+    ///
+    /// * `client_received_at` - When the client received the response.
+    ///
+    /// # Returns
+    ///
+    /// The offset in microseconds, positive if the server clock is ahead, [None](None) if any
+    /// timestamp is missing or out of range.
+    fn clock_offset_us(&self, client_received_at: SystemTime) -> Option<i64>;
+}
+
+/// Internal function converting an optional timestamp to nanoseconds since the Unix epoch.
+fn epoch_nanos(timestamp: Option<&Timestamp>) -> Option<i128> {
+    let time = SystemTime::try_from(timestamp?.clone()).ok()?;
+    Some(time.duration_since(UNIX_EPOCH).ok()?.as_nanos() as i128)
+}
+
+/// Internal function converting a non-negative number of nanoseconds to a [Duration](Duration).
+fn nanos_duration(nanos: i128) -> Option<Duration> {
+    u64::try_from(nanos).ok().map(Duration::from_nanos)
+}
+
+impl PingResponseExt for PingResponse {
+    fn network_latency(&self, client_received_at: SystemTime) -> Option<Duration> {
+        let client_sent_at = epoch_nanos(self.client_sent_at.as_ref())?;
+        let client_received_at = epoch_nanos(Some(&client_received_at.into()))?;
+        let round_trip = client_received_at - client_sent_at;
+        nanos_duration(round_trip - self.server_processing()?.as_nanos() as i128)
+    }
+
+    fn server_processing(&self) -> Option<Duration> {
+        let received_at = epoch_nanos(self.server_received_at.as_ref())?;
+        let sent_at = epoch_nanos(self.server_sent_at.as_ref())?;
+        nanos_duration(sent_at - received_at)
+    }
+
+    fn clock_offset_us(&self, client_received_at: SystemTime) -> Option<i64> {
+        let client_sent_at = epoch_nanos(self.client_sent_at.as_ref())?;
+        let server_received_at = epoch_nanos(self.server_received_at.as_ref())?;
+        let server_sent_at = epoch_nanos(self.server_sent_at.as_ref())?;
+        let client_received_at = epoch_nanos(Some(&client_received_at.into()))?;
+        let offset_nanos = ((server_received_at - client_sent_at) + (server_sent_at - client_received_at)) / 2;
+        i64::try_from(offset_nanos / 1000).ok()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        let timestamp = Timestamp::from_millis(1_686_727_555_138);
+        assert_eq!(timestamp, Timestamp { seconds: 1_686_727_555, nanos: 138_000_000 });
+        assert_eq!(timestamp.to_millis(), Some(1_686_727_555_138));
+        assert_eq!(timestamp.to_system_time(), Some(UNIX_EPOCH + Duration::from_millis(1_686_727_555_138)));
+        assert_eq!(timestamp.to_string(), "2023-06-14T07:25:55.138Z");
+        assert_eq!(Timestamp { seconds: -1, nanos: 0 }.to_millis(), None);
+        assert!(timestamp.elapsed() > Duration::ZERO);
+        assert_eq!(Timestamp::from(SystemTime::now() + Duration::from_secs(3600)).elapsed(), Duration::ZERO);
+        assert!(Timestamp::now().elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_ping_latency() {
+        // server clock 500ms ahead, 10ms each way on the network, 2ms of processing
+        let response = PingResponse {
+            client_sent_at: Some(Timestamp::from_millis(1_000_000)),
+            server_received_at: Some(Timestamp::from_millis(1_000_510)),
+            server_sent_at: Some(Timestamp::from_millis(1_000_512)),
+        };
+        let client_received_at = UNIX_EPOCH + Duration::from_millis(1_000_022);
+        assert_eq!(response.network_latency(client_received_at), Some(Duration::from_millis(20)));
+        assert_eq!(response.server_processing(), Some(Duration::from_millis(2)));
+        assert_eq!(response.clock_offset_us(client_received_at), Some(500_000));
+        let unanswered = PingResponse { server_sent_at: None, ..response };
+        assert_eq!(unanswered.server_processing(), None);
+        assert_eq!(unanswered.clock_offset_us(client_received_at), None);
+    }
+}
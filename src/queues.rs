@@ -0,0 +1,66 @@
+//! Capacities of the internal queues, read from a JSON file so that they can be tuned
+//! without rebuilding. The depth of each queue is sampled in [QUEUE_DEPTHS](crate::metrics::QUEUE_DEPTHS).
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{DocumentedConfig, Validate, Validator};
+
+
+/// Default capacity of the queue of data from each exchange adapter.
+const DEFAULT_ADAPTER_DATA_CAPACITY: usize = 16;
+/// Default capacity of the queue of commands to each exchange adapter.
+const DEFAULT_ADAPTER_COMMAND_CAPACITY: usize = 1;
+/// Default capacity of the queue of responses to each streaming client.
+const DEFAULT_CLIENT_RESPONSE_CAPACITY: usize = 128;
+
+
+/// Capacities of the internal queues. Missing values take their defaults.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct QueueCapacities {
+    /// Data from each exchange adapter.
+    pub adapter_data: usize,
+    /// Commands to each exchange adapter.
+    pub adapter_command: usize,
+    /// Responses to each streaming client.
+    pub client_response: usize,
+}
+
+impl Default for QueueCapacities {
+    fn default() -> Self {
+        Self {
+            adapter_data: DEFAULT_ADAPTER_DATA_CAPACITY,
+            adapter_command: DEFAULT_ADAPTER_COMMAND_CAPACITY,
+            client_response: DEFAULT_CLIENT_RESPONSE_CAPACITY,
+        }
+    }
+}
+
+impl Validate for QueueCapacities {
+    fn validate(&self, validator: &mut Validator) {
+        validator.positive("adapter_data", self.adapter_data as u64);
+        validator.positive("adapter_command", self.adapter_command as u64);
+        validator.positive("client_response", self.client_response as u64);
+    }
+}
+
+impl DocumentedConfig for QueueCapacities {
+    const DESCRIPTION: &'static str = "Capacities of the internal queues.";
+    const FIELDS: &'static [(&'static str, &'static str)] = &[
+        ("adapter_data", "Data from each exchange adapter."),
+        ("adapter_command", "Commands to each exchange adapter."),
+        ("client_response", "Responses to each streaming client."),
+    ];
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_config() {
+        let capacities: QueueCapacities = serde_json::from_str(r#"{"client_response": 512}"#).unwrap();
+        assert_eq!(capacities, QueueCapacities { client_response: 512, ..QueueCapacities::default() });
+    }
+}
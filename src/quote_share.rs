@@ -0,0 +1,183 @@
+//! Share of time, over a rolling window, during which each exchange provides the consolidated
+//! best bid and best ask, tracked from the summaries of a feed and published in
+//! [BEST_BID_SHARES](BEST_BID_SHARES) and [BEST_ASK_SHARES](BEST_ASK_SHARES).
+
+use log::info;
+use std::collections::{BTreeMap, VecDeque};
+use tokio::sync::watch;
+use tokio::time::{Duration, Instant};
+
+use crate::clock::SharedClock;
+use crate::feed::{FeedReceiver, SummaryMetrics};
+use crate::metrics::{BEST_ASK_SHARES, BEST_BID_SHARES};
+
+
+/// Receiver of the latest [quote shares](QuoteShares), if any summary was produced.
+pub type QuoteShareReceiver = watch::Receiver<Option<QuoteShares>>;
+
+/// Share of time each exchange provided the best bid and best ask.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct QuoteShares {
+    /// Canonical symbol.
+    pub symbol: String,
+    /// Time covered, up to the window.
+    pub covered: Duration,
+    /// Share of time, from 0 to 1, during which each exchange provided the best bid, by exchange.
+    pub best_bid: BTreeMap<String, f64>,
+    /// Share of time, from 0 to 1, during which each exchange provided the best ask, by exchange.
+    pub best_ask: BTreeMap<String, f64>,
+}
+
+/// A period during which the same exchanges provided the best bid and best ask.
+struct BestQuotes {
+    /// Start of the period.
+    start: Instant,
+    /// Exchange providing the best bid, empty if none.
+    bid_exchange: String,
+    /// Exchange providing the best ask, empty if none.
+    ask_exchange: String,
+}
+
+/// Tracks the exchanges providing the best bid and best ask over a rolling window.
+pub struct QuoteShareTracker {
+    /// Length of the window.
+    window: Duration,
+    /// Periods overlapping the window, oldest first.
+    periods: VecDeque<BestQuotes>,
+}
+
+impl QuoteShareTracker {
+    /// Create a new [QuoteShareTracker](QuoteShareTracker) object.
+    ///
+    /// # Arguments
+    ///
+    /// * `window` - The length of the rolling window.
+    pub fn new(window: Duration) -> Self {
+        Self { window, periods: VecDeque::new() }
+    }
+
+    /// Record the exchanges providing the best bid and best ask from now on.
+    ///
+    /// # Arguments
+    ///
+    /// * `bid_exchange` - The exchange providing the best bid, empty if none.
+    ///
+    /// * `ask_exchange` - The exchange providing the best ask, empty if none.
+    ///
+    /// * `now` - The current time.
+    pub fn update(&mut self, bid_exchange: &str, ask_exchange: &str, now: Instant) {
+        if let Some(last) = self.periods.back() {
+            if last.bid_exchange == bid_exchange && last.ask_exchange == ask_exchange {
+                return;
+            }
+        }
+        self.periods.push_back(BestQuotes { start: now, bid_exchange: bid_exchange.to_string(), ask_exchange: ask_exchange.to_string() });
+        let window_start = now.checked_sub(self.window).unwrap_or(now);
+        while self.periods.len() > 1 && self.periods[1].start <= window_start {
+            self.periods.pop_front();
+        }
+    }
+
+    /// The share of time each exchange provided the best bid and best ask over the window.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time.
+    ///
+    /// # Returns
+    ///
+    /// The time covered, and the shares of the best bid and best ask by exchange.
+    pub fn shares(&self, now: Instant) -> (Duration, BTreeMap<String, f64>, BTreeMap<String, f64>) {
+        let window_start = now.checked_sub(self.window).unwrap_or(now);
+        let mut bid_times: BTreeMap<String, Duration> = BTreeMap::new();
+        let mut ask_times: BTreeMap<String, Duration> = BTreeMap::new();
+        let mut covered = Duration::ZERO;
+        for (i, period) in self.periods.iter().enumerate() {
+            let end = self.periods.get(i + 1).map(|next| next.start).unwrap_or(now);
+            let duration = end.saturating_duration_since(period.start.max(window_start));
+            covered += duration;
+            if !period.bid_exchange.is_empty() {
+                *bid_times.entry(period.bid_exchange.clone()).or_default() += duration;
+            }
+            if !period.ask_exchange.is_empty() {
+                *ask_times.entry(period.ask_exchange.clone()).or_default() += duration;
+            }
+        }
+        let share = |times: BTreeMap<String, Duration>| times.into_iter()
+            .map(|(exchange, time)| {
+                let share = if covered.is_zero() { 1.0 } else { time.as_secs_f64() / covered.as_secs_f64() };
+                (exchange, share)
+            })
+            .collect();
+        (covered, share(bid_times), share(ask_times))
+    }
+}
+
+/// Spawn a task tracking the exchanges providing the best bid and best ask of the summaries
+/// of a feed, and publishing their shares as metrics, in basis points.
+///
+/// # Arguments
+///
+/// * `feed` - A [FeedReceiver](FeedReceiver).
+///
+/// * `window` - The length of the rolling window.
+///
+/// * `clock` - Time source for the shares.
+///
+/// # Returns
+///
+/// A [QuoteShareReceiver](QuoteShareReceiver), updated after each summary.
+pub fn spawn_quote_share(mut feed: FeedReceiver, window: Duration, clock: SharedClock) -> QuoteShareReceiver {
+    let (sender, receiver) = watch::channel(None);
+    tokio::spawn(async move {
+        let mut tracker = QuoteShareTracker::new(window);
+        while feed.changed().await.is_ok() {
+            let maybe_summary = feed.borrow_and_update().clone();
+            if let Some(summary) = maybe_summary {
+                let metrics = SummaryMetrics::from(&summary);
+                let now = clock.now();
+                tracker.update(&metrics.best_bid_exchange, &metrics.best_ask_exchange, now);
+                let (covered, best_bid, best_ask) = tracker.shares(now);
+                for (gauge, shares) in [(&BEST_BID_SHARES, &best_bid), (&BEST_ASK_SHARES, &best_ask)] {
+                    for (label, _) in gauge.values() {
+                        if !shares.contains_key(&label) {
+                            gauge.set(&label, 0);
+                        }
+                    }
+                    for (exchange, share) in shares {
+                        gauge.set(exchange, (share * 10_000.0).round() as u64);
+                    }
+                }
+                sender.send_replace(Some(QuoteShares { symbol: summary.symbol, covered, best_bid, best_ask }));
+            }
+        }
+        info!("Feed closed, quote share tracking stopped");
+    });
+    receiver
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rolling_shares() {
+        let start = Instant::now();
+        let mut tracker = QuoteShareTracker::new(Duration::from_secs(10));
+        tracker.update("binance", "bitstamp", start);
+        tracker.update("binance", "bitstamp", start + Duration::from_secs(2));
+        tracker.update("bitstamp", "bitstamp", start + Duration::from_secs(4));
+        let (covered, best_bid, best_ask) = tracker.shares(start + Duration::from_secs(5));
+        assert_eq!(covered, Duration::from_secs(5));
+        assert_eq!(best_bid, BTreeMap::from([("binance".to_string(), 0.8), ("bitstamp".to_string(), 0.2)]));
+        assert_eq!(best_ask, BTreeMap::from([("bitstamp".to_string(), 1.0)]));
+        // binance leaves the window
+        tracker.update("bitstamp", "", start + Duration::from_secs(19));
+        let (covered, best_bid, best_ask) = tracker.shares(start + Duration::from_secs(19));
+        assert_eq!(covered, Duration::from_secs(10));
+        assert_eq!(best_bid, BTreeMap::from([("bitstamp".to_string(), 1.0)]));
+        assert_eq!(best_ask, BTreeMap::from([("bitstamp".to_string(), 1.0)]));
+        assert_eq!(tracker.periods.len(), 2);
+    }
+}
@@ -0,0 +1,228 @@
+//! Budget of the connection attempts of the [exchange adapters](crate::exchange::ExchangeAdapter),
+//! so that a flapping network cannot cause hundreds of reconnections per minute, which would get
+//! the IP address banned by the exchanges. Attempts take a token from a bucket of each exchange
+//! and from a bucket shared by all of them; when either is empty, the adapter cools down for a
+//! long time and notifies it as [ReconnectsThrottled](crate::status::ExchangeStatus::ReconnectsThrottled).
+
+use std::sync::{Arc, Mutex};
+use tokio::time::{Duration, Instant};
+
+
+/// Default connection attempts of each exchange in a burst.
+const DEFAULT_VENUE_CAPACITY: u32 = 10;
+/// Default interval between two connection attempts of each exchange added to its budget.
+const DEFAULT_VENUE_REFILL_MS: u64 = 6_000;
+/// Default connection attempts of all the exchanges in a burst.
+const DEFAULT_GLOBAL_CAPACITY: u32 = 30;
+/// Default interval between two connection attempts added to the budget of all the exchanges.
+const DEFAULT_GLOBAL_REFILL_MS: u64 = 2_000;
+/// Default time without connection attempts once the budget is exhausted.
+const DEFAULT_COOL_DOWN_MS: u64 = 300_000;
+
+
+/// Size and refill rate of a [TokenBucket](TokenBucket).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BucketLimits {
+    /// Maximum number of tokens, i.e. of attempts in a burst.
+    pub capacity: u32,
+    /// Interval between two tokens added to the bucket.
+    pub refill_interval: Duration,
+}
+
+/// Token bucket, initially full.
+#[derive(Debug)]
+pub struct TokenBucket {
+    /// Size and refill rate.
+    limits: BucketLimits,
+    /// Tokens available.
+    tokens: u32,
+    /// Instant when the last token was added, [None](None) before the first use.
+    last_refill: Option<Instant>,
+}
+
+impl TokenBucket {
+    /// Create a new [TokenBucket](TokenBucket) object, full.
+    ///
+    /// # Arguments
+    ///
+    /// * `limits` - The [BucketLimits](BucketLimits).
+    pub fn new(limits: BucketLimits) -> Self {
+        Self { limits, tokens: limits.capacity, last_refill: None }
+    }
+
+    /// Tokens available, after adding the tokens due since the last refill.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current instant.
+    pub fn available(&mut self, now: Instant) -> u32 {
+        match self.last_refill {
+            None => self.last_refill = Some(now),
+            Some(last_refill) if self.limits.refill_interval.is_zero() => {
+                self.tokens = self.limits.capacity;
+                self.last_refill = Some(last_refill.max(now));
+            },
+            Some(last_refill) => {
+                let elapsed = now.saturating_duration_since(last_refill);
+                let due = (elapsed.as_nanos() / self.limits.refill_interval.as_nanos()) as u32;
+                if self.tokens.saturating_add(due) >= self.limits.capacity {
+                    self.tokens = self.limits.capacity;
+                    self.last_refill = Some(now);
+                } else {
+                    self.tokens += due;
+                    self.last_refill = Some(last_refill + self.limits.refill_interval * due);
+                }
+            },
+        }
+        self.tokens
+    }
+
+    /// Take a token, if available.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current instant.
+    ///
+    /// # Returns
+    ///
+    /// Whether a token was taken.
+    pub fn try_take(&mut self, now: Instant) -> bool {
+        if self.available(now) == 0 {
+            return false;
+        }
+        self.tokens -= 1;
+        true
+    }
+}
+
+/// Budget of the connection attempts of the exchange adapters. Clones share the budget of
+/// all the exchanges, each adapter gets its own budget with [guard](ReconnectBudget::guard).
+#[derive(Clone, Debug)]
+pub struct ReconnectBudget {
+    /// Size and refill rate of the budget of each exchange.
+    venue_limits: BucketLimits,
+    /// Budget of all the exchanges.
+    global: Arc<Mutex<TokenBucket>>,
+    /// Time without connection attempts once the budget is exhausted.
+    cool_down: Duration,
+}
+
+impl ReconnectBudget {
+    /// Create a new [ReconnectBudget](ReconnectBudget) object.
+    ///
+    /// # Arguments
+    ///
+    /// * `venue_limits` - [BucketLimits](BucketLimits) of each exchange.
+    ///
+    /// * `global_limits` - [BucketLimits](BucketLimits) of all the exchanges.
+    ///
+    /// * `cool_down` - Time without connection attempts once the budget is exhausted.
+    pub fn new(venue_limits: BucketLimits, global_limits: BucketLimits, cool_down: Duration) -> Self {
+        Self { venue_limits, global: Arc::new(Mutex::new(TokenBucket::new(global_limits))), cool_down }
+    }
+
+    /// Create the guard of the connection attempts of an exchange, with its own budget and
+    /// sharing the budget of all the exchanges.
+    ///
+    /// # Returns
+    ///
+    /// A [ReconnectGuard](ReconnectGuard) object.
+    pub fn guard(&self) -> ReconnectGuard {
+        ReconnectGuard {
+            budget: self.clone(),
+            venue: Arc::new(Mutex::new(TokenBucket::new(self.venue_limits))),
+        }
+    }
+}
+
+impl Default for ReconnectBudget {
+    fn default() -> Self {
+        Self::new(
+            BucketLimits { capacity: DEFAULT_VENUE_CAPACITY, refill_interval: Duration::from_millis(DEFAULT_VENUE_REFILL_MS) },
+            BucketLimits { capacity: DEFAULT_GLOBAL_CAPACITY, refill_interval: Duration::from_millis(DEFAULT_GLOBAL_REFILL_MS) },
+            Duration::from_millis(DEFAULT_COOL_DOWN_MS),
+        )
+    }
+}
+
+/// Guard of the connection attempts of an exchange. Clones share the budget.
+#[derive(Clone, Debug)]
+pub struct ReconnectGuard {
+    /// Budget of all the exchanges.
+    budget: ReconnectBudget,
+    /// Budget of the exchange.
+    venue: Arc<Mutex<TokenBucket>>,
+}
+
+impl ReconnectGuard {
+    /// Take a connection attempt from the budgets of the exchange and of all the exchanges,
+    /// only if both have one left.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current instant.
+    ///
+    /// # Returns
+    ///
+    /// Whether the connection attempt is allowed.
+    pub fn try_acquire(&self, now: Instant) -> bool {
+        let mut venue = self.venue.lock().unwrap();
+        if venue.available(now) == 0 || !self.budget.global.lock().unwrap().try_take(now) {
+            return false;
+        }
+        venue.try_take(now)
+    }
+
+    /// Time without connection attempts once the budget is exhausted.
+    pub fn cool_down(&self) -> Duration {
+        self.budget.cool_down
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(capacity: u32, refill_ms: u64) -> BucketLimits {
+        BucketLimits { capacity, refill_interval: Duration::from_millis(refill_ms) }
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(limits(2, 1000));
+        assert!(bucket.try_take(now));
+        assert!(bucket.try_take(now));
+        assert!(!bucket.try_take(now + Duration::from_millis(999)));
+        assert!(bucket.try_take(now + Duration::from_millis(1500)));
+        assert!(!bucket.try_take(now + Duration::from_millis(1999)));
+        assert!(bucket.try_take(now + Duration::from_millis(2000)));
+        assert_eq!(bucket.available(now + Duration::from_secs(60)), 2);
+    }
+
+    #[test]
+    fn test_venue_budget() {
+        let now = Instant::now();
+        let budget = ReconnectBudget::new(limits(2, 1000), limits(10, 1000), Duration::from_secs(60));
+        let binance = budget.guard();
+        let bitstamp = budget.guard();
+        assert!(binance.try_acquire(now) && binance.clone().try_acquire(now));
+        assert!(!binance.try_acquire(now));
+        assert!(bitstamp.try_acquire(now));
+        assert_eq!(binance.cool_down(), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_global_budget() {
+        let now = Instant::now();
+        let budget = ReconnectBudget::new(limits(2, 1000), limits(3, 1000), Duration::from_secs(60));
+        let binance = budget.guard();
+        let bitstamp = budget.guard();
+        assert!(binance.try_acquire(now) && binance.try_acquire(now));
+        assert!(bitstamp.try_acquire(now));
+        assert!(!bitstamp.try_acquire(now));
+        // a refused attempt does not consume the budget of the exchange
+        assert!(bitstamp.try_acquire(now + Duration::from_millis(1000)));
+    }
+}
@@ -0,0 +1,351 @@
+//! Conversion of the summaries recorded by the pipe sink (feature `pipe`), e.g. redirected
+//! to a file, between formats, so that recorded data is usable by non-Rust tooling:
+//! [compact JSON](crate::feed::CompactSummary) lines and length-prefixed Protobuf messages are
+//! read, and written back in either format or as CSV, one row per level. Parquet is not
+//! supported, and is rejected rather than written in another format.
+//! The summaries can be filtered by a [time range](TimeRange), on their creation time, only
+//! carried by the Protobuf recordings of a server built with the `latency-budget` feature.
+
+use prost::Message;
+use serde::Deserialize;
+use std::io::{self, BufRead, ErrorKind, Write};
+use std::time::SystemTime;
+
+use crate::feed::CompactSummary;
+use crate::numbers::{format_f64, NumberFormat};
+use crate::orderbook::{Level, Summary};
+use crate::proto_ext::TimestampExt;
+
+
+/// Header of the CSV files.
+const CSV_HEADER: &str = "symbol,spread,side,rank,exchange,price,amount";
+/// Maximum length of the varint prefixing a Protobuf message.
+const MAX_VARINT_LEN: usize = 10;
+
+
+/// Format of a recording.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordingFormat {
+    /// One [compact JSON summary](CompactSummary) per line.
+    Ndjson,
+    /// Protobuf messages, each prefixed by its length as a varint.
+    Protobuf,
+    /// One row per level, with a header, only written.
+    Csv,
+}
+
+impl RecordingFormat {
+    /// Parse the name of a format.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name: `ndjson`, `protobuf` or `csv`.
+    ///
+    /// # Returns
+    ///
+    /// The [RecordingFormat](RecordingFormat), or an error if the name is unknown or the format,
+    /// e.g. `parquet`, not supported.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "ndjson" => Ok(RecordingFormat::Ndjson),
+            "protobuf" => Ok(RecordingFormat::Protobuf),
+            "csv" => Ok(RecordingFormat::Csv),
+            "parquet" => Err("Parquet is not supported, use csv instead".to_string()),
+            _ => Err(format!("unknown recording format: {}", name)),
+        }
+    }
+}
+
+/// Range of creation times of the summaries kept by [convert](convert), unbounded by default.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TimeRange {
+    /// Summaries created before are dropped, if set.
+    pub from: Option<SystemTime>,
+    /// Summaries created at or after are dropped, if set.
+    pub to: Option<SystemTime>,
+}
+
+impl TimeRange {
+    /// Whether the range is unbounded, keeping every summary.
+    pub fn is_unbounded(&self) -> bool {
+        self.from.is_none() && self.to.is_none()
+    }
+
+    /// Whether a summary was created within the range.
+    ///
+    /// # Arguments
+    ///
+    /// * `summary` - The [Summary](Summary).
+    ///
+    /// # Returns
+    ///
+    /// Whether the summary is kept, or an error if the range is bounded and the summary does not
+    /// carry its creation time, in its [latency budget](crate::latency_budget).
+    pub fn contains(&self, summary: &Summary) -> io::Result<bool> {
+        if self.is_unbounded() {
+            return Ok(true);
+        }
+        let created_at = summary.latency_budget.as_ref()
+            .and_then(|latency_budget| latency_budget.created_at.as_ref())
+            .and_then(|created_at| created_at.to_system_time())
+            .ok_or_else(|| io::Error::new(
+                ErrorKind::InvalidData,
+                "summary without creation time, recorded without the latency-budget feature, cannot be filtered by time",
+            ))?;
+        Ok(self.from.map(|from| created_at >= from).unwrap_or(true) && self.to.map(|to| created_at < to).unwrap_or(true))
+    }
+}
+
+/// Number of summaries converted by [convert](convert).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ConversionStats {
+    /// Summaries read.
+    pub read: usize,
+    /// Summaries written, after filtering.
+    pub written: usize,
+}
+
+/// A [compact JSON summary](CompactSummary), as read back.
+#[derive(Deserialize)]
+struct RecordedSummary {
+    s: String,
+    sp: Option<f64>,
+    b: Vec<(String, Option<f64>, Option<f64>)>,
+    a: Vec<(String, Option<f64>, Option<f64>)>,
+}
+
+/// Decode a line of compact JSON.
+///
+/// # Arguments
+///
+/// * `line` - The line.
+///
+/// # Returns
+///
+/// The [Summary](Summary), missing numbers being `NaN`, or an error if the line is not valid.
+pub fn decode_ndjson(line: &str) -> io::Result<Summary> {
+    let recorded: RecordedSummary = serde_json::from_str(line)?;
+    let levels = |levels: Vec<(String, Option<f64>, Option<f64>)>| levels.into_iter()
+        .map(|(exchange, price, amount)| Level {
+            exchange,
+            price: price.unwrap_or(f64::NAN),
+            amount: amount.unwrap_or(f64::NAN),
+            order_count: None,
+            latency_class: 0,
+        })
+        .collect();
+    Ok(Summary {
+        spread: recorded.sp.unwrap_or(f64::NAN),
+        bids: levels(recorded.b),
+        asks: levels(recorded.a),
+        symbol: recorded.s,
+        ..Default::default()
+    })
+}
+
+/// Read the next summary of a recording.
+///
+/// # Arguments
+///
+/// * `reader` - The recording.
+///
+/// * `format` - The [RecordingFormat](RecordingFormat), [Ndjson](RecordingFormat::Ndjson) or
+///   [Protobuf](RecordingFormat::Protobuf).
+///
+/// # Returns
+///
+/// The next [Summary](Summary), [None](None) at the end of the recording, or an error if
+/// the recording is not valid.
+pub fn read_summary(reader: &mut impl BufRead, format: RecordingFormat) -> io::Result<Option<Summary>> {
+    match format {
+        RecordingFormat::Ndjson => {
+            let mut line = String::new();
+            loop {
+                line.clear();
+                if reader.read_line(&mut line)? == 0 {
+                    return Ok(None);
+                }
+                if !line.trim().is_empty() {
+                    return decode_ndjson(&line).map(Some);
+                }
+            }
+        },
+        RecordingFormat::Protobuf => {
+            let mut prefix = Vec::with_capacity(MAX_VARINT_LEN);
+            let mut byte = [0u8];
+            loop {
+                if reader.read(&mut byte)? == 0 {
+                    return match prefix.is_empty() {
+                        true => Ok(None),
+                        false => Err(ErrorKind::UnexpectedEof.into()),
+                    };
+                }
+                prefix.push(byte[0]);
+                if byte[0] & 0x80 == 0 {
+                    break;
+                }
+                if prefix.len() == MAX_VARINT_LEN {
+                    return Err(io::Error::new(ErrorKind::InvalidData, "invalid length prefix"));
+                }
+            }
+            let len = prost::decode_length_delimiter(prefix.as_slice())?;
+            let mut message = vec![0u8; len];
+            reader.read_exact(&mut message)?;
+            Ok(Some(Summary::decode(message.as_slice())?))
+        },
+        RecordingFormat::Csv => Err(io::Error::new(ErrorKind::Unsupported, "CSV recordings cannot be read")),
+    }
+}
+
+/// Write a summary to a recording.
+///
+/// # Arguments
+///
+/// * `writer` - The recording.
+///
+/// * `summary` - The [Summary](Summary).
+///
+/// * `format` - The [RecordingFormat](RecordingFormat).
+pub fn write_summary(writer: &mut impl Write, summary: &Summary, format: RecordingFormat) -> io::Result<()> {
+    match format {
+        RecordingFormat::Ndjson => {
+            serde_json::to_writer(&mut *writer, &CompactSummary::new(summary, &NumberFormat::default()))?;
+            writer.write_all(b"\n")
+        },
+        RecordingFormat::Protobuf => writer.write_all(&summary.encode_length_delimited_to_vec()),
+        RecordingFormat::Csv => {
+            let format = |value: f64| format_f64(value, None).unwrap_or_default();
+            for (side, levels) in [("bid", &summary.bids), ("ask", &summary.asks)] {
+                for (rank, level) in levels.iter().enumerate() {
+                    writeln!(
+                        writer, "{},{},{},{},{},{},{}",
+                        summary.symbol, format(summary.spread), side, rank + 1, level.exchange,
+                        format(level.price), format(level.amount),
+                    )?;
+                }
+            }
+            Ok(())
+        },
+    }
+}
+
+/// Convert a recording to another format.
+///
+/// # Arguments
+///
+/// * `reader` - The recording to convert.
+///
+/// * `input_format` - The [RecordingFormat](RecordingFormat) of the recording to convert.
+///
+/// * `writer` - The converted recording.
+///
+/// * `output_format` - The [RecordingFormat](RecordingFormat) of the converted recording.
+///
+/// * `symbol` - The canonical symbol whose summaries are kept, [None](None) to keep all of them.
+///
+/// * `time_range` - The [TimeRange](TimeRange) of the summaries kept.
+///
+/// # Returns
+///
+/// The [ConversionStats](ConversionStats), or an error if the recording is not valid, cannot
+/// be written, or cannot be filtered by time.
+pub fn convert(
+    reader: &mut impl BufRead,
+    input_format: RecordingFormat,
+    writer: &mut impl Write,
+    output_format: RecordingFormat,
+    symbol: Option<&str>,
+    time_range: &TimeRange,
+) -> io::Result<ConversionStats> {
+    let mut stats = ConversionStats::default();
+    if output_format == RecordingFormat::Csv {
+        writeln!(writer, "{}", CSV_HEADER)?;
+    }
+    while let Some(summary) = read_summary(reader, input_format)? {
+        stats.read += 1;
+        if symbol.map(|symbol| symbol == summary.symbol).unwrap_or(true) && time_range.contains(&summary)? {
+            write_summary(writer, &summary, output_format)?;
+            stats.written += 1;
+        }
+    }
+    writer.flush()?;
+    Ok(stats)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderbook::LatencyBudget;
+    use crate::proto_ext::Timestamp;
+
+    const RECORDING: &str = concat!(
+        "{\"s\":\"ETH-BTC\",\"sp\":0.00001,\"b\":[[\"binance\",0.0612,1.5]],\"a\":[[\"bitstamp\",0.06121,2]]}\n",
+        "{\"s\":\"BTC-USD\",\"sp\":null,\"b\":[],\"a\":[]}\n",
+        "{\"s\":\"ETH-BTC\",\"sp\":0.00002,\"b\":[[\"binance\",0.0612,1],[\"bitstamp\",0.0611,3]],\"a\":[]}\n",
+    );
+
+    #[test]
+    fn test_convert_round_trip() {
+        let mut protobuf = vec![];
+        let stats = convert(&mut RECORDING.as_bytes(), RecordingFormat::Ndjson, &mut protobuf, RecordingFormat::Protobuf, None, &TimeRange::default()).unwrap();
+        assert_eq!(stats, ConversionStats { read: 3, written: 3 });
+        let mut ndjson = vec![];
+        convert(&mut protobuf.as_slice(), RecordingFormat::Protobuf, &mut ndjson, RecordingFormat::Ndjson, None, &TimeRange::default()).unwrap();
+        assert_eq!(String::from_utf8(ndjson).unwrap(), RECORDING);
+    }
+
+    #[test]
+    fn test_convert_csv_filtered() {
+        let mut csv = vec![];
+        let stats = convert(&mut RECORDING.as_bytes(), RecordingFormat::Ndjson, &mut csv, RecordingFormat::Csv, Some("ETH-BTC"), &TimeRange::default()).unwrap();
+        assert_eq!(stats, ConversionStats { read: 3, written: 2 });
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "symbol,spread,side,rank,exchange,price,amount\n\
+             ETH-BTC,0.00001,bid,1,binance,0.0612,1.5\n\
+             ETH-BTC,0.00001,ask,1,bitstamp,0.06121,2\n\
+             ETH-BTC,0.00002,bid,1,binance,0.0612,1\n\
+             ETH-BTC,0.00002,bid,2,bitstamp,0.0611,3\n",
+        );
+    }
+
+    #[test]
+    fn test_convert_time_range() {
+        let mut protobuf = vec![];
+        for (symbol, created_at_ms) in [("ETH-BTC", 1_000), ("BTC-USD", 2_000), ("ETH-BTC", 3_000)] {
+            let summary = Summary {
+                symbol: symbol.to_string(),
+                latency_budget: Some(LatencyBudget { created_at: Some(Timestamp::from_millis(created_at_ms)), ..Default::default() }),
+                ..Default::default()
+            };
+            write_summary(&mut protobuf, &summary, RecordingFormat::Protobuf).unwrap();
+        }
+        let time_range = TimeRange {
+            from: Some(Timestamp::from_millis(2_000).to_system_time().unwrap()),
+            to: Some(Timestamp::from_millis(3_000).to_system_time().unwrap()),
+        };
+        let mut csv = vec![];
+        let stats = convert(&mut protobuf.as_slice(), RecordingFormat::Protobuf, &mut csv, RecordingFormat::Csv, None, &time_range).unwrap();
+        assert_eq!(stats, ConversionStats { read: 3, written: 1 });
+        let stats = convert(&mut protobuf.as_slice(), RecordingFormat::Protobuf, &mut vec![], RecordingFormat::Csv, Some("ETH-BTC"), &time_range).unwrap();
+        assert_eq!(stats, ConversionStats { read: 3, written: 0 });
+        // NDJSON recordings carry no creation time
+        let result = convert(&mut RECORDING.as_bytes(), RecordingFormat::Ndjson, &mut vec![], RecordingFormat::Csv, None, &time_range);
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_unsupported_format() {
+        assert_eq!(RecordingFormat::parse("csv"), Ok(RecordingFormat::Csv));
+        assert!(RecordingFormat::parse("parquet").unwrap_err().contains("not supported"));
+        assert!(RecordingFormat::parse("xml").is_err());
+    }
+
+    #[test]
+    fn test_truncated_protobuf() {
+        let bytes = Summary { symbol: "ETH-BTC".to_string(), ..Default::default() }.encode_length_delimited_to_vec();
+        let result = read_summary(&mut &bytes[..bytes.len() - 1], RecordingFormat::Protobuf);
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
+}
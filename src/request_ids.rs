@@ -0,0 +1,91 @@
+//! Identifiers of the requests sent on an exchange connection. Requests are templates in which
+//! [REQUEST_ID_PLACEHOLDER](REQUEST_ID_PLACEHOLDER) is replaced by an identifier generated per
+//! connection, and the responses of the exchange are correlated to the pending requests by it,
+//! so that several subscribe or unsubscribe requests can be issued on one connection.
+
+use std::collections::BTreeMap;
+
+
+/// Placeholder of the request identifier in the request templates.
+pub const REQUEST_ID_PLACEHOLDER: &str = "{request_id}";
+/// Identifier of the first request of each connection, by default.
+pub const DEFAULT_FIRST_REQUEST_ID: u64 = 1;
+
+
+/// Requests issued on a connection and not yet answered.
+#[derive(Debug)]
+pub struct RequestTracker {
+    /// Identifier of the next request issued.
+    next_id: u64,
+    /// Pending requests, by identifier.
+    pending: BTreeMap<u64, String>,
+}
+
+impl RequestTracker {
+    /// Create a new [RequestTracker](RequestTracker) object for a new connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `first_id` - Identifier of the first request.
+    pub fn new(first_id: u64) -> Self {
+        Self { next_id: first_id, pending: BTreeMap::new() }
+    }
+
+    /// Issue a request, assigning it the next identifier if the template has a placeholder.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - The request, with the [placeholder](REQUEST_ID_PLACEHOLDER) of its identifier, if any.
+    ///
+    /// # Returns
+    ///
+    /// The request to send. Requests without placeholder are sent as they are and not tracked.
+    pub fn issue(&mut self, template: &str) -> String {
+        if !template.contains(REQUEST_ID_PLACEHOLDER) {
+            return template.to_string();
+        }
+        let request = template.replace(REQUEST_ID_PLACEHOLDER, &self.next_id.to_string());
+        self.pending.insert(self.next_id, request.clone());
+        self.next_id += 1;
+        request
+    }
+
+    /// Correlate a response to its request, which is no longer pending.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The request identifier in the response.
+    ///
+    /// # Returns
+    ///
+    /// The request answered, [None](None) if no pending request has this identifier.
+    pub fn correlate(&mut self, id: u64) -> Option<String> {
+        self.pending.remove(&id)
+    }
+
+    /// Number of requests not yet answered.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_tracker() {
+        let mut tracker = RequestTracker::new(10);
+        assert_eq!(tracker.issue(r#"{"method":"SUBSCRIBE","params":["a"],"id":{request_id}}"#), r#"{"method":"SUBSCRIBE","params":["a"],"id":10}"#);
+        assert_eq!(tracker.issue(r#"{"method":"UNSUBSCRIBE","params":["b"],"id":{request_id}}"#), r#"{"method":"UNSUBSCRIBE","params":["b"],"id":11}"#);
+        assert_eq!(tracker.issue(r#"{"event":"subscribe"}"#), r#"{"event":"subscribe"}"#);
+        assert_eq!(tracker.pending(), 2);
+        assert_eq!(tracker.correlate(11), Some(r#"{"method":"UNSUBSCRIBE","params":["b"],"id":11}"#.to_string()));
+        assert_eq!(tracker.correlate(11), None);
+        assert_eq!(tracker.correlate(12), None);
+        assert_eq!(tracker.pending(), 1);
+        // identifiers restart with each connection
+        assert_eq!(RequestTracker::new(10).issue(r#"{"id":{request_id}}"#), r#"{"id":10}"#);
+    }
+}
@@ -0,0 +1,242 @@
+//! REST fallback of the [exchange adapters](crate::exchange::ExchangeAdapter): while the
+//! WebSocket service of an exchange cannot be reached, or if it has none, an adapter configured
+//! with [with_rest_fallback](crate::exchange::ExchangeAdapter::with_rest_fallback) polls depth
+//! snapshots from a REST endpoint. Polled snapshots are less frequent than streamed ones, so
+//! the exchange is notified [degraded](crate::status::ExchangeStatus::Degraded) rather than up.
+
+use hyper::{body, client::conn, header, Body, Method, Request, Uri};
+use log::error;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::time::Duration;
+
+use crate::exchange::{ExchangeProtocol, ExchangeProtocolReader};
+
+
+/// Default interval between two snapshots polled.
+const DEFAULT_POLL_INTERVAL_MS: u64 = 1000;
+/// Default time spent polling before trying the WebSocket service again.
+const DEFAULT_WEBSOCKET_RETRY_MS: u64 = 30_000;
+
+
+/// REST endpoint of an exchange serving depth snapshots.
+pub struct RestFallback<T: 'static + Send> {
+    /// URL of the depth snapshots, `http` or `https`.
+    url: String,
+    /// Exchange-specific snapshot parser function.
+    reader: ExchangeProtocolReader<T>,
+    /// Interval between two snapshots polled.
+    poll_interval: Duration,
+    /// Time spent polling before trying the WebSocket service again.
+    websocket_retry: Duration,
+}
+
+impl <T: 'static + Send> RestFallback<T> {
+    /// Create a new [RestFallback](RestFallback) object.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - URL of the depth snapshots.
+    ///
+    /// * `reader` - Exchange-specific parser function, delivering each snapshot as
+    ///   [Data](ExchangeProtocol::Data).
+    pub fn new(url: String, reader: ExchangeProtocolReader<T>) -> Self {
+        Self {
+            url,
+            reader,
+            poll_interval: Duration::from_millis(DEFAULT_POLL_INTERVAL_MS),
+            websocket_retry: Duration::from_millis(DEFAULT_WEBSOCKET_RETRY_MS),
+        }
+    }
+
+    /// Set the interval between two snapshots polled.
+    ///
+    /// # Arguments
+    ///
+    /// * `poll_interval` - The interval.
+    ///
+    /// # Returns
+    ///
+    /// The modified [RestFallback](RestFallback).
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Set the time spent polling before trying the WebSocket service again.
+    ///
+    /// # Arguments
+    ///
+    /// * `websocket_retry` - The time.
+    ///
+    /// # Returns
+    ///
+    /// The modified [RestFallback](RestFallback).
+    pub fn with_websocket_retry(mut self, websocket_retry: Duration) -> Self {
+        self.websocket_retry = websocket_retry;
+        self
+    }
+
+    /// URL of the depth snapshots.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Interval between two snapshots polled.
+    pub fn poll_interval(&self) -> Duration {
+        self.poll_interval
+    }
+
+    /// Time spent polling before trying the WebSocket service again.
+    pub fn websocket_retry(&self) -> Duration {
+        self.websocket_retry
+    }
+
+    /// Fetch and parse a snapshot. Errors are logged.
+    ///
+    /// # Arguments
+    ///
+    /// * `exchange_code` - The code of the exchange, for the logs.
+    ///
+    /// # Returns
+    ///
+    /// The snapshot, [None](None) if it could not be fetched or parsed.
+    pub async fn poll(&self, exchange_code: &str) -> Option<T> {
+        match fetch(&self.url).await {
+            Ok(text) => match (self.reader)(&text) {
+                Some(ExchangeProtocol::Data(data)) => Some(data),
+                _ => {
+                    error!("Could not parse REST snapshot from {}", exchange_code);
+                    None
+                },
+            },
+            Err(error) => {
+                error!("Error polling REST snapshot from {}: {:?}", exchange_code, error);
+                None
+            },
+        }
+    }
+}
+
+/// Manual implementation, since `T` is not required to be [Clone](Clone).
+impl <T: 'static + Send> Clone for RestFallback<T> {
+    fn clone(&self) -> Self {
+        Self {
+            url: self.url.clone(),
+            reader: self.reader.clone(),
+            poll_interval: self.poll_interval,
+            websocket_retry: self.websocket_retry,
+        }
+    }
+}
+
+/// Fetch the body of a resource with an HTTP GET request, on a new connection.
+///
+/// # Arguments
+///
+/// * `url` - The URL of the resource, `http` or `https`.
+///
+/// # Returns
+///
+/// The body, or an error if the request failed or the response is not successful.
+pub async fn fetch(url: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    request(Method::GET, url).await
+}
+
+/// Send an HTTP POST request without body, on a new connection, e.g. to obtain a token.
+///
+/// # Arguments
+///
+/// * `url` - The URL of the resource, `http` or `https`.
+///
+/// # Returns
+///
+/// The body of the response, or an error if the request failed or the response is not successful.
+pub async fn post(url: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    request(Method::POST, url).await
+}
+
+/// Internal function sending a request without body on a new connection, and reading the response body.
+async fn request(method: Method, url: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let uri: Uri = url.parse()?;
+    let host = uri.host().ok_or("URL without host")?.trim_matches(|c| c == '[' || c == ']').to_string();
+    let https = uri.scheme_str() == Some("https");
+    let port = uri.port_u16().unwrap_or(if https { 443 } else { 80 });
+    let mut builder = Request::builder()
+        .method(method.clone())
+        .uri(uri.path_and_query().map(|p| p.as_str()).unwrap_or("/"))
+        .header(header::HOST, uri.authority().map(|a| a.as_str()).unwrap_or(&host));
+    if method == Method::POST {
+        builder = builder.header(header::CONTENT_LENGTH, 0);
+    }
+    let request = builder.body(Body::empty())?;
+    let tcp_stream = TcpStream::connect((host.as_str(), port)).await?;
+    if https {
+        let connector = tokio_native_tls::TlsConnector::from(native_tls::TlsConnector::new()?);
+        send(connector.connect(&host, tcp_stream).await?, request).await
+    } else {
+        send(tcp_stream, request).await
+    }
+}
+
+/// Internal function sending a request on a connection, and reading the response body.
+async fn send<S>(io: S, request: Request<Body>) -> Result<String, Box<dyn std::error::Error + Send + Sync>>
+        where S: AsyncRead + AsyncWrite + Unpin + Send + 'static {
+    let (mut sender, connection) = conn::handshake(io).await?;
+    tokio::spawn(async move {
+        if let Err(error) = connection.await {
+            error!("REST connection failed: {:?}", error);
+        }
+    });
+    let response = sender.send_request(request).await?;
+    if !response.status().is_success() {
+        return Err(format!("REST endpoint responded {}", response.status()).into());
+    }
+    let bytes = body::to_bytes(response.into_body()).await?;
+    Ok(String::from_utf8(bytes.to_vec())?)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// Serve one HTTP response on a local port, returning the URL.
+    fn serve_once(status: &'static str, body: &'static str) -> String {
+        serve_once_checked(status, body, |_| ())
+    }
+
+    /// Serve one HTTP response on a local port, checking the request, returning the URL.
+    fn serve_once_checked(status: &'static str, body: &'static str, check: fn(&str)) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/depth?symbol=ETHBTC", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 1024];
+            let size = stream.read(&mut request).unwrap();
+            check(&String::from_utf8_lossy(&request[..size]));
+            let response = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_fetch() {
+        let url = serve_once("200 OK", r#"{"bids":[],"asks":[]}"#);
+        assert_eq!(fetch(&url).await.unwrap(), r#"{"bids":[],"asks":[]}"#);
+        let url = serve_once("503 Service Unavailable", "");
+        assert!(fetch(&url).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_post() {
+        let url = serve_once_checked("200 OK", r#"{"token":"abc"}"#, |request| {
+            assert!(request.starts_with("POST /depth?symbol=ETHBTC HTTP/1.1"), "{}", request);
+            assert!(request.to_lowercase().contains("content-length: 0"), "{}", request);
+        });
+        assert_eq!(post(&url).await.unwrap(), r#"{"token":"abc"}"#);
+    }
+}
@@ -0,0 +1,174 @@
+//! Statistics rolled up by symbol from a [recording](crate::recording) of summaries, e.g. one
+//! recording per day: spread, share of the summaries in which each exchange provided the best
+//! bid and best ask, and depth of the published levels. Shares are measured in summaries rather
+//! than in time, the creation time of the summaries being recorded only in the latency budget of
+//! Protobuf recordings.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::feed::SummaryMetrics;
+use crate::orderbook::Summary;
+
+
+/// Distribution of a statistic.
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct Distribution {
+    /// Number of values, `NaN` values excluded.
+    pub count: usize,
+    /// Average value.
+    pub mean: f64,
+    /// Median value.
+    pub median: f64,
+    /// Smallest value.
+    pub min: f64,
+    /// Largest value.
+    pub max: f64,
+}
+
+impl Distribution {
+    /// Compute the distribution of a series of values.
+    ///
+    /// # Arguments
+    ///
+    /// * `values` - The values, `NaN` values being ignored.
+    ///
+    /// # Returns
+    ///
+    /// The [Distribution](Distribution), with `NaN` statistics if there are no values.
+    pub fn of(values: &[f64]) -> Self {
+        // adding zero turns the negative zero of empty sums into zero
+        let mut values: Vec<f64> = values.iter().filter(|value| !value.is_nan()).map(|value| value + 0.0).collect();
+        if values.is_empty() {
+            return Self { count: 0, mean: f64::NAN, median: f64::NAN, min: f64::NAN, max: f64::NAN };
+        }
+        values.sort_by(f64::total_cmp);
+        let count = values.len();
+        let median = match count % 2 {
+            0 => (values[count / 2 - 1] + values[count / 2]) / 2.0,
+            _ => values[count / 2],
+        };
+        Self {
+            count,
+            mean: values.iter().sum::<f64>() / count as f64,
+            median,
+            min: values[0],
+            max: values[count - 1],
+        }
+    }
+}
+
+/// Statistics of a symbol.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SymbolRollup {
+    /// Number of summaries.
+    pub summaries: usize,
+    /// Distribution of the spread.
+    pub spread: Distribution,
+    /// Share of the summaries, from 0 to 1, in which each exchange provided the best bid.
+    pub best_bid_share: BTreeMap<String, f64>,
+    /// Share of the summaries, from 0 to 1, in which each exchange provided the best ask.
+    pub best_ask_share: BTreeMap<String, f64>,
+    /// Distribution of the total amount of the published bid levels.
+    pub bid_depth: Distribution,
+    /// Distribution of the total amount of the published ask levels.
+    pub ask_depth: Distribution,
+}
+
+/// Accumulated values of a symbol.
+#[derive(Default)]
+struct SymbolSeries {
+    spreads: Vec<f64>,
+    bid_depths: Vec<f64>,
+    ask_depths: Vec<f64>,
+    best_bids: BTreeMap<String, usize>,
+    best_asks: BTreeMap<String, usize>,
+}
+
+/// Accumulates summaries and computes the [statistics](SymbolRollup) of each symbol.
+#[derive(Default)]
+pub struct Rollup {
+    /// Accumulated values, by symbol.
+    series: BTreeMap<String, SymbolSeries>,
+}
+
+impl Rollup {
+    /// Create a new [Rollup](Rollup) object, without summaries.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a summary.
+    ///
+    /// # Arguments
+    ///
+    /// * `summary` - The [Summary](Summary).
+    pub fn add(&mut self, summary: &Summary) {
+        let metrics = SummaryMetrics::from(summary);
+        let series = self.series.entry(summary.symbol.clone()).or_default();
+        series.spreads.push(metrics.spread);
+        series.bid_depths.push(metrics.bid_depth);
+        series.ask_depths.push(metrics.ask_depth);
+        if !metrics.best_bid_exchange.is_empty() {
+            *series.best_bids.entry(metrics.best_bid_exchange).or_default() += 1;
+        }
+        if !metrics.best_ask_exchange.is_empty() {
+            *series.best_asks.entry(metrics.best_ask_exchange).or_default() += 1;
+        }
+    }
+
+    /// Compute the statistics of each symbol.
+    ///
+    /// # Returns
+    ///
+    /// The [SymbolRollup](SymbolRollup) of each symbol, by symbol.
+    pub fn report(&self) -> BTreeMap<String, SymbolRollup> {
+        self.series.iter().map(|(symbol, series)| {
+            let summaries = series.spreads.len();
+            let share = |counts: &BTreeMap<String, usize>| counts.iter()
+                .map(|(exchange, &count)| (exchange.clone(), count as f64 / summaries as f64))
+                .collect();
+            (symbol.clone(), SymbolRollup {
+                summaries,
+                spread: Distribution::of(&series.spreads),
+                best_bid_share: share(&series.best_bids),
+                best_ask_share: share(&series.best_asks),
+                bid_depth: Distribution::of(&series.bid_depths),
+                ask_depth: Distribution::of(&series.ask_depths),
+            })
+        }).collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderbook::Level;
+
+    fn summary(spread: f64, best_bid_exchange: &str, best_ask_exchange: &str) -> Summary {
+        Summary {
+            spread,
+            bids: vec![Level { exchange: best_bid_exchange.to_string(), price: 99.0, amount: 1.0, order_count: None, latency_class: 0 }],
+            asks: vec![Level { exchange: best_ask_exchange.to_string(), price: 99.0 + spread, amount: 2.0, order_count: None, latency_class: 0 }],
+            symbol: "ETH-BTC".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_report() {
+        let mut rollup = Rollup::new();
+        rollup.add(&summary(1.0, "binance", "bitstamp"));
+        rollup.add(&summary(2.0, "binance", "binance"));
+        rollup.add(&summary(4.0, "bitstamp", "binance"));
+        rollup.add(&summary(f64::NAN, "bitstamp", "binance"));
+        let report = rollup.report();
+        let rollup = &report["ETH-BTC"];
+        assert_eq!(rollup.summaries, 4);
+        assert_eq!(rollup.spread, Distribution { count: 3, mean: 7.0 / 3.0, median: 2.0, min: 1.0, max: 4.0 });
+        assert_eq!(rollup.best_bid_share, BTreeMap::from([("binance".to_string(), 0.5), ("bitstamp".to_string(), 0.5)]));
+        assert_eq!(rollup.best_ask_share, BTreeMap::from([("binance".to_string(), 0.75), ("bitstamp".to_string(), 0.25)]));
+        assert_eq!(rollup.ask_depth.median, 2.0);
+    }
+}
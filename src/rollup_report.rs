@@ -0,0 +1,37 @@
+//! Computes the statistics of each symbol from a recording of summaries, e.g. the output of
+//! the pipe sink redirected to a file each day, and writes them as a JSON report.
+
+use log::{LevelFilter, info};
+use simple_logger::SimpleLogger;
+use std::{env, fs::File, io::{BufReader, Write}};
+
+use orderbook_server::cli::ArgParser;
+use orderbook_server::recording::{read_summary, RecordingFormat};
+use orderbook_server::rollup::Rollup;
+
+
+const USAGE_MESSAGE: &str = "Usage: rollup <input> <ndjson|protobuf> [report]";
+
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    SimpleLogger::new().with_level(LevelFilter::Info).init().unwrap();
+    let mut arg_parser = ArgParser::new(env::args(), USAGE_MESSAGE);
+    let input = arg_parser.extract_string();
+    let input_format = RecordingFormat::parse(&arg_parser.extract_string())
+        .map_err(|error| format!("{}\n{}", error, USAGE_MESSAGE))?;
+    let output = arg_parser.extract_optional_string();
+    let mut reader = BufReader::new(File::open(&input)?);
+    let mut rollup = Rollup::new();
+    while let Some(summary) = read_summary(&mut reader, input_format)? {
+        rollup.add(&summary);
+    }
+    let report = serde_json::to_string_pretty(&rollup.report())?;
+    match output {
+        Some(output) => {
+            File::create(&output)?.write_all(report.as_bytes())?;
+            info!("Report of {} written to {}", input, output);
+        },
+        None => println!("{}", report),
+    }
+    Ok(())
+}
@@ -0,0 +1,190 @@
+//! Advisory routing of an order across exchanges, computed from the consolidated
+//! trading book: a suggested split only, no order is placed.
+
+use rust_decimal::prelude::*;
+use std::collections::HashMap;
+
+use crate::aggregator::AggregateBook;
+use crate::core::{ExchangeId, ExchangeLevel, Side};
+
+
+/// The part of a [Route](Route) executed on one exchange.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Allocation {
+    /// Exchange code.
+    pub exchange_code: ExchangeId,
+    /// Amount to execute on the exchange.
+    pub amount: Decimal,
+    /// Average price of the amount, before fees.
+    pub average_price: Decimal,
+    /// Last price reached on the exchange.
+    pub worst_price: Decimal,
+    /// Fees paid, in counter currency.
+    pub fees: Decimal,
+}
+
+/// A suggested split of an order across exchanges.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Route {
+    /// One [Allocation](Allocation) per exchange, in order of execution.
+    pub allocations: Vec<Allocation>,
+    /// Amount that the book is not deep enough to fill.
+    pub unfilled: Decimal,
+}
+
+/// Computes [routes](Route) taking liquidity from the best prices, optionally adjusted by
+/// the taker fee of each exchange.
+#[derive(Default)]
+pub struct Router {
+    /// Taker fees in basis points, by exchange code.
+    fees_bps: HashMap<String, Decimal>,
+}
+
+impl Router {
+    /// Create a new [Router](Router) without fees.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the taker fee of an exchange.
+    ///
+    /// # Arguments
+    ///
+    /// * `exchange_code` - The exchange code.
+    ///
+    /// * `fee_bps` - The fee, in basis points of the notional.
+    ///
+    /// # Returns
+    ///
+    /// The modified [Router](Router).
+    pub fn with_fee(mut self, exchange_code: &str, fee_bps: Decimal) -> Self {
+        self.fees_bps.insert(exchange_code.to_string(), fee_bps);
+        self
+    }
+
+    /// Fee rate of an exchange, as a fraction of the notional.
+    fn fee_rate(&self, exchange_code: &str) -> Decimal {
+        self.fees_bps.get(exchange_code).copied().unwrap_or_default() / Decimal::from(10_000)
+    }
+
+    /// Price including the fee: paid on top when buying from the asks, deducted when selling
+    /// to the bids.
+    fn effective_price(&self, side: Side, level: &ExchangeLevel) -> Decimal {
+        let fee_rate = self.fee_rate(&level.exchange_code);
+        match side {
+            Side::Buy => level.price * (Decimal::ONE - fee_rate),
+            Side::Sell => level.price * (Decimal::ONE + fee_rate),
+        }
+    }
+
+    /// Suggest how to split an amount across exchanges, taking the levels with the best
+    /// price after fees first.
+    ///
+    /// # Arguments
+    ///
+    /// * `book` - The [AggregateBook](AggregateBook).
+    ///
+    /// * `side` - The [Side](Side) of the book: bids for [Buy](Side::Buy), asks for [Sell](Side::Sell).
+    ///
+    /// * `amount` - The amount to execute, in base currency.
+    ///
+    /// # Returns
+    ///
+    /// A [Route](Route) object.
+    pub fn suggest_split(&self, book: &AggregateBook, side: Side, amount: Decimal) -> Route {
+        let mut levels: Vec<(Decimal, &ExchangeLevel)> = book.levels(side)
+            .flat_map(|level| level.levels_by_amount())
+            .map(|level| (self.effective_price(side, level), level))
+            .collect();
+        // stable sort: for the same price after fees, larger amounts and better raw prices first
+        match side {
+            Side::Buy => levels.sort_by_key(|(price, _)| std::cmp::Reverse(*price)),
+            Side::Sell => levels.sort_by_key(|(price, _)| *price),
+        }
+        let mut allocations: Vec<Allocation> = vec![];
+        let mut unfilled = amount.max(Decimal::ZERO);
+        for (_, level) in levels {
+            if unfilled.is_zero() {
+                break;
+            }
+            let taken = unfilled.min(level.amount);
+            unfilled -= taken;
+            let fees = level.price * taken * self.fee_rate(&level.exchange_code);
+            match allocations.iter_mut().find(|a| a.exchange_code == level.exchange_code) {
+                Some(allocation) => {
+                    let notional = allocation.average_price * allocation.amount + level.price * taken;
+                    allocation.amount += taken;
+                    allocation.average_price = notional / allocation.amount;
+                    allocation.worst_price = level.price;
+                    allocation.fees += fees;
+                }
+                None => allocations.push(Allocation {
+                    exchange_code: level.exchange_code.clone(),
+                    amount: taken,
+                    average_price: level.price,
+                    worst_price: level.price,
+                    fees,
+                }),
+            }
+        }
+        Route { allocations, unfilled }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::BookUpdate;
+
+    fn d(value: &str) -> Decimal {
+        Decimal::from_str(value).unwrap()
+    }
+
+    fn book() -> AggregateBook {
+        let mut book = AggregateBook::new(10);
+        book.update(BookUpdate {
+            exchange_code: "cheap".into(),
+            bids: vec![],
+            asks: vec![
+                ExchangeLevel::from_strs("cheap", "100", "1"),
+                ExchangeLevel::from_strs("cheap", "102", "1"),
+            ],
+        });
+        book.update(BookUpdate {
+            exchange_code: "costly".into(),
+            bids: vec![],
+            asks: vec![ExchangeLevel::from_strs("costly", "101", "2")],
+        });
+        book
+    }
+
+    #[test]
+    fn test_split_by_price() {
+        let route = Router::new().suggest_split(&book(), Side::Sell, d("2.5"));
+        assert_eq!(route.unfilled, Decimal::ZERO);
+        assert_eq!(route.allocations, vec![
+            Allocation { exchange_code: "cheap".into(), amount: d("1"), average_price: d("100"), worst_price: d("100"), fees: Decimal::ZERO },
+            Allocation { exchange_code: "costly".into(), amount: d("1.5"), average_price: d("101"), worst_price: d("101"), fees: Decimal::ZERO },
+        ]);
+    }
+
+    #[test]
+    fn test_split_with_fees() {
+        let router = Router::new().with_fee("cheap", d("200"));
+        let route = router.suggest_split(&book(), Side::Sell, d("5"));
+        assert_eq!(route.unfilled, d("1"));
+        assert_eq!(route.allocations[0].exchange_code, "costly");
+        assert_eq!(route.allocations[0].amount, d("2"));
+        let cheap = &route.allocations[1];
+        assert_eq!((cheap.exchange_code.as_str(), cheap.amount), ("cheap", d("2")));
+        assert_eq!((cheap.average_price, cheap.worst_price), (d("101"), d("102")));
+        assert_eq!(cheap.fees, d("4.04"));
+    }
+
+    #[test]
+    fn test_split_empty_side() {
+        let route = Router::new().suggest_split(&book(), Side::Buy, d("1"));
+        assert_eq!(route, Route { allocations: vec![], unfilled: d("1") });
+    }
+}
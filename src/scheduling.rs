@@ -0,0 +1,147 @@
+//! Priority of the ingestion of the exchange messages over the publication of the summaries:
+//! the exchange adapters, reading and parsing messages, and the feeds, maintaining the
+//! aggregate books, run on a dedicated ingest runtime, while the clients and sinks are served
+//! on a separate publish runtime. Summaries are conflated towards the clients, so when the CPU
+//! is saturated by many clients only the publication slows down, never the book.
+//! The thread counts are read from a JSON file.
+
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::io;
+use std::sync::OnceLock;
+use std::thread;
+use tokio::runtime::{Builder, Runtime};
+use tokio::task::JoinHandle;
+
+use crate::config::{DocumentedConfig, Validate, Validator};
+
+
+/// Default number of worker threads of the ingest runtime.
+const DEFAULT_INGEST_THREADS: usize = 1;
+/// Name of the worker threads of the ingest runtime.
+pub const INGEST_THREAD_NAME: &str = "ingest";
+/// Name of the worker threads of the publish runtime.
+pub const PUBLISH_THREAD_NAME: &str = "publish";
+
+
+/// The ingest runtime, once started.
+static INGEST_RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+
+/// Thread counts of the ingest and publish runtimes. Missing values take their defaults.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct Scheduling {
+    /// Worker threads of the ingest runtime.
+    pub ingest_threads: usize,
+    /// Worker threads of the publish runtime, [None](None) for the available cores not used
+    /// by the ingest runtime.
+    pub publish_threads: Option<usize>,
+}
+
+impl Default for Scheduling {
+    fn default() -> Self {
+        Self {
+            ingest_threads: DEFAULT_INGEST_THREADS,
+            publish_threads: None,
+        }
+    }
+}
+
+impl Scheduling {
+    /// Worker threads of the publish runtime.
+    ///
+    /// # Arguments
+    ///
+    /// * `available_cores` - The number of cores available to the process.
+    ///
+    /// # Returns
+    ///
+    /// The configured count, or else the available cores not used by the ingest runtime,
+    /// at least one.
+    pub fn publish_threads(&self, available_cores: usize) -> usize {
+        self.publish_threads.unwrap_or(available_cores.saturating_sub(self.ingest_threads).max(1))
+    }
+
+    /// Start the ingest runtime, used by [spawn_ingest](spawn_ingest) from then on.
+    ///
+    /// # Returns
+    ///
+    /// An error if the runtime cannot be built or was already started.
+    pub fn start_ingest_runtime(&self) -> io::Result<()> {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(self.ingest_threads)
+            .thread_name(INGEST_THREAD_NAME)
+            .enable_all()
+            .build()?;
+        INGEST_RUNTIME.set(runtime)
+            .map_err(|_| io::Error::new(io::ErrorKind::AlreadyExists, "ingest runtime already started"))
+    }
+
+    /// Build the publish runtime, where the server is run.
+    ///
+    /// # Returns
+    ///
+    /// The [Runtime](Runtime), or an error if it cannot be built.
+    pub fn publish_runtime(&self) -> io::Result<Runtime> {
+        let available_cores = thread::available_parallelism().map(|cores| cores.get()).unwrap_or(1);
+        Builder::new_multi_thread()
+            .worker_threads(self.publish_threads(available_cores))
+            .thread_name(PUBLISH_THREAD_NAME)
+            .enable_all()
+            .build()
+    }
+}
+
+impl Validate for Scheduling {
+    fn validate(&self, validator: &mut Validator) {
+        validator.positive("ingest_threads", self.ingest_threads as u64);
+        if let Some(publish_threads) = self.publish_threads {
+            validator.positive("publish_threads", publish_threads as u64);
+        }
+    }
+}
+
+impl DocumentedConfig for Scheduling {
+    const DESCRIPTION: &'static str = "Thread counts of the ingest and publish runtimes.";
+    const FIELDS: &'static [(&'static str, &'static str)] = &[
+        ("ingest_threads", "Worker threads of the ingest runtime."),
+        ("publish_threads", "Worker threads of the publish runtime, null for the available cores not used by the ingest runtime."),
+    ];
+}
+
+/// Spawn an ingest task: on the ingest runtime if started, otherwise on the current runtime.
+/// Tasks spawned from an ingest task also run on the ingest runtime.
+///
+/// # Arguments
+///
+/// * `future` - The task.
+///
+/// # Returns
+///
+/// The [JoinHandle](JoinHandle) of the task.
+pub fn spawn_ingest<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    match INGEST_RUNTIME.get() {
+        Some(runtime) => runtime.spawn(future),
+        None => tokio::spawn(future),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publish_threads() {
+        let scheduling = Scheduling { ingest_threads: 2, publish_threads: None };
+        assert_eq!(scheduling.publish_threads(8), 6);
+        assert_eq!(scheduling.publish_threads(2), 1);
+        let scheduling = Scheduling { ingest_threads: 2, publish_threads: Some(3) };
+        assert_eq!(scheduling.publish_threads(8), 3);
+    }
+}
@@ -1,117 +1,351 @@
-//! Protobuf RPC server for continuously updated snapshots of a trading book
-//! consolidated from multiple exchanges.
+//! Command line entry point of the Protobuf RPC server for continuously updated
+//! snapshots of a trading book consolidated from multiple exchanges.
 
-use log::{LevelFilter, info};
+use log::{error, LevelFilter};
+use serde::Serialize;
 use simple_logger::SimpleLogger;
-use futures::Stream;
-use std::{env, pin::Pin, net, str::FromStr};
-use tokio::sync::mpsc;
-use tokio_stream::{wrappers::ReceiverStream, StreamExt};
-use tonic::{transport::Server, Request, Response, Status};
+use std::{env, path::{Path, PathBuf}};
+use tokio::time::Duration;
 
-use orderbook_server::orderbook::{Summary, Empty, orderbook_aggregator_server::{OrderbookAggregator, OrderbookAggregatorServer}};
-
-use orderbook_server::core::BookUpdate;
+use orderbook_server::accounting::UsageRegistry;
+use orderbook_server::adaptive_depth::AdaptiveDepth;
+use orderbook_server::staleness::StalenessThresholds;
+use orderbook_server::allocator::{spawn_stats, GlobalAllocator, GLOBAL_ALLOCATOR};
+use orderbook_server::alerts::AlertsConfig;
+use orderbook_server::binance::BinanceDepthConfig;
+use orderbook_server::clock::system_clock;
+use orderbook_server::config::{commented_files, serialize_documented, serialize_documented_optional, ConfigLoader, ValidationErrors};
+use orderbook_server::core::{CurrencyPair, ExchangeId};
 use orderbook_server::cli::ArgParser;
-use orderbook_server::exchange::{ExchangeAdapter, ExchangeDataStream};
-use orderbook_server::service::BookSummaryService;
-use orderbook_server::binance::make_binance_exchange_adapter;
-use orderbook_server::bitstamp::make_bitstamp_echange_adapter;
+use orderbook_server::crash_dump::install_panic_hook;
+use orderbook_server::exchange::Registry;
+use orderbook_server::grpc::ProtobufOrderbookServer;
+use orderbook_server::ingest::IngestLimits;
+use orderbook_server::maintenance::MaintenanceSchedule;
+use orderbook_server::numbers::{FloatRounding, NumberFormat};
+use orderbook_server::queues::QueueCapacities;
+use orderbook_server::reconnect::ReconnectBudget;
+use orderbook_server::scheduling::Scheduling;
+use orderbook_server::settings::ServerSettings;
+use orderbook_server::shadow::ShadowConfig;
+use orderbook_server::symbol_groups::SymbolGroups;
 
-type ResponseStream = Pin<Box<dyn Stream<Item = Result<Summary, Status>> + Send>>;
-type SummaryResult = Result<Response<ResponseStream>, Status>;
 
+const USAGE_MESSAGE: &str = "Usage: server <currency pair> [<currency pair> ...] [port] [<exchange>,...]\n       server print-default-config [<file>]";
+/// Subcommand printing the configuration files with their defaults, fully commented.
+const PRINT_DEFAULT_CONFIG_COMMAND: &str = "print-default-config";
+/// Exchanges consolidated unless listed on the command line.
+const DEFAULT_EXCHANGES: &str = "binance,bitstamp";
+/// File where the usage of each client API key is persisted.
+const USAGE_FILE: &str = "usage.json";
+/// Interval between two saves of the client usage.
+const USAGE_SAVE_INTERVAL_S: u64 = 60;
+/// Directory of the diagnostic dumps written after unexpected panics.
+const CRASH_DUMP_DIR: &str = "crash_dumps";
+/// Interval between two samples of the statistics of the global allocator.
+const ALLOCATOR_STATS_INTERVAL_S: u64 = 60;
+/// File with the settings of the server, defaults are used if missing.
+const SERVER_SETTINGS_FILE: &str = "server.json";
+/// File with the capacities of the internal queues, defaults are used if missing.
+const QUEUES_FILE: &str = "queues.json";
+/// File with the limits of the messages received from the exchanges, defaults are used if missing.
+const INGEST_FILE: &str = "ingest.json";
+/// File with the channel of the periodic snapshots of Binance, defaults are used if missing.
+const BINANCE_FILE: &str = "binance.json";
+/// File with the thread counts of the ingest and publish runtimes, defaults are used if missing.
+const SCHEDULING_FILE: &str = "scheduling.json";
+/// File with the adaptive depth of the summaries, all levels are published if missing.
+const ADAPTIVE_DEPTH_FILE: &str = "adaptive_depth.json";
+/// File with the instance the feeds are compared with, no comparison if missing.
+const SHADOW_FILE: &str = "shadow.json";
+/// File with the thresholds of the latency classes of the exchanges, defaults are used if missing.
+const STALENESS_FILE: &str = "staleness.json";
+/// File with the rounding of the numbers of the summaries, numbers are not rounded if missing.
+const FLOAT_ROUNDING_FILE: &str = "float_rounding.json";
+/// File with the alerting rules, alerting is disabled if missing.
+const ALERTS_FILE: &str = "alerts.json";
+/// File with the maintenance windows of the exchanges, none if missing.
+const MAINTENANCE_FILE: &str = "maintenance.json";
+/// File with the groups of symbols clients can subscribe to, none if missing.
+const SYMBOL_GROUPS_FILE: &str = "symbol_groups.json";
+/// File with the webhook where changes of the status of the exchanges are posted.
+#[cfg(feature = "webhook")]
+const STATUS_WEBHOOK_FILE: &str = "webhook.json";
+/// File with the database and interval of the SQLite sink, defaults are used if missing.
+#[cfg(feature = "sqlite")]
+const SQLITE_SINK_CONFIG_FILE: &str = "sqlite_sink.json";
+/// File with the database and batches of the PostgreSQL sink, defaults are used if missing.
+#[cfg(feature = "postgres")]
+const POSTGRES_SINK_CONFIG_FILE: &str = "postgres_sink.json";
+/// File with the destination and interval of the InfluxDB sink, defaults are used if missing.
+#[cfg(feature = "influx")]
+const INFLUX_SINK_CONFIG_FILE: &str = "influx_sink.json";
+/// File with the broker and topics of the MQTT sink, defaults are used if missing.
+#[cfg(feature = "mqtt")]
+const MQTT_SINK_CONFIG_FILE: &str = "mqtt_sink.json";
+/// File with the formatting of the numbers published by the JSON sinks, defaults are used if missing.
+const NUMBER_FORMAT_FILE: &str = "number_format.json";
+/// Local port of the HTTP endpoint where the metrics are exposed to Prometheus.
+#[cfg(feature = "prometheus")]
+const PROMETHEUS_PORT: u16 = 9184;
+/// File with the encoding and destination of the pipe sink, defaults are used if missing.
+#[cfg(feature = "pipe")]
+const PIPE_SINK_CONFIG_FILE: &str = "pipe_sink.json";
 
-const USAGE_MESSAGE: &str = "Usage: server <currency pair> [port]";
 
+#[global_allocator]
+static GLOBAL: GlobalAllocator = GLOBAL_ALLOCATOR;
 
-/// Top level object representing a Profobuf RPC server.
-pub struct ProtobufOrderbookServer {
-    /// The exchange adapters.
-    exchange_adapters: Vec<ExchangeAdapter<BookUpdate>>,
+/// Configuration read from the files of the working directory, serialized with one field per
+/// file, named after it.
+#[derive(Serialize, Default)]
+struct Config {
+    /// Settings of the server.
+    #[serde(rename = "server.json", serialize_with = "serialize_documented")]
+    server: ServerSettings,
+    /// Thread counts of the runtimes.
+    #[serde(rename = "scheduling.json", serialize_with = "serialize_documented")]
+    scheduling: Scheduling,
+    /// Limits of the messages received from the exchanges.
+    #[serde(rename = "ingest.json", serialize_with = "serialize_documented")]
+    ingest_limits: IngestLimits,
+    /// Capacities of the internal queues.
+    #[serde(rename = "queues.json", serialize_with = "serialize_documented")]
+    queue_capacities: QueueCapacities,
+    /// Channel of the periodic snapshots of Binance, not used in full-depth mode.
+    #[serde(rename = "binance.json", serialize_with = "serialize_documented")]
+    binance: BinanceDepthConfig,
+    /// Thresholds of the latency classes of the exchanges.
+    #[serde(rename = "staleness.json", serialize_with = "serialize_documented")]
+    staleness_thresholds: StalenessThresholds,
+    /// Rounding of the numbers of the summaries.
+    #[serde(rename = "float_rounding.json", serialize_with = "serialize_documented")]
+    float_rounding: FloatRounding,
+    /// Formatting of the numbers published by the JSON sinks.
+    #[serde(rename = "number_format.json", serialize_with = "serialize_documented")]
+    number_format: NumberFormat,
+    /// Alerting rules.
+    #[serde(rename = "alerts.json", serialize_with = "serialize_documented")]
+    alerts: AlertsConfig,
+    /// Maintenance windows of the exchanges.
+    #[serde(rename = "maintenance.json", serialize_with = "serialize_documented")]
+    maintenance: MaintenanceSchedule,
+    /// Groups of symbols.
+    #[serde(rename = "symbol_groups.json", serialize_with = "serialize_documented")]
+    symbol_groups: SymbolGroups,
+    /// Adaptive depth of the summaries, if any.
+    #[serde(rename = "adaptive_depth.json", serialize_with = "serialize_documented_optional")]
+    adaptive_depth: Option<AdaptiveDepth>,
+    /// Instance the feeds are compared with, if any.
+    #[serde(rename = "shadow.json", serialize_with = "serialize_documented_optional")]
+    shadow: Option<ShadowConfig>,
+    /// Webhook where changes of the status of the exchanges are posted, if any.
+    #[cfg(feature = "webhook")]
+    #[serde(rename = "webhook.json", serialize_with = "serialize_documented_optional")]
+    status_webhook: Option<orderbook_server::webhook::WebhookConfig>,
+    /// Database and interval of the SQLite sink.
+    #[cfg(feature = "sqlite")]
+    #[serde(rename = "sqlite_sink.json", serialize_with = "serialize_documented")]
+    sqlite_sink: orderbook_server::sqlite_sink::SqliteSinkConfig,
+    /// Database and batches of the PostgreSQL sink.
+    #[cfg(feature = "postgres")]
+    #[serde(rename = "postgres_sink.json", serialize_with = "serialize_documented")]
+    postgres_sink: orderbook_server::postgres_sink::PostgresSinkConfig,
+    /// Destination and interval of the InfluxDB sink.
+    #[cfg(feature = "influx")]
+    #[serde(rename = "influx_sink.json", serialize_with = "serialize_documented")]
+    influx_sink: orderbook_server::influx_sink::InfluxSinkConfig,
+    /// Broker and topics of the MQTT sink.
+    #[cfg(feature = "mqtt")]
+    #[serde(rename = "mqtt_sink.json", serialize_with = "serialize_documented")]
+    mqtt_sink: orderbook_server::mqtt_sink::MqttSinkConfig,
+    /// Encoding and destination of the pipe sink.
+    #[cfg(feature = "pipe")]
+    #[serde(rename = "pipe_sink.json", serialize_with = "serialize_documented")]
+    pipe_sink: orderbook_server::pipe_sink::PipeSinkConfig,
 }
 
-impl ProtobufOrderbookServer {
-    /// Create a new [ProtobufOrderbookServer](ProtobufOrderbookServer) object.
-    ///
-    /// # Arguments
-    ///
-    /// * `exchange_adapters` - A [vector](Vec) of [ExchangeAdapter](ExchangeAdapter) objects, one
-    /// for each exchange.
-    ///
-    /// # Returns
-    ///
-    /// A [ProtobufOrderbookServer](ProtobufOrderbookServer) object.
-    pub fn new(exchange_adapters: Vec<ExchangeAdapter<BookUpdate>>) -> Self {
-        Self { exchange_adapters }
-    }
-
-    /// Start the Protobuf RPC server on a port.
-    ///
-    /// # Arguments
-    ///
-    /// * `port` - The TCP port of the server.
-    ///
-    /// # Returns
-    ///
-    /// An empty [Result](Result).
-    pub async fn serve(self, port: u16) -> Result<(), Box<dyn std::error::Error>> {
-        let our_address = net::SocketAddr::new(
-            net::IpAddr::V6(net::Ipv6Addr::from_str("::1").unwrap()),
-            port
-        );
-        Server::builder()
-            .add_service(OrderbookAggregatorServer::new(self))
-            .serve(our_address)
-            .await
-            .unwrap();
-        Ok(())
+impl Config {
+    /// Read and validate every configuration file, reporting the errors of all of them together.
+    fn load() -> Result<Self, ValidationErrors> {
+        let mut loader = ConfigLoader::default();
+        let config = Self {
+            server: loader.load(Path::new(SERVER_SETTINGS_FILE)),
+            scheduling: loader.load(Path::new(SCHEDULING_FILE)),
+            ingest_limits: loader.load(Path::new(INGEST_FILE)),
+            queue_capacities: loader.load(Path::new(QUEUES_FILE)),
+            binance: loader.load(Path::new(BINANCE_FILE)),
+            staleness_thresholds: loader.load(Path::new(STALENESS_FILE)),
+            float_rounding: loader.load(Path::new(FLOAT_ROUNDING_FILE)),
+            number_format: loader.load(Path::new(NUMBER_FORMAT_FILE)),
+            alerts: loader.load(Path::new(ALERTS_FILE)),
+            maintenance: loader.load(Path::new(MAINTENANCE_FILE)),
+            symbol_groups: loader.load::<SymbolGroups>(Path::new(SYMBOL_GROUPS_FILE)).canonical(),
+            adaptive_depth: loader.load_optional(Path::new(ADAPTIVE_DEPTH_FILE)),
+            shadow: loader.load_optional(Path::new(SHADOW_FILE)),
+            #[cfg(feature = "webhook")]
+            status_webhook: loader.load_optional(Path::new(STATUS_WEBHOOK_FILE)),
+            #[cfg(feature = "sqlite")]
+            sqlite_sink: loader.load(Path::new(SQLITE_SINK_CONFIG_FILE)),
+            #[cfg(feature = "postgres")]
+            postgres_sink: loader.load(Path::new(POSTGRES_SINK_CONFIG_FILE)),
+            #[cfg(feature = "influx")]
+            influx_sink: loader.load(Path::new(INFLUX_SINK_CONFIG_FILE)),
+            #[cfg(feature = "mqtt")]
+            mqtt_sink: loader.load(Path::new(MQTT_SINK_CONFIG_FILE)),
+            #[cfg(feature = "pipe")]
+            pipe_sink: loader.load(Path::new(PIPE_SINK_CONFIG_FILE)),
+        };
+        loader.finish()?;
+        Ok(config)
     }
 }
 
-/// Implementation of the trait automatically generated from the file `proto/orderbook.proto`.
-#[tonic::async_trait]
-impl OrderbookAggregator for ProtobufOrderbookServer {
-
-    type BookSummaryStream = ResponseStream;
-
-    async fn book_summary(&self, req: Request<Empty>) -> SummaryResult {
-        info!("OrderbookServer::book_summary");
-        info!("Client connected from: {:?}", req.remote_addr());
-
-        let (tx, rx) = mpsc::channel(128);
-        let book_update_stream = ExchangeDataStream::new(&self.exchange_adapters).await;
-        let mut service: BookSummaryService = BookSummaryService::new(book_update_stream);
-
-        tokio::spawn(async move {
-            while let Some(item) = service.next().await {
-                if tx.send(Result::<Summary, Status>::Ok(item)).await.is_err() {
-                    break;
-                }
-            }
-            info!("Client disconnected");
-            service.disconnect().await;
-        });
-
-        let output_stream = ReceiverStream::new(rx);
-        Ok(Response::new(
-            Box::pin(output_stream) as Self::BookSummaryStream
-        ))
+/// Print the configuration files with defaults, fully commented.
+///
+/// # Arguments
+///
+/// * `file` - The file to print, all of them if [None](None).
+fn print_default_config(file: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let files = commented_files(&Config::default())?;
+    let printed: Vec<&String> = files.iter()
+        .filter(|(name, _)| file.is_none_or(|file| file == name))
+        .map(|(_, text)| text)
+        .collect();
+    if printed.is_empty() {
+        let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+        return Err(format!("no defaults for {}, files with defaults: {}", file.unwrap_or_default(), names.join(", ")).into());
+    }
+    for (i, text) in printed.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("{}", text);
     }
+    Ok(())
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    if env::args().nth(1).as_deref() == Some(PRINT_DEFAULT_CONFIG_COMMAND) {
+        return print_default_config(env::args().nth(2).as_deref());
+    }
     SimpleLogger::new().with_level(LevelFilter::Info).init().unwrap();
     let mut arg_parser = ArgParser::new(env::args(), USAGE_MESSAGE);
-    let product = arg_parser.extract_currency_pair();
+    let products = arg_parser.extract_currency_pairs();
     let port = arg_parser.extract_port();
-    let binance_adapter = make_binance_exchange_adapter(&product).await;
-    let bitstamp_adapter = make_bitstamp_echange_adapter(&product).await;
-    let exchange_adapters: Vec<ExchangeAdapter<BookUpdate>> = vec![
-        binance_adapter,
-        bitstamp_adapter,
-    ];
-    let server = ProtobufOrderbookServer::new(exchange_adapters);
+    let exchange_names = arg_parser.extract_optional_string().unwrap_or(DEFAULT_EXCHANGES.to_string());
+    let config = Config::load().unwrap_or_else(|errors| {
+        error!("{}", errors);
+        std::process::exit(1)
+    });
+    let registry = Registry::builtin();
+    #[cfg(not(feature = "full-depth"))]
+    let registry = {
+        let binance = config.binance;
+        registry.with_exchange("binance", orderbook_server::exchange::adapter_factory(move |product| {
+            orderbook_server::binance::make_binance_configured_adapter(product, binance)
+        }))
+    };
+    let exchanges = registry.parse_names(&exchange_names)?;
+    config.scheduling.start_ingest_runtime()?;
+    config.scheduling.publish_runtime()?.block_on(run(products, port, &registry, &exchanges, config))
+}
+
+/// Run the server on the publish runtime, the exchange adapters being spawned on the ingest runtime.
+/// Every currency pair is served alike, with a feed of its own delivered to the sinks.
+async fn run(products: Vec<CurrencyPair>, port: u16, registry: &Registry, exchanges: &[ExchangeId], config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    spawn_stats(Duration::from_secs(ALLOCATOR_STATS_INTERVAL_S));
+    install_panic_hook(PathBuf::from(CRASH_DUMP_DIR))?;
+    let usage = UsageRegistry::load(Path::new(USAGE_FILE), system_clock())?;
+    usage.spawn_persistence(PathBuf::from(USAGE_FILE), Duration::from_secs(USAGE_SAVE_INTERVAL_S));
+    let mut products = products.into_iter();
+    let product = products.next().expect(USAGE_MESSAGE);
+    let exchange_adapters = registry.make_adapters(exchanges, &product);
+    let server = products.fold(ProtobufOrderbookServer::new(product, exchange_adapters, usage), |server, product| {
+        let exchange_adapters = registry.make_adapters(exchanges, &product);
+        server.with_product(product, exchange_adapters)
+    });
+    let server = server
+        .with_queue_capacities(config.queue_capacities)
+        .with_ingest_limits(config.ingest_limits)
+        .with_inverted_book_check(true)
+        .with_reconnect_budget(ReconnectBudget::default())
+        .with_full_depth(cfg!(feature = "full-depth"))
+        .with_cross_check(cfg!(feature = "cross-check"))
+        .with_latency_budget(cfg!(feature = "latency-budget"))
+        .with_connection_stagger(Duration::from_millis(config.server.connection_stagger_ms))
+        .with_depth_bands(config.server.depth_bands_bps.clone())
+        .with_volatility_horizons(config.server.volatility_horizons())
+        .with_summary_log(config.server.summary_log_sampling())
+        .with_wait_for_snapshots(config.server.wait_for_snapshots)
+        .with_alerts(config.alerts)
+        .with_maintenance(config.maintenance)
+        .with_symbol_groups(config.symbol_groups)
+        .with_float_rounding(config.float_rounding)
+        .with_staleness_thresholds(config.staleness_thresholds);
+    let server = match config.server.stale_grace_s {
+        Some(stale_grace_s) => server.with_stale_grace(Duration::from_secs(stale_grace_s)),
+        None => server,
+    };
+    let server = match config.adaptive_depth {
+        Some(adaptive_depth) => server.with_adaptive_depth(adaptive_depth),
+        None => server,
+    };
+    let server = match config.shadow {
+        Some(shadow) => server.with_shadow(shadow),
+        None => server,
+    };
+    #[cfg(feature = "webhook")]
+    let server = match config.status_webhook {
+        Some(config) => server
+            .with_sink_endpoint("status_webhook", &config.url)
+            .with_status_webhook(orderbook_server::webhook::Webhook::from_config(&config)),
+        None => server,
+    };
+    #[cfg(feature = "sqlite")]
+    let server = server.with_sink_endpoint("sqlite", &config.sqlite_sink.file);
+    #[cfg(feature = "postgres")]
+    let server = server.with_sink_endpoint("postgres", &config.postgres_sink.connection);
+    #[cfg(feature = "influx")]
+    let server = server.with_sink_endpoint("influx", &config.influx_sink.url);
+    #[cfg(feature = "mqtt")]
+    let server = server.with_sink_endpoint("mqtt", &format!("{}:{}", config.mqtt_sink.host, config.mqtt_sink.port));
+    #[cfg(feature = "pipe")]
+    let server = server.with_sink_endpoint("pipe", &config.pipe_sink.named_pipe.as_ref().map_or(String::from("stdout"), |path| path.display().to_string()));
+    #[cfg(any(feature = "sqlite", feature = "postgres", feature = "influx", feature = "mqtt", feature = "pipe", all(feature = "systemd", unix), feature = "prometheus"))]
+    let mut feeds = vec![];
+    #[cfg(any(feature = "sqlite", feature = "postgres", feature = "influx", feature = "mqtt", feature = "pipe", all(feature = "systemd", unix), feature = "prometheus"))]
+    for symbol in server.served_symbols() {
+        feeds.push((server.feed(&symbol).await.expect("symbol served"), server.book(&symbol).await.expect("symbol served")));
+    }
+    #[cfg(any(feature = "sqlite", feature = "postgres", feature = "influx", feature = "mqtt", feature = "pipe"))]
+    let sinks = feeds.iter().skip(1).fold(
+        orderbook_server::sink::SinkManager::new(feeds[0].0.clone(), feeds[0].1.clone(), system_clock()),
+        |sinks, (feed, book)| sinks.with_feed(feed.clone(), book.clone()),
+    );
+    #[cfg(feature = "sqlite")]
+    let sinks = sinks.with_sink(orderbook_server::sqlite_sink::SqliteSink::from_config(&config.sqlite_sink)?);
+    #[cfg(feature = "postgres")]
+    let sinks = sinks.with_sink(orderbook_server::postgres_sink::PostgresSink::from_config(&config.postgres_sink));
+    #[cfg(feature = "influx")]
+    let sinks = sinks.with_sink(orderbook_server::influx_sink::InfluxSink::from_config(&config.influx_sink)?);
+    #[cfg(any(feature = "mqtt", feature = "pipe"))]
+    let number_format = config.number_format;
+    #[cfg(feature = "mqtt")]
+    let sinks = sinks.with_sink(orderbook_server::mqtt_sink::MqttSink::from_config(&config.mqtt_sink)
+        .with_number_format(number_format.clone()));
+    #[cfg(feature = "pipe")]
+    let sinks = sinks.with_sink(orderbook_server::pipe_sink::PipeSink::from_config(&config.pipe_sink)
+        .with_number_format(number_format.clone()));
+    #[cfg(any(feature = "sqlite", feature = "postgres", feature = "influx", feature = "mqtt", feature = "pipe"))]
+    #[cfg_attr(not(all(feature = "systemd", unix)), allow(unused_variables))]
+    let sink_health = sinks.spawn();
+    #[cfg(all(feature = "systemd", unix, not(any(feature = "sqlite", feature = "postgres", feature = "influx", feature = "mqtt", feature = "pipe"))))]
+    let sink_health = orderbook_server::sink::SinkHealth::default();
+    #[cfg(all(feature = "systemd", unix))]
+    orderbook_server::systemd::spawn_supervision(feeds.iter().map(|(feed, _)| feed.clone()).collect(), sink_health.clone(), system_clock());
+    #[cfg(feature = "prometheus")]
+    // the market gauges follow the feeds, started above
+    orderbook_server::prometheus::spawn_exporter(PROMETHEUS_PORT)?;
     server.serve(port).await
 }
\ No newline at end of file
@@ -2,16 +2,40 @@
 //! them in an aggregate trading book and delivering snapshots of the
 //! aggregate book via an output [stream](Stream).
 
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant, SystemTime};
 use futures::stream::Stream;
-use rust_decimal::prelude::ToPrimitive;
+use log::{debug, info, warn};
+use rust_decimal::{Decimal, prelude::ToPrimitive};
 
 use crate::core::*;
+use crate::adaptive_depth::AdaptiveDepth;
 use crate::aggregator::AggregateBook;
-use crate::exchange::ExchangeDataStream;
+use crate::analytics::{FairValueCalculator, FairValueMethod};
+use crate::book_diff;
+use crate::clock::{system_clock, SharedClock, Sleep};
+use crate::cross_check::CrossCheck;
+use crate::crash_dump::{format_book, write_dump};
+use crate::event_bus::{BookEventStream, EventBus};
+use crate::exchange::{ExchangeDataStream, ExchangeEvent};
+use crate::latency_budget;
+use crate::latency_budget::PARSE_TIMINGS;
+use crate::maintenance::MaintenanceSchedule;
+use crate::metrics::{CROSS_CHECK_DISCREPANCIES, SUPPRESSED_DUPLICATES};
+use crate::numbers::round_significant;
+use crate::proto_ext::{Timestamp, TimestampExt};
+use crate::staleness::{StalenessScorer, StalenessThresholds};
+use crate::summary_fields::SummaryFields;
+use crate::summary_log::{format_summary, SummaryLogSampling, SummarySampler};
+use crate::symbols::canonical_symbol;
 
-use crate::orderbook::{Summary, Level};
+use crate::orderbook::{Summary, Level, LatencyClass, DepthBand, FairValue, StreamQuality, StreamStatus, StreamStatusReason};
+use crate::orderbook::summary::Frame;
 
 /// Conversion from internal exchange price level to protobuf type.
 impl From<&ExchangeLevel> for Level {
@@ -20,17 +44,108 @@ impl From<&ExchangeLevel> for Level {
             exchange: value.exchange_code.to_string(),
             price: value.price.to_f64().unwrap(),
             amount: value.amount.to_f64().unwrap(),
+            order_count: value.order_count,
+            latency_class: LatencyClass::Unknown as i32,
         }
     }
 }
 
+/// Cause of the last change applied to the aggregate book.
+#[derive(PartialEq, Debug, Clone)]
+pub enum BookChange {
+    /// Snapshot received from an exchange.
+    Update(ExchangeId),
+    /// First snapshot received from an exchange after a reconnection.
+    Reconnection(ExchangeId),
+    /// Removal of all the levels from an exchange which lost its connection.
+    Ejection(ExchangeId),
+}
+
+/// Interval at which the last known summary is published again while no exchange contributes.
+const STALE_SUMMARY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Source of the [exchange events](ExchangeEvent) consumed by a [BookSummaryService](BookSummaryService).
+enum BookEventSource {
+    /// Connections to the exchanges, owned by the service.
+    Exchanges(Pin<Box<ExchangeDataStream<BookUpdate>>>),
+    /// Subscription to the book events of an [EventBus](EventBus), whose exchanges are
+    /// connected independently of the service.
+    Bus(BookEventStream),
+}
+
 /// Service providing a stream a consolidated book snapshots, one for each update
-/// received from `book_update_stream`.
+/// received from its exchanges.
 pub struct BookSummaryService {
-    /// An object representing a merged stream of trading book snapshots.
-    book_update_stream: Pin<Box<ExchangeDataStream<BookUpdate>>>,
+    /// Canonical symbol of the currency pair, included in each summary.
+    symbol: String,
+    /// The source of the trading book snapshots.
+    book_events: BookEventSource,
     /// The aggregate book where all the trading book snapshots are consolidated.
     aggregate_book: AggregateBook,
+    /// Levels of each side published, at most the [NUM_LEVELS](NUM_LEVELS) maintained in the
+    /// aggregate book.
+    depth: usize,
+    /// Whether the aggregate book maintains all the levels received.
+    full_depth: bool,
+    /// Distances from the mid price, in basis points, for which the total depth is published.
+    depth_bands_bps: Vec<u32>,
+    /// If true, no summary is published while an exchange which (re)connected has not yet
+    /// delivered its first snapshot.
+    wait_for_snapshots: bool,
+    /// Exchanges which (re)connected and did not deliver a snapshot yet.
+    awaiting_snapshot: HashSet<ExchangeId>,
+    /// Hash of the last update applied for each exchange, to detect duplicates.
+    last_update_hashes: HashMap<ExchangeId, u64>,
+    /// Exchanges which delivered at least one snapshot.
+    seen_exchanges: HashSet<ExchangeId>,
+    /// Exchanges which delivered a snapshot since they were last ejected.
+    contributing_exchanges: HashSet<ExchangeId>,
+    /// Number of exchanges configured.
+    expected_exchanges: usize,
+    /// Maintenance windows, during which the exchanges not delivering data are not expected.
+    maintenance: MaintenanceSchedule,
+    /// Exchanges which reconnected after delivering snapshots, and did not deliver a new one yet.
+    reconnecting: HashSet<ExchangeId>,
+    /// Cause of the last change applied to the aggregate book.
+    last_change: Option<BookChange>,
+    /// Selects the published summaries which are logged, if any.
+    summary_sampler: Option<SummarySampler>,
+    /// Optional fields included in the summaries.
+    summary_fields: SummaryFields,
+    /// Whether the mutations of the aggregate book are written to the [book diff log](book_diff)
+    /// while it is running for the symbol.
+    book_diff_log: bool,
+    /// Levels published depending on the spread, all the levels of the aggregate book if [None](None).
+    adaptive_depth: Option<AdaptiveDepth>,
+    /// Significant digits to which the published numbers are rounded, [None](None) for no rounding.
+    significant_digits: Option<u32>,
+    /// Calculators of the fair values included in the summaries.
+    fair_values: Vec<FairValueCalculator>,
+    /// Latency class of each exchange, annotating its levels.
+    staleness: StalenessScorer,
+    /// Naive book compared with the aggregate book after each change, if cross-checking.
+    cross_check: Option<CrossCheck>,
+    /// Whether the summaries carry their [latency budget](latency_budget).
+    latency_budget: bool,
+    /// Time source of the smoothed fair values and of the latency classes.
+    clock: SharedClock,
+    /// Whether [status frames](StreamStatus) are published when the quality of the stream changes.
+    status_frames: bool,
+    /// Exchanges which delivered snapshots and were then dropped or are resyncing, with the reason.
+    degraded_venues: HashMap<ExchangeId, StreamStatusReason>,
+    /// Whether more than half of the expected exchanges contribute, [None](None) until they first do.
+    quorum: Option<bool>,
+    /// Status frames and summaries not yet delivered, the frames preceding the summary of the
+    /// same event.
+    pending: VecDeque<Summary>,
+    /// How long the last known summary is published, marked stale, once no exchange contributes,
+    /// [None](None) to publish the empty book.
+    stale_grace: Option<Duration>,
+    /// Last summary published while an exchange contributed, with the time of its publication.
+    last_live_summary: Option<(Summary, tokio::time::Instant)>,
+    /// Since when no exchange contributes, with the timer of the next stale summary, while
+    /// the last known summary is published.
+    stale_since: Option<(tokio::time::Instant, Sleep)>,
 }
 
 impl  BookSummaryService {
@@ -38,58 +153,677 @@ impl  BookSummaryService {
     ///
     /// # Arguments
     ///
+    /// * `product` - The currency pair traded.
+    ///
     /// * `book_update_stream` - An object of type [BookUpdateStream](ExchangeDataStream).
     ///
     /// # Returns
     ///
     /// An instance of [BookSummaryService](BookSummaryService)
-    pub fn new(book_update_stream: ExchangeDataStream<BookUpdate>) -> Self {
-        let aggregate_book = AggregateBook::new(NUM_LEVELS);
-        Self { book_update_stream: Box::pin(book_update_stream), aggregate_book }
+    pub fn new(product: &CurrencyPair, book_update_stream: ExchangeDataStream<BookUpdate>) -> Self {
+        let expected_exchanges = book_update_stream.exchange_count();
+        Self::with_source(product, BookEventSource::Exchanges(Box::pin(book_update_stream)), expected_exchanges)
     }
 
-    /// Disconnect from all exchanges, it consumes the service.
-    pub async fn disconnect(self) {
-        let book_update_stream: Box<ExchangeDataStream<BookUpdate>> = Pin::into_inner(self.book_update_stream);
-        book_update_stream.disconnect().await;
+    /// Create a new instance of the service, subscribed to the book events of an [EventBus](EventBus)
+    /// rather than connected to the exchanges.
+    ///
+    /// # Arguments
+    ///
+    /// * `product` - The currency pair traded.
+    ///
+    /// * `bus` - The [EventBus](EventBus) where the book events are published.
+    ///
+    /// * `expected_exchanges` - The number of exchanges publishing book events.
+    ///
+    /// # Returns
+    ///
+    /// An instance of [BookSummaryService](BookSummaryService)
+    pub fn from_bus(product: &CurrencyPair, bus: &EventBus, expected_exchanges: usize) -> Self {
+        Self::with_source(product, BookEventSource::Bus(bus.book_events()), expected_exchanges)
+    }
+
+    /// Internal function creating a new instance of the service consuming a source of events.
+    fn with_source(product: &CurrencyPair, book_events: BookEventSource, expected_exchanges: usize) -> Self {
+        Self {
+            symbol: canonical_symbol(product),
+            book_events,
+            aggregate_book: AggregateBook::new(NUM_LEVELS),
+            depth: NUM_LEVELS,
+            full_depth: false,
+            depth_bands_bps: vec![],
+            wait_for_snapshots: false,
+            awaiting_snapshot: HashSet::new(),
+            last_update_hashes: HashMap::new(),
+            seen_exchanges: HashSet::new(),
+            contributing_exchanges: HashSet::new(),
+            expected_exchanges,
+            maintenance: MaintenanceSchedule::default(),
+            reconnecting: HashSet::new(),
+            last_change: None,
+            summary_sampler: None,
+            summary_fields: SummaryFields::default(),
+            book_diff_log: false,
+            adaptive_depth: None,
+            significant_digits: None,
+            fair_values: vec![],
+            staleness: StalenessScorer::default(),
+            cross_check: None,
+            latency_budget: false,
+            clock: system_clock(),
+            status_frames: false,
+            degraded_venues: HashMap::new(),
+            quorum: None,
+            pending: VecDeque::new(),
+            stale_grace: None,
+            last_live_summary: None,
+            stale_since: None,
+        }
+    }
+
+    /// Suppress publishing while any exchange which (re)connected has not yet delivered
+    /// its first snapshot, to avoid publishing a flood of partial books when several
+    /// exchanges reconnect at the same time.
+    ///
+    /// # Arguments
+    ///
+    /// * `wait_for_snapshots` - Whether to suppress publishing.
+    ///
+    /// # Returns
+    ///
+    /// The modified [BookSummaryService](BookSummaryService).
+    pub fn with_wait_for_snapshots(mut self, wait_for_snapshots: bool) -> Self {
+        self.wait_for_snapshots = wait_for_snapshots;
+        self
+    }
+
+    /// Maintain all the levels received from the exchanges, which deliver their complete books,
+    /// rather than the best ones only: sweeps and depth are computed against full depth, while
+    /// only the best levels are published.
+    ///
+    /// # Arguments
+    ///
+    /// * `full_depth` - Whether the exchanges deliver their complete books.
+    ///
+    /// # Returns
+    ///
+    /// The modified [BookSummaryService](BookSummaryService).
+    pub fn with_full_depth(mut self, full_depth: bool) -> Self {
+        self.full_depth = full_depth;
+        self.aggregate_book = self.make_aggregate_book();
+        self
+    }
+
+    /// Publish fewer levels of each side than the [NUM_LEVELS](NUM_LEVELS) maintained, e.g. as
+    /// requested by a client. The aggregate book still maintains all of them, so that the depth
+    /// bands and the fair values are computed on the whole book.
+    ///
+    /// # Arguments
+    ///
+    /// * `depth` - The levels of each side, capped at [NUM_LEVELS](NUM_LEVELS).
+    ///
+    /// # Returns
+    ///
+    /// The modified [BookSummaryService](BookSummaryService).
+    pub fn with_depth(mut self, depth: usize) -> Self {
+        self.depth = depth.min(NUM_LEVELS);
+        self
+    }
+
+    /// Internal function creating an empty aggregate book, as set for the service.
+    fn make_aggregate_book(&self) -> AggregateBook {
+        let aggregate_book = if self.full_depth { AggregateBook::full_depth(NUM_LEVELS) } else { AggregateBook::new(NUM_LEVELS) };
+        aggregate_book.with_depth_bands(self.depth_bands_bps.clone())
+    }
+
+    /// Publish the total amount available on each side within some distances from the mid price.
+    ///
+    /// # Arguments
+    ///
+    /// * `depth_bands_bps` - The distances from the mid price, in basis points.
+    ///
+    /// # Returns
+    ///
+    /// The modified [BookSummaryService](BookSummaryService).
+    pub fn with_depth_bands(mut self, depth_bands_bps: Vec<u32>) -> Self {
+        self.depth_bands_bps = depth_bands_bps;
+        self.aggregate_book = self.make_aggregate_book();
+        self
+    }
+
+    /// Include only some of the optional fields in the summaries, the others taking their
+    /// default values.
+    ///
+    /// # Arguments
+    ///
+    /// * `summary_fields` - The [SummaryFields](SummaryFields) to include.
+    ///
+    /// # Returns
+    ///
+    /// The modified [BookSummaryService](BookSummaryService).
+    pub fn with_summary_fields(mut self, summary_fields: SummaryFields) -> Self {
+        self.summary_fields = summary_fields;
+        self
+    }
+
+    /// Log a sample of the published summaries at INFO level, on a single compact line.
+    ///
+    /// # Arguments
+    ///
+    /// * `sampling` - The [SummaryLogSampling](SummaryLogSampling) rate.
+    ///
+    /// * `clock` - Time source for interval sampling.
+    ///
+    /// # Returns
+    ///
+    /// The modified [BookSummaryService](BookSummaryService).
+    pub fn with_summary_log(mut self, sampling: SummaryLogSampling, clock: SharedClock) -> Self {
+        self.summary_sampler = Some(SummarySampler::new(sampling, clock));
+        self
+    }
+
+    /// Write the mutations of the aggregate book to the [book diff log](book_diff), while it is
+    /// running for the symbol. Only one service of the symbol should, e.g. the one of its feed.
+    ///
+    /// # Arguments
+    ///
+    /// * `book_diff_log` - Whether to write the mutations.
+    ///
+    /// # Returns
+    ///
+    /// The modified [BookSummaryService](BookSummaryService).
+    pub fn with_book_diff_log(mut self, book_diff_log: bool) -> Self {
+        self.book_diff_log = book_diff_log;
+        self
+    }
+
+    /// Publish few levels while the spread is narrow, and more as it widens.
+    ///
+    /// # Arguments
+    ///
+    /// * `adaptive_depth` - The [AdaptiveDepth](AdaptiveDepth) configuration.
+    ///
+    /// # Returns
+    ///
+    /// The modified [BookSummaryService](BookSummaryService).
+    pub fn with_adaptive_depth(mut self, adaptive_depth: AdaptiveDepth) -> Self {
+        self.adaptive_depth = Some(adaptive_depth);
+        self
+    }
+
+    /// Round the published prices, amounts, spread and depth to significant digits, so that
+    /// clients do not see noise in the last digits of the floating point numbers.
+    ///
+    /// # Arguments
+    ///
+    /// * `significant_digits` - The number of significant digits.
+    ///
+    /// # Returns
+    ///
+    /// The modified [BookSummaryService](BookSummaryService).
+    pub fn with_significant_digits(mut self, significant_digits: u32) -> Self {
+        self.significant_digits = Some(significant_digits);
+        self
+    }
+
+    /// Do not count the exchanges in a maintenance window among the expected exchanges of the
+    /// summaries, unless they deliver data anyway.
+    ///
+    /// # Arguments
+    ///
+    /// * `maintenance` - The [MaintenanceSchedule](MaintenanceSchedule).
+    ///
+    /// # Returns
+    ///
+    /// The modified [BookSummaryService](BookSummaryService).
+    pub fn with_maintenance(mut self, maintenance: MaintenanceSchedule) -> Self {
+        self.maintenance = maintenance;
+        self
+    }
+
+    /// Include fair values of the aggregate book in the summaries.
+    ///
+    /// # Arguments
+    ///
+    /// * `methods` - The [methods](FairValueMethod) of calculation, in the order published.
+    ///
+    /// * `clock` - The time source of the smoothed methods.
+    ///
+    /// # Returns
+    ///
+    /// The modified [BookSummaryService](BookSummaryService).
+    pub fn with_fair_values(mut self, methods: &[FairValueMethod], clock: SharedClock) -> Self {
+        self.fair_values = methods.iter().copied().map(FairValueCalculator::new).collect();
+        self.clock = clock;
+        self
+    }
+
+    /// Thresholds of the latency classes annotating the levels of each exchange.
+    ///
+    /// # Arguments
+    ///
+    /// * `thresholds` - The [StalenessThresholds](StalenessThresholds).
+    ///
+    /// # Returns
+    ///
+    /// The modified [BookSummaryService](BookSummaryService).
+    pub fn with_staleness_thresholds(mut self, thresholds: StalenessThresholds) -> Self {
+        self.staleness = StalenessScorer::new(thresholds);
+        self
     }
 
-    /// Extract a protobuf message [Summary](Summary) from the current state of an aggregate book (static method).
+    /// Maintain a naive book alongside the aggregate book, rebuilt from the latest book of each
+    /// exchange, and log the price levels where the two differ after each change. Meant for
+    /// exchanges delivering their complete books, see [cross_check](crate::cross_check).
     ///
     /// # Arguments
     ///
-    /// * `aggregate_book` - A reference to an [aggregate book](AggregateBook).
+    /// * `cross_check` - Whether to cross-check the aggregate book.
+    ///
+    /// # Returns
+    ///
+    /// The modified [BookSummaryService](BookSummaryService).
+    pub fn with_cross_check(mut self, cross_check: bool) -> Self {
+        self.cross_check = cross_check.then(|| CrossCheck::new(NUM_LEVELS));
+        self
+    }
+
+    /// Attach a [latency budget](latency_budget) to each summary produced by an exchange event,
+    /// timing its aggregation, to be completed on delivery.
+    ///
+    /// # Arguments
+    ///
+    /// * `latency_budget` - Whether the summaries carry their latency budget.
+    ///
+    /// # Returns
+    ///
+    /// The modified [BookSummaryService](BookSummaryService).
+    pub fn with_latency_budget(mut self, latency_budget: bool) -> Self {
+        self.latency_budget = latency_budget;
+        self
+    }
+
+    /// Publish a [status frame](StreamStatus), a summary with the symbol and the status only,
+    /// whenever the stream degrades or recovers: an exchange which delivered snapshots is dropped,
+    /// is resyncing after a reconnection, or is restored by its first snapshot, and the quorum of
+    /// more than half of the expected exchanges is lost or restored.
+    ///
+    /// # Arguments
+    ///
+    /// * `status_frames` - Whether to publish the status frames.
+    ///
+    /// # Returns
+    ///
+    /// The modified [BookSummaryService](BookSummaryService).
+    pub fn with_status_frames(mut self, status_frames: bool) -> Self {
+        self.status_frames = status_frames;
+        self
+    }
+
+    /// Keep publishing the last known summary, marked stale with its age, for a grace period
+    /// once all the exchanges are disconnected, rather than the empty book: it is published
+    /// again every second until an exchange delivers a snapshot. When the grace period expires,
+    /// a [status frame](StreamStatus) with reason [StaleExpired](StreamStatusReason::StaleExpired)
+    /// is published, whatever the [status frames](BookSummaryService::with_status_frames), so
+    /// that the client streams can be ended with an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `stale_grace` - The grace period, [None](None) to publish the empty book.
+    ///
+    /// # Returns
+    ///
+    /// The modified [BookSummaryService](BookSummaryService).
+    pub fn with_stale_grace(mut self, stale_grace: Option<Duration>) -> Self {
+        self.stale_grace = stale_grace;
+        self
+    }
+
+    /// Cause of the change reflected in the last published summary.
+    ///
+    /// # Returns
+    ///
+    /// An optional [BookChange](BookChange), [None](None) if nothing has been published yet.
+    pub fn last_change(&self) -> Option<BookChange> {
+        self.last_change.clone()
+    }
+
+    /// The aggregate book reflected in the last published summary.
+    ///
+    /// # Returns
+    ///
+    /// A reference to the [AggregateBook](AggregateBook).
+    pub fn aggregate_book(&self) -> &AggregateBook {
+        &self.aggregate_book
+    }
+
+    /// Disconnect from all exchanges, or unsubscribe from the [EventBus](EventBus), it consumes
+    /// the service.
+    pub async fn disconnect(self) {
+        match self.book_events {
+            BookEventSource::Exchanges(book_update_stream) => Pin::into_inner(book_update_stream).disconnect().await,
+            BookEventSource::Bus(_) => (),
+        }
+    }
+
+    /// Extract a protobuf message [Summary](Summary) from the current state of the aggregate book,
+    /// with the levels published depending on the [adaptive depth](AdaptiveDepth) if any, the
+    /// optional [fields](SummaryFields) and [fair values](FairValueMethod) included, and the
+    /// numbers rounded to the significant digits if set.
     ///
     /// # Returns
     ///
     /// An instance of [Summary](Summary) object.
-    fn make_summary(aggregate_book: &AggregateBook) -> Summary {
+    fn make_summary(&mut self) -> Summary {
+        let now = self.clock.now();
+        let significant_digits = self.significant_digits;
+        let aggregate_book = &self.aggregate_book;
+        let fair_values = self.fair_values.iter_mut()
+            .filter_map(|calculator| calculator.update(aggregate_book, now).map(|value| FairValue {
+                method: calculator.method().to_string(),
+                value: round_significant(value, significant_digits),
+            }))
+            .collect();
+        let aggregate_book = &self.aggregate_book;
+        let fields = &self.summary_fields;
+        let staleness = &self.staleness;
+        let published_levels = self.adaptive_depth
+            .map(|adaptive_depth| adaptive_depth.levels(aggregate_book.spread(), aggregate_book.mid_price()))
+            .unwrap_or(usize::MAX)
+            .min(self.depth);
+        let round = |value: Decimal| round_significant(value, self.significant_digits);
         let best_bids = aggregate_book.best_bids();
         let best_asks = aggregate_book.best_asks();
-        let bids: Vec<Level> = best_bids.iter().map(|&l| l.into()).collect();
-        let asks: Vec<Level> = best_asks.iter().map(|&l| l.into()).collect();
-        let spread = if best_bids.is_empty() || best_asks.is_empty() {
+        let level = |l: &&ExchangeLevel| Level {
+            exchange: if fields.venues { l.exchange_code.to_string() } else { String::new() },
+            price: round(l.price),
+            amount: round(l.amount),
+            order_count: l.order_count,
+            latency_class: if fields.latency { staleness.class(&l.exchange_code, now) as i32 } else { LatencyClass::Unknown as i32 },
+        };
+        let bids: Vec<Level> = best_bids.iter().take(published_levels).map(level).collect();
+        let asks: Vec<Level> = best_asks.iter().take(published_levels).map(level).collect();
+        let spread = if !fields.spread {
+            0.0
+        } else if best_bids.is_empty() || best_asks.is_empty() {
             f64::NAN
         } else {
-            (best_asks[0].price - best_bids[0].price).to_f64().unwrap_or(f64::NAN)
+            round(best_asks[0].price - best_bids[0].price)
+        };
+        let depth_bands_bps: &[u32] = if fields.depth { &self.depth_bands_bps } else { &[] };
+        let depth = depth_bands_bps.iter()
+            .filter_map(|&bps| aggregate_book.depth_within(bps).map(|(bid_amount, ask_amount)| DepthBand {
+                bps,
+                bid_amount: round(bid_amount),
+                ask_amount: round(ask_amount),
+            }))
+            .collect();
+        Summary {
+            spread,
+            bids,
+            asks,
+            symbol: self.symbol.clone(),
+            depth,
+            contributing_exchanges: if fields.exchange_counts { self.contributing_exchanges.len() as u32 } else { 0 },
+            expected_exchanges: if fields.exchange_counts { self.expected_exchange_count() as u32 } else { 0 },
+            fair_values,
+            latency_budget: None,
+            frame: None,
+            stale: false,
+            stale_age_ms: 0,
+        }
+    }
+
+    /// Internal function making a copy of the last known summary, marked stale with its age.
+    fn make_stale_summary(&self) -> Option<Summary> {
+        let now = self.clock.now();
+        self.last_live_summary.as_ref().map(|(summary, published)| Summary {
+            stale: true,
+            stale_age_ms: now.saturating_duration_since(*published).as_millis() as u64,
+            ..summary.clone()
+        })
+    }
+
+    /// Internal function publishing the last known summary again, once the timer of the stale
+    /// summaries completes, or the frame notifying that the grace period expired.
+    fn poll_stale(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let (Some(grace), Some((since, timer))) = (self.stale_grace, self.stale_since.as_mut()) else {
+            return Poll::Pending;
         };
-        Summary { spread, bids, asks }
+        if timer.as_mut().poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+        let since = *since;
+        if self.clock.now().saturating_duration_since(since) >= grace {
+            warn!("No exchange of {} contributed for {:?}", self.symbol, grace);
+            self.stale_since = None;
+            self.last_live_summary = None;
+            let frame = self.status_frame(StreamQuality::Degraded, StreamStatusReason::StaleExpired, "");
+            self.pending.push_back(frame);
+        } else {
+            self.stale_since = Some((since, self.clock.sleep(STALE_SUMMARY_INTERVAL)));
+            if let Some(summary) = self.make_stale_summary() {
+                self.pending.push_back(summary);
+            }
+        }
+        Poll::Ready(())
     }
 
-    /// Apply a [book update](BookUpdate) object if available, and return an up-to-date [Summary](Summary) object.
+    /// Internal function recording the state of an exchange which delivered snapshots, and the
+    /// quorum of the contributing exchanges, queuing a [status frame](StreamStatus) for each change
+    /// if they are published.
     ///
     /// # Arguments
     ///
-    /// * `maybe_book_update` - An optional [BookUpdate](BookUpdate)
+    /// * `exchange_code` - The exchange.
+    ///
+    /// * `degradation` - Why the exchange is degraded, [None](None) if it delivers snapshots.
+    fn track_status(&mut self, exchange_code: &ExchangeId, degradation: Option<StreamStatusReason>) {
+        if !self.status_frames {
+            return;
+        }
+        let previous = match degradation {
+            Some(reason) => self.degraded_venues.insert(exchange_code.clone(), reason),
+            None => self.degraded_venues.remove(exchange_code),
+        };
+        if previous != degradation {
+            self.queue_status(degradation.unwrap_or(StreamStatusReason::VenueRestored), exchange_code);
+        }
+        let quorum = self.contributing_exchanges.len() * 2 > self.expected_exchange_count();
+        match self.quorum {
+            Some(previous) if previous != quorum => {
+                self.quorum = Some(quorum);
+                self.queue_status(if quorum { StreamStatusReason::QuorumRestored } else { StreamStatusReason::QuorumLost }, "");
+            },
+            None if quorum => self.quorum = Some(true),
+            _ => (),
+        }
+    }
+
+    /// Internal function queuing a [status frame](StreamStatus), the stream being degraded while
+    /// any exchange is, or the quorum is lost.
+    fn queue_status(&mut self, reason: StreamStatusReason, exchange_code: &str) {
+        let quality = if self.degraded_venues.is_empty() && self.quorum != Some(false) {
+            StreamQuality::Healthy
+        } else {
+            StreamQuality::Degraded
+        };
+        info!("Stream of {} {:?} after {:?} {}", self.symbol, quality, reason, exchange_code);
+        let frame = self.status_frame(quality, reason, exchange_code);
+        self.pending.push_back(frame);
+    }
+
+    /// Internal function making a [status frame](StreamStatus), with the current exchange counts.
+    fn status_frame(&self, quality: StreamQuality, reason: StreamStatusReason, exchange_code: &str) -> Summary {
+        let status = StreamStatus {
+            quality: quality as i32,
+            reason: reason as i32,
+            exchange: exchange_code.to_string(),
+            contributing_exchanges: self.contributing_exchanges.len() as u32,
+            expected_exchanges: self.expected_exchange_count() as u32,
+            time: Some(Timestamp::now()),
+        };
+        Summary { symbol: self.symbol.clone(), frame: Some(Frame::Status(status)), ..Default::default() }
+    }
+
+    /// Internal function counting the exchanges expected, those configured except the ones in
+    /// a maintenance window which do not contribute.
+    fn expected_exchange_count(&self) -> usize {
+        let offline = self.maintenance.offline_count(SystemTime::now(), |exchange| self.contributing_exchanges.contains(exchange));
+        self.expected_exchanges.saturating_sub(offline)
+    }
+
+    /// Check if a [book update](BookUpdate) is identical to the previous one from the
+    /// same exchange, and remember it for the next check.
+    ///
+    /// # Arguments
+    ///
+    /// * `book_update` - A reference to a [BookUpdate](BookUpdate).
     ///
     /// # Returns
     ///
-    /// An instance of [Summary](Summary) object.
-    fn update_and_make_summary(&mut self, maybe_book_update: Option<BookUpdate>) -> Summary {
-        if let Some(book_update) = maybe_book_update {
-            self.aggregate_book.update(book_update);
+    /// A [boolean](bool) value: [true](true) if the update is a duplicate.
+    fn is_duplicate(&mut self, book_update: &BookUpdate) -> bool {
+        let mut hasher = DefaultHasher::new();
+        book_update.hash(&mut hasher);
+        let hash = hasher.finish();
+        self.last_update_hashes.insert(book_update.exchange_code.clone(), hash) == Some(hash)
+    }
+
+    /// Internal function capturing the levels of an exchange before a mutation of the aggregate
+    /// book, if the mutations are being logged.
+    fn venue_levels_to_diff(&self, exchange_code: &str) -> Option<BTreeMap<(Side, Decimal), Decimal>> {
+        (self.book_diff_log && book_diff::is_logging(&self.symbol))
+            .then(|| book_diff::venue_levels(&self.aggregate_book, exchange_code))
+    }
+
+    /// Internal function logging the mutation of the levels of an exchange, captured by
+    /// [venue_levels_to_diff](BookSummaryService::venue_levels_to_diff).
+    fn record_diff(&self, exchange_code: &str, before: Option<BTreeMap<(Side, Decimal), Decimal>>) {
+        if let Some(before) = before {
+            book_diff::record(&self.symbol, exchange_code, &book_diff::diff(&before, &self.aggregate_book, exchange_code));
+        }
+    }
+
+    /// Internal function comparing the aggregate book with the naive book, if cross-checking,
+    /// and logging the discrepancies.
+    fn check_consolidation(&self) {
+        if let Some(cross_check) = &self.cross_check {
+            for discrepancy in cross_check.compare(&self.aggregate_book) {
+                warn!("Cross-check of {} after {:?}: {}", self.symbol, self.last_change, discrepancy);
+                CROSS_CHECK_DISCREPANCIES.increment(match discrepancy.side {
+                    Side::Buy => "bids",
+                    Side::Sell => "asks",
+                });
+            }
+        }
+    }
+
+    /// Apply an [exchange event](ExchangeEvent) if available, and return an up-to-date [Summary](Summary) object.
+    /// A [book update](BookUpdate) is applied to the aggregate book, unless identical to the
+    /// previous one from the same exchange, while a disconnection removes all the levels
+    /// from the exchange.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - An [ExchangeEvent](ExchangeEvent)
+    ///
+    /// # Returns
+    ///
+    /// An optional instance of [Summary](Summary) object: none if the event did not change the
+    /// aggregate book, or if publishing is suppressed.
+    fn update_and_make_summary(&mut self, event: ExchangeEvent<BookUpdate>) -> Option<Summary> {
+        let started = self.latency_budget.then(Instant::now);
+        let parse_timing = match &event {
+            ExchangeEvent::Data(book_update) if self.latency_budget => PARSE_TIMINGS.get(&book_update.exchange_code),
+            _ => None,
+        };
+        match event {
+            ExchangeEvent::Data(book_update) => {
+                let exchange_code = book_update.exchange_code.clone();
+                self.staleness.record(&exchange_code, self.clock.now());
+                self.awaiting_snapshot.remove(&exchange_code);
+                if !self.contributing_exchanges.contains(&exchange_code) {
+                    self.contributing_exchanges.insert(exchange_code.clone());
+                }
+                self.track_status(&exchange_code, None);
+                if self.stale_since.take().is_some() {
+                    info!("Stream of {} live again after {}", self.symbol, exchange_code);
+                }
+                if self.is_duplicate(&book_update) {
+                    debug!("Suppressed duplicate update from {}", exchange_code);
+                    SUPPRESSED_DUPLICATES.increment(&exchange_code);
+                    return None;
+                }
+                if !self.seen_exchanges.contains(&exchange_code) {
+                    self.seen_exchanges.insert(exchange_code.clone());
+                }
+                self.last_change = if self.reconnecting.remove(&exchange_code) {
+                    Some(BookChange::Reconnection(exchange_code.clone()))
+                } else {
+                    Some(BookChange::Update(exchange_code.clone()))
+                };
+                let before = self.venue_levels_to_diff(&exchange_code);
+                if let Some(cross_check) = self.cross_check.as_mut() {
+                    cross_check.update(book_update.clone());
+                }
+                let aggregate_book = &mut self.aggregate_book;
+                if let Err(payload) = catch_unwind(AssertUnwindSafe(|| aggregate_book.update(book_update))) {
+                    let book = catch_unwind(AssertUnwindSafe(|| format_book(aggregate_book))).ok();
+                    write_dump(&exchange_code, book.as_deref());
+                    resume_unwind(payload);
+                }
+                self.record_diff(&exchange_code, before);
+                self.check_consolidation();
+            },
+            ExchangeEvent::Connected(exchange_code) => {
+                self.last_update_hashes.remove(&exchange_code);
+                if self.seen_exchanges.contains(&exchange_code) {
+                    self.reconnecting.insert(exchange_code.clone());
+                    self.track_status(&exchange_code, Some(StreamStatusReason::VenueResyncing));
+                }
+                if self.wait_for_snapshots {
+                    self.awaiting_snapshot.insert(exchange_code);
+                }
+                return None;
+            },
+            ExchangeEvent::Disconnected(exchange_code) => {
+                self.last_update_hashes.remove(&exchange_code);
+                self.awaiting_snapshot.remove(&exchange_code);
+                self.contributing_exchanges.remove(&exchange_code);
+                if self.seen_exchanges.contains(&exchange_code) {
+                    self.track_status(&exchange_code, Some(StreamStatusReason::VenueDropped));
+                }
+                self.staleness.remove(&exchange_code);
+                let before = self.venue_levels_to_diff(&exchange_code);
+                self.aggregate_book.remove_exchange(&exchange_code);
+                self.record_diff(&exchange_code, before);
+                if let Some(cross_check) = self.cross_check.as_mut() {
+                    cross_check.remove_exchange(&exchange_code);
+                }
+                self.last_change = Some(BookChange::Ejection(exchange_code));
+                self.check_consolidation();
+                if self.stale_grace.is_some() && self.contributing_exchanges.is_empty() && self.last_live_summary.is_some() {
+                    // the last known summary rather than the empty book, once only
+                    if self.stale_since.is_some() {
+                        return None;
+                    }
+                    info!("No exchange of {} contributes, publishing the last known summary", self.symbol);
+                    self.stale_since = Some((self.clock.now(), self.clock.sleep(STALE_SUMMARY_INTERVAL)));
+                    return self.make_stale_summary();
+                }
+            },
+        }
+        if self.awaiting_snapshot.is_empty() {
+            let mut summary = self.make_summary();
+            summary.latency_budget = started.map(|started| latency_budget::start(started, parse_timing));
+            if self.stale_grace.is_some() && !self.contributing_exchanges.is_empty() {
+                self.last_live_summary = Some((summary.clone(), self.clock.now()));
+            }
+            Some(summary)
+        } else {
+            None
         }
-        Self::make_summary(&self.aggregate_book)
     }
 }
 
@@ -98,9 +832,33 @@ impl  Stream for BookSummaryService {
     type Item = Summary;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        self.book_update_stream.as_mut().poll_next(cx).map( |maybe_book_update|
-            Some(self.update_and_make_summary(maybe_book_update))
-        )
+        loop {
+            if let Some(summary) = self.pending.pop_front() {
+                return Poll::Ready(Some(summary));
+            }
+            if self.poll_stale(cx).is_ready() {
+                continue;
+            }
+            let polled = match &mut self.book_events {
+                BookEventSource::Exchanges(book_update_stream) => book_update_stream.as_mut().poll_next(cx),
+                BookEventSource::Bus(book_events) => book_events.as_mut().poll_next(cx),
+            };
+            match polled {
+                Poll::Ready(Some(event)) => {
+                    if let Some(summary) = self.update_and_make_summary(event) {
+                        if let Some(sampler) = self.summary_sampler.as_mut() {
+                            if sampler.sample() {
+                                info!("Summary #{}: {}", sampler.count(), format_summary(&summary));
+                            }
+                        }
+                        self.pending.push_back(summary);
+                    }
+                },
+                // the stream ends with its source of book events
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
     }
 }
 
@@ -0,0 +1,145 @@
+//! Settings of the server tuned by operators, read from a JSON file so that they can be
+//! changed without rebuilding.
+
+use serde::{Deserialize, Serialize};
+use tokio::time::Duration;
+
+use crate::config::{DocumentedConfig, Validate, Validator};
+use crate::exchange::DEFAULT_CONNECTION_STAGGER_MS;
+use crate::grpc::{DEFAULT_DEPTH_BANDS_BPS, DEFAULT_STALE_GRACE_S, DEFAULT_VOLATILITY_HORIZONS_MS};
+use crate::summary_log::{SummaryLogSampling, DEFAULT_SUMMARY_LOG_INTERVAL_MS};
+use crate::volatility::MIN_RETURN_INTERVAL;
+
+
+/// Settings of the server. Missing values take their defaults.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct ServerSettings {
+    /// Connection delay added for each exchange after the first, in milliseconds.
+    pub connection_stagger_ms: u64,
+    /// Distances from the mid price, in basis points, for which the total depth is published.
+    pub depth_bands_bps: Vec<u32>,
+    /// Horizons of the volatility estimates of the mid price, in milliseconds, in increasing order.
+    pub volatility_horizons_ms: Vec<u64>,
+    /// Minimum interval between logged summaries, in milliseconds.
+    pub summary_log_interval_ms: Option<u64>,
+    /// Log one summary every this many, instead of one per interval, overriding the interval.
+    pub summary_log_every_nth: Option<u64>,
+    /// Suppress publishing after (re)connections, until every exchange delivered a snapshot.
+    pub wait_for_snapshots: bool,
+    /// Grace period, in seconds, during which the last known summary of a symbol is published
+    /// once all its exchanges are disconnected, before the client streams end with an error.
+    pub stale_grace_s: Option<u64>,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            connection_stagger_ms: DEFAULT_CONNECTION_STAGGER_MS,
+            depth_bands_bps: DEFAULT_DEPTH_BANDS_BPS.to_vec(),
+            volatility_horizons_ms: DEFAULT_VOLATILITY_HORIZONS_MS.to_vec(),
+            summary_log_interval_ms: Some(DEFAULT_SUMMARY_LOG_INTERVAL_MS),
+            summary_log_every_nth: None,
+            wait_for_snapshots: true,
+            stale_grace_s: Some(DEFAULT_STALE_GRACE_S),
+        }
+    }
+}
+
+impl ServerSettings {
+    /// Rate at which published summaries are logged.
+    ///
+    /// # Returns
+    ///
+    /// The [SummaryLogSampling](SummaryLogSampling) rate, or [None](None) if neither an
+    /// interval nor a count is set.
+    pub fn summary_log_sampling(&self) -> Option<SummaryLogSampling> {
+        match (self.summary_log_every_nth, self.summary_log_interval_ms) {
+            (Some(n), _) => Some(SummaryLogSampling::EveryNth(n)),
+            (None, Some(interval_ms)) => Some(SummaryLogSampling::Interval(Duration::from_millis(interval_ms))),
+            (None, None) => None,
+        }
+    }
+
+    /// Horizons of the volatility estimates of the mid price.
+    ///
+    /// # Returns
+    ///
+    /// A [vector](Vec) of durations, in increasing order.
+    pub fn volatility_horizons(&self) -> Vec<Duration> {
+        self.volatility_horizons_ms.iter().map(|&ms| Duration::from_millis(ms)).collect()
+    }
+}
+
+impl Validate for ServerSettings {
+    fn validate(&self, validator: &mut Validator) {
+        for (i, band) in self.depth_bands_bps.iter().enumerate() {
+            validator.positive(&format!("depth_bands_bps[{}]", i), *band as u64);
+        }
+        if self.volatility_horizons_ms.is_empty() {
+            validator.error("volatility_horizons_ms", "must not be empty");
+        }
+        let min_horizon_ms = MIN_RETURN_INTERVAL.as_millis() as u64;
+        for (i, &horizon_ms) in self.volatility_horizons_ms.iter().enumerate() {
+            let field = format!("volatility_horizons_ms[{}]", i);
+            if horizon_ms <= min_horizon_ms {
+                validator.error(&field, &format!("must be above {}", min_horizon_ms));
+            } else if i > 0 && horizon_ms <= self.volatility_horizons_ms[i - 1] {
+                validator.error(&field, "must be above the previous horizon");
+            }
+        }
+        if let Some(interval_ms) = self.summary_log_interval_ms {
+            validator.positive("summary_log_interval_ms", interval_ms);
+        }
+        if let Some(stale_grace_s) = self.stale_grace_s {
+            validator.positive("stale_grace_s", stale_grace_s);
+        }
+        if let Some(n) = self.summary_log_every_nth {
+            validator.positive("summary_log_every_nth", n);
+        }
+    }
+}
+
+impl DocumentedConfig for ServerSettings {
+    const DESCRIPTION: &'static str = "Settings of the server.";
+    const FIELDS: &'static [(&'static str, &'static str)] = &[
+        ("connection_stagger_ms", "Connection delay added for each exchange after the first, in milliseconds."),
+        ("depth_bands_bps", "Distances from the mid price, in basis points, for which the total depth is published."),
+        ("volatility_horizons_ms", "Horizons of the volatility estimates of the mid price, in milliseconds, in increasing order."),
+        ("summary_log_interval_ms", "Minimum interval between logged summaries, in milliseconds, null to disable the log."),
+        ("summary_log_every_nth", "Log one summary every this many instead, overriding summary_log_interval_ms."),
+        ("wait_for_snapshots", "Suppress publishing after (re)connections, until every exchange delivered a snapshot."),
+        ("stale_grace_s", "Grace period, in seconds, during which the last known summary of a symbol is published once all its exchanges are disconnected, null to publish the empty book."),
+    ];
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_config() {
+        let settings: ServerSettings = serde_json::from_str(r#"{"connection_stagger_ms": 100}"#).unwrap();
+        assert_eq!(settings, ServerSettings { connection_stagger_ms: 100, ..ServerSettings::default() });
+        let settings: ServerSettings = serde_json::from_str("{}").unwrap();
+        assert_eq!(settings, ServerSettings::default());
+    }
+
+    #[test]
+    fn test_volatility_horizons() {
+        let settings: ServerSettings = serde_json::from_str(r#"{"volatility_horizons_ms": [500, 5000]}"#).unwrap();
+        assert_eq!(settings.volatility_horizons(), vec![Duration::from_millis(500), Duration::from_secs(5)]);
+        assert_eq!(ServerSettings::default().volatility_horizons_ms, vec![1_000, 10_000, 60_000]);
+    }
+
+    #[test]
+    fn test_summary_log_sampling() {
+        let settings = ServerSettings::default();
+        assert_eq!(settings.summary_log_sampling(), Some(SummaryLogSampling::Interval(Duration::from_secs(60))));
+        let settings: ServerSettings = serde_json::from_str(r#"{"summary_log_every_nth": 100}"#).unwrap();
+        assert_eq!(settings.summary_log_sampling(), Some(SummaryLogSampling::EveryNth(100)));
+        let settings: ServerSettings = serde_json::from_str(r#"{"summary_log_interval_ms": null}"#).unwrap();
+        assert_eq!(settings.summary_log_sampling(), None);
+    }
+}
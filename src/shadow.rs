@@ -0,0 +1,375 @@
+//! Shadow comparison of the feed of a symbol with the summary stream of a second orderbook-server
+//! instance serving the same symbol, e.g. a canary running a rewritten aggregator: each summary
+//! is matched with the same book published by the other instance, the lag between them recorded
+//! in [SHADOW_LAGS](SHADOW_LAGS), and the summaries left unmatched past a tolerance counted in
+//! [SHADOW_DIVERGENCES](SHADOW_DIVERGENCES), by kind of divergence.
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use tokio::time::{Duration, Instant};
+use tokio_stream::StreamExt;
+use tonic::Streaming;
+
+use crate::clock::SharedClock;
+use crate::config::{DocumentedConfig, Validate, Validator};
+use crate::feed::FeedReceiver;
+use crate::metrics::{SHADOW_DIVERGENCES, SHADOW_LAGS};
+use crate::orderbook::{orderbook_aggregator_client::OrderbookAggregatorClient, BookSummaryRequest, Level, Summary};
+
+
+/// URL of the other instance in the printed defaults.
+const DEFAULT_URL: &str = "http://[::1]:50052";
+/// Default time a summary waits for the same book from the other instance, in milliseconds.
+const DEFAULT_TOLERANCE_MS: u64 = 1000;
+/// Delay before reconnecting to the other instance.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+
+/// Configuration of the shadow comparison, read from a JSON file.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ShadowConfig {
+    /// URL of the Protobuf RPC server of the other instance, e.g. `http://[::1]:50052`.
+    pub url: String,
+    /// Time a summary waits for the same book from the other instance before being reported
+    /// as divergent, in milliseconds.
+    #[serde(default = "default_tolerance_ms")]
+    pub tolerance_ms: u64,
+}
+
+/// Default of [tolerance_ms](ShadowConfig::tolerance_ms).
+fn default_tolerance_ms() -> u64 {
+    DEFAULT_TOLERANCE_MS
+}
+
+impl Default for ShadowConfig {
+    fn default() -> Self {
+        Self { url: DEFAULT_URL.to_string(), tolerance_ms: DEFAULT_TOLERANCE_MS }
+    }
+}
+
+impl Validate for ShadowConfig {
+    fn validate(&self, validator: &mut Validator) {
+        validator.url("url", &self.url, &["http", "https"]);
+        validator.positive("tolerance_ms", self.tolerance_ms);
+    }
+}
+
+impl DocumentedConfig for ShadowConfig {
+    const DESCRIPTION: &'static str = "Instance the feeds are compared with, no comparison if the file is missing.";
+    const FIELDS: &'static [(&'static str, &'static str)] = &[
+        ("url", "URL of the Protobuf RPC server of the other instance."),
+        ("tolerance_ms", "Time a summary waits for the same book from the other instance before being reported as divergent, in milliseconds."),
+    ];
+}
+
+/// Instance publishing a summary.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Instance {
+    /// This server, through its feed of the symbol.
+    Local,
+    /// The other server, through its summary stream.
+    Shadow,
+}
+
+impl Instance {
+    /// Name of the instance, as a metric label.
+    pub fn name(self) -> &'static str {
+        match self {
+            Instance::Local => "local",
+            Instance::Shadow => "shadow",
+        }
+    }
+
+    /// The other instance.
+    fn other(self) -> Self {
+        match self {
+            Instance::Local => Instance::Shadow,
+            Instance::Shadow => Instance::Local,
+        }
+    }
+
+    /// Internal index of the instance.
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Kind of divergence of a summary never matched by the other instance, compared with the
+/// latest summary of the other instance.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Divergence {
+    /// The best bid or the best ask differs.
+    Price,
+    /// The same levels, in a different order, e.g. exchanges quoting the same price.
+    Ordering,
+    /// The same best prices, with different levels beyond them or different amounts.
+    Levels,
+}
+
+impl Divergence {
+    /// Name of the divergence, as a metric label.
+    pub fn name(self) -> &'static str {
+        match self {
+            Divergence::Price => "price",
+            Divergence::Ordering => "ordering",
+            Divergence::Levels => "levels",
+        }
+    }
+}
+
+/// Levels of a side of a summary compared between the instances: exchange, price and amount,
+/// regardless of the latency classes, which depend on the timing of each instance.
+type SideLevels = Vec<(String, f64, f64)>;
+
+/// Bids and asks of a summary compared between the instances.
+type BookLevels = (SideLevels, SideLevels);
+
+/// Internal function extracting the levels of a summary compared between the instances.
+fn book_levels(summary: &Summary) -> BookLevels {
+    let side = |levels: &[Level]| levels.iter()
+        .map(|level| (level.exchange.clone(), level.price, level.amount))
+        .collect();
+    (side(&summary.bids), side(&summary.asks))
+}
+
+/// Internal function classifying the divergence of a book from the latest book of the other
+/// instance, empty if none.
+fn classify(book: &BookLevels, other: Option<&BookLevels>) -> Divergence {
+    let empty = (vec![], vec![]);
+    let other = other.unwrap_or(&empty);
+    let best = |levels: &SideLevels| levels.first().map(|level| level.1);
+    if best(&book.0) != best(&other.0) || best(&book.1) != best(&other.1) {
+        return Divergence::Price;
+    }
+    let sorted = |levels: &SideLevels| {
+        let mut levels = levels.clone();
+        levels.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        levels
+    };
+    if sorted(&book.0) == sorted(&other.0) && sorted(&book.1) == sorted(&other.1) {
+        Divergence::Ordering
+    } else {
+        Divergence::Levels
+    }
+}
+
+/// A summary published by an instance and not matched by the other one yet.
+struct Pending {
+    /// Levels of the summary.
+    book: BookLevels,
+    /// When the summary was received.
+    published: Instant,
+}
+
+/// Matches the summaries published by two instances.
+pub struct ShadowComparator {
+    /// Time a summary waits for the same book from the other instance.
+    tolerance: Duration,
+    /// Summaries of each instance not matched yet, oldest first.
+    pending: [VecDeque<Pending>; 2],
+    /// Latest book of each instance.
+    latest: [Option<BookLevels>; 2],
+}
+
+impl ShadowComparator {
+    /// Create a new [ShadowComparator](ShadowComparator) object.
+    ///
+    /// # Arguments
+    ///
+    /// * `tolerance` - Time a summary waits for the same book from the other instance.
+    pub fn new(tolerance: Duration) -> Self {
+        Self { tolerance, pending: [VecDeque::new(), VecDeque::new()], latest: [None, None] }
+    }
+
+    /// Record a summary published by an instance, matching it with the same book published
+    /// earlier by the other instance, if any. The books published before it by either instance
+    /// are then superseded, e.g. conflated by a slower instance, and not reported.
+    ///
+    /// # Arguments
+    ///
+    /// * `instance` - The [Instance](Instance) publishing the summary.
+    ///
+    /// * `summary` - The [Summary](Summary).
+    ///
+    /// * `now` - When the summary was received.
+    ///
+    /// # Returns
+    ///
+    /// The lag of the instance behind the other one, if the other one published the same book
+    /// earlier.
+    pub fn record(&mut self, instance: Instance, summary: &Summary, now: Instant) -> Option<Duration> {
+        let book = book_levels(summary);
+        let other = instance.other().index();
+        let lag = match self.pending[other].iter().position(|pending| pending.book == book) {
+            Some(i) => {
+                let lag = now.saturating_duration_since(self.pending[other][i].published);
+                self.pending[other].drain(..=i);
+                self.pending[instance.index()].clear();
+                Some(lag)
+            },
+            // already in line with the other instance
+            None if self.latest[other].as_ref() == Some(&book) => None,
+            None => {
+                self.pending[instance.index()].push_back(Pending { book: book.clone(), published: now });
+                None
+            },
+        };
+        self.latest[instance.index()] = Some(book);
+        lag
+    }
+
+    /// Expire the summaries not matched by the other instance within the tolerance.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time.
+    ///
+    /// # Returns
+    ///
+    /// The [instance](Instance) and [kind of divergence](Divergence) of each summary expired.
+    pub fn expire(&mut self, now: Instant) -> Vec<(Instance, Divergence)> {
+        let mut divergences = vec![];
+        for instance in [Instance::Local, Instance::Shadow] {
+            let other = self.latest[instance.other().index()].as_ref();
+            let pending = &mut self.pending[instance.index()];
+            while let Some(oldest) = pending.front() {
+                if now.saturating_duration_since(oldest.published) < self.tolerance {
+                    break;
+                }
+                divergences.push((instance, classify(&oldest.book, other)));
+                pending.pop_front();
+            }
+        }
+        divergences
+    }
+}
+
+/// Internal function recording a summary of an instance, unless it is a status frame.
+fn record_summary(comparator: &mut ShadowComparator, instance: Instance, summary: &Summary, now: Instant) {
+    if summary.frame.is_some() {
+        return;
+    }
+    if let Some(lag) = comparator.record(instance, summary, now) {
+        SHADOW_LAGS.record(instance.name(), lag.as_millis() as u64);
+    }
+}
+
+/// Internal function opening the summary stream of a symbol on the other instance.
+async fn connect(url: &str, symbol: &str) -> Result<Streaming<Summary>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut client = OrderbookAggregatorClient::connect(url.to_string()).await?;
+    let request = BookSummaryRequest { symbol: symbol.to_string(), ..Default::default() };
+    Ok(client.book_summary(request).await?.into_inner())
+}
+
+/// Spawn a task comparing the summaries of a feed with those streamed by another instance for
+/// the same symbol, reconnecting to it after each failure, until the feed is closed.
+///
+/// # Arguments
+///
+/// * `feed` - The [FeedReceiver](FeedReceiver) of this instance.
+///
+/// * `symbol` - The canonical symbol of the feed.
+///
+/// * `config` - The [ShadowConfig](ShadowConfig).
+///
+/// * `clock` - Time source of the lags and of the tolerance.
+pub fn spawn_shadow(mut feed: FeedReceiver, symbol: String, config: ShadowConfig, clock: SharedClock) {
+    let tolerance = Duration::from_millis(config.tolerance_ms);
+    tokio::spawn(async move {
+        let mut comparator = ShadowComparator::new(tolerance);
+        loop {
+            let mut stream = match connect(&config.url, &symbol).await {
+                Ok(stream) => stream,
+                Err(error) => {
+                    warn!("Shadow instance {} not reachable: {}", config.url, error);
+                    clock.sleep(RECONNECT_DELAY).await;
+                    continue;
+                },
+            };
+            info!("Comparing the summaries of {} with the shadow instance {}", symbol, config.url);
+            let mut tick = clock.sleep(tolerance);
+            loop {
+                tokio::select! {
+                    changed = feed.changed() => {
+                        if changed.is_err() {
+                            info!("Feed closed, shadow comparison stopped");
+                            return;
+                        }
+                        let maybe_summary = feed.borrow_and_update().clone();
+                        if let Some(summary) = maybe_summary {
+                            record_summary(&mut comparator, Instance::Local, &summary, clock.now());
+                        }
+                    },
+                    message = stream.next() => match message {
+                        Some(Ok(summary)) => record_summary(&mut comparator, Instance::Shadow, &summary, clock.now()),
+                        Some(Err(status)) => {
+                            warn!("Shadow stream failed: {}", status);
+                            break;
+                        },
+                        None => {
+                            warn!("Shadow stream ended");
+                            break;
+                        },
+                    },
+                    _ = &mut tick => tick = clock.sleep(tolerance),
+                }
+                for (instance, divergence) in comparator.expire(clock.now()) {
+                    debug!("Summary of the {} instance not matched: {} divergence", instance.name(), divergence.name());
+                    SHADOW_DIVERGENCES.increment(divergence.name());
+                }
+            }
+            clock.sleep(RECONNECT_DELAY).await;
+        }
+    });
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(bids: &[(&str, f64)], asks: &[(&str, f64)]) -> Summary {
+        let levels = |levels: &[(&str, f64)]| levels.iter()
+            .map(|&(exchange, price)| Level { exchange: exchange.to_string(), price, amount: 1.0, ..Default::default() })
+            .collect();
+        Summary { bids: levels(bids), asks: levels(asks), ..Default::default() }
+    }
+
+    #[test]
+    fn test_matching() {
+        let start = Instant::now();
+        let ms = |ms| start + Duration::from_millis(ms);
+        let mut comparator = ShadowComparator::new(Duration::from_millis(100));
+        let first = summary(&[("binance", 10.0)], &[("bitstamp", 11.0)]);
+        let second = summary(&[("binance", 10.5)], &[("bitstamp", 11.0)]);
+        assert_eq!(comparator.record(Instance::Local, &first, start), None);
+        assert_eq!(comparator.record(Instance::Local, &second, ms(10)), None);
+        // the shadow instance conflated the first book
+        assert_eq!(comparator.record(Instance::Shadow, &second, ms(30)), Some(Duration::from_millis(20)));
+        assert_eq!(comparator.expire(ms(500)), vec![]);
+        // the local instance lagging
+        assert_eq!(comparator.record(Instance::Shadow, &first, ms(600)), None);
+        assert_eq!(comparator.record(Instance::Local, &first, ms(650)), Some(Duration::from_millis(50)));
+        assert_eq!(comparator.record(Instance::Shadow, &first, ms(660)), None);
+        assert_eq!(comparator.expire(ms(1000)), vec![]);
+    }
+
+    #[test]
+    fn test_divergences() {
+        let start = Instant::now();
+        let ms = |ms| start + Duration::from_millis(ms);
+        let mut comparator = ShadowComparator::new(Duration::from_millis(100));
+        comparator.record(Instance::Local, &summary(&[("binance", 10.0), ("bitstamp", 10.0)], &[("bitstamp", 11.0)]), start);
+        comparator.record(Instance::Shadow, &summary(&[("bitstamp", 10.0), ("binance", 10.0)], &[("bitstamp", 11.0)]), ms(10));
+        assert_eq!(comparator.expire(ms(50)), vec![]);
+        assert_eq!(comparator.expire(ms(100)), vec![(Instance::Local, Divergence::Ordering)]);
+        comparator.record(Instance::Local, &summary(&[("binance", 10.0)], &[("bitstamp", 11.0)]), ms(120));
+        comparator.record(Instance::Local, &summary(&[("binance", 9.0)], &[("bitstamp", 11.0)]), ms(130));
+        assert_eq!(comparator.expire(ms(230)), vec![
+            (Instance::Local, Divergence::Levels),
+            (Instance::Local, Divergence::Price),
+            (Instance::Shadow, Divergence::Price),
+        ]);
+    }
+}
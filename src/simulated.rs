@@ -0,0 +1,297 @@
+//! Simulated exchange: a local `WebSocket` server publishing scripted trading book
+//! snapshots, used to exercise the whole pipeline without connecting to real exchanges.
+//! Snapshots use the same format as Binance partial book depth streams.
+
+use futures::{SinkExt, StreamExt};
+use log::{debug, error, info};
+use rust_decimal::prelude::*;
+use serde::Deserialize;
+use std::{net::SocketAddr, sync::{Arc, Mutex}};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, watch};
+use tokio_tungstenite::{accept_async, tungstenite::protocol::Message};
+
+use crate::capabilities::ExchangeCapabilities;
+use crate::core::*;
+use crate::exchange::{ExchangeAdapter, ExchangeProtocol, SubscriptionRequest};
+use crate::symbols::canonical_symbol;
+
+
+/// Exchange code of the simulated exchange.
+pub const SIMULATED_CODE: &str = "simulated";
+/// Message requesting the adapter to reconnect.
+const RECONNECT_MESSAGE: &str = r#"{"event":"reconnect"}"#;
+/// Number of published messages buffered for each connection.
+const CONNECTION_BUFFER: usize = 1024;
+
+
+/// Format the request subscribing to, or unsubscribing from, a currency pair, e.g.
+/// `{"method":"UNSUBSCRIBE","symbol":"ETH-BTC"}`.
+fn simulated_request(product: &CurrencyPair, request: SubscriptionRequest) -> String {
+    let method = match request {
+        SubscriptionRequest::Subscribe => "SUBSCRIBE",
+        SubscriptionRequest::Unsubscribe => "UNSUBSCRIBE",
+    };
+    format!(r#"{{"method":"{}","symbol":"{}"}}"#, method, canonical_symbol(product))
+}
+
+/// Parse string messages from the simulated exchange into the exchange [protocol](ExchangeProtocol).
+fn read_simulated_book_update(value: &str) -> Option<ExchangeProtocol<BookUpdate>> {
+    if value == RECONNECT_MESSAGE {
+        return Some(ExchangeProtocol::ReconnectionRequest);
+    }
+    match serde_json::from_str::<SimulatedBookUpdate>(value) {
+        Ok(book_update) => book_update.into_book_update().map(ExchangeProtocol::Data),
+        Err(_) => {
+            debug!("Parse failed {:?}", value);
+            None
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct SimulatedBookUpdate {
+    bids: Vec<(String, String)>,
+    asks: Vec<(String, String)>,
+}
+
+impl SimulatedBookUpdate {
+    fn into_book_update(self) -> Option<BookUpdate> {
+        let levels = |pairs: Vec<(String, String)>| pairs.into_iter()
+            .map(|(price, amount)| Some(ExchangeLevel {
+                exchange_code: SIMULATED_CODE.into(),
+                price: Decimal::from_str(&price).ok()?,
+                amount: Decimal::from_str(&amount).ok()?,
+                order_count: None,
+            }))
+            .collect::<Option<Vec<ExchangeLevel>>>();
+        Some(BookUpdate { exchange_code: SIMULATED_CODE.into(), bids: levels(self.bids)?, asks: levels(self.asks)? })
+    }
+}
+
+/// Format a trading book snapshot as published by the simulated exchange.
+///
+/// # Arguments
+///
+/// * `bids` - Bid levels, as (price, amount) pairs.
+///
+/// * `asks` - Ask levels, as (price, amount) pairs.
+///
+/// # Returns
+///
+/// The message.
+pub fn book_update_message(bids: &[(&str, &str)], asks: &[(&str, &str)]) -> String {
+    serde_json::json!({ "bids": bids, "asks": asks }).to_string()
+}
+
+/// Instructions sent to the connections of the simulated exchange.
+#[derive(Clone, Debug)]
+enum SimulatedCommand {
+    /// Send a text message.
+    Send(String),
+    /// Send a binary frame.
+    SendBinary(Vec<u8>),
+    /// Send a ping.
+    Ping,
+    /// Drop the connection without closing handshake.
+    Drop,
+}
+
+/// Counters shared by the connections of the simulated exchange.
+struct ConnectionCounters {
+    /// Number of open connections.
+    connections: watch::Sender<usize>,
+    /// Number of pongs received.
+    pongs: watch::Sender<usize>,
+}
+
+/// A running simulated exchange.
+pub struct SimulatedExchange {
+    /// Address the `WebSocket` server listens on.
+    address: SocketAddr,
+    /// Channel broadcasting instructions to every connection.
+    commands: broadcast::Sender<SimulatedCommand>,
+    /// Number of open connections.
+    connections: watch::Receiver<usize>,
+    /// Text messages received from the connections, such as subscriptions.
+    received: Arc<Mutex<Vec<String>>>,
+    /// Number of pongs received from the connections.
+    pongs: watch::Receiver<usize>,
+}
+
+impl SimulatedExchange {
+    /// Start a simulated exchange on an ephemeral local port.
+    ///
+    /// # Returns
+    ///
+    /// A [SimulatedExchange](SimulatedExchange) object, or an error if the port could not be bound.
+    pub async fn start() -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        let (commands, _) = broadcast::channel(CONNECTION_BUFFER);
+        let (connection_sender, connections) = watch::channel(0);
+        let (pong_sender, pongs) = watch::channel(0);
+        let counters = Arc::new(ConnectionCounters { connections: connection_sender, pongs: pong_sender });
+        let received = Arc::new(Mutex::new(vec![]));
+        let task_commands = commands.clone();
+        let task_received = received.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let command_receiver = task_commands.subscribe();
+                tokio::spawn(Self::serve_connection(stream, command_receiver, counters.clone(), task_received.clone()));
+            }
+        });
+        info!("Simulated exchange listening on {}", address);
+        Ok(Self { address, commands, connections, received, pongs })
+    }
+
+    /// Internal function forwarding the instructions to a connection.
+    async fn serve_connection(
+            stream: TcpStream,
+            mut command_receiver: broadcast::Receiver<SimulatedCommand>,
+            counters: Arc<ConnectionCounters>,
+            received: Arc<Mutex<Vec<String>>>) {
+        let mut ws = match accept_async(stream).await {
+            Ok(ws) => ws,
+            Err(error) => {
+                error!("Simulated exchange handshake failed: {:?}", error);
+                return;
+            }
+        };
+        counters.connections.send_modify(|n| *n += 1);
+        loop {
+            tokio::select! {
+                command = command_receiver.recv() => match command {
+                    Ok(SimulatedCommand::Send(text)) => {
+                        if ws.send(Message::Text(text)).await.is_err() {
+                            break;
+                        }
+                    },
+                    Ok(SimulatedCommand::SendBinary(frame)) => {
+                        if ws.send(Message::Binary(frame)).await.is_err() {
+                            break;
+                        }
+                    },
+                    Ok(SimulatedCommand::Ping) => {
+                        if ws.send(Message::Ping(vec![1])).await.is_err() {
+                            break;
+                        }
+                    },
+                    Ok(SimulatedCommand::Drop) | Err(_) => break,
+                },
+                message = ws.next() => match message {
+                    Some(Ok(Message::Ping(data))) => {
+                        let _ = ws.send(Message::Pong(data)).await;
+                    },
+                    Some(Ok(Message::Pong(_))) => counters.pongs.send_modify(|n| *n += 1),
+                    Some(Ok(Message::Text(text))) => received.lock().unwrap().push(text),
+                    Some(Ok(_)) => (),
+                    Some(Err(_)) | None => break,
+                },
+            }
+        }
+        counters.connections.send_modify(|n| *n -= 1);
+    }
+
+    /// `WebSocket` URL of the simulated exchange.
+    pub fn url(&self) -> String {
+        format!("ws://{}", self.address)
+    }
+
+    /// Create an [exchange adapter](ExchangeAdapter) connecting to this simulated exchange.
+    ///
+    /// # Arguments
+    ///
+    /// * `product` - The currency pair subscribed to. The simulated exchange publishes
+    ///   the same snapshots whatever the subscription.
+    pub fn adapter(&self, product: &CurrencyPair) -> ExchangeAdapter<BookUpdate> {
+        ExchangeAdapter::new(SIMULATED_CODE, self.url(), Arc::new(read_simulated_book_update))
+            .with_subscribe_message(simulated_request(product, SubscriptionRequest::Subscribe))
+            .with_subscription_formatter(Arc::new(simulated_request))
+            .with_capabilities(ExchangeCapabilities { region: "local", ..ExchangeCapabilities::default() })
+    }
+
+    /// Number of open connections.
+    pub fn connections(&self) -> usize {
+        *self.connections.borrow()
+    }
+
+    /// Wait until at least a number of connections are open.
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - The number of connections.
+    pub async fn wait_for_connections(&self, count: usize) {
+        let mut connections = self.connections.clone();
+        let _ = connections.wait_for(|&n| n >= count).await;
+    }
+
+    /// Text messages received so far from all the connections, such as subscriptions.
+    pub fn received_messages(&self) -> Vec<String> {
+        self.received.lock().unwrap().clone()
+    }
+
+    /// Send a ping to every open connection.
+    pub fn ping(&self) {
+        let _ = self.commands.send(SimulatedCommand::Ping);
+    }
+
+    /// Wait until at least a number of pongs were received.
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - The number of pongs.
+    pub async fn wait_for_pongs(&self, count: usize) {
+        let mut pongs = self.pongs.clone();
+        let _ = pongs.wait_for(|&n| n >= count).await;
+    }
+
+    /// Publish a message to every open connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message, e.g. created with [book_update_message](book_update_message).
+    pub fn publish(&self, message: String) {
+        let _ = self.commands.send(SimulatedCommand::Send(message));
+    }
+
+    /// Publish a binary frame to every open connection, e.g. a compressed message.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The payload of the frame.
+    pub fn publish_binary(&self, frame: Vec<u8>) {
+        let _ = self.commands.send(SimulatedCommand::SendBinary(frame));
+    }
+
+    /// Request every open connection to reconnect, through the exchange protocol.
+    pub fn request_reconnection(&self) {
+        self.publish(RECONNECT_MESSAGE.to_string());
+    }
+
+    /// Drop every open connection, without closing handshake.
+    pub fn drop_connections(&self) {
+        let _ = self.commands.send(SimulatedCommand::Drop);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_simulated_book_update() {
+        let message = book_update_message(&[("100.5", "1")], &[("101", "2.5")]);
+        assert_eq!(
+            read_simulated_book_update(&message),
+            Some(ExchangeProtocol::Data(BookUpdate {
+                exchange_code: SIMULATED_CODE.into(),
+                bids: vec![ExchangeLevel::from_strs(SIMULATED_CODE, "100.5", "1")],
+                asks: vec![ExchangeLevel::from_strs(SIMULATED_CODE, "101", "2.5")],
+            }))
+        );
+        assert_eq!(read_simulated_book_update(RECONNECT_MESSAGE), Some(ExchangeProtocol::ReconnectionRequest));
+        assert_eq!(read_simulated_book_update(r#"{"bids":[["x","1"]],"asks":[]}"#), None);
+    }
+}
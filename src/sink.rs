@@ -0,0 +1,627 @@
+//! Common interface of the sinks of the feeds, e.g. databases, brokers or pipes, and
+//! a [SinkManager](SinkManager) running each of them in a task of its own: each sink consumes
+//! from a queue of its own for each feed, either conflated to the latest summary or bounded, dropping the
+//! summaries which do not fit, so that a slow sink never delays the publishing path nor the
+//! other sinks, and a sink which fails or panics is stopped without affecting them.
+//! The lag of each sink is tracked in [SINK_LAGS](SINK_LAGS), and its [health](SinkHealth)
+//! is available to readiness checks.
+
+use futures::future::select_all;
+use log::{error, info, warn};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, watch};
+use tokio::time::{Duration, Instant};
+
+use crate::aggregator::AggregateBook;
+use crate::clock::SharedClock;
+use crate::feed::{BookReceiver, FeedReceiver};
+use crate::metrics::{QUEUE_DEPTHS, SINK_DROPPED, SINK_ERRORS, SINK_LAGS};
+use crate::orderbook::Summary;
+
+
+/// Default capacity of a [bounded](SinkQueue::Bounded) sink queue.
+pub const DEFAULT_SINK_QUEUE_CAPACITY: usize = 64;
+/// Largest lag of a healthy sink, beyond its sample interval.
+const MAX_HEALTHY_LAG: Duration = Duration::from_secs(10);
+
+
+/// Error of a [SummarySink](SummarySink).
+#[derive(Debug, Clone, PartialEq)]
+pub enum SinkError {
+    /// The sink keeps running, e.g. after a failed write to retry.
+    Transient(String),
+    /// The sink is stopped, e.g. after an invalid configuration.
+    Fatal(String),
+}
+
+impl fmt::Display for SinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SinkError::Transient(message) => write!(f, "{}", message),
+            SinkError::Fatal(message) => write!(f, "{} (fatal)", message),
+        }
+    }
+}
+
+/// Result of the operations of a [SummarySink](SummarySink).
+pub type SinkResult = Result<(), SinkError>;
+
+/// Queue of the summaries delivered to a [SummarySink](SummarySink).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SinkQueue {
+    /// Only the latest summary is kept.
+    Conflate,
+    /// Summaries are kept up to a capacity, further ones being dropped until the sink catches up.
+    Bounded(usize),
+}
+
+/// Health of a sink.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SinkStatus {
+    /// The sink is consuming the feed.
+    Running,
+    /// The lag of the sink exceeds its limit.
+    Lagging,
+    /// The sink failed or panicked.
+    Stopped,
+}
+
+impl SinkStatus {
+    /// Name of the status, for logs.
+    pub fn name(&self) -> &'static str {
+        match self {
+            SinkStatus::Running => "running",
+            SinkStatus::Lagging => "lagging",
+            SinkStatus::Stopped => "stopped",
+        }
+    }
+}
+
+/// Health of the sinks run by a [SinkManager](SinkManager), shared with readiness checks.
+#[derive(Debug, Clone, Default)]
+pub struct SinkHealth {
+    /// Status of each sink, by name.
+    statuses: Arc<Mutex<BTreeMap<&'static str, SinkStatus>>>,
+}
+
+impl SinkHealth {
+    /// Status of a sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The sink name.
+    ///
+    /// # Returns
+    ///
+    /// An optional [SinkStatus](SinkStatus), [None](None) if the sink is unknown.
+    pub fn status(&self, name: &str) -> Option<SinkStatus> {
+        self.statuses.lock().unwrap().iter()
+            .find(|(sink_name, _)| **sink_name == name)
+            .map(|(_, &status)| status)
+    }
+
+    /// Status of each sink.
+    ///
+    /// # Returns
+    ///
+    /// A [vector](Vec) of sink name and [SinkStatus](SinkStatus) pairs, ordered by name.
+    pub fn statuses(&self) -> Vec<(&'static str, SinkStatus)> {
+        self.statuses.lock().unwrap().iter().map(|(&name, &status)| (name, status)).collect()
+    }
+
+    /// Whether every sink is [running](SinkStatus::Running).
+    pub fn is_ready(&self) -> bool {
+        self.statuses.lock().unwrap().values().all(|&status| status == SinkStatus::Running)
+    }
+
+    /// Internal function updating the status of a sink, logging its changes.
+    fn set(&self, name: &'static str, status: SinkStatus) {
+        let previous = self.statuses.lock().unwrap().insert(name, status);
+        if previous.is_some() && previous != Some(status) {
+            warn!("Sink {} is {}", name, status.name());
+        }
+    }
+}
+
+/// A summary with the time it was taken from the feed.
+type Stamped = (Instant, Summary);
+
+/// Internal queue of a sink.
+enum QueueReceiver {
+    /// Receiver of a [conflated](SinkQueue::Conflate) queue.
+    Conflated(watch::Receiver<Option<Stamped>>),
+    /// Receiver of a [bounded](SinkQueue::Bounded) queue.
+    Bounded(mpsc::Receiver<Stamped>),
+}
+
+/// Sending end of the internal queue of a sink.
+enum QueueSender {
+    /// Sender of a [conflated](SinkQueue::Conflate) queue.
+    Conflated(watch::Sender<Option<Stamped>>),
+    /// Sender of a [bounded](SinkQueue::Bounded) queue.
+    Bounded(mpsc::Sender<Stamped>),
+}
+
+/// A consumer of the feeds, run by a [SinkManager](SinkManager).
+#[tonic::async_trait]
+pub trait SummarySink: Send {
+    /// Name of the sink, for logs and metrics.
+    fn name(&self) -> &'static str;
+
+    /// Queue of the summaries delivered to the sink, [conflated](SinkQueue::Conflate) by default.
+    fn queue(&self) -> SinkQueue {
+        SinkQueue::Conflate
+    }
+
+    /// Interval at which the latest summary is delivered, or [None](None) to deliver each one.
+    /// Only applies to a [conflated](SinkQueue::Conflate) queue.
+    fn sample_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Interval at which the sink is [flushed](SummarySink::flush), if any.
+    fn flush_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Whether the sink consumes the aggregate books, delivered to
+    /// [on_book_update](SummarySink::on_book_update).
+    fn wants_book_updates(&self) -> bool {
+        false
+    }
+
+    /// Consume a summary.
+    ///
+    /// # Arguments
+    ///
+    /// * `summary` - The [Summary](Summary).
+    async fn on_summary(&mut self, summary: &Summary) -> SinkResult;
+
+    /// Consume the aggregate book of the latest summary, if [wanted](SummarySink::wants_book_updates).
+    ///
+    /// # Arguments
+    ///
+    /// * `aggregate_book` - The [AggregateBook](AggregateBook).
+    async fn on_book_update(&mut self, _aggregate_book: &AggregateBook) -> SinkResult {
+        Ok(())
+    }
+
+    /// Write the data buffered by the sink, if any.
+    async fn flush(&mut self) -> SinkResult {
+        Ok(())
+    }
+
+    /// Release the resources of the sink, once stopped.
+    async fn close(&mut self) -> SinkResult {
+        Ok(())
+    }
+}
+
+/// Runs [sinks](SummarySink) of one or more feeds, e.g. one per symbol, each in a task of its own.
+pub struct SinkManager {
+    /// The summaries and aggregate books of each feed.
+    feeds: Vec<(FeedReceiver, BookReceiver)>,
+    /// Time source for the sample and flush intervals.
+    clock: SharedClock,
+    /// The sinks.
+    sinks: Vec<Box<dyn SummarySink>>,
+}
+
+impl SinkManager {
+    /// Create a new [SinkManager](SinkManager) object, without sinks.
+    ///
+    /// # Arguments
+    ///
+    /// * `feed` - A [FeedReceiver](FeedReceiver).
+    ///
+    /// * `book` - A [BookReceiver](BookReceiver) of the same feed.
+    ///
+    /// * `clock` - Time source for the sample and flush intervals.
+    pub fn new(feed: FeedReceiver, book: BookReceiver, clock: SharedClock) -> Self {
+        Self { feeds: vec![(feed, book)], clock, sinks: vec![] }
+    }
+
+    /// Add a further feed, e.g. of another symbol, delivered to the same sinks.
+    ///
+    /// # Arguments
+    ///
+    /// * `feed` - A [FeedReceiver](FeedReceiver).
+    ///
+    /// * `book` - A [BookReceiver](BookReceiver) of the same feed.
+    ///
+    /// # Returns
+    ///
+    /// The modified [SinkManager](SinkManager).
+    pub fn with_feed(mut self, feed: FeedReceiver, book: BookReceiver) -> Self {
+        self.feeds.push((feed, book));
+        self
+    }
+
+    /// Add a sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - The [SummarySink](SummarySink).
+    ///
+    /// # Returns
+    ///
+    /// The modified [SinkManager](SinkManager).
+    pub fn with_sink(mut self, sink: impl SummarySink + 'static) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    /// Names of the sinks.
+    pub fn sink_names(&self) -> Vec<&'static str> {
+        self.sinks.iter().map(|sink| sink.name()).collect()
+    }
+
+    /// Spawn a task for each sink, running until a feed is closed or the sink fails, and a
+    /// task for each feed dispatching each of its summaries to the queues of the sinks.
+    ///
+    /// # Returns
+    ///
+    /// The [SinkHealth](SinkHealth) of the sinks.
+    pub fn spawn(self) -> SinkHealth {
+        let health = SinkHealth::default();
+        let mut senders: Vec<Vec<(&'static str, QueueSender)>> = self.feeds.iter().map(|_| vec![]).collect();
+        let books: Vec<BookReceiver> = self.feeds.iter().map(|(_, book)| book.clone()).collect();
+        for sink in self.sinks {
+            let name = sink.name();
+            let mut receivers = vec![];
+            for feed_senders in &mut senders {
+                let (sender, receiver) = match sink.queue() {
+                    SinkQueue::Conflate => {
+                        let (sender, receiver) = watch::channel(None);
+                        (QueueSender::Conflated(sender), QueueReceiver::Conflated(receiver))
+                    },
+                    SinkQueue::Bounded(capacity) => {
+                        let (sender, receiver) = mpsc::channel(capacity);
+                        (QueueSender::Bounded(sender), QueueReceiver::Bounded(receiver))
+                    },
+                };
+                feed_senders.push((name, sender));
+                receivers.push(receiver);
+            }
+            health.set(name, SinkStatus::Running);
+            let handle = tokio::spawn(run_sink(sink, receivers, books.clone(), self.clock.clone(), health.clone()));
+            let health = health.clone();
+            tokio::spawn(async move {
+                if let Err(join_error) = handle.await {
+                    SINK_ERRORS.increment(name);
+                    health.set(name, SinkStatus::Stopped);
+                    error!("Sink {} failed: {}", name, join_error);
+                }
+            });
+        }
+        for ((feed, _), senders) in self.feeds.into_iter().zip(senders) {
+            tokio::spawn(dispatch(feed, senders, self.clock.clone()));
+        }
+        health
+    }
+}
+
+/// Internal function delivering each summary of the feed to the queues of the sinks, until
+/// the feed is closed or every sink stopped.
+async fn dispatch(mut feed: FeedReceiver, mut senders: Vec<(&'static str, QueueSender)>, clock: SharedClock) {
+    while !senders.is_empty() && feed.changed().await.is_ok() {
+        let Some(summary) = feed.borrow_and_update().clone() else {
+            continue;
+        };
+        let received = clock.now();
+        senders.retain(|(name, sender)| match sender {
+            QueueSender::Conflated(sender) => {
+                sender.send_replace(Some((received, summary.clone())));
+                !sender.is_closed()
+            },
+            QueueSender::Bounded(sender) => {
+                let result = sender.try_send((received, summary.clone()));
+                QUEUE_DEPTHS.record_channel("sink", name, sender);
+                match result {
+                    Ok(()) => true,
+                    Err(mpsc::error::TrySendError::Full(_)) => {
+                        SINK_DROPPED.increment(name);
+                        true
+                    },
+                    Err(mpsc::error::TrySendError::Closed(_)) => false,
+                }
+            },
+        });
+    }
+}
+
+/// Internal function delivering the summaries of its queues, one per feed, to a sink, until a
+/// feed is closed or the sink fails.
+async fn run_sink(mut sink: Box<dyn SummarySink>, mut queues: Vec<QueueReceiver>, mut books: Vec<BookReceiver>, clock: SharedClock, health: SinkHealth) {
+    let name = sink.name();
+    let sample_interval = match sink.queue() {
+        SinkQueue::Conflate => sink.sample_interval(),
+        SinkQueue::Bounded(_) => None,
+    };
+    let max_lag = sample_interval.unwrap_or_default() + MAX_HEALTHY_LAG;
+    let flush_interval = sink.flush_interval();
+    let mut book_updates = sink.wants_book_updates();
+    let mut last_sample = clock.now();
+    let mut last_flush = clock.now();
+    info!("Sink {} started", name);
+    loop {
+        let sample_delay = sample_interval.map(|interval| (last_sample + interval).saturating_duration_since(clock.now()));
+        let flush_delay = flush_interval.map(|interval| (last_flush + interval).saturating_duration_since(clock.now()));
+        let (result, received) = tokio::select! {
+            maybe_stamped = next_summary(&mut queues), if sample_interval.is_none() => {
+                match maybe_stamped {
+                    Some((received, summary)) => (sink.on_summary(&summary).await, Some(received)),
+                    None => break,
+                }
+            },
+            _ = clock.sleep(sample_delay.unwrap_or_default()), if sample_delay.is_some() => {
+                last_sample = clock.now();
+                match sample(&mut sink, &mut queues).await {
+                    Some(sampled) => sampled,
+                    None => break,
+                }
+            },
+            changed = next_book(&mut books), if book_updates => {
+                let maybe_book: Option<Arc<AggregateBook>> = match changed {
+                    Ok(maybe_book) => maybe_book,
+                    Err(_) => {
+                        book_updates = false;
+                        None
+                    },
+                };
+                match maybe_book {
+                    Some(aggregate_book) => (sink.on_book_update(&aggregate_book).await, None),
+                    None => (Ok(()), None),
+                }
+            },
+            _ = clock.sleep(flush_delay.unwrap_or_default()), if flush_delay.is_some() => {
+                last_flush = clock.now();
+                (sink.flush().await, None)
+            },
+        };
+        if let Some(received) = received {
+            let lag = clock.now().saturating_duration_since(received);
+            SINK_LAGS.set(name, lag.as_millis() as u64);
+            health.set(name, if lag > max_lag { SinkStatus::Lagging } else { SinkStatus::Running });
+        }
+        match result {
+            Ok(()) => (),
+            Err(SinkError::Transient(message)) => {
+                SINK_ERRORS.increment(name);
+                error!("Sink {} error: {}", name, message);
+            },
+            Err(SinkError::Fatal(message)) => {
+                SINK_ERRORS.increment(name);
+                health.set(name, SinkStatus::Stopped);
+                error!("Sink {} stopped: {}", name, message);
+                break;
+            },
+        }
+    }
+    if let Err(error) = sink.flush().await {
+        error!("Sink {} not flushed: {}", name, error);
+    }
+    if let Err(error) = sink.close().await {
+        error!("Sink {} not closed: {}", name, error);
+    }
+    info!("Sink {} stopped", name);
+}
+
+/// Internal function waiting for the next summary of any of the queues of a sink.
+///
+/// # Returns
+///
+/// The next summary with the time it was taken from its feed, [None](None) once a feed is closed.
+async fn next_summary(queues: &mut [QueueReceiver]) -> Option<Stamped> {
+    select_all(queues.iter_mut().map(|queue| Box::pin(next_queued(queue)))).await.0
+}
+
+/// Internal function waiting for the next summary of a queue of a sink.
+///
+/// # Returns
+///
+/// The next summary with the time it was taken from the feed, [None](None) once the feed is closed.
+async fn next_queued(queue: &mut QueueReceiver) -> Option<Stamped> {
+    match queue {
+        QueueReceiver::Conflated(receiver) => loop {
+            receiver.changed().await.ok()?;
+            if let Some(stamped) = receiver.borrow_and_update().clone() {
+                return Some(stamped);
+            }
+        },
+        QueueReceiver::Bounded(receiver) => receiver.recv().await,
+    }
+}
+
+/// Internal function delivering the latest summary of each of the conflated queues of a sink,
+/// at the end of a sample interval.
+///
+/// # Returns
+///
+/// The result of the sink, with the time the oldest summary delivered was taken from its feed,
+/// [None](None) once a feed is closed.
+async fn sample(sink: &mut Box<dyn SummarySink>, queues: &mut [QueueReceiver]) -> Option<(SinkResult, Option<Instant>)> {
+    let mut oldest: Option<Instant> = None;
+    for queue in queues {
+        let QueueReceiver::Conflated(receiver) = queue else {
+            return None;
+        };
+        if receiver.has_changed().is_err() {
+            return None;
+        }
+        let maybe_stamped = receiver.borrow_and_update().clone();
+        if let Some((received, summary)) = maybe_stamped {
+            oldest = Some(oldest.map_or(received, |oldest| oldest.min(received)));
+            if let Err(error) = sink.on_summary(&summary).await {
+                return Some((Err(error), oldest));
+            }
+        }
+    }
+    Some((Ok(()), oldest))
+}
+
+/// Internal function waiting for the next aggregate book of any of the feeds of a sink.
+///
+/// # Returns
+///
+/// The aggregate book, if any, or an error once a feed is closed.
+async fn next_book(books: &mut [BookReceiver]) -> Result<Option<Arc<AggregateBook>>, watch::error::RecvError> {
+    let (changed, index, _) = select_all(books.iter_mut().map(|book| Box::pin(book.changed()))).await;
+    changed?;
+    Ok(books[index].borrow_and_update().clone())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::system_clock;
+    use tokio::sync::{mpsc, watch};
+    use tokio::time::timeout;
+
+    /// Sink forwarding the symbols of the summaries, failing, panicking, or blocked.
+    struct TestSink {
+        name: &'static str,
+        symbols: mpsc::UnboundedSender<String>,
+        fail: Option<SinkError>,
+        panics: bool,
+        queue: SinkQueue,
+        blocked: Option<Arc<tokio::sync::Semaphore>>,
+    }
+
+    impl TestSink {
+        fn new(name: &'static str, symbols: &mpsc::UnboundedSender<String>) -> Self {
+            Self { name, symbols: symbols.clone(), fail: None, panics: false, queue: SinkQueue::Conflate, blocked: None }
+        }
+    }
+
+    #[tonic::async_trait]
+    impl SummarySink for TestSink {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn queue(&self) -> SinkQueue {
+            self.queue
+        }
+
+        async fn on_summary(&mut self, summary: &Summary) -> SinkResult {
+            assert!(!self.panics, "sink panicked");
+            if let Some(error) = self.fail.clone() {
+                return Err(error);
+            }
+            let _ = self.symbols.send(summary.symbol.clone());
+            if let Some(blocked) = &self.blocked {
+                blocked.acquire().await.unwrap().forget();
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failing_sinks_isolated() {
+        let (feed_sender, feed) = watch::channel(None);
+        let (_book_sender, book) = watch::channel(None);
+        let (symbols, mut received) = mpsc::unbounded_channel();
+        let manager = SinkManager::new(feed, book, system_clock())
+            .with_sink(TestSink { fail: Some(SinkError::Fatal("boom".to_string())), ..TestSink::new("test_fatal", &symbols) })
+            .with_sink(TestSink { fail: Some(SinkError::Transient("retry".to_string())), ..TestSink::new("test_transient", &symbols) })
+            .with_sink(TestSink { panics: true, ..TestSink::new("test_panicking", &symbols) })
+            .with_sink(TestSink::new("test_healthy", &symbols));
+        assert_eq!(manager.sink_names(), vec!["test_fatal", "test_transient", "test_panicking", "test_healthy"]);
+        let health = manager.spawn();
+        for symbol in ["ETH-BTC", "BTC-USD"] {
+            feed_sender.send_replace(Some(Summary { symbol: symbol.to_string(), ..Default::default() }));
+            let received = timeout(Duration::from_secs(5), received.recv()).await.unwrap();
+            assert_eq!(received.as_deref(), Some(symbol));
+        }
+        timeout(Duration::from_secs(5), async {
+            while SINK_ERRORS.get("test_fatal") == 0 || SINK_ERRORS.get("test_transient") == 0 || SINK_ERRORS.get("test_panicking") == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.expect("sink errors not counted");
+        assert_eq!(SINK_ERRORS.get("test_fatal"), 1);
+        timeout(Duration::from_secs(5), async {
+            while health.status("test_panicking") != Some(SinkStatus::Stopped) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.expect("panicking sink not stopped");
+        assert_eq!(health.status("test_fatal"), Some(SinkStatus::Stopped));
+        assert_eq!(health.status("test_transient"), Some(SinkStatus::Running));
+        assert_eq!(health.status("test_healthy"), Some(SinkStatus::Running));
+        assert!(!health.is_ready());
+    }
+
+    #[tokio::test]
+    async fn test_slow_sinks_queues() {
+        let (feed_sender, feed) = watch::channel(None);
+        let (_book_sender, book) = watch::channel(None);
+        let (symbols, mut received) = mpsc::unbounded_channel();
+        let (bounded_symbols, mut bounded_received) = mpsc::unbounded_channel();
+        let (conflated_symbols, mut conflated_received) = mpsc::unbounded_channel();
+        let bounded_gate = Arc::new(tokio::sync::Semaphore::new(0));
+        let conflated_gate = Arc::new(tokio::sync::Semaphore::new(0));
+        let health = SinkManager::new(feed, book, system_clock())
+            .with_sink(TestSink { queue: SinkQueue::Bounded(2), blocked: Some(bounded_gate.clone()), ..TestSink::new("test_bounded", &bounded_symbols) })
+            .with_sink(TestSink { blocked: Some(conflated_gate.clone()), ..TestSink::new("test_conflated", &conflated_symbols) })
+            .with_sink(TestSink::new("test_fast", &symbols))
+            .spawn();
+        let symbols: Vec<String> = (0..6).map(|i| format!("S{}", i)).collect();
+        for (i, symbol) in symbols.iter().enumerate() {
+            feed_sender.send_replace(Some(Summary { symbol: symbol.clone(), ..Default::default() }));
+            let received = timeout(Duration::from_secs(5), received.recv()).await.unwrap();
+            assert_eq!(received.as_ref(), Some(symbol));
+            if i == 0 {
+                // both slow sinks are now blocked on the first summary
+                assert_eq!(timeout(Duration::from_secs(5), bounded_received.recv()).await.unwrap().as_ref(), Some(symbol));
+                assert_eq!(timeout(Duration::from_secs(5), conflated_received.recv()).await.unwrap().as_ref(), Some(symbol));
+            }
+        }
+        // the bounded sink queued the next two summaries and dropped the others
+        assert_eq!(SINK_DROPPED.get("test_bounded"), 3);
+        bounded_gate.add_permits(3);
+        for symbol in &symbols[1..3] {
+            let received = timeout(Duration::from_secs(5), bounded_received.recv()).await.unwrap();
+            assert_eq!(received.as_ref(), Some(symbol));
+        }
+        // the conflated sink only gets the latest summary
+        conflated_gate.add_permits(2);
+        let received = timeout(Duration::from_secs(5), conflated_received.recv()).await.unwrap();
+        assert_eq!(received.as_ref(), Some(&symbols[5]));
+        assert!(SINK_LAGS.get("test_fast").is_some());
+        assert!(health.is_ready());
+    }
+
+    #[tokio::test]
+    async fn test_several_feeds() {
+        let (eth_btc_sender, eth_btc) = watch::channel(None);
+        let (btc_usdt_sender, btc_usdt) = watch::channel(None);
+        let (_book_sender, book) = watch::channel(None);
+        let (symbols, mut received) = mpsc::unbounded_channel();
+        let (conflated_symbols, mut conflated_received) = mpsc::unbounded_channel();
+        let conflated_gate = Arc::new(tokio::sync::Semaphore::new(0));
+        let _health = SinkManager::new(eth_btc, book.clone(), system_clock())
+            .with_feed(btc_usdt, book)
+            .with_sink(TestSink { queue: SinkQueue::Bounded(4), ..TestSink::new("test_feeds_bounded", &symbols) })
+            .with_sink(TestSink { blocked: Some(conflated_gate.clone()), ..TestSink::new("test_feeds_conflated", &conflated_symbols) })
+            .spawn();
+        eth_btc_sender.send_replace(Some(Summary { symbol: "ETH-BTC".to_string(), ..Default::default() }));
+        assert_eq!(timeout(Duration::from_secs(5), received.recv()).await.unwrap().as_deref(), Some("ETH-BTC"));
+        assert_eq!(timeout(Duration::from_secs(5), conflated_received.recv()).await.unwrap().as_deref(), Some("ETH-BTC"));
+        // the conflated sink is blocked, yet keeps the latest summary of each feed
+        for symbol in ["BTC-USDT", "ETH-BTC"] {
+            let sender = if symbol == "ETH-BTC" { &eth_btc_sender } else { &btc_usdt_sender };
+            sender.send_replace(Some(Summary { symbol: symbol.to_string(), ..Default::default() }));
+            assert_eq!(timeout(Duration::from_secs(5), received.recv()).await.unwrap().as_deref(), Some(symbol));
+        }
+        conflated_gate.add_permits(3);
+        let mut conflated = vec![];
+        for _ in 0..2 {
+            conflated.push(timeout(Duration::from_secs(5), conflated_received.recv()).await.unwrap().unwrap());
+        }
+        conflated.sort();
+        assert_eq!(conflated, vec!["BTC-USDT".to_string(), "ETH-BTC".to_string()]);
+    }
+}
@@ -0,0 +1,110 @@
+//! Soak test: runs the server against a simulated exchange, with clients repeatedly
+//! connecting and disconnecting, and fails if memory or open exchange connections grow
+//! steadily over the run, revealing leaks in the per-client pipelines.
+
+use log::{LevelFilter, info, error};
+use simple_logger::SimpleLogger;
+use std::{env, net::{Ipv6Addr, TcpListener}, process::ExitCode, sync::Arc};
+use tokio::time::{sleep, Duration, Instant};
+use tokio_stream::StreamExt;
+
+use orderbook_server::accounting::UsageRegistry;
+use orderbook_server::allocator::{GlobalAllocator, GLOBAL_ALLOCATOR};
+use orderbook_server::cli::ArgParser;
+use orderbook_server::clock::system_clock;
+use orderbook_server::grpc::ProtobufOrderbookServer;
+use orderbook_server::leak_detection::{grows_monotonically, rss_bytes};
+use orderbook_server::orderbook::{orderbook_aggregator_client::OrderbookAggregatorClient, BookSummaryRequest};
+use orderbook_server::simulated::{book_update_message, SimulatedExchange};
+use orderbook_server::symbols::parse_currency_pair;
+
+
+const USAGE_MESSAGE: &str = "Usage: soak [minutes] [clients]";
+const DEFAULT_MINUTES: u64 = 60;
+const DEFAULT_CLIENTS: u64 = 4;
+/// Interval between two resource samples.
+const SAMPLE_INTERVAL_S: u64 = 10;
+/// Samples ignored at the beginning of the run, while caches and pools fill up.
+const WARMUP_SAMPLES: usize = 6;
+/// Windows into which the samples are split to detect steady growth.
+const LEAK_WINDOWS: usize = 4;
+/// Summaries received by each client before disconnecting.
+const SUMMARIES_PER_CLIENT: usize = 100;
+/// Interval between two snapshots published by the simulated exchange.
+const PUBLISH_INTERVAL_MS: u64 = 10;
+
+
+#[global_allocator]
+static GLOBAL: GlobalAllocator = GLOBAL_ALLOCATOR;
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    SimpleLogger::new().with_level(LevelFilter::Info).init().unwrap();
+    let mut arg_parser = ArgParser::new(env::args(), USAGE_MESSAGE);
+    let minutes = arg_parser.extract_number(DEFAULT_MINUTES);
+    let clients = arg_parser.extract_number(DEFAULT_CLIENTS);
+
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    let exchange = Arc::new(SimulatedExchange::start().await.expect("Could not start simulated exchange"));
+    let server = ProtobufOrderbookServer::new(product.clone(), vec![Box::new(exchange.adapter(&product))], UsageRegistry::new(system_clock()));
+    let port = TcpListener::bind((Ipv6Addr::LOCALHOST, 0)).unwrap().local_addr().unwrap().port();
+    tokio::spawn(async move {
+        let _ = server.serve(port).await;
+    });
+
+    let publisher = exchange.clone();
+    tokio::spawn(async move {
+        for i in 0u64.. {
+            let bid = format!("{}.{}", 100 + i % 7, i % 100);
+            publisher.publish(book_update_message(&[(&bid, "1"), ("99", "2")], &[("108", "1.5")]));
+            sleep(Duration::from_millis(PUBLISH_INTERVAL_MS)).await;
+        }
+    });
+
+    let server_url = format!("http://[::1]:{}", port);
+    for client in 0..clients {
+        let server_url = server_url.clone();
+        tokio::spawn(async move {
+            loop {
+                match OrderbookAggregatorClient::connect(server_url.clone()).await {
+                    Ok(mut client) => {
+                        if let Ok(response) = client.book_summary(BookSummaryRequest::default()).await {
+                            let mut summaries = response.into_inner().take(SUMMARIES_PER_CLIENT);
+                            while summaries.next().await.is_some() {}
+                        }
+                    },
+                    Err(error) => error!("Client {} could not connect: {:?}", client, error),
+                }
+                sleep(Duration::from_millis(100)).await;
+            }
+        });
+    }
+
+    info!("Soak test running for {} minutes with {} clients", minutes, clients);
+    let end = Instant::now() + Duration::from_secs(minutes * 60);
+    let mut rss_samples = vec![];
+    let mut connection_samples = vec![];
+    while Instant::now() < end {
+        sleep(Duration::from_secs(SAMPLE_INTERVAL_S)).await;
+        let rss = rss_bytes().unwrap_or_default();
+        let connections = exchange.connections() as u64;
+        info!("RSS {} KiB, open exchange connections {}", rss / 1024, connections);
+        rss_samples.push(rss);
+        connection_samples.push(connections);
+    }
+
+    let rss_leak = grows_monotonically(rss_samples.get(WARMUP_SAMPLES..).unwrap_or_default(), LEAK_WINDOWS);
+    let connection_leak = grows_monotonically(connection_samples.get(WARMUP_SAMPLES..).unwrap_or_default(), LEAK_WINDOWS);
+    if rss_leak {
+        error!("Memory grew steadily over the run");
+    }
+    if connection_leak {
+        error!("Open exchange connections grew steadily over the run");
+    }
+    if rss_leak || connection_leak {
+        ExitCode::FAILURE
+    } else {
+        info!("No leak detected");
+        ExitCode::SUCCESS
+    }
+}
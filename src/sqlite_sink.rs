@@ -0,0 +1,210 @@
+//! Optional sink writing periodic snapshots of the consolidated book into a SQLite
+//! database: one row per symbol per interval, with top of book, spread and depth.
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::Duration;
+
+use crate::config::{DocumentedConfig, Validate, Validator};
+use crate::feed::SummaryMetrics;
+use crate::orderbook::Summary;
+use crate::sink::{SinkError, SinkResult, SummarySink};
+
+
+/// Default database file, in the working directory.
+const DEFAULT_FILE: &str = "snapshots.sqlite";
+/// Default time between two snapshots.
+const DEFAULT_INTERVAL_MS: u64 = 1000;
+/// Statement creating the snapshot table, if it does not exist.
+const CREATE_TABLE: &str = "CREATE TABLE IF NOT EXISTS snapshots (
+    timestamp_ms INTEGER NOT NULL,
+    symbol TEXT NOT NULL,
+    best_bid REAL,
+    best_bid_exchange TEXT,
+    best_ask REAL,
+    best_ask_exchange TEXT,
+    spread REAL,
+    bid_depth REAL,
+    ask_depth REAL
+)";
+
+/// Statement inserting a snapshot.
+const INSERT_SNAPSHOT: &str = "INSERT INTO snapshots (
+    timestamp_ms, symbol, best_bid, best_bid_exchange, best_ask, best_ask_exchange, spread, bid_depth, ask_depth
+) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)";
+
+
+/// Settings of the SQLite sink, read from a JSON file. Missing values take their defaults.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SqliteSinkConfig {
+    /// Database file, created if it does not exist.
+    pub file: String,
+    /// Time between two snapshots, in milliseconds.
+    pub interval_ms: u64,
+}
+
+impl Default for SqliteSinkConfig {
+    fn default() -> Self {
+        Self { file: DEFAULT_FILE.to_string(), interval_ms: DEFAULT_INTERVAL_MS }
+    }
+}
+
+impl Validate for SqliteSinkConfig {
+    fn validate(&self, validator: &mut Validator) {
+        if self.file.is_empty() {
+            validator.error("file", "must not be empty");
+        }
+        validator.positive("interval_ms", self.interval_ms);
+    }
+}
+
+impl DocumentedConfig for SqliteSinkConfig {
+    const DESCRIPTION: &'static str = "Settings of the SQLite sink.";
+    const FIELDS: &'static [(&'static str, &'static str)] = &[
+        ("file", "Database file, created if it does not exist."),
+        ("interval_ms", "Time between two snapshots, in milliseconds."),
+    ];
+}
+
+/// A SQLite database storing periodic snapshots.
+#[derive(Clone)]
+pub struct SqliteSink {
+    /// Connection to the database, shared with the blocking writes.
+    connection: Arc<Mutex<Connection>>,
+    /// Time between two snapshots.
+    interval: Duration,
+}
+
+impl SqliteSink {
+    /// Open (or create) a database, creating the snapshot table if needed. Snapshots are
+    /// written every second by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The database file.
+    ///
+    /// # Returns
+    ///
+    /// A [SqliteSink](SqliteSink) object, or an error.
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.execute(CREATE_TABLE, [])?;
+        Ok(Self { connection: Arc::new(Mutex::new(connection)), interval: Duration::from_millis(DEFAULT_INTERVAL_MS) })
+    }
+
+    /// Open (or create) the database of a configuration, writing snapshots at its interval.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The [SqliteSinkConfig](SqliteSinkConfig).
+    ///
+    /// # Returns
+    ///
+    /// A [SqliteSink](SqliteSink) object, or an error.
+    pub fn from_config(config: &SqliteSinkConfig) -> rusqlite::Result<Self> {
+        Ok(Self::open(Path::new(&config.file))?.with_interval(Duration::from_millis(config.interval_ms)))
+    }
+
+    /// Set the time between two snapshots.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - The time between two snapshots.
+    ///
+    /// # Returns
+    ///
+    /// The modified [SqliteSink](SqliteSink).
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Write a snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp_ms` - Snapshot time, in milliseconds since the epoch.
+    ///
+    /// * `symbol` - The canonical symbol of the currency pair.
+    ///
+    /// * `metrics` - The [metrics](SummaryMetrics) of the snapshot.
+    pub fn write(&self, timestamp_ms: i64, symbol: &str, metrics: &SummaryMetrics) -> rusqlite::Result<()> {
+        self.connection.lock().unwrap().execute(INSERT_SNAPSHOT, params![
+            timestamp_ms,
+            symbol,
+            metrics.best_bid,
+            metrics.best_bid_exchange,
+            metrics.best_ask,
+            metrics.best_ask_exchange,
+            metrics.spread,
+            metrics.bid_depth,
+            metrics.ask_depth,
+        ])?;
+        Ok(())
+    }
+}
+
+#[tonic::async_trait]
+impl SummarySink for SqliteSink {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn sample_interval(&self) -> Option<Duration> {
+        Some(self.interval)
+    }
+
+    async fn on_summary(&mut self, summary: &Summary) -> SinkResult {
+        let timestamp_ms = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default();
+        let metrics = SummaryMetrics::from(summary);
+        let symbol = summary.symbol.clone();
+        let sink = self.clone();
+        match tokio::task::spawn_blocking(move || sink.write(timestamp_ms, &symbol, &metrics)).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(error)) => Err(SinkError::Transient(format!("error writing snapshot to SQLite: {:?}", error))),
+            Err(error) => Err(SinkError::Fatal(format!("SQLite write failed: {:?}", error))),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_snapshot() {
+        let sink = SqliteSink::open(Path::new(":memory:")).unwrap();
+        let metrics = SummaryMetrics {
+            best_bid: 99.0,
+            best_bid_exchange: "test1".to_string(),
+            best_ask: 100.0,
+            best_ask_exchange: "test2".to_string(),
+            mid: 99.5,
+            spread: 1.0,
+            bid_depth: 3.0,
+            ask_depth: 4.0,
+        };
+        sink.write(1000, "ETH-BTC", &metrics).unwrap();
+        let row: (i64, String, f64, String, f64) = sink.connection.lock().unwrap().query_row(
+            "SELECT timestamp_ms, symbol, best_bid, best_ask_exchange, ask_depth FROM snapshots",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))
+        ).unwrap();
+        assert_eq!(row, (1000, "ETH-BTC".to_string(), 99.0, "test2".to_string(), 4.0));
+    }
+
+    #[test]
+    fn test_partial_config() {
+        let config: SqliteSinkConfig = serde_json::from_str(r#"{"interval_ms": 5000}"#).unwrap();
+        assert_eq!(config, SqliteSinkConfig { interval_ms: 5000, ..SqliteSinkConfig::default() });
+        let sink = SqliteSink::from_config(&SqliteSinkConfig { file: ":memory:".to_string(), ..config }).unwrap();
+        assert_eq!(sink.sample_interval(), Some(Duration::from_millis(5000)));
+    }
+}
@@ -0,0 +1,149 @@
+//! Scoring of the feed latency of the exchanges, from the age of their latest book: each
+//! published level is annotated with the [latency class](LatencyClass) of its exchange, so that
+//! consumers can discount the levels of the exchanges lagging right now.
+//! The thresholds of the classes are read from a JSON file.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::time::{Duration, Instant};
+
+use crate::config::{DocumentedConfig, Validate, Validator};
+use crate::core::ExchangeId;
+use crate::orderbook::LatencyClass;
+
+
+/// Default maximum age of the latest book of a fresh exchange, in milliseconds.
+const DEFAULT_FRESH_MS: u64 = 500;
+/// Default age of the latest book beyond which an exchange is stale, in milliseconds.
+const DEFAULT_STALE_MS: u64 = 5000;
+
+
+/// Thresholds of the [latency classes](LatencyClass). Missing values take their defaults.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct StalenessThresholds {
+    /// Maximum age of the latest book of a [fresh](LatencyClass::Fresh) exchange, in milliseconds.
+    pub fresh_ms: u64,
+    /// Age of the latest book beyond which an exchange is [stale](LatencyClass::Stale), in milliseconds.
+    pub stale_ms: u64,
+}
+
+impl Default for StalenessThresholds {
+    fn default() -> Self {
+        Self {
+            fresh_ms: DEFAULT_FRESH_MS,
+            stale_ms: DEFAULT_STALE_MS,
+        }
+    }
+}
+
+impl Validate for StalenessThresholds {
+    fn validate(&self, validator: &mut Validator) {
+        validator.not_above("fresh_ms", self.fresh_ms, "stale_ms", self.stale_ms);
+    }
+}
+
+impl DocumentedConfig for StalenessThresholds {
+    const DESCRIPTION: &'static str = "Thresholds of the latency classes of the exchanges.";
+    const FIELDS: &'static [(&'static str, &'static str)] = &[
+        ("fresh_ms", "Maximum age of the latest book of a fresh exchange, in milliseconds."),
+        ("stale_ms", "Age of the latest book beyond which an exchange is stale, in milliseconds."),
+    ];
+}
+
+/// Scorer of the latency class of each exchange, from the time of its latest book.
+#[derive(Clone, Debug, Default)]
+pub struct StalenessScorer {
+    /// Thresholds of the classes.
+    thresholds: StalenessThresholds,
+    /// Time of the latest book of each exchange.
+    last_updates: HashMap<ExchangeId, Instant>,
+}
+
+impl StalenessScorer {
+    /// Create a new [StalenessScorer](StalenessScorer) object.
+    ///
+    /// # Arguments
+    ///
+    /// * `thresholds` - The [StalenessThresholds](StalenessThresholds).
+    pub fn new(thresholds: StalenessThresholds) -> Self {
+        Self { thresholds, last_updates: HashMap::new() }
+    }
+
+    /// Record a book received from an exchange, even if identical to the previous one.
+    ///
+    /// # Arguments
+    ///
+    /// * `exchange_code` - The exchange code.
+    ///
+    /// * `now` - The time of reception.
+    pub fn record(&mut self, exchange_code: &ExchangeId, now: Instant) {
+        match self.last_updates.get_mut(exchange_code) {
+            Some(last_update) => *last_update = now,
+            None => {
+                self.last_updates.insert(exchange_code.clone(), now);
+            }
+        }
+    }
+
+    /// Forget an exchange, e.g. disconnected.
+    ///
+    /// # Arguments
+    ///
+    /// * `exchange_code` - The exchange code.
+    pub fn remove(&mut self, exchange_code: &str) {
+        self.last_updates.remove(exchange_code);
+    }
+
+    /// Latency class of an exchange.
+    ///
+    /// # Arguments
+    ///
+    /// * `exchange_code` - The exchange code.
+    ///
+    /// * `now` - The current time.
+    ///
+    /// # Returns
+    ///
+    /// The [LatencyClass](LatencyClass), [Unknown](LatencyClass::Unknown) if no book was recorded.
+    pub fn class(&self, exchange_code: &str, now: Instant) -> LatencyClass {
+        let Some(&last_update) = self.last_updates.get(exchange_code) else {
+            return LatencyClass::Unknown;
+        };
+        let age = now.saturating_duration_since(last_update);
+        if age <= Duration::from_millis(self.thresholds.fresh_ms) {
+            LatencyClass::Fresh
+        } else if age <= Duration::from_millis(self.thresholds.stale_ms) {
+            LatencyClass::Normal
+        } else {
+            LatencyClass::Stale
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classes() {
+        let mut scorer = StalenessScorer::new(StalenessThresholds { fresh_ms: 100, stale_ms: 1000 });
+        let start = Instant::now();
+        assert_eq!(scorer.class("binance", start), LatencyClass::Unknown);
+        scorer.record(&"binance".into(), start);
+        assert_eq!(scorer.class("binance", start + Duration::from_millis(100)), LatencyClass::Fresh);
+        assert_eq!(scorer.class("binance", start + Duration::from_millis(101)), LatencyClass::Normal);
+        assert_eq!(scorer.class("binance", start + Duration::from_millis(1001)), LatencyClass::Stale);
+        scorer.record(&"binance".into(), start + Duration::from_millis(1001));
+        assert_eq!(scorer.class("binance", start + Duration::from_millis(1001)), LatencyClass::Fresh);
+        scorer.remove("binance");
+        assert_eq!(scorer.class("binance", start + Duration::from_millis(1001)), LatencyClass::Unknown);
+    }
+
+    #[test]
+    fn test_partial_config() {
+        let thresholds: StalenessThresholds = serde_json::from_str(r#"{"stale_ms": 2000}"#).unwrap();
+        assert_eq!(thresholds, StalenessThresholds { stale_ms: 2000, ..StalenessThresholds::default() });
+    }
+}
@@ -0,0 +1,209 @@
+//! Changes of the status of the exchange connections, notified by the
+//! [exchange adapters](crate::exchange::ExchangeAdapter) so that operations can be
+//! alerted without scraping the logs.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast, watch};
+#[cfg(feature = "webhook")]
+use log::{error, info};
+
+use crate::core::ExchangeId;
+#[cfg(feature = "webhook")]
+use crate::webhook::Webhook;
+
+
+/// Sender shared by the adapters notifying [status events](ExchangeStatusEvent).
+pub type StatusSender = broadcast::Sender<ExchangeStatusEvent>;
+
+/// Receiver of the latest [status](ExchangeStatus) of each exchange which notified any.
+pub type StatusBoard = watch::Receiver<BTreeMap<ExchangeId, ExchangeStatus>>;
+
+/// Status of the connection to an exchange.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ExchangeStatus {
+    /// Connected and subscribed.
+    Up,
+    /// The WebSocket service cannot be reached: snapshots are polled from the REST fallback,
+    /// less frequently.
+    Degraded,
+    /// Connection lost, reconnecting.
+    Down,
+    /// The adapter failed and is being restarted: the levels of the exchange are removed
+    /// from the book.
+    Ejected,
+    /// The budget of connection attempts is exhausted: no attempt is made during a cool-down.
+    ReconnectsThrottled {
+        /// Duration of the cool-down, in milliseconds.
+        cool_down_ms: u64,
+    },
+    /// The primary endpoint, or the one in use, is unreachable or degraded: the next
+    /// connection is to another endpoint.
+    FailedOver {
+        /// WebSocket URL of the endpoint.
+        endpoint: String,
+    },
+    /// Disconnected during a scheduled maintenance window, as expected.
+    ScheduledOffline {
+        /// End of the window, in milliseconds since the UNIX epoch.
+        until_ms: u64,
+    },
+    /// Consecutive messages which could not be parsed.
+    ParseFailures {
+        /// Number of messages.
+        count: u64,
+    },
+}
+
+impl ExchangeStatus {
+    /// Name of the status, as serialized.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExchangeStatus::Up => "up",
+            ExchangeStatus::Degraded => "degraded",
+            ExchangeStatus::Down => "down",
+            ExchangeStatus::Ejected => "ejected",
+            ExchangeStatus::ReconnectsThrottled { .. } => "reconnects_throttled",
+            ExchangeStatus::FailedOver { .. } => "failed_over",
+            ExchangeStatus::ScheduledOffline { .. } => "scheduled_offline",
+            ExchangeStatus::ParseFailures { .. } => "parse_failures",
+        }
+    }
+
+    /// Whether the exchange is delivering data, possibly [degraded](ExchangeStatus::Degraded).
+    pub fn is_healthy(&self) -> bool {
+        matches!(self, ExchangeStatus::Up | ExchangeStatus::Degraded)
+    }
+}
+
+/// A change of the [status](ExchangeStatus) of an exchange.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ExchangeStatusEvent {
+    /// Exchange code.
+    pub exchange: ExchangeId,
+    /// The new status.
+    #[serde(flatten)]
+    pub status: ExchangeStatus,
+    /// Time of the event, in milliseconds since the UNIX epoch.
+    pub timestamp_ms: u64,
+}
+
+impl ExchangeStatusEvent {
+    /// Create a new [ExchangeStatusEvent](ExchangeStatusEvent) object, at the current time.
+    ///
+    /// # Arguments
+    ///
+    /// * `exchange` - The exchange code.
+    ///
+    /// * `status` - The [ExchangeStatus](ExchangeStatus).
+    pub fn new(exchange: impl Into<ExchangeId>, status: ExchangeStatus) -> Self {
+        let timestamp_ms = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        Self { exchange: exchange.into(), status, timestamp_ms }
+    }
+}
+
+/// Spawn a task keeping the latest status of each exchange.
+///
+/// # Arguments
+///
+/// * `events` - A [broadcast::Receiver](broadcast::Receiver) of [status events](ExchangeStatusEvent).
+///
+/// # Returns
+///
+/// A [StatusBoard](StatusBoard). The task stops once all the receivers are dropped, or the
+/// events closed.
+pub fn spawn_status_board(mut events: broadcast::Receiver<ExchangeStatusEvent>) -> StatusBoard {
+    let (sender, receiver) = watch::channel(BTreeMap::new());
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    if sender.is_closed() {
+                        break;
+                    }
+                    sender.send_modify(|board| {
+                        board.insert(event.exchange, event.status);
+                    });
+                },
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+    receiver
+}
+
+/// Spawn a task posting each status event to a webhook.
+///
+/// # Arguments
+///
+/// * `events` - A [broadcast::Receiver](broadcast::Receiver) of [status events](ExchangeStatusEvent).
+///
+/// * `webhook` - The [Webhook](Webhook).
+#[cfg(feature = "webhook")]
+pub fn spawn_webhook(mut events: broadcast::Receiver<ExchangeStatusEvent>, webhook: Webhook) {
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    if let Err(error) = webhook.post(&event).await {
+                        error!("Error posting exchange status to webhook: {:?}", error);
+                    }
+                },
+                Err(broadcast::error::RecvError::Lagged(count)) =>
+                    error!("Webhook lagging, {} exchange status events dropped", count),
+                Err(broadcast::error::RecvError::Closed) => {
+                    info!("Exchange status closed, webhook stopped");
+                    break;
+                },
+            }
+        }
+    });
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_json() {
+        let event = ExchangeStatusEvent { exchange: "binance".into(), status: ExchangeStatus::ParseFailures { count: 10 }, timestamp_ms: 1000 };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"exchange":"binance","status":"parse_failures","count":10,"timestamp_ms":1000}"#
+        );
+        let event = ExchangeStatusEvent { exchange: "bitstamp".into(), status: ExchangeStatus::Down, timestamp_ms: 1000 };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"exchange":"bitstamp","status":"down","timestamp_ms":1000}"#
+        );
+        let event = ExchangeStatusEvent { exchange: "binance".into(), status: ExchangeStatus::FailedOver { endpoint: "wss://host/ws".to_string() }, timestamp_ms: 1000 };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"exchange":"binance","status":"failed_over","endpoint":"wss://host/ws","timestamp_ms":1000}"#
+        );
+        let event = ExchangeStatusEvent { exchange: "binance".into(), status: ExchangeStatus::ScheduledOffline { until_ms: 2000 }, timestamp_ms: 1000 };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"exchange":"binance","status":"scheduled_offline","until_ms":2000,"timestamp_ms":1000}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_status_board() {
+        let (sender, _) = broadcast::channel(8);
+        let mut board = spawn_status_board(sender.subscribe());
+        sender.send(ExchangeStatusEvent::new("binance", ExchangeStatus::Up)).unwrap();
+        sender.send(ExchangeStatusEvent::new("bitstamp", ExchangeStatus::Up)).unwrap();
+        sender.send(ExchangeStatusEvent::new("binance", ExchangeStatus::Down)).unwrap();
+        let board = board.wait_for(|board| board.get("binance") == Some(&ExchangeStatus::Down)).await.unwrap().clone();
+        assert_eq!(board.get("bitstamp"), Some(&ExchangeStatus::Up));
+        assert!(!board["binance"].is_healthy());
+        assert_eq!(board["binance"].name(), "down");
+    }
+}
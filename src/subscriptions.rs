@@ -0,0 +1,183 @@
+//! Registry of the subscriptions open to the exchanges, so that the server never holds more
+//! than one subscription per venue and symbol, even when adapters are restarted or replaced
+//! while the previous connection is still closing. An [exchange adapter](crate::exchange::ExchangeAdapter)
+//! registered with it takes a [lease](SubscriptionLease) before connecting, waiting for any
+//! other holder to release it, keeps it across reconnections and releases it once stopped.
+
+use log::warn;
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+use crate::core::ExchangeId;
+
+
+/// Venue and symbol of a subscription.
+type SubscriptionKey = (ExchangeId, String);
+
+/// Registry of the subscriptions open. Clones share the registry.
+#[derive(Clone, Debug, Default)]
+pub struct SubscriptionRegistry {
+    /// Venue and symbol of each subscription leased.
+    active: Arc<Mutex<BTreeSet<SubscriptionKey>>>,
+    /// Notified when a lease is released.
+    released: Arc<Notify>,
+}
+
+impl SubscriptionRegistry {
+    /// Create a new [SubscriptionRegistry](SubscriptionRegistry) object, empty.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take the lease of a subscription, if not held.
+    ///
+    /// # Arguments
+    ///
+    /// * `venue` - The exchange code.
+    ///
+    /// * `symbol` - The canonical symbol.
+    ///
+    /// # Returns
+    ///
+    /// The [SubscriptionLease](SubscriptionLease), or [None](None) if already held.
+    pub fn try_acquire(&self, venue: impl Into<ExchangeId>, symbol: &str) -> Option<SubscriptionLease> {
+        let key = (venue.into(), symbol.to_string());
+        if !self.active.lock().unwrap().insert(key.clone()) {
+            return None;
+        }
+        Some(SubscriptionLease { registry: self.clone(), key })
+    }
+
+    /// Take the lease of a subscription, waiting until released if held.
+    ///
+    /// # Arguments
+    ///
+    /// * `venue` - The exchange code.
+    ///
+    /// * `symbol` - The canonical symbol.
+    ///
+    /// # Returns
+    ///
+    /// The [SubscriptionLease](SubscriptionLease).
+    pub async fn acquire(&self, venue: impl Into<ExchangeId>, symbol: &str) -> SubscriptionLease {
+        let venue = venue.into();
+        let mut warned = false;
+        loop {
+            // registered before trying, not to miss a release in between
+            let released = self.released.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+            if let Some(lease) = self.try_acquire(&venue, symbol) {
+                return lease;
+            }
+            if !warned {
+                warn!("Duplicate subscription to {} {}, waiting for the open one to close", venue, symbol);
+                warned = true;
+            }
+            released.await;
+        }
+    }
+
+    /// Whether the lease of a subscription is held.
+    ///
+    /// # Arguments
+    ///
+    /// * `venue` - The exchange code.
+    ///
+    /// * `symbol` - The canonical symbol.
+    pub fn is_active(&self, venue: impl Into<ExchangeId>, symbol: &str) -> bool {
+        self.active.lock().unwrap().contains(&(venue.into(), symbol.to_string()))
+    }
+
+    /// Number of subscriptions leased.
+    pub fn len(&self) -> usize {
+        self.active.lock().unwrap().len()
+    }
+
+    /// Whether no subscription is leased.
+    pub fn is_empty(&self) -> bool {
+        self.active.lock().unwrap().is_empty()
+    }
+}
+
+/// Exclusive right to subscribe to a symbol of a venue, released when dropped.
+#[derive(Debug)]
+pub struct SubscriptionLease {
+    /// Registry the lease was taken from.
+    registry: SubscriptionRegistry,
+    /// Venue and symbol of the subscription.
+    key: SubscriptionKey,
+}
+
+impl Drop for SubscriptionLease {
+    fn drop(&mut self) {
+        self.registry.active.lock().unwrap().remove(&self.key);
+        self.registry.released.notify_waiters();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::time::{timeout, Duration};
+
+    #[test]
+    fn test_try_acquire() {
+        let registry = SubscriptionRegistry::new();
+        let lease = registry.try_acquire("binance", "ETH-BTC").unwrap();
+        assert!(registry.try_acquire("binance", "ETH-BTC").is_none());
+        let other = registry.try_acquire("bitstamp", "ETH-BTC").unwrap();
+        assert!(registry.clone().try_acquire("binance", "BTC-USDT").is_some());
+        assert_eq!(registry.len(), 2);
+        drop(lease);
+        assert!(!registry.is_active("binance", "ETH-BTC"));
+        assert!(registry.is_active("bitstamp", "ETH-BTC"));
+        drop(other);
+        assert!(registry.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_acquire_waits_for_release() {
+        let registry = SubscriptionRegistry::new();
+        let lease = registry.acquire("binance", "ETH-BTC").await;
+        let waiting = tokio::spawn({
+            let registry = registry.clone();
+            async move { registry.acquire("binance", "ETH-BTC").await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+        drop(lease);
+        let lease = timeout(Duration::from_secs(1), waiting).await.expect("lease not acquired").unwrap();
+        assert!(registry.try_acquire("binance", "ETH-BTC").is_none());
+        drop(lease);
+        assert!(registry.is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_churn() {
+        let registry = SubscriptionRegistry::new();
+        let holders = Arc::new(AtomicUsize::new(0));
+        let tasks: Vec<_> = (0..32).map(|i| {
+            let registry = registry.clone();
+            let holders = holders.clone();
+            tokio::spawn(async move {
+                for _ in 0..50 {
+                    let lease = registry.acquire("binance", "ETH-BTC").await;
+                    assert_eq!(holders.fetch_add(1, Ordering::SeqCst), 0, "two subscriptions open");
+                    if i % 2 == 0 {
+                        tokio::task::yield_now().await;
+                    }
+                    holders.fetch_sub(1, Ordering::SeqCst);
+                    drop(lease);
+                }
+            })
+        }).collect();
+        for task in tasks {
+            timeout(Duration::from_secs(10), task).await.expect("lease starved").unwrap();
+        }
+        assert!(registry.is_empty());
+    }
+}
@@ -0,0 +1,174 @@
+//! Optional fields of the published [summaries](Summary), so that latency-sensitive consumers
+//! can subscribe to the smallest possible messages. Levels and symbol are always included;
+//! fields left out take their Protobuf default values, which are not transmitted.
+
+use std::fmt;
+
+use crate::orderbook::{LatencyClass, Summary};
+
+
+/// Name of the spread field in a field list.
+const SPREAD_FIELD: &str = "spread";
+/// Name of the depth bands field in a field list.
+const DEPTH_FIELD: &str = "depth";
+/// Name of the exchange of each level in a field list.
+const VENUES_FIELD: &str = "venues";
+/// Name of the contributing and expected exchange counts in a field list.
+const EXCHANGE_COUNTS_FIELD: &str = "exchange_counts";
+/// Name of the latency class of the exchange of each level in a field list.
+const LATENCY_FIELD: &str = "latency";
+/// Name of the in-band status frames in a field list.
+const STATUS_FIELD: &str = "status";
+
+
+/// Optional fields included in the summaries, all of them by default.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SummaryFields {
+    /// Difference between best ask and best bid.
+    pub spread: bool,
+    /// Total depth within each distance from the mid price.
+    pub depth: bool,
+    /// Exchange of each level.
+    pub venues: bool,
+    /// Number of contributing and of expected exchanges.
+    pub exchange_counts: bool,
+    /// Latency class of the exchange of each level.
+    pub latency: bool,
+    /// In-band [status frames](crate::orderbook::StreamStatus) notifying the changes of quality of
+    /// the stream.
+    pub status: bool,
+}
+
+impl Default for SummaryFields {
+    fn default() -> Self {
+        Self { spread: true, depth: true, venues: true, exchange_counts: true, latency: true, status: true }
+    }
+}
+
+impl fmt::Display for SummaryFields {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields: Vec<&str> = [
+            (self.spread, SPREAD_FIELD),
+            (self.depth, DEPTH_FIELD),
+            (self.venues, VENUES_FIELD),
+            (self.exchange_counts, EXCHANGE_COUNTS_FIELD),
+            (self.latency, LATENCY_FIELD),
+            (self.status, STATUS_FIELD),
+        ].into_iter().filter_map(|(included, name)| included.then_some(name)).collect();
+        write!(f, "{}", fields.join(","))
+    }
+}
+
+impl SummaryFields {
+    /// Only the levels and the symbol.
+    pub fn none() -> Self {
+        Self { spread: false, depth: false, venues: false, exchange_counts: false, latency: false, status: false }
+    }
+
+    /// Parse a comma separated list of the optional fields to include, e.g. `spread,venues`.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The field list, empty for none of them.
+    ///
+    /// # Returns
+    ///
+    /// A [SummaryFields](SummaryFields) object, or an error naming an unknown field.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut fields = Self::none();
+        for name in value.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            match name.to_ascii_lowercase().as_str() {
+                SPREAD_FIELD => fields.spread = true,
+                DEPTH_FIELD => fields.depth = true,
+                VENUES_FIELD => fields.venues = true,
+                EXCHANGE_COUNTS_FIELD => fields.exchange_counts = true,
+                LATENCY_FIELD => fields.latency = true,
+                STATUS_FIELD => fields.status = true,
+                _ => return Err(format!("unknown summary field: {}", name)),
+            }
+        }
+        Ok(fields)
+    }
+
+    /// Reset the fields left out of a complete summary to their default values.
+    ///
+    /// # Arguments
+    ///
+    /// * `summary` - The [Summary](Summary) to trim.
+    pub fn trim(&self, summary: &mut Summary) {
+        if !self.spread {
+            summary.spread = 0.0;
+        }
+        if !self.depth {
+            summary.depth.clear();
+        }
+        if !self.venues {
+            for level in summary.bids.iter_mut().chain(summary.asks.iter_mut()) {
+                level.exchange.clear();
+            }
+        }
+        if !self.exchange_counts {
+            summary.contributing_exchanges = 0;
+            summary.expected_exchanges = 0;
+        }
+        if !self.latency {
+            for level in summary.bids.iter_mut().chain(summary.asks.iter_mut()) {
+                level.latency_class = LatencyClass::Unknown as i32;
+            }
+        }
+        if !self.status {
+            summary.frame = None;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderbook::{DepthBand, Level};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(SummaryFields::parse(""), Ok(SummaryFields::none()));
+        assert_eq!(
+            SummaryFields::parse(" Spread, exchange_counts"),
+            Ok(SummaryFields { spread: true, exchange_counts: true, ..SummaryFields::none() }),
+        );
+        assert!(SummaryFields::parse("spread,timestamp").is_err());
+        assert_eq!(SummaryFields::parse(&SummaryFields::default().to_string()), Ok(SummaryFields::default()));
+    }
+
+    #[test]
+    fn test_trim() {
+        let level = Level { exchange: "binance".to_string(), price: 100.0, amount: 1.0, order_count: None, latency_class: LatencyClass::Fresh as i32 };
+        let mut summary = Summary {
+            spread: 1.0,
+            bids: vec![level.clone()],
+            asks: vec![Level { price: 101.0, ..level }],
+            symbol: "ETH-BTC".to_string(),
+            depth: vec![DepthBand { bps: 10, bid_amount: 1.0, ask_amount: 1.0 }],
+            contributing_exchanges: 1,
+            expected_exchanges: 2,
+            fair_values: vec![],
+            latency_budget: None,
+            frame: None,
+            stale: false,
+            stale_age_ms: 0,
+        };
+        let complete = summary.clone();
+        SummaryFields::default().trim(&mut summary);
+        assert_eq!(summary, complete);
+        SummaryFields { venues: true, ..SummaryFields::none() }.trim(&mut summary);
+        let trimmed_level = Level { latency_class: LatencyClass::Unknown as i32, ..complete.bids[0].clone() };
+        assert_eq!(summary, Summary {
+            spread: 0.0,
+            bids: vec![trimmed_level.clone()],
+            asks: vec![Level { price: 101.0, ..trimmed_level }],
+            depth: vec![],
+            contributing_exchanges: 0,
+            expected_exchanges: 0,
+            ..complete
+        });
+    }
+}
@@ -0,0 +1,138 @@
+//! Sampled logging of published summaries, for lightweight production observability:
+//! one compact line every N summaries or every interval, instead of one per summary.
+
+use tokio::time::{Duration, Instant};
+
+use crate::clock::SharedClock;
+use crate::orderbook::Summary;
+
+
+/// Default interval between logged summaries, in milliseconds.
+pub const DEFAULT_SUMMARY_LOG_INTERVAL_MS: u64 = 60_000;
+
+/// Rate at which summaries are logged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SummaryLogSampling {
+    /// Log one summary every N, starting with the first one.
+    EveryNth(u64),
+    /// Log at most one summary per interval, starting with the first one.
+    Interval(Duration),
+}
+
+/// Decides which summaries are logged.
+pub struct SummarySampler {
+    /// Sampling rate.
+    sampling: SummaryLogSampling,
+    /// Number of summaries seen so far.
+    count: u64,
+    /// Time the last summary was logged.
+    last_logged: Option<Instant>,
+    /// Time source for interval sampling.
+    clock: SharedClock,
+}
+
+impl SummarySampler {
+    /// Create a new [SummarySampler](SummarySampler) object.
+    ///
+    /// # Arguments
+    ///
+    /// * `sampling` - The [SummaryLogSampling](SummaryLogSampling).
+    ///
+    /// * `clock` - Time source for interval sampling.
+    pub fn new(sampling: SummaryLogSampling, clock: SharedClock) -> Self {
+        Self { sampling, count: 0, last_logged: None, clock }
+    }
+
+    /// Account for a published summary.
+    ///
+    /// # Returns
+    ///
+    /// A [boolean](bool) value: [true](true) if the summary should be logged.
+    pub fn sample(&mut self) -> bool {
+        self.count += 1;
+        match self.sampling {
+            SummaryLogSampling::EveryNth(n) => n > 0 && (self.count - 1).is_multiple_of(n),
+            SummaryLogSampling::Interval(interval) => {
+                let now = self.clock.now();
+                let due = self.last_logged.map(|last| now - last >= interval).unwrap_or(true);
+                if due {
+                    self.last_logged = Some(now);
+                }
+                due
+            },
+        }
+    }
+
+    /// Number of summaries seen so far.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+/// Format a summary on a single compact line: best levels, spread, and number of levels per side.
+///
+/// # Arguments
+///
+/// * `summary` - A [Summary](Summary).
+///
+/// # Returns
+///
+/// The formatted line.
+pub fn format_summary(summary: &Summary) -> String {
+    let best = |levels: &[crate::orderbook::Level]| levels.first()
+        .map(|l| format!("{}x{}@{}", l.price, l.amount, l.exchange))
+        .unwrap_or_else(|| "-".to_string());
+    format!(
+        "{} bid {} ask {} spread {} levels {}/{} exchanges {}/{}",
+        summary.symbol, best(&summary.bids), best(&summary.asks), summary.spread, summary.bids.len(), summary.asks.len(),
+        summary.contributing_exchanges, summary.expected_exchanges
+    )
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::clock::ManualClock;
+    use crate::orderbook::Level;
+
+    #[test]
+    fn test_sample_every_nth() {
+        let mut sampler = SummarySampler::new(SummaryLogSampling::EveryNth(3), Arc::new(ManualClock::new()));
+        let sampled: Vec<bool> = (0..7).map(|_| sampler.sample()).collect();
+        assert_eq!(sampled, vec![true, false, false, true, false, false, true]);
+        assert_eq!(sampler.count(), 7);
+    }
+
+    #[test]
+    fn test_sample_interval() {
+        let clock = Arc::new(ManualClock::new());
+        let mut sampler = SummarySampler::new(SummaryLogSampling::Interval(Duration::from_secs(10)), clock.clone());
+        assert!(sampler.sample());
+        clock.advance(Duration::from_secs(9));
+        assert!(!sampler.sample());
+        clock.advance(Duration::from_secs(1));
+        assert!(sampler.sample());
+        assert!(!sampler.sample());
+    }
+
+    #[test]
+    fn test_format_summary() {
+        let summary = Summary {
+            spread: 1.0,
+            bids: vec![Level { exchange: "test1".to_string(), price: 99.0, amount: 1.5, order_count: None, latency_class: 0 }],
+            asks: vec![],
+            symbol: "ETH-BTC".to_string(),
+            depth: vec![],
+            contributing_exchanges: 1,
+            expected_exchanges: 2,
+            fair_values: vec![],
+            latency_budget: None,
+            frame: None,
+            stale: false,
+            stale_age_ms: 0,
+        };
+        assert_eq!(format_summary(&summary), "ETH-BTC bid 99x1.5@test1 ask - spread 1 levels 1/0 exchanges 1/2");
+    }
+}
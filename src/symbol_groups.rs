@@ -0,0 +1,167 @@
+//! Named groups of symbols, read from a JSON file, and selection of the symbols served by a
+//! list of symbols, groups and wildcard patterns, so that a client can subscribe to many
+//! symbols on a single stream, each summary carrying its symbol.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::config::{DocumentedConfig, Validate, Validator};
+use crate::symbols::canonicalize;
+
+
+/// Wildcard matching any sequence of characters in a symbol pattern.
+const WILDCARD: char = '*';
+
+
+/// Named groups of canonical symbols.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct SymbolGroups {
+    /// Symbols of each group, by group name.
+    #[serde(default)]
+    groups: BTreeMap<String, Vec<String>>,
+}
+
+impl SymbolGroups {
+    /// The groups with their symbols canonicalized, those not recognized being dropped.
+    pub fn canonical(self) -> Self {
+        let groups = self.groups.into_iter()
+            .map(|(name, symbols)| (name, symbols.iter().filter_map(|symbol| canonicalize(symbol)).collect()))
+            .collect();
+        Self { groups }
+    }
+
+    /// Define a group.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The group name.
+    ///
+    /// * `symbols` - The symbols of the group, in any accepted notation; those not recognized
+    ///   are ignored.
+    ///
+    /// # Returns
+    ///
+    /// The modified [SymbolGroups](SymbolGroups).
+    pub fn with_group(mut self, name: &str, symbols: &[&str]) -> Self {
+        self.groups.insert(name.to_string(), symbols.iter().filter_map(|symbol| canonicalize(symbol)).collect());
+        self
+    }
+
+    /// Select the symbols served matching a list of symbols, groups and wildcard patterns.
+    ///
+    /// # Arguments
+    ///
+    /// * `selection` - Comma separated group names, patterns where `*` matches any characters,
+    ///   e.g. `*-USDT`, and symbols in any accepted notation.
+    ///
+    /// * `served` - The canonical symbols served.
+    ///
+    /// # Returns
+    ///
+    /// The symbols served selected, in the order served, possibly none, or an error naming an
+    /// item which is neither a group, a pattern nor a symbol.
+    pub fn select(&self, selection: &str, served: &[String]) -> Result<Vec<String>, String> {
+        let mut selected = vec![false; served.len()];
+        let items: Vec<&str> = selection.split(',').map(str::trim).filter(|item| !item.is_empty()).collect();
+        if items.is_empty() {
+            return Err(String::from("empty symbol selection"));
+        }
+        for item in items {
+            let matches: Box<dyn Fn(&str) -> bool> = if let Some(group) = self.groups.get(item) {
+                Box::new(move |symbol| group.iter().any(|member| member == symbol))
+            } else if item.contains(WILDCARD) {
+                let pattern = item.to_uppercase();
+                Box::new(move |symbol| matches_pattern(&pattern, symbol))
+            } else {
+                let symbol = canonicalize(item).ok_or_else(|| format!("unknown symbol or group: {}", item))?;
+                Box::new(move |served| served == symbol)
+            };
+            for (is_selected, symbol) in selected.iter_mut().zip(served) {
+                *is_selected |= matches(symbol);
+            }
+        }
+        Ok(served.iter().zip(selected).filter(|(_, is_selected)| *is_selected).map(|(symbol, _)| symbol.clone()).collect())
+    }
+}
+
+impl Validate for SymbolGroups {
+    fn validate(&self, validator: &mut Validator) {
+        for (name, symbols) in &self.groups {
+            for symbol in symbols.iter().filter(|symbol| canonicalize(symbol).is_none()) {
+                validator.error(&format!("groups.{}", name), &format!("has unknown symbol {}", symbol));
+            }
+        }
+    }
+}
+
+impl DocumentedConfig for SymbolGroups {
+    const DESCRIPTION: &'static str = "Groups of symbols clients can subscribe to.";
+    const FIELDS: &'static [(&'static str, &'static str)] = &[
+        ("groups", "Symbols of each group, by group name, e.g. {\"majors\": [\"BTC-USDT\", \"ETH-USDT\"]}."),
+    ];
+}
+
+/// Match a symbol with a pattern where [WILDCARD](WILDCARD) matches any sequence of characters.
+fn matches_pattern(pattern: &str, symbol: &str) -> bool {
+    let mut parts = pattern.split(WILDCARD);
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = symbol.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    fn served() -> Vec<String> {
+        vec!["BTC-USDT".to_string(), "ETH-USDT".to_string(), "ETH-BTC".to_string()]
+    }
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("*-USDT", "BTC-USDT"));
+        assert!(matches_pattern("ETH-*", "ETH-BTC"));
+        assert!(matches_pattern("*", "ETH-BTC"));
+        assert!(matches_pattern("E*-*T", "ETH-USDT"));
+        assert!(!matches_pattern("*-USDT", "ETH-BTC"));
+        assert!(!matches_pattern("ETH-BTC", "ETH-BTCX"));
+        assert!(matches_pattern("B*TC", "BTC"));
+        assert!(!matches_pattern("BT*TC", "BTC"));
+    }
+
+    #[test]
+    fn test_select() {
+        let groups = SymbolGroups::default().with_group("majors", &["btcusdt", "ETH/USDT", "SOL-USDT"]);
+        assert_eq!(groups.select("majors", &served()), Ok(vec!["BTC-USDT".to_string(), "ETH-USDT".to_string()]));
+        assert_eq!(groups.select("eth-*", &served()), Ok(vec!["ETH-USDT".to_string(), "ETH-BTC".to_string()]));
+        assert_eq!(groups.select("ethbtc, majors", &served()), Ok(served()));
+        assert_eq!(groups.select("SOL-USDT", &served()), Ok(vec![]));
+        assert!(groups.select("minors", &served()).is_err());
+        assert!(groups.select(" , ", &served()).is_err());
+    }
+
+    #[test]
+    fn test_load_canonicalizes() {
+        let path = std::env::temp_dir().join(format!("symbol_groups_{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"groups": {"majors": ["btc_usdt", "XETHXXBT"]}}"#).unwrap();
+        let groups = config::load::<SymbolGroups>(&path).unwrap().canonical();
+        assert_eq!(groups, SymbolGroups::default().with_group("majors", &["BTC-USDT", "ETH-BTC"]));
+        std::fs::write(&path, r#"{"groups": {"majors": ["bitcoin"]}}"#).unwrap();
+        assert!(config::load::<SymbolGroups>(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,157 @@
+//! Currency pair symbols: parsing of the common notations used by exchanges and
+//! clients, and canonical representation.
+//!
+//! Accepted notations, case insensitive:
+//! * Separated: `ETH-BTC`, `ETH/BTC`, `ETH_BTC`
+//! * Concatenated: `ETHBTC`, split on a known counter currency
+//! * Kraken style: `XETHXXBT`, `XXBTZUSD`
+//!
+//! The canonical symbol is `MAIN-COUNTER` in upper case, e.g. `ETH-BTC`.
+
+use crate::core::CurrencyPair;
+
+
+/// Separators accepted between the two currencies.
+const SEPARATORS: [char; 3] = ['-', '/', '_'];
+
+/// Known counter currencies, used to split concatenated symbols.
+/// Longer codes come first, so that e.g. `USDT` is tried before `USD`.
+const COUNTER_CURRENCIES: [&str; 14] = [
+    "FDUSD", "USDT", "USDC", "BUSD", "TUSD",
+    "BTC", "ETH", "BNB", "EUR", "USD", "GBP", "JPY", "TRY", "DAI",
+];
+
+/// Currency code aliases, mapped to the canonical code.
+const CURRENCY_ALIASES: [(&str, &str); 2] = [
+    ("XBT", "BTC"),
+    ("XDG", "DOGE"),
+];
+
+
+/// Parse a currency pair from any of the accepted notations.
+///
+/// # Arguments
+///
+/// * `symbol` - The symbol to parse.
+///
+/// # Returns
+///
+/// An optional [CurrencyPair](CurrencyPair), [None](None) if the symbol is not recognized.
+pub fn parse_currency_pair(symbol: &str) -> Option<CurrencyPair> {
+    let symbol = symbol.trim().to_uppercase();
+    let (main, counter) = if let Some(index) = symbol.find(SEPARATORS) {
+        (symbol[..index].to_string(), symbol[index + 1..].to_string())
+    } else if let Some(pair) = split_kraken_symbol(&symbol) {
+        pair
+    } else {
+        split_concatenated_symbol(&symbol)?
+    };
+    if !is_currency_code(&main) || !is_currency_code(&counter) {
+        return None;
+    }
+    Some(CurrencyPair { main: canonical_currency(&main), counter: canonical_currency(&counter) })
+}
+
+/// Canonical symbol for a currency pair.
+///
+/// # Arguments
+///
+/// * `pair` - The currency pair.
+///
+/// # Returns
+///
+/// A [String](String) with shape `MAIN-COUNTER`.
+pub fn canonical_symbol(pair: &CurrencyPair) -> String {
+    format!("{}-{}", pair.main.to_uppercase(), pair.counter.to_uppercase())
+}
+
+/// Canonicalize a symbol given in any of the accepted notations.
+///
+/// # Arguments
+///
+/// * `symbol` - The symbol to canonicalize.
+///
+/// # Returns
+///
+/// An optional canonical symbol, [None](None) if the symbol is not recognized.
+pub fn canonicalize(symbol: &str) -> Option<String> {
+    parse_currency_pair(symbol).map(|pair| canonical_symbol(&pair))
+}
+
+/// Split a Kraken style symbol, made of two four-letter codes prefixed by `X` (crypto)
+/// or `Z` (fiat), e.g. `XETHXXBT`.
+fn split_kraken_symbol(symbol: &str) -> Option<(String, String)> {
+    let is_kraken_code = |code: &str| code.starts_with(['X', 'Z']);
+    if symbol.len() == 8 && symbol.is_ascii() && is_kraken_code(&symbol[..4]) && is_kraken_code(&symbol[4..]) {
+        Some((symbol[1..4].to_string(), symbol[5..].to_string()))
+    } else {
+        None
+    }
+}
+
+/// Split a concatenated symbol, e.g. `ETHBTC`, on a known counter currency.
+fn split_concatenated_symbol(symbol: &str) -> Option<(String, String)> {
+    COUNTER_CURRENCIES.iter()
+        .chain(CURRENCY_ALIASES.iter().map(|(alias, _)| alias))
+        .find(|&&counter| symbol.len() > counter.len() && symbol.ends_with(counter))
+        .map(|counter| (symbol[..symbol.len() - counter.len()].to_string(), counter.to_string()))
+}
+
+/// Check that a string is a plausible currency code.
+fn is_currency_code(code: &str) -> bool {
+    (2..=10).contains(&code.len()) && code.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+/// Replace a currency code alias with the canonical code.
+fn canonical_currency(code: &str) -> String {
+    CURRENCY_ALIASES.iter()
+        .find(|(alias, _)| *alias == code)
+        .map(|(_, canonical)| canonical.to_string())
+        .unwrap_or_else(|| code.to_string())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(main: &str, counter: &str) -> Option<CurrencyPair> {
+        Some(CurrencyPair { main: main.to_string(), counter: counter.to_string() })
+    }
+
+    #[test]
+    fn test_parse_separated_symbols() {
+        assert_eq!(parse_currency_pair("ETH-BTC"), pair("ETH", "BTC"));
+        assert_eq!(parse_currency_pair("eth-btc"), pair("ETH", "BTC"));
+        assert_eq!(parse_currency_pair("ETH/BTC"), pair("ETH", "BTC"));
+        assert_eq!(parse_currency_pair("eth_usdt"), pair("ETH", "USDT"));
+    }
+
+    #[test]
+    fn test_parse_concatenated_symbols() {
+        assert_eq!(parse_currency_pair("ETHBTC"), pair("ETH", "BTC"));
+        assert_eq!(parse_currency_pair("btcusdt"), pair("BTC", "USDT"));
+        assert_eq!(parse_currency_pair("ETHXBT"), pair("ETH", "BTC"));
+    }
+
+    #[test]
+    fn test_parse_kraken_symbols() {
+        assert_eq!(parse_currency_pair("XETHXXBT"), pair("ETH", "BTC"));
+        assert_eq!(parse_currency_pair("XXBTZUSD"), pair("BTC", "USD"));
+    }
+
+    #[test]
+    fn test_parse_invalid_symbols() {
+        assert_eq!(parse_currency_pair("ETH"), None);
+        assert_eq!(parse_currency_pair("ETH-"), None);
+        assert_eq!(parse_currency_pair("FOOBAR"), None);
+        assert_eq!(parse_currency_pair("ETH-B TC"), None);
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        assert_eq!(canonicalize("xethxxbt"), Some("ETH-BTC".to_string()));
+        assert_eq!(canonicalize("ETH/BTC"), Some("ETH-BTC".to_string()));
+        assert_eq!(canonicalize("??"), None);
+    }
+}
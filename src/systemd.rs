@@ -0,0 +1,185 @@
+//! Optional integration with systemd supervision, using the `sd_notify` protocol.
+//! Readiness is signaled once every consolidated feed produced its first summary, and
+//! watchdog pings are only sent while every feed keeps producing summaries and the sinks are
+//! healthy, so that systemd restarts the server if an ingest pipeline or a sink stalls.
+
+use futures::future::select_all;
+use log::{info, warn};
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use tokio::time::Duration;
+
+use crate::clock::SharedClock;
+use crate::feed::FeedReceiver;
+use crate::sink::SinkHealth;
+
+
+/// Environment variable holding the path of the notification socket.
+const NOTIFY_SOCKET: &str = "NOTIFY_SOCKET";
+/// Environment variable holding the watchdog timeout, in microseconds.
+const WATCHDOG_USEC: &str = "WATCHDOG_USEC";
+/// Environment variable holding the process expected to send watchdog pings.
+const WATCHDOG_PID: &str = "WATCHDOG_PID";
+
+
+/// Send a state notification to a socket.
+///
+/// # Arguments
+///
+/// * `socket_path` - The notification socket, an abstract socket if starting with `@`.
+///
+/// * `state` - The state, e.g. `READY=1`.
+fn notify_socket(socket_path: &str, state: &str) -> io::Result<()> {
+    let socket = UnixDatagram::unbound()?;
+    if let Some(name) = socket_path.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::linux::net::SocketAddrExt;
+            let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &address)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = name;
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "abstract sockets not supported"));
+        }
+    } else {
+        socket.send_to(state.as_bytes(), socket_path)?;
+    }
+    Ok(())
+}
+
+/// Send a state notification to systemd.
+///
+/// # Arguments
+///
+/// * `state` - The state, e.g. `READY=1`.
+///
+/// # Returns
+///
+/// `true` if the notification was sent, `false` if the server is not run by systemd, or an error.
+pub fn notify(state: &str) -> io::Result<bool> {
+    match std::env::var(NOTIFY_SOCKET) {
+        Ok(socket_path) => notify_socket(&socket_path, state).map(|_| true),
+        Err(_) => Ok(false),
+    }
+}
+
+/// Parse the watchdog timeout set by systemd.
+///
+/// # Arguments
+///
+/// * `usec` - Value of `WATCHDOG_USEC`, if set.
+///
+/// * `pid` - Value of `WATCHDOG_PID`, if set.
+///
+/// * `own_pid` - Identifier of the current process.
+///
+/// # Returns
+///
+/// The timeout, or `None` if the watchdog is not enabled for this process.
+fn parse_watchdog_timeout(usec: Option<&str>, pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+    if let Some(pid) = pid {
+        if pid.parse::<u32>().ok()? != own_pid {
+            return None;
+        }
+    }
+    let usec: u64 = usec?.parse().ok()?;
+    Some(Duration::from_micros(usec)).filter(|d| !d.is_zero())
+}
+
+/// Spawn a task notifying systemd of readiness and sending watchdog pings while the feeds are
+/// alive and their sinks are healthy. Nothing is done if the server is not run by systemd.
+///
+/// # Arguments
+///
+/// * `feeds` - A [FeedReceiver](FeedReceiver) for each feed, e.g. one per symbol.
+///
+/// * `sinks` - The [SinkHealth](SinkHealth) of the sinks of the feeds.
+///
+/// * `clock` - Time source for the watchdog pings.
+pub fn spawn_supervision(mut feeds: Vec<FeedReceiver>, sinks: SinkHealth, clock: SharedClock) {
+    if std::env::var(NOTIFY_SOCKET).is_err() || feeds.is_empty() {
+        return;
+    }
+    let watchdog_timeout = parse_watchdog_timeout(
+        std::env::var(WATCHDOG_USEC).ok().as_deref(),
+        std::env::var(WATCHDOG_PID).ok().as_deref(),
+        std::process::id(),
+    );
+    tokio::spawn(async move {
+        for feed in &mut feeds {
+            while feed.borrow_and_update().is_none() {
+                if feed.changed().await.is_err() {
+                    return;
+                }
+            }
+        }
+        info!("First summaries produced, notifying systemd");
+        if let Err(error) = notify("READY=1") {
+            warn!("Error notifying systemd: {:?}", error);
+        }
+        let Some(timeout) = watchdog_timeout else {
+            return;
+        };
+        let mut last_updates = vec![clock.now(); feeds.len()];
+        let mut stalled = false;
+        loop {
+            tokio::select! {
+                (changed, index, _) = select_all(feeds.iter_mut().map(|feed| Box::pin(feed.changed()))) => {
+                    if changed.is_err() {
+                        warn!("Feed closed, watchdog pings stopped");
+                        return;
+                    }
+                    last_updates[index] = clock.now();
+                },
+                _ = clock.sleep(timeout / 2) => {
+                    let last_update = last_updates.iter().min().copied().expect("at least one feed");
+                    if clock.now() - last_update >= timeout {
+                        if !stalled {
+                            warn!("No summary for {:?}, watchdog pings stopped", timeout);
+                            stalled = true;
+                        }
+                    } else if !sinks.is_ready() {
+                        if !stalled {
+                            warn!("Sinks not healthy {:?}, watchdog pings stopped", sinks.statuses());
+                            stalled = true;
+                        }
+                    } else {
+                        stalled = false;
+                        if let Err(error) = notify("WATCHDOG=1") {
+                            warn!("Error sending watchdog ping: {:?}", error);
+                        }
+                    }
+                },
+            }
+        }
+    });
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_watchdog_timeout() {
+        assert_eq!(parse_watchdog_timeout(Some("30000000"), None, 42), Some(Duration::from_secs(30)));
+        assert_eq!(parse_watchdog_timeout(Some("30000000"), Some("42"), 42), Some(Duration::from_secs(30)));
+        assert_eq!(parse_watchdog_timeout(Some("30000000"), Some("43"), 42), None);
+        assert_eq!(parse_watchdog_timeout(Some("0"), None, 42), None);
+        assert_eq!(parse_watchdog_timeout(None, None, 42), None);
+    }
+
+    #[test]
+    fn test_notify_socket() {
+        let path = std::env::temp_dir().join(format!("orderbook-notify-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let receiver = UnixDatagram::bind(&path).unwrap();
+        notify_socket(path.to_str().unwrap(), "READY=1").unwrap();
+        let mut buffer = [0u8; 16];
+        let len = receiver.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..len], b"READY=1");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,204 @@
+//! Stream of discrete events describing changes at the top of the consolidated
+//! trading book: best price or leading exchange, for each side.
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use futures::stream::{Stream, StreamExt};
+
+use crate::core::Side;
+use crate::service::{BookChange, BookSummaryService};
+use crate::orderbook::{Level, Summary, TopOfBookEvent, BookSide, TopOfBookChangeReason};
+
+
+/// Stream of [top of book events](TopOfBookEvent), built on top of a [BookSummaryService](BookSummaryService).
+pub struct TopOfBookEventStream {
+    /// The service producing consolidated book snapshots.
+    service: BookSummaryService,
+    /// Last snapshot received from the service.
+    last_summary: Option<Summary>,
+    /// Events ready to be delivered.
+    pending_events: VecDeque<TopOfBookEvent>,
+}
+
+impl TopOfBookEventStream {
+    /// Create a new instance of the stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `service` - A [BookSummaryService](BookSummaryService) object.
+    ///
+    /// # Returns
+    ///
+    /// An instance of [TopOfBookEventStream](TopOfBookEventStream)
+    pub fn new(service: BookSummaryService) -> Self {
+        Self { service, last_summary: None, pending_events: VecDeque::new() }
+    }
+
+    /// Disconnect from all exchanges, it consumes the stream.
+    pub async fn disconnect(self) {
+        self.service.disconnect().await;
+    }
+
+    /// Compare a new snapshot with the previous one, and queue the resulting events.
+    fn process_summary(&mut self, summary: Summary, change: Option<BookChange>) {
+        let previous = self.last_summary.as_ref();
+        let previous_bids = previous.map(|s| s.bids.as_slice()).unwrap_or(&[]);
+        let previous_asks = previous.map(|s| s.asks.as_slice()).unwrap_or(&[]);
+        let events = [
+            side_event(&summary.symbol, Side::Buy, previous_bids, &summary.bids, change.as_ref()),
+            side_event(&summary.symbol, Side::Sell, previous_asks, &summary.asks, change.as_ref()),
+        ];
+        self.pending_events.extend(events.into_iter().flatten());
+        self.last_summary = Some(summary);
+    }
+}
+
+impl Stream for TopOfBookEventStream {
+    type Item = TopOfBookEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(event) = self.pending_events.pop_front() {
+                return Poll::Ready(Some(event));
+            }
+            match self.service.poll_next_unpin(cx) {
+                Poll::Ready(Some(summary)) => {
+                    let change = self.service.last_change();
+                    self.process_summary(summary, change);
+                },
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Check if the top of one side of the book changed, and build the corresponding event.
+///
+/// # Arguments
+///
+/// * `symbol` - The canonical symbol of the currency pair.
+///
+/// * `side` - The side of the book.
+///
+/// * `previous` - The previous levels of the side, best first.
+///
+/// * `current` - The current levels of the side, best first.
+///
+/// * `change` - The cause of the last change applied to the book, if known.
+///
+/// # Returns
+///
+/// An optional [TopOfBookEvent](TopOfBookEvent), [None](None) if the best price and leading
+/// exchange did not change.
+fn side_event(symbol: &str, side: Side, previous: &[Level], current: &[Level], change: Option<&BookChange>) -> Option<TopOfBookEvent> {
+    let previous_best = previous.first();
+    let current_best = current.first();
+    let unchanged = match (previous_best, current_best) {
+        (Some(p), Some(c)) => p.price == c.price && p.exchange == c.exchange,
+        (None, None) => true,
+        _ => false,
+    };
+    if unchanged {
+        return None;
+    }
+    let reason = match change {
+        Some(BookChange::Ejection(_)) => TopOfBookChangeReason::VenueEjected,
+        Some(BookChange::Reconnection(_)) => TopOfBookChangeReason::VenueReconnected,
+        _ => match (previous_best, current_best) {
+            (None, _) => TopOfBookChangeReason::NewLevel,
+            (_, None) => TopOfBookChangeReason::LevelRemoved,
+            (Some(p), Some(c)) => {
+                let improved = match side {
+                    Side::Buy => c.price > p.price,
+                    Side::Sell => c.price < p.price,
+                };
+                let previous_leader_present = current.iter()
+                    .take_while(|l| l.price == c.price)
+                    .any(|l| l.exchange == p.exchange);
+                if improved || (c.price == p.price && previous_leader_present) {
+                    TopOfBookChangeReason::NewLevel
+                } else {
+                    TopOfBookChangeReason::LevelRemoved
+                }
+            }
+        }
+    };
+    let book_side = match side {
+        Side::Buy => BookSide::Bid,
+        Side::Sell => BookSide::Ask,
+    };
+    Some(TopOfBookEvent {
+        symbol: symbol.to_string(),
+        side: book_side as i32,
+        level: current_best.cloned(),
+        reason: reason as i32,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(exchange: &str, price: f64, amount: f64) -> Level {
+        Level { exchange: exchange.to_string(), price, amount, order_count: None, latency_class: 0 }
+    }
+
+    fn reason(event: Option<TopOfBookEvent>) -> Option<TopOfBookChangeReason> {
+        event.map(|e| TopOfBookChangeReason::from_i32(e.reason).unwrap())
+    }
+
+    #[test]
+    fn test_side_event_unchanged() {
+        let previous = vec![level("test1", 99.0, 1.0)];
+        let current = vec![level("test1", 99.0, 2.0), level("test2", 98.0, 1.0)];
+        let change = Some(BookChange::Update("test2".into()));
+        assert_eq!(side_event("ETH-BTC", Side::Buy, &previous, &current, change.as_ref()), None);
+    }
+
+    #[test]
+    fn test_side_event_new_level() {
+        let previous = vec![level("test1", 99.0, 1.0)];
+        let current = vec![level("test2", 99.5, 1.0), level("test1", 99.0, 1.0)];
+        let event = side_event("ETH-BTC", Side::Buy, &previous, &current, Some(&BookChange::Update("test2".into())));
+        assert_eq!(event.as_ref().unwrap().level, Some(level("test2", 99.5, 1.0)));
+        assert_eq!(event.as_ref().unwrap().side, BookSide::Bid as i32);
+        assert_eq!(reason(event), Some(TopOfBookChangeReason::NewLevel));
+        let event = side_event("ETH-BTC", Side::Buy, &[], &current, Some(&BookChange::Update("test2".into())));
+        assert_eq!(reason(event), Some(TopOfBookChangeReason::NewLevel));
+    }
+
+    #[test]
+    fn test_side_event_level_removed() {
+        let previous = vec![level("test1", 100.0, 1.0), level("test2", 101.0, 1.0)];
+        let current = vec![level("test2", 101.0, 1.0)];
+        let event = side_event("ETH-BTC", Side::Sell, &previous, &current, Some(&BookChange::Update("test1".into())));
+        assert_eq!(event.as_ref().unwrap().side, BookSide::Ask as i32);
+        assert_eq!(reason(event), Some(TopOfBookChangeReason::LevelRemoved));
+        let event = side_event("ETH-BTC", Side::Sell, &previous, &[], Some(&BookChange::Update("test1".into())));
+        assert_eq!(event.as_ref().unwrap().level, None);
+    }
+
+    #[test]
+    fn test_side_event_same_price_new_leader() {
+        let previous = vec![level("test1", 100.0, 1.0)];
+        let current = vec![level("test2", 100.0, 2.0), level("test1", 100.0, 1.0)];
+        let event = side_event("ETH-BTC", Side::Sell, &previous, &current, Some(&BookChange::Update("test2".into())));
+        assert_eq!(reason(event), Some(TopOfBookChangeReason::NewLevel));
+        let current = vec![level("test2", 100.0, 2.0)];
+        let event = side_event("ETH-BTC", Side::Sell, &previous, &current, Some(&BookChange::Update("test1".into())));
+        assert_eq!(reason(event), Some(TopOfBookChangeReason::LevelRemoved));
+    }
+
+    #[test]
+    fn test_side_event_venue_status() {
+        let previous = vec![level("test1", 100.0, 1.0)];
+        let current = vec![level("test2", 101.0, 2.0)];
+        let event = side_event("ETH-BTC", Side::Sell, &previous, &current, Some(&BookChange::Ejection("test1".into())));
+        assert_eq!(reason(event), Some(TopOfBookChangeReason::VenueEjected));
+        let event = side_event("ETH-BTC", Side::Sell, &current, &previous, Some(&BookChange::Reconnection("test1".into())));
+        assert_eq!(reason(event), Some(TopOfBookChangeReason::VenueReconnected));
+    }
+}
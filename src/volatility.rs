@@ -0,0 +1,168 @@
+//! Short-horizon realized volatility of the consolidated mid price, estimated incrementally
+//! as an exponentially weighted moving average of the squared log returns of the mid.
+
+use log::info;
+use tokio::sync::watch;
+use tokio::time::{Duration, Instant};
+
+use crate::clock::SharedClock;
+use crate::feed::{FeedReceiver, SummaryMetrics};
+
+
+/// Minimum time attributed to a return, so that mid changes published at the same instant
+/// do not produce an infinite variance rate.
+pub const MIN_RETURN_INTERVAL: Duration = Duration::from_millis(1);
+
+
+/// Receiver of the latest [volatility estimate](VolatilityEstimate), if any was produced.
+pub type VolatilityReceiver = watch::Receiver<Option<VolatilityEstimate>>;
+
+/// Volatility of the mid price at several horizons.
+#[derive(PartialEq, Debug, Clone)]
+pub struct VolatilityEstimate {
+    /// Canonical symbol.
+    pub symbol: String,
+    /// Latest mid price.
+    pub mid: f64,
+    /// For each horizon, the expected standard deviation of the log return of the mid over
+    /// the horizon, in basis points.
+    pub horizons: Vec<(Duration, f64)>,
+}
+
+/// Incremental estimator of the volatility of the mid price. For each horizon, the variance
+/// per second of the log returns is averaged with weights decaying exponentially with the
+/// age of the return, the horizon being the time constant of the decay.
+pub struct VolatilityEstimator {
+    /// Horizons, and the estimated variance per second of the log returns for each one.
+    horizons: Vec<(Duration, Option<f64>)>,
+    /// Latest mid price and its time.
+    last_mid: Option<(f64, Instant)>,
+}
+
+impl VolatilityEstimator {
+    /// Create a new [VolatilityEstimator](VolatilityEstimator) object.
+    ///
+    /// # Arguments
+    ///
+    /// * `horizons` - The horizons of the estimates.
+    pub fn new(horizons: Vec<Duration>) -> Self {
+        Self { horizons: horizons.into_iter().map(|h| (h, None)).collect(), last_mid: None }
+    }
+
+    /// Update the estimates with a new mid price.
+    ///
+    /// # Arguments
+    ///
+    /// * `mid` - The mid price, ignored if not positive.
+    ///
+    /// * `now` - The time of the mid price.
+    pub fn update(&mut self, mid: f64, now: Instant) {
+        if !(mid.is_finite() && mid > 0.0) {
+            return;
+        }
+        if let Some((last_mid, last_time)) = self.last_mid {
+            let elapsed = now.saturating_duration_since(last_time).max(MIN_RETURN_INTERVAL);
+            let log_return = (mid / last_mid).ln();
+            let variance_rate = log_return * log_return / elapsed.as_secs_f64();
+            for (horizon, estimate) in &mut self.horizons {
+                *estimate = Some(match estimate {
+                    Some(previous) => {
+                        let weight = 1.0 - (-elapsed.as_secs_f64() / horizon.as_secs_f64()).exp();
+                        *previous + weight * (variance_rate - *previous)
+                    },
+                    None => variance_rate,
+                });
+            }
+        }
+        self.last_mid = Some((mid, now));
+    }
+
+    /// The current estimates.
+    ///
+    /// # Returns
+    ///
+    /// For each horizon, the expected standard deviation of the log return of the mid over
+    /// the horizon, in basis points: NaN until two mid prices were received.
+    pub fn volatilities(&self) -> Vec<(Duration, f64)> {
+        self.horizons.iter()
+            .map(|(horizon, estimate)| (
+                *horizon,
+                estimate.map(|rate| (rate * horizon.as_secs_f64()).sqrt() * 10_000.0).unwrap_or(f64::NAN)
+            ))
+            .collect()
+    }
+}
+
+/// Spawn a task estimating the volatility of the mid price of the summaries of a feed.
+///
+/// # Arguments
+///
+/// * `feed` - A [FeedReceiver](FeedReceiver).
+///
+/// * `horizons` - The horizons of the estimates.
+///
+/// * `clock` - Time source for the returns.
+///
+/// # Returns
+///
+/// A [VolatilityReceiver](VolatilityReceiver), updated after each summary.
+pub fn spawn_volatility(mut feed: FeedReceiver, horizons: Vec<Duration>, clock: SharedClock) -> VolatilityReceiver {
+    let (sender, receiver) = watch::channel(None);
+    tokio::spawn(async move {
+        let mut estimator = VolatilityEstimator::new(horizons);
+        while feed.changed().await.is_ok() {
+            let maybe_summary = feed.borrow_and_update().clone();
+            if let Some(summary) = maybe_summary {
+                let mid = SummaryMetrics::from(&summary).mid;
+                estimator.update(mid, clock.now());
+                sender.send_replace(Some(VolatilityEstimate {
+                    symbol: summary.symbol,
+                    mid,
+                    horizons: estimator.volatilities(),
+                }));
+            }
+        }
+        info!("Feed closed, volatility estimation stopped");
+    });
+    receiver
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant_returns() {
+        let start = Instant::now();
+        let mut estimator = VolatilityEstimator::new(vec![Duration::from_secs(1), Duration::from_secs(4)]);
+        estimator.update(100.0, start);
+        assert!(estimator.volatilities().iter().all(|(_, v)| v.is_nan()));
+        // alternating moves of 10 bps (in log terms) every second
+        let step = (0.001f64).exp();
+        for i in 1..=10 {
+            let mid = if i % 2 == 1 { 100.0 * step } else { 100.0 };
+            estimator.update(mid, start + Duration::from_secs(i));
+        }
+        let volatilities = estimator.volatilities();
+        assert_eq!(volatilities[0].0, Duration::from_secs(1));
+        assert!((volatilities[0].1 - 10.0).abs() < 1e-6, "{:?}", volatilities);
+        assert!((volatilities[1].1 - 20.0).abs() < 1e-6, "{:?}", volatilities);
+    }
+
+    #[test]
+    fn test_decay() {
+        let start = Instant::now();
+        let mut estimator = VolatilityEstimator::new(vec![Duration::from_secs(1)]);
+        estimator.update(100.0, start);
+        estimator.update(101.0, start + Duration::from_secs(1));
+        let initial = estimator.volatilities()[0].1;
+        estimator.update(101.0, start + Duration::from_secs(2));
+        let decayed = estimator.volatilities()[0].1;
+        assert!(decayed < initial);
+        assert!((decayed / initial - (-1.0f64).exp().sqrt()).abs() < 1e-9);
+        // invalid mid prices are ignored
+        estimator.update(f64::NAN, start + Duration::from_secs(3));
+        assert_eq!(estimator.volatilities()[0].1, decayed);
+    }
+}
@@ -0,0 +1,230 @@
+//! Optional HTTP webhook, posting JSON notifications to an external service.
+//! Failed posts are retried with an increasing delay. With a secret, each notification is
+//! signed with HMAC-SHA256, so that the receiver can check its origin.
+
+use hmac::{Hmac, Mac};
+use hyper::{client::HttpConnector, header, Body, Client, Method, Request};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::time::Duration;
+
+use crate::clock::{system_clock, SharedClock};
+use crate::config::{DocumentedConfig, Validate, Validator};
+
+
+/// Header carrying the signature of the notification, as `sha256=<hex digest>`.
+pub const SIGNATURE_HEADER: &str = "x-orderbook-signature";
+/// URL where notifications are posted in the printed defaults.
+const DEFAULT_URL: &str = "http://localhost:8080/status";
+/// Default number of attempts to post a notification.
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+/// Delay before the first retry, doubled at each retry.
+const INITIAL_RETRY_DELAY_MS: u64 = 500;
+
+
+/// Webhook configuration, read from a JSON file.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct WebhookConfig {
+    /// URL where notifications are posted.
+    pub url: String,
+    /// Secret signing the notifications, if any.
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// Number of attempts to post a notification.
+    #[serde(default)]
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self { url: DEFAULT_URL.to_string(), secret: None, max_attempts: Some(DEFAULT_MAX_ATTEMPTS) }
+    }
+}
+
+impl Validate for WebhookConfig {
+    fn validate(&self, validator: &mut Validator) {
+        validator.url("url", &self.url, &["http"]);
+        if let Some(max_attempts) = self.max_attempts {
+            validator.positive("max_attempts", max_attempts as u64);
+        }
+    }
+}
+
+impl DocumentedConfig for WebhookConfig {
+    const DESCRIPTION: &'static str = "Webhook where changes of the status of the exchanges are posted, none if the file is missing.";
+    const FIELDS: &'static [(&'static str, &'static str)] = &[
+        ("url", "URL where notifications are posted."),
+        ("secret", "Secret signing the notifications, if any."),
+        ("max_attempts", "Number of attempts to post a notification."),
+    ];
+}
+
+/// An HTTP endpoint receiving JSON notifications.
+#[derive(Clone)]
+pub struct Webhook {
+    /// URL of the endpoint.
+    url: String,
+    /// HTTP client, sharing connections between notifications.
+    client: Client<HttpConnector>,
+    /// Secret signing the notifications, if any.
+    secret: Option<String>,
+    /// Number of attempts to post a notification.
+    max_attempts: u32,
+    /// Time source for the retry delays.
+    clock: SharedClock,
+}
+
+impl Webhook {
+    /// Create a new [Webhook](Webhook) object, without signature.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL where the notifications are posted.
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            client: Client::new(),
+            secret: None,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            clock: system_clock(),
+        }
+    }
+
+    /// Create a new [Webhook](Webhook) object from a configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The [WebhookConfig](WebhookConfig).
+    pub fn from_config(config: &WebhookConfig) -> Self {
+        let webhook = Self::new(&config.url).with_max_attempts(config.max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS));
+        match &config.secret {
+            Some(secret) => webhook.with_secret(secret),
+            None => webhook,
+        }
+    }
+
+    /// Sign the notifications.
+    ///
+    /// # Arguments
+    ///
+    /// * `secret` - The secret shared with the receiver.
+    ///
+    /// # Returns
+    ///
+    /// The modified [Webhook](Webhook).
+    pub fn with_secret(mut self, secret: &str) -> Self {
+        self.secret = Some(secret.to_string());
+        self
+    }
+
+    /// Set the number of attempts to post a notification.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_attempts` - The number of attempts, at least one.
+    ///
+    /// # Returns
+    ///
+    /// The modified [Webhook](Webhook).
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Replace the time source used for the retry delays, by default the system clock.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - A [shared clock](SharedClock).
+    ///
+    /// # Returns
+    ///
+    /// The modified [Webhook](Webhook).
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Post a notification, retrying on failure.
+    ///
+    /// # Arguments
+    ///
+    /// * `notification` - The notification, serialized as JSON.
+    ///
+    /// # Returns
+    ///
+    /// An empty [Result](Result), the last error if no attempt succeeded.
+    pub async fn post<T: Serialize>(&self, notification: &T) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let body = serde_json::to_vec(notification)?;
+        let signature = self.secret.as_ref().map(|secret| sign(secret, &body));
+        let mut delay = Duration::from_millis(INITIAL_RETRY_DELAY_MS);
+        let mut attempt = 1;
+        loop {
+            match self.post_once(body.clone(), signature.as_deref()).await {
+                Ok(()) => return Ok(()),
+                Err(error) if attempt >= self.max_attempts => return Err(error),
+                Err(_) => {
+                    self.clock.sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                },
+            }
+        }
+    }
+
+    /// Internal function making a single attempt to post a notification.
+    async fn post_once(&self, body: Vec<u8>, signature: Option<&str>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri(&self.url)
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(signature) = signature {
+            request = request.header(SIGNATURE_HEADER, signature);
+        }
+        let response = self.client.request(request.body(Body::from(body))?).await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("webhook responded {}", response.status()).into())
+        }
+    }
+}
+
+/// Sign a notification body.
+///
+/// # Arguments
+///
+/// * `secret` - The secret shared with the receiver.
+///
+/// * `body` - The notification body.
+///
+/// # Returns
+///
+/// The signature, as `sha256=<hex digest>` of the HMAC-SHA256 of the body.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    let digest: String = mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256={}", digest)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_config() {
+        let config: WebhookConfig = serde_json::from_str(r#"{"url": "http://localhost/hook", "secret": "s"}"#).unwrap();
+        assert_eq!(config, WebhookConfig { url: "http://localhost/hook".to_string(), secret: Some("s".to_string()), max_attempts: None });
+    }
+}
@@ -0,0 +1,63 @@
+//! Channel command test: an adapter of a simulated exchange subscribes to and unsubscribes from
+//! channels on its open connection without reconnecting, and resubscribes only to the channels
+//! subscribed after each reconnection.
+
+use futures::StreamExt;
+use tokio::time::{timeout, Duration};
+
+use orderbook_server::core::BookUpdate;
+use orderbook_server::exchange::{ExchangeAdapterStream, ExchangeEvent};
+use orderbook_server::simulated::{SimulatedExchange, SIMULATED_CODE};
+use orderbook_server::symbols::parse_currency_pair;
+
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+
+async fn expect_connected(stream: &mut ExchangeAdapterStream<BookUpdate>) {
+    assert_eq!(timeout(TIMEOUT, stream.next()).await.unwrap(), Some(ExchangeEvent::Connected(SIMULATED_CODE.into())));
+}
+
+/// Wait until the exchange received a number of copies of a message.
+async fn wait_for_message(exchange: &SimulatedExchange, message: &str, count: usize) {
+    let received = timeout(TIMEOUT, async {
+        while exchange.received_messages().iter().filter(|m| *m == message).count() < count {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }).await;
+    assert!(received.is_ok(), "no {} in {:?}", message, exchange.received_messages());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_subscribe_and_unsubscribe_commands() {
+    let exchange = SimulatedExchange::start().await.unwrap();
+    let eth_btc = parse_currency_pair("ETH-BTC").unwrap();
+    let btc_usdt = parse_currency_pair("BTC-USDT").unwrap();
+    let mut stream = exchange.adapter(&eth_btc).make_stream().await;
+    expect_connected(&mut stream).await;
+    wait_for_message(&exchange, r#"{"method":"SUBSCRIBE","symbol":"ETH-BTC"}"#, 1).await;
+
+    // on the open connection
+    stream.subscribe(&btc_usdt).await;
+    wait_for_message(&exchange, r#"{"method":"SUBSCRIBE","symbol":"BTC-USDT"}"#, 1).await;
+    stream.unsubscribe(&eth_btc).await;
+    wait_for_message(&exchange, r#"{"method":"UNSUBSCRIBE","symbol":"ETH-BTC"}"#, 1).await;
+    // already in the requested state
+    stream.subscribe(&btc_usdt).await;
+    stream.unsubscribe(&eth_btc).await;
+    assert_eq!(exchange.connections(), 1);
+
+    // after a reconnection, only the channels subscribed
+    exchange.drop_connections();
+    expect_connected(&mut stream).await;
+    wait_for_message(&exchange, r#"{"method":"SUBSCRIBE","symbol":"BTC-USDT"}"#, 2).await;
+    let subscriptions: Vec<String> = exchange.received_messages().into_iter().filter(|m| m.contains("SUBSCRIBE")).collect();
+    assert_eq!(subscriptions, vec![
+        r#"{"method":"SUBSCRIBE","symbol":"ETH-BTC"}"#,
+        r#"{"method":"SUBSCRIBE","symbol":"BTC-USDT"}"#,
+        r#"{"method":"UNSUBSCRIBE","symbol":"ETH-BTC"}"#,
+        r#"{"method":"SUBSCRIBE","symbol":"BTC-USDT"}"#,
+    ]);
+
+    stream.disconnect().await;
+}
@@ -0,0 +1,66 @@
+//! Resilience test: an adapter with injected faults keeps delivering valid, ordered data
+//! from a simulated exchange, reconnecting after the connection drops it causes.
+
+use futures::StreamExt;
+use rust_decimal::prelude::*;
+use std::sync::Arc;
+use tokio::time::{timeout, Duration};
+
+use orderbook_server::chaos::ChaosConfig;
+use orderbook_server::exchange::ExchangeEvent;
+use orderbook_server::simulated::{book_update_message, SimulatedExchange, SIMULATED_CODE};
+use orderbook_server::symbols::parse_currency_pair;
+
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+const DATA_EVENTS: usize = 200;
+const RECONNECTIONS: usize = 3;
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_adapter_survives_chaos() {
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    let exchange = Arc::new(SimulatedExchange::start().await.unwrap());
+    let chaos = ChaosConfig {
+        drop_rate: 0.1,
+        corrupt_rate: 0.1,
+        disconnect_rate: 0.02,
+        delay_rate: 0.1,
+        max_delay: Duration::from_millis(5),
+        seed: 7,
+    };
+    let mut stream = exchange.adapter(&product).with_chaos(chaos).make_stream().await;
+    let publisher = exchange.clone();
+    let publishing = tokio::spawn(async move {
+        for i in 1.. {
+            let price = i.to_string();
+            publisher.publish(book_update_message(&[(&price, "1")], &[]));
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    });
+
+    let mut data_events = 0;
+    let mut connections = 0;
+    let mut last_price = Decimal::ZERO;
+    timeout(TIMEOUT, async {
+        while data_events < DATA_EVENTS || connections <= RECONNECTIONS {
+            match stream.next().await.expect("adapter stream ended") {
+                ExchangeEvent::Connected(code) => {
+                    assert_eq!(code, SIMULATED_CODE);
+                    connections += 1;
+                },
+                ExchangeEvent::Data(update) => {
+                    assert_eq!(update.bids.len(), 1, "corrupted update delivered: {:?}", update);
+                    let price = update.bids[0].price;
+                    assert!(price > last_price, "update {} delivered after {}", price, last_price);
+                    last_price = price;
+                    data_events += 1;
+                },
+                ExchangeEvent::Disconnected(code) => panic!("adapter task for {} failed", code),
+            }
+        }
+    }).await.unwrap_or_else(|_| panic!("{} data events and {} connections before timeout", data_events, connections));
+
+    publishing.abort();
+    stream.disconnect().await;
+}
@@ -0,0 +1,47 @@
+//! Helpers shared by the integration tests.
+
+#![allow(dead_code)]
+
+use futures::StreamExt;
+use std::net::{Ipv6Addr, TcpListener};
+use tokio::time::{timeout, Duration};
+
+use orderbook_server::core::{BookUpdate, ExchangeLevel};
+use orderbook_server::exchange::{ExchangeAdapterStream, ExchangeEvent};
+use orderbook_server::orderbook::Summary;
+use orderbook_server::service::BookSummaryService;
+
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+
+/// Find a free local port.
+pub fn free_port() -> u16 {
+    TcpListener::bind((Ipv6Addr::LOCALHOST, 0)).unwrap().local_addr().unwrap().port()
+}
+
+/// Receive the next event of an exchange adapter stream.
+pub async fn next_event(stream: &mut ExchangeAdapterStream<BookUpdate>) -> ExchangeEvent<BookUpdate> {
+    timeout(TIMEOUT, stream.next()).await.expect("no event from adapter").expect("adapter stream ended")
+}
+
+/// Make a book update of an exchange with a single bid, and a single ask at 101.
+pub fn book_update(exchange_code: &'static str, bid: &str) -> ExchangeEvent<BookUpdate> {
+    ExchangeEvent::Data(BookUpdate {
+        exchange_code: exchange_code.into(),
+        bids: vec![ExchangeLevel::from_strs(exchange_code, bid, "1")],
+        asks: vec![ExchangeLevel::from_strs(exchange_code, "101", "1")],
+    })
+}
+
+/// Receive the next summary or status frame of a service.
+pub async fn next_summary(service: &mut BookSummaryService) -> Summary {
+    timeout(TIMEOUT, service.next()).await.expect("no summary").expect("service stopped")
+}
+
+/// Receive a summary, failing on a status frame.
+pub async fn expect_summary(service: &mut BookSummaryService) -> Summary {
+    let summary = next_summary(service).await;
+    assert_eq!(summary.frame, None);
+    summary
+}
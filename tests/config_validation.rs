@@ -0,0 +1,101 @@
+//! Configuration validation test: the constraints violated by several configuration files are
+//! reported together, each with its file and field, and the printed defaults load back unchanged.
+
+use std::path::{Path, PathBuf};
+
+use orderbook_server::adaptive_depth::AdaptiveDepth;
+use orderbook_server::alerts::AlertsConfig;
+use orderbook_server::config::{self, commented_defaults, ConfigLoader};
+use orderbook_server::maintenance::MaintenanceSchedule;
+use orderbook_server::queues::QueueCapacities;
+use orderbook_server::scheduling::Scheduling;
+use orderbook_server::settings::ServerSettings;
+use orderbook_server::shadow::ShadowConfig;
+use orderbook_server::staleness::StalenessThresholds;
+
+
+/// Write a configuration file to a directory of the test.
+fn write_file(dir: &Path, file: &str, text: &str) -> PathBuf {
+    let path = dir.join(file);
+    std::fs::write(&path, text).unwrap();
+    path
+}
+
+#[test]
+fn test_errors_aggregated() {
+    let dir = std::env::temp_dir().join(format!("orderbook-config-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let queues = write_file(&dir, "queues.json", r#"{"adapter_data": 0, "client_response": 0}"#);
+    let staleness = write_file(&dir, "staleness.json", "// thresholds\n{\"fresh_ms\": 900, \"stale_ms\": 100}");
+    let scheduling = write_file(&dir, "scheduling.json", r#"{"ingest_threads": "many"}"#);
+    let alerts = write_file(&dir, "alerts.json", r#"{"rules": [{"name": "", "condition": "crossed"}], "webhook_url": "localhost:80"}"#);
+    let adaptive_depth = write_file(&dir, "adaptive_depth.json", r#"{"min_levels": 5, "max_levels": 10, "spread_threshold_bps": 2.5}"#);
+    let server = write_file(&dir, "server.json", r#"{"depth_bands_bps": [10, 0], "volatility_horizons_ms": [10000, 1000]}"#);
+
+    let mut loader = ConfigLoader::default();
+    let capacities: QueueCapacities = loader.load(&queues);
+    let thresholds: StalenessThresholds = loader.load(&staleness);
+    let _: Scheduling = loader.load(&scheduling);
+    let _: AlertsConfig = loader.load(&alerts);
+    let depth: Option<AdaptiveDepth> = loader.load_optional(&adaptive_depth);
+    let missing: Option<AdaptiveDepth> = loader.load_optional(&dir.join("missing.json"));
+    let _: ServerSettings = loader.load(&server);
+    assert_eq!((capacities, thresholds), (QueueCapacities::default(), StalenessThresholds::default()));
+    assert_eq!(depth.map(|depth| depth.max_levels), Some(10));
+    assert_eq!(missing, None);
+
+    let errors = loader.finish().unwrap_err();
+    let fields: Vec<(String, &str)> = errors.errors().iter()
+        .map(|error| (PathBuf::from(&error.file).file_name().unwrap().to_string_lossy().into_owned(), error.field.as_str()))
+        .collect();
+    assert_eq!(fields, vec![
+        ("queues.json".to_string(), "adapter_data"),
+        ("queues.json".to_string(), "client_response"),
+        ("staleness.json".to_string(), "fresh_ms"),
+        ("scheduling.json".to_string(), ""),
+        ("alerts.json".to_string(), "rules[0].name"),
+        ("alerts.json".to_string(), "webhook_url"),
+        ("server.json".to_string(), "depth_bands_bps[1]"),
+        ("server.json".to_string(), "volatility_horizons_ms[1]"),
+    ]);
+    let report = errors.to_string();
+    assert!(report.starts_with("invalid configuration (8 errors)\n"), "{}", report);
+    assert!(report.contains("staleness.json: fresh_ms must not be above stale_ms"), "{}", report);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_volatility_horizons_validated() {
+    let dir = std::env::temp_dir().join(format!("orderbook-horizons-config-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let cases = [
+        ("[]", "volatility_horizons_ms must not be empty"),
+        ("[1, 1000]", "volatility_horizons_ms[0] must be above 1"),
+        ("[1000, 1000]", "volatility_horizons_ms[1] must be above the previous horizon"),
+    ];
+    for (horizons, message) in cases {
+        let server = write_file(&dir, "server.json", &format!(r#"{{"volatility_horizons_ms": {}}}"#, horizons));
+        let errors = config::load::<ServerSettings>(&server).unwrap_err();
+        assert_eq!(errors.errors().len(), 1, "{}", errors);
+        assert!(errors.to_string().ends_with(message), "{}", errors);
+    }
+    let server = write_file(&dir, "server.json", r#"{"volatility_horizons_ms": [2, 500, 5000]}"#);
+    assert_eq!(config::load::<ServerSettings>(&server).unwrap().volatility_horizons_ms, vec![2, 500, 5000]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_printed_defaults_loadable() {
+    let dir = std::env::temp_dir().join(format!("orderbook-default-config-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let scheduling = write_file(&dir, "scheduling.json", &commented_defaults::<Scheduling>("scheduling.json"));
+    let alerts = write_file(&dir, "alerts.json", &commented_defaults::<AlertsConfig>("alerts.json"));
+    let maintenance = write_file(&dir, "maintenance.json", &commented_defaults::<MaintenanceSchedule>("maintenance.json"));
+    let shadow = write_file(&dir, "shadow.json", &commented_defaults::<ShadowConfig>("shadow.json"));
+    assert_eq!(config::load::<Scheduling>(&scheduling).unwrap(), Scheduling::default());
+    assert_eq!(config::load::<AlertsConfig>(&alerts).unwrap(), AlertsConfig::default());
+    assert_eq!(config::load::<MaintenanceSchedule>(&maintenance).unwrap(), MaintenanceSchedule::default());
+    // the files read only if they exist are printed with example values
+    assert_eq!(config::load_optional::<ShadowConfig>(&shadow).unwrap(), Some(ShadowConfig::default()));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
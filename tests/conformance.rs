@@ -0,0 +1,195 @@
+//! Conformance suite for exchange adapters: each adapter is driven by a simulated exchange
+//! serving its own message formats, and must parse its fixture snapshots, answer keepalive
+//! pings, survive dropped connections and reconnection requests, resubscribe after each
+//! reconnection, notify the changes of its status, and format symbols which round-trip through the symbol parser.
+
+mod common;
+
+use tokio::sync::broadcast;
+use tokio::time::{timeout, Duration};
+
+use orderbook_server::binance::{binance_symbol, make_binance_exchange_adapter};
+use orderbook_server::bitstamp::{bitstamp_symbol, make_bitstamp_echange_adapter};
+use orderbook_server::core::{BookUpdate, CurrencyPair, ExchangeLevel};
+use orderbook_server::exchange::{ExchangeAdapter, ExchangeAdapterStream, ExchangeEvent};
+use orderbook_server::status::{ExchangeStatus, ExchangeStatusEvent};
+use orderbook_server::simulated::{book_update_message, SimulatedExchange, SIMULATED_CODE};
+use orderbook_server::symbols::{canonical_symbol, parse_currency_pair};
+
+use common::next_event;
+
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Products whose symbols must round-trip.
+const PRODUCTS: [&str; 5] = ["ETH-BTC", "BTC-USDT", "ADA-BTC", "DOGE-EUR", "SOL-USDC"];
+
+
+/// What an adapter must handle to conform.
+struct ConformanceSpec {
+    /// Exchange code of the adapter.
+    exchange_code: &'static str,
+    /// Symbol formatting function of the adapter.
+    format_symbol: fn(&CurrencyPair) -> String,
+    /// Messages in the exchange format, and the book updates they must be parsed into.
+    fixtures: Vec<(String, BookUpdate)>,
+    /// Message by which the exchange requests a reconnection, if supported.
+    reconnection_request: Option<String>,
+    /// Message by which the exchange acknowledges a subscription, if awaited by the adapter.
+    subscription_ack: Option<String>,
+}
+
+fn book_update(exchange_code: &'static str, bids: &[(&str, &str)], asks: &[(&str, &str)]) -> BookUpdate {
+    let levels = |pairs: &[(&str, &str)]| pairs.iter()
+        .map(|&(price, amount)| ExchangeLevel::from_strs(exchange_code, price, amount))
+        .collect();
+    BookUpdate { exchange_code: exchange_code.into(), bids: levels(bids), asks: levels(asks) }
+}
+
+async fn expect_fixtures(exchange: &SimulatedExchange, stream: &mut ExchangeAdapterStream<BookUpdate>, spec: &ConformanceSpec) {
+    for (message, expected) in &spec.fixtures {
+        exchange.publish(message.clone());
+        match next_event(stream).await {
+            ExchangeEvent::Data(update) => assert_eq!(&update, expected, "fixture {}", message),
+            other => panic!("expected data for fixture {}, got {:?}", message, other),
+        }
+    }
+}
+
+async fn expect_status(status: &mut broadcast::Receiver<ExchangeStatusEvent>, spec: &ConformanceSpec, expected: ExchangeStatus) {
+    let event = timeout(TIMEOUT, status.recv()).await.expect("no status from adapter").unwrap();
+    assert_eq!((event.exchange.as_str(), event.status), (spec.exchange_code, expected));
+}
+
+/// Wait for a subscription to the symbol on the open connection, and acknowledge it if the
+/// adapter awaits it.
+async fn expect_subscription(exchange: &SimulatedExchange, spec: &ConformanceSpec, symbol: &str) {
+    let subscribed = timeout(TIMEOUT, async {
+        while !exchange.received_messages().iter().any(|m| m.contains(symbol)) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }).await;
+    assert!(subscribed.is_ok(), "no subscription to {} in {:?}", symbol, exchange.received_messages());
+    if let Some(ack) = &spec.subscription_ack {
+        exchange.publish(ack.clone());
+    }
+}
+
+/// Number of subscriptions to the symbol received so far, from all the connections.
+fn subscriptions(exchange: &SimulatedExchange, symbol: &str) -> usize {
+    exchange.received_messages().iter().filter(|m| m.contains(symbol)).count()
+}
+
+/// Expect a reconnection, after `previous` subscriptions to the symbol were received.
+async fn expect_reconnection(exchange: &SimulatedExchange, stream: &mut ExchangeAdapterStream<BookUpdate>, spec: &ConformanceSpec, symbol: &str, previous: usize) {
+    assert_eq!(next_event(stream).await, ExchangeEvent::Connected(spec.exchange_code.into()));
+    timeout(TIMEOUT, exchange.wait_for_connections(1)).await.expect("adapter not reconnected");
+    let resubscribed = timeout(TIMEOUT, async {
+        while subscriptions(exchange, symbol) <= previous {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }).await;
+    assert!(resubscribed.is_ok(), "no resubscription to {}", symbol);
+    if let Some(ack) = &spec.subscription_ack {
+        exchange.publish(ack.clone());
+    }
+    expect_fixtures(exchange, stream, spec).await;
+}
+
+/// Run the conformance checks on an adapter.
+async fn check_conformance(adapter: ExchangeAdapter<BookUpdate>, spec: ConformanceSpec) {
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    let exchange = SimulatedExchange::start().await.unwrap();
+    let (status_sender, mut status) = broadcast::channel(16);
+    let mut stream = adapter.with_ws_url(exchange.url()).with_status_sender(status_sender).make_stream().await;
+
+    // subscription, with the symbol formatted by the adapter, up once acknowledged
+    assert_eq!(next_event(&mut stream).await, ExchangeEvent::Connected(spec.exchange_code.into()));
+    timeout(TIMEOUT, exchange.wait_for_connections(1)).await.expect("adapter not connected");
+    let symbol = (spec.format_symbol)(&product);
+    if spec.subscription_ack.is_some() {
+        assert!(status.try_recv().is_err(), "adapter up before the subscription was acknowledged");
+    }
+    expect_subscription(&exchange, &spec, &symbol).await;
+    expect_status(&mut status, &spec, ExchangeStatus::Up).await;
+
+    // fixture snapshots
+    expect_fixtures(&exchange, &mut stream, &spec).await;
+
+    // keepalive
+    exchange.ping();
+    timeout(TIMEOUT, exchange.wait_for_pongs(1)).await.expect("no pong from adapter");
+
+    // repeated parse failures
+    for _ in 0..10 {
+        exchange.publish("not a message".to_string());
+    }
+    expect_status(&mut status, &spec, ExchangeStatus::ParseFailures { count: 10 }).await;
+
+    // dropped connection
+    let previous = subscriptions(&exchange, &symbol);
+    exchange.drop_connections();
+    expect_status(&mut status, &spec, ExchangeStatus::Down).await;
+    expect_reconnection(&exchange, &mut stream, &spec, &symbol, previous).await;
+    expect_status(&mut status, &spec, ExchangeStatus::Up).await;
+
+    // reconnection requested by the exchange
+    if let Some(request) = &spec.reconnection_request {
+        let previous = subscriptions(&exchange, &symbol);
+        exchange.publish(request.clone());
+        expect_reconnection(&exchange, &mut stream, &spec, &symbol, previous).await;
+    }
+
+    // symbol formatting round-trip
+    for product_str in PRODUCTS {
+        let product = parse_currency_pair(product_str).unwrap();
+        assert_eq!(parse_currency_pair(&(spec.format_symbol)(&product)), Some(product), "{}", product_str);
+    }
+
+    stream.disconnect().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_binance_conformance() {
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    check_conformance(make_binance_exchange_adapter(&product), ConformanceSpec {
+        exchange_code: "binance",
+        format_symbol: binance_symbol,
+        fixtures: vec![(
+            r#"{"lastUpdateId":160,"bids":[["0.0024","10"],["0.0023","5"]],"asks":[["0.0026","100"]]}"#.to_string(),
+            book_update("binance", &[("0.0024", "10"), ("0.0023", "5")], &[("0.0026", "100")]),
+        )],
+        reconnection_request: None,
+        subscription_ack: Some(r#"{"result":null,"id":10}"#.to_string()),
+    }).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_bitstamp_conformance() {
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    check_conformance(make_bitstamp_echange_adapter(&product), ConformanceSpec {
+        exchange_code: "bitstamp",
+        format_symbol: bitstamp_symbol,
+        fixtures: vec![(
+            r#"{"data":{"timestamp":"1686727555","microtimestamp":"1686727555138288","bids":[["0.06","1.5"]],"asks":[["0.061","2"],["0.062","3"]]},"channel":"order_book_ethbtc","event":"data"}"#.to_string(),
+            book_update("bitstamp", &[("0.06", "1.5")], &[("0.061", "2"), ("0.062", "3")]),
+        )],
+        reconnection_request: Some(r#"{"event":"bts:request_reconnect","channel":"","data":""}"#.to_string()),
+        subscription_ack: Some(r#"{"event":"bts:subscription_succeeded","channel":"order_book_ethbtc","data":{}}"#.to_string()),
+    }).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_simulated_conformance() {
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    let exchange = SimulatedExchange::start().await.unwrap();
+    check_conformance(exchange.adapter(&product), ConformanceSpec {
+        exchange_code: SIMULATED_CODE,
+        format_symbol: canonical_symbol,
+        fixtures: vec![(
+            book_update_message(&[("100", "1")], &[("101", "2")]),
+            book_update(SIMULATED_CODE, &[("100", "1")], &[("101", "2")]),
+        )],
+        reconnection_request: Some(r#"{"event":"reconnect"}"#.to_string()),
+        subscription_ack: None,
+    }).await;
+}
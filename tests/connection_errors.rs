@@ -0,0 +1,41 @@
+//! Connection errors test: an exchange which cannot be connected to is restarted by the
+//! supervisor with an increasing delay, without panicking.
+
+mod common;
+
+use futures::StreamExt;
+use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+use tokio::time::{timeout, Duration, Instant};
+
+use orderbook_server::core::BookUpdate;
+use orderbook_server::exchange::{ExchangeAdapter, ExchangeEvent, ExchangeProtocol, ReconnectPolicy};
+use orderbook_server::simulated::SIMULATED_CODE;
+
+use common::free_port;
+
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Number of panics of the test process.
+static PANICS: AtomicUsize = AtomicUsize::new(0);
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_connection_errors_restarted() {
+    let previous_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        PANICS.fetch_add(1, Ordering::SeqCst);
+        previous_hook(info);
+    }));
+    let url = format!("ws://127.0.0.1:{}", free_port());
+    let adapter = ExchangeAdapter::new(SIMULATED_CODE, url, Arc::new(|_: &str| Some(ExchangeProtocol::<BookUpdate>::Skipped)))
+        .with_reconnect_policy(ReconnectPolicy { reconnect_delay: Duration::from_millis(50), max_restart_delay: Duration::from_millis(200) });
+    let mut stream = adapter.make_stream().await;
+    let started = Instant::now();
+    for _ in 0..3 {
+        assert_eq!(timeout(TIMEOUT, stream.next()).await.unwrap(), Some(ExchangeEvent::Disconnected(SIMULATED_CODE.into())));
+    }
+    // restarted after 50ms, then 100ms
+    assert!(started.elapsed() >= Duration::from_millis(150));
+    assert_eq!(PANICS.load(Ordering::SeqCst), 0);
+}
@@ -0,0 +1,46 @@
+//! Crash dump test: a panic while parsing a message from a simulated exchange writes a dump
+//! with the offending message, and the supervisor restarts the adapter task.
+
+use futures::StreamExt;
+use std::sync::Arc;
+use tokio::time::{sleep, timeout, Duration};
+
+use orderbook_server::core::BookUpdate;
+use orderbook_server::crash_dump::install_panic_hook;
+use orderbook_server::exchange::{ExchangeAdapter, ExchangeEvent, ExchangeProtocol};
+use orderbook_server::simulated::{book_update_message, SimulatedExchange, SIMULATED_CODE};
+
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const POISON_MESSAGE: &str = "poison";
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_dump_written_on_reader_panic() {
+    let dir = std::env::temp_dir().join(format!("orderbook-crash-dumps-{}", std::process::id()));
+    install_panic_hook(dir.clone()).unwrap();
+    let exchange = SimulatedExchange::start().await.unwrap();
+    let adapter = ExchangeAdapter::new(SIMULATED_CODE, exchange.url(), Arc::new(|text: &str| {
+        assert_ne!(text, POISON_MESSAGE, "poisoned message");
+        Some(ExchangeProtocol::<BookUpdate>::Skipped)
+    }));
+    let mut stream = adapter.make_stream().await;
+    assert_eq!(timeout(TIMEOUT, stream.next()).await.unwrap(), Some(ExchangeEvent::Connected(SIMULATED_CODE.into())));
+
+    exchange.publish(book_update_message(&[("100", "1")], &[]));
+    exchange.publish(POISON_MESSAGE.to_string());
+    assert_eq!(timeout(TIMEOUT, stream.next()).await.unwrap(), Some(ExchangeEvent::Disconnected(SIMULATED_CODE.into())));
+    let dump = timeout(TIMEOUT, async {
+        loop {
+            if let Some(Ok(entry)) = std::fs::read_dir(&dir).unwrap().next() {
+                break std::fs::read_to_string(entry.path()).unwrap();
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+    }).await.expect("no dump written");
+    assert!(dump.contains("poisoned message"), "{}", dump);
+    assert!(dump.contains(&format!("venue {}: 2 messages", SIMULATED_CODE)), "{}", dump);
+    assert!(dump.contains(&format!("last message: {}", POISON_MESSAGE)), "{}", dump);
+    assert_eq!(timeout(TIMEOUT, stream.next()).await.unwrap(), Some(ExchangeEvent::Connected(SIMULATED_CODE.into())));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
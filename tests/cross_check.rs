@@ -0,0 +1,66 @@
+//! Cross-check test of the consolidation: random complete books from several exchanges are
+//! applied to the incremental aggregate book, which must always agree with the naive book.
+
+use rust_decimal::Decimal;
+
+use orderbook_server::aggregator::AggregateBook;
+use orderbook_server::core::{BookUpdate, ExchangeLevel, NUM_LEVELS};
+use orderbook_server::cross_check::CrossCheck;
+
+
+const EXCHANGES: [&str; 3] = ["binance", "bitstamp", "bitfinex"];
+const ROUNDS: usize = 5000;
+
+
+/// Deterministic pseudo-random generator (xorshift), so that failures can be reproduced.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self, bound: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % bound
+    }
+
+    /// Distinct prices out of a small range, so that exchanges often share prices, sorted.
+    fn levels(&mut self, exchange_code: &'static str, descending: bool) -> Vec<ExchangeLevel> {
+        let mut prices: Vec<u64> = (0..40).filter(|_| self.next(3) == 0).collect();
+        if descending {
+            prices.reverse();
+        }
+        prices.into_iter()
+            .map(|price| ExchangeLevel {
+                exchange_code: exchange_code.into(),
+                price: Decimal::new(1000 + price as i64, 2),
+                amount: Decimal::new(1 + self.next(500) as i64, 1),
+                order_count: None,
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_random_books() {
+    let mut random = Random(0x2545F4914F6CDD1D);
+    let mut aggregate_book = AggregateBook::full_depth(NUM_LEVELS);
+    let mut cross_check = CrossCheck::new(NUM_LEVELS);
+    for round in 0..ROUNDS {
+        let exchange_code = EXCHANGES[random.next(EXCHANGES.len() as u64) as usize];
+        if random.next(20) == 0 {
+            aggregate_book.remove_exchange(exchange_code);
+            cross_check.remove_exchange(exchange_code);
+        } else {
+            let book_update = BookUpdate {
+                exchange_code: exchange_code.into(),
+                bids: random.levels(exchange_code, true),
+                asks: random.levels(exchange_code, false),
+            };
+            aggregate_book.update(book_update.clone());
+            cross_check.update(book_update);
+        }
+        let discrepancies = cross_check.compare(&aggregate_book);
+        assert!(discrepancies.is_empty(), "round {}: {}", round,
+            discrepancies.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "));
+    }
+}
@@ -0,0 +1,83 @@
+//! Custom exchange test: a venue implemented outside the crate, through the `Exchange` trait,
+//! is registered by name, consolidated and reported by the server like the exchange adapters.
+
+mod common;
+
+use futures::{future::BoxFuture, stream, StreamExt};
+use std::sync::Arc;
+use tokio::time::{timeout, Duration};
+
+use orderbook_server::accounting::UsageRegistry;
+use orderbook_server::clock::system_clock;
+use orderbook_server::core::{BookUpdate, ExchangeId, ExchangeLevel};
+use orderbook_server::exchange::{ConnectionSettings, Exchange, ExchangeAdapterStream, ExchangeEvent, Registry};
+use orderbook_server::grpc::ProtobufOrderbookServer;
+use orderbook_server::orderbook::{orderbook_aggregator_client::OrderbookAggregatorClient, BookSummaryRequest, Empty};
+use orderbook_server::simulated::{book_update_message, SimulatedExchange, SIMULATED_CODE};
+use orderbook_server::symbols::parse_currency_pair;
+
+use common::free_port;
+
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const CUSTOM_CODE: &str = "custom";
+
+
+/// Venue quoting a fixed book.
+struct FixedExchange;
+
+impl Exchange for FixedExchange {
+    fn code(&self) -> ExchangeId {
+        CUSTOM_CODE.into()
+    }
+
+    fn stream(&self, _stagger: Duration, settings: &ConnectionSettings) -> BoxFuture<'static, ExchangeAdapterStream<BookUpdate>> {
+        let book_update = BookUpdate {
+            exchange_code: CUSTOM_CODE.into(),
+            bids: vec![ExchangeLevel::from_strs(CUSTOM_CODE, "100", "2")],
+            asks: vec![ExchangeLevel::from_strs(CUSTOM_CODE, "103", "2")],
+        };
+        let events = stream::iter([ExchangeEvent::Connected(CUSTOM_CODE.into()), ExchangeEvent::Data(book_update)])
+            .chain(stream::pending())
+            .boxed();
+        let stream = ExchangeAdapterStream::from_stream(events, settings.queue_capacities.unwrap_or_default());
+        Box::pin(async move { stream })
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_custom_exchange_consolidated() {
+    let exchange = SimulatedExchange::start().await.unwrap();
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    let registry = Registry::default().with_exchange(CUSTOM_CODE, Arc::new(|_| Box::new(FixedExchange)));
+    let mut exchanges = registry.make_adapters(&registry.parse_names(CUSTOM_CODE).unwrap(), &product);
+    exchanges.push(Box::new(exchange.adapter(&product)));
+    let server = ProtobufOrderbookServer::new(product.clone(), exchanges, UsageRegistry::new(system_clock()));
+    let port = free_port();
+    tokio::spawn(async move {
+        let _ = server.serve(port).await;
+    });
+    let url = format!("http://[::1]:{}", port);
+    let mut client = timeout(TIMEOUT, async {
+        loop {
+            match OrderbookAggregatorClient::connect(url.clone()).await {
+                Ok(client) => break client,
+                Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+            }
+        }
+    }).await.expect("server not started");
+
+    let mut summaries = client.book_summary(BookSummaryRequest::default()).await.unwrap().into_inner();
+    let summary = timeout(TIMEOUT, summaries.next()).await.expect("no summary").unwrap().unwrap();
+    assert_eq!((summary.bids[0].exchange.as_str(), summary.bids[0].price), (CUSTOM_CODE, 100.0));
+    timeout(TIMEOUT, exchange.wait_for_connections(1)).await.expect("adapter not connected");
+    exchange.publish(book_update_message(&[("101", "1")], &[("102", "1")]));
+    let summary = timeout(TIMEOUT, summaries.next()).await.expect("no summary").unwrap().unwrap();
+    let best: Vec<(&str, f64)> = summary.bids.iter().map(|level| (level.exchange.as_str(), level.price)).collect();
+    assert_eq!(best, vec![(SIMULATED_CODE, 101.0), (CUSTOM_CODE, 100.0)]);
+
+    let exchanges = client.list_exchanges(Empty {}).await.unwrap().into_inner().exchanges;
+    let codes: Vec<&str> = exchanges.iter().map(|exchange| exchange.code.as_str()).collect();
+    assert_eq!(codes, vec![CUSTOM_CODE, SIMULATED_CODE]);
+    assert_eq!(exchanges[0].region, "unknown");
+}
@@ -0,0 +1,39 @@
+//! Decimal normalization test: the books of a simulated exchange are delivered with their
+//! prices and amounts normalized, whatever the formatting of the exchange.
+
+use futures::StreamExt;
+use tokio::time::{timeout, Duration};
+
+use orderbook_server::core::BookUpdate;
+use orderbook_server::exchange::ExchangeEvent;
+use orderbook_server::simulated::{book_update_message, SimulatedExchange, SIMULATED_CODE};
+use orderbook_server::symbols::parse_currency_pair;
+
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+
+/// Internal function formatting the levels of a book update.
+fn formatted(book_update: &BookUpdate) -> Vec<(String, String)> {
+    book_update.bids.iter().chain(&book_update.asks)
+        .map(|level| (level.price.to_string(), level.amount.to_string()))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_decimals_normalized() {
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    let exchange = SimulatedExchange::start().await.unwrap();
+    let mut stream = exchange.adapter(&product).with_decimal_normalization().make_stream().await;
+    assert_eq!(timeout(TIMEOUT, stream.next()).await.unwrap(), Some(ExchangeEvent::Connected(SIMULATED_CODE.into())));
+
+    exchange.publish(book_update_message(&[("0.061200", "1.500")], &[("0.06130000", "2.0")]));
+    match timeout(TIMEOUT, stream.next()).await.unwrap() {
+        Some(ExchangeEvent::Data(book_update)) => assert_eq!(formatted(&book_update), vec![
+            ("0.0612".to_string(), "1.5".to_string()),
+            ("0.0613".to_string(), "2".to_string()),
+        ]),
+        event => panic!("unexpected event {:?}", event),
+    }
+    stream.disconnect().await;
+}
@@ -0,0 +1,279 @@
+//! End-to-end test: the gRPC server consolidating a simulated exchange, queried by a client.
+
+mod common;
+
+use std::time::SystemTime;
+use tokio::time::{timeout, Duration};
+use tokio_stream::StreamExt;
+
+use orderbook_server::accounting::UsageRegistry;
+use orderbook_server::clock::system_clock;
+use orderbook_server::grpc::{ProtobufOrderbookServer, PROTOCOL_VERSION, PROTOCOL_VERSION_METADATA, SERVER_VERSION, SERVER_VERSION_METADATA, SNAPSHOT_METADATA, FAIR_VALUES_METADATA, SYMBOLS_METADATA, SUMMARY_FIELDS_METADATA};
+use orderbook_server::orderbook::{orderbook_aggregator_client::OrderbookAggregatorClient, BookSide, BookSummaryRequest, Empty, ExchangeInfo, FairValue, LatencyClass, Level, PingRequest, RouteRequest, Summary, SweepPriceRequest, SymbolInfo, SymbolList, SymbolVenue, VenueQuoteShare};
+use orderbook_server::proto_ext::{PingResponseExt, Timestamp, TimestampExt};
+use orderbook_server::simulated::{book_update_message, SimulatedExchange, SIMULATED_CODE};
+use orderbook_server::symbols::parse_currency_pair;
+
+use common::free_port;
+
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+
+fn level(price: f64, amount: f64) -> Level {
+    Level { exchange: SIMULATED_CODE.to_string(), price, amount, order_count: None, latency_class: LatencyClass::Fresh as i32 }
+}
+
+/// Wait for the next summary of a stream with a best bid, skipping the current book replayed
+/// to new subscriptions.
+async fn next_with_best_bid(summaries: &mut tonic::Streaming<Summary>, best_bid: Level) -> Summary {
+    timeout(TIMEOUT, async {
+        loop {
+            let summary = summaries.next().await.unwrap().unwrap();
+            if summary.bids.first() == Some(&best_bid) {
+                break summary;
+            }
+        }
+    }).await.expect("no summary")
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_summaries_from_simulated_exchange() {
+    let exchange = SimulatedExchange::start().await.unwrap();
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    let server = ProtobufOrderbookServer::new(
+        product.clone(),
+        vec![Box::new(exchange.adapter(&product))],
+        UsageRegistry::new(system_clock()),
+    );
+    let port = free_port();
+    tokio::spawn(async move {
+        let _ = server.serve(port).await;
+    });
+    let url = format!("http://[::1]:{}", port);
+    let mut client = timeout(TIMEOUT, async {
+        loop {
+            match OrderbookAggregatorClient::connect(url.clone()).await {
+                Ok(client) => break client,
+                Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+            }
+        }
+    }).await.expect("server not started");
+    let summaries = client.book_summary(BookSummaryRequest::default()).await.unwrap();
+    assert_eq!(summaries.metadata().get(SERVER_VERSION_METADATA).unwrap(), SERVER_VERSION);
+    assert_eq!(summaries.metadata().get(PROTOCOL_VERSION_METADATA).unwrap(), PROTOCOL_VERSION.to_string().as_str());
+    let mut summaries = summaries.into_inner();
+    timeout(TIMEOUT, exchange.wait_for_connections(1)).await.expect("adapter not connected");
+
+    let best_bids = ["100.1", "100.2", "100.3", "100.4", "100.5"];
+    for best_bid in best_bids {
+        exchange.publish(book_update_message(&[(best_bid, "1"), ("99", "2")], &[("101", "1.5"), ("102", "3")]));
+    }
+    for best_bid in best_bids {
+        let summary = timeout(TIMEOUT, summaries.next()).await.expect("no summary").unwrap().unwrap();
+        let best_bid: f64 = best_bid.parse().unwrap();
+        assert_eq!(summary.symbol, "ETH-BTC");
+        assert_eq!(summary.bids, vec![level(best_bid, 1.0), level(99.0, 2.0)]);
+        assert_eq!(summary.asks, vec![level(101.0, 1.5), level(102.0, 3.0)]);
+        assert!((summary.spread - (101.0 - best_bid)).abs() < 1e-9);
+        assert_eq!((summary.contributing_exchanges, summary.expected_exchanges), (1, 1));
+    }
+    assert_eq!(exchange.connections(), 1);
+
+    // Queries use the feed shared by the server, on the same connection as the client streams.
+    exchange.publish(book_update_message(&[("100", "1"), ("99", "2")], &[("101", "1.5"), ("102", "3")]));
+    timeout(TIMEOUT, async {
+        while client.get_snapshots(SymbolList::default()).await.unwrap().into_inner().summaries[0].bids[0].price != 100.0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }).await.expect("update not consolidated");
+    let sweep = client.get_sweep_price(SweepPriceRequest { side: BookSide::Ask as i32, notional: 253.5, ..Default::default() }).await.unwrap().into_inner();
+    assert_eq!(exchange.connections(), 1);
+    assert_eq!(sweep.symbol, "ETH-BTC");
+    assert_eq!(sweep.worst_price, 102.0);
+    assert!((sweep.amount - 2.5).abs() < 1e-9);
+    assert!((sweep.average_price - 253.5 / 2.5).abs() < 1e-9);
+    assert!(sweep.complete);
+
+    let route = client.suggest_route(RouteRequest { side: BookSide::Ask as i32, amount: 5.0, ..Default::default() })
+        .await.unwrap().into_inner();
+    assert_eq!(route.allocations.len(), 1);
+    assert_eq!(route.allocations[0].exchange, SIMULATED_CODE);
+    assert_eq!((route.allocations[0].amount, route.allocations[0].worst_price), (4.5, 102.0));
+    assert_eq!(route.unfilled, 0.5);
+
+    // A new subscription asking for a snapshot receives the current book without any update.
+    let mut request = tonic::Request::new(BookSummaryRequest::default());
+    request.metadata_mut().insert(SNAPSHOT_METADATA, "true".parse().unwrap());
+    let mut primed = client.book_summary(request).await.unwrap().into_inner();
+    let snapshot = timeout(TIMEOUT, primed.next()).await.expect("no snapshot").unwrap().unwrap();
+    assert_eq!(snapshot.bids, vec![level(100.0, 1.0), level(99.0, 2.0)]);
+    assert_eq!(snapshot.asks, vec![level(101.0, 1.5), level(102.0, 3.0)]);
+
+    // Optional fields left out are not published.
+    let mut request = tonic::Request::new(BookSummaryRequest::default());
+    request.metadata_mut().insert(SNAPSHOT_METADATA, "true".parse().unwrap());
+    request.metadata_mut().insert(SUMMARY_FIELDS_METADATA, "spread".parse().unwrap());
+    let mut trimmed = client.book_summary(request).await.unwrap().into_inner();
+    let snapshot = timeout(TIMEOUT, trimmed.next()).await.expect("no snapshot").unwrap().unwrap();
+    let anonymous = |level: Level| Level { exchange: String::new(), latency_class: LatencyClass::Unknown as i32, ..level };
+    assert_eq!(snapshot.bids, vec![anonymous(level(100.0, 1.0)), anonymous(level(99.0, 2.0))]);
+    assert_eq!(snapshot.spread, 1.0);
+    assert!(snapshot.depth.is_empty());
+    assert_eq!((snapshot.contributing_exchanges, snapshot.expected_exchanges), (0, 0));
+    let mut request = tonic::Request::new(BookSummaryRequest::default());
+    request.metadata_mut().insert(SUMMARY_FIELDS_METADATA, "spread,timestamp".parse().unwrap());
+    let status = client.book_summary(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+    // Fair values are computed for the subscriptions asking for them.
+    let mut request = tonic::Request::new(BookSummaryRequest::default());
+    request.metadata_mut().insert(FAIR_VALUES_METADATA, "weighted_mid,depth_weighted_mid:2,mid,microprice:2".parse().unwrap());
+    let mut valued = client.book_summary(request).await.unwrap().into_inner();
+    exchange.publish(book_update_message(&[("100", "3"), ("99", "3")], &[("101", "1"), ("103", "1")]));
+    let summary = next_with_best_bid(&mut valued, level(100.0, 3.0)).await;
+    assert_eq!(summary.fair_values, vec![
+        FairValue { method: "weighted_mid".to_string(), value: 100.75 },
+        FairValue { method: "depth_weighted_mid:2".to_string(), value: 101.375 },
+        FairValue { method: "mid".to_string(), value: 100.5 },
+        FairValue { method: "microprice:2".to_string(), value: 100.75 },
+    ]);
+    assert!(timeout(TIMEOUT, primed.next()).await.unwrap().unwrap().unwrap().fair_values.is_empty());
+    let mut request = tonic::Request::new(BookSummaryRequest::default());
+    request.metadata_mut().insert(FAIR_VALUES_METADATA, "vwap".parse().unwrap());
+    let status = client.book_summary(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+    // Subscriptions set their symbol and depth in the request.
+    let mut request = tonic::Request::new(BookSummaryRequest { symbol: "ethbtc".to_string(), depth: 1 });
+    request.metadata_mut().insert(SNAPSHOT_METADATA, "true".parse().unwrap());
+    let mut shallow = client.book_summary(request).await.unwrap().into_inner();
+    let snapshot = timeout(TIMEOUT, shallow.next()).await.expect("no snapshot").unwrap().unwrap();
+    assert_eq!((snapshot.bids, snapshot.asks), (vec![level(100.0, 3.0)], vec![level(101.0, 1.0)]));
+    exchange.publish(book_update_message(&[("100", "2"), ("99", "1")], &[("101", "1"), ("103", "1")]));
+    let summary = next_with_best_bid(&mut shallow, level(100.0, 2.0)).await;
+    assert_eq!((summary.bids, summary.asks), (vec![level(100.0, 2.0)], vec![level(101.0, 1.0)]));
+    // the depth bands are computed on the whole book, not on the levels published
+    exchange.publish(book_update_message(&[("100", "3"), ("99.9", "1")], &[("101", "1"), ("101.1", "2")]));
+    let summary = next_with_best_bid(&mut shallow, level(100.0, 3.0)).await;
+    assert_eq!(summary.bids.len(), 1);
+    let band = summary.depth.iter().find(|band| band.bps == 100).expect("no 100 bps band");
+    assert_eq!((band.bid_amount, band.ask_amount), (4.0, 3.0));
+    let status = client.book_summary(BookSummaryRequest { depth: 11, ..Default::default() }).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    let status = client.book_summary(BookSummaryRequest { symbol: "BTC-USDT".to_string(), depth: 0 }).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
+
+    let configuration = client.get_configuration(Empty {}).await.unwrap().into_inner();
+    let configuration: serde_json::Value = serde_json::from_str(&configuration.json).unwrap();
+    assert_eq!(configuration["symbols"][0], "ETH-BTC");
+    assert_eq!(configuration["venues"][0]["exchange"], SIMULATED_CODE);
+
+    let server_info = client.get_server_info(Empty {}).await.unwrap().into_inner();
+    assert_eq!((server_info.version.as_str(), server_info.protocol_version), (SERVER_VERSION, PROTOCOL_VERSION));
+    assert!(!server_info.git_hash.is_empty());
+
+    let client_sent_at = Timestamp::now();
+    let pong = client.ping(PingRequest { client_sent_at: Some(client_sent_at.clone()) }).await.unwrap().into_inner();
+    let client_received_at = SystemTime::now();
+    assert_eq!(pong.client_sent_at, Some(client_sent_at));
+    assert!(pong.server_processing().unwrap() < TIMEOUT);
+    assert!(pong.network_latency(client_received_at).unwrap() < TIMEOUT);
+    assert!(pong.clock_offset_us(client_received_at).unwrap().abs() < TIMEOUT.as_micros() as i64);
+
+    let symbols = timeout(TIMEOUT, async {
+        loop {
+            let symbols = client.list_symbols(Empty {}).await.unwrap().into_inner().symbols;
+            if symbols.iter().all(|symbol| symbol.healthy) {
+                break symbols;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }).await.expect("symbol not healthy");
+    assert_eq!(symbols.len(), 1);
+    assert_eq!(symbols[0].symbol, "ETH-BTC");
+    assert_eq!(
+        symbols[0].venues,
+        vec![SymbolVenue { exchange: SIMULATED_CODE.to_string(), healthy: true, status: "up".to_string() }],
+    );
+
+    let exchanges = client.list_exchanges(Empty {}).await.unwrap().into_inner().exchanges;
+    assert_eq!(exchanges, vec![ExchangeInfo {
+        code: SIMULATED_CODE.to_string(),
+        updates: "snapshots".to_string(),
+        max_depth: 0,
+        heartbeat: "websocket_ping".to_string(),
+        region: "local".to_string(),
+        state: "up".to_string(),
+        endpoint: exchange.url(),
+        symbol: "ETH-BTC".to_string(),
+    }]);
+
+    let quote_share = client.get_quote_share(Empty {}).await.unwrap().into_inner();
+    assert_eq!((quote_share.symbol.as_str(), quote_share.window_ms), ("ETH-BTC", 300_000));
+    assert_eq!(quote_share.venues, vec![VenueQuoteShare { exchange: SIMULATED_CODE.to_string(), best_bid_share: 1.0, best_ask_share: 1.0 }]);
+
+    let symbol_list = |symbols: &[&str]| SymbolList {
+        symbols: symbols.iter().map(|symbol| SymbolInfo { symbol: symbol.to_string(), ..Default::default() }).collect(),
+    };
+    let batch = client.get_snapshots(symbol_list(&["ethbtc", "BTC-USDT"])).await.unwrap().into_inner();
+    assert_eq!(batch.summaries.len(), 1);
+    assert_eq!(batch.summaries[0].symbol, "ETH-BTC");
+    assert_eq!(batch.summaries[0].bids[0].price, 100.0);
+    assert_eq!(batch.missing, vec!["BTC-USDT".to_string()]);
+    let batch = client.get_snapshots(symbol_list(&[])).await.unwrap().into_inner();
+    assert_eq!((batch.summaries.len(), batch.missing.len()), (1, 0));
+
+    // Subscriptions to symbols, groups and patterns, none of them served or unknown.
+    let selection_status = |selection: &'static str| {
+        let mut client = client.clone();
+        async move {
+            let mut request = tonic::Request::new(BookSummaryRequest::default());
+            request.metadata_mut().insert(SYMBOLS_METADATA, selection.parse().unwrap());
+            client.book_summary(request).await.err().map(|status| status.code())
+        }
+    };
+    assert_eq!(selection_status("BTC-USDT, eth-*").await, None);
+    assert_eq!(selection_status("*-USDT").await, Some(tonic::Code::NotFound));
+    assert_eq!(selection_status("majors").await, Some(tonic::Code::InvalidArgument));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_single_upstream_subscription() {
+    let exchange = SimulatedExchange::start().await.unwrap();
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    let server = ProtobufOrderbookServer::new(
+        product.clone(),
+        vec![Box::new(exchange.adapter(&product))],
+        UsageRegistry::new(system_clock()),
+    );
+    let port = free_port();
+    tokio::spawn(async move {
+        let _ = server.serve(port).await;
+    });
+    let url = format!("http://[::1]:{}", port);
+    let mut client = timeout(TIMEOUT, async {
+        loop {
+            match OrderbookAggregatorClient::connect(url.clone()).await {
+                Ok(client) => break client,
+                Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+            }
+        }
+    }).await.expect("server not started");
+
+    // two client streams of the same symbol share the subscription to the exchange
+    let mut first = client.book_summary(BookSummaryRequest::default()).await.unwrap().into_inner();
+    let mut second = client.book_summary(BookSummaryRequest { symbol: "ETH-BTC".to_string(), depth: 0 }).await.unwrap().into_inner();
+    timeout(TIMEOUT, exchange.wait_for_connections(1)).await.expect("adapter not connected");
+    exchange.publish(book_update_message(&[("100", "1")], &[("101", "1")]));
+    next_with_best_bid(&mut first, level(100.0, 1.0)).await;
+    next_with_best_bid(&mut second, level(100.0, 1.0)).await;
+    assert_eq!(exchange.connections(), 1);
+    let subscriptions = || exchange.received_messages().iter().filter(|message| message.contains("SUBSCRIBE")).count();
+    timeout(TIMEOUT, async {
+        while subscriptions() == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }).await.expect("no subscription received");
+    assert_eq!(subscriptions(), 1);
+}
@@ -0,0 +1,33 @@
+//! Event bus test: a summary stream subscribed to the book events of the bus of a symbol ends
+//! once the bus is dropped, after the summaries of the events published.
+
+use futures::StreamExt;
+use tokio::time::{timeout, Duration};
+
+use orderbook_server::core::{BookUpdate, ExchangeLevel};
+use orderbook_server::event_bus::EventBus;
+use orderbook_server::exchange::ExchangeEvent;
+use orderbook_server::service::BookSummaryService;
+use orderbook_server::symbols::parse_currency_pair;
+
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+
+#[tokio::test]
+async fn test_stream_ends_with_bus() {
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    let bus = EventBus::new();
+    let mut service = BookSummaryService::from_bus(&product, &bus, 1);
+    bus.publish_book_event(ExchangeEvent::Connected("test".into()));
+    bus.publish_book_event(ExchangeEvent::Data(BookUpdate {
+        exchange_code: "test".into(),
+        bids: vec![ExchangeLevel::from_strs("test", "100", "1")],
+        asks: vec![ExchangeLevel::from_strs("test", "101", "1")],
+    }));
+    drop(bus);
+
+    let summary = timeout(TIMEOUT, service.next()).await.expect("no summary").expect("service stopped");
+    assert_eq!(summary.bids[0].price, 100.0);
+    assert_eq!(timeout(TIMEOUT, service.next()).await.expect("service not stopped"), None);
+}
@@ -0,0 +1,39 @@
+//! Failover test: with the primary endpoint unreachable, the adapter connects to the next
+//! endpoint, a simulated exchange, and notifies the change.
+
+use futures::StreamExt;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio::time::{timeout, Duration};
+
+use orderbook_server::core::BookUpdate;
+use orderbook_server::exchange::{ExchangeAdapter, ExchangeEvent, ExchangeProtocol};
+use orderbook_server::simulated::{SimulatedExchange, SIMULATED_CODE};
+use orderbook_server::status::ExchangeStatus;
+
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+
+#[tokio::test]
+async fn test_failover_to_next_endpoint() {
+    let unreachable = {
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        format!("ws://{}/ws", closed.local_addr().unwrap())
+    };
+    let exchange = SimulatedExchange::start().await.unwrap();
+    let (status_sender, mut status) = broadcast::channel(16);
+    let adapter = ExchangeAdapter::new(SIMULATED_CODE, unreachable.clone(), Arc::new(|_: &str| Some(ExchangeProtocol::<BookUpdate>::Skipped)))
+        .with_failover_url(exchange.url())
+        .with_status_sender(status_sender);
+    assert_eq!(adapter.active_endpoint(), unreachable);
+    assert_eq!(adapter.config().failover_urls, vec![exchange.url()]);
+    let mut stream = adapter.make_stream().await;
+    assert_eq!(timeout(TIMEOUT, stream.next()).await.unwrap(), Some(ExchangeEvent::Connected(SIMULATED_CODE.into())));
+    let event = timeout(TIMEOUT, status.recv()).await.unwrap().unwrap();
+    assert_eq!(event.status, ExchangeStatus::FailedOver { endpoint: exchange.url() });
+    assert_eq!(timeout(TIMEOUT, status.recv()).await.unwrap().unwrap().status, ExchangeStatus::Up);
+    assert_eq!(adapter.active_endpoint(), exchange.url());
+    stream.disconnect().await;
+}
@@ -0,0 +1,81 @@
+//! HTX test: the adapter decompresses the gzip frames of a simulated exchange, answers its
+//! heartbeats with the same timestamp, and is up once its subscription is answered.
+
+mod common;
+
+use tokio::sync::broadcast;
+use tokio::time::{timeout, Duration};
+
+use orderbook_server::core::{BookUpdate, ExchangeLevel};
+use orderbook_server::exchange::ExchangeEvent;
+use orderbook_server::htx::make_htx_exchange_adapter;
+use orderbook_server::simulated::SimulatedExchange;
+use orderbook_server::status::ExchangeStatus;
+use orderbook_server::symbols::parse_currency_pair;
+
+use common::next_event;
+
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const HTX_CODE: &str = "htx";
+/// `{"id":"1","status":"ok","subbed":"market.ethbtc.depth.step0","ts":1686727555138}`, compressed.
+const SUBSCRIBED: &str = "1f8b0800000000000203ab56ca4c51b2523254d2512a2e492c292d0672f2b341bcd2a4a45490546e6251766a895e6a49465249b25e4a6a4149865e71496a810150510950b9a1998599b991b9a9a9a9a1b1452d00bf38e58a50000000";
+/// `{"ping":1492420473027}`, compressed.
+const PING: &str = "1f8b0800000000000203ab562ac8cc4b57b23234b134323132303137363032af05006c9fbb7a16000000";
+/// A snapshot of 3 levels on each side, compressed.
+const TICK: &str = "1f8b0800000000000203458acd0a80201006dfe53b2f8bf677f055620f65822281e4dea2772f28e936c3cc091fe1b02f470eca41e3aa9eb7503472d5500c089a7c863bb1a6adc2cdb36133d98e2c8f42af58ea1a522f42586afed79e6cabc3933f1c6910b9ae1b0f46ff1180000000";
+
+
+fn hex(value: &str) -> Vec<u8> {
+    (0..value.len()).step_by(2).map(|i| u8::from_str_radix(&value[i..i + 2], 16).unwrap()).collect()
+}
+
+/// Wait until the exchange received a message.
+async fn wait_for_message(exchange: &SimulatedExchange, message: &str) {
+    let received = timeout(TIMEOUT, async {
+        while !exchange.received_messages().iter().any(|m| m == message) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }).await;
+    assert!(received.is_ok(), "no {} in {:?}", message, exchange.received_messages());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_htx_compressed_frames() {
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    let exchange = SimulatedExchange::start().await.unwrap();
+    let (status_sender, mut status) = broadcast::channel(16);
+    let mut stream = make_htx_exchange_adapter(&product)
+        .with_ws_url(exchange.url())
+        .with_status_sender(status_sender)
+        .make_stream()
+        .await;
+    assert_eq!(next_event(&mut stream).await, ExchangeEvent::Connected(HTX_CODE.into()));
+    wait_for_message(&exchange, r#"{"sub":"market.ethbtc.depth.step0","id":"1"}"#).await;
+
+    // frames which cannot be decompressed are parse failures
+    exchange.publish_binary(b"not gzip".to_vec());
+    exchange.publish_binary(hex(SUBSCRIBED));
+    let event = timeout(TIMEOUT, status.recv()).await.expect("no status from adapter").unwrap();
+    assert_eq!((event.exchange.as_str(), event.status), (HTX_CODE, ExchangeStatus::Up));
+
+    exchange.publish_binary(hex(PING));
+    wait_for_message(&exchange, r#"{"pong":1492420473027}"#).await;
+
+    exchange.publish_binary(hex(TICK));
+    assert_eq!(next_event(&mut stream).await, ExchangeEvent::Data(BookUpdate {
+        exchange_code: HTX_CODE.into(),
+        bids: vec![
+            ExchangeLevel::from_strs(HTX_CODE, "0.0612", "1.5"),
+            ExchangeLevel::from_strs(HTX_CODE, "0.0611", "2"),
+            ExchangeLevel::from_strs(HTX_CODE, "0.061", "3"),
+        ],
+        asks: vec![
+            ExchangeLevel::from_strs(HTX_CODE, "0.0613", "1"),
+            ExchangeLevel::from_strs(HTX_CODE, "0.0614", "3"),
+            ExchangeLevel::from_strs(HTX_CODE, "0.0615", "4"),
+        ],
+    }));
+
+    stream.disconnect().await;
+}
@@ -0,0 +1,36 @@
+//! Inverted book test: a book from a simulated exchange with the best bid above the best ask
+//! is rejected and counted, and the adapter reconnects to resynchronize.
+
+use futures::StreamExt;
+use tokio::time::{timeout, Duration};
+
+use orderbook_server::core::{BookUpdate, ExchangeLevel};
+use orderbook_server::exchange::ExchangeEvent;
+use orderbook_server::metrics::REJECTED_UPDATES;
+use orderbook_server::simulated::{book_update_message, SimulatedExchange, SIMULATED_CODE};
+use orderbook_server::symbols::parse_currency_pair;
+
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+
+#[tokio::test]
+async fn test_inverted_book_rejected() {
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    let exchange = SimulatedExchange::start().await.unwrap();
+    let mut stream = exchange.adapter(&product).with_inverted_book_check().make_stream().await;
+    assert_eq!(timeout(TIMEOUT, stream.next()).await.unwrap(), Some(ExchangeEvent::Connected(SIMULATED_CODE.into())));
+    let rejected = REJECTED_UPDATES.get(SIMULATED_CODE);
+
+    exchange.publish(book_update_message(&[("101", "1")], &[("100", "1")]));
+    assert_eq!(timeout(TIMEOUT, stream.next()).await.unwrap(), Some(ExchangeEvent::Connected(SIMULATED_CODE.into())));
+    assert_eq!(REJECTED_UPDATES.get(SIMULATED_CODE), rejected + 1);
+
+    exchange.publish(book_update_message(&[("99", "1")], &[("100", "1")]));
+    assert_eq!(timeout(TIMEOUT, stream.next()).await.unwrap(), Some(ExchangeEvent::Data(BookUpdate {
+        exchange_code: SIMULATED_CODE.into(),
+        bids: vec![ExchangeLevel::from_strs(SIMULATED_CODE, "99", "1")],
+        asks: vec![ExchangeLevel::from_strs(SIMULATED_CODE, "100", "1")],
+    })));
+    stream.disconnect().await;
+}
@@ -0,0 +1,82 @@
+//! KuCoin bootstrap test: before each connection, the adapter requests a token from a local
+//! REST service, retrying while it fails, then connects to the simulated exchange it returns
+//! and is up once its subscription is acknowledged.
+
+mod common;
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio::time::{timeout, Duration};
+
+use orderbook_server::core::{BookUpdate, ExchangeLevel};
+use orderbook_server::exchange::{ExchangeEvent, ReconnectPolicy};
+use orderbook_server::kucoin::make_kucoin_exchange_adapter;
+use orderbook_server::simulated::SimulatedExchange;
+use orderbook_server::status::ExchangeStatus;
+use orderbook_server::symbols::parse_currency_pair;
+
+use common::next_event;
+
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const KUCOIN_CODE: &str = "kucoin";
+
+
+/// Serve token responses on a local port, the first request failing, returning the URL.
+fn serve_tokens(ws_url: String) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/api/v1/bullet-public", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for (index, stream) in listener.incoming().enumerate() {
+            let mut stream = stream.unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            let (status, body) = match index {
+                0 => ("503 Service Unavailable", String::new()),
+                _ => ("200 OK", format!(r#"{{"code":"200000","data":{{"token":"t{}","instanceServers":[{{"endpoint":"{}/","pingInterval":18000}}]}}}}"#, index, ws_url)),
+            };
+            let response = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+    url
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_kucoin_bootstrap() {
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    let exchange = SimulatedExchange::start().await.unwrap();
+    let (status_sender, mut status) = broadcast::channel(16);
+    let mut stream = make_kucoin_exchange_adapter(&product)
+        .with_ws_url(serve_tokens(exchange.url()))
+        .with_reconnect_policy(ReconnectPolicy { reconnect_delay: Duration::from_millis(10), max_restart_delay: Duration::from_millis(100) })
+        .with_status_sender(status_sender)
+        .make_stream()
+        .await;
+
+    // the first token request fails
+    let event = timeout(TIMEOUT, status.recv()).await.expect("no status from adapter").unwrap();
+    assert_eq!((event.exchange.as_str(), event.status), (KUCOIN_CODE, ExchangeStatus::Down));
+
+    assert_eq!(next_event(&mut stream).await, ExchangeEvent::Connected(KUCOIN_CODE.into()));
+    let subscribed = timeout(TIMEOUT, async {
+        while !exchange.received_messages().iter().any(|m| m.contains(r#""id":"1","type":"subscribe","topic":"/spotMarket/level2Depth50:ETH-BTC""#)) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }).await;
+    assert!(subscribed.is_ok(), "no subscription in {:?}", exchange.received_messages());
+    assert!(status.try_recv().is_err(), "adapter up before the subscription was acknowledged");
+    exchange.publish(r#"{"id":"1","type":"ack"}"#.to_string());
+    let event = timeout(TIMEOUT, status.recv()).await.expect("no status from adapter").unwrap();
+    assert_eq!(event.status, ExchangeStatus::Up);
+
+    exchange.publish(r#"{"type":"message","topic":"/spotMarket/level2Depth50:ETH-BTC","subject":"level2","data":{"asks":[["0.0613","1"]],"bids":[["0.0612","1.5"]],"timestamp":1686727555138}}"#.to_string());
+    assert_eq!(next_event(&mut stream).await, ExchangeEvent::Data(BookUpdate {
+        exchange_code: KUCOIN_CODE.into(),
+        bids: vec![ExchangeLevel::from_strs(KUCOIN_CODE, "0.0612", "1.5")],
+        asks: vec![ExchangeLevel::from_strs(KUCOIN_CODE, "0.0613", "1")],
+    }));
+
+    stream.disconnect().await;
+}
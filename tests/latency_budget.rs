@@ -0,0 +1,56 @@
+//! Latency budget test: with the latency budget attached, each summary received from the gRPC
+//! server consolidating a simulated exchange carries its breakdown, also recorded in histograms.
+
+mod common;
+
+use tokio::time::{timeout, Duration};
+use tokio_stream::StreamExt;
+
+use orderbook_server::accounting::UsageRegistry;
+use orderbook_server::clock::system_clock;
+use orderbook_server::grpc::ProtobufOrderbookServer;
+use orderbook_server::latency_budget::{AGGREGATION_STAGE, PARSE_STAGE, QUEUE_WAIT_STAGE, SERIALIZE_STAGE};
+use orderbook_server::metrics::LATENCY_BUDGET;
+use orderbook_server::orderbook::{orderbook_aggregator_client::OrderbookAggregatorClient, BookSummaryRequest};
+use orderbook_server::simulated::{book_update_message, SimulatedExchange};
+use orderbook_server::symbols::parse_currency_pair;
+
+use common::free_port;
+
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_summaries_carry_latency_budget() {
+    let exchange = SimulatedExchange::start().await.unwrap();
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    let server = ProtobufOrderbookServer::new(
+        product.clone(),
+        vec![Box::new(exchange.adapter(&product))],
+        UsageRegistry::new(system_clock()),
+    ).with_latency_budget(true);
+    let port = free_port();
+    tokio::spawn(async move {
+        let _ = server.serve(port).await;
+    });
+    let url = format!("http://[::1]:{}", port);
+    let mut client = timeout(TIMEOUT, async {
+        loop {
+            match OrderbookAggregatorClient::connect(url.clone()).await {
+                Ok(client) => break client,
+                Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+            }
+        }
+    }).await.expect("server not started");
+    let mut summaries = client.book_summary(BookSummaryRequest::default()).await.unwrap().into_inner();
+    timeout(TIMEOUT, exchange.wait_for_connections(1)).await.expect("adapter not connected");
+
+    exchange.publish(book_update_message(&[("100", "1")], &[("101", "1")]));
+    let summary = timeout(TIMEOUT, summaries.next()).await.expect("no summary").unwrap().unwrap();
+    let budget = summary.latency_budget.expect("no latency budget");
+    assert!(budget.created_at.is_some());
+    for stage in [PARSE_STAGE, AGGREGATION_STAGE, QUEUE_WAIT_STAGE, SERIALIZE_STAGE] {
+        assert!(LATENCY_BUDGET.count(stage) > 0, "no {} sample", stage);
+    }
+}
@@ -0,0 +1,40 @@
+//! Maintenance window test: while a simulated exchange is in a maintenance window, its
+//! disconnections are notified as scheduled offline rather than down.
+
+use futures::StreamExt;
+use std::time::SystemTime;
+use tokio::sync::broadcast;
+use tokio::time::{timeout, Duration};
+
+use orderbook_server::exchange::ExchangeEvent;
+use orderbook_server::maintenance::MaintenanceSchedule;
+use orderbook_server::simulated::{SimulatedExchange, SIMULATED_CODE};
+use orderbook_server::status::ExchangeStatus;
+use orderbook_server::symbols::parse_currency_pair;
+
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+
+#[tokio::test]
+async fn test_scheduled_offline() {
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    let exchange = SimulatedExchange::start().await.unwrap();
+    let now = SystemTime::now();
+    let end = now + Duration::from_secs(3600);
+    let maintenance = MaintenanceSchedule::default().with_window(SIMULATED_CODE, now - Duration::from_secs(60), end);
+    let (status_sender, mut status) = broadcast::channel(16);
+    let mut stream = exchange.adapter(&product)
+        .with_status_sender(status_sender)
+        .with_maintenance(maintenance)
+        .make_stream().await;
+    assert_eq!(timeout(TIMEOUT, stream.next()).await.unwrap(), Some(ExchangeEvent::Connected(SIMULATED_CODE.into())));
+    assert_eq!(timeout(TIMEOUT, status.recv()).await.unwrap().unwrap().status, ExchangeStatus::Up);
+
+    exchange.drop_connections();
+    let until_ms = end.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis() as u64;
+    assert_eq!(timeout(TIMEOUT, status.recv()).await.unwrap().unwrap().status, ExchangeStatus::ScheduledOffline { until_ms });
+    assert_eq!(timeout(TIMEOUT, stream.next()).await.unwrap(), Some(ExchangeEvent::Connected(SIMULATED_CODE.into())));
+    assert_eq!(timeout(TIMEOUT, status.recv()).await.unwrap().unwrap().status, ExchangeStatus::Up);
+    stream.disconnect().await;
+}
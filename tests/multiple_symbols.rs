@@ -0,0 +1,203 @@
+//! Multiple symbols test: a server consolidating each currency pair from exchanges of its own
+//! routes the summary streams to the pipelines of the symbols they select.
+
+mod common;
+
+use tokio::time::{timeout, Duration};
+use tokio_stream::StreamExt;
+use tonic::codec::CompressionEncoding;
+
+use orderbook_server::accounting::UsageRegistry;
+use orderbook_server::clock::system_clock;
+use orderbook_server::grpc::{ProtobufOrderbookServer, MAX_SNAPSHOT_SYMBOLS, SYMBOLS_METADATA};
+use orderbook_server::orderbook::{orderbook_aggregator_client::OrderbookAggregatorClient, BookSide, BookSummaryRequest, Empty, Summary, SweepPriceRequest, SymbolInfo, SymbolList};
+use orderbook_server::simulated::{book_update_message, SimulatedExchange};
+use orderbook_server::symbols::parse_currency_pair;
+
+use common::free_port;
+
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+
+/// Open a summary stream selecting some symbols.
+async fn book_summary(client: &mut OrderbookAggregatorClient<tonic::transport::Channel>, selection: &str) -> tonic::Streaming<Summary> {
+    let mut request = tonic::Request::new(BookSummaryRequest::default());
+    request.metadata_mut().insert(SYMBOLS_METADATA, selection.parse().unwrap());
+    client.book_summary(request).await.unwrap().into_inner()
+}
+
+/// Receive a summary, returning its symbol and best bid.
+async fn next_best_bid(summaries: &mut tonic::Streaming<Summary>) -> (String, f64) {
+    let summary = timeout(TIMEOUT, summaries.next()).await.expect("no summary").unwrap().unwrap();
+    (summary.symbol, summary.bids[0].price)
+}
+
+/// Receive summaries until one of a symbol with a best bid, skipping the current books
+/// replayed to new subscriptions.
+async fn expect_best_bid(summaries: &mut tonic::Streaming<Summary>, symbol: &str, best_bid: f64) {
+    timeout(TIMEOUT, async {
+        while next_best_bid(summaries).await != (symbol.to_string(), best_bid) {}
+    }).await.expect("no summary")
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_summaries_of_several_symbols() {
+    let eth_btc_exchange = SimulatedExchange::start().await.unwrap();
+    let btc_usdt_exchange = SimulatedExchange::start().await.unwrap();
+    let eth_btc = parse_currency_pair("ETH-BTC").unwrap();
+    let btc_usdt = parse_currency_pair("BTC-USDT").unwrap();
+    let server = ProtobufOrderbookServer::new(
+        eth_btc.clone(),
+        vec![Box::new(eth_btc_exchange.adapter(&eth_btc))],
+        UsageRegistry::new(system_clock()),
+    ).with_product(btc_usdt.clone(), vec![Box::new(btc_usdt_exchange.adapter(&btc_usdt))]);
+    assert_eq!(server.served_symbols(), vec!["ETH-BTC".to_string(), "BTC-USDT".to_string()]);
+    let port = free_port();
+    tokio::spawn(async move {
+        let _ = server.serve(port).await;
+    });
+    let url = format!("http://[::1]:{}", port);
+    let mut client = timeout(TIMEOUT, async {
+        loop {
+            match OrderbookAggregatorClient::connect(url.clone()).await {
+                Ok(client) => break client,
+                Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+            }
+        }
+    }).await.expect("server not started");
+
+    // only the exchanges of the symbol selected are connected
+    let mut summaries = book_summary(&mut client, "btcusdt").await;
+    timeout(TIMEOUT, btc_usdt_exchange.wait_for_connections(1)).await.expect("adapter not connected");
+    btc_usdt_exchange.publish(book_update_message(&[("30000", "1")], &[("30001", "1")]));
+    assert_eq!(next_best_bid(&mut summaries).await, ("BTC-USDT".to_string(), 30000.0));
+    assert_eq!(eth_btc_exchange.connections(), 0);
+    drop(summaries);
+
+    // several symbols multiplexed on a stream, the exchanges of each subscribed only once
+    let mut summaries = book_summary(&mut client, "ETH-BTC,BTC-USDT").await;
+    timeout(TIMEOUT, eth_btc_exchange.wait_for_connections(1)).await.expect("adapter not connected");
+    eth_btc_exchange.publish(book_update_message(&[("0.06", "1")], &[("0.061", "1")]));
+    expect_best_bid(&mut summaries, "ETH-BTC", 0.06).await;
+    btc_usdt_exchange.publish(book_update_message(&[("30002", "1")], &[("30003", "1")]));
+    expect_best_bid(&mut summaries, "BTC-USDT", 30002.0).await;
+    assert_eq!((eth_btc_exchange.connections(), btc_usdt_exchange.connections()), (1, 1));
+
+    // the latest summaries of all the symbols, from the feeds of the client streams
+    eth_btc_exchange.publish(book_update_message(&[("0.062", "1")], &[("0.063", "1")]));
+    btc_usdt_exchange.publish(book_update_message(&[("30004", "1")], &[("30005", "1")]));
+    timeout(TIMEOUT, async {
+        loop {
+            let batch = client.get_snapshots(SymbolList::default()).await.unwrap().into_inner();
+            assert!(batch.missing.is_empty());
+            let best_bids: Vec<(&str, f64)> = batch.summaries.iter()
+                .map(|summary| (summary.symbol.as_str(), summary.bids[0].price))
+                .collect();
+            if best_bids == vec![("ETH-BTC", 0.062), ("BTC-USDT", 30004.0)] {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }).await.expect("no snapshots");
+
+    // the symbols and exchanges of every currency pair served
+    let symbols = client.list_symbols(Empty {}).await.unwrap().into_inner().symbols;
+    let symbols: Vec<(&str, bool)> = symbols.iter().map(|symbol| (symbol.symbol.as_str(), symbol.healthy)).collect();
+    assert_eq!(symbols, vec![("ETH-BTC", true), ("BTC-USDT", true)]);
+    let exchanges = client.list_exchanges(Empty {}).await.unwrap().into_inner().exchanges;
+    let endpoints: Vec<(&str, String)> = exchanges.iter().map(|exchange| (exchange.symbol.as_str(), exchange.endpoint.clone())).collect();
+    assert_eq!(endpoints, vec![("ETH-BTC", eth_btc_exchange.url()), ("BTC-USDT", btc_usdt_exchange.url())]);
+
+    // the queries answer for the symbol requested, whichever currency pair it is
+    let request = SweepPriceRequest { side: BookSide::Bid as i32, notional: 1.0, symbol: "btcusdt".to_string() };
+    let sweep = client.get_sweep_price(request).await.unwrap().into_inner();
+    assert_eq!((sweep.symbol.as_str(), sweep.worst_price), ("BTC-USDT", 30004.0));
+    let request = SweepPriceRequest { side: BookSide::Bid as i32, notional: 1.0, symbol: "XRP-USDT".to_string() };
+    let status = client.get_sweep_price(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    let mut request = tonic::Request::new(Empty {});
+    request.metadata_mut().insert(SYMBOLS_METADATA, "BTC-USDT".parse().unwrap());
+    let quote_share = client.get_quote_share(request).await.unwrap().into_inner();
+    assert_eq!(quote_share.symbol, "BTC-USDT");
+    let mut request = tonic::Request::new(Empty {});
+    request.metadata_mut().insert(SYMBOLS_METADATA, "BTC-USDT".parse().unwrap());
+    let mut volatility = client.volatility_stream(request).await.unwrap().into_inner();
+    btc_usdt_exchange.publish(book_update_message(&[("30006", "1")], &[("30007", "1")]));
+    let estimate = timeout(TIMEOUT, volatility.next()).await.expect("no volatility estimate").unwrap().unwrap();
+    assert_eq!(estimate.symbol, "BTC-USDT");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_snapshots_deadline() {
+    let eth_btc_exchange = SimulatedExchange::start().await.unwrap();
+    let btc_usdt_exchange = SimulatedExchange::start().await.unwrap();
+    let eth_btc = parse_currency_pair("ETH-BTC").unwrap();
+    let btc_usdt = parse_currency_pair("BTC-USDT").unwrap();
+    let server = ProtobufOrderbookServer::new(
+        eth_btc.clone(),
+        vec![Box::new(eth_btc_exchange.adapter(&eth_btc))],
+        UsageRegistry::new(system_clock()),
+    ).with_product(btc_usdt.clone(), vec![Box::new(btc_usdt_exchange.adapter(&btc_usdt))]);
+    let port = free_port();
+    tokio::spawn(async move {
+        let _ = server.serve(port).await;
+    });
+    let url = format!("http://[::1]:{}", port);
+    let mut client = timeout(TIMEOUT, async {
+        loop {
+            match OrderbookAggregatorClient::connect(url.clone()).await {
+                Ok(client) => break client,
+                Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+            }
+        }
+    }).await.expect("server not started");
+
+    // symbols without summaries are waited for together, not one after the other
+    let started = tokio::time::Instant::now();
+    let batch = client.get_snapshots(SymbolList::default()).await.unwrap().into_inner();
+    assert!(started.elapsed() < Duration::from_secs(8));
+    assert!(batch.summaries.is_empty());
+    assert_eq!(batch.missing, vec!["ETH-BTC".to_string(), "BTC-USDT".to_string()]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_snapshots_limit_compressed() {
+    let eth_btc_exchange = SimulatedExchange::start().await.unwrap();
+    let eth_btc = parse_currency_pair("ETH-BTC").unwrap();
+    let server = ProtobufOrderbookServer::new(
+        eth_btc.clone(),
+        vec![Box::new(eth_btc_exchange.adapter(&eth_btc))],
+        UsageRegistry::new(system_clock()),
+    );
+    let port = free_port();
+    tokio::spawn(async move {
+        let _ = server.serve(port).await;
+    });
+    let url = format!("http://[::1]:{}", port);
+    let client = timeout(TIMEOUT, async {
+        loop {
+            match OrderbookAggregatorClient::connect(url.clone()).await {
+                Ok(client) => break client,
+                Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+            }
+        }
+    }).await.expect("server not started");
+    let mut client = client.send_compressed(CompressionEncoding::Gzip).accept_compressed(CompressionEncoding::Gzip);
+
+    // compressed requests and responses
+    let request = SymbolList { symbols: vec![SymbolInfo { symbol: "ETH-BTC".to_string(), ..Default::default() }] };
+    let response = client.get_snapshots(request.clone());
+    let (batch, _) = tokio::join!(response, async {
+        timeout(TIMEOUT, eth_btc_exchange.wait_for_connections(1)).await.expect("adapter not connected");
+        eth_btc_exchange.publish(book_update_message(&[("0.06", "1")], &[("0.061", "1")]));
+    });
+    let batch = batch.unwrap();
+    assert_eq!(batch.metadata().get("grpc-encoding").and_then(|value| value.to_str().ok()), Some("gzip"));
+    assert_eq!(batch.into_inner().summaries[0].bids[0].price, 0.06);
+
+    // too many symbols in a request
+    let request = SymbolList { symbols: vec![request.symbols[0].clone(); MAX_SNAPSHOT_SYMBOLS + 1] };
+    let status = client.get_snapshots(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+}
@@ -0,0 +1,81 @@
+//! Pause and resume test: a client pausing its subscription receives no summary until it
+//! resumes, then the latest summary, and the following ones, on the same stream.
+
+mod common;
+
+use tokio::sync::mpsc;
+use tokio::time::{sleep, timeout, Duration};
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+
+use orderbook_server::accounting::UsageRegistry;
+use orderbook_server::clock::system_clock;
+use orderbook_server::grpc::ProtobufOrderbookServer;
+use orderbook_server::orderbook::{orderbook_aggregator_client::OrderbookAggregatorClient, Summary, SubscriptionAction, SubscriptionControl};
+use orderbook_server::simulated::{book_update_message, SimulatedExchange};
+use orderbook_server::symbols::parse_currency_pair;
+
+use common::free_port;
+
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Time for a control to reach the server.
+const CONTROL_DELAY: Duration = Duration::from_millis(200);
+/// Time during which a paused subscription must not deliver summaries.
+const NO_SUMMARY: Duration = Duration::from_millis(300);
+
+
+fn best_bid(summary: Summary) -> f64 {
+    summary.bids[0].price
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_pause_and_resume() {
+    let exchange = SimulatedExchange::start().await.unwrap();
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    let server = ProtobufOrderbookServer::new(
+        product.clone(),
+        vec![Box::new(exchange.adapter(&product))],
+        UsageRegistry::new(system_clock()),
+    );
+    let port = free_port();
+    tokio::spawn(async move {
+        let _ = server.serve(port).await;
+    });
+    let url = format!("http://[::1]:{}", port);
+    let mut client = timeout(TIMEOUT, async {
+        loop {
+            match OrderbookAggregatorClient::connect(url.clone()).await {
+                Ok(client) => break client,
+                Err(_) => sleep(Duration::from_millis(50)).await,
+            }
+        }
+    }).await.expect("server not started");
+    let (controls, control_receiver) = mpsc::channel(4);
+    let mut summaries = client.subscribe(ReceiverStream::new(control_receiver)).await.unwrap().into_inner();
+    timeout(TIMEOUT, exchange.wait_for_connections(1)).await.expect("adapter not connected");
+    let control = |action: SubscriptionAction| SubscriptionControl { action: action as i32 };
+
+    exchange.publish(book_update_message(&[("100", "1")], &[("101", "1")]));
+    let summary = timeout(TIMEOUT, summaries.next()).await.expect("no summary").unwrap().unwrap();
+    assert_eq!(best_bid(summary), 100.0);
+
+    controls.send(control(SubscriptionAction::Pause)).await.unwrap();
+    sleep(CONTROL_DELAY).await;
+    exchange.publish(book_update_message(&[("100.1", "1")], &[("101", "1")]));
+    exchange.publish(book_update_message(&[("100.2", "1")], &[("101", "1")]));
+    assert!(timeout(NO_SUMMARY, summaries.next()).await.is_err(), "summary delivered while paused");
+
+    // the latest summary on resuming, then the next ones
+    controls.send(control(SubscriptionAction::Resume)).await.unwrap();
+    let summary = timeout(TIMEOUT, summaries.next()).await.expect("no snapshot").unwrap().unwrap();
+    assert_eq!(best_bid(summary), 100.2);
+    exchange.publish(book_update_message(&[("100.3", "1")], &[("101", "1")]));
+    let summary = timeout(TIMEOUT, summaries.next()).await.expect("no summary").unwrap().unwrap();
+    assert_eq!(best_bid(summary), 100.3);
+
+    // the client can stop sending controls and keep receiving summaries
+    drop(controls);
+    exchange.publish(book_update_message(&[("100.4", "1")], &[("101", "1")]));
+    let summary = timeout(TIMEOUT, summaries.next()).await.expect("no summary").unwrap().unwrap();
+    assert_eq!(best_bid(summary), 100.4);
+}
@@ -0,0 +1,36 @@
+//! Prometheus test: the exporter serves the market gauges in the text format over HTTP.
+
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{Ipv6Addr, TcpStream};
+
+use orderbook_server::metrics::{BEST_BIDS, SPREADS_BPS};
+use orderbook_server::prometheus::spawn_exporter;
+
+use common::free_port;
+
+
+/// Send a GET request to a local port, returning the whole response.
+fn get(port: u16, path: &str) -> String {
+    let mut stream = TcpStream::connect((Ipv6Addr::LOCALHOST, port)).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_prometheus_exporter() {
+    BEST_BIDS.set("ETH-BTC", 0.0612);
+    SPREADS_BPS.set("ETH-BTC", 1.5);
+    let port = free_port();
+    spawn_exporter(port).unwrap();
+
+    let response = tokio::task::spawn_blocking(move || (get(port, "/metrics"), get(port, "/other"))).await.unwrap();
+    assert!(response.0.starts_with("HTTP/1.1 200 OK"), "{}", response.0);
+    assert!(response.0.contains("content-type: text/plain; version=0.0.4"), "{}", response.0);
+    assert!(response.0.contains("# TYPE orderbook_best_bid gauge\norderbook_best_bid{symbol=\"ETH-BTC\"} 0.0612\n"), "{}", response.0);
+    assert!(response.0.contains("orderbook_spread_bps{symbol=\"ETH-BTC\"} 1.5\n"), "{}", response.0);
+    assert!(response.1.starts_with("HTTP/1.1 404 Not Found"), "{}", response.1);
+}
@@ -0,0 +1,79 @@
+//! Request correlation test: the subscriptions of an adapter are numbered per connection, and
+//! the connection is up only once the exchange answered each of them, by identifier.
+
+use futures::StreamExt;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tokio::time::{timeout, Duration};
+
+use orderbook_server::core::BookUpdate;
+use orderbook_server::exchange::{ExchangeAdapter, ExchangeEvent, ExchangeProtocol};
+use orderbook_server::request_ids::REQUEST_ID_PLACEHOLDER;
+use orderbook_server::simulated::SimulatedExchange;
+use orderbook_server::status::ExchangeStatus;
+
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const EXCHANGE_CODE: &str = "correlated";
+
+
+/// Parse the responses to requests, e.g. `{"result":null,"id":5}`.
+fn read_response(value: &str) -> Option<ExchangeProtocol<BookUpdate>> {
+    let response: serde_json::Value = serde_json::from_str(value).ok()?;
+    match (response.get("result"), response.get("id").and_then(|id| id.as_u64())) {
+        (Some(serde_json::Value::Null), Some(id)) => Some(ExchangeProtocol::Response(id)),
+        _ => None,
+    }
+}
+
+/// Wait until a number of messages containing a pattern were received by the exchange.
+async fn wait_for_messages(exchange: &SimulatedExchange, pattern: &str, count: usize) {
+    let received = timeout(TIMEOUT, async {
+        while exchange.received_messages().iter().filter(|m| m.contains(pattern)).count() < count {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }).await;
+    assert!(received.is_ok(), "no {} in {:?}", pattern, exchange.received_messages());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_responses_correlated_by_request_id() {
+    let exchange = SimulatedExchange::start().await.unwrap();
+    let (status_sender, mut status) = broadcast::channel(16);
+    let template = |channel: &str| format!(r#"{{"method":"SUBSCRIBE","params":["{}"],"id":{}}}"#, channel, REQUEST_ID_PLACEHOLDER);
+    let mut stream = ExchangeAdapter::new(EXCHANGE_CODE, exchange.url(), Arc::new(read_response))
+        .with_subscribe_message(template("ethbtc@depth"))
+        .with_subscribe_message(template("ethbtc@trade"))
+        .with_first_request_id(5)
+        .with_subscription_acks()
+        .with_status_sender(status_sender)
+        .make_stream()
+        .await;
+    let connected = timeout(TIMEOUT, stream.next()).await.expect("no event from adapter");
+    assert_eq!(connected, Some(ExchangeEvent::Connected(EXCHANGE_CODE.into())));
+    wait_for_messages(&exchange, r#""params":["ethbtc@depth"],"id":5"#, 1).await;
+    wait_for_messages(&exchange, r#""params":["ethbtc@trade"],"id":6"#, 1).await;
+
+    // responses to unknown requests, or answered twice, are not acknowledgments
+    exchange.publish(r#"{"result":null,"id":99}"#.to_string());
+    exchange.publish(r#"{"result":null,"id":6}"#.to_string());
+    exchange.publish(r#"{"result":null,"id":6}"#.to_string());
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(status.try_recv().is_err(), "adapter up before every request was answered");
+    exchange.publish(r#"{"result":null,"id":5}"#.to_string());
+    let event = timeout(TIMEOUT, status.recv()).await.expect("no status from adapter").unwrap();
+    assert_eq!((event.exchange.as_str(), event.status), (EXCHANGE_CODE, ExchangeStatus::Up));
+
+    // identifiers restart with each connection
+    exchange.drop_connections();
+    let event = timeout(TIMEOUT, status.recv()).await.expect("no status from adapter").unwrap();
+    assert_eq!(event.status, ExchangeStatus::Down);
+    wait_for_messages(&exchange, r#""params":["ethbtc@depth"],"id":5"#, 2).await;
+    wait_for_messages(&exchange, r#""params":["ethbtc@trade"],"id":6"#, 2).await;
+    exchange.publish(r#"{"result":null,"id":5}"#.to_string());
+    exchange.publish(r#"{"result":null,"id":6}"#.to_string());
+    let event = timeout(TIMEOUT, status.recv()).await.expect("no status from adapter").unwrap();
+    assert_eq!(event.status, ExchangeStatus::Up);
+
+    stream.disconnect().await;
+}
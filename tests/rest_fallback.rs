@@ -0,0 +1,72 @@
+//! REST fallback test: an adapter whose WebSocket service cannot be reached polls depth
+//! snapshots from a REST endpoint, and is notified degraded.
+
+mod common;
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tokio::time::{timeout, Duration};
+
+use orderbook_server::core::{BookUpdate, ExchangeLevel};
+use orderbook_server::exchange::{ExchangeAdapter, ExchangeEvent, ExchangeProtocol};
+use orderbook_server::rest::RestFallback;
+use orderbook_server::status::ExchangeStatus;
+
+use common::next_event;
+
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const EXCHANGE_CODE: &str = "polled";
+const SNAPSHOT: &str = r#"{"bids":[["100","1"]],"asks":[["101","2"]]}"#;
+
+
+/// Serve the snapshot on a local port to any number of requests, returning the URL.
+fn serve_snapshots() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/depth", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", SNAPSHOT.len(), SNAPSHOT);
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+    url
+}
+
+fn snapshot() -> BookUpdate {
+    BookUpdate {
+        exchange_code: EXCHANGE_CODE.into(),
+        bids: vec![ExchangeLevel::from_strs(EXCHANGE_CODE, "100", "1")],
+        asks: vec![ExchangeLevel::from_strs(EXCHANGE_CODE, "101", "2")],
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_rest_fallback() {
+    // nothing listens on the WebSocket port
+    let unreachable = TcpListener::bind("127.0.0.1:0").unwrap();
+    let ws_url = format!("ws://{}", unreachable.local_addr().unwrap());
+    drop(unreachable);
+    let reader = Arc::new(|text: &str| (text == SNAPSHOT).then(|| ExchangeProtocol::Data(snapshot())));
+    let rest_fallback = RestFallback::new(serve_snapshots(), reader)
+        .with_poll_interval(Duration::from_millis(10));
+    let (status_sender, mut status) = broadcast::channel(16);
+    let mut stream = ExchangeAdapter::new(EXCHANGE_CODE, ws_url, Arc::new(|_: &str| None))
+        .with_rest_fallback(rest_fallback)
+        .with_status_sender(status_sender)
+        .make_stream()
+        .await;
+
+    assert_eq!(next_event(&mut stream).await, ExchangeEvent::Connected(EXCHANGE_CODE.into()));
+    let event = timeout(TIMEOUT, status.recv()).await.expect("no status from adapter").unwrap();
+    assert_eq!((event.exchange.as_str(), event.status), (EXCHANGE_CODE, ExchangeStatus::Degraded));
+    for _ in 0..3 {
+        assert_eq!(next_event(&mut stream).await, ExchangeEvent::Data(snapshot()));
+    }
+    stream.disconnect().await;
+}
@@ -0,0 +1,50 @@
+//! Scheduling test: with the ingest runtime started, the messages of a simulated exchange are
+//! parsed on the ingest threads, while the stream of events is consumed on the publish runtime.
+
+use futures::StreamExt;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tokio::time::{timeout, Duration};
+
+use orderbook_server::core::BookUpdate;
+use orderbook_server::exchange::{ExchangeAdapter, ExchangeEvent, ExchangeProtocol};
+use orderbook_server::scheduling::{Scheduling, INGEST_THREAD_NAME, PUBLISH_THREAD_NAME};
+use orderbook_server::simulated::{book_update_message, SimulatedExchange, SIMULATED_CODE};
+
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+
+#[test]
+fn test_ingest_on_dedicated_runtime() {
+    let scheduling = Scheduling { ingest_threads: 1, publish_threads: Some(1) };
+    scheduling.start_ingest_runtime().unwrap();
+    assert!(scheduling.start_ingest_runtime().is_err());
+    scheduling.publish_runtime().unwrap().block_on(async {
+        // block_on runs on the test thread, spawned tasks on the publish workers
+        let consumer = tokio::spawn(async {
+            let parsing_threads = Arc::new(Mutex::new(Vec::new()));
+            let exchange = SimulatedExchange::start().await.unwrap();
+            let threads = parsing_threads.clone();
+            let adapter = ExchangeAdapter::new(SIMULATED_CODE, exchange.url(), Arc::new(move |_: &str| {
+                threads.lock().unwrap().push(thread::current().name().map(str::to_string));
+                Some(ExchangeProtocol::<BookUpdate>::Skipped)
+            }));
+            let mut stream = adapter.make_stream().await;
+            assert_eq!(timeout(TIMEOUT, stream.next()).await.unwrap(), Some(ExchangeEvent::Connected(SIMULATED_CODE.into())));
+            exchange.publish(book_update_message(&[("100", "1")], &[]));
+            timeout(TIMEOUT, async {
+                while parsing_threads.lock().unwrap().is_empty() {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            }).await.expect("message not parsed");
+            stream.disconnect().await;
+            let consumer_thread = thread::current().name().map(str::to_string);
+            let parsing_threads = parsing_threads.lock().unwrap().clone();
+            (consumer_thread, parsing_threads)
+        });
+        let (consumer_thread, parsing_threads) = consumer.await.unwrap();
+        assert_eq!(consumer_thread.as_deref(), Some(PUBLISH_THREAD_NAME));
+        assert!(parsing_threads.iter().all(|name| name.as_deref() == Some(INGEST_THREAD_NAME)), "{:?}", parsing_threads);
+    });
+}
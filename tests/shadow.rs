@@ -0,0 +1,68 @@
+//! Shadow comparison test: a server comparing its feed with the summary stream of a second
+//! instance records the lag of the books they both publish, and the divergence of the books
+//! published by the second instance only.
+
+mod common;
+
+use tokio::time::{timeout, Duration};
+
+use orderbook_server::accounting::UsageRegistry;
+use orderbook_server::clock::system_clock;
+use orderbook_server::grpc::ProtobufOrderbookServer;
+use orderbook_server::metrics::{SHADOW_DIVERGENCES, SHADOW_LAGS};
+use orderbook_server::shadow::ShadowConfig;
+use orderbook_server::simulated::{book_update_message, SimulatedExchange};
+use orderbook_server::symbols::parse_currency_pair;
+
+use common::free_port;
+
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const PUBLISH_INTERVAL: Duration = Duration::from_millis(50);
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_shadow_comparison() {
+    let local_exchange = SimulatedExchange::start().await.unwrap();
+    let shadow_exchange = SimulatedExchange::start().await.unwrap();
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    let shadow_port = free_port();
+    let shadow = ProtobufOrderbookServer::new(
+        product.clone(),
+        vec![Box::new(shadow_exchange.adapter(&product))],
+        UsageRegistry::new(system_clock()),
+    );
+    tokio::spawn(async move {
+        let _ = shadow.serve(shadow_port).await;
+    });
+    let local = ProtobufOrderbookServer::new(
+        product.clone(),
+        vec![Box::new(local_exchange.adapter(&product))],
+        UsageRegistry::new(system_clock()),
+    ).with_shadow(ShadowConfig { url: format!("http://[::1]:{}", shadow_port), tolerance_ms: 200 });
+    let port = free_port();
+    tokio::spawn(async move {
+        let _ = local.serve(port).await;
+    });
+
+    // the same books published by both instances, once both are connected
+    timeout(TIMEOUT, async {
+        let mut bid = 100;
+        while SHADOW_LAGS.count("local") + SHADOW_LAGS.count("shadow") == 0 {
+            bid += 1;
+            let message = book_update_message(&[(&bid.to_string(), "1")], &[("200", "1")]);
+            local_exchange.publish(message.clone());
+            shadow_exchange.publish(message);
+            tokio::time::sleep(PUBLISH_INTERVAL).await;
+        }
+    }).await.expect("no lag recorded");
+
+    // a better bid published by the second instance only
+    let price_divergences = SHADOW_DIVERGENCES.get("price");
+    timeout(TIMEOUT, async {
+        while SHADOW_DIVERGENCES.get("price") == price_divergences {
+            shadow_exchange.publish(book_update_message(&[("150", "1")], &[("200", "1")]));
+            tokio::time::sleep(PUBLISH_INTERVAL).await;
+        }
+    }).await.expect("no divergence reported");
+}
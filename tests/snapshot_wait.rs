@@ -0,0 +1,53 @@
+//! Snapshot wait test: after (re)connections, the summary stream of a symbol publishes once
+//! every exchange delivered a snapshot, or on each update if it does not wait for snapshots.
+
+mod common;
+
+use orderbook_server::event_bus::EventBus;
+use orderbook_server::exchange::ExchangeEvent;
+use orderbook_server::service::BookSummaryService;
+use orderbook_server::symbols::parse_currency_pair;
+
+use common::{book_update, expect_summary};
+
+
+/// Connect two exchanges, then deliver their snapshots.
+fn connect_exchanges(bus: &EventBus) {
+    bus.publish_book_event(ExchangeEvent::Connected("test1".into()));
+    bus.publish_book_event(ExchangeEvent::Connected("test2".into()));
+    bus.publish_book_event(book_update("test1", "100"));
+    bus.publish_book_event(book_update("test2", "99"));
+}
+
+#[tokio::test]
+async fn test_wait_for_snapshots() {
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    let bus = EventBus::new();
+    let mut service = BookSummaryService::from_bus(&product, &bus, 2).with_wait_for_snapshots(true);
+    connect_exchanges(&bus);
+    // the book of the first exchange alone is not published
+    assert_eq!(expect_summary(&mut service).await.bids.len(), 2);
+
+    bus.publish_book_event(ExchangeEvent::Connected("test1".into()));
+    bus.publish_book_event(book_update("test2", "98"));
+    bus.publish_book_event(book_update("test1", "101"));
+    // the update of the second exchange is published with the snapshot of the first one
+    let summary = expect_summary(&mut service).await;
+    assert_eq!(summary.bids[0].price, 101.0);
+    assert!(summary.bids.iter().any(|level| level.price == 98.0), "{:?}", summary);
+}
+
+#[tokio::test]
+async fn test_no_wait_for_snapshots() {
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    let bus = EventBus::new();
+    let mut service = BookSummaryService::from_bus(&product, &bus, 2).with_wait_for_snapshots(false);
+    connect_exchanges(&bus);
+    assert_eq!(expect_summary(&mut service).await.bids.len(), 1);
+    assert_eq!(expect_summary(&mut service).await.bids.len(), 2);
+
+    bus.publish_book_event(ExchangeEvent::Connected("test1".into()));
+    bus.publish_book_event(book_update("test2", "98"));
+    let summary = expect_summary(&mut service).await;
+    assert!(summary.bids.iter().any(|level| level.price == 98.0), "{:?}", summary);
+}
@@ -0,0 +1,138 @@
+//! Stale summaries test: once every exchange of a symbol is disconnected, the summary stream
+//! keeps publishing the last known summary, marked stale with its age, until an exchange
+//! delivers again or the grace period expires, ending the client streams with an error.
+
+mod common;
+
+use futures::StreamExt;
+use std::sync::Arc;
+use tokio::time::{timeout, Duration};
+
+use orderbook_server::accounting::UsageRegistry;
+use orderbook_server::clock::{system_clock, ManualClock};
+use orderbook_server::event_bus::EventBus;
+use orderbook_server::exchange::{ExchangeAdapter, ExchangeEvent, ExchangeProtocol};
+use orderbook_server::grpc::ProtobufOrderbookServer;
+use orderbook_server::orderbook::{orderbook_aggregator_client::OrderbookAggregatorClient, BookSummaryRequest, StreamStatusReason, Summary};
+use orderbook_server::orderbook::summary::Frame;
+use orderbook_server::service::BookSummaryService;
+use orderbook_server::simulated::{SimulatedExchange, SIMULATED_CODE};
+use orderbook_server::symbols::parse_currency_pair;
+
+use common::{book_update, expect_summary, free_port, next_summary};
+
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const POISON_MESSAGE: &str = "poison";
+
+
+/// Best bid of a summary, whether it is stale and its age.
+fn staleness(summary: Summary) -> (f64, bool, u64) {
+    (summary.bids[0].price, summary.stale, summary.stale_age_ms)
+}
+
+#[tokio::test]
+async fn test_stale_summaries() {
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    let bus = EventBus::new();
+    let clock = Arc::new(ManualClock::new());
+    let mut service = BookSummaryService::from_bus(&product, &bus, 2)
+        .with_fair_values(&[], clock.clone())
+        .with_stale_grace(Some(Duration::from_secs(3)));
+    bus.publish_book_event(book_update("test1", "100"));
+    bus.publish_book_event(book_update("test2", "99"));
+    assert_eq!(staleness(expect_summary(&mut service).await), (100.0, false, 0));
+    assert_eq!(staleness(expect_summary(&mut service).await), (100.0, false, 0));
+
+    // the book without the first exchange is still live
+    bus.publish_book_event(ExchangeEvent::Disconnected("test1".into()));
+    assert_eq!(staleness(expect_summary(&mut service).await), (99.0, false, 0));
+    clock.advance(Duration::from_millis(500));
+    bus.publish_book_event(ExchangeEvent::Disconnected("test2".into()));
+    assert_eq!(staleness(expect_summary(&mut service).await), (99.0, true, 500));
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(staleness(expect_summary(&mut service).await), (99.0, true, 1500));
+
+    // live again on the next snapshot
+    bus.publish_book_event(book_update("test1", "98"));
+    assert_eq!(staleness(expect_summary(&mut service).await), (98.0, false, 0));
+    bus.publish_book_event(ExchangeEvent::Disconnected("test1".into()));
+    assert_eq!(staleness(expect_summary(&mut service).await), (98.0, true, 0));
+    for age in [1000, 2000] {
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(staleness(expect_summary(&mut service).await), (98.0, true, age));
+    }
+    clock.advance(Duration::from_secs(1));
+    let summary = next_summary(&mut service).await;
+    match summary.frame {
+        Some(Frame::Status(status)) => assert_eq!(status.reason(), StreamStatusReason::StaleExpired),
+        None => panic!("summary rather than status frame: {:?}", summary),
+    }
+    // the empty book once the grace period expired
+    bus.publish_book_event(ExchangeEvent::Disconnected("test2".into()));
+    assert!(next_summary(&mut service).await.bids.is_empty());
+}
+
+#[tokio::test]
+async fn test_empty_book_by_default() {
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    let bus = EventBus::new();
+    let mut service = BookSummaryService::from_bus(&product, &bus, 1);
+    bus.publish_book_event(book_update("test1", "100"));
+    bus.publish_book_event(ExchangeEvent::Disconnected("test1".into()));
+    assert_eq!(staleness(expect_summary(&mut service).await), (100.0, false, 0));
+    let summary = next_summary(&mut service).await;
+    assert!(summary.bids.is_empty() && !summary.stale);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_stream_ended_after_grace_period() {
+    let exchange = SimulatedExchange::start().await.unwrap();
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    // every message is a book, except the one failing the adapter task
+    let adapter = ExchangeAdapter::new(SIMULATED_CODE, exchange.url(), Arc::new(|text: &str| {
+        assert_ne!(text, POISON_MESSAGE, "poisoned message");
+        match book_update(SIMULATED_CODE, text) {
+            ExchangeEvent::Data(book_update) => Some(ExchangeProtocol::Data(book_update)),
+            _ => None,
+        }
+    }));
+    let server = ProtobufOrderbookServer::new(product, vec![Box::new(adapter)], UsageRegistry::new(system_clock()))
+        .with_stale_grace(Duration::from_millis(1500));
+    let port = free_port();
+    tokio::spawn(async move {
+        let _ = server.serve(port).await;
+    });
+    let url = format!("http://[::1]:{}", port);
+    let mut client = timeout(TIMEOUT, async {
+        loop {
+            match OrderbookAggregatorClient::connect(url.clone()).await {
+                Ok(client) => break client,
+                Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+            }
+        }
+    }).await.expect("server not started");
+
+    let mut summaries = client.book_summary(BookSummaryRequest::default()).await.unwrap().into_inner();
+    timeout(TIMEOUT, exchange.wait_for_connections(1)).await.expect("adapter not connected");
+    exchange.publish("100".to_string());
+    let summary = timeout(TIMEOUT, summaries.next()).await.expect("no summary").unwrap().unwrap();
+    assert!(!summary.stale);
+
+    // the adapter is restarted, and delivers nothing after its reconnection
+    exchange.publish(POISON_MESSAGE.to_string());
+    let mut stale_summaries = 0;
+    let status = loop {
+        match timeout(TIMEOUT, summaries.next()).await.expect("stream not ended").expect("stream ended without error") {
+            Ok(summary) if summary.frame.is_some() => (),
+            Ok(summary) => {
+                assert!(summary.stale, "{:?}", summary);
+                assert_eq!(summary.bids[0].price, 100.0);
+                stale_summaries += 1;
+            },
+            Err(status) => break status,
+        }
+    };
+    assert!(stale_summaries >= 1);
+    assert_eq!(status.code(), tonic::Code::Unavailable);
+}
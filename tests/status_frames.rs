@@ -0,0 +1,77 @@
+//! Status frame test: the summary stream of a symbol notifies in-band, before the summary of the
+//! same event, the exchanges dropped, resyncing and restored, and the quorum lost and restored.
+
+mod common;
+
+use orderbook_server::event_bus::EventBus;
+use orderbook_server::exchange::ExchangeEvent;
+use orderbook_server::orderbook::{StreamQuality, StreamStatusReason};
+use orderbook_server::orderbook::summary::Frame;
+use orderbook_server::service::BookSummaryService;
+use orderbook_server::symbols::parse_currency_pair;
+
+use common::{book_update, expect_summary, next_summary};
+
+
+/// Receive a status frame, returning its reason, exchange, quality and exchange counts.
+async fn expect_status(service: &mut BookSummaryService) -> (StreamStatusReason, String, StreamQuality, u32) {
+    let summary = next_summary(service).await;
+    assert_eq!(summary.symbol, "ETH-BTC");
+    assert!(summary.bids.is_empty() && summary.asks.is_empty(), "status frame with levels: {:?}", summary);
+    match summary.frame {
+        Some(Frame::Status(status)) => {
+            assert!(status.time.is_some());
+            assert_eq!(status.expected_exchanges, 3);
+            (status.reason(), status.exchange.clone(), status.quality(), status.contributing_exchanges)
+        },
+        None => panic!("summary rather than status frame: {:?}", summary),
+    }
+}
+
+#[tokio::test]
+async fn test_status_frames() {
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    let bus = EventBus::new();
+    let mut service = BookSummaryService::from_bus(&product, &bus, 3).with_status_frames(true);
+    for exchange_code in ["test1", "test2", "test3"] {
+        bus.publish_book_event(ExchangeEvent::Connected(exchange_code.into()));
+        bus.publish_book_event(book_update(exchange_code, "100"));
+    }
+    // the quorum is reached silently on start
+    for bids in 1..=3 {
+        assert_eq!(expect_summary(&mut service).await.bids.len(), bids);
+    }
+
+    bus.publish_book_event(ExchangeEvent::Disconnected("test1".into()));
+    assert_eq!(expect_status(&mut service).await, (StreamStatusReason::VenueDropped, "test1".to_string(), StreamQuality::Degraded, 2));
+    assert_eq!(expect_summary(&mut service).await.bids.len(), 2);
+    bus.publish_book_event(ExchangeEvent::Disconnected("test2".into()));
+    assert_eq!(expect_status(&mut service).await, (StreamStatusReason::VenueDropped, "test2".to_string(), StreamQuality::Degraded, 1));
+    assert_eq!(expect_status(&mut service).await, (StreamStatusReason::QuorumLost, String::new(), StreamQuality::Degraded, 1));
+    assert_eq!(expect_summary(&mut service).await.bids.len(), 1);
+
+    bus.publish_book_event(ExchangeEvent::Connected("test1".into()));
+    assert_eq!(expect_status(&mut service).await, (StreamStatusReason::VenueResyncing, "test1".to_string(), StreamQuality::Degraded, 1));
+    bus.publish_book_event(book_update("test1", "99"));
+    assert_eq!(expect_status(&mut service).await, (StreamStatusReason::VenueRestored, "test1".to_string(), StreamQuality::Degraded, 2));
+    assert_eq!(expect_status(&mut service).await, (StreamStatusReason::QuorumRestored, String::new(), StreamQuality::Degraded, 2));
+    assert_eq!(expect_summary(&mut service).await.bids.len(), 2);
+    bus.publish_book_event(ExchangeEvent::Connected("test2".into()));
+    assert_eq!(expect_status(&mut service).await, (StreamStatusReason::VenueResyncing, "test2".to_string(), StreamQuality::Degraded, 2));
+    bus.publish_book_event(book_update("test2", "98"));
+    assert_eq!(expect_status(&mut service).await, (StreamStatusReason::VenueRestored, "test2".to_string(), StreamQuality::Healthy, 3));
+    assert_eq!(expect_summary(&mut service).await.bids.len(), 3);
+}
+
+#[tokio::test]
+async fn test_no_status_frames_by_default() {
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    let bus = EventBus::new();
+    let mut service = BookSummaryService::from_bus(&product, &bus, 1);
+    bus.publish_book_event(book_update("test1", "100"));
+    bus.publish_book_event(ExchangeEvent::Disconnected("test1".into()));
+    bus.publish_book_event(book_update("test1", "99"));
+    for bids in [1, 0, 1] {
+        assert_eq!(expect_summary(&mut service).await.bids.len(), bids);
+    }
+}
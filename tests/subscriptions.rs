@@ -0,0 +1,71 @@
+//! Subscription registry test: adapters of a simulated exchange sharing a registry subscribe
+//! one at a time, each waiting for the previous one to disconnect, however they are started
+//! and stopped.
+
+use futures::StreamExt;
+use tokio::time::{timeout, Duration};
+
+use orderbook_server::core::BookUpdate;
+use orderbook_server::exchange::{ExchangeAdapterStream, ExchangeEvent};
+use orderbook_server::simulated::{SimulatedExchange, SIMULATED_CODE};
+use orderbook_server::subscriptions::SubscriptionRegistry;
+use orderbook_server::symbols::parse_currency_pair;
+
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Time during which a waiting adapter must not connect.
+const NO_CONNECTION: Duration = Duration::from_millis(300);
+
+
+async fn expect_connected(stream: &mut ExchangeAdapterStream<BookUpdate>) {
+    assert_eq!(timeout(TIMEOUT, stream.next()).await.unwrap(), Some(ExchangeEvent::Connected(SIMULATED_CODE.into())));
+}
+
+/// Disconnect adapters.
+async fn disconnect(streams: &mut [&mut ExchangeAdapterStream<BookUpdate>]) {
+    for stream in streams {
+        stream.disconnect().await;
+    }
+}
+
+async fn expect_waiting(stream: &mut ExchangeAdapterStream<BookUpdate>) {
+    assert!(timeout(NO_CONNECTION, stream.next()).await.is_err(), "duplicate subscription opened");
+}
+
+#[tokio::test]
+async fn test_single_subscription_per_symbol() {
+    let product = parse_currency_pair("ETH-BTC").unwrap();
+    let exchange = SimulatedExchange::start().await.unwrap();
+    let registry = SubscriptionRegistry::new();
+    let adapter = exchange.adapter(&product).with_subscription_registry(&registry, "ETH-BTC");
+
+    let mut first = adapter.make_stream().await;
+    expect_connected(&mut first).await;
+    let mut second = adapter.make_stream().await;
+    let mut third = adapter.clone().make_stream().await;
+    expect_waiting(&mut second).await;
+    expect_waiting(&mut third).await;
+
+    // a waiting adapter stopped never subscribes
+    disconnect(&mut [&mut third, &mut first]).await;
+    expect_connected(&mut second).await;
+    assert!(registry.is_active(SIMULATED_CODE, "ETH-BTC"));
+
+    // a reconnection keeps the lease
+    let mut fourth = adapter.make_stream().await;
+    exchange.drop_connections();
+    expect_waiting(&mut fourth).await;
+    expect_connected(&mut second).await;
+    disconnect(&mut [&mut second]).await;
+    expect_connected(&mut fourth).await;
+
+    // another symbol is not held back
+    let mut other = exchange.adapter(&product).with_subscription_registry(&registry, "BTC-USDT").make_stream().await;
+    expect_connected(&mut other).await;
+    disconnect(&mut [&mut fourth, &mut other]).await;
+    timeout(TIMEOUT, async {
+        while !registry.is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }).await.expect("subscriptions not released");
+}
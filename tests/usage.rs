@@ -0,0 +1,61 @@
+//! Usage test: the usage reported to a client is only the one of its own API key.
+
+mod common;
+
+use tokio::time::{timeout, Duration};
+use tonic::Code;
+
+use orderbook_server::accounting::{UsageRegistry, API_KEY_METADATA};
+use orderbook_server::clock::system_clock;
+use orderbook_server::grpc::ProtobufOrderbookServer;
+use orderbook_server::orderbook::{orderbook_aggregator_client::OrderbookAggregatorClient, BookSummaryRequest, UsageRequest};
+use orderbook_server::simulated::SimulatedExchange;
+use orderbook_server::symbols::parse_currency_pair;
+
+use common::free_port;
+
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+
+/// Create a request carrying an API key.
+fn with_api_key<T>(message: T, api_key: &str) -> tonic::Request<T> {
+    let mut request = tonic::Request::new(message);
+    request.metadata_mut().insert(API_KEY_METADATA, api_key.parse().unwrap());
+    request
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_usage_of_own_api_key() {
+    let exchange = SimulatedExchange::start().await.unwrap();
+    let eth_btc = parse_currency_pair("ETH-BTC").unwrap();
+    let server = ProtobufOrderbookServer::new(eth_btc.clone(), vec![Box::new(exchange.adapter(&eth_btc))], UsageRegistry::new(system_clock()));
+    let port = free_port();
+    tokio::spawn(async move {
+        let _ = server.serve(port).await;
+    });
+    let url = format!("http://[::1]:{}", port);
+    let mut client = timeout(TIMEOUT, async {
+        loop {
+            match OrderbookAggregatorClient::connect(url.clone()).await {
+                Ok(client) => break client,
+                Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+            }
+        }
+    }).await.expect("server not started");
+    let _alice = client.book_summary(with_api_key(BookSummaryRequest::default(), "alice")).await.unwrap();
+    let _bob = client.book_summary(with_api_key(BookSummaryRequest::default(), "bob")).await.unwrap();
+
+    let report = client.get_usage(with_api_key(UsageRequest::default(), "alice")).await.unwrap().into_inner();
+    let api_keys: Vec<&str> = report.usage.iter().map(|usage| usage.api_key.as_str()).collect();
+    assert_eq!(api_keys, vec!["alice"]);
+    assert_eq!(report.usage[0].streams_opened, 1);
+    let request = UsageRequest { api_key: "alice".to_string() };
+    assert_eq!(client.get_usage(with_api_key(request, "alice")).await.unwrap().into_inner(), report);
+
+    let request = UsageRequest { api_key: "bob".to_string() };
+    assert_eq!(client.get_usage(with_api_key(request, "alice")).await.unwrap_err().code(), Code::PermissionDenied);
+    // clients without a key only see the usage of the anonymous key
+    let report = client.get_usage(UsageRequest::default()).await.unwrap().into_inner();
+    assert!(report.usage.is_empty(), "{:?}", report);
+}